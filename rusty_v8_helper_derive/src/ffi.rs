@@ -0,0 +1,1242 @@
+//! Code generation of `#[v8_ffi]`, split in stages for the macros
+//! generating trampolines of their own:
+//! - `FfiOptions::parse` reads the flags of `#[v8_ffi(..)]`,
+//! - `FfiSignature::analyze` checks a fn signature and sorts its
+//!   arguments into the wrapped `this`, `scope` and `context`, and those
+//!   converted from JS,
+//! - `this_prelude`, `arg_preludes` and `call_args` convert the receiver
+//!   and arguments of a call and pass them on,
+//! - `return_postlude` converts the returned value,
+//! - `expand` puts them together with the `FfiFn` impl of a fn.
+
+use crate::{camel_case, doc_string};
+use proc_macro2::TokenStream as TokenStream2;
+use quote::ToTokens;
+use std::fmt::Display;
+use std::result::Result;
+use syn::spanned::Spanned;
+use syn::*;
+
+/// Flags given in `#[v8_ffi(...)]`.
+#[derive(Default)]
+pub(crate) struct FfiOptions {
+    /// The function takes `scope` and `context` as its first arguments.
+    pub scoped: bool,
+    /// Primitive arguments are converted with JS coercion, see `Coerced`.
+    pub coerce: bool,
+    /// A failed return value conversion returns `undefined` instead of
+    /// throwing.
+    pub return_undefined_on_error: bool,
+    /// Property names for the returned tuple, which is then converted to an
+    /// object instead of an array.
+    pub multi_return: Option<Vec<String>>,
+    /// Also generate a deno_core JSON op, see `load_deno_op`.
+    pub deno_op: bool,
+    /// A module whose `to_value` fn converts the returned value in place of
+    /// its `FFICompat` impl.
+    pub return_with: Option<Path>,
+    /// The visibility of the items loading the fn, which is the `FfiFn`
+    /// struct named like it and its `__v8_ffi_` and `__v8_ffi_deno_` fns,
+    /// in place of the fn's own.
+    pub loader_vis: Option<Visibility>,
+}
+
+/// The options of `#[v8_ffi(..)]`, with their syntax.
+const OPTIONS: &[(&str, &str)] = &[
+    ("scoped", "scoped"),
+    ("coerce", "coerce"),
+    ("return_undefined_on_error", "return_undefined_on_error"),
+    ("multi_return", "multi_return(field, ..)"),
+    ("deno_op", "deno_op"),
+    ("return_with", "return_with = \"path::to::module\""),
+    ("loader_vis", "loader_vis = \"pub\""),
+];
+
+impl FfiOptions {
+    pub(crate) fn parse(metadata: &[NestedMeta]) -> Result<FfiOptions, Error> {
+        let mut options = FfiOptions::default();
+        let mut errors = Errors::default();
+        for item in metadata.iter() {
+            match item {
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("multi_return") => {
+                    match parse_multi_return(list) {
+                        Ok(names) => options.multi_return = Some(names),
+                        Err(e) => errors.push(e),
+                    }
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("scoped") => {
+                    options.scoped = true;
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("coerce") => {
+                    options.coerce = true;
+                }
+                NestedMeta::Meta(Meta::Path(path))
+                    if path.is_ident("return_undefined_on_error") =>
+                {
+                    options.return_undefined_on_error = true;
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("deno_op") => {
+                    options.deno_op = true;
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("fast") => {
+                    errors.push(error_help(
+                        path,
+                        "V8 fast API calls are not supported",
+                        "the rusty_v8 this crate builds on cannot attach a `CFunction` to a function template, remove `fast`",
+                    ));
+                }
+                NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                    path,
+                    lit: Lit::Str(module),
+                    ..
+                })) if path.is_ident("return_with") => match module.parse::<Path>() {
+                    Ok(module) => options.return_with = Some(module),
+                    Err(_) => errors.push(error_help(
+                        module,
+                        format!("`{}` is not a module path", module.value()),
+                        "give the module whose `to_value` fn converts the returned value, i.e. `return_with = \"rusty_v8_helper::json\"`",
+                    )),
+                },
+                NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                    path,
+                    lit: Lit::Str(vis),
+                    ..
+                })) if path.is_ident("loader_vis") => match vis.parse::<Visibility>() {
+                    Ok(Visibility::Inherited) | Err(_) => errors.push(error_help(
+                        vis,
+                        format!("`{}` is not a visibility", vis.value()),
+                        "give the visibility of the loader, i.e. `loader_vis = \"pub\"` or `loader_vis = \"pub(crate)\"`",
+                    )),
+                    Ok(vis) => options.loader_vis = Some(vis),
+                },
+                item => errors.push(unknown_option(item)),
+            }
+        }
+        errors.finish()?;
+        Ok(options)
+    }
+}
+
+/// The error of an unknown or malformed `#[v8_ffi(..)]` option, suggesting
+/// the closest known one.
+fn unknown_option(item: &NestedMeta) -> Error {
+    let name = match item {
+        NestedMeta::Meta(meta) => meta.path().get_ident().map(|x| x.to_string()),
+        NestedMeta::Lit(_) => None,
+    };
+    let name = match name {
+        Some(name) => name,
+        None => {
+            return error_help(
+                item,
+                "expected a v8_ffi option",
+                format!("the options are {}", option_list()),
+            )
+        }
+    };
+    if let Some((_, syntax)) = OPTIONS.iter().find(|(option, _)| *option == name) {
+        return error_help(
+            item,
+            format!("invalid `{}` option", name),
+            format!("write it as `{}`", syntax),
+        );
+    }
+    let closest = OPTIONS
+        .iter()
+        .map(|(option, syntax)| (edit_distance(&name, option), syntax))
+        .min_by_key(|(distance, _)| *distance)
+        .filter(|(distance, _)| *distance <= 2.max(name.len() / 3));
+    match closest {
+        Some((_, syntax)) => error_help(
+            item,
+            format!("unknown v8_ffi option `{}`", name),
+            format!("did you mean `{}`?", syntax),
+        ),
+        None => error_help(
+            item,
+            format!("unknown v8_ffi option `{}`", name),
+            format!("the options are {}", option_list()),
+        ),
+    }
+}
+
+fn option_list() -> String {
+    let options: Vec<String> = OPTIONS
+        .iter()
+        .map(|(_, syntax)| format!("`{}`", syntax))
+        .collect();
+    options.join(", ")
+}
+
+/// The Levenshtein distance of `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substituted = diagonal + (a != *b) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+fn parse_multi_return(list: &MetaList) -> Result<Vec<String>, Error> {
+    let mut names = vec![];
+    for item in list.nested.iter() {
+        match item {
+            NestedMeta::Meta(Meta::Path(path)) if path.get_ident().is_some() => {
+                names.push(camel_case(&path.get_ident().unwrap().to_string()));
+            }
+            NestedMeta::Lit(Lit::Str(name)) => names.push(name.value()),
+            _ => {
+                return Err(error_help(
+                    item,
+                    "multi_return expects field names",
+                    "name each element of the returned tuple, i.e. `multi_return(bytes_read, eof)`, or give a JS name as a string, i.e. `multi_return(\"bytesRead\", eof)`",
+                ));
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// An error at `tokens`, followed by a `help:` line suggesting a fix.
+fn error_help(tokens: impl ToTokens, message: impl Display, help: impl Display) -> Error {
+    Error::new_spanned(tokens, format!("{}\n\nhelp: {}", message, help))
+}
+
+/// The errors found in a `v8_ffi` fn, reported together.
+#[derive(Default)]
+struct Errors(Option<Error>);
+
+impl Errors {
+    fn push(&mut self, error: Error) {
+        match &mut self.0 {
+            Some(errors) => errors.combine(error),
+            None => self.0 = Some(error),
+        }
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        match self.0 {
+            Some(errors) => Err(errors),
+            None => Ok(()),
+        }
+    }
+}
+
+pub(crate) enum SimpleType {
+    /// A wrapped `this`, either a path or a `dyn Trait`.
+    This(bool, Type),
+    Type(Type),
+    /// `&str` or `&[u8]`, converted to the owned type which is kept alive
+    /// across the call and passed by reference. The flag marks `&str`.
+    Borrowed(Type, bool),
+}
+
+fn parse_simple_type(ty: &Type) -> SimpleType {
+    if let Type::Reference(TypeReference {
+        mutability: None,
+        elem,
+        ..
+    }) = ty
+    {
+        match &**elem {
+            Type::Path(TypePath { qself: None, path }) if path.is_ident("str") => {
+                return SimpleType::Borrowed(parse_quote!(::std::string::String), true);
+            }
+            Type::Slice(TypeSlice { elem, .. }) => {
+                if let Type::Path(TypePath { qself: None, path }) = &**elem {
+                    if path.is_ident("u8") {
+                        return SimpleType::Borrowed(parse_quote!(::rusty_v8_helper::Bytes), false);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    if let Type::Reference(TypeReference {
+        lifetime: None,
+        mutability,
+        elem,
+        ..
+    }) = ty
+    {
+        if let Type::TraitObject(_) = &**elem {
+            return SimpleType::This(mutability.is_some(), (**elem).clone());
+        }
+    }
+    match ty {
+        Type::Reference(TypeReference {
+            lifetime: None,
+            mutability,
+            elem,
+            ..
+        }) => match (mutability, &**elem) {
+            (
+                mutability,
+                Type::Path(TypePath {
+                    qself: None,
+                    path: x,
+                }),
+            ) => SimpleType::This(
+                mutability.is_some(),
+                Type::Path(TypePath {
+                    qself: None,
+                    path: x.clone(),
+                }),
+            ),
+            _ => SimpleType::Type(ty.clone()),
+        },
+        _ => SimpleType::Type(ty.clone()),
+    }
+}
+
+/// `ty` as written in source, i.e. `Vec<Option<String>>`, for `FfiFnMeta`.
+fn type_name(ty: &Type) -> String {
+    let tokens = quote!(#ty).to_string();
+    let mut name = String::new();
+    let mut chars = tokens.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != ' ' {
+            name.push(c);
+            continue;
+        }
+        let word = |c: &char| c.is_alphanumeric() || *c == '_' || *c == '\'';
+        let between_words = name.chars().last().map(|x| word(&x)).unwrap_or(false)
+            && chars.peek().map(word).unwrap_or(false);
+        let after_lifetime = name
+            .rsplit(|c: char| !word(&c))
+            .next()
+            .map(|x| x.starts_with('\''))
+            .unwrap_or(false)
+            && !matches!(chars.peek(), Some(',') | Some('>'));
+        let arrow = name.ends_with("->") || chars.peek() == Some(&'-');
+        if between_words || after_lifetime || arrow || name.ends_with([',', ';']) {
+            name.push(' ');
+        }
+    }
+    name
+}
+
+/// The module of `#[ffi(with = "path::to::module")]` among the attributes of
+/// a `v8_ffi` fn argument, whose `from_value` fn converts the argument in
+/// place of its `FFICompat` impl. `#[ffi(json)]` is the module
+/// `rusty_v8_helper::json`.
+fn ffi_with(attrs: &[Attribute]) -> Result<Option<Path>, Error> {
+    let mut module = None;
+    for attr in attrs.iter().filter(|x| x.path.is_ident("ffi")) {
+        let parsed = match attr.parse_meta() {
+            Ok(Meta::List(list)) if list.nested.len() == 1 => match list.nested.first() {
+                Some(NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                    path,
+                    lit: Lit::Str(path_str),
+                    ..
+                }))) if path.is_ident("with") => match path_str.parse::<Path>() {
+                    Ok(path) => Some(path),
+                    Err(_) => {
+                        return Err(error_help(
+                            path_str,
+                            format!("`{}` is not a module path", path_str.value()),
+                            "give the module whose `from_value` fn converts the argument, i.e. `#[ffi(with = \"rusty_v8_helper::range::array\")]`",
+                        ));
+                    }
+                },
+                Some(NestedMeta::Meta(Meta::Path(path))) if path.is_ident("json") => {
+                    Some(parse_quote! { ::rusty_v8_helper::json })
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        match parsed {
+            Some(parsed) => module = Some(parsed),
+            None => {
+                return Err(error_help(
+                    attr,
+                    "invalid `#[ffi]` attribute",
+                    "write `#[ffi(with = \"path::to::module\")]` to convert the argument with the module's `from_value` fn, or `#[ffi(json)]` to convert it through JSON",
+                ));
+            }
+        }
+    }
+    Ok(module)
+}
+
+/// `ty` with the borrows it contains replaced by owned types, i.e.
+/// `Option<String>` for `Option<&str>`, if it contains any.
+fn owned_type(ty: &Type) -> Option<Type> {
+    let mut owned = ty.clone();
+    if own_borrows(&mut owned) {
+        Some(owned)
+    } else {
+        None
+    }
+}
+
+fn own_borrows(ty: &mut Type) -> bool {
+    match ty {
+        Type::Reference(reference) => {
+            let owned = match &*reference.elem {
+                Type::Path(TypePath { qself: None, path }) if path.is_ident("str") => {
+                    parse_quote!(String)
+                }
+                Type::Slice(TypeSlice { elem, .. }) => match &**elem {
+                    Type::Path(TypePath { qself: None, path }) if path.is_ident("u8") => {
+                        parse_quote!(Bytes)
+                    }
+                    elem => parse_quote!(Vec<#elem>),
+                },
+                elem => elem.clone(),
+            };
+            *ty = owned;
+            own_borrows(ty);
+            true
+        }
+        Type::Path(TypePath { qself: None, path }) => {
+            let mut borrows = false;
+            for segment in path.segments.iter_mut() {
+                if let PathArguments::AngleBracketed(args) = &mut segment.arguments {
+                    for arg in args.args.iter_mut() {
+                        if let GenericArgument::Type(ty) = arg {
+                            borrows |= own_borrows(ty);
+                        }
+                    }
+                }
+            }
+            borrows
+        }
+        Type::Tuple(tuple) => tuple
+            .elems
+            .iter_mut()
+            .fold(false, |borrows, ty| own_borrows(ty) | borrows),
+        Type::Array(TypeArray { elem, .. })
+        | Type::Slice(TypeSlice { elem, .. })
+        | Type::Paren(TypeParen { elem, .. })
+        | Type::Group(TypeGroup { elem, .. }) => own_borrows(elem),
+        _ => false,
+    }
+}
+
+/// Whether `ty` is a primitive that `#[v8_ffi(coerce)]` converts with
+/// `Coerced`.
+fn is_coercible(ty: &Type) -> bool {
+    let ident = match ty {
+        Type::Path(TypePath { qself: None, path }) => path.get_ident(),
+        _ => None,
+    };
+    match ident {
+        Some(ident) => {
+            ["String", "bool", "f64", "i64", "u64", "i32", "u32"].contains(&&*ident.to_string())
+        }
+        None => false,
+    }
+}
+
+/// The `this` argument of a `v8_ffi` fn, unwrapped from the JS receiver.
+pub(crate) struct ThisArg {
+    pub name: Ident,
+    pub mutable: bool,
+    pub ty: Type,
+}
+
+/// An argument of a `v8_ffi` fn converted from a JS argument.
+pub(crate) struct FfiArg<'a> {
+    pub name: Ident,
+    pub ty: SimpleType,
+    /// The module of `#[ffi(with)]` or `#[ffi(json)]` converting the
+    /// argument.
+    pub with: Option<Path>,
+    /// The argument as written in source.
+    pub input: &'a PatType,
+}
+
+/// The checked signature of a `v8_ffi` fn: its wrapped `this`, whether it
+/// takes `scope` and `context`, and the arguments and return value
+/// converted to and from JS.
+pub(crate) struct FfiSignature<'a> {
+    pub sig: &'a Signature,
+    pub this: Option<ThisArg>,
+    pub scoped: bool,
+    pub args: Vec<FfiArg<'a>>,
+    pub return_type: Option<SimpleType>,
+}
+
+const SCOPED_SIGNATURE: &str =
+    "fn name<'sc>(scope: &mut impl ToLocal<'sc>, context: Local<Context>, ..)";
+
+impl<'a> FfiSignature<'a> {
+    /// Check that `sig` can be called from JS with `options`, reporting
+    /// every argument that cannot.
+    pub(crate) fn analyze(
+        options: &FfiOptions,
+        sig: &'a Signature,
+    ) -> Result<FfiSignature<'a>, Error> {
+        let mut errors = Errors::default();
+        if let Some(constness) = &sig.constness {
+            errors.push(error_help(
+                constness,
+                "const fn not allowed in v8_ffi",
+                "remove `const`, v8_ffi fns are only called at runtime",
+            ));
+        }
+        if let Some(asyncness) = &sig.asyncness {
+            errors.push(error_help(
+                asyncness,
+                "async fn not allowed in v8_ffi",
+                "return the `Promise` of `rusty_v8_helper::executor::spawn_local(scope, future)` from a `scoped` v8_ffi fn instead",
+            ));
+        }
+        if let Some(unsafety) = &sig.unsafety {
+            errors.push(error_help(
+                unsafety,
+                "unsafe fn not allowed in v8_ffi",
+                "JS cannot uphold the fn's safety contract, make it safe with an `unsafe` block in its body",
+            ));
+        }
+        if let Some(abi) = &sig.abi {
+            errors.push(error_help(
+                abi,
+                "extern fn not allowed in v8_ffi",
+                "remove the ABI, v8_ffi generates the callback V8 calls",
+            ));
+        }
+        if let Some(where_clause) = &sig.generics.where_clause {
+            errors.push(error_help(
+                where_clause,
+                "generics where clause not allowed in v8_ffi fn",
+                "remove the where clause, a v8_ffi fn may only be generic over lifetimes",
+            ));
+        }
+        for param in sig.generics.params.iter() {
+            if !matches!(param, GenericParam::Lifetime(_)) {
+                errors.push(error_help(
+                    param,
+                    "non-lifetime generics not allowed in v8_ffi fn",
+                    "JS arguments have no Rust type to infer it from, add a v8_ffi fn for each type, calling this one",
+                ));
+            }
+        }
+        if let Some(variadic) = &sig.variadic {
+            errors.push(error_help(
+                variadic,
+                "variadic not allowed in v8_ffi fn",
+                "take the remaining arguments as a `Vec`, and call the fn with an array",
+            ));
+        }
+        let mut args = vec![];
+        for input in sig.inputs.iter() {
+            let input = match input {
+                FnArg::Typed(input) => input,
+                FnArg::Receiver(receiver) => {
+                    errors.push(error_help(
+                        receiver,
+                        "self is not allowed in v8_ffi fn",
+                        "move the fn out of the impl block and take `this: &SomeType` as its first argument to use auto `ObjectWrap` unwrapping",
+                    ));
+                    continue;
+                }
+            };
+            let name = if let Pat::Ident(PatIdent {
+                by_ref: None,
+                subpat: None,
+                ident,
+                ..
+            }) = &*input.pat
+            {
+                ident.clone()
+            } else {
+                errors.push(error_help(
+                    &input.pat,
+                    "invalid non-ident argument name for v8_ffi fn",
+                    format!(
+                        "bind the argument to a name, i.e. `arg{}: {}`, and destructure it in the fn body",
+                        args.len(),
+                        type_name(&input.ty)
+                    ),
+                ));
+                continue;
+            };
+            for attr in input.attrs.iter() {
+                if attr.path.is_ident("cfg") || attr.path.is_ident("cfg_attr") {
+                    errors.push(error_help(
+                        attr,
+                        "`#[cfg]` is not supported on v8_ffi fn arguments",
+                        "JS arguments are passed by position, which a compiled out argument would shift, put the `#[cfg]` on a v8_ffi fn for each configuration instead",
+                    ));
+                }
+            }
+            let with = match ffi_with(&input.attrs) {
+                Ok(with) => with,
+                Err(e) => {
+                    errors.push(e);
+                    None
+                }
+            };
+            args.push(FfiArg {
+                name,
+                with,
+                ty: parse_simple_type(&input.ty),
+                input,
+            });
+        }
+        let return_type = match &sig.output {
+            ReturnType::Default => None,
+            ReturnType::Type(_, ty) => {
+                let return_type = parse_simple_type(ty);
+                if let SimpleType::This(_, _) = &return_type {
+                    errors.push(error_help(
+                        ty,
+                        "cannot return wrapped object from v8_ffi fn",
+                        match owned_type(ty).unwrap() {
+                            Type::TraitObject(_) => {
+                                "return an owned value, converted with its `FFICompat` impl"
+                                    .to_string()
+                            }
+                            owned => format!(
+                                "return `{}` by value, converted with its `FFICompat` impl",
+                                type_name(&owned)
+                            ),
+                        },
+                    ));
+                } else if let SimpleType::Borrowed(_, _) = &return_type {
+                    errors.push(error_help(
+                        ty,
+                        "cannot return borrowed value from v8_ffi fn",
+                        format!("return `{}` instead", type_name(&owned_type(ty).unwrap())),
+                    ));
+                } else if let Some(owned) = owned_type(ty) {
+                    errors.push(error_help(
+                        ty,
+                        "cannot return borrowed value from v8_ffi fn",
+                        format!("return `{}` instead", type_name(&owned)),
+                    ));
+                }
+                if let (Some(names), Type::Tuple(tuple)) = (&options.multi_return, &**ty) {
+                    if names.len() != tuple.elems.len() {
+                        errors.push(error_help(
+                            ty,
+                            format!(
+                                "multi_return names must match the returned tuple length, got {} names for {} elements",
+                                names.len(),
+                                tuple.elems.len()
+                            ),
+                            "give multi_return a name for each element of the tuple",
+                        ));
+                    }
+                }
+                Some(return_type)
+            }
+        };
+        if options.return_with.is_some() && return_type.is_none() {
+            errors.push(error_help(
+                &sig.ident,
+                "return_with v8_ffi fn must return a value",
+                "add a return type, or remove `return_with`",
+            ));
+        }
+        if options.return_with.is_some() && options.multi_return.is_some() {
+            errors.push(error_help(
+                &sig.ident,
+                "return_with and multi_return cannot be combined",
+                "remove `multi_return`, the `to_value` fn of the return_with module can build the object",
+            ));
+        }
+        if options.multi_return.is_some() && return_type.is_none() {
+            errors.push(error_help(
+                &sig.ident,
+                "multi_return v8_ffi fn must return a tuple",
+                "return a tuple with an element for each multi_return name, or remove `multi_return`",
+            ));
+        }
+
+        // arguments provided by the trampoline rather than converted from JS
+        let mut provided = vec![];
+        let mut this = None;
+        if let Some(first) = args.first() {
+            if let SimpleType::This(mutable, ty) = &first.ty {
+                if first.name == "this" {
+                    let mutable = *mutable;
+                    let ty = ty.clone();
+                    let is_this_of = match &ty {
+                        Type::Path(TypePath { path, .. }) => path
+                            .segments
+                            .last()
+                            .map(|x| x.ident == "ThisOf")
+                            .unwrap_or(false),
+                        _ => false,
+                    };
+                    if matches!(ty, Type::TraitObject(_)) && mutable {
+                        errors.push(error_help(
+                            &first.input.ty,
+                            "`this: &mut dyn Trait` is not supported in v8_ffi fn",
+                            format!("take `this: &{}`, with interior mutability", type_name(&ty)),
+                        ));
+                    }
+                    if is_this_of && mutable {
+                        errors.push(error_help(
+                            &first.input.ty,
+                            "`this: &mut ThisOf<..>` is not supported in v8_ffi fn",
+                            format!("take `this: &{}`, of `Mutex`es", type_name(&ty)),
+                        ));
+                    }
+                    this = Some(ThisArg {
+                        name: first.name.clone(),
+                        mutable,
+                        ty,
+                    });
+                    provided.push(args.remove(0));
+                }
+            }
+        }
+        if options.scoped {
+            if args.len() < 2 {
+                errors.push(error_help(
+                    &sig.ident,
+                    "scoped function must have at least 2 arguments: scope, context",
+                    format!("add them before the JS arguments, `{}`", SCOPED_SIGNATURE),
+                ));
+            } else {
+                let input0_name = args[0].name.to_string();
+                let input1_name = args[1].name.to_string();
+                if !(input0_name == "scope" || input0_name == "_scope")
+                    || !(input1_name == "context" || input1_name == "_context")
+                {
+                    let (scope, context) = (args[0].input, args[1].input);
+                    errors.push(error_help(
+                        quote! { #scope, #context },
+                        "scoped function's first two arguments must be named: scope, context",
+                        format!("write them as `{}`", SCOPED_SIGNATURE),
+                    ));
+                }
+                provided.extend(args.drain(..2));
+            }
+        } else if args.len() >= 2
+            && ["scope", "_scope"].contains(&&*args[0].name.to_string())
+            && ["context", "_context"].contains(&&*args[1].name.to_string())
+        {
+            let (scope, context) = (args[0].input, args[1].input);
+            errors.push(error_help(
+                quote! { #scope, #context },
+                "`scope` and `context` are only passed to scoped v8_ffi fns",
+                "add `scoped`, i.e. `#[v8_ffi(scoped)]`",
+            ));
+            args.drain(..2);
+        }
+        if let Some(arg) = provided.iter().find(|x| x.with.is_some()) {
+            let attr = arg
+                .input
+                .attrs
+                .iter()
+                .find(|x| x.path.is_ident("ffi"))
+                .unwrap();
+            errors.push(error_help(
+                attr,
+                format!("`#[ffi]` is not supported on `{}`", arg.name),
+                format!(
+                    "remove it, `{}` is not converted from a JS argument",
+                    arg.name
+                ),
+            ));
+        }
+        for arg in args.iter() {
+            let ty = &arg.input.ty;
+            let name = &arg.name;
+            if let SimpleType::This(_, wrapped) = &arg.ty {
+                let help = if arg.name == "this" {
+                    format!(
+                        "move `this: {}` to be the first argument to unwrap the JS receiver",
+                        type_name(ty)
+                    )
+                } else if this.is_none() && std::ptr::eq(arg, &args[0]) && !options.scoped {
+                    format!(
+                        "rename `{}` to `this` to unwrap the JS receiver as an `ObjectWrap` of `{}`, or take `{}` by value",
+                        name,
+                        type_name(wrapped),
+                        type_name(wrapped)
+                    )
+                } else {
+                    format!("take `{}` by value", type_name(wrapped))
+                };
+                errors.push(error_help(
+                    ty,
+                    format!("cannot borrow `{}` from a JS argument", type_name(ty)),
+                    format!(
+                        "{}; arguments are converted with their `FFICompat` impl, only `&str`, `&[u8]` and a wrapped `this` are borrowed",
+                        help
+                    ),
+                ));
+            } else if let SimpleType::Type(inner) = &arg.ty {
+                if let Type::ImplTrait(_) = inner {
+                    errors.push(error_help(
+                        ty,
+                        "`impl Trait` arguments are not supported in v8_ffi fn",
+                        "take a concrete type implementing `FFICompat`",
+                    ));
+                } else if let Some(owned) = owned_type(inner) {
+                    errors.push(error_help(
+                        ty,
+                        format!("cannot borrow `{}` from a JS argument", type_name(ty)),
+                        format!(
+                            "take `{}` instead; arguments are converted with their `FFICompat` impl, only `&str`, `&[u8]` and a wrapped `this` are borrowed",
+                            type_name(&owned)
+                        ),
+                    ));
+                }
+            }
+        }
+        errors.finish()?;
+
+        Ok(FfiSignature {
+            sig,
+            this,
+            scoped: options.scoped,
+            args,
+            return_type,
+        })
+    }
+
+    /// The JS arguments, with their types as written in source.
+    fn js_params(&self) -> impl Iterator<Item = (&FfiArg<'a>, &Type)> {
+        self.args.iter().map(|arg| (arg, &*arg.input.ty))
+    }
+}
+
+/// Unwrap the `this` of `sig` from the JS receiver of the call.
+pub(crate) fn this_prelude(sig: &FfiSignature) -> Option<TokenStream2> {
+    let ThisArg { name, mutable, ty } = sig.this.as_ref()?;
+    let function_name = sig.sig.ident.to_string();
+    let is_dyn = matches!(ty, Type::TraitObject(_));
+    let is_this_of = match ty {
+        Type::Path(TypePath { path, .. }) => path
+            .segments
+            .last()
+            .map(|x| x.ident == "ThisOf")
+            .unwrap_or(false),
+        _ => false,
+    };
+    let prelude = if is_this_of {
+        quote! {
+            let #name: ::std::result::Result<#ty, _> = ::rusty_v8_helper::FromThis::from_this(__v8_ffi_args.this());
+            if let Err(e) = &#name {
+                ::rusty_v8_helper::util::throw_invalid_this(__v8_ffi_scope, &__v8_ffi_call, #function_name, e);
+                return;
+            }
+            let #name = #name.unwrap();
+            let #name = &#name;
+        }
+    } else if is_dyn {
+        quote! {
+            let #name: ::std::result::Result<::std::rc::Rc<::std::rc::Rc<#ty>>, _> = ::rusty_v8_helper::ObjectWrap::try_from_object(__v8_ffi_args.this());
+            if let Err(e) = &#name {
+                ::rusty_v8_helper::util::throw_invalid_this(__v8_ffi_scope, &__v8_ffi_call, #function_name, e);
+                return;
+            }
+            let #name = #name.unwrap();
+            let #name: &#ty = &**#name;
+        }
+    } else if *mutable {
+        quote! {
+            let #name: ::std::result::Result<::std::rc::Rc<::std::sync::Mutex<#ty>>, _> = ::rusty_v8_helper::ObjectWrap::try_from_object(__v8_ffi_args.this());
+            if let Err(e) = &#name {
+                ::rusty_v8_helper::util::throw_invalid_this(__v8_ffi_scope, &__v8_ffi_call, #function_name, e);
+                return;
+            }
+            let #name = #name.unwrap();
+            let #name = ::rusty_v8_helper::ThisGuard::lock(&#name, #function_name);
+            if let Err(e) = &#name {
+                ::rusty_v8_helper::util::throw_call_error(__v8_ffi_scope, &__v8_ffi_call, e);
+                return;
+            }
+            let mut #name = #name.unwrap();
+            let mut #name = &mut #name;
+        }
+    } else {
+        quote! {
+            let #name: ::std::result::Result<::std::rc::Rc<#ty>, _> = ::rusty_v8_helper::ObjectWrap::try_from_object(__v8_ffi_args.this());
+            if let Err(e) = &#name {
+                ::rusty_v8_helper::util::throw_invalid_this(__v8_ffi_scope, &__v8_ffi_call, #function_name, e);
+                return;
+            }
+            let #name = #name.unwrap();
+            let #name = &#name;
+        }
+    };
+    Some(prelude)
+}
+
+/// Convert the JS arguments of the call to the arguments of `sig`,
+/// throwing and returning on the first that fails.
+pub(crate) fn arg_preludes(options: &FfiOptions, sig: &FfiSignature) -> TokenStream2 {
+    let coerce = options.coerce;
+    let mut preludes: Vec<TokenStream2> = vec![];
+    for (i, arg) in sig.args.iter().enumerate() {
+        let name = &arg.name;
+        let i = i as i32;
+        let convert = match (&arg.with, &arg.ty) {
+            (Some(module), _) => quote! {
+                #module::from_value(#name, __v8_ffi_scope, __v8_ffi_context)
+            },
+            (None, SimpleType::This(_, _)) => continue,
+            (None, SimpleType::Borrowed(owned, is_str)) => {
+                if coerce && *is_str {
+                    quote! {
+                        <::rusty_v8_helper::Coerced<#owned> as ::rusty_v8_helper::FFICompat>::from_value(#name, __v8_ffi_scope, __v8_ffi_context).map(|x| x.0)
+                    }
+                } else {
+                    quote! {
+                        <#owned as ::rusty_v8_helper::FFICompat>::from_value(#name, __v8_ffi_scope, __v8_ffi_context)
+                    }
+                }
+            }
+            (None, SimpleType::Type(ty)) if coerce && is_coercible(ty) => quote! {
+                <::rusty_v8_helper::Coerced<#ty> as ::rusty_v8_helper::FFICompat>::from_value(#name, __v8_ffi_scope, __v8_ffi_context).map(|x| x.0)
+            },
+            // spanned at the type, where a missing conversion is reported
+            (None, SimpleType::Type(ty)) => quote_spanned! { ty.span() =>
+                (&<::rusty_v8_helper::owned::Dispatch<#ty>>::default()).from_js(#name, __v8_ffi_scope, __v8_ffi_context)
+            },
+        };
+        preludes.push(quote! {
+            let #name = __v8_ffi_args.get(#i);
+            let #name = #convert;
+            if let Err(e) = #name {
+                ::rusty_v8_helper::util::throw_conversion_error(__v8_ffi_scope, &__v8_ffi_call, &e);
+                return;
+            }
+            let #name = #name.unwrap();
+        });
+    }
+    preludes.into_iter().collect()
+}
+
+/// The arguments passing the converted values to the fn of `sig`.
+pub(crate) fn call_args(sig: &FfiSignature) -> TokenStream2 {
+    let mut arg_names: Vec<TokenStream2> = vec![];
+    if let Some(this) = &sig.this {
+        let name = &this.name;
+        arg_names.push(quote! { #name, });
+    }
+    if sig.scoped {
+        arg_names.push(quote! { __v8_ffi_scope, });
+        arg_names.push(quote! { __v8_ffi_context, });
+    }
+    for arg in sig.args.iter() {
+        let name = &arg.name;
+        if let SimpleType::Borrowed(_, _) = &arg.ty {
+            arg_names.push(quote! { &#name, })
+        } else {
+            arg_names.push(quote! { #name, })
+        }
+    }
+    arg_names.into_iter().collect()
+}
+
+/// Convert `__returned` and set it as the return value of the call.
+pub(crate) fn return_postlude(options: &FfiOptions, sig: &FfiSignature) -> Option<TokenStream2> {
+    let return_type = match &sig.return_type {
+        Some(SimpleType::Type(ty)) => ty,
+        _ => return None,
+    };
+    let throw_return_error = if options.return_undefined_on_error {
+        quote! {
+            __v8_ffi_call.exception(&e);
+        }
+    } else {
+        quote! {
+            ::rusty_v8_helper::util::throw_call_error(__v8_ffi_scope, &__v8_ffi_call, &e);
+        }
+    };
+    let convert_return = match (&options.multi_return, &options.return_with) {
+        (Some(names), _) => quote! {
+            ::rusty_v8_helper::js_object::MultiReturn::to_object(__returned, __v8_ffi_scope, __v8_ffi_context, &[#(#names),*])
+        },
+        (None, Some(module)) => quote! {
+            #module::to_value(__returned, __v8_ffi_scope, __v8_ffi_context)
+        },
+        (None, None) => quote_spanned! { return_type.span() =>
+            (&::rusty_v8_helper::owned::Dispatch::of(&__returned)).to_js(__returned, __v8_ffi_scope, __v8_ffi_context)
+        },
+    };
+    Some(quote! {
+        let __v8_ffi_value = #convert_return;
+        match __v8_ffi_value {
+            Ok(__v8_ffi_value) => __v8_ffi_rv.set(__v8_ffi_value),
+            Err(e) => {
+                __v8_ffi_rv.set(::rusty_v8_helper::v8::undefined(__v8_ffi_scope).into());
+                #throw_return_error
+                return;
+            }
+        }
+    })
+}
+
+/// The `FfiFnMeta` describing the fn of `sig` to JS tooling.
+fn meta(sig: &FfiSignature, attrs: &[Attribute]) -> TokenStream2 {
+    let name = sig.sig.ident.to_string();
+    let params = sig
+        .js_params()
+        .map(|(arg, ty)| {
+            let name = arg.name.to_string();
+            let ty = type_name(ty);
+            quote! { ::rusty_v8_helper::FfiParam { name: #name, ty: #ty } }
+        })
+        .collect::<Vec<TokenStream2>>();
+    let arity = sig.args.len();
+    let returns = match &sig.sig.output {
+        ReturnType::Default => "()".to_string(),
+        ReturnType::Type(_, ty) => type_name(ty),
+    };
+    let doc = doc_string(attrs);
+    quote! {
+        ::rusty_v8_helper::FfiFnMeta {
+            name: #name,
+            arity: #arity,
+            params: &[#(#params),*],
+            returns: #returns,
+            doc: #doc,
+        }
+    }
+}
+
+/// The deno_core JSON op of a `#[v8_ffi(deno_op)]` fn.
+fn deno_op(
+    options: &FfiOptions,
+    sig: &FfiSignature,
+    vis: &Visibility,
+) -> Result<Option<TokenStream2>, Error> {
+    if !options.deno_op {
+        return Ok(None);
+    }
+    if sig.scoped || sig.this.is_some() {
+        return Err(error_help(
+            &sig.sig.ident,
+            "deno_op v8_ffi fn cannot be scoped or take a wrapped `this`",
+            "deno ops are called with JSON arguments only, remove `deno_op` or move the op into a separate fn",
+        ));
+    }
+    if let Some(arg) = sig.args.iter().find(|x| x.with.is_some()) {
+        return Err(error_help(
+            arg.input.attrs.iter().find(|x| x.path.is_ident("ffi")),
+            "deno_op v8_ffi fn cannot convert with `#[ffi(with)]`",
+            "deno op arguments are deserialized from JSON, remove `#[ffi]` and implement `Deserialize` for the argument type",
+        ));
+    }
+    if options.return_with.is_some() {
+        return Err(error_help(
+            &sig.sig.ident,
+            "deno_op v8_ffi fn cannot convert with return_with",
+            "deno op return values are serialized to JSON, remove `return_with` and implement `Serialize` for the return type",
+        ));
+    }
+    let original_ident = &sig.sig.ident;
+    let deno_ident = Ident::new(
+        &format!("__v8_ffi_deno_{}", original_ident),
+        original_ident.span(),
+    );
+    let mut deno_args: Vec<TokenStream2> = vec![];
+    for (i, arg) in sig.args.iter().enumerate() {
+        let name = &arg.name;
+        let ty = match &arg.ty {
+            SimpleType::Borrowed(_, true) => quote! { ::std::string::String },
+            SimpleType::Borrowed(_, false) => quote! { ::std::vec::Vec<u8> },
+            SimpleType::Type(ty) => quote! { #ty },
+            SimpleType::This(_, _) => unreachable!(),
+        };
+        deno_args.push(quote! {
+            let #name: #ty = ::rusty_v8_helper::deno::op_arg(&__v8_ffi_deno_args, #i)?;
+        });
+    }
+    let arg_names = call_args(sig);
+    Ok(Some(quote! {
+        #vis fn #deno_ident(__v8_ffi_deno_args: ::rusty_v8_helper::deno::Value) -> ::std::result::Result<::rusty_v8_helper::deno::Value, ::rusty_v8_helper::FFIError> {
+            #(#deno_args)*
+            let __returned = #original_ident(#arg_names);
+            ::rusty_v8_helper::deno::OpReturn::into_op_value(__returned)
+        }
+    }))
+}
+
+/// The attributes of a `v8_ffi` fn carried over to the items generated for
+/// it, so that they are compiled with it: `cfg`s and lint levels for all
+/// of them, and also `cfg_attr`s, docs and `inline` for the generated fns.
+fn passthrough(attrs: &[Attribute]) -> (Vec<&Attribute>, Vec<&Attribute>) {
+    let is_any = |attr: &Attribute, names: &[&str]| names.iter().any(|x| attr.path.is_ident(x));
+    let item_attrs = attrs
+        .iter()
+        .filter(|x| is_any(x, &["cfg", "allow", "warn", "deny"]))
+        .collect();
+    let fn_attrs = attrs
+        .iter()
+        .filter(|x| {
+            is_any(
+                x,
+                &["cfg", "allow", "warn", "deny", "cfg_attr", "doc", "inline"],
+            )
+        })
+        .collect();
+    (item_attrs, fn_attrs)
+}
+
+/// Expand `#[v8_ffi]` on `ast`: the fn itself, its trampoline, the
+/// `FfiFn` struct named like it, and the entry points of its options.
+pub(crate) fn expand(options: &FfiOptions, ast: &ItemFn) -> Result<TokenStream2, Error> {
+    let sig = FfiSignature::analyze(options, &ast.sig)?;
+    let vis = &ast.vis;
+    let loader_vis = options.loader_vis.as_ref().unwrap_or(vis);
+    let deno_op = deno_op(options, &sig, loader_vis)?;
+    let this_prelude = this_prelude(&sig);
+    let arg_preludes = arg_preludes(options, &sig);
+    let arg_names = call_args(&sig);
+    let return_postlude = return_postlude(options, &sig);
+    let meta = meta(&sig, &ast.attrs);
+    let (item_attrs, fn_attrs) = passthrough(&ast.attrs);
+    let deno_op = deno_op.map(|deno_op| quote! { #(#fn_attrs)* #deno_op });
+
+    let original_ident = &ast.sig.ident;
+    let original_name = original_ident.to_string();
+    let ffi_internal_ident = Ident::new(
+        &format!("__v8_ffi_internal_{}", original_ident),
+        original_ident.span(),
+    );
+    let ffi_ident = Ident::new(
+        &format!("__v8_ffi_{}", original_ident),
+        original_ident.span(),
+    );
+
+    // `#[ffi(..)]` is only meaningful to this macro
+    let mut item = ast.clone();
+    for input in item.sig.inputs.iter_mut() {
+        if let FnArg::Typed(input) = input {
+            input.attrs.retain(|x| !x.path.is_ident("ffi"));
+        }
+    }
+
+    Ok(quote! {
+        #item
+
+        #(#fn_attrs)*
+        fn #ffi_internal_ident<'sc>(mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>, __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>, mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>) {
+            #[allow(unused_imports)]
+            use ::rusty_v8_helper::owned::{ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _};
+            let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+            let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(__v8_ffi_scope, #original_name);
+            if !::rusty_v8_helper::policy::check_policy(__v8_ffi_scope, __v8_ffi_context, &__v8_ffi_call, #original_name) {
+                return;
+            }
+            let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(__v8_ffi_scope, __v8_ffi_context);
+            #this_prelude
+            #arg_preludes
+            let __returned = #original_ident(#arg_names);
+            #return_postlude
+        }
+
+        #(#fn_attrs)*
+        #loader_vis fn #ffi_ident<'sc, 'c>(__v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>, __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+            ::rusty_v8_helper::v8::Function::new(
+                __v8_ffi_scope,
+                __v8_ffi_context,
+                #ffi_internal_ident,
+            ).unwrap()
+        }
+
+        #(#item_attrs)*
+        #[doc(hidden)]
+        #[allow(non_camel_case_types, dead_code)]
+        #loader_vis struct #original_ident {}
+
+        #(#item_attrs)*
+        impl ::rusty_v8_helper::FfiFn for #original_ident {
+            const NAME: &'static str = #original_name;
+
+            const META: ::rusty_v8_helper::FfiFnMeta = #meta;
+
+            fn load<'sc, 'c>(__v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>, __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+                #ffi_ident(__v8_ffi_scope, __v8_ffi_context)
+            }
+
+            fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+                ::rusty_v8_helper::v8::MapFnTo::map_fn_to(#ffi_internal_ident)
+            }
+
+            fn template<'sc>(__v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+                ::rusty_v8_helper::v8::FunctionTemplate::new(__v8_ffi_scope, #ffi_internal_ident)
+            }
+        }
+
+        #deno_op
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Expand the `#[v8_ffi]` fns of the source `file`, as `v8_ffi` would.
+    fn expand_file(file: &str) -> String {
+        let mut file = parse_file(file).unwrap();
+        let mut items = vec![];
+        for item in file.items.drain(..) {
+            let mut item = match item {
+                Item::Fn(item) => item,
+                item => {
+                    items.push(item);
+                    continue;
+                }
+            };
+            let attr = match item.attrs.iter().position(|x| x.path.is_ident("v8_ffi")) {
+                Some(i) => item.attrs.remove(i),
+                None => {
+                    items.push(Item::Fn(item));
+                    continue;
+                }
+            };
+            let metadata: Vec<NestedMeta> = match attr.parse_meta().unwrap() {
+                Meta::Path(_) => vec![],
+                Meta::List(list) => list.nested.into_iter().collect(),
+                Meta::NameValue(_) => panic!("invalid #[v8_ffi] in expansion test"),
+            };
+            let gen = FfiOptions::parse(&metadata)
+                .and_then(|options| expand(&options, &item))
+                .unwrap_or_else(|e| panic!("{} failed to expand: {}", item.sig.ident, e));
+            items.extend(parse2::<File>(gen).unwrap().items);
+        }
+        file.items = items;
+        prettyplease::unparse(&file)
+    }
+
+    /// Compare the expansion of every `tests/expand/*.rs` to its
+    /// `*.expanded.rs`, or write it there with `MACROTEST=overwrite`.
+    #[test]
+    fn expansion_snapshots() {
+        let overwrite = std::env::var("MACROTEST").map(|x| x == "overwrite") == Ok(true);
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/expand");
+        let mut sources = fs::read_dir(&dir)
+            .unwrap()
+            .map(|x| x.unwrap().path())
+            .filter(|x| !x.to_string_lossy().ends_with(".expanded.rs"))
+            .collect::<Vec<_>>();
+        sources.sort();
+        assert!(!sources.is_empty());
+        let mut mismatched = vec![];
+        for source in sources {
+            let expanded = expand_file(&fs::read_to_string(&source).unwrap());
+            let snapshot = source.with_extension("expanded.rs");
+            if overwrite || !snapshot.exists() {
+                fs::write(&snapshot, &expanded).unwrap();
+            } else if fs::read_to_string(&snapshot).unwrap() != expanded {
+                mismatched.push(snapshot.display().to_string());
+            }
+        }
+        assert!(
+            mismatched.is_empty(),
+            "expansions differ from {}, rerun with MACROTEST=overwrite to update them",
+            mismatched.join(", ")
+        );
+    }
+}
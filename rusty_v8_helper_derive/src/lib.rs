@@ -4,396 +4,961 @@ extern crate quote;
 extern crate proc_macro;
 use crate::proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
+use proc_macro2::{Delimiter, Spacing, TokenTree};
 use proc_macro_hack::proc_macro_hack;
 use quote::quote;
 use std::result::Result;
 use syn::parse::Parser;
 use syn::*;
 
+mod ffi;
+
+use ffi::FfiOptions;
+
+/// Convert a snake_case Rust identifier to a camelCase JS property name.
+fn camel_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.trim_start_matches("r#").chars() {
+        if c == '_' && !out.is_empty() {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
 #[proc_macro_attribute]
 pub fn v8_ffi(metadata: TokenStream, input: TokenStream) -> TokenStream {
-    let parser = punctuated::Punctuated::<Ident, Token![,]>::parse_terminated;
-    let ast = parser.parse(metadata).unwrap();
-    let inner = ast
-        .into_iter()
-        .map(|i| format!("{}", i))
-        .collect::<Vec<String>>();
-    let mut scoped = false;
-    for flag in inner {
-        if flag == "scoped" {
-            scoped = true;
+    let metadata = parse_macro_input!(metadata as AttributeArgs);
+    let options = match FfiOptions::parse(&metadata) {
+        Ok(options) => options,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let ast = parse_macro_input!(input as ItemFn);
+    match ffi::expand(&options, &ast) {
+        Ok(gen) => gen.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Rewrite the path to a `v8_ffi` fn to the path of its generated
+/// `<prefix><name>` counterpart.
+fn generated_fn_path(function_ref: &Expr, prefix: &str) -> Result<Expr, TokenStream> {
+    match function_ref {
+        Expr::Path(ExprPath { path, qself, attrs }) => {
+            let mut new_path = path.clone();
+            let func_name = new_path.segments.last_mut().unwrap();
+            let ffi_ident = Ident::new(
+                &format!("{}{}", prefix, func_name.ident),
+                func_name.ident.span(),
+            );
+            func_name.ident = ffi_ident;
+            Ok(Expr::Path(ExprPath {
+                path: new_path,
+                qself: qself.clone(),
+                attrs: attrs.clone(),
+            }))
+        }
+        _ => Err(quote! {
+            compile_error!("expected path for ffi function reference");
         }
+        .into()),
     }
-    let ast = parse_macro_input!(input as ItemFn);
-    impl_v8_ffi(scoped, &ast)
 }
 
+/// `load_v8_ffi!(function, scope, context)` is the JS function for a
+/// `#[v8_ffi]` fn. `load_v8_ffi!(function, scope, context, target, path)`
+/// instead sets it at the dotted `path` below the `target` object, i.e.
+/// `"fs.promises.readFile"`, creating intermediate objects as needed, and
+/// is a `Result<(), FFIError>`.
 #[proc_macro_hack]
 pub fn load_v8_ffi(input: TokenStream) -> TokenStream {
     let parser = punctuated::Punctuated::<Expr, Token![,]>::parse_terminated;
     let ast = parser.parse(input).unwrap();
     let inner = ast.into_iter().collect::<Vec<Expr>>();
-    if inner.len() != 3 {
+    if inner.len() != 3 && inner.len() != 5 {
         return quote! {
-            compile_error!("invalid call to load_v8_ffi, expected args: ffi function reference, scope, context");
+            compile_error!("invalid call to load_v8_ffi, expected args: ffi function reference, scope, context[, target object, \"dotted.path\"]");
         }.into();
     }
     let function_ref = &inner[0];
     let scope_ref = &inner[1];
     let context_ref = &inner[2];
-    let function_ref = match function_ref {
-        Expr::Path(ExprPath { path, qself, attrs }) => {
-            let mut new_path = path.clone();
-            let func_name = new_path.segments.last_mut().unwrap();
-            let ffi_ident = Ident::new(
-                &format!("__v8_ffi_{}", func_name.ident),
-                func_name.ident.span(),
-            );
-            func_name.ident = ffi_ident;
-            Expr::Path(ExprPath {
-                path: new_path,
-                qself: qself.clone(),
-                attrs: attrs.clone(),
-            })
+    if !matches!(function_ref, Expr::Path(_)) {
+        return quote! {
+            compile_error!("expected path for ffi function reference");
         }
-        _ => {
-            return quote! {
-                compile_error!("expected path for ffi function reference");
+        .into();
+    }
+    // resolved through the `FfiFn` struct named like the fn, which follows
+    // the fn through re-exports
+    let load = quote! {
+        ::rusty_v8_helper::load_ffi_fn::<#function_ref>(#scope_ref, #context_ref)
+    };
+    if inner.len() == 5 {
+        let target_ref = &inner[3];
+        let path_ref = &inner[4];
+        return quote! {
+            {
+                let __v8_ffi_function = #load;
+                ::rusty_v8_helper::util::set_path(#scope_ref, #context_ref, #target_ref, #path_ref, __v8_ffi_function.into())
             }
-            .into();
         }
-    };
-    return quote! { #function_ref(#scope_ref, #context_ref).into() }.into();
+        .into();
+    }
+    return quote! { #load.into() }.into();
 }
 
-enum SimpleType {
-    This(bool, Path),
-    Type(Type),
+/// `load_deno_op!(function)` is the deno_core JSON op generated for a
+/// `#[v8_ffi(deno_op)]` fn, to pass to `rusty_v8_helper::deno::register_op`.
+#[proc_macro_hack]
+pub fn load_deno_op(input: TokenStream) -> TokenStream {
+    let function_ref = parse_macro_input!(input as Expr);
+    match generated_fn_path(&function_ref, "__v8_ffi_deno_") {
+        Ok(function_ref) => quote! { #function_ref }.into(),
+        Err(e) => e,
+    }
 }
 
-fn parse_simple_type(ty: &Type) -> SimpleType {
-    match ty {
-        Type::Reference(TypeReference {
-            lifetime: None,
-            mutability,
-            elem,
-            ..
-        }) => match (mutability, &**elem) {
-            (
-                mutability,
-                Type::Path(TypePath {
-                    qself: None,
-                    path: x,
-                }),
-            ) => {
-                return SimpleType::This(mutability.is_some(), x.clone());
+/// `#[js_class]` turns a trait describing the methods of a JS object into
+/// a struct wrapping a `Global<Object>`, with a typed method calling into
+/// JS for each trait method. Methods marked `#[getter]` read a property
+/// instead, and `#[js_name = "..."]` sets the JS name to use.
+#[proc_macro_attribute]
+pub fn js_class(_metadata: TokenStream, input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as ItemTrait);
+    impl_js_class(&ast)
+}
+
+fn impl_js_class(ast: &ItemTrait) -> TokenStream {
+    let vis = &ast.vis;
+    let ident = &ast.ident;
+    let class_name = ident.to_string();
+    let doc_attrs = ast.attrs.iter().filter(|x| x.path.is_ident("doc"));
+    let mut methods: Vec<TokenStream2> = vec![];
+    for item in ast.items.iter() {
+        let method = match item {
+            TraitItem::Method(method) => method,
+            _ => {
+                return quote_spanned! {
+                    ident.span() =>
+                    compile_error!("only methods are allowed in js_class");
+                }
+                .into();
             }
+        };
+        let sig = &method.sig;
+        match sig.inputs.first() {
+            Some(FnArg::Receiver(Receiver {
+                reference: Some(_),
+                mutability: None,
+                ..
+            })) => (),
             _ => {
-                return SimpleType::Type(ty.clone());
+                return quote_spanned! {
+                    sig.ident.span() =>
+                    compile_error!("js_class methods must take `&self` as first argument");
+                }
+                .into();
+            }
+        }
+        let mut getter = false;
+        let mut js_name = sig.ident.to_string();
+        for attr in method.attrs.iter() {
+            if attr.path.is_ident("getter") {
+                getter = true;
+            } else if attr.path.is_ident("js_name") {
+                match attr.parse_meta() {
+                    Ok(Meta::NameValue(MetaNameValue {
+                        lit: Lit::Str(name),
+                        ..
+                    })) => js_name = name.value(),
+                    _ => {
+                        return quote_spanned! {
+                            sig.ident.span() =>
+                            compile_error!("expected `#[js_name = \"name\"]`");
+                        }
+                        .into();
+                    }
+                }
             }
-        },
-        _ => {
-            return SimpleType::Type(ty.clone());
         }
+        let mut arg_names: Vec<Ident> = vec![];
+        let mut arg_types: Vec<&Type> = vec![];
+        for input in sig.inputs.iter().skip(1) {
+            match input {
+                FnArg::Typed(PatType { pat, ty, .. }) => match &**pat {
+                    Pat::Ident(PatIdent { ident, .. }) => {
+                        arg_names.push(ident.clone());
+                        arg_types.push(ty);
+                    }
+                    _ => {
+                        return quote_spanned! {
+                            sig.ident.span() =>
+                            compile_error!("invalid non-ident argument name for js_class method");
+                        }
+                        .into();
+                    }
+                },
+                FnArg::Receiver(_) => unreachable!(),
+            }
+        }
+        if getter && !arg_names.is_empty() {
+            return quote_spanned! {
+                sig.ident.span() =>
+                compile_error!("js_class getters cannot take arguments");
+            }
+            .into();
+        }
+        let return_type = match &sig.output {
+            ReturnType::Default => quote! { () },
+            ReturnType::Type(_, ty) => quote! { #ty },
+        };
+        let body = if getter {
+            quote! {
+                ::rusty_v8_helper::js_class::get_property(scope, context, &self.object, #js_name)
+            }
+        } else {
+            quote! {
+                let __js_args = vec![#(
+                    ::rusty_v8_helper::FFICompat::to_value(#arg_names, scope, context)
+                        .map_err(|e| ::rusty_v8_helper::FFIError::TypeError(::std::format!("{:?}", e)))?,
+                )*];
+                ::rusty_v8_helper::js_class::call_method(scope, context, &self.object, #js_name, &__js_args)
+            }
+        };
+        let method_ident = &sig.ident;
+        let method_docs = method.attrs.iter().filter(|x| x.path.is_ident("doc"));
+        methods.push(quote! {
+            #(#method_docs)*
+            pub fn #method_ident<'sc, 'c>(
+                &self,
+                scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+                context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+                #(#arg_names: #arg_types,)*
+            ) -> ::std::result::Result<#return_type, ::rusty_v8_helper::FFIError> {
+                #body
+            }
+        });
     }
+
+    let gen = quote! {
+        #(#doc_attrs)*
+        #vis struct #ident {
+            object: ::rusty_v8_helper::v8::Global<::rusty_v8_helper::v8::Object>,
+        }
+
+        impl #ident {
+            pub fn new<'sc>(
+                scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+                object: ::rusty_v8_helper::v8::Local<::rusty_v8_helper::v8::Object>,
+            ) -> Self {
+                #ident {
+                    object: ::rusty_v8_helper::v8::Global::new_from(scope, object),
+                }
+            }
+
+            /// The wrapped JS object.
+            pub fn object<'sc>(
+                &self,
+                scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+            ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Object> {
+                self.object.get(scope).unwrap()
+            }
+
+            #(#methods)*
+        }
+
+        impl<'sc, 'c> ::rusty_v8_helper::FFICompat<'sc, 'c> for #ident {
+            type E = ::rusty_v8_helper::FFIError;
+
+            fn from_value(
+                value: ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Value>,
+                scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+                _context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+            ) -> ::std::result::Result<Self, ::rusty_v8_helper::FFIError> {
+                ::rusty_v8_helper::js_class::object_from_value(value, scope, #class_name)
+                    .map(|object| #ident { object })
+            }
+
+            fn to_value(
+                self,
+                scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+                _context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+            ) -> ::std::result::Result<::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Value>, ::rusty_v8_helper::FFIError> {
+                ::std::result::Result::Ok(self.object.get(scope).unwrap().into())
+            }
+        }
+    };
+    gen.into()
 }
 
-fn impl_v8_ffi(scoped: bool, ast: &ItemFn) -> TokenStream {
-    let sig = &ast.sig;
-    if sig.constness.is_some() {
+/// `#[derive(FromJsObject)]` implements `FFICompat` for a struct with named
+/// fields by reading each field from the property of the same name (or
+/// `#[js_name = "..."]`) of a JS object, reporting every missing or invalid
+/// field in a single error. Fields marked `#[js_default]` fall back to
+/// `Default::default()` when undefined.
+#[proc_macro_derive(FromJsObject, attributes(js_name, js_default, js_rename_all))]
+pub fn from_js_object(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    impl_from_js_object(&ast)
+}
+
+fn impl_from_js_object(ast: &DeriveInput) -> TokenStream {
+    let ident = &ast.ident;
+    let name = ident.to_string();
+    if !ast.generics.params.is_empty() {
         return quote_spanned! {
-            sig.constness.unwrap().span =>
-            compile_error!("const fn not allowed in v8_ffi");
+            ident.span() =>
+            compile_error!("FromJsObject cannot be derived for generic structs");
         }
         .into();
     }
-    if sig.asyncness.is_some() {
-        return quote_spanned! {
-            sig.asyncness.unwrap().span =>
-            compile_error!("async fn not allowed in v8_ffi");
+    let fields = match &ast.data {
+        Data::Struct(DataStruct {
+            fields: Fields::Named(fields),
+            ..
+        }) => fields,
+        _ => {
+            return quote_spanned! {
+                ident.span() =>
+                compile_error!("FromJsObject can only be derived for structs with named fields");
+            }
+            .into();
         }
-        .into();
-    }
-    if sig.unsafety.is_some() {
-        return quote_spanned! {
-            sig.unsafety.unwrap().span =>
-            compile_error!("unsafe fn not allowed in v8_ffi");
+    };
+    let mut camel = false;
+    for attr in ast.attrs.iter() {
+        if !attr.path.is_ident("js_rename_all") {
+            continue;
+        }
+        match attr.parse_meta() {
+            Ok(Meta::NameValue(MetaNameValue {
+                lit: Lit::Str(policy),
+                ..
+            })) if policy.value() == "camelCase" => camel = true,
+            _ => {
+                return quote_spanned! {
+                    ident.span() =>
+                    compile_error!("expected `#[js_rename_all = \"camelCase\"]`");
+                }
+                .into();
+            }
         }
-        .into();
     }
-    if sig.abi.is_some() {
-        return quote_spanned! {
-            sig.abi.as_ref().unwrap().extern_token.span =>
-            compile_error!("extern fn not allowed in v8_ffi");
+    let mut field_idents: Vec<&Ident> = vec![];
+    let mut js_names: Vec<String> = vec![];
+    let mut readers: Vec<TokenStream2> = vec![];
+    for field in fields.named.iter() {
+        let field_ident = field.ident.as_ref().unwrap();
+        let mut js_name = field_ident.to_string();
+        if camel {
+            js_name = camel_case(&js_name);
         }
-        .into();
+        let mut default = false;
+        for attr in field.attrs.iter() {
+            if attr.path.is_ident("js_default") {
+                default = true;
+            } else if attr.path.is_ident("js_name") {
+                match attr.parse_meta() {
+                    Ok(Meta::NameValue(MetaNameValue {
+                        lit: Lit::Str(name),
+                        ..
+                    })) => js_name = name.value(),
+                    _ => {
+                        return quote_spanned! {
+                            field_ident.span() =>
+                            compile_error!("expected `#[js_name = \"name\"]`");
+                        }
+                        .into();
+                    }
+                }
+            }
+        }
+        let reader = if default {
+            quote! { ::rusty_v8_helper::js_object::read_field_or_default }
+        } else {
+            quote! { ::rusty_v8_helper::js_object::read_field }
+        };
+        readers.push(reader);
+        field_idents.push(field_ident);
+        js_names.push(js_name);
     }
-    if sig.generics.where_clause.is_some() {
+    let field_types = fields.named.iter().map(|x| &x.ty);
+    let field_locals = field_idents
+        .iter()
+        .map(|x| Ident::new(&format!("__js_field_{}", x), x.span()))
+        .collect::<Vec<Ident>>();
+
+    let gen = quote! {
+        impl<'sc, 'c> ::rusty_v8_helper::FFICompat<'sc, 'c> for #ident {
+            type E = ::rusty_v8_helper::FFIError;
+
+            fn from_value(
+                value: ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Value>,
+                scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+                context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+            ) -> ::std::result::Result<Self, ::rusty_v8_helper::FFIError> {
+                let object = ::rusty_v8_helper::js_object::expect_object(value, #name)?;
+                let mut errors = ::std::vec::Vec::new();
+                #(
+                    let #field_locals: ::std::option::Option<#field_types> = #readers(scope, context, object, #js_names, &mut errors);
+                )*
+                ::rusty_v8_helper::js_object::check_fields(#name, errors)?;
+                ::std::result::Result::Ok(#ident {
+                    #(#field_idents: #field_locals.unwrap(),)*
+                })
+            }
+
+            fn to_value(
+                self,
+                scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+                context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+            ) -> ::std::result::Result<::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Value>, ::rusty_v8_helper::FFIError> {
+                let object = ::rusty_v8_helper::v8::Object::new(scope);
+                #(
+                    ::rusty_v8_helper::js_object::write_field(scope, context, object, #js_names, self.#field_idents)?;
+                )*
+                ::std::result::Result::Ok(object.into())
+            }
+        }
+    };
+    gen.into()
+}
+
+#[proc_macro_derive(JsEnum)]
+pub fn js_enum(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    impl_js_enum(&ast)
+}
+
+fn impl_js_enum(ast: &DeriveInput) -> TokenStream {
+    let ident = &ast.ident;
+    let name = ident.to_string();
+    if !ast.generics.params.is_empty() {
         return quote_spanned! {
-            sig.generics.where_clause.as_ref().unwrap().where_token.span =>
-            compile_error!("generics where clause not allowed in v8_ffi fn");
+            ident.span() =>
+            compile_error!("JsEnum cannot be derived for generic enums");
         }
         .into();
     }
-    for param in sig.generics.params.iter() {
-        if let GenericParam::Lifetime(_) = param {
-            // nop
-        } else {
+    let variants = match &ast.data {
+        Data::Enum(DataEnum { variants, .. }) => variants,
+        _ => {
             return quote_spanned! {
-                sig.generics.lt_token.as_ref().unwrap().span =>
-                compile_error!("non-lifetime generics not allowed in v8_ffi fn");
+                ident.span() =>
+                compile_error!("JsEnum can only be derived for enums");
             }
             .into();
         }
-    }
-    if sig.variadic.is_some() {
-        return quote_spanned! {
-            sig.variadic.as_ref().unwrap().dots.spans[0] =>
-            compile_error!("variadic not allowed in v8_ffi fn");
-        }
-        .into();
-    }
-    let inputs = sig.inputs.iter().collect::<Vec<&FnArg>>();
-    for input in &inputs {
-        if let FnArg::Receiver(receiver) = input {
+    };
+    let mut variant_idents: Vec<&Ident> = vec![];
+    for variant in variants.iter() {
+        if !matches!(variant.fields, Fields::Unit) {
             return quote_spanned! {
-                receiver.self_token.span =>
-                compile_error!("self is not allowed in v8_ffi fn, use `this: &SomeType` as first argument to use auto `ObjectWrap` unwrapping");
-            }.into();
+                variant.ident.span() =>
+                compile_error!("JsEnum can only be derived for enums of unit variants");
+            }
+            .into();
         }
+        variant_idents.push(&variant.ident);
     }
-    let inputs = inputs
-        .iter()
-        .map(|x| if let FnArg::Typed(x) = x { x } else { panic!() })
-        .collect::<Vec<&PatType>>();
-    let inputs: Result<Vec<(Ident, SimpleType)>, _> = inputs
-        .into_iter()
-        .map(|input| {
-            let name = if let Pat::Ident(PatIdent {
-                by_ref: None,
-                subpat: None,
-                ident,
-                ..
-            }) = &*input.pat
-            {
-                ident.clone()
-            } else {
-                return Err(quote_spanned! {
-                    input.colon_token.span =>
-                    compile_error!("invalid non-ident argument name for v8_ffi fn");
-                }
-                .into());
-            };
-            let ty = parse_simple_type(&input.ty);
-            Ok((name, ty))
-        })
-        .collect();
-    let mut inputs = match inputs {
-        Err(e) => return e,
-        Ok(x) => x,
-    };
-    let this: Vec<(Ident, bool, Path)> = inputs
-        .iter()
-        .filter_map(|x| {
-            if let (name, SimpleType::This(mutability, path)) = x {
-                Some((name.clone(), *mutability, path.clone()))
-            } else {
-                None
+    let variant_names = variant_idents.iter().map(|x| x.to_string());
+
+    let gen = quote! {
+        impl ::rusty_v8_helper::JsEnum for #ident {
+            const NAME: &'static str = #name;
+
+            fn variants() -> ::std::vec::Vec<(&'static str, Self)> {
+                vec![#((#variant_names, #ident::#variant_idents),)*]
             }
-        })
-        .collect();
-    if this.len() > 1 {
+        }
+    };
+    gen.into()
+}
+
+/// `#[derive(FFIObject)]` implements `FFIObject` and `FFICompat` for a
+/// serde type, converting it through JSON. `#[js_rename_all = "..."]`,
+/// `"camelCase"` or `"keep"`, overrides the isolate's rename policy, and
+/// `#[js_schema = "path::to::fn"]` validates values from JS against the
+/// JSON Schema the fn returns.
+#[proc_macro_derive(FFIObject, attributes(js_rename_all, js_schema))]
+pub fn ffi_object(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    impl_ffi_object(&ast)
+}
+
+fn impl_ffi_object(ast: &DeriveInput) -> TokenStream {
+    let ident = &ast.ident;
+    if !ast.generics.params.is_empty() {
         return quote_spanned! {
-            sig.fn_token.span =>
-            compile_error!("can only object wrap one argument in v8_ffi fn");
+            ident.span() =>
+            compile_error!("FFIObject cannot be derived for generic types");
         }
         .into();
     }
-    let return_type = match &sig.output {
-        ReturnType::Default => None,
-        ReturnType::Type(arrow, ty) => {
-            let return_type = parse_simple_type(&ty);
-            if let SimpleType::This(_, _) = &return_type {
+    let mut rename = None;
+    let mut schema = None;
+    for attr in ast.attrs.iter() {
+        if attr.path.is_ident("js_rename_all") {
+            rename = match attr.parse_meta() {
+                Ok(Meta::NameValue(MetaNameValue {
+                    lit: Lit::Str(policy),
+                    ..
+                })) if policy.value() == "camelCase" => Some(quote! { CamelCase }),
+                Ok(Meta::NameValue(MetaNameValue {
+                    lit: Lit::Str(policy),
+                    ..
+                })) if policy.value() == "keep" => Some(quote! { Keep }),
+                _ => {
+                    return quote_spanned! {
+                        attr.pound_token.span =>
+                        compile_error!("expected `#[js_rename_all = \"camelCase\"]` or `#[js_rename_all = \"keep\"]`");
+                    }
+                    .into();
+                }
+            };
+        } else if attr.path.is_ident("js_schema") {
+            schema = match attr.parse_meta() {
+                Ok(Meta::NameValue(MetaNameValue {
+                    lit: Lit::Str(path),
+                    ..
+                })) => path.parse::<Path>().ok(),
+                _ => None,
+            };
+            if schema.is_none() {
                 return quote_spanned! {
-                    arrow.spans[0] =>
-                    compile_error!("cannot return wrapped object from v8_ffi fn");
+                    attr.pound_token.span =>
+                    compile_error!("expected `#[js_schema = \"path::to::fn\"]`");
                 }
                 .into();
             }
-            Some(return_type)
         }
-    };
-    let this = this.into_iter().next();
-    let mut preludes: Vec<TokenStream2> = vec![];
+    }
+    let rename = rename.map(|policy| {
+        quote! {
+            const RENAME: ::std::option::Option<::rusty_v8_helper::RenamePolicy> =
+                ::std::option::Option::Some(::rusty_v8_helper::RenamePolicy::#policy);
+        }
+    });
+    let schema = schema.map(|path| {
+        quote! {
+            fn schema() -> ::std::option::Option<::rusty_v8_helper::json::Value> {
+                ::std::option::Option::Some(#path())
+            }
+        }
+    });
 
-    if let Some((name, mutability, ty)) = &this {
-        if name != &inputs[0].0 || format!("{}", name) != "this" {
-            return quote_spanned! {
-                name.span() =>
-                compile_error!("object wrapped argument must be first in v8_ffi fn and be named `this`");
-            }.into();
+    let gen = quote! {
+        impl ::rusty_v8_helper::FFIObject for #ident {
+            #rename
+            #schema
         }
-        let ty = Type::Path(TypePath {
-            qself: None,
-            path: ty.clone(),
-        });
-        if *mutability {
-            preludes.push(quote! {
-                let #name: ::std::option::Option<::std::rc::Rc<::std::sync::Mutex<#ty>>> = ::rusty_v8_helper::ObjectWrap::from_object(__v8_ffi_args.this());
-                if #name.is_none() {
-                    throw_exception(__v8_ffi_scope, "invalid 'this' for ffi call");
-                    return;
-                }
-                let #name = #name.unwrap();
-                let #name = #name.try_lock();
-                if #name.is_err() {
-                    throw_exception(__v8_ffi_scope, "deadlock in ffi call");
-                    return;
-                }
-                let mut #name = #name.unwrap();
-                let mut #name = &mut #name;
-            });
-        } else {
-            preludes.push(quote! {
-                let #name: ::std::option::Option<::std::rc::Rc<#ty>> = ::rusty_v8_helper::ObjectWrap::from_object(__v8_ffi_args.this());
-                if #name.is_none() {
-                    throw_exception(__v8_ffi_scope, "invalid 'this' for ffi call");
-                    return;
-                }
-                let #name = #name.unwrap();
-                let #name = &#name;
-            });
+
+        impl<'sc, 'c> ::rusty_v8_helper::FFICompat<'sc, 'c> for #ident {
+            type E = ::std::string::String;
+
+            fn from_value(
+                value: ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Value>,
+                scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+                context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+            ) -> ::std::result::Result<Self, ::std::string::String> {
+                ::rusty_v8_helper::json::object_from_value(value, scope, context)
+            }
+
+            fn to_value(
+                self,
+                scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+                context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+            ) -> ::std::result::Result<::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Value>, ::std::string::String> {
+                ::rusty_v8_helper::json::object_to_value(self, scope, context)
+            }
         }
-        inputs.remove(0);
-    }
+    };
+    gen.into()
+}
+
+/// `#[derive(FfiTransparent)]` implements `FFICompat` for a newtype, a
+/// struct of a single field, by converting it as its field, i.e. to give a
+/// local name to `Js<T, A>` of a foreign type.
+#[proc_macro_derive(FfiTransparent)]
+pub fn ffi_transparent(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    impl_ffi_transparent(&ast)
+}
 
-    if scoped {
-        if inputs.len() < 2 {
+fn impl_ffi_transparent(ast: &DeriveInput) -> TokenStream {
+    let ident = &ast.ident;
+    if !ast.generics.params.is_empty() {
+        return quote_spanned! {
+            ident.span() =>
+            compile_error!("FfiTransparent cannot be derived for generic structs");
+        }
+        .into();
+    }
+    let fields = match &ast.data {
+        Data::Struct(DataStruct { fields, .. }) if fields.len() == 1 => fields,
+        _ => {
             return quote_spanned! {
-                sig.fn_token.span =>
-                compile_error!("scoped function must have at least 2 arguments: scope, context");
+                ident.span() =>
+                compile_error!("FfiTransparent can only be derived for structs of a single field");
             }
             .into();
         }
-        let input0_name = format!("{}", &inputs.get(0).as_ref().unwrap().0);
-        let input1_name = format!("{}", &inputs.get(1).as_ref().unwrap().0);
-        if !(input0_name == "scope" || input0_name == "_scope")
-            || !(input1_name == "context" || input1_name == "_context")
+    };
+    let field = fields.iter().next().unwrap();
+    let ty = &field.ty;
+    let (construct, access) = match &field.ident {
+        Some(name) => (quote! { #ident { #name: x } }, quote! { self.#name }),
+        None => (quote! { #ident(x) }, quote! { self.0 }),
+    };
+
+    let gen = quote! {
+        impl<'sc, 'c> ::rusty_v8_helper::FFICompat<'sc, 'c> for #ident
+        where
+            #ty: ::rusty_v8_helper::FFICompat<'sc, 'c>,
         {
+            type E = <#ty as ::rusty_v8_helper::FFICompat<'sc, 'c>>::E;
+
+            fn from_value(
+                value: ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Value>,
+                scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+                context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+            ) -> ::std::result::Result<Self, Self::E> {
+                <#ty as ::rusty_v8_helper::FFICompat<'sc, 'c>>::from_value(value, scope, context).map(|x| #construct)
+            }
+
+            fn to_value(
+                self,
+                scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+                context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+            ) -> ::std::result::Result<::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Value>, Self::E> {
+                <#ty as ::rusty_v8_helper::FFICompat<'sc, 'c>>::to_value(#access, scope, context)
+            }
+        }
+    };
+    gen.into()
+}
+
+/// `#[derive(JsErrorClass)]` implements `JsErrorClass` for an error enum.
+/// The thrown error has the named fields of its variant as properties, by
+/// field name or `#[js_name = "..."]`, and tuple fields as the array
+/// `values`. Fields are converted with `JsValue::from` of a clone; mark any
+/// other with `#[js_skip]`.
+#[proc_macro_derive(JsErrorClass, attributes(js_name, js_skip))]
+pub fn js_error_class(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    impl_js_error_class(&ast)
+}
+
+fn impl_js_error_class(ast: &DeriveInput) -> TokenStream {
+    let ident = &ast.ident;
+    let name = ident.to_string();
+    if !ast.generics.params.is_empty() {
+        return quote_spanned! {
+            ident.span() =>
+            compile_error!("JsErrorClass cannot be derived for generic enums");
+        }
+        .into();
+    }
+    let variants = match &ast.data {
+        Data::Enum(DataEnum { variants, .. }) => variants,
+        _ => {
             return quote_spanned! {
-                sig.fn_token.span =>
-                compile_error!("scoped function's first two arguments must be named: scope, context");
-            }.into();
+                ident.span() =>
+                compile_error!("JsErrorClass can only be derived for enums");
+            }
+            .into();
         }
-        inputs.remove(1);
-        inputs.remove(0);
+    };
+    if variants.is_empty() {
+        return quote_spanned! {
+            ident.span() =>
+            compile_error!("JsErrorClass cannot be derived for enums without variants");
+        }
+        .into();
     }
-
-    for (i, input) in inputs.iter().enumerate() {
-        let name = &input.0;
-        let i = i as i32;
-        match &input.1 {
-            SimpleType::This(_, _) => {}
-            SimpleType::Type(ty) => {
-                let from_value_ident = Ident::new("from_value", sig.ident.span());
-                let ty = match ty {
-                    Type::Path(TypePath { qself, path }) => {
-                        let mut path = path.clone();
-                        for seg in path.segments.iter_mut() {
-                            if let PathArguments::AngleBracketed(args) = &mut seg.arguments {
-                                if !args.colon2_token.is_some() {
-                                    args.colon2_token = Some(token::Colon2 {
-                                        spans: [sig.ident.span(), sig.ident.span()],
-                                    });
-                                }
+    let mut variant_names: Vec<String> = vec![];
+    let mut patterns: Vec<TokenStream2> = vec![];
+    let mut fields: Vec<TokenStream2> = vec![];
+    for variant in variants.iter() {
+        let variant_ident = &variant.ident;
+        let mut bindings: Vec<TokenStream2> = vec![];
+        let mut named: Vec<TokenStream2> = vec![];
+        let mut values: Vec<TokenStream2> = vec![];
+        for (i, field) in variant.fields.iter().enumerate() {
+            let mut skip = false;
+            let mut js_name = field.ident.as_ref().map(|x| x.to_string());
+            for attr in field.attrs.iter() {
+                if attr.path.is_ident("js_skip") {
+                    skip = true;
+                } else if attr.path.is_ident("js_name") {
+                    match attr.parse_meta() {
+                        Ok(Meta::NameValue(MetaNameValue {
+                            lit: Lit::Str(name),
+                            ..
+                        })) if field.ident.is_some() => js_name = Some(name.value()),
+                        _ => {
+                            return quote_spanned! {
+                                variant_ident.span() =>
+                                compile_error!("expected `#[js_name = \"name\"]` on a named field");
                             }
+                            .into();
                         }
-                        if !path.segments.empty_or_trailing() {
-                            path.segments.push_punct(token::Colon2 {
-                                spans: [sig.ident.span(), sig.ident.span()],
-                            });
-                        }
-                        path.segments.push_value(PathSegment {
-                            ident: from_value_ident,
-                            arguments: PathArguments::None,
-                        });
-                        let ty = Type::Path(TypePath {
-                            qself: qself.clone(),
-                            path,
-                        });
-                        quote! { #ty }
-                    }
-                    _ => quote! { <#ty>::#from_value_ident },
-                };
-                preludes.push(quote! {
-                    let mut #name = __v8_ffi_args.get(#i);
-                    let #name = #ty(#name, __v8_ffi_scope, __v8_ffi_context);
-                    if let Err(e) = #name {
-                        ::rusty_v8_helper::util::throw_exception(__v8_ffi_scope, &format!("{:?}", e));
-                        return;
                     }
-                    let #name = #name.unwrap();
-                })
+                }
+            }
+            if skip {
+                continue;
+            }
+            let binding = Ident::new(&format!("__js_field_{}", i), variant_ident.span());
+            let value = quote! {
+                ::rusty_v8_helper::JsValue::from(::std::clone::Clone::clone(#binding))
+            };
+            match &field.ident {
+                Some(field_ident) => {
+                    bindings.push(quote! { #field_ident: #binding });
+                    named.push(quote! { (#js_name, #value) });
+                }
+                None => {
+                    let index = Index::from(i);
+                    bindings.push(quote! { #index: #binding });
+                    values.push(value);
+                }
             }
         }
+        if !values.is_empty() {
+            named.push(quote! {
+                ("values", ::rusty_v8_helper::JsValue::Array(vec![#(#values),*]))
+            });
+        }
+        variant_names.push(variant_ident.to_string());
+        patterns.push(quote! { #ident::#variant_ident { #(#bindings,)* .. } });
+        fields.push(quote! { vec![#(#named),*] });
     }
-    let vis = &ast.vis;
-    let ffi_internal_ident = Ident::new(
-        &format!("__v8_ffi_internal_{}", sig.ident),
-        sig.ident.span(),
-    );
-    let ffi_ident = Ident::new(&format!("__v8_ffi_{}", sig.ident), sig.ident.span());
-    let preludes: TokenStream2 = preludes.into_iter().collect();
-    let original_ident = &sig.ident;
-
-    let mut arg_names: Vec<TokenStream2> = vec![];
-    if this.is_some() {
-        let name = &this.as_ref().unwrap().0;
-        arg_names.push(quote! { #name, });
-    }
-    if scoped {
-        arg_names.push(quote! { __v8_ffi_scope, });
-        arg_names.push(quote! { __v8_ffi_context, });
-    }
-    for input in inputs.iter() {
-        let name = &input.0;
-        arg_names.push(quote! { #name, })
-    }
-    let arg_names: TokenStream2 = arg_names.into_iter().collect();
-    let return_postlude = if let Some(SimpleType::Type(_)) = return_type {
-        Some(quote! {
-            let __v8_ffi_value = __returned.to_value(__v8_ffi_scope, __v8_ffi_context);
-            match __v8_ffi_value {
-                Ok(__v8_ffi_value) => __v8_ffi_rv.set(__v8_ffi_value),
-                Err(e) => {
-                    ::rusty_v8_helper::util::throw_exception(__v8_ffi_scope, &format!("{:?}", e));
-                    return;
+
+    let gen = quote! {
+        impl ::rusty_v8_helper::JsErrorClass for #ident {
+            const NAME: &'static str = #name;
+
+            fn classes() -> &'static [&'static str] {
+                &[#(#variant_names),*]
+            }
+
+            fn class_name(&self) -> &'static str {
+                match self {
+                    #(#patterns => #variant_names,)*
                 }
             }
 
-        })
-    } else {
-        None
+            #[allow(unused_variables)]
+            fn fields(&self) -> ::std::vec::Vec<(&'static str, ::rusty_v8_helper::JsValue)> {
+                match self {
+                    #(#patterns => #fields,)*
+                }
+            }
+        }
     };
+    gen.into()
+}
 
-    let gen = quote! {
-        #ast
+fn take_js_arg(tokens: &mut impl Iterator<Item = TokenTree>) -> TokenStream2 {
+    let mut arg = TokenStream2::new();
+    for token in tokens {
+        match &token {
+            TokenTree::Punct(p) if p.as_char() == ',' => break,
+            _ => arg.extend(Some(token)),
+        }
+    }
+    arg
+}
+
+/// JS keywords after which an expression starts, so that a `/` after them
+/// begins a regex literal rather than dividing.
+const JS_OPERATOR_KEYWORDS: &[&str] = &[
+    "await",
+    "case",
+    "delete",
+    "do",
+    "else",
+    "in",
+    "instanceof",
+    "new",
+    "of",
+    "return",
+    "throw",
+    "typeof",
+    "void",
+    "yield",
+];
 
-        fn #ffi_internal_ident<'sc>(mut __v8_ffi_scope: ::rusty_v8_protryon::FunctionCallbackScope<'sc>, __v8_ffi_args: ::rusty_v8_protryon::FunctionCallbackArguments<'sc>, mut __v8_ffi_rv: ::rusty_v8_protryon::ReturnValue<'sc>) {
-            let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
-            #preludes
-            let __returned = #original_ident(#arg_names);
-            #return_postlude
+/// Render `tokens` as JS source, replacing `#name` and `#(rust_expr)` with
+/// bindings, and reject the JS that does not survive Rust tokenization.
+fn render_js(
+    tokens: TokenStream2,
+    source: &mut String,
+    exprs: &mut Vec<TokenStream2>,
+) -> Result<(), Error> {
+    let mut tokens = tokens.into_iter().peekable();
+    // whether the last token ended an operand, so a `/` after it divides
+    let mut after_operand = false;
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Punct(p) if p.as_char() == '#' => match tokens.peek() {
+                Some(TokenTree::Ident(_)) => {
+                    exprs.push(tokens.next().into_iter().collect());
+                    source.push_str(&format!("__js_{} ", exprs.len() - 1));
+                    after_operand = true;
+                }
+                Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Parenthesis => {
+                    exprs.push(g.stream());
+                    tokens.next();
+                    source.push_str(&format!("__js_{} ", exprs.len() - 1));
+                    after_operand = true;
+                }
+                Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Bracket => {
+                    return Err(Error::new(
+                        p.span(),
+                        "comments in js! are Rust comments, use `//` rather than a doc comment",
+                    ));
+                }
+                _ => {
+                    source.push('#');
+                    after_operand = false;
+                }
+            },
+            TokenTree::Punct(p) if p.as_char() == '\'' => {
+                return Err(Error::new(p.span(), "strings in js! must be double-quoted"));
+            }
+            TokenTree::Punct(p) if p.as_char() == '/' && !after_operand => {
+                return Err(Error::new(
+                    p.span(),
+                    "regex literals are not supported in js!, use `new RegExp(\"...\")`",
+                ));
+            }
+            TokenTree::Punct(p) => {
+                source.push(p.as_char());
+                if p.spacing() == Spacing::Alone {
+                    source.push(' ');
+                }
+                after_operand = false;
+            }
+            TokenTree::Group(g) => {
+                let (open, close) = match g.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => ("", ""),
+                };
+                source.push_str(open);
+                render_js(g.stream(), source, exprs)?;
+                source.push_str(close);
+                source.push(' ');
+                after_operand = g.delimiter() != Delimiter::Brace;
+            }
+            TokenTree::Literal(literal) => {
+                let text = literal.to_string();
+                if !text.starts_with(|c: char| c == '"' || c.is_ascii_digit() || c == '.') {
+                    return Err(Error::new(
+                        literal.span(),
+                        "strings in js! must be double-quoted",
+                    ));
+                }
+                source.push_str(&text);
+                source.push(' ');
+                after_operand = true;
+            }
+            TokenTree::Ident(ident) => {
+                let text = ident.to_string();
+                after_operand = !JS_OPERATOR_KEYWORDS.contains(&text.as_str());
+                source.push_str(&text);
+                source.push(' ');
+            }
         }
+    }
+    Ok(())
+}
 
-        #vis fn #ffi_ident<'sc, 'c>(__v8_ffi_scope: &mut impl ::rusty_v8_protryon::ToLocal<'sc>, __v8_ffi_context: ::rusty_v8_protryon::Local<'c, ::rusty_v8_protryon::Context>) -> ::rusty_v8_protryon::Local<'sc, ::rusty_v8_protryon::Function> {
-            ::rusty_v8_protryon::Function::new(
-                __v8_ffi_scope,
-                __v8_ffi_context,
-                #ffi_internal_ident,
-            ).unwrap()
+/// `js!(scope, context, <expression>)` evaluates a JS expression written
+/// inline, where `#name` or `#(rust_expr)` passes a Rust value converted
+/// with `FFICompat` as a binding rather than splicing it into the source.
+/// Expands to `eval_with_bindings`, returning a `Result<T, JsError>`.
+///
+/// The expression is tokenized by Rust first, so only JS made of Rust
+/// tokens is accepted: identifiers, numbers, double-quoted strings with
+/// escapes common to both, operators and balanced `()`, `[]` and `{}`.
+/// `//` and `/* */` comments are dropped as Rust comments. These are
+/// rejected at compile time:
+/// - single-quoted strings, raw and byte strings,
+/// - template literals,
+/// - regex literals, a `/` where an operand is expected,
+/// - doc comments.
+#[proc_macro_hack]
+pub fn js(input: TokenStream) -> TokenStream {
+    let mut tokens = TokenStream2::from(input).into_iter();
+    let scope_ref = take_js_arg(&mut tokens);
+    let context_ref = take_js_arg(&mut tokens);
+    if scope_ref.is_empty() || context_ref.is_empty() {
+        return quote! {
+            compile_error!("invalid call to js, expected args: scope, context, js expression");
+        }
+        .into();
+    }
+    let mut source = String::new();
+    let mut exprs = vec![];
+    if let Err(e) = render_js(tokens.collect(), &mut source, &mut exprs) {
+        return e.to_compile_error().into();
+    }
+    let source = source.trim_end();
+    let names = (0..exprs.len())
+        .map(|i| format!("__js_{}", i))
+        .collect::<Vec<String>>();
+    let count = exprs.len();
+    quote! {{
+        let mut __js_error = ::std::option::Option::None;
+        let mut __js_values = ::std::vec::Vec::new();
+        #(
+            match ::rusty_v8_helper::FFICompat::to_value(#exprs, #scope_ref, #context_ref) {
+                ::std::result::Result::Ok(value) => __js_values.push(value),
+                ::std::result::Result::Err(e) => if __js_error.is_none() {
+                    __js_error = ::std::option::Option::Some(::rusty_v8_helper::JsError::new(::std::format!("{:?}", e)));
+                },
+            }
+        )*
+        match __js_error {
+            ::std::option::Option::Some(e) => ::std::result::Result::Err(e),
+            ::std::option::Option::None => {
+                let __js_names: [&str; #count] = [#(#names),*];
+                let __js_bindings = __js_names.iter().cloned().zip(__js_values.into_iter()).collect::<::std::vec::Vec<_>>();
+                ::rusty_v8_helper::eval_with_bindings(#scope_ref, #context_ref, #source, &__js_bindings)
+            }
         }
+    }}
+    .into()
+}
 
-    };
-    gen.into()
+/// The doc comment of `attrs`, without the leading `///` or the space
+/// after it.
+fn doc_string(attrs: &[Attribute]) -> String {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter(|x| x.path.is_ident("doc"))
+        .filter_map(|x| match x.parse_meta() {
+            Ok(Meta::NameValue(MetaNameValue {
+                lit: Lit::Str(line),
+                ..
+            })) => Some(line.value()),
+            _ => None,
+        })
+        .map(|line| match line.strip_prefix(' ') {
+            Some(line) => line.to_string(),
+            None => line,
+        })
+        .collect();
+    lines.join("\n")
 }
@@ -0,0 +1,479 @@
+/// Add two numbers.
+fn add(a: f64, b: f64) -> f64 {
+    a + b
+}
+/// Add two numbers.
+fn __v8_ffi_internal_add<'sc>(
+    mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>,
+    __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>,
+    mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>,
+) {
+    #[allow(unused_imports)]
+    use ::rusty_v8_helper::owned::{
+        ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _,
+    };
+    let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+    let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(
+        __v8_ffi_scope,
+        "add",
+    );
+    if !::rusty_v8_helper::policy::check_policy(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &__v8_ffi_call,
+        "add",
+    ) {
+        return;
+    }
+    let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    let a = __v8_ffi_args.get(0i32);
+    let a = (&<::rusty_v8_helper::owned::Dispatch<f64>>::default())
+        .from_js(a, __v8_ffi_scope, __v8_ffi_context);
+    if let Err(e) = a {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let a = a.unwrap();
+    let b = __v8_ffi_args.get(1i32);
+    let b = (&<::rusty_v8_helper::owned::Dispatch<f64>>::default())
+        .from_js(b, __v8_ffi_scope, __v8_ffi_context);
+    if let Err(e) = b {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let b = b.unwrap();
+    let __returned = add(a, b);
+    let __v8_ffi_value = (&::rusty_v8_helper::owned::Dispatch::of(&__returned))
+        .to_js(__returned, __v8_ffi_scope, __v8_ffi_context);
+    match __v8_ffi_value {
+        Ok(__v8_ffi_value) => __v8_ffi_rv.set(__v8_ffi_value),
+        Err(e) => {
+            __v8_ffi_rv.set(::rusty_v8_helper::v8::undefined(__v8_ffi_scope).into());
+            ::rusty_v8_helper::util::throw_call_error(
+                __v8_ffi_scope,
+                &__v8_ffi_call,
+                &e,
+            );
+            return;
+        }
+    }
+}
+/// Add two numbers.
+fn __v8_ffi_add<'sc, 'c>(
+    __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+    ::rusty_v8_helper::v8::Function::new(
+            __v8_ffi_scope,
+            __v8_ffi_context,
+            __v8_ffi_internal_add,
+        )
+        .unwrap()
+}
+#[doc(hidden)]
+#[allow(non_camel_case_types, dead_code)]
+struct add {}
+impl ::rusty_v8_helper::FfiFn for add {
+    const NAME: &'static str = "add";
+    const META: ::rusty_v8_helper::FfiFnMeta = ::rusty_v8_helper::FfiFnMeta {
+        name: "add",
+        arity: 2usize,
+        params: &[
+            ::rusty_v8_helper::FfiParam {
+                name: "a",
+                ty: "f64",
+            },
+            ::rusty_v8_helper::FfiParam {
+                name: "b",
+                ty: "f64",
+            },
+        ],
+        returns: "f64",
+        doc: "Add two numbers.",
+    };
+    fn load<'sc, 'c>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+        __v8_ffi_context: ::rusty_v8_helper::v8::Local<
+            'c,
+            ::rusty_v8_helper::v8::Context,
+        >,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+        __v8_ffi_add(__v8_ffi_scope, __v8_ffi_context)
+    }
+    fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+        ::rusty_v8_helper::v8::MapFnTo::map_fn_to(__v8_ffi_internal_add)
+    }
+    fn template<'sc>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+        ::rusty_v8_helper::v8::FunctionTemplate::new(
+            __v8_ffi_scope,
+            __v8_ffi_internal_add,
+        )
+    }
+}
+fn log(message: &str, data: &[u8]) {}
+fn __v8_ffi_internal_log<'sc>(
+    mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>,
+    __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>,
+    mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>,
+) {
+    #[allow(unused_imports)]
+    use ::rusty_v8_helper::owned::{
+        ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _,
+    };
+    let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+    let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(
+        __v8_ffi_scope,
+        "log",
+    );
+    if !::rusty_v8_helper::policy::check_policy(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &__v8_ffi_call,
+        "log",
+    ) {
+        return;
+    }
+    let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    let message = __v8_ffi_args.get(0i32);
+    let message = <::std::string::String as ::rusty_v8_helper::FFICompat>::from_value(
+        message,
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    if let Err(e) = message {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let message = message.unwrap();
+    let data = __v8_ffi_args.get(1i32);
+    let data = <::rusty_v8_helper::Bytes as ::rusty_v8_helper::FFICompat>::from_value(
+        data,
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    if let Err(e) = data {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let data = data.unwrap();
+    let __returned = log(&message, &data);
+}
+fn __v8_ffi_log<'sc, 'c>(
+    __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+    ::rusty_v8_helper::v8::Function::new(
+            __v8_ffi_scope,
+            __v8_ffi_context,
+            __v8_ffi_internal_log,
+        )
+        .unwrap()
+}
+#[doc(hidden)]
+#[allow(non_camel_case_types, dead_code)]
+struct log {}
+impl ::rusty_v8_helper::FfiFn for log {
+    const NAME: &'static str = "log";
+    const META: ::rusty_v8_helper::FfiFnMeta = ::rusty_v8_helper::FfiFnMeta {
+        name: "log",
+        arity: 2usize,
+        params: &[
+            ::rusty_v8_helper::FfiParam {
+                name: "message",
+                ty: "&str",
+            },
+            ::rusty_v8_helper::FfiParam {
+                name: "data",
+                ty: "&[u8]",
+            },
+        ],
+        returns: "()",
+        doc: "",
+    };
+    fn load<'sc, 'c>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+        __v8_ffi_context: ::rusty_v8_helper::v8::Local<
+            'c,
+            ::rusty_v8_helper::v8::Context,
+        >,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+        __v8_ffi_log(__v8_ffi_scope, __v8_ffi_context)
+    }
+    fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+        ::rusty_v8_helper::v8::MapFnTo::map_fn_to(__v8_ffi_internal_log)
+    }
+    fn template<'sc>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+        ::rusty_v8_helper::v8::FunctionTemplate::new(
+            __v8_ffi_scope,
+            __v8_ffi_internal_log,
+        )
+    }
+}
+fn repeat(text: &str, times: u32) -> String {
+    text.repeat(times as usize)
+}
+fn __v8_ffi_internal_repeat<'sc>(
+    mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>,
+    __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>,
+    mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>,
+) {
+    #[allow(unused_imports)]
+    use ::rusty_v8_helper::owned::{
+        ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _,
+    };
+    let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+    let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(
+        __v8_ffi_scope,
+        "repeat",
+    );
+    if !::rusty_v8_helper::policy::check_policy(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &__v8_ffi_call,
+        "repeat",
+    ) {
+        return;
+    }
+    let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    let text = __v8_ffi_args.get(0i32);
+    let text = <::rusty_v8_helper::Coerced<
+        ::std::string::String,
+    > as ::rusty_v8_helper::FFICompat>::from_value(
+            text,
+            __v8_ffi_scope,
+            __v8_ffi_context,
+        )
+        .map(|x| x.0);
+    if let Err(e) = text {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let text = text.unwrap();
+    let times = __v8_ffi_args.get(1i32);
+    let times = <::rusty_v8_helper::Coerced<
+        u32,
+    > as ::rusty_v8_helper::FFICompat>::from_value(
+            times,
+            __v8_ffi_scope,
+            __v8_ffi_context,
+        )
+        .map(|x| x.0);
+    if let Err(e) = times {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let times = times.unwrap();
+    let __returned = repeat(&text, times);
+    let __v8_ffi_value = (&::rusty_v8_helper::owned::Dispatch::of(&__returned))
+        .to_js(__returned, __v8_ffi_scope, __v8_ffi_context);
+    match __v8_ffi_value {
+        Ok(__v8_ffi_value) => __v8_ffi_rv.set(__v8_ffi_value),
+        Err(e) => {
+            __v8_ffi_rv.set(::rusty_v8_helper::v8::undefined(__v8_ffi_scope).into());
+            ::rusty_v8_helper::util::throw_call_error(
+                __v8_ffi_scope,
+                &__v8_ffi_call,
+                &e,
+            );
+            return;
+        }
+    }
+}
+fn __v8_ffi_repeat<'sc, 'c>(
+    __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+    ::rusty_v8_helper::v8::Function::new(
+            __v8_ffi_scope,
+            __v8_ffi_context,
+            __v8_ffi_internal_repeat,
+        )
+        .unwrap()
+}
+#[doc(hidden)]
+#[allow(non_camel_case_types, dead_code)]
+struct repeat {}
+impl ::rusty_v8_helper::FfiFn for repeat {
+    const NAME: &'static str = "repeat";
+    const META: ::rusty_v8_helper::FfiFnMeta = ::rusty_v8_helper::FfiFnMeta {
+        name: "repeat",
+        arity: 2usize,
+        params: &[
+            ::rusty_v8_helper::FfiParam {
+                name: "text",
+                ty: "&str",
+            },
+            ::rusty_v8_helper::FfiParam {
+                name: "times",
+                ty: "u32",
+            },
+        ],
+        returns: "String",
+        doc: "",
+    };
+    fn load<'sc, 'c>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+        __v8_ffi_context: ::rusty_v8_helper::v8::Local<
+            'c,
+            ::rusty_v8_helper::v8::Context,
+        >,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+        __v8_ffi_repeat(__v8_ffi_scope, __v8_ffi_context)
+    }
+    fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+        ::rusty_v8_helper::v8::MapFnTo::map_fn_to(__v8_ffi_internal_repeat)
+    }
+    fn template<'sc>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+        ::rusty_v8_helper::v8::FunctionTemplate::new(
+            __v8_ffi_scope,
+            __v8_ffi_internal_repeat,
+        )
+    }
+}
+fn rotate(point: Point, angle: f64) {}
+fn __v8_ffi_internal_rotate<'sc>(
+    mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>,
+    __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>,
+    mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>,
+) {
+    #[allow(unused_imports)]
+    use ::rusty_v8_helper::owned::{
+        ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _,
+    };
+    let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+    let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(
+        __v8_ffi_scope,
+        "rotate",
+    );
+    if !::rusty_v8_helper::policy::check_policy(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &__v8_ffi_call,
+        "rotate",
+    ) {
+        return;
+    }
+    let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    let point = __v8_ffi_args.get(0i32);
+    let point = ::rusty_v8_helper::json::from_value(
+        point,
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    if let Err(e) = point {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let point = point.unwrap();
+    let angle = __v8_ffi_args.get(1i32);
+    let angle = crate::angle::from_value(angle, __v8_ffi_scope, __v8_ffi_context);
+    if let Err(e) = angle {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let angle = angle.unwrap();
+    let __returned = rotate(point, angle);
+}
+fn __v8_ffi_rotate<'sc, 'c>(
+    __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+    ::rusty_v8_helper::v8::Function::new(
+            __v8_ffi_scope,
+            __v8_ffi_context,
+            __v8_ffi_internal_rotate,
+        )
+        .unwrap()
+}
+#[doc(hidden)]
+#[allow(non_camel_case_types, dead_code)]
+struct rotate {}
+impl ::rusty_v8_helper::FfiFn for rotate {
+    const NAME: &'static str = "rotate";
+    const META: ::rusty_v8_helper::FfiFnMeta = ::rusty_v8_helper::FfiFnMeta {
+        name: "rotate",
+        arity: 2usize,
+        params: &[
+            ::rusty_v8_helper::FfiParam {
+                name: "point",
+                ty: "Point",
+            },
+            ::rusty_v8_helper::FfiParam {
+                name: "angle",
+                ty: "f64",
+            },
+        ],
+        returns: "()",
+        doc: "",
+    };
+    fn load<'sc, 'c>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+        __v8_ffi_context: ::rusty_v8_helper::v8::Local<
+            'c,
+            ::rusty_v8_helper::v8::Context,
+        >,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+        __v8_ffi_rotate(__v8_ffi_scope, __v8_ffi_context)
+    }
+    fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+        ::rusty_v8_helper::v8::MapFnTo::map_fn_to(__v8_ffi_internal_rotate)
+    }
+    fn template<'sc>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+        ::rusty_v8_helper::v8::FunctionTemplate::new(
+            __v8_ffi_scope,
+            __v8_ffi_internal_rotate,
+        )
+    }
+}
@@ -0,0 +1,16 @@
+/// Add two numbers.
+#[v8_ffi]
+fn add(a: f64, b: f64) -> f64 {
+    a + b
+}
+
+#[v8_ffi]
+fn log(message: &str, data: &[u8]) {}
+
+#[v8_ffi(coerce)]
+fn repeat(text: &str, times: u32) -> String {
+    text.repeat(times as usize)
+}
+
+#[v8_ffi]
+fn rotate(#[ffi(json)] point: Point, #[ffi(with = "crate::angle")] angle: f64) {}
@@ -0,0 +1,249 @@
+/// Only compiled with `sqlite`.
+#[cfg(feature = "sqlite")]
+#[inline]
+#[allow(clippy::needless_pass_by_value)]
+pub fn query(sql: String) -> Vec<String> {
+    vec![sql]
+}
+/// Only compiled with `sqlite`.
+#[cfg(feature = "sqlite")]
+#[inline]
+#[allow(clippy::needless_pass_by_value)]
+fn __v8_ffi_internal_query<'sc>(
+    mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>,
+    __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>,
+    mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>,
+) {
+    #[allow(unused_imports)]
+    use ::rusty_v8_helper::owned::{
+        ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _,
+    };
+    let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+    let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(
+        __v8_ffi_scope,
+        "query",
+    );
+    if !::rusty_v8_helper::policy::check_policy(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &__v8_ffi_call,
+        "query",
+    ) {
+        return;
+    }
+    let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    let sql = __v8_ffi_args.get(0i32);
+    let sql = (&<::rusty_v8_helper::owned::Dispatch<String>>::default())
+        .from_js(sql, __v8_ffi_scope, __v8_ffi_context);
+    if let Err(e) = sql {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let sql = sql.unwrap();
+    let __returned = query(sql);
+    let __v8_ffi_value = (&::rusty_v8_helper::owned::Dispatch::of(&__returned))
+        .to_js(__returned, __v8_ffi_scope, __v8_ffi_context);
+    match __v8_ffi_value {
+        Ok(__v8_ffi_value) => __v8_ffi_rv.set(__v8_ffi_value),
+        Err(e) => {
+            __v8_ffi_rv.set(::rusty_v8_helper::v8::undefined(__v8_ffi_scope).into());
+            ::rusty_v8_helper::util::throw_call_error(
+                __v8_ffi_scope,
+                &__v8_ffi_call,
+                &e,
+            );
+            return;
+        }
+    }
+}
+/// Only compiled with `sqlite`.
+#[cfg(feature = "sqlite")]
+#[inline]
+#[allow(clippy::needless_pass_by_value)]
+pub fn __v8_ffi_query<'sc, 'c>(
+    __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+    ::rusty_v8_helper::v8::Function::new(
+            __v8_ffi_scope,
+            __v8_ffi_context,
+            __v8_ffi_internal_query,
+        )
+        .unwrap()
+}
+#[cfg(feature = "sqlite")]
+#[allow(clippy::needless_pass_by_value)]
+#[doc(hidden)]
+#[allow(non_camel_case_types, dead_code)]
+pub struct query {}
+#[cfg(feature = "sqlite")]
+#[allow(clippy::needless_pass_by_value)]
+impl ::rusty_v8_helper::FfiFn for query {
+    const NAME: &'static str = "query";
+    const META: ::rusty_v8_helper::FfiFnMeta = ::rusty_v8_helper::FfiFnMeta {
+        name: "query",
+        arity: 1usize,
+        params: &[
+            ::rusty_v8_helper::FfiParam {
+                name: "sql",
+                ty: "String",
+            },
+        ],
+        returns: "Vec<String>",
+        doc: "Only compiled with `sqlite`.",
+    };
+    fn load<'sc, 'c>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+        __v8_ffi_context: ::rusty_v8_helper::v8::Local<
+            'c,
+            ::rusty_v8_helper::v8::Context,
+        >,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+        __v8_ffi_query(__v8_ffi_scope, __v8_ffi_context)
+    }
+    fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+        ::rusty_v8_helper::v8::MapFnTo::map_fn_to(__v8_ffi_internal_query)
+    }
+    fn template<'sc>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+        ::rusty_v8_helper::v8::FunctionTemplate::new(
+            __v8_ffi_scope,
+            __v8_ffi_internal_query,
+        )
+    }
+}
+/// Only compiled with `sqlite`.
+#[cfg(feature = "sqlite")]
+#[inline]
+#[allow(clippy::needless_pass_by_value)]
+pub fn __v8_ffi_deno_query(
+    __v8_ffi_deno_args: ::rusty_v8_helper::deno::Value,
+) -> ::std::result::Result<::rusty_v8_helper::deno::Value, ::rusty_v8_helper::FFIError> {
+    let sql: String = ::rusty_v8_helper::deno::op_arg(&__v8_ffi_deno_args, 0usize)?;
+    let __returned = query(sql);
+    ::rusty_v8_helper::deno::OpReturn::into_op_value(__returned)
+}
+#[cfg_attr(test, inline(never))]
+#[deny(unused_variables)]
+pub fn double(x: f64) -> f64 {
+    x * 2.0
+}
+#[cfg_attr(test, inline(never))]
+#[deny(unused_variables)]
+fn __v8_ffi_internal_double<'sc>(
+    mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>,
+    __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>,
+    mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>,
+) {
+    #[allow(unused_imports)]
+    use ::rusty_v8_helper::owned::{
+        ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _,
+    };
+    let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+    let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(
+        __v8_ffi_scope,
+        "double",
+    );
+    if !::rusty_v8_helper::policy::check_policy(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &__v8_ffi_call,
+        "double",
+    ) {
+        return;
+    }
+    let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    let x = __v8_ffi_args.get(0i32);
+    let x = <::rusty_v8_helper::Coerced<
+        f64,
+    > as ::rusty_v8_helper::FFICompat>::from_value(x, __v8_ffi_scope, __v8_ffi_context)
+        .map(|x| x.0);
+    if let Err(e) = x {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let x = x.unwrap();
+    let __returned = double(x);
+    let __v8_ffi_value = (&::rusty_v8_helper::owned::Dispatch::of(&__returned))
+        .to_js(__returned, __v8_ffi_scope, __v8_ffi_context);
+    match __v8_ffi_value {
+        Ok(__v8_ffi_value) => __v8_ffi_rv.set(__v8_ffi_value),
+        Err(e) => {
+            __v8_ffi_rv.set(::rusty_v8_helper::v8::undefined(__v8_ffi_scope).into());
+            ::rusty_v8_helper::util::throw_call_error(
+                __v8_ffi_scope,
+                &__v8_ffi_call,
+                &e,
+            );
+            return;
+        }
+    }
+}
+#[cfg_attr(test, inline(never))]
+#[deny(unused_variables)]
+pub fn __v8_ffi_double<'sc, 'c>(
+    __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+    ::rusty_v8_helper::v8::Function::new(
+            __v8_ffi_scope,
+            __v8_ffi_context,
+            __v8_ffi_internal_double,
+        )
+        .unwrap()
+}
+#[deny(unused_variables)]
+#[doc(hidden)]
+#[allow(non_camel_case_types, dead_code)]
+pub struct double {}
+#[deny(unused_variables)]
+impl ::rusty_v8_helper::FfiFn for double {
+    const NAME: &'static str = "double";
+    const META: ::rusty_v8_helper::FfiFnMeta = ::rusty_v8_helper::FfiFnMeta {
+        name: "double",
+        arity: 1usize,
+        params: &[
+            ::rusty_v8_helper::FfiParam {
+                name: "x",
+                ty: "f64",
+            },
+        ],
+        returns: "f64",
+        doc: "",
+    };
+    fn load<'sc, 'c>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+        __v8_ffi_context: ::rusty_v8_helper::v8::Local<
+            'c,
+            ::rusty_v8_helper::v8::Context,
+        >,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+        __v8_ffi_double(__v8_ffi_scope, __v8_ffi_context)
+    }
+    fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+        ::rusty_v8_helper::v8::MapFnTo::map_fn_to(__v8_ffi_internal_double)
+    }
+    fn template<'sc>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+        ::rusty_v8_helper::v8::FunctionTemplate::new(
+            __v8_ffi_scope,
+            __v8_ffi_internal_double,
+        )
+    }
+}
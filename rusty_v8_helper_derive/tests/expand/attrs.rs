@@ -0,0 +1,15 @@
+/// Only compiled with `sqlite`.
+#[v8_ffi(deno_op)]
+#[cfg(feature = "sqlite")]
+#[inline]
+#[allow(clippy::needless_pass_by_value)]
+pub fn query(sql: String) -> Vec<String> {
+    vec![sql]
+}
+
+#[v8_ffi(coerce)]
+#[cfg_attr(test, inline(never))]
+#[deny(unused_variables)]
+pub fn double(x: f64) -> f64 {
+    x * 2.0
+}
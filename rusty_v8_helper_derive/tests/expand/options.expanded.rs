@@ -0,0 +1,244 @@
+pub fn square(x: f64) -> f64 {
+    x * x
+}
+fn __v8_ffi_internal_square<'sc>(
+    mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>,
+    __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>,
+    mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>,
+) {
+    #[allow(unused_imports)]
+    use ::rusty_v8_helper::owned::{
+        ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _,
+    };
+    let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+    let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(
+        __v8_ffi_scope,
+        "square",
+    );
+    if !::rusty_v8_helper::policy::check_policy(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &__v8_ffi_call,
+        "square",
+    ) {
+        return;
+    }
+    let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    let x = __v8_ffi_args.get(0i32);
+    let x = <::rusty_v8_helper::Coerced<
+        f64,
+    > as ::rusty_v8_helper::FFICompat>::from_value(x, __v8_ffi_scope, __v8_ffi_context)
+        .map(|x| x.0);
+    if let Err(e) = x {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let x = x.unwrap();
+    let __returned = square(x);
+    let __v8_ffi_value = (&::rusty_v8_helper::owned::Dispatch::of(&__returned))
+        .to_js(__returned, __v8_ffi_scope, __v8_ffi_context);
+    match __v8_ffi_value {
+        Ok(__v8_ffi_value) => __v8_ffi_rv.set(__v8_ffi_value),
+        Err(e) => {
+            __v8_ffi_rv.set(::rusty_v8_helper::v8::undefined(__v8_ffi_scope).into());
+            ::rusty_v8_helper::util::throw_call_error(
+                __v8_ffi_scope,
+                &__v8_ffi_call,
+                &e,
+            );
+            return;
+        }
+    }
+}
+pub fn __v8_ffi_square<'sc, 'c>(
+    __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+    ::rusty_v8_helper::v8::Function::new(
+            __v8_ffi_scope,
+            __v8_ffi_context,
+            __v8_ffi_internal_square,
+        )
+        .unwrap()
+}
+#[doc(hidden)]
+#[allow(non_camel_case_types, dead_code)]
+pub struct square {}
+impl ::rusty_v8_helper::FfiFn for square {
+    const NAME: &'static str = "square";
+    const META: ::rusty_v8_helper::FfiFnMeta = ::rusty_v8_helper::FfiFnMeta {
+        name: "square",
+        arity: 1usize,
+        params: &[
+            ::rusty_v8_helper::FfiParam {
+                name: "x",
+                ty: "f64",
+            },
+        ],
+        returns: "f64",
+        doc: "",
+    };
+    fn load<'sc, 'c>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+        __v8_ffi_context: ::rusty_v8_helper::v8::Local<
+            'c,
+            ::rusty_v8_helper::v8::Context,
+        >,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+        __v8_ffi_square(__v8_ffi_scope, __v8_ffi_context)
+    }
+    fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+        ::rusty_v8_helper::v8::MapFnTo::map_fn_to(__v8_ffi_internal_square)
+    }
+    fn template<'sc>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+        ::rusty_v8_helper::v8::FunctionTemplate::new(
+            __v8_ffi_scope,
+            __v8_ffi_internal_square,
+        )
+    }
+}
+pub(crate) fn greet(name: &str, times: u32) -> String {
+    format!("hello {}", name).repeat(times as usize)
+}
+fn __v8_ffi_internal_greet<'sc>(
+    mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>,
+    __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>,
+    mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>,
+) {
+    #[allow(unused_imports)]
+    use ::rusty_v8_helper::owned::{
+        ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _,
+    };
+    let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+    let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(
+        __v8_ffi_scope,
+        "greet",
+    );
+    if !::rusty_v8_helper::policy::check_policy(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &__v8_ffi_call,
+        "greet",
+    ) {
+        return;
+    }
+    let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    let name = __v8_ffi_args.get(0i32);
+    let name = <::std::string::String as ::rusty_v8_helper::FFICompat>::from_value(
+        name,
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    if let Err(e) = name {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let name = name.unwrap();
+    let times = __v8_ffi_args.get(1i32);
+    let times = (&<::rusty_v8_helper::owned::Dispatch<u32>>::default())
+        .from_js(times, __v8_ffi_scope, __v8_ffi_context);
+    if let Err(e) = times {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let times = times.unwrap();
+    let __returned = greet(&name, times);
+    let __v8_ffi_value = (&::rusty_v8_helper::owned::Dispatch::of(&__returned))
+        .to_js(__returned, __v8_ffi_scope, __v8_ffi_context);
+    match __v8_ffi_value {
+        Ok(__v8_ffi_value) => __v8_ffi_rv.set(__v8_ffi_value),
+        Err(e) => {
+            __v8_ffi_rv.set(::rusty_v8_helper::v8::undefined(__v8_ffi_scope).into());
+            ::rusty_v8_helper::util::throw_call_error(
+                __v8_ffi_scope,
+                &__v8_ffi_call,
+                &e,
+            );
+            return;
+        }
+    }
+}
+pub(crate) fn __v8_ffi_greet<'sc, 'c>(
+    __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+    ::rusty_v8_helper::v8::Function::new(
+            __v8_ffi_scope,
+            __v8_ffi_context,
+            __v8_ffi_internal_greet,
+        )
+        .unwrap()
+}
+#[doc(hidden)]
+#[allow(non_camel_case_types, dead_code)]
+pub(crate) struct greet {}
+impl ::rusty_v8_helper::FfiFn for greet {
+    const NAME: &'static str = "greet";
+    const META: ::rusty_v8_helper::FfiFnMeta = ::rusty_v8_helper::FfiFnMeta {
+        name: "greet",
+        arity: 2usize,
+        params: &[
+            ::rusty_v8_helper::FfiParam {
+                name: "name",
+                ty: "&str",
+            },
+            ::rusty_v8_helper::FfiParam {
+                name: "times",
+                ty: "u32",
+            },
+        ],
+        returns: "String",
+        doc: "",
+    };
+    fn load<'sc, 'c>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+        __v8_ffi_context: ::rusty_v8_helper::v8::Local<
+            'c,
+            ::rusty_v8_helper::v8::Context,
+        >,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+        __v8_ffi_greet(__v8_ffi_scope, __v8_ffi_context)
+    }
+    fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+        ::rusty_v8_helper::v8::MapFnTo::map_fn_to(__v8_ffi_internal_greet)
+    }
+    fn template<'sc>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+        ::rusty_v8_helper::v8::FunctionTemplate::new(
+            __v8_ffi_scope,
+            __v8_ffi_internal_greet,
+        )
+    }
+}
+pub(crate) fn __v8_ffi_deno_greet(
+    __v8_ffi_deno_args: ::rusty_v8_helper::deno::Value,
+) -> ::std::result::Result<::rusty_v8_helper::deno::Value, ::rusty_v8_helper::FFIError> {
+    let name: ::std::string::String = ::rusty_v8_helper::deno::op_arg(
+        &__v8_ffi_deno_args,
+        0usize,
+    )?;
+    let times: u32 = ::rusty_v8_helper::deno::op_arg(&__v8_ffi_deno_args, 1usize)?;
+    let __returned = greet(&name, times);
+    ::rusty_v8_helper::deno::OpReturn::into_op_value(__returned)
+}
@@ -0,0 +1,9 @@
+#[v8_ffi(coerce)]
+pub fn square(x: f64) -> f64 {
+    x * x
+}
+
+#[v8_ffi(deno_op)]
+pub(crate) fn greet(name: &str, times: u32) -> String {
+    format!("hello {}", name).repeat(times as usize)
+}
@@ -0,0 +1,304 @@
+fn read(length: u32) -> (u32, bool) {
+    (length, true)
+}
+fn __v8_ffi_internal_read<'sc>(
+    mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>,
+    __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>,
+    mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>,
+) {
+    #[allow(unused_imports)]
+    use ::rusty_v8_helper::owned::{
+        ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _,
+    };
+    let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+    let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(
+        __v8_ffi_scope,
+        "read",
+    );
+    if !::rusty_v8_helper::policy::check_policy(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &__v8_ffi_call,
+        "read",
+    ) {
+        return;
+    }
+    let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    let length = __v8_ffi_args.get(0i32);
+    let length = (&<::rusty_v8_helper::owned::Dispatch<u32>>::default())
+        .from_js(length, __v8_ffi_scope, __v8_ffi_context);
+    if let Err(e) = length {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let length = length.unwrap();
+    let __returned = read(length);
+    let __v8_ffi_value = ::rusty_v8_helper::js_object::MultiReturn::to_object(
+        __returned,
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &["bytesRead", "eof"],
+    );
+    match __v8_ffi_value {
+        Ok(__v8_ffi_value) => __v8_ffi_rv.set(__v8_ffi_value),
+        Err(e) => {
+            __v8_ffi_rv.set(::rusty_v8_helper::v8::undefined(__v8_ffi_scope).into());
+            ::rusty_v8_helper::util::throw_call_error(
+                __v8_ffi_scope,
+                &__v8_ffi_call,
+                &e,
+            );
+            return;
+        }
+    }
+}
+fn __v8_ffi_read<'sc, 'c>(
+    __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+    ::rusty_v8_helper::v8::Function::new(
+            __v8_ffi_scope,
+            __v8_ffi_context,
+            __v8_ffi_internal_read,
+        )
+        .unwrap()
+}
+#[doc(hidden)]
+#[allow(non_camel_case_types, dead_code)]
+struct read {}
+impl ::rusty_v8_helper::FfiFn for read {
+    const NAME: &'static str = "read";
+    const META: ::rusty_v8_helper::FfiFnMeta = ::rusty_v8_helper::FfiFnMeta {
+        name: "read",
+        arity: 1usize,
+        params: &[
+            ::rusty_v8_helper::FfiParam {
+                name: "length",
+                ty: "u32",
+            },
+        ],
+        returns: "(u32, bool)",
+        doc: "",
+    };
+    fn load<'sc, 'c>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+        __v8_ffi_context: ::rusty_v8_helper::v8::Local<
+            'c,
+            ::rusty_v8_helper::v8::Context,
+        >,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+        __v8_ffi_read(__v8_ffi_scope, __v8_ffi_context)
+    }
+    fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+        ::rusty_v8_helper::v8::MapFnTo::map_fn_to(__v8_ffi_internal_read)
+    }
+    fn template<'sc>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+        ::rusty_v8_helper::v8::FunctionTemplate::new(
+            __v8_ffi_scope,
+            __v8_ffi_internal_read,
+        )
+    }
+}
+fn origin() -> Point {
+    Point::default()
+}
+fn __v8_ffi_internal_origin<'sc>(
+    mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>,
+    __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>,
+    mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>,
+) {
+    #[allow(unused_imports)]
+    use ::rusty_v8_helper::owned::{
+        ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _,
+    };
+    let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+    let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(
+        __v8_ffi_scope,
+        "origin",
+    );
+    if !::rusty_v8_helper::policy::check_policy(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &__v8_ffi_call,
+        "origin",
+    ) {
+        return;
+    }
+    let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    let __returned = origin();
+    let __v8_ffi_value = rusty_v8_helper::json::to_value(
+        __returned,
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    match __v8_ffi_value {
+        Ok(__v8_ffi_value) => __v8_ffi_rv.set(__v8_ffi_value),
+        Err(e) => {
+            __v8_ffi_rv.set(::rusty_v8_helper::v8::undefined(__v8_ffi_scope).into());
+            ::rusty_v8_helper::util::throw_call_error(
+                __v8_ffi_scope,
+                &__v8_ffi_call,
+                &e,
+            );
+            return;
+        }
+    }
+}
+fn __v8_ffi_origin<'sc, 'c>(
+    __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+    ::rusty_v8_helper::v8::Function::new(
+            __v8_ffi_scope,
+            __v8_ffi_context,
+            __v8_ffi_internal_origin,
+        )
+        .unwrap()
+}
+#[doc(hidden)]
+#[allow(non_camel_case_types, dead_code)]
+struct origin {}
+impl ::rusty_v8_helper::FfiFn for origin {
+    const NAME: &'static str = "origin";
+    const META: ::rusty_v8_helper::FfiFnMeta = ::rusty_v8_helper::FfiFnMeta {
+        name: "origin",
+        arity: 0usize,
+        params: &[],
+        returns: "Point",
+        doc: "",
+    };
+    fn load<'sc, 'c>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+        __v8_ffi_context: ::rusty_v8_helper::v8::Local<
+            'c,
+            ::rusty_v8_helper::v8::Context,
+        >,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+        __v8_ffi_origin(__v8_ffi_scope, __v8_ffi_context)
+    }
+    fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+        ::rusty_v8_helper::v8::MapFnTo::map_fn_to(__v8_ffi_internal_origin)
+    }
+    fn template<'sc>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+        ::rusty_v8_helper::v8::FunctionTemplate::new(
+            __v8_ffi_scope,
+            __v8_ffi_internal_origin,
+        )
+    }
+}
+fn parse(text: String) -> Result<u32, String> {
+    text.parse().map_err(|e| format!("{}", e))
+}
+fn __v8_ffi_internal_parse<'sc>(
+    mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>,
+    __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>,
+    mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>,
+) {
+    #[allow(unused_imports)]
+    use ::rusty_v8_helper::owned::{
+        ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _,
+    };
+    let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+    let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(
+        __v8_ffi_scope,
+        "parse",
+    );
+    if !::rusty_v8_helper::policy::check_policy(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &__v8_ffi_call,
+        "parse",
+    ) {
+        return;
+    }
+    let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    let text = __v8_ffi_args.get(0i32);
+    let text = (&<::rusty_v8_helper::owned::Dispatch<String>>::default())
+        .from_js(text, __v8_ffi_scope, __v8_ffi_context);
+    if let Err(e) = text {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let text = text.unwrap();
+    let __returned = parse(text);
+    let __v8_ffi_value = (&::rusty_v8_helper::owned::Dispatch::of(&__returned))
+        .to_js(__returned, __v8_ffi_scope, __v8_ffi_context);
+    match __v8_ffi_value {
+        Ok(__v8_ffi_value) => __v8_ffi_rv.set(__v8_ffi_value),
+        Err(e) => {
+            __v8_ffi_rv.set(::rusty_v8_helper::v8::undefined(__v8_ffi_scope).into());
+            __v8_ffi_call.exception(&e);
+            return;
+        }
+    }
+}
+fn __v8_ffi_parse<'sc, 'c>(
+    __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+    ::rusty_v8_helper::v8::Function::new(
+            __v8_ffi_scope,
+            __v8_ffi_context,
+            __v8_ffi_internal_parse,
+        )
+        .unwrap()
+}
+#[doc(hidden)]
+#[allow(non_camel_case_types, dead_code)]
+struct parse {}
+impl ::rusty_v8_helper::FfiFn for parse {
+    const NAME: &'static str = "parse";
+    const META: ::rusty_v8_helper::FfiFnMeta = ::rusty_v8_helper::FfiFnMeta {
+        name: "parse",
+        arity: 1usize,
+        params: &[
+            ::rusty_v8_helper::FfiParam {
+                name: "text",
+                ty: "String",
+            },
+        ],
+        returns: "Result<u32, String>",
+        doc: "",
+    };
+    fn load<'sc, 'c>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+        __v8_ffi_context: ::rusty_v8_helper::v8::Local<
+            'c,
+            ::rusty_v8_helper::v8::Context,
+        >,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+        __v8_ffi_parse(__v8_ffi_scope, __v8_ffi_context)
+    }
+    fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+        ::rusty_v8_helper::v8::MapFnTo::map_fn_to(__v8_ffi_internal_parse)
+    }
+    fn template<'sc>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+        ::rusty_v8_helper::v8::FunctionTemplate::new(
+            __v8_ffi_scope,
+            __v8_ffi_internal_parse,
+        )
+    }
+}
@@ -0,0 +1,14 @@
+#[v8_ffi(multi_return(bytes_read, eof))]
+fn read(length: u32) -> (u32, bool) {
+    (length, true)
+}
+
+#[v8_ffi(return_with = "rusty_v8_helper::json")]
+fn origin() -> Point {
+    Point::default()
+}
+
+#[v8_ffi(return_undefined_on_error)]
+fn parse(text: String) -> Result<u32, String> {
+    text.parse().map_err(|e| format!("{}", e))
+}
@@ -0,0 +1,531 @@
+fn name(this: &Named) -> String {
+    this.0.clone()
+}
+fn __v8_ffi_internal_name<'sc>(
+    mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>,
+    __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>,
+    mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>,
+) {
+    #[allow(unused_imports)]
+    use ::rusty_v8_helper::owned::{
+        ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _,
+    };
+    let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+    let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(
+        __v8_ffi_scope,
+        "name",
+    );
+    if !::rusty_v8_helper::policy::check_policy(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &__v8_ffi_call,
+        "name",
+    ) {
+        return;
+    }
+    let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    let this: ::std::result::Result<::std::rc::Rc<Named>, _> = ::rusty_v8_helper::ObjectWrap::try_from_object(
+        __v8_ffi_args.this(),
+    );
+    if let Err(e) = &this {
+        ::rusty_v8_helper::util::throw_invalid_this(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            "name",
+            e,
+        );
+        return;
+    }
+    let this = this.unwrap();
+    let this = &this;
+    let __returned = name(this);
+    let __v8_ffi_value = (&::rusty_v8_helper::owned::Dispatch::of(&__returned))
+        .to_js(__returned, __v8_ffi_scope, __v8_ffi_context);
+    match __v8_ffi_value {
+        Ok(__v8_ffi_value) => __v8_ffi_rv.set(__v8_ffi_value),
+        Err(e) => {
+            __v8_ffi_rv.set(::rusty_v8_helper::v8::undefined(__v8_ffi_scope).into());
+            ::rusty_v8_helper::util::throw_call_error(
+                __v8_ffi_scope,
+                &__v8_ffi_call,
+                &e,
+            );
+            return;
+        }
+    }
+}
+fn __v8_ffi_name<'sc, 'c>(
+    __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+    ::rusty_v8_helper::v8::Function::new(
+            __v8_ffi_scope,
+            __v8_ffi_context,
+            __v8_ffi_internal_name,
+        )
+        .unwrap()
+}
+#[doc(hidden)]
+#[allow(non_camel_case_types, dead_code)]
+struct name {}
+impl ::rusty_v8_helper::FfiFn for name {
+    const NAME: &'static str = "name";
+    const META: ::rusty_v8_helper::FfiFnMeta = ::rusty_v8_helper::FfiFnMeta {
+        name: "name",
+        arity: 0usize,
+        params: &[],
+        returns: "String",
+        doc: "",
+    };
+    fn load<'sc, 'c>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+        __v8_ffi_context: ::rusty_v8_helper::v8::Local<
+            'c,
+            ::rusty_v8_helper::v8::Context,
+        >,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+        __v8_ffi_name(__v8_ffi_scope, __v8_ffi_context)
+    }
+    fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+        ::rusty_v8_helper::v8::MapFnTo::map_fn_to(__v8_ffi_internal_name)
+    }
+    fn template<'sc>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+        ::rusty_v8_helper::v8::FunctionTemplate::new(
+            __v8_ffi_scope,
+            __v8_ffi_internal_name,
+        )
+    }
+}
+fn rename(this: &mut Named, name: String) {
+    this.0 = name;
+}
+fn __v8_ffi_internal_rename<'sc>(
+    mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>,
+    __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>,
+    mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>,
+) {
+    #[allow(unused_imports)]
+    use ::rusty_v8_helper::owned::{
+        ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _,
+    };
+    let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+    let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(
+        __v8_ffi_scope,
+        "rename",
+    );
+    if !::rusty_v8_helper::policy::check_policy(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &__v8_ffi_call,
+        "rename",
+    ) {
+        return;
+    }
+    let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    let this: ::std::result::Result<::std::rc::Rc<::std::sync::Mutex<Named>>, _> = ::rusty_v8_helper::ObjectWrap::try_from_object(
+        __v8_ffi_args.this(),
+    );
+    if let Err(e) = &this {
+        ::rusty_v8_helper::util::throw_invalid_this(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            "rename",
+            e,
+        );
+        return;
+    }
+    let this = this.unwrap();
+    let this = ::rusty_v8_helper::ThisGuard::lock(&this, "rename");
+    if let Err(e) = &this {
+        ::rusty_v8_helper::util::throw_call_error(__v8_ffi_scope, &__v8_ffi_call, e);
+        return;
+    }
+    let mut this = this.unwrap();
+    let mut this = &mut this;
+    let name = __v8_ffi_args.get(0i32);
+    let name = (&<::rusty_v8_helper::owned::Dispatch<String>>::default())
+        .from_js(name, __v8_ffi_scope, __v8_ffi_context);
+    if let Err(e) = name {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let name = name.unwrap();
+    let __returned = rename(this, name);
+}
+fn __v8_ffi_rename<'sc, 'c>(
+    __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+    ::rusty_v8_helper::v8::Function::new(
+            __v8_ffi_scope,
+            __v8_ffi_context,
+            __v8_ffi_internal_rename,
+        )
+        .unwrap()
+}
+#[doc(hidden)]
+#[allow(non_camel_case_types, dead_code)]
+struct rename {}
+impl ::rusty_v8_helper::FfiFn for rename {
+    const NAME: &'static str = "rename";
+    const META: ::rusty_v8_helper::FfiFnMeta = ::rusty_v8_helper::FfiFnMeta {
+        name: "rename",
+        arity: 1usize,
+        params: &[
+            ::rusty_v8_helper::FfiParam {
+                name: "name",
+                ty: "String",
+            },
+        ],
+        returns: "()",
+        doc: "",
+    };
+    fn load<'sc, 'c>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+        __v8_ffi_context: ::rusty_v8_helper::v8::Local<
+            'c,
+            ::rusty_v8_helper::v8::Context,
+        >,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+        __v8_ffi_rename(__v8_ffi_scope, __v8_ffi_context)
+    }
+    fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+        ::rusty_v8_helper::v8::MapFnTo::map_fn_to(__v8_ffi_internal_rename)
+    }
+    fn template<'sc>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+        ::rusty_v8_helper::v8::FunctionTemplate::new(
+            __v8_ffi_scope,
+            __v8_ffi_internal_rename,
+        )
+    }
+}
+fn describe(this: &dyn Describe) -> String {
+    this.describe()
+}
+fn __v8_ffi_internal_describe<'sc>(
+    mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>,
+    __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>,
+    mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>,
+) {
+    #[allow(unused_imports)]
+    use ::rusty_v8_helper::owned::{
+        ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _,
+    };
+    let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+    let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(
+        __v8_ffi_scope,
+        "describe",
+    );
+    if !::rusty_v8_helper::policy::check_policy(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &__v8_ffi_call,
+        "describe",
+    ) {
+        return;
+    }
+    let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    let this: ::std::result::Result<::std::rc::Rc<::std::rc::Rc<dyn Describe>>, _> = ::rusty_v8_helper::ObjectWrap::try_from_object(
+        __v8_ffi_args.this(),
+    );
+    if let Err(e) = &this {
+        ::rusty_v8_helper::util::throw_invalid_this(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            "describe",
+            e,
+        );
+        return;
+    }
+    let this = this.unwrap();
+    let this: &dyn Describe = &**this;
+    let __returned = describe(this);
+    let __v8_ffi_value = (&::rusty_v8_helper::owned::Dispatch::of(&__returned))
+        .to_js(__returned, __v8_ffi_scope, __v8_ffi_context);
+    match __v8_ffi_value {
+        Ok(__v8_ffi_value) => __v8_ffi_rv.set(__v8_ffi_value),
+        Err(e) => {
+            __v8_ffi_rv.set(::rusty_v8_helper::v8::undefined(__v8_ffi_scope).into());
+            ::rusty_v8_helper::util::throw_call_error(
+                __v8_ffi_scope,
+                &__v8_ffi_call,
+                &e,
+            );
+            return;
+        }
+    }
+}
+fn __v8_ffi_describe<'sc, 'c>(
+    __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+    ::rusty_v8_helper::v8::Function::new(
+            __v8_ffi_scope,
+            __v8_ffi_context,
+            __v8_ffi_internal_describe,
+        )
+        .unwrap()
+}
+#[doc(hidden)]
+#[allow(non_camel_case_types, dead_code)]
+struct describe {}
+impl ::rusty_v8_helper::FfiFn for describe {
+    const NAME: &'static str = "describe";
+    const META: ::rusty_v8_helper::FfiFnMeta = ::rusty_v8_helper::FfiFnMeta {
+        name: "describe",
+        arity: 0usize,
+        params: &[],
+        returns: "String",
+        doc: "",
+    };
+    fn load<'sc, 'c>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+        __v8_ffi_context: ::rusty_v8_helper::v8::Local<
+            'c,
+            ::rusty_v8_helper::v8::Context,
+        >,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+        __v8_ffi_describe(__v8_ffi_scope, __v8_ffi_context)
+    }
+    fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+        ::rusty_v8_helper::v8::MapFnTo::map_fn_to(__v8_ffi_internal_describe)
+    }
+    fn template<'sc>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+        ::rusty_v8_helper::v8::FunctionTemplate::new(
+            __v8_ffi_scope,
+            __v8_ffi_internal_describe,
+        )
+    }
+}
+fn counter(this: &ThisOf<Counter>) -> u64 {
+    this.value()
+}
+fn __v8_ffi_internal_counter<'sc>(
+    mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>,
+    __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>,
+    mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>,
+) {
+    #[allow(unused_imports)]
+    use ::rusty_v8_helper::owned::{
+        ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _,
+    };
+    let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+    let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(
+        __v8_ffi_scope,
+        "counter",
+    );
+    if !::rusty_v8_helper::policy::check_policy(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &__v8_ffi_call,
+        "counter",
+    ) {
+        return;
+    }
+    let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    let this: ::std::result::Result<ThisOf<Counter>, _> = ::rusty_v8_helper::FromThis::from_this(
+        __v8_ffi_args.this(),
+    );
+    if let Err(e) = &this {
+        ::rusty_v8_helper::util::throw_invalid_this(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            "counter",
+            e,
+        );
+        return;
+    }
+    let this = this.unwrap();
+    let this = &this;
+    let __returned = counter(this);
+    let __v8_ffi_value = (&::rusty_v8_helper::owned::Dispatch::of(&__returned))
+        .to_js(__returned, __v8_ffi_scope, __v8_ffi_context);
+    match __v8_ffi_value {
+        Ok(__v8_ffi_value) => __v8_ffi_rv.set(__v8_ffi_value),
+        Err(e) => {
+            __v8_ffi_rv.set(::rusty_v8_helper::v8::undefined(__v8_ffi_scope).into());
+            ::rusty_v8_helper::util::throw_call_error(
+                __v8_ffi_scope,
+                &__v8_ffi_call,
+                &e,
+            );
+            return;
+        }
+    }
+}
+fn __v8_ffi_counter<'sc, 'c>(
+    __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+    ::rusty_v8_helper::v8::Function::new(
+            __v8_ffi_scope,
+            __v8_ffi_context,
+            __v8_ffi_internal_counter,
+        )
+        .unwrap()
+}
+#[doc(hidden)]
+#[allow(non_camel_case_types, dead_code)]
+struct counter {}
+impl ::rusty_v8_helper::FfiFn for counter {
+    const NAME: &'static str = "counter";
+    const META: ::rusty_v8_helper::FfiFnMeta = ::rusty_v8_helper::FfiFnMeta {
+        name: "counter",
+        arity: 0usize,
+        params: &[],
+        returns: "u64",
+        doc: "",
+    };
+    fn load<'sc, 'c>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+        __v8_ffi_context: ::rusty_v8_helper::v8::Local<
+            'c,
+            ::rusty_v8_helper::v8::Context,
+        >,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+        __v8_ffi_counter(__v8_ffi_scope, __v8_ffi_context)
+    }
+    fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+        ::rusty_v8_helper::v8::MapFnTo::map_fn_to(__v8_ffi_internal_counter)
+    }
+    fn template<'sc>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+        ::rusty_v8_helper::v8::FunctionTemplate::new(
+            __v8_ffi_scope,
+            __v8_ffi_internal_counter,
+        )
+    }
+}
+fn global<'sc>(
+    scope: &mut impl ToLocal<'sc>,
+    context: Local<Context>,
+    name: String,
+) -> Value {
+    Value::Null
+}
+fn __v8_ffi_internal_global<'sc>(
+    mut __v8_ffi_scope: ::rusty_v8_helper::v8::FunctionCallbackScope<'sc>,
+    __v8_ffi_args: ::rusty_v8_helper::v8::FunctionCallbackArguments<'sc>,
+    mut __v8_ffi_rv: ::rusty_v8_helper::v8::ReturnValue<'sc>,
+) {
+    #[allow(unused_imports)]
+    use ::rusty_v8_helper::owned::{
+        ViaFromCompat as _, ViaFromOwned as _, ViaIntoCompat as _, ViaIntoOwned as _,
+    };
+    let __v8_ffi_context = __v8_ffi_scope.get_current_context().unwrap();
+    let __v8_ffi_call = ::rusty_v8_helper::instrument::FfiCall::start(
+        __v8_ffi_scope,
+        "global",
+    );
+    if !::rusty_v8_helper::policy::check_policy(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+        &__v8_ffi_call,
+        "global",
+    ) {
+        return;
+    }
+    let __v8_ffi_span = ::rusty_v8_helper::accounting::enter_ffi(
+        __v8_ffi_scope,
+        __v8_ffi_context,
+    );
+    let name = __v8_ffi_args.get(0i32);
+    let name = (&<::rusty_v8_helper::owned::Dispatch<String>>::default())
+        .from_js(name, __v8_ffi_scope, __v8_ffi_context);
+    if let Err(e) = name {
+        ::rusty_v8_helper::util::throw_conversion_error(
+            __v8_ffi_scope,
+            &__v8_ffi_call,
+            &e,
+        );
+        return;
+    }
+    let name = name.unwrap();
+    let __returned = global(__v8_ffi_scope, __v8_ffi_context, name);
+    let __v8_ffi_value = (&::rusty_v8_helper::owned::Dispatch::of(&__returned))
+        .to_js(__returned, __v8_ffi_scope, __v8_ffi_context);
+    match __v8_ffi_value {
+        Ok(__v8_ffi_value) => __v8_ffi_rv.set(__v8_ffi_value),
+        Err(e) => {
+            __v8_ffi_rv.set(::rusty_v8_helper::v8::undefined(__v8_ffi_scope).into());
+            ::rusty_v8_helper::util::throw_call_error(
+                __v8_ffi_scope,
+                &__v8_ffi_call,
+                &e,
+            );
+            return;
+        }
+    }
+}
+fn __v8_ffi_global<'sc, 'c>(
+    __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    __v8_ffi_context: ::rusty_v8_helper::v8::Local<'c, ::rusty_v8_helper::v8::Context>,
+) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+    ::rusty_v8_helper::v8::Function::new(
+            __v8_ffi_scope,
+            __v8_ffi_context,
+            __v8_ffi_internal_global,
+        )
+        .unwrap()
+}
+#[doc(hidden)]
+#[allow(non_camel_case_types, dead_code)]
+struct global {}
+impl ::rusty_v8_helper::FfiFn for global {
+    const NAME: &'static str = "global";
+    const META: ::rusty_v8_helper::FfiFnMeta = ::rusty_v8_helper::FfiFnMeta {
+        name: "global",
+        arity: 1usize,
+        params: &[
+            ::rusty_v8_helper::FfiParam {
+                name: "name",
+                ty: "String",
+            },
+        ],
+        returns: "Value",
+        doc: "",
+    };
+    fn load<'sc, 'c>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+        __v8_ffi_context: ::rusty_v8_helper::v8::Local<
+            'c,
+            ::rusty_v8_helper::v8::Context,
+        >,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::Function> {
+        __v8_ffi_global(__v8_ffi_scope, __v8_ffi_context)
+    }
+    fn callback() -> ::rusty_v8_helper::v8::FunctionCallback {
+        ::rusty_v8_helper::v8::MapFnTo::map_fn_to(__v8_ffi_internal_global)
+    }
+    fn template<'sc>(
+        __v8_ffi_scope: &mut impl ::rusty_v8_helper::v8::ToLocal<'sc>,
+    ) -> ::rusty_v8_helper::v8::Local<'sc, ::rusty_v8_helper::v8::FunctionTemplate> {
+        ::rusty_v8_helper::v8::FunctionTemplate::new(
+            __v8_ffi_scope,
+            __v8_ffi_internal_global,
+        )
+    }
+}
@@ -0,0 +1,24 @@
+#[v8_ffi]
+fn name(this: &Named) -> String {
+    this.0.clone()
+}
+
+#[v8_ffi]
+fn rename(this: &mut Named, name: String) {
+    this.0 = name;
+}
+
+#[v8_ffi]
+fn describe(this: &dyn Describe) -> String {
+    this.describe()
+}
+
+#[v8_ffi]
+fn counter(this: &ThisOf<Counter>) -> u64 {
+    this.value()
+}
+
+#[v8_ffi(scoped)]
+fn global<'sc>(scope: &mut impl ToLocal<'sc>, context: Local<Context>, name: String) -> Value {
+    Value::Null
+}
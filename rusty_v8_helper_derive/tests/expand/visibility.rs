@@ -0,0 +1,12 @@
+#[v8_ffi(deno_op)]
+pub(crate) fn checksum(data: &[u8]) -> u32 {
+    data.iter().map(|x| *x as u32).sum()
+}
+
+#[v8_ffi(loader_vis = "pub", coerce)]
+fn next_id(last: u32) -> u32 {
+    last + 1
+}
+
+#[v8_ffi(loader_vis = "pub(in crate::runtime)")]
+pub(super) fn reset() {}
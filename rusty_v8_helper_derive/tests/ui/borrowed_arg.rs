@@ -0,0 +1,8 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+struct Named(String);
+
+#[v8_ffi]
+fn rename(this: &Named, other: &Named, scores: &[u32]) {}
+
+fn main() {}
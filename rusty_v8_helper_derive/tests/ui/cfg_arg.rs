@@ -0,0 +1,6 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi]
+fn open(path: String, #[cfg(unix)] mode: u32) {}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi(deno_op)]
+fn parse(#[ffi(json)] point: String) {}
+
+fn main() {}
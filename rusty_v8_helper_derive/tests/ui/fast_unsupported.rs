@@ -0,0 +1,8 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi(fast)]
+fn square(x: f64) -> f64 {
+    x * x
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi]
+fn render(value: impl ToString) -> String {
+    value.to_string()
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use proc_macro_hack::proc_macro_hack;
+
+#[proc_macro_hack]
+use rusty_v8_helper_derive::js;
+
+fn main() {
+    let _ = js!(scope, context, {
+        /// the answer
+        42
+    });
+}
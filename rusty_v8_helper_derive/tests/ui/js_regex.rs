@@ -0,0 +1,8 @@
+use proc_macro_hack::proc_macro_hack;
+
+#[proc_macro_hack]
+use rusty_v8_helper_derive::js;
+
+fn main() {
+    let _ = js!(scope, context, /a+/.test("aa"));
+}
@@ -0,0 +1,13 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi(loader_vis = "public")]
+fn answer() -> u32 {
+    42
+}
+
+#[v8_ffi(loader_vis = "")]
+fn question() -> u32 {
+    42
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi(multi_return(bytes_read, eof))]
+fn read() -> (u32, bool, String) {
+    (0, true, String::new())
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi(multi_return("bytes read", 2))]
+fn read() -> (u32, bool) {
+    (0, true)
+}
+
+fn main() {}
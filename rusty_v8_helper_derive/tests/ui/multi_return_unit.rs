@@ -0,0 +1,6 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi(multi_return(bytes_read, eof))]
+fn read() {}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi]
+async fn sum((a, b): (f64, f64), #[ffi(via = "crate::number")] c: f64) -> &str {
+    ""
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+trait Describe {}
+
+#[v8_ffi]
+fn describe(this: &mut dyn Describe) {}
+
+fn main() {}
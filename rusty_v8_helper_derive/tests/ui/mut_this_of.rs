@@ -0,0 +1,8 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+struct ThisOf<T>(T);
+
+#[v8_ffi]
+fn reset(this: &mut ThisOf<u32>) {}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi]
+fn greet(name: Option<&str>, tags: Vec<(&str, &[u8])>) {}
+
+fn main() {}
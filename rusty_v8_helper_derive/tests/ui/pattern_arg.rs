@@ -0,0 +1,8 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi]
+fn sum((a, b): (f64, f64)) -> f64 {
+    a + b
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi]
+fn name(id: u32) -> &str {
+    "name"
+}
+
+fn main() {}
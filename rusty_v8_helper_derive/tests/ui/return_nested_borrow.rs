@@ -0,0 +1,8 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi]
+fn first(names: Vec<String>) -> Option<&'static str> {
+    None
+}
+
+fn main() {}
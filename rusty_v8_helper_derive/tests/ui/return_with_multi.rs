@@ -0,0 +1,8 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi(return_with = "crate::pair", multi_return(a, b))]
+fn pair() -> (u32, u32) {
+    (1, 2)
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi(return_with = "not a path")]
+fn answer() -> u32 {
+    42
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi(return_with = "crate::number")]
+fn reset() {}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+struct Named(String);
+
+#[v8_ffi]
+fn named(name: String) -> &Named {
+    unimplemented!()
+}
+
+fn main() {}
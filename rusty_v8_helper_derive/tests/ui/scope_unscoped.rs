@@ -0,0 +1,6 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi]
+fn global<'sc>(scope: &mut impl ToLocal<'sc>, context: Local<Context>, name: String) {}
+
+fn main() {}
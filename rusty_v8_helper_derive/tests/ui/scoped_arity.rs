@@ -0,0 +1,8 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi(scoped)]
+fn answer(scope: u32) -> u32 {
+    42
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+struct Counter(u32);
+
+impl Counter {
+    #[v8_ffi]
+    fn value(&self) -> u32 {
+        self.0
+    }
+}
+
+fn main() {}
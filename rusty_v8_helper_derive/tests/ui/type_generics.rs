@@ -0,0 +1,8 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi]
+fn first<T>(items: Vec<T>) -> T {
+    items.into_iter().next().unwrap()
+}
+
+fn main() {}
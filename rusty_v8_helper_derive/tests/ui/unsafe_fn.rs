@@ -0,0 +1,8 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi]
+unsafe fn answer() -> u32 {
+    42
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi]
+fn first<'a>(items: Vec<String>) -> String
+where
+    'a: 'static,
+{
+    items[0].clone()
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use rusty_v8_helper_derive::v8_ffi;
+
+#[v8_ffi]
+fn parse(#[ffi(via = "crate::number")] text: String) {}
+
+fn main() {}
@@ -0,0 +1,338 @@
+//! Per-context accounting of wall time, CPU time and external memory, i.e.
+//! to bill or throttle the tenants of a multi-tenant host. Contexts are
+//! metered once `Runtime::meter_context` is called for them.
+//!
+//! Time is measured in spans: every `v8_ffi` call, every callback invoked
+//! through the `CallbackRegistry`, and every run wrapped in `metered`.
+//! Spans nested in a span of the same context, i.e. the FFI calls of a
+//! metered script, are counted once, as part of the outermost. A span of
+//! another context nested in a span, i.e. a function exposed from another
+//! context, is counted for both.
+//!
+//! ```ignore
+//! let (tenant, context) = runtime.create_context();
+//! let meter = runtime.meter_context(tenant).unwrap();
+//! // deny calls once the tenant used up its CPU time
+//! runtime.set_policy(tenant, move |_: &'static str, _: ContextId| {
+//!     if meter.usage().cpu_time > quota {
+//!         return Err("quota exceeded".to_string());
+//!     }
+//!     Ok(())
+//! });
+//! ```
+
+use crate::util::{isolate_slot, set_isolate_slot};
+use crate::{ContextId, JsRef};
+use rusty_v8 as v8;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// What a context used, see `accounting`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContextUsage {
+    pub wall_time: Duration,
+    /// CPU time of the isolate's thread, zero on platforms without a
+    /// per-thread CPU clock.
+    pub cpu_time: Duration,
+    pub ffi_calls: u64,
+    /// Callbacks invoked and runs wrapped in `metered`, not counting those
+    /// nested in another of the context.
+    pub script_runs: u64,
+    /// Bytes of external memory currently attributed to the context with
+    /// `adjust_external_memory`.
+    pub external_memory: u64,
+    pub peak_external_memory: u64,
+}
+
+#[derive(Default)]
+struct MeterState {
+    usage: Cell<ContextUsage>,
+    depth: Cell<u32>,
+}
+
+/// `UsageMeter` accumulates the usage of one context. Clones share the
+/// same counts, so a meter can be moved into a `PolicyHook` to throttle the
+/// context by its own usage.
+#[derive(Clone, Default)]
+pub struct UsageMeter(Rc<MeterState>);
+
+impl UsageMeter {
+    pub fn usage(&self) -> ContextUsage {
+        self.0.usage.get()
+    }
+
+    /// Start counting from zero, keeping the external memory still
+    /// attributed.
+    pub fn reset(&self) {
+        let usage = self.0.usage.get();
+        self.0.usage.set(ContextUsage {
+            external_memory: usage.external_memory,
+            peak_external_memory: usage.external_memory,
+            ..ContextUsage::default()
+        });
+    }
+
+    fn update(&self, f: impl FnOnce(&mut ContextUsage)) {
+        let mut usage = self.0.usage.get();
+        f(&mut usage);
+        self.0.usage.set(usage);
+    }
+}
+
+#[cfg(unix)]
+fn thread_cpu_time() -> Duration {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // `clock_gettime` only writes to `time`
+    unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) };
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+#[cfg(not(unix))]
+fn thread_cpu_time() -> Duration {
+    Duration::from_secs(0)
+}
+
+struct Entry {
+    id: ContextId,
+    context: JsRef<v8::Context>,
+    meter: UsageMeter,
+}
+
+#[derive(Default)]
+struct MeterRegistry(RefCell<Vec<Entry>>);
+
+impl MeterRegistry {
+    fn find<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+    ) -> Option<UsageMeter> {
+        let target = context.global(scope);
+        self.0
+            .borrow()
+            .iter()
+            .find(|entry| {
+                entry
+                    .context
+                    .get(scope)
+                    .global(scope)
+                    .strict_equals(target.into())
+            })
+            .map(|entry| entry.meter.clone())
+    }
+}
+
+/// Meter the context `id`, returning its meter, or the one it already had.
+pub(crate) fn meter_context(
+    isolate: &mut v8::Isolate,
+    id: ContextId,
+    context: &v8::Global<v8::Context>,
+) -> UsageMeter {
+    let mut hs = v8::HandleScope::new(isolate);
+    let scope = hs.enter();
+    let registry = match isolate_slot::<MeterRegistry>(scope) {
+        Some(registry) => registry,
+        None => {
+            set_isolate_slot(scope, MeterRegistry::default());
+            isolate_slot::<MeterRegistry>(scope).unwrap()
+        }
+    };
+    if let Some(meter) = meter_of(scope, id) {
+        return meter;
+    }
+    let context = context.get(scope).unwrap();
+    let meter = UsageMeter::default();
+    registry.0.borrow_mut().push(Entry {
+        id,
+        context: JsRef::new(scope, context),
+        meter: meter.clone(),
+    });
+    meter
+}
+
+/// The meter of the context `id`, if it is metered.
+pub(crate) fn meter_of(scope: &mut impl v8::InIsolate, id: ContextId) -> Option<UsageMeter> {
+    let registry = isolate_slot::<MeterRegistry>(scope)?;
+    let meter = registry
+        .0
+        .borrow()
+        .iter()
+        .find(|x| x.id == id)
+        .map(|x| x.meter.clone());
+    meter
+}
+
+/// Stop metering the context `id`, i.e. as it is disposed.
+pub(crate) fn clear_meter(isolate: &mut v8::Isolate, id: ContextId) -> bool {
+    let registry = match isolate_slot::<MeterRegistry>(isolate) {
+        Some(registry) => registry,
+        None => return false,
+    };
+    let index = registry.0.borrow().iter().position(|x| x.id == id);
+    match index {
+        Some(index) => {
+            let entry = registry.0.borrow_mut().remove(index);
+            entry.context.release(isolate);
+            true
+        }
+        None => false,
+    }
+}
+
+/// A span of time attributed to a metered context, closed when dropped.
+#[doc(hidden)]
+pub struct MeteredSpan {
+    meter: UsageMeter,
+    started: Option<(Instant, Duration)>,
+}
+
+impl MeteredSpan {
+    fn enter<'sc>(
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+    ) -> Option<MeteredSpan> {
+        let meter = isolate_slot::<MeterRegistry>(scope)?.find(scope, context)?;
+        let depth = meter.0.depth.get();
+        meter.0.depth.set(depth + 1);
+        let started = if depth == 0 {
+            Some((Instant::now(), thread_cpu_time()))
+        } else {
+            None
+        };
+        Some(MeteredSpan { meter, started })
+    }
+}
+
+impl Drop for MeteredSpan {
+    fn drop(&mut self) {
+        self.meter.0.depth.set(self.meter.0.depth.get() - 1);
+        if let Some((wall, cpu)) = self.started {
+            let cpu = thread_cpu_time().checked_sub(cpu).unwrap_or_default();
+            self.meter.update(|usage| {
+                usage.wall_time += wall.elapsed();
+                usage.cpu_time += cpu;
+            });
+        }
+    }
+}
+
+/// Open the span of a `v8_ffi` call from `context`. Called by the
+/// generated `v8_ffi` trampolines.
+#[doc(hidden)]
+pub fn enter_ffi<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+) -> Option<MeteredSpan> {
+    let span = MeteredSpan::enter(scope, context)?;
+    span.meter.update(|usage| usage.ffi_calls += 1);
+    Some(span)
+}
+
+/// Run `f`, which runs script in `context`, attributing the time it takes
+/// to the context if it is metered.
+pub fn metered<'sc, S: v8::ToLocal<'sc>, R>(
+    scope: &mut S,
+    context: v8::Local<v8::Context>,
+    f: impl FnOnce(&mut S) -> R,
+) -> R {
+    let span = MeteredSpan::enter(scope, context);
+    if let Some(span) = &span {
+        if span.started.is_some() {
+            span.meter.update(|usage| usage.script_runs += 1);
+        }
+    }
+    f(scope)
+}
+
+/// Attribute `delta` bytes of external memory to `context`, if it is
+/// metered: positive as the host allocates memory on the context's behalf,
+/// i.e. buffers backing its objects, and negative as it frees it.
+pub fn adjust_external_memory<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    delta: i64,
+) {
+    let meter = match isolate_slot::<MeterRegistry>(scope).and_then(|x| x.find(scope, context)) {
+        Some(meter) => meter,
+        None => return,
+    };
+    meter.update(|usage| {
+        usage.external_memory = if delta < 0 {
+            usage.external_memory.saturating_sub(delta.unsigned_abs())
+        } else {
+            usage.external_memory.saturating_add(delta as u64)
+        };
+        usage.peak_external_memory = usage.peak_external_memory.max(usage.external_memory);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{make_str, run_script};
+    use crate::FFICompat;
+    use rusty_v8_helper_derive::v8_ffi;
+
+    #[v8_ffi]
+    fn test_ffi_return() -> String {
+        "test".to_string()
+    }
+
+    #[test]
+    fn context_accounting() {
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        let (metered_id, metered) = runtime.create_context();
+        let (other_id, other) = runtime.create_context();
+        let meter = runtime.meter_context(metered_id).unwrap();
+        assert_eq!(runtime.usage(other_id), None);
+        {
+            let isolate = runtime.isolate();
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            for context in [&metered, &other].iter() {
+                let context = context.get(scope).unwrap();
+                let global = context.global(scope);
+                let function = load_v8_ffi!(test_ffi_return, scope, context);
+                global.set(context, make_str(scope, "test_ffi_return"), function);
+            }
+            let busy = "let x = 0; for (let i = 0; i < 1e6; i++) x += i; \
+                [test_ffi_return(), test_ffi_return()].join()";
+            let context = metered.get(scope).unwrap();
+            let result = crate::accounting::metered(scope, context, |scope| {
+                run_script(scope, context, busy).unwrap()
+            });
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok("test,test".to_string())
+            );
+            crate::accounting::adjust_external_memory(scope, context, 4096);
+            crate::accounting::adjust_external_memory(scope, context, -1024);
+            let context = other.get(scope).unwrap();
+            run_script(scope, context, busy).unwrap();
+            crate::accounting::adjust_external_memory(scope, context, 4096);
+        }
+        let usage = runtime.usage(metered_id).unwrap();
+        assert_eq!(usage, meter.usage());
+        assert_eq!(usage.ffi_calls, 2);
+        assert_eq!(usage.script_runs, 1);
+        assert!(usage.wall_time > std::time::Duration::from_secs(0));
+        assert!(usage.cpu_time <= usage.wall_time);
+        assert_eq!(usage.external_memory, 3072);
+        assert_eq!(usage.peak_external_memory, 4096);
+        meter.reset();
+        let usage = meter.usage();
+        assert_eq!((usage.ffi_calls, usage.script_runs), (0, 0));
+        assert_eq!(usage.wall_time, std::time::Duration::from_secs(0));
+        assert_eq!(usage.external_memory, 3072);
+        let (mut metered, mut other) = (metered, other);
+        metered.reset(runtime.isolate());
+        other.reset(runtime.isolate());
+        assert!(runtime.dispose_context(metered_id));
+        assert_eq!(runtime.usage(metered_id), None);
+    }
+}
@@ -0,0 +1,236 @@
+//! `Js<T, A>`, converting a type through an adapter rather than its own
+//! `FFICompat` impl, so that types of other crates can be given a JS
+//! representation despite the orphan rule.
+//!
+//! The ways of converting a type, from the most to the least direct:
+//! - implement `FFICompat` for it, for types of your own crate,
+//! - `#[derive(FromJsObject)]` for structs read from plain objects, and
+//!   `#[derive(FfiTransparent)]` for newtypes converted as their field,
+//! - `Js<T, A>` with a `JsAdapter<T>` of your own crate, for types of
+//!   other crates, usable anywhere an `FFICompat` type is, i.e. in a `Vec`,
+//! - `#[ffi(with = "..")]` and `return_with`, converting a single argument
+//!   or return value with the functions of a module.
+//!
+//! ```ignore
+//! struct Rfc3339;
+//!
+//! impl JsAdapter<DateTime<Utc>> for Rfc3339 { .. }
+//!
+//! #[derive(FfiTransparent)]
+//! struct Timestamp(Js<DateTime<Utc>, Rfc3339>);
+//!
+//! #[v8_ffi]
+//! fn schedule(at: Timestamp, ids: Vec<Js<Uuid, AsString>>) { .. }
+//! ```
+
+use crate::util::make_str;
+use crate::{FFICompat, FFIError};
+use rusty_v8 as v8;
+use std::fmt::{self, Debug, Display};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
+
+/// `JsAdapter` converts `T` to and from JS for `Js<T, Self>`. Implemented
+/// by a type of your own crate, it may convert types of any crate.
+///
+/// Both directions default to failing with a `TypeError`, so an adapter
+/// used only for arguments or only for return values needs to implement
+/// only one.
+pub trait JsAdapter<T> {
+    fn from_js<'sc, 'c>(
+        _value: v8::Local<'sc, v8::Value>,
+        _scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<T, FFIError> {
+        Err(FFIError::TypeError(format!(
+            "{} cannot be converted from JS",
+            std::any::type_name::<T>()
+        )))
+    }
+
+    fn to_js<'sc, 'c>(
+        _value: T,
+        _scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        Err(FFIError::TypeError(format!(
+            "{} cannot be converted to JS",
+            std::any::type_name::<T>()
+        )))
+    }
+}
+
+/// A `T` converted to and from JS by the adapter `A`.
+pub struct Js<T, A>(pub T, PhantomData<fn() -> A>);
+
+impl<T, A> Js<T, A> {
+    pub fn new(value: T) -> Js<T, A> {
+        Js(value, PhantomData)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T, A> From<T> for Js<T, A> {
+    fn from(value: T) -> Js<T, A> {
+        Js::new(value)
+    }
+}
+
+impl<T, A> Deref for Js<T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T, A> DerefMut for Js<T, A> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Debug, A> Debug for Js<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: Clone, A> Clone for Js<T, A> {
+    fn clone(&self) -> Js<T, A> {
+        Js::new(self.0.clone())
+    }
+}
+
+impl<T: PartialEq, A> PartialEq for Js<T, A> {
+    fn eq(&self, other: &Js<T, A>) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<'sc, 'c, T, A: JsAdapter<T>> FFICompat<'sc, 'c> for Js<T, A> {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        A::from_js(value, scope, context).map(Js::new)
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        A::to_js(self.0, scope, context)
+    }
+}
+
+/// `JsAdapter` converting types as strings, through their `Display` and
+/// `FromStr` impls, i.e. for ids, addresses and decimals.
+pub struct AsString;
+
+impl<T: Display + FromStr> JsAdapter<T> for AsString
+where
+    T::Err: Display,
+{
+    fn from_js<'sc, 'c>(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<T, FFIError> {
+        if !value.is_string() {
+            return Err(FFIError::TypeError(format!(
+                "expected a string for {}",
+                std::any::type_name::<T>()
+            )));
+        }
+        let text = value.to_rust_string_lossy(scope);
+        text.parse()
+            .map_err(|e: T::Err| FFIError::TypeError(format!("invalid {:?}: {}", text, e)))
+    }
+
+    fn to_js<'sc, 'c>(
+        value: T,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        Ok(make_str(scope, &value.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+    use rusty_v8_helper_derive::v8_ffi;
+
+    /// A host, converted as a lowercase string, for `Js`.
+    struct TestLowercase;
+
+    impl crate::JsAdapter<String> for TestLowercase {
+        fn from_js<'sc, 'c>(
+            value: v8::Local<'sc, v8::Value>,
+            scope: &mut impl v8::ToLocal<'sc>,
+            context: v8::Local<'c, v8::Context>,
+        ) -> Result<String, crate::FFIError> {
+            Ok(String::from_value(value, scope, context)?.to_lowercase())
+        }
+    }
+
+    #[derive(crate::FfiTransparent)]
+    struct TestHost(crate::Js<String, TestLowercase>);
+
+    #[derive(crate::FfiTransparent)]
+    struct TestAddr {
+        addr: crate::Js<std::net::IpAddr, crate::AsString>,
+    }
+
+    #[v8_ffi]
+    fn test_ffi_adapter(
+        host: TestHost,
+        addrs: Vec<crate::Js<std::net::IpAddr, crate::AsString>>,
+    ) -> (String, Vec<TestAddr>) {
+        let addrs = addrs
+            .into_iter()
+            .filter(|x| x.is_loopback())
+            .map(|addr| TestAddr { addr })
+            .collect();
+        ((host.0).0, addrs)
+    }
+
+    #[test]
+    fn foreign_type_adapter() {
+        with_context!([test_ffi_adapter], |scope, context| {
+            let result = run_script(
+                scope,
+                context,
+                r#"
+                const attempt = (f) => {
+                    try {
+                        return JSON.stringify(f());
+                    } catch (e) {
+                        return `${e.name}: ${e.message}`;
+                    }
+                };
+                [
+                    () => test_ffi_adapter('Example.COM', ['127.0.0.1', '10.0.0.1', '::1']),
+                    () => test_ffi_adapter('example.com', ['localhost']),
+                ].map(attempt).join('\n')
+                "#,
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context).unwrap(),
+                r#"["example.com",["127.0.0.1","::1"]]
+    TypeError: value[0]: invalid "localhost": invalid IP address syntax"#
+            );
+        });
+    }
+}
@@ -0,0 +1,180 @@
+//! Checks that values tied to an isolate are used on the isolate's thread,
+//! and `SendWrapper`, which carries such a value through other threads
+//! without touching it there.
+
+use std::fmt;
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::thread::{self, ThreadId};
+
+/// The thread a value tied to an isolate was created on. In debug builds,
+/// `check` panics with an explanation when the value is used on another
+/// thread, which would otherwise be undefined behavior in V8.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Affinity {
+    #[cfg(debug_assertions)]
+    thread: ThreadId,
+}
+
+impl Affinity {
+    pub(crate) fn current() -> Affinity {
+        Affinity {
+            #[cfg(debug_assertions)]
+            thread: thread::current().id(),
+        }
+    }
+
+    /// Panic if this is not the thread `what` was created on, in debug
+    /// builds only.
+    pub(crate) fn check(&self, what: &str) {
+        #[cfg(debug_assertions)]
+        {
+            if self.thread != thread::current().id() {
+                wrong_thread(what, self.thread);
+            }
+        }
+        #[cfg(not(debug_assertions))]
+        let _ = what;
+    }
+}
+
+fn wrong_thread(what: &str, origin: ThreadId) -> ! {
+    panic!(
+        "{} used on {:?}, but it belongs to an isolate on {:?}. V8 handles and wrapped objects \
+         cannot leave the thread of their isolate: send plain Rust values to other threads, \
+         i.e. through `spawn_blocking_ffi`, or carry the value in a `SendWrapper` and only use \
+         it once it is back",
+        what,
+        thread::current().id(),
+        origin
+    )
+}
+
+/// `SendWrapper` lets a value that must stay on one thread, such as a
+/// `JsRef`, travel through other threads, i.e. inside a future run by a
+/// multithreaded executor, as long as it is only used on the thread it was
+/// wrapped on. Using or dropping it on any other thread panics, in release
+/// builds too, instead of corrupting the isolate.
+///
+/// ```ignore
+/// let callback = SendWrapper::new(callback);
+/// let value = tokio::task::spawn_blocking(move || {
+///     let value = compute();
+///     (callback, value)
+/// })
+/// .await?;
+/// // back on the isolate's thread
+/// let (callback, value) = value;
+/// callback.call(scope, context, recv, &[value])?;
+/// ```
+pub struct SendWrapper<T> {
+    value: ManuallyDrop<T>,
+    thread: ThreadId,
+}
+
+// the value is only ever touched on the thread it was wrapped on
+unsafe impl<T> Send for SendWrapper<T> {}
+unsafe impl<T> Sync for SendWrapper<T> {}
+
+impl<T> SendWrapper<T> {
+    pub fn new(value: T) -> SendWrapper<T> {
+        SendWrapper {
+            value: ManuallyDrop::new(value),
+            thread: thread::current().id(),
+        }
+    }
+
+    /// Whether this is the thread the value was wrapped on, where it can
+    /// be used.
+    pub fn is_valid(&self) -> bool {
+        self.thread == thread::current().id()
+    }
+
+    /// Unwrap the value. Panics on another thread than it was wrapped on.
+    pub fn take(self) -> T {
+        self.check();
+        let mut this = ManuallyDrop::new(self);
+        unsafe { ManuallyDrop::take(&mut this.value) }
+    }
+
+    fn check(&self) {
+        if !self.is_valid() {
+            wrong_thread("SendWrapper", self.thread);
+        }
+    }
+}
+
+impl<T> Deref for SendWrapper<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.check();
+        &self.value
+    }
+}
+
+impl<T> DerefMut for SendWrapper<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.check();
+        &mut self.value
+    }
+}
+
+impl<T> Drop for SendWrapper<T> {
+    fn drop(&mut self) {
+        if self.is_valid() {
+            unsafe { ManuallyDrop::drop(&mut self.value) };
+        } else if !thread::panicking() {
+            // leaked rather than dropped on the wrong thread
+            wrong_thread("SendWrapper", self.thread);
+        }
+    }
+}
+
+impl<T> fmt::Debug for SendWrapper<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SendWrapper")
+            .field("thread", &self.thread)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn send_wrapper() {
+        let wrapped = SendWrapper::new(Rc::new(5));
+        let wrapped = thread::spawn(move || {
+            assert!(!wrapped.is_valid());
+            wrapped
+        })
+        .join()
+        .unwrap();
+        assert_eq!(**wrapped, 5);
+        assert_eq!(*wrapped.take(), 5);
+
+        let wrapped = SendWrapper::new(Rc::new(5));
+        let used = thread::spawn(move || {
+            let _ = **wrapped;
+        })
+        .join();
+        assert!(used.is_err());
+
+        let dropped = thread::spawn(move || {
+            drop(SendWrapper::new(1));
+        })
+        .join();
+        assert!(dropped.is_ok());
+    }
+
+    #[test]
+    fn affinity() {
+        let affinity = Affinity::current();
+        affinity.check("JsRef");
+        let checked = thread::spawn(move || affinity.check("JsRef")).join();
+        assert_eq!(checked.is_err(), cfg!(debug_assertions));
+    }
+}
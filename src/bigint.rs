@@ -0,0 +1,162 @@
+//! `FFICompat` conversions between JS `BigInt`s and `i128`, `u128` and
+//! `num_bigint::BigInt`, transferring the 64-bit words directly.
+
+use crate::util::type_of;
+use crate::FFICompat;
+use crate::FFIError;
+use num_bigint::{BigInt, BigUint, Sign};
+use rusty_v8 as v8;
+use std::convert::TryInto;
+
+/// Read a JS `BigInt`, or an integral `Number`, as a sign and little endian
+/// 64-bit magnitude words.
+fn value_to_words<'sc>(
+    value: v8::Local<'sc, v8::Value>,
+    scope: &mut impl v8::ToLocal<'sc>,
+) -> Result<(bool, Vec<u64>), FFIError> {
+    if let Ok(bigint) = TryInto::<v8::Local<v8::BigInt>>::try_into(value) {
+        let mut words = vec![0u64; bigint.word_count()];
+        let (negative, words) = bigint.to_words_array(&mut words);
+        return Ok((negative, words.to_vec()));
+    }
+    if let Ok(number) = TryInto::<v8::Local<v8::Number>>::try_into(value) {
+        let number = number.number_value(scope).unwrap_or(f64::NAN);
+        if number.fract() != 0.0 || number.abs() > 9_007_199_254_740_991.0 {
+            return Err(FFIError::RangeError(format!(
+                "{} is not a safe integer, pass a BigInt instead",
+                number
+            )));
+        }
+        return Ok((number < 0.0, vec![number.abs() as u64]));
+    }
+    Err(FFIError::TypeError(format!(
+        "invalid type for argument in ffi call, expected bigint, received {}",
+        type_of(value)
+    )))
+}
+
+fn words_to_value<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    negative: bool,
+    words: &[u64],
+) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+    v8::BigInt::new_from_words(scope, negative, words)
+        .map(|x| x.into())
+        .ok_or_else(|| FFIError::RangeError("bigint too large".to_string()))
+}
+
+fn words_to_u128(words: &[u64]) -> Option<u128> {
+    let significant = words.iter().rposition(|x| *x != 0).map_or(0, |x| x + 1);
+    if significant > 2 {
+        return None;
+    }
+    let low = words.get(0).copied().unwrap_or(0) as u128;
+    let high = words.get(1).copied().unwrap_or(0) as u128;
+    Some(low | (high << 64))
+}
+
+fn u128_to_words(value: u128) -> [u64; 2] {
+    [value as u64, (value >> 64) as u64]
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for u128 {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        let (negative, words) = value_to_words(value, scope)?;
+        match words_to_u128(&words) {
+            Some(value) if !negative || value == 0 => Ok(value),
+            _ => Err(FFIError::RangeError(
+                "bigint out of range for u128".to_string(),
+            )),
+        }
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        words_to_value(scope, false, &u128_to_words(self))
+    }
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for i128 {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        let (negative, words) = value_to_words(value, scope)?;
+        let magnitude = words_to_u128(&words);
+        match magnitude {
+            Some(magnitude) if !negative && magnitude <= i128::MAX as u128 => Ok(magnitude as i128),
+            Some(magnitude) if negative && magnitude <= 1 << 127 => {
+                Ok((magnitude as i128).wrapping_neg())
+            }
+            _ => Err(FFIError::RangeError(
+                "bigint out of range for i128".to_string(),
+            )),
+        }
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        let magnitude = if self < 0 {
+            (self as u128).wrapping_neg()
+        } else {
+            self as u128
+        };
+        words_to_value(scope, self < 0, &u128_to_words(magnitude))
+    }
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for BigInt {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        let (negative, words) = value_to_words(value, scope)?;
+        let digits = words
+            .iter()
+            .flat_map(|word| vec![*word as u32, (*word >> 32) as u32])
+            .collect();
+        let sign = if negative { Sign::Minus } else { Sign::Plus };
+        Ok(BigInt::from_biguint(sign, BigUint::new(digits)))
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        let (sign, words) = self.to_u64_digits();
+        words_to_value(scope, sign == Sign::Minus, &words)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn u128_words() {
+        let value = 0x1234_5678_9abc_def0_0fed_cba9_8765_4321u128;
+        assert_eq!(words_to_u128(&u128_to_words(value)), Some(value));
+        assert_eq!(words_to_u128(&[1, 2, 0]), Some(1 | (2 << 64)));
+        assert_eq!(words_to_u128(&[1, 2, 3]), None);
+        assert_eq!(words_to_u128(&[]), Some(0));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::event_loop;
+use crate::promise;
+use crate::{FFICompat, FFIError};
+use rusty_v8 as v8;
+use std::any::Any;
+use std::fmt::Debug;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Number of worker threads in the process-wide pool for blocking work.
+const BLOCKING_THREADS: usize = 4;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct ThreadPool {
+    sender: Sender<Job>,
+}
+
+impl ThreadPool {
+    fn new(size: usize) -> ThreadPool {
+        let (sender, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..size {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("rusty_v8_helper blocking {}", i))
+                .spawn(move || loop {
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                })
+                .expect("failed to spawn blocking thread");
+        }
+        ThreadPool { sender }
+    }
+
+    fn execute(&self, job: impl FnOnce() + Send + 'static) {
+        self.sender.send(Box::new(job)).unwrap();
+    }
+}
+
+/// The pool shared by every isolate, started on first use.
+static POOL: Mutex<Option<ThreadPool>> = Mutex::new(None);
+
+fn execute(job: impl FnOnce() + Send + 'static) {
+    let mut pool = POOL.lock().unwrap();
+    pool.get_or_insert_with(|| ThreadPool::new(BLOCKING_THREADS))
+        .execute(job);
+}
+
+/// The message of a caught panic, as passed to `panic!`.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&'static str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+/// Run a CPU-heavy closure on a background thread pool, returning a
+/// `Promise` that is settled with the closure's `FFICompat` result.
+///
+/// The promise is settled on the isolate thread while the event loop is
+/// being driven through `event_loop::run_pending` or
+/// `event_loop::run_until_idle`. If `f` panics, the promise is rejected with
+/// an `Error` carrying the panic message and the worker thread lives on.
+pub fn spawn_blocking_ffi<'sc, R, E, F>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    f: F,
+) -> v8::Local<'sc, v8::Promise>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: for<'a, 'b> FFICompat<'a, 'b, E = E> + Send + 'static,
+    E: Debug + Any,
+{
+    let context = scope.get_current_context().unwrap();
+    let (id, promise) = promise::new_pending(scope, context);
+    event_loop::begin_outstanding(scope);
+    let handle = event_loop::handle(scope);
+    execute(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(f)).map_err(|panic| {
+            FFIError::Error(format!(
+                "blocking task panicked: {}",
+                panic_message(&*panic)
+            ))
+        });
+        handle.post(move |isolate| {
+            event_loop::end_outstanding(isolate);
+            match result {
+                Ok(result) => promise::settle(isolate, id, result),
+                Err(error) => promise::settle(isolate, id, Err::<(), _>(error)),
+            }
+        });
+    });
+    promise
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+    use rusty_v8_helper_derive::v8_ffi;
+
+    #[v8_ffi(scoped)]
+    fn test_ffi_blocking_panic<'sc, 'c>(
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+        fail: bool,
+    ) -> v8::Local<'sc, v8::Value> {
+        spawn_blocking_ffi(scope, move || {
+            if fail {
+                panic!("worker failed");
+            }
+            1u32
+        })
+        .into()
+    }
+
+    #[test]
+    fn blocking_panic() {
+        with_context!([test_ffi_blocking_panic], |scope, context| {
+            run_script(
+                scope,
+                context,
+                "let settled = []; for (const fail of [true, false, true]) test_ffi_blocking_panic(fail).then(x => settled.push(x), e => settled.push(e.message));",
+            )
+            .unwrap();
+            event_loop::run_until_idle(scope);
+            let settled = run_script(scope, context, "settled.sort().join()").unwrap();
+            assert_eq!(
+                String::from_value(settled, scope, context),
+                Ok(
+                    "1,blocking task panicked: worker failed,blocking task panicked: worker failed"
+                        .to_string()
+                )
+            );
+        });
+    }
+}
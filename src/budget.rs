@@ -0,0 +1,215 @@
+//! Cumulative execution budgets, enforced by terminating execution from a
+//! watchdog thread.
+
+use crate::FFIError;
+use rusty_v8 as v8;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct WatchState {
+    deadline: Option<Instant>,
+    fired: bool,
+    stopped: bool,
+}
+
+struct Watchdog {
+    state: Mutex<WatchState>,
+    wakeup: Condvar,
+}
+
+impl Watchdog {
+    fn spawn(handle: v8::IsolateHandle) -> Arc<Watchdog> {
+        let watchdog = Arc::new(Watchdog {
+            state: Mutex::new(WatchState::default()),
+            wakeup: Condvar::new(),
+        });
+        let thread_watchdog = watchdog.clone();
+        thread::Builder::new()
+            .name("rusty_v8_helper budget".to_string())
+            .spawn(move || thread_watchdog.watch(handle))
+            .expect("failed to spawn budget thread");
+        watchdog
+    }
+
+    fn watch(&self, handle: v8::IsolateHandle) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.stopped {
+                return;
+            }
+            state = match state.deadline {
+                None => self.wakeup.wait(state).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        handle.terminate_execution();
+                        state.deadline = None;
+                        state.fired = true;
+                        continue;
+                    }
+                    self.wakeup.wait_timeout(state, deadline - now).unwrap().0
+                }
+            };
+        }
+    }
+
+    fn arm(&self, deadline: Instant) {
+        let mut state = self.state.lock().unwrap();
+        state.deadline = Some(deadline);
+        state.fired = false;
+        self.wakeup.notify_one();
+    }
+
+    /// Returns whether execution was terminated since `arm`.
+    fn disarm(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.deadline = None;
+        std::mem::replace(&mut state.fired, false)
+    }
+
+    fn stop(&self) {
+        self.state.lock().unwrap().stopped = true;
+        self.wakeup.notify_one();
+    }
+}
+
+/// Returned when a call ran out of its `ExecutionBudget`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetExceeded {
+    /// Label of the call that exceeded the budget.
+    pub label: String,
+    pub limit: Duration,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} exceeded the execution budget of {:?}",
+            self.label, self.limit
+        )
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+impl From<BudgetExceeded> for FFIError {
+    fn from(error: BudgetExceeded) -> FFIError {
+        FFIError::RangeError(error.to_string())
+    }
+}
+
+/// `ExecutionBudget` limits the total time spent in a series of calls into
+/// JS, i.e. every callback run in one plugin's context, rather than each
+/// call on its own.
+///
+/// A call that runs past the remaining budget is terminated, and it and
+/// every later call fail with `BudgetExceeded` until the budget is `reset`.
+/// Budgets are not shared between isolates; create one per context to
+/// budget contexts separately.
+pub struct ExecutionBudget {
+    limit: Duration,
+    used: Cell<Duration>,
+    exceeded: RefCell<Option<BudgetExceeded>>,
+    watchdog: Arc<Watchdog>,
+}
+
+impl ExecutionBudget {
+    pub fn new(scope: &mut impl v8::InIsolate, limit: Duration) -> ExecutionBudget {
+        let handle = scope.isolate().thread_safe_handle();
+        ExecutionBudget {
+            limit,
+            used: Cell::new(Duration::from_secs(0)),
+            exceeded: RefCell::new(None),
+            watchdog: Watchdog::spawn(handle),
+        }
+    }
+
+    /// Run `f`, which calls into JS, against the budget. `label` names the
+    /// call, i.e. the script being run, for `BudgetExceeded`.
+    ///
+    /// If `f` is terminated, the termination is cancelled before returning so
+    /// the isolate can be used again.
+    pub fn run<S: v8::InIsolate, R>(
+        &self,
+        scope: &mut S,
+        label: &str,
+        f: impl FnOnce(&mut S) -> R,
+    ) -> Result<R, BudgetExceeded> {
+        if let Some(exceeded) = self.exceeded() {
+            return Err(exceeded);
+        }
+        let start = Instant::now();
+        self.watchdog.arm(start + self.remaining());
+        let result = f(scope);
+        let terminated = self.watchdog.disarm();
+        self.used.set(self.used.get() + start.elapsed());
+        if terminated {
+            scope.isolate().cancel_terminate_execution();
+            let exceeded = BudgetExceeded {
+                label: label.to_string(),
+                limit: self.limit,
+            };
+            self.exceeded.replace(Some(exceeded.clone()));
+            return Err(exceeded);
+        }
+        Ok(result)
+    }
+
+    /// Time spent in calls so far.
+    pub fn used(&self) -> Duration {
+        self.used.get()
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.limit.checked_sub(self.used.get()).unwrap_or_default()
+    }
+
+    /// The call that exceeded the budget, if any.
+    pub fn exceeded(&self) -> Option<BudgetExceeded> {
+        self.exceeded.borrow().clone()
+    }
+
+    /// Start over with the full budget.
+    pub fn reset(&self) {
+        self.used.set(Duration::from_secs(0));
+        self.exceeded.replace(None);
+    }
+}
+
+impl Drop for ExecutionBudget {
+    fn drop(&mut self) {
+        self.watchdog.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+
+    #[test]
+    fn execution_budget() {
+        with_context!(|scope, context| {
+            let budget = crate::ExecutionBudget::new(scope, std::time::Duration::from_millis(200));
+            for _ in 0..3 {
+                let result = budget.run(scope, "small.js", |scope| {
+                    run_script(scope, context, "1 + 1")
+                });
+                assert!(result.unwrap().is_some());
+            }
+            let result = budget.run(scope, "spin.js", |scope| {
+                run_script(scope, context, "while (true) {}")
+            });
+            assert_eq!(result.err().map(|x| x.label), Some("spin.js".to_string()));
+            assert!(budget.run(scope, "small.js", |_| ()).is_err());
+            budget.reset();
+            let result = budget.run(scope, "small.js", |scope| run_script(scope, context, "2"));
+            assert!(result.unwrap().is_some());
+        });
+    }
+}
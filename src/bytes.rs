@@ -0,0 +1,74 @@
+use crate::FFICompat;
+use rusty_v8 as v8;
+use std::convert::TryInto;
+use std::ops::{Deref, DerefMut};
+
+/// `Bytes` is a byte buffer that converts to and from a JS `Uint8Array`.
+///
+/// Any `ArrayBufferView` or `ArrayBuffer` is accepted from JS, and its
+/// contents are copied out.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Bytes(pub Vec<u8>);
+
+impl Deref for Bytes {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.0
+    }
+}
+
+impl DerefMut for Bytes {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.0
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(item: Vec<u8>) -> Bytes {
+        Bytes(item)
+    }
+}
+
+impl From<Bytes> for Vec<u8> {
+    fn from(item: Bytes) -> Vec<u8> {
+        item.0
+    }
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for Bytes {
+    type E = String;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        _scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, String> {
+        let view: Option<v8::Local<v8::ArrayBufferView>> = value.try_into().ok();
+        let view = match view {
+            Some(view) => view,
+            None => {
+                let buffer: v8::Local<v8::ArrayBuffer> = value.try_into().map_err(|_| {
+                    "invalid type for argument in ffi call, expected Uint8Array".to_string()
+                })?;
+                let length = buffer.byte_length();
+                v8::Uint8Array::new(buffer, 0, length).unwrap().into()
+            }
+        };
+        let mut data = vec![0; view.byte_length()];
+        view.copy_contents(&mut data[..]);
+        Ok(Bytes(data))
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, String> {
+        let length = self.0.len();
+        let backing_store =
+            v8::ArrayBuffer::new_backing_store_from_boxed_slice(self.0.into_boxed_slice());
+        let buffer = v8::ArrayBuffer::with_backing_store(scope, &mut backing_store.make_shared());
+        Ok(v8::Uint8Array::new(buffer, 0, length).unwrap().into())
+    }
+}
@@ -0,0 +1,231 @@
+//! `CallbackRegistry`, JS functions kept by id for long-lived
+//! subscriptions, i.e. event listeners registered from JS.
+
+use crate::accounting;
+use crate::util::{call_function, isolate_slot, set_isolate_slot};
+use crate::{FFIError, JsRef};
+use rusty_v8 as v8;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// Identifies a callback in a `CallbackRegistry`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CallbackId(u64);
+
+struct Entry {
+    id: CallbackId,
+    context: JsRef<v8::Context>,
+    callback: JsRef<v8::Function>,
+}
+
+/// `CallbackRegistry` holds the JS functions registered with an isolate,
+/// each with the context it was registered from. Callbacks of a context
+/// disposed through a `Runtime` are removed along with it; otherwise use
+/// `remove_context`.
+///
+/// ```ignore
+/// #[v8_ffi(scoped)]
+/// fn subscribe<'sc, 'c>(
+///     scope: &mut impl v8::ToLocal<'sc>,
+///     context: v8::Local<'c, v8::Context>,
+///     listener: JsRef<v8::Function>,
+/// ) -> f64 {
+///     let id = CallbackRegistry::of(scope).register(scope, context, listener);
+///     LISTENERS.with(|listeners| listeners.borrow_mut().push(id));
+///     ...
+/// }
+/// ```
+#[derive(Default)]
+pub struct CallbackRegistry {
+    entries: RefCell<Vec<Entry>>,
+    next_id: Cell<u64>,
+}
+
+impl CallbackRegistry {
+    /// The registry of the isolate of `scope`, created on first use.
+    pub fn of(scope: &mut impl v8::InIsolate) -> Rc<CallbackRegistry> {
+        if let Some(registry) = isolate_slot::<CallbackRegistry>(scope) {
+            return registry;
+        }
+        set_isolate_slot(scope, CallbackRegistry::default());
+        isolate_slot::<CallbackRegistry>(scope).unwrap()
+    }
+
+    /// Register `callback`, belonging to `context`.
+    pub fn register(
+        &self,
+        scope: &mut impl v8::InIsolate,
+        context: v8::Local<v8::Context>,
+        callback: JsRef<v8::Function>,
+    ) -> CallbackId {
+        let id = CallbackId(self.next_id.get());
+        self.next_id.set(id.0 + 1);
+        self.entries.borrow_mut().push(Entry {
+            id,
+            context: JsRef::new(scope, context),
+            callback,
+        });
+        id
+    }
+
+    /// Call the callback `id` with `args`, catching anything it throws.
+    /// The callback may register or remove callbacks itself.
+    pub fn invoke<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+        id: CallbackId,
+        args: &[v8::Local<v8::Value>],
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        let function = {
+            let entries = self.entries.borrow();
+            let entry = entries
+                .iter()
+                .find(|x| x.id == id)
+                .ok_or_else(|| FFIError::Error(format!("no callback registered as {:?}", id)))?;
+            entry.callback.get(scope)
+        };
+        let recv = v8::undefined(scope).into();
+        accounting::metered(scope, context, |scope| {
+            call_function(scope, context, function, recv, args)
+        })
+    }
+
+    /// The context the callback `id` was registered from.
+    pub fn context_of<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        id: CallbackId,
+    ) -> Option<v8::Local<'sc, v8::Context>> {
+        let entries = self.entries.borrow();
+        let entry = entries.iter().find(|x| x.id == id)?;
+        Some(entry.context.get(scope))
+    }
+
+    /// Remove and release the callback `id`. Returns `false` if it was not
+    /// registered.
+    pub fn remove(&self, scope: &mut impl v8::InIsolate, id: CallbackId) -> bool {
+        let entry = {
+            let mut entries = self.entries.borrow_mut();
+            match entries.iter().position(|x| x.id == id) {
+                Some(index) => entries.remove(index),
+                None => return false,
+            }
+        };
+        entry.context.release(scope);
+        entry.callback.release(scope);
+        true
+    }
+
+    /// Remove and release every callback registered from `context`,
+    /// returning how many there were.
+    pub fn remove_context<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+    ) -> usize {
+        self.remove_context_since(scope, context, CallbackId(0))
+    }
+
+    /// The id the next callback will be registered as.
+    pub(crate) fn next_id(&self) -> CallbackId {
+        CallbackId(self.next_id.get())
+    }
+
+    /// Remove and release the callbacks registered from `context` as
+    /// `since` or later, returning how many there were.
+    pub(crate) fn remove_context_since<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+        since: CallbackId,
+    ) -> usize {
+        let target = context.global(scope);
+        let entries = self.entries.replace(vec![]);
+        let mut removed = vec![];
+        for entry in entries {
+            if entry.id.0 >= since.0
+                && entry
+                    .context
+                    .get(scope)
+                    .global(scope)
+                    .strict_equals(target.into())
+            {
+                removed.push(entry);
+            } else {
+                self.entries.borrow_mut().push(entry);
+            }
+        }
+        let count = removed.len();
+        for entry in removed {
+            entry.context.release(scope);
+            entry.callback.release(scope);
+        }
+        count
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+}
+
+/// Remove the callbacks registered from `context` as it is disposed.
+pub(crate) fn release_context(isolate: &mut v8::Isolate, context: &v8::Global<v8::Context>) {
+    let mut hs = v8::HandleScope::new(isolate);
+    let scope = hs.enter();
+    let registry = isolate_slot::<CallbackRegistry>(scope);
+    if let (Some(registry), Some(context)) = (registry, context.get(scope)) {
+        registry.remove_context(scope, context);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{make_num, run_script};
+    use crate::FFICompat;
+
+    #[test]
+    fn callback_registry() {
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        let (first, first_context) = runtime.create_context();
+        let (_, second_context) = runtime.create_context();
+        let registry = {
+            let isolate = runtime.isolate();
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            let registry = crate::CallbackRegistry::of(scope);
+            let mut ids = vec![];
+            for context in [&first_context, &second_context].iter() {
+                let context = context.get(scope).unwrap();
+                let mut cs = v8::ContextScope::new(scope, context);
+                let scope = cs.enter();
+                let function = run_script(scope, context, "(x) => x + 1").unwrap();
+                let function = crate::JsRef::<v8::Function>::from_value(function, scope, context);
+                ids.push(registry.register(scope, context, function.unwrap()));
+            }
+            let context = second_context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            let arg = make_num(scope, 1.0);
+            let result = registry.invoke(scope, context, ids[1], &[arg]).unwrap();
+            assert_eq!(f64::from_value(result, scope, context), Ok(2.0));
+            assert!(registry.remove(scope, ids[1]));
+            assert!(!registry.remove(scope, ids[1]));
+            assert!(registry.invoke(scope, context, ids[1], &[arg]).is_err());
+            registry
+        };
+        assert_eq!(registry.len(), 1);
+        assert!(runtime.dispose_context(first));
+        assert!(registry.is_empty());
+        let mut first_context = first_context;
+        first_context.reset(runtime.isolate());
+        let mut second_context = second_context;
+        second_context.reset(runtime.isolate());
+    }
+}
@@ -0,0 +1,326 @@
+//! Exposing the functions of one context to another, so that a privileged
+//! host context can hand limited capabilities to sandboxed contexts.
+
+use crate::limits;
+use crate::shim::own_property_names;
+use crate::util::{call_function, eval_function, make_object_wrap, make_str};
+use crate::{FFIError, ObjectWrap};
+use rusty_v8 as v8;
+use std::convert::TryInto;
+use v8::Global;
+
+/// Builds the values of a context, so that they are created in it.
+const BUILD_SOURCE: &str = r#"(function (kind, ...items) {
+    if (kind === 'array') {
+        return items;
+    }
+    if (kind === 'wrap') {
+        const [call, target, name] = items;
+        const exposed = function (...args) {
+            return call(target, args);
+        };
+        Object.defineProperty(exposed, 'name', { value: name });
+        return exposed;
+    }
+    const object = {};
+    for (let i = 0; i < items.length; i += 2) {
+        // defined rather than assigned, so that `__proto__` is just a key
+        Object.defineProperty(object, items[i], {
+            value: items[i + 1],
+            writable: true,
+            enumerable: true,
+            configurable: true,
+        });
+    }
+    return kind === 'frozen' ? Object.freeze(object) : object;
+})"#;
+
+/// A context and its builder, evaluated from `BUILD_SOURCE`.
+struct Side<'a> {
+    context: v8::Local<'a, v8::Context>,
+    build: v8::Local<'a, v8::Function>,
+}
+
+impl<'a> Side<'a> {
+    fn new<'sc: 'a>(
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'a, v8::Context>,
+    ) -> Result<Side<'a>, FFIError> {
+        Ok(Side {
+            context,
+            build: eval_function(scope, context, BUILD_SOURCE)?,
+        })
+    }
+
+    fn build<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        kind: &str,
+        items: Vec<v8::Local<v8::Value>>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        let mut args = Vec::with_capacity(items.len() + 1);
+        args.push(make_str(scope, kind));
+        args.extend(items);
+        let undefined = v8::undefined(scope).into();
+        call_function(scope, self.context, self.build, undefined, &args)
+    }
+}
+
+/// A function exposed to another context, with the context it runs in and
+/// the builders of both contexts.
+struct Exposed {
+    function: Global<v8::Function>,
+    from: Global<v8::Context>,
+    from_build: Global<v8::Function>,
+    into: Global<v8::Context>,
+    into_build: Global<v8::Function>,
+}
+
+/// A function of `into` that calls `function` of `from`, so that a context
+/// can be handed a capability without sharing any of its objects.
+///
+/// The exposed function is created in `into`, so its prototype chain and
+/// anything reachable from it belong to `into`. Arguments and return
+/// values are copied between the contexts rather than shared: primitives
+/// as they are, arrays and other objects as new arrays and plain objects
+/// of their own enumerable properties, and functions by exposing them in
+/// turn, i.e. for callbacks. Exceptions thrown by `function` are rethrown
+/// in `into` as an `Error` with the same message.
+///
+/// ```ignore
+/// let log = run_script(scope, host, "(line) => lines.push(line)").unwrap();
+/// let log = expose_function(scope, host, log.try_into().unwrap(), plugin)?;
+/// plugin.global(scope).set(plugin, make_str(scope, "log"), log.into());
+/// ```
+pub fn expose_function<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    from: v8::Local<v8::Context>,
+    function: v8::Local<v8::Function>,
+    into: v8::Local<v8::Context>,
+) -> Result<v8::Local<'sc, v8::Function>, FFIError> {
+    let from = Side::new(scope, from)?;
+    let into = Side::new(scope, into)?;
+    expose(scope, &from, function, &into)
+}
+
+fn expose<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    from: &Side,
+    function: v8::Local<v8::Function>,
+    into: &Side,
+) -> Result<v8::Local<'sc, v8::Function>, FFIError> {
+    let name_key = make_str(scope, "name");
+    let name = function
+        .get(scope, from.context, name_key)
+        .filter(|x| x.is_string())
+        .unwrap_or_else(|| make_str(scope, ""));
+    let exposed = Exposed {
+        function: Global::new_from(scope, function),
+        from: Global::new_from(scope, from.context),
+        from_build: Global::new_from(scope, from.build),
+        into: Global::new_from(scope, into.context),
+        into_build: Global::new_from(scope, into.build),
+    };
+    let mut wrapped = make_object_wrap(scope, into.context, exposed);
+    wrapped.make_weak();
+    let target = wrapped.get(scope).unwrap();
+    let call = v8::Function::new(scope, into.context, call_exposed)
+        .ok_or_else(|| FFIError::Error("failed to create function".to_string()))?;
+    let exposed = into.build(scope, "wrap", vec![call.into(), target.into(), name])?;
+    Ok(exposed.try_into().unwrap())
+}
+
+/// A frozen object of `into` with the functions among the own enumerable
+/// properties of `object` of `from`, each exposed with `expose_function`.
+/// Other properties are left out.
+pub fn expose_object<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    from: v8::Local<v8::Context>,
+    object: v8::Local<'sc, v8::Object>,
+    into: v8::Local<v8::Context>,
+) -> Result<v8::Local<'sc, v8::Object>, FFIError> {
+    let from = Side::new(scope, from)?;
+    let into = Side::new(scope, into)?;
+    let mut items = vec![];
+    for name in own_property_names(object, scope, from.context) {
+        let key = make_str(scope, &name);
+        let value = object.get(scope, from.context, key);
+        if let Some(function) = value.and_then(|x| x.try_into().ok()) {
+            items.push(key);
+            items.push(expose(scope, &from, function, &into)?.into());
+        }
+    }
+    let exposed = into.build(scope, "frozen", items)?;
+    Ok(exposed.try_into().unwrap())
+}
+
+/// Copy `value` of `from` to `into` as the arguments of exposed functions
+/// are, see `expose_function`.
+pub(crate) fn copy_between<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    value: v8::Local<'sc, v8::Value>,
+    from: v8::Local<v8::Context>,
+    into: v8::Local<v8::Context>,
+) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+    if !value.is_object() {
+        return Ok(value);
+    }
+    let from = Side::new(scope, from)?;
+    let into = Side::new(scope, into)?;
+    copy_value(scope, value, &from, &into)
+}
+
+/// Copy `value` of `from` to `into`, see `expose_function`.
+fn copy_value<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    value: v8::Local<'sc, v8::Value>,
+    from: &Side,
+    into: &Side,
+) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+    if let Ok(function) = TryInto::<v8::Local<v8::Function>>::try_into(value) {
+        return Ok(expose(scope, from, function, into)?.into());
+    }
+    let object: v8::Local<v8::Object> = match value.try_into() {
+        Ok(object) => object,
+        // primitives belong to no context
+        Err(_) => return Ok(value),
+    };
+    let _nested = limits::enter(scope)?;
+    if let Ok(array) = TryInto::<v8::Local<v8::Array>>::try_into(value) {
+        limits::count_entries(array.length() as usize)?;
+        let mut items = Vec::with_capacity(array.length() as usize);
+        for i in 0..array.length() {
+            let item = array
+                .get_index(scope, from.context, i)
+                .unwrap_or_else(|| v8::undefined(scope).into());
+            items.push(copy_value(scope, item, from, into)?);
+        }
+        return into.build(scope, "array", items);
+    }
+    let names = own_property_names(object, scope, from.context);
+    limits::count_entries(names.len())?;
+    let mut items = Vec::with_capacity(names.len() * 2);
+    for name in names {
+        let key = make_str(scope, &name);
+        let item = object
+            .get(scope, from.context, key)
+            .unwrap_or_else(|| v8::undefined(scope).into());
+        items.push(key);
+        items.push(copy_value(scope, item, from, into)?);
+    }
+    into.build(scope, "object", items)
+}
+
+fn call_exposed<'sc>(
+    scope: v8::FunctionCallbackScope<'sc>,
+    args: v8::FunctionCallbackArguments<'sc>,
+    mut rv: v8::ReturnValue<'sc>,
+) {
+    let target: Option<v8::Local<v8::Object>> = args.get(0).try_into().ok();
+    let exposed = match target.and_then(ObjectWrap::<Exposed>::from_object) {
+        Some(exposed) => exposed,
+        None => return FFIError::TypeError("illegal invocation".to_string()).throw(scope),
+    };
+    let arguments: v8::Local<v8::Array> = match args.get(1).try_into() {
+        Ok(arguments) => arguments,
+        Err(_) => return FFIError::TypeError("illegal invocation".to_string()).throw(scope),
+    };
+    match call_in(scope, &exposed, arguments) {
+        Ok(value) => rv.set(value),
+        Err(e) => e.throw(scope),
+    }
+}
+
+/// Call the exposed function with `arguments` of the context it was
+/// exposed to, copying them to its own context and the result back.
+fn call_in<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    exposed: &Exposed,
+    arguments: v8::Local<v8::Array>,
+) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+    let from = Side {
+        context: exposed.from.get(scope).unwrap(),
+        build: exposed.from_build.get(scope).unwrap(),
+    };
+    let into = Side {
+        context: exposed.into.get(scope).unwrap(),
+        build: exposed.into_build.get(scope).unwrap(),
+    };
+    let function = exposed.function.get(scope).unwrap();
+    let mut args = Vec::with_capacity(arguments.length() as usize);
+    for i in 0..arguments.length() {
+        let arg = arguments
+            .get_index(scope, into.context, i)
+            .unwrap_or_else(|| v8::undefined(scope).into());
+        args.push(copy_value(scope, arg, &into, &from)?);
+    }
+    let undefined = v8::undefined(scope).into();
+    let result = call_function(scope, from.context, function, undefined, &args)?;
+    copy_value(scope, result, &from, &into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+    use crate::FFICompat;
+
+    #[test]
+    fn expose_across_contexts() {
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        let (_, host) = runtime.create_context();
+        let (_, plugin) = runtime.create_context();
+        let isolate = runtime.isolate();
+        let mut hs = v8::HandleScope::new(isolate);
+        let scope = hs.enter();
+        let host = host.get(scope).unwrap();
+        let plugin = plugin.get(scope).unwrap();
+        let api = run_script(
+            scope,
+            host,
+            r#"
+            const secret = { token: 'host' };
+            ({
+                add: (a, b) => a + b,
+                measure: (o) => o.label + o.items.length,
+                twice: (f) => f(1) + f(2),
+                make: () => ({ list: [1, 2], secret: undefined, __proto__: { leaked: secret } }),
+                fail: () => { throw new TypeError('nope'); },
+                version: 3,
+            })
+            "#,
+        )
+        .unwrap();
+        let api: v8::Local<v8::Object> = api.try_into().unwrap();
+        let api = crate::expose_object(scope, host, api, plugin).unwrap();
+        let mut cs = v8::ContextScope::new(scope, plugin);
+        let scope = cs.enter();
+        let key = make_str(scope, "api");
+        plugin.global(scope).set(plugin, key, api.into());
+        let result = run_script(
+            scope,
+            plugin,
+            r#"
+            let failed;
+            try { api.fail(); } catch (e) { failed = e instanceof Error && e.message; }
+            const made = api.make();
+            [
+                api.add(2, 3),
+                api.measure({ label: 'n', items: [1, 2, 3] }),
+                api.twice((x) => x * 10),
+                Object.getPrototypeOf(made) === Object.prototype && made.leaked === undefined,
+                made.list instanceof Array,
+                api.add.constructor === Function && api.add.name,
+                Object.isFrozen(api) && Object.keys(api).join(),
+                failed,
+            ].join('|')
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_value(result, scope, plugin),
+            Ok("5|n3|30|true|true|add|add,measure,twice,make,fail|TypeError: nope".to_string())
+        );
+    }
+}
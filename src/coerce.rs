@@ -0,0 +1,283 @@
+use crate::util::exception_message;
+use crate::FFICompat;
+use crate::FFIError;
+use rusty_v8 as v8;
+use std::convert::TryInto;
+use std::ops::{Deref, DerefMut};
+
+/// Types that can be built from any JS value using JS coercion semantics,
+/// i.e. `ToString()` for strings, `ToNumber()` for `f64`, `ToInt32()` and
+/// `ToUint32()` for `i32` and `u32` and truthiness for booleans. `i64` and
+/// `u64` wrap modulo 2^64 like `BigInt.asIntN(64, ...)` and
+/// `BigInt.asUintN(64, ...)` of the truncated number.
+pub trait Coercible: Sized {
+    fn coerce<'sc>(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+    ) -> Result<Self, FFIError>;
+}
+
+/// JS truthiness of `value`, as used by `if (value)`.
+pub(crate) fn is_truthy<'sc>(
+    value: v8::Local<'sc, v8::Value>,
+    scope: &mut impl v8::ToLocal<'sc>,
+) -> bool {
+    if value.is_undefined() || value.is_null() {
+        return false;
+    }
+    if let Ok(value) = TryInto::<v8::Local<v8::Boolean>>::try_into(value) {
+        return value.is_true();
+    }
+    if let Ok(value) = TryInto::<v8::Local<v8::Number>>::try_into(value) {
+        return value
+            .number_value(scope)
+            .map(|x| x != 0.0 && !x.is_nan())
+            .unwrap_or(false);
+    }
+    if let Ok(value) = TryInto::<v8::Local<v8::String>>::try_into(value) {
+        return value.length() > 0;
+    }
+    if let Ok(value) = TryInto::<v8::Local<v8::BigInt>>::try_into(value) {
+        let mut words = vec![0u64; value.word_count()];
+        let (_, words) = value.to_words_array(&mut words);
+        return words.iter().any(|x| *x != 0);
+    }
+    true
+}
+
+/// The `TypeError` of a value that could not be converted to `target`,
+/// with the message of the exception its conversion threw, i.e. from a
+/// `toString` or `valueOf` of its own.
+fn conversion_error<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    exception: Option<v8::Local<v8::Value>>,
+    target: &str,
+) -> FFIError {
+    let message = format!("cannot convert value to {}", target);
+    match exception {
+        Some(exception) => FFIError::TypeError(format!(
+            "{}: {}",
+            message,
+            exception_message(scope, exception)
+        )),
+        None => FFIError::TypeError(message),
+    }
+}
+
+/// Wrap an integral number modulo 2^64, like JS `ToUint32` does modulo 2^32.
+fn wrap_u64(value: f64) -> u64 {
+    if !value.is_finite() {
+        return 0;
+    }
+    // the remainder is exact, but adding 2^64 to a negative one is not
+    let value = value.trunc() % 18_446_744_073_709_551_616.0;
+    if value < 0.0 {
+        (-value as u64).wrapping_neg()
+    } else {
+        value as u64
+    }
+}
+
+impl Coercible for bool {
+    fn coerce<'sc>(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+    ) -> Result<Self, FFIError> {
+        Ok(is_truthy(value, scope))
+    }
+}
+
+impl Coercible for String {
+    fn coerce<'sc>(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+    ) -> Result<Self, FFIError> {
+        let mut try_catch = v8::TryCatch::new(scope);
+        let tc = try_catch.enter();
+        match value.to_string(scope) {
+            Some(value) => Ok(value.to_rust_string_lossy(scope)),
+            None => Err(conversion_error(scope, tc.exception(), "a string")),
+        }
+    }
+}
+
+macro_rules! coercible_number {
+    ($ty:ty, $method:ident, $convert:expr) => {
+        impl Coercible for $ty {
+            fn coerce<'sc>(
+                value: v8::Local<'sc, v8::Value>,
+                scope: &mut impl v8::ToLocal<'sc>,
+            ) -> Result<Self, FFIError> {
+                let mut try_catch = v8::TryCatch::new(scope);
+                let tc = try_catch.enter();
+                match value.$method(scope) {
+                    Some(value) => Ok($convert(value)),
+                    None => Err(conversion_error(scope, tc.exception(), "a number")),
+                }
+            }
+        }
+    };
+}
+
+coercible_number!(f64, number_value, |x| x);
+coercible_number!(i64, number_value, |x| wrap_u64(x) as i64);
+coercible_number!(u64, number_value, wrap_u64);
+coercible_number!(i32, int32_value, |x| x);
+coercible_number!(u32, uint32_value, |x| x);
+
+/// `Coerced` is an FFI argument type that converts JS values the way JS
+/// itself would rather than requiring the exact JS type, so `"5"` is
+/// accepted for a `Coerced<u32>` and `5` for a `Coerced<String>`.
+///
+/// `#[v8_ffi(coerce)]` applies it to every `String`, `bool` and number
+/// argument of a function.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Coerced<T>(pub T);
+
+impl<T> Deref for Coerced<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Coerced<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<'sc, 'c, T: Coercible + FFICompat<'sc, 'c>> FFICompat<'sc, 'c> for Coerced<T> {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        T::coerce(value, scope).map(Coerced)
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        self.0
+            .to_value(scope, context)
+            .map_err(|e| FFIError::Error(format!("{:?}", e)))
+    }
+}
+
+/// `Truthy` is an FFI argument type accepting any JS value as its
+/// truthiness, for callers passing `1` or `"yes"` where a strict `bool`
+/// parameter would throw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Truthy(pub bool);
+
+impl Deref for Truthy {
+    type Target = bool;
+
+    fn deref(&self) -> &bool {
+        &self.0
+    }
+}
+
+impl From<Truthy> for bool {
+    fn from(value: Truthy) -> bool {
+        value.0
+    }
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for Truthy {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        Ok(Truthy(is_truthy(value, scope)))
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        Ok(v8::Boolean::new(scope, self.0).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+    use rusty_v8_helper_derive::v8_ffi;
+
+    #[v8_ffi]
+    fn test_ffi_wrapping(
+        int: Coerced<i32>,
+        uint: Coerced<u32>,
+        long: Coerced<i64>,
+        ulong: Coerced<u64>,
+    ) -> String {
+        format!("{} {} {} {}", *int, *uint, *long, *ulong)
+    }
+
+    #[v8_ffi]
+    fn test_ffi_truthiness(values: Vec<Truthy>) -> Vec<bool> {
+        values.into_iter().map(bool::from).collect()
+    }
+
+    #[test]
+    fn js_coercion() {
+        with_context!(
+            [test_ffi_wrapping, test_ffi_truthiness],
+            |scope, context| {
+                let result = run_script(
+                    scope,
+                    context,
+                    r#"
+                [
+                    test_ffi_wrapping(2 ** 32 + 5, -1, 2 ** 64 + 4096, -1),
+                    test_ffi_wrapping(2 ** 31, '4294967297.5', -(2 ** 63), NaN),
+                    test_ffi_wrapping(Infinity, -1.5, 2 ** 63, 2 ** 64),
+                ].join('\n')
+                "#,
+                )
+                .unwrap();
+                assert_eq!(
+                    String::from_value(result, scope, context),
+                    Ok("5 4294967295 4096 18446744073709551615\n\
+                    -2147483648 1 -9223372036854775808 0\n\
+                    0 4294967295 -9223372036854775808 0"
+                        .to_string())
+                );
+                let result = run_script(
+                    scope,
+                    context,
+                    "test_ffi_truthiness([0n, -0n, 1n, 2n ** 64n, -0, '', 'x', null, {}])",
+                )
+                .unwrap();
+                assert_eq!(
+                    Vec::<bool>::from_value(result, scope, context),
+                    Ok(vec![
+                        false, false, true, true, false, false, true, false, true
+                    ])
+                );
+                let result = run_script(
+                scope,
+                context,
+                "try { test_ffi_wrapping({ valueOf() { throw new Error('no number'); } }, 0, 0, 0) } catch (e) { `${e.name}: ${e.message}` }",
+            )
+            .unwrap();
+                assert_eq!(
+                    String::from_value(result, scope, context),
+                    Ok("TypeError: cannot convert value to a number: Error: no number".to_string())
+                );
+            }
+        );
+    }
+}
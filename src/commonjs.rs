@@ -0,0 +1,179 @@
+use crate::extensions::{run_bootstrap, Extension};
+pub use crate::module::FsModuleResolver;
+use crate::module::ModuleResolver;
+use crate::util::*;
+use crate::FFIError;
+use rusty_v8 as v8;
+use rusty_v8_helper_derive::v8_ffi;
+use std::rc::Rc;
+
+struct CommonJsLoader {
+    resolver: Rc<dyn ModuleResolver>,
+}
+
+/// Resolve `specifier` from `referrer` to the urls to probe, in order: as
+/// is, and the `.js`, `.json` and `/index.js` variants as node does.
+#[v8_ffi]
+fn commonjs_resolve(
+    this: &CommonJsLoader,
+    specifier: String,
+    referrer: String,
+) -> Result<Vec<String>, FFIError> {
+    let base = this
+        .resolver
+        .resolve(&specifier, &referrer)
+        .map_err(FFIError::Error)?;
+    Ok(vec![
+        base.clone(),
+        format!("{}.js", base),
+        format!("{}.json", base),
+        format!("{}/index.js", base),
+    ])
+}
+
+/// Load the first of the `candidates` from `commonjs_resolve` that exists.
+#[v8_ffi]
+fn commonjs_load(
+    this: &CommonJsLoader,
+    candidates: Vec<String>,
+    specifier: String,
+    referrer: String,
+) -> Result<(String, String), FFIError> {
+    for candidate in candidates.iter() {
+        if let Ok(source) = this.resolver.load(candidate) {
+            return Ok((candidate.clone(), source));
+        }
+    }
+    Err(FFIError::Error(format!(
+        "Cannot find module '{}' from '{}'",
+        specifier, referrer
+    )))
+}
+
+const COMMONJS_BOOTSTRAP: &str = r#"
+(function (loader, resolve, load) {
+    const cache = Object.create(null);
+    const makeRequire = (referrer) => {
+        const require = (specifier) => {
+            specifier = String(specifier);
+            const candidates = resolve.call(loader, specifier, referrer);
+            for (const candidate of candidates) {
+                if (cache[candidate]) return cache[candidate].exports;
+            }
+            const [filename, source] = load.call(loader, candidates, specifier, referrer);
+            const module = { id: filename, filename, exports: {}, loaded: false };
+            cache[filename] = module;
+            try {
+                if (filename.endsWith('.json')) {
+                    module.exports = JSON.parse(source);
+                } else {
+                    const dirname = filename.includes('/') ? filename.slice(0, filename.lastIndexOf('/')) : '';
+                    const wrapper = (0, eval)(
+                        `(function (exports, require, module, __filename, __dirname) {${source}\n})\n//# sourceURL=${filename}`
+                    );
+                    wrapper.call(module.exports, module.exports, makeRequire(filename), module, filename, dirname);
+                }
+            } catch (e) {
+                delete cache[filename];
+                throw e;
+            }
+            module.loaded = true;
+            return module.exports;
+        };
+        require.cache = cache;
+        return require;
+    };
+    this.require = makeRequire('');
+})
+"#;
+
+/// Installs a node-style global `require` loading CommonJS modules through a
+/// `ModuleResolver`, such as `FsModuleResolver` or `MemoryModuleResolver`.
+///
+/// Each installation keeps its own module cache, exposed as
+/// `require.cache`, so contexts do not share module instances.
+pub struct CommonJsExtension {
+    resolver: Rc<dyn ModuleResolver>,
+}
+
+impl CommonJsExtension {
+    pub fn new(resolver: impl ModuleResolver + 'static) -> CommonJsExtension {
+        CommonJsExtension {
+            resolver: Rc::new(resolver),
+        }
+    }
+}
+
+impl Extension for CommonJsExtension {
+    fn install<'sc, 'c>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<(), FFIError> {
+        let mut loader = make_object_wrap(
+            scope,
+            context,
+            CommonJsLoader {
+                resolver: self.resolver.clone(),
+            },
+        );
+        loader.make_weak();
+        let loader = loader.get(scope).unwrap().into();
+        let resolve = load_v8_ffi!(commonjs_resolve, scope, context);
+        let load = load_v8_ffi!(commonjs_load, scope, context);
+        run_bootstrap(scope, context, COMMONJS_BOOTSTRAP, &[loader, resolve, load])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FFICompat;
+
+    #[test]
+    fn require_modules() {
+        with_context!(|scope, context| {
+            let mut modules = crate::module::MemoryModuleResolver::new();
+            modules.insert("lib/index.js", "exports.add = require('./add').add;");
+            modules.insert("lib/add.js", "module.exports = { add: (a, b) => a + b };");
+            crate::commonjs::CommonJsExtension::new(modules)
+                .install(scope, context)
+                .unwrap();
+            let result = run_script(scope, context, "require('./lib').add(2, 3)").unwrap();
+            assert_eq!(i32::from_value(result, scope, context), Ok(5));
+        });
+    }
+
+    struct CountingResolver(
+        crate::module::MemoryModuleResolver,
+        Rc<std::cell::Cell<u32>>,
+    );
+
+    impl ModuleResolver for CountingResolver {
+        fn load(&self, url: &str) -> Result<String, String> {
+            self.1.set(self.1.get() + 1);
+            self.0.load(url)
+        }
+    }
+
+    #[test]
+    fn cached_modules() {
+        with_context!(|scope, context| {
+            let mut modules = crate::module::MemoryModuleResolver::new();
+            modules.insert("counter.js", "exports.count = (exports.count || 0) + 1;");
+            let loads = Rc::new(std::cell::Cell::new(0));
+            CommonJsExtension::new(CountingResolver(modules, loads.clone()))
+                .install(scope, context)
+                .unwrap();
+            let result = run_script(
+                scope,
+                context,
+                "require('./counter').count + require('./counter.js').count + require('counter').count",
+            )
+            .unwrap();
+            assert_eq!(i32::from_value(result, scope, context), Ok(3));
+            // `./counter` probes `counter` before `counter.js`, later requires hit the cache
+            assert_eq!(loads.get(), 2);
+        });
+    }
+}
@@ -0,0 +1,256 @@
+//! Installation of constant values, such as error code tables, onto a JS
+//! namespace object.
+
+use crate::ffi_map::serde_to_js_value;
+use crate::rename::rename_policy;
+use crate::util::*;
+use crate::{FFICompat, FFIError, FFIObject, JsValue, RenamePolicy};
+use rusty_v8 as v8;
+use serde::Serialize;
+
+const INSTALL_SOURCE: &str = r#"(function (target, consts, freeze) {
+    const deepFreeze = (value) => {
+        if (value !== null && typeof value === 'object' && !Object.isFrozen(value)) {
+            Object.freeze(value);
+            for (const key of Object.keys(value)) {
+                deepFreeze(value[key]);
+            }
+        }
+        return value;
+    };
+    for (const key of Object.keys(consts)) {
+        Object.defineProperty(target, key, {
+            value: freeze ? deepFreeze(consts[key]) : consts[key],
+            enumerable: true,
+            writable: !freeze,
+            configurable: !freeze,
+        });
+    }
+})"#;
+
+enum Const {
+    Value(JsValue),
+    Object(serde_json::Value, Option<RenamePolicy>),
+}
+
+/// `Consts` is a set of constant values to install onto an object, built
+/// without a scope. See also `load_v8_consts!`.
+///
+/// ```ignore
+/// Consts::new()
+///     .set("ENOENT", 2)
+///     .set("VERSION", "1.0.0")
+///     .set_object("DEFAULTS", Defaults { retries: 3 })?
+///     .freeze()
+///     .install(scope, context, errno)?;
+/// ```
+#[derive(Default)]
+pub struct Consts {
+    entries: Vec<(String, Const)>,
+    freeze: bool,
+}
+
+impl Consts {
+    pub fn new() -> Consts {
+        Consts::default()
+    }
+
+    /// Add the constant `name` with a number, string, array or other
+    /// `JsValue`.
+    pub fn set(mut self, name: &str, value: impl Into<JsValue>) -> Consts {
+        self.entries
+            .push((name.to_string(), Const::Value(value.into())));
+        self
+    }
+
+    /// Add the constant `name` with a `FFIObject` struct, converted as it
+    /// would be when returned from a `v8_ffi` fn.
+    pub fn set_object<T: Serialize + FFIObject>(
+        mut self,
+        name: &str,
+        value: T,
+    ) -> Result<Consts, FFIError> {
+        let value = crate::ser::to_value(&value)
+            .map_err(|e| FFIError::TypeError(format!("invalid constant {}: {}", name, e)))?;
+        self.entries
+            .push((name.to_string(), Const::Object(value, T::RENAME)));
+        Ok(self)
+    }
+
+    /// Make the installed properties read-only and deeply `Object.freeze`
+    /// the installed objects.
+    pub fn freeze(mut self) -> Consts {
+        self.freeze = true;
+        self
+    }
+
+    /// Define the constants as properties of `target`.
+    pub fn install<'sc>(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+        target: v8::Local<v8::Object>,
+    ) -> Result<(), FFIError> {
+        let consts = v8::Object::new(scope);
+        for (name, value) in self.entries {
+            let value = match value {
+                Const::Value(value) => value.to_value(scope, context)?,
+                Const::Object(value, policy) => {
+                    let policy = policy.unwrap_or_else(|| rename_policy(scope));
+                    let value = policy.rename_keys(value, RenamePolicy::to_js);
+                    serde_to_js_value(value, scope, context).map_err(|e| {
+                        FFIError::TypeError(format!("invalid constant {}: {}", name, e))
+                    })?
+                }
+            };
+            let key = make_str(scope, &name);
+            consts.set(context, key, value);
+        }
+        let install = eval_function(scope, context, INSTALL_SOURCE)?;
+        let freeze = make_bool(scope, self.freeze);
+        let undefined = v8::undefined(scope).into();
+        call_function(
+            scope,
+            context,
+            install,
+            undefined,
+            &[target.into(), consts.into(), freeze],
+        )?;
+        Ok(())
+    }
+
+    /// Install the constants onto a new object, set as the property `name`
+    /// of `parent`. The new object is itself frozen if `freeze` was called.
+    pub fn install_namespace<'sc>(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+        parent: v8::Local<v8::Object>,
+        name: &str,
+    ) -> Result<v8::Local<'sc, v8::Object>, FFIError> {
+        let namespace = v8::Object::new(scope);
+        let freeze = self.freeze;
+        self.install(scope, context, namespace)?;
+        if freeze {
+            let freeze = eval_function(scope, context, "Object.freeze")?;
+            let undefined = v8::undefined(scope).into();
+            call_function(scope, context, freeze, undefined, &[namespace.into()])?;
+        }
+        let key = make_str(scope, name);
+        parent.set(context, key, namespace.into());
+        Ok(namespace)
+    }
+}
+
+/// `JsEnum` lists the variants of a Rust enum of unit variants, to mirror
+/// it in JS with `install_js_enum`. Derive it with `#[derive(JsEnum)]`.
+pub trait JsEnum: Serialize + FFIObject + Sized {
+    /// The name of the JS enum object.
+    const NAME: &'static str;
+
+    /// The variants with their Rust names, in declaration order.
+    fn variants() -> Vec<(&'static str, Self)>;
+}
+
+/// Install the frozen objects `T::NAME`, mapping each variant name to its JS
+/// value, i.e. `Color.Red === "red"`, and `T::NAME` + `Names`, mapping the JS
+/// values back, i.e. `ColorNames.red === "Red"`, onto `parent`.
+///
+/// The JS values are the variants converted as `FFIObject`s, so they stay
+/// in sync with the values `v8_ffi` fns take and return.
+pub fn install_js_enum<'sc, T: JsEnum>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    parent: v8::Local<v8::Object>,
+) -> Result<(), FFIError> {
+    let mut values = Consts::new().freeze();
+    let mut names = Consts::new().freeze();
+    for (name, variant) in T::variants() {
+        let value = serde_json::to_value(variant)
+            .map_err(|e| FFIError::TypeError(format!("invalid variant {}: {}", name, e)))?;
+        let key = match &value {
+            serde_json::Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        values
+            .entries
+            .push((name.to_string(), Const::Object(value, T::RENAME)));
+        names = names.set(&key, name);
+    }
+    values.install_namespace(scope, context, parent, T::NAME)?;
+    names.install_namespace(scope, context, parent, &format!("{}Names", T::NAME))?;
+    Ok(())
+}
+
+/// Install constants onto `target`, optionally frozen, see `Consts`.
+///
+/// ```ignore
+/// load_v8_consts!(scope, context, errno, { ENOENT: 2, EACCES: 13 })?;
+/// load_v8_consts!(scope, context, errno, freeze, { ENOENT: 2, EACCES: 13 })?;
+/// ```
+#[macro_export]
+macro_rules! load_v8_consts {
+    ($scope:expr, $context:expr, $target:expr, freeze, { $($name:ident : $value:expr),* $(,)? }) => {
+        $crate::Consts::new()
+            $(.set(stringify!($name), $value))*
+            .freeze()
+            .install($scope, $context, $target)
+    };
+    ($scope:expr, $context:expr, $target:expr, { $($name:ident : $value:expr),* $(,)? }) => {
+        $crate::Consts::new()
+            $(.set(stringify!($name), $value))*
+            .install($scope, $context, $target)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, crate::FFIObject)]
+    #[js_rename_all = "camelCase"]
+    struct TestDefaults {
+        max_retries: u32,
+    }
+
+    #[derive(Serialize, Deserialize, crate::FFIObject, crate::JsEnum)]
+    #[serde(rename_all = "lowercase")]
+    enum TestColor {
+        Red,
+        Green,
+    }
+
+    #[test]
+    fn consts_and_enums() {
+        with_context!(|scope, context| {
+            let global = context.global(scope);
+            let errno = v8::Object::new(scope);
+            global.set(context, make_str(scope, "errno"), errno.into());
+            crate::load_v8_consts!(scope, context, errno, freeze, { ENOENT: 2, NAME: "errno", CODES: vec![1, 2] })
+                .unwrap();
+            crate::Consts::new()
+                .set_object("DEFAULTS", TestDefaults { max_retries: 3 })
+                .unwrap()
+                .freeze()
+                .install_namespace(scope, context, global, "config")
+                .unwrap();
+            let result = run_script(
+                scope,
+                context,
+                "(() => { 'use strict'; let failed = 0; for (const f of [() => { errno.ENOENT = 3; }, () => errno.CODES.push(3), () => { config.other = 1; }]) { try { f(); } catch (e) { failed += 1; } } return failed === 3 && errno.ENOENT === 2 && errno.NAME === 'errno' && errno.CODES.length === 2 && config.DEFAULTS.maxRetries === 3; })()",
+            )
+            .unwrap();
+            assert!(result.is_true());
+
+            crate::install_js_enum::<TestColor>(scope, context, global).unwrap();
+            let result = run_script(
+                scope,
+                context,
+                "TestColor.Red === 'red' && TestColor.Green === 'green' && TestColorNames.red === 'Red' && Object.isFrozen(TestColor) && Object.isFrozen(TestColorNames)",
+            )
+            .unwrap();
+            assert!(result.is_true());
+        });
+    }
+}
@@ -0,0 +1,287 @@
+//! `ContextTemplate`, the global state of a bootstrapped context, frozen
+//! and copied into new contexts rather than bootstrapping each of them.
+
+use crate::capability::copy_between;
+use crate::shim::own_property_names;
+use crate::util::{call_function, eval_function, make_str};
+use crate::{ContextId, FFICompat, FFIError, Runtime};
+use rusty_v8 as v8;
+use std::collections::HashSet;
+use std::convert::TryInto;
+use v8::Global;
+
+/// Deep freezes the named globals and collects them into a frozen object,
+/// along with the paths of the functions reachable from them.
+const CAPTURE_BOOTSTRAP: &str = r#"
+(function (names) {
+    const seen = new Set();
+    const functions = [];
+    const freeze = (value, path) => {
+        if (value === null || (typeof value !== 'object' && typeof value !== 'function') ||
+            seen.has(value)) {
+            return;
+        }
+        seen.add(value);
+        if (typeof value === 'function') functions.push(path);
+        for (const key of Reflect.ownKeys(value)) {
+            const descriptor = Reflect.getOwnPropertyDescriptor(value, key);
+            if ('value' in descriptor) freeze(descriptor.value, `${path}.${String(key)}`);
+        }
+        try {
+            Object.freeze(value);
+        } catch (e) {
+            // typed arrays with elements cannot be frozen
+        }
+    };
+    const state = {};
+    for (const name of names) {
+        state[name] = globalThis[name];
+        freeze(state[name], name);
+    }
+    return [Object.freeze(state), functions];
+})
+"#;
+
+/// `ContextTemplate` captures the globals a context was given beyond the
+/// JS builtins, i.e. by bootstrap scripts, so that request contexts can be
+/// instantiated from it without running them again. Complements
+/// `ContextRecycler` for executions that mutate their globals too much to
+/// be reset.
+///
+/// The captured values are deep frozen in the template context, and
+/// copied into each instantiated context as values crossing contexts are
+/// by `expose_function`: primitives as they are, arrays and objects as new
+/// arrays and plain objects of their own enumerable properties, so that
+/// each context can mutate its copy, and functions exposed from the
+/// template. Exposed functions run in the template context, seeing its
+/// frozen globals rather than the instantiated context's.
+///
+/// # Shared state
+///
+/// Captured functions are not copied: every instantiated context calls the
+/// same function, so state it closes over, i.e. a counter or cache in a
+/// `let` of the bootstrap script, is shared by all of them and can leak
+/// between requests. Freezing only reaches properties, not closures. Check
+/// `shared_functions` for what a template exposes, and keep mutable state
+/// out of captured functions, i.e. in globals installed per context by
+/// `Runtime::on_context_created` hooks.
+///
+/// Globals an instantiated context already has, i.e. installed by
+/// `Runtime::on_context_created` hooks, are left as they are, so
+/// extensions with state of their own per context are installed by hooks
+/// rather than captured.
+///
+/// ```ignore
+/// let (_, template_context) = runtime.create_context();
+/// // run the bootstrap scripts in template_context
+/// let template = ContextTemplate::capture(scope, context)?;
+/// // for every request
+/// let (id, context) = template.instantiate(&mut runtime)?;
+/// ```
+pub struct ContextTemplate {
+    context: Global<v8::Context>,
+    state: Global<v8::Object>,
+    globals: Vec<String>,
+    functions: Vec<String>,
+}
+
+impl ContextTemplate {
+    /// Capture the enumerable globals of `context` that a new context does
+    /// not have, freezing them. `context` should not be used for anything
+    /// but the template afterwards.
+    pub fn capture<'sc>(
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+    ) -> Result<ContextTemplate, FFIError> {
+        let builtins = {
+            let fresh = v8::Context::new(scope);
+            let global = fresh.global(scope);
+            own_property_names(global, scope, fresh)
+                .into_iter()
+                .collect::<HashSet<_>>()
+        };
+        let global = context.global(scope);
+        let globals = own_property_names(global, scope, context)
+            .into_iter()
+            .filter(|x| !builtins.contains(x))
+            .collect::<Vec<_>>();
+        let capture = eval_function(scope, context, CAPTURE_BOOTSTRAP)?;
+        let names = globals.clone().to_value(scope, context)?;
+        let captured = call_function(scope, context, capture, global.into(), &[names])?;
+        let (state, functions) =
+            <(v8::Local<v8::Object>, Vec<String>)>::from_value(captured, scope, context)
+                .map_err(|e| FFIError::Error(format!("failed to capture context: {:?}", e)))?;
+        Ok(ContextTemplate {
+            context: Global::new_from(scope, context),
+            state: Global::new_from(scope, state),
+            globals,
+            functions,
+        })
+    }
+
+    /// The names of the captured globals.
+    pub fn globals(&self) -> &[String] {
+        &self.globals
+    }
+
+    /// The paths of the captured functions, i.e. `config.format`, which are
+    /// shared by every instantiated context, see `ContextTemplate`.
+    pub fn shared_functions(&self) -> &[String] {
+        &self.functions
+    }
+
+    /// Create a context through `runtime`, running its
+    /// `on_context_created` hooks, and define the captured globals in it.
+    pub fn instantiate(
+        &self,
+        runtime: &mut Runtime,
+    ) -> Result<(ContextId, Global<v8::Context>), FFIError> {
+        let (id, mut context) = runtime.create_context();
+        let applied = {
+            let mut hs = v8::HandleScope::new(runtime.isolate());
+            let scope = hs.enter();
+            let local = context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, local);
+            let scope = cs.enter();
+            self.apply(scope, local)
+        };
+        match applied {
+            Ok(()) => Ok((id, context)),
+            Err(e) => {
+                context.reset(runtime.isolate());
+                runtime.dispose_context(id);
+                Err(e)
+            }
+        }
+    }
+
+    /// Define the captured globals that `context` does not have yet in it.
+    pub fn apply<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+    ) -> Result<(), FFIError> {
+        let from = self.context.get(scope).unwrap();
+        let state = self.state.get(scope).unwrap();
+        let copied = copy_between(scope, state.into(), from, context)?;
+        let copied: v8::Local<v8::Object> = copied.try_into().unwrap();
+        let global = context.global(scope);
+        let existing = own_property_names(global, scope, context)
+            .into_iter()
+            .collect::<HashSet<_>>();
+        for name in &self.globals {
+            if existing.contains(name) {
+                continue;
+            }
+            let key = make_str(scope, name);
+            let value = copied
+                .get(scope, context, key)
+                .unwrap_or_else(|| v8::undefined(scope).into());
+            global.set(context, key, value);
+        }
+        Ok(())
+    }
+
+    /// Release the handles to the template context and its state.
+    pub fn release(mut self, scope: &mut impl v8::InIsolate) {
+        self.state.reset(scope);
+        self.context.reset(scope);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+
+    #[test]
+    fn context_template() {
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        runtime.on_context_created(|isolate, _, context| {
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            let context = context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            run_script(scope, context, "globalThis.host = 'hook'");
+        });
+        let (_, mut template_context) = runtime.create_context();
+        let run = |isolate: &mut v8::Isolate, context: &v8::Global<v8::Context>, source: &str| {
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            let context = context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            let value = run_script(scope, context, source).unwrap();
+            String::from_value(value, scope, context).unwrap()
+        };
+        run(
+            runtime.isolate(),
+            &template_context,
+            r#"
+            globalThis.config = { limits: { max: 3 }, tags: ['a'] };
+            var counter = 0;
+            function describe(x) { return `${x.name} ${Object.isFrozen(config)}`; }
+            globalThis.host = 'template';
+            globalThis.next = (() => { let calls = 0; return () => ++calls; })();
+            ''
+            "#,
+        );
+        let template = {
+            let mut hs = v8::HandleScope::new(runtime.isolate());
+            let scope = hs.enter();
+            let context = template_context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            ContextTemplate::capture(scope, context).unwrap()
+        };
+        let mut globals = template.globals().to_vec();
+        globals.sort();
+        assert_eq!(
+            globals,
+            vec!["config", "counter", "describe", "host", "next"]
+        );
+        let mut functions = template.shared_functions().to_vec();
+        functions.sort();
+        assert_eq!(functions, vec!["describe", "next"]);
+        assert_eq!(
+            run(
+                runtime.isolate(),
+                &template_context,
+                "String(Object.isFrozen(config.limits))"
+            ),
+            "true"
+        );
+        let (_, mut first) = template.instantiate(&mut runtime).unwrap();
+        let (_, mut second) = template.instantiate(&mut runtime).unwrap();
+        assert_eq!(
+            run(
+                runtime.isolate(),
+                &first,
+                r#"
+                config.limits.max = 10;
+                config.tags.push('b');
+                counter = 5;
+                [config.limits.max, config.tags, counter, host, describe({ name: 'x' })].join()
+                "#
+            ),
+            "10,a,b,5,hook,x true"
+        );
+        assert_eq!(
+            run(
+                runtime.isolate(),
+                &second,
+                "[config.limits.max, config.tags, counter, host].join()"
+            ),
+            "3,a,0,hook"
+        );
+        // captured functions and their closures are shared, not copied
+        assert_eq!(run(runtime.isolate(), &first, "String(next())"), "1");
+        assert_eq!(run(runtime.isolate(), &second, "String(next())"), "2");
+        first.reset(runtime.isolate());
+        second.reset(runtime.isolate());
+        template.release(runtime.isolate());
+        template_context.reset(runtime.isolate());
+    }
+}
@@ -0,0 +1,319 @@
+//! Reports of the precise coverage V8 collects for scripts, as lcov or
+//! istanbul JSON.
+//!
+//! rusty_v8 does not bind V8's coverage API, so coverage is collected over
+//! the inspector protocol, with `Profiler.startPreciseCoverage` (with
+//! `callCount` and `detailed` set) before running the scripts and
+//! `Profiler.takePreciseCoverage` after. `parse` reads its result, and the
+//! reports take the source of each script from the text recorded for it,
+//! see `sources`, which `compile_only` and the module loader do.
+//!
+//! ```ignore
+//! let coverage = coverage::parse(&take_precise_coverage_response)?;
+//! std::fs::write("lcov.info", coverage::to_lcov(scope, &coverage))?;
+//! ```
+
+use crate::sources::source_of;
+use rusty_v8 as v8;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+/// The coverage of a script, as in `Profiler.takePreciseCoverage`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptCoverage {
+    pub script_id: String,
+    /// The resource name of the script.
+    pub url: String,
+    pub functions: Vec<FunctionCoverage>,
+}
+
+/// The coverage of a function of a script. Its first range spans the whole
+/// function, and with block coverage, the ranges after it the blocks within
+/// that ran a different number of times, inner blocks after outer ones.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionCoverage {
+    pub function_name: String,
+    pub ranges: Vec<CoverageRange>,
+    pub is_block_coverage: bool,
+}
+
+/// A range of a script, in UTF-16 code units, and how many times it ran.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverageRange {
+    pub start_offset: usize,
+    pub end_offset: usize,
+    pub count: u64,
+}
+
+#[derive(Deserialize)]
+struct TakePreciseCoverage {
+    result: Vec<ScriptCoverage>,
+}
+
+/// Read the result of `Profiler.takePreciseCoverage`, either the whole
+/// response or its `result` array.
+pub fn parse(json: &str) -> Result<Vec<ScriptCoverage>, serde_json::Error> {
+    match serde_json::from_str::<TakePreciseCoverage>(json) {
+        Ok(response) => Ok(response.result),
+        Err(_) => serde_json::from_str(json),
+    }
+}
+
+/// A line of a source, by UTF-16 offsets.
+struct Line {
+    start: usize,
+    end: usize,
+    /// The offset of the first non-whitespace character, if any.
+    code: Option<usize>,
+}
+
+fn lines(source: &str) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut offset = 0;
+    for text in source.split('\n') {
+        let mut code = None;
+        let mut end = offset;
+        for c in text.chars() {
+            if code.is_none() && !c.is_whitespace() {
+                code = Some(end);
+            }
+            end += c.len_utf16();
+        }
+        lines.push(Line {
+            start: offset,
+            end,
+            code,
+        });
+        offset = end + 1;
+    }
+    lines
+}
+
+/// The 1-based line and 0-based column of `offset`.
+fn position(lines: &[Line], offset: usize) -> (usize, usize) {
+    let index = match lines.binary_search_by(|x| x.start.cmp(&offset)) {
+        Ok(index) => index,
+        Err(index) => index.saturating_sub(1),
+    };
+    (index + 1, offset - lines[index].start)
+}
+
+/// How many times each UTF-16 offset of a script of `length` ran, with
+/// inner ranges overriding outer ones.
+fn offset_counts(script: &ScriptCoverage, length: usize) -> Vec<u64> {
+    let mut counts = vec![0; length];
+    for function in &script.functions {
+        for range in &function.ranges {
+            let end = range.end_offset.min(length);
+            for count in counts.iter_mut().take(end).skip(range.start_offset) {
+                *count = range.count;
+            }
+        }
+    }
+    counts
+}
+
+/// The functions of `script` worth reporting, leaving out the top-level
+/// code, which V8 reports as a nameless function spanning the script.
+fn functions(script: &ScriptCoverage, length: usize) -> Vec<(String, CoverageRange)> {
+    let mut anonymous = 0;
+    let mut functions = vec![];
+    for function in &script.functions {
+        let range = match function.ranges.first() {
+            Some(range) => *range,
+            None => continue,
+        };
+        let name = if function.function_name.is_empty() {
+            if range.start_offset == 0 && range.end_offset >= length {
+                continue;
+            }
+            anonymous += 1;
+            format!("(anonymous_{})", anonymous)
+        } else {
+            function.function_name.clone()
+        };
+        functions.push((name, range));
+    }
+    functions
+}
+
+/// Per line execution counts of the lines with code, as
+/// `(line, count)` with 1-based lines, taken at the first character of
+/// code of each line.
+pub fn line_counts(script: &ScriptCoverage, source: &str) -> Vec<(usize, u64)> {
+    let lines = lines(source);
+    let length = lines.last().map(|x| x.end).unwrap_or(0);
+    let counts = offset_counts(script, length);
+    lines
+        .iter()
+        .enumerate()
+        .filter_map(|(i, line)| Some((i + 1, counts[line.code?])))
+        .collect()
+}
+
+/// The coverage of the scripts with a recorded source as an lcov
+/// tracefile, i.e. for `genhtml`.
+pub fn to_lcov(scope: &mut impl v8::InIsolate, coverage: &[ScriptCoverage]) -> String {
+    let mut out = String::new();
+    for script in coverage {
+        let source = match source_of(scope, &script.url) {
+            Some(source) => source,
+            None => continue,
+        };
+        let lines = lines(&source);
+        let length = lines.last().map(|x| x.end).unwrap_or(0);
+        out.push_str("TN:\n");
+        out.push_str(&format!("SF:{}\n", script.url));
+        let functions = functions(script, length);
+        for (name, range) in &functions {
+            let (line, _) = position(&lines, range.start_offset);
+            out.push_str(&format!("FN:{},{}\n", line, name));
+        }
+        for (name, range) in &functions {
+            out.push_str(&format!("FNDA:{},{}\n", range.count, name));
+        }
+        out.push_str(&format!("FNF:{}\n", functions.len()));
+        let hit = functions.iter().filter(|x| x.1.count > 0).count();
+        out.push_str(&format!("FNH:{}\n", hit));
+        let counts = line_counts(script, &source);
+        for (line, count) in &counts {
+            out.push_str(&format!("DA:{},{}\n", line, count));
+        }
+        out.push_str(&format!("LF:{}\n", counts.len()));
+        let hit = counts.iter().filter(|x| x.1 > 0).count();
+        out.push_str(&format!("LH:{}\n", hit));
+        out.push_str("end_of_record\n");
+    }
+    out
+}
+
+fn location(lines: &[Line], start: usize, end: usize) -> Value {
+    let (start_line, start_column) = position(lines, start);
+    let (end_line, end_column) = position(lines, end);
+    json!({
+        "start": { "line": start_line, "column": start_column },
+        "end": { "line": end_line, "column": end_column },
+    })
+}
+
+/// The coverage of the scripts with a recorded source as istanbul's
+/// coverage JSON, keyed by resource name, i.e. for `nyc report`. Lines
+/// with code are its statements, functions its functions, and blocks
+/// within functions single-location branches.
+pub fn to_istanbul(scope: &mut impl v8::InIsolate, coverage: &[ScriptCoverage]) -> Value {
+    let mut files = Map::new();
+    for script in coverage {
+        let source = match source_of(scope, &script.url) {
+            Some(source) => source,
+            None => continue,
+        };
+        let lines = lines(&source);
+        let length = lines.last().map(|x| x.end).unwrap_or(0);
+        let counts = offset_counts(script, length);
+
+        let (mut statement_map, mut s) = (Map::new(), Map::new());
+        for (i, line) in lines.iter().filter(|x| x.code.is_some()).enumerate() {
+            let start = line.code.unwrap();
+            statement_map.insert(i.to_string(), location(&lines, start, line.end));
+            s.insert(i.to_string(), json!(counts[start]));
+        }
+
+        let (mut fn_map, mut f) = (Map::new(), Map::new());
+        for (i, (name, range)) in functions(script, length).into_iter().enumerate() {
+            let loc = location(&lines, range.start_offset, range.end_offset.min(length));
+            let (line, _) = position(&lines, range.start_offset);
+            fn_map.insert(
+                i.to_string(),
+                json!({ "name": name, "decl": loc, "loc": loc, "line": line }),
+            );
+            f.insert(i.to_string(), json!(range.count));
+        }
+
+        let (mut branch_map, mut b) = (Map::new(), Map::new());
+        let blocks = script
+            .functions
+            .iter()
+            .filter(|x| x.is_block_coverage)
+            .flat_map(|x| x.ranges.iter().skip(1));
+        for (i, range) in blocks.enumerate() {
+            let loc = location(&lines, range.start_offset, range.end_offset.min(length));
+            let (line, _) = position(&lines, range.start_offset);
+            branch_map.insert(
+                i.to_string(),
+                json!({ "type": "branch", "line": line, "loc": loc, "locations": [loc] }),
+            );
+            b.insert(i.to_string(), json!([range.count]));
+        }
+
+        files.insert(
+            script.url.clone(),
+            json!({
+                "path": script.url,
+                "statementMap": statement_map,
+                "s": s,
+                "fnMap": fn_map,
+                "f": f,
+                "branchMap": branch_map,
+                "b": b,
+            }),
+        );
+    }
+    Value::Object(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coverage_reports() {
+        use crate::coverage;
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        let (_, context) = runtime.create_context();
+        let isolate = runtime.isolate();
+        let mut hs = v8::HandleScope::new(isolate);
+        let scope = hs.enter();
+        let context = context.get(scope).unwrap();
+        let source = "function add(a, b) {\n  if (a > 0) {\n    return a + b;\n  }\n  return 0;\n}\n\nadd(1, 2);\nadd(2, 3);\n[1].map((x) => x);\n";
+        crate::compile_only(scope, context, source, "plugin.js").unwrap();
+        // as taken with `Profiler.takePreciseCoverage` after running it
+        let coverage = coverage::parse(
+            r#"{"result":[{"scriptId":"110","url":"plugin.js","functions":[
+                {"functionName":"","ranges":[{"startOffset":0,"endOffset":114,"count":1}],"isBlockCoverage":true},
+                {"functionName":"add","ranges":[{"startOffset":0,"endOffset":71,"count":2},{"startOffset":57,"endOffset":70,"count":0}],"isBlockCoverage":true},
+                {"functionName":"","ranges":[{"startOffset":103,"endOffset":111,"count":1}],"isBlockCoverage":true}
+            ]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            coverage::line_counts(&coverage[0], source),
+            vec![
+                (1, 2),
+                (2, 2),
+                (3, 2),
+                (4, 2),
+                (5, 0),
+                (6, 2),
+                (8, 1),
+                (9, 1),
+                (10, 1)
+            ]
+        );
+        let lcov = coverage::to_lcov(scope, &coverage);
+        assert!(lcov.starts_with("TN:\nSF:plugin.js\nFN:1,add\nFN:10,(anonymous_1)\n"));
+        assert!(lcov.ends_with("DA:10,1\nLF:9\nLH:8\nend_of_record\n"));
+        let istanbul = coverage::to_istanbul(scope, &coverage);
+        let file = &istanbul["plugin.js"];
+        assert_eq!(file["f"], serde_json::json!({ "0": 2, "1": 1 }));
+        assert_eq!(file["b"], serde_json::json!({ "0": [0] }));
+        assert_eq!(file["s"]["4"], serde_json::json!(0));
+        assert_eq!(
+            file["fnMap"]["0"]["loc"]["end"]["line"],
+            serde_json::json!(6)
+        );
+    }
+}
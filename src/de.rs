@@ -0,0 +1,403 @@
+//! Deserialization of `FFIObject`s from `serde_json::Value` that reports
+//! where in the value it failed, i.e. `value.items[3].price: invalid type:
+//! string "x", expected f64`.
+
+use crate::RenamePolicy;
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde_json::{Map, Number, Value};
+use std::fmt;
+
+/// The root of error paths, which also names the value in other conversion
+/// errors, such as circular references.
+pub(crate) const ROOT: &str = "value";
+
+/// Deserialize a `T` from `value`, whose object keys were renamed from JS
+/// with `policy`. Paths in errors use the JS names of keys.
+pub(crate) fn from_value<T: DeserializeOwned>(
+    value: Value,
+    policy: RenamePolicy,
+) -> Result<T, Error> {
+    T::deserialize(ValueDeserializer {
+        value,
+        path: ROOT.to_string(),
+        policy,
+    })
+}
+
+/// Prefix `message`, the error of converting the `segment` of a value, i.e.
+/// `[3]`, with its path, joining it with the path already in `message`.
+pub(crate) fn at_path(segment: &str, message: &str) -> String {
+    match message.strip_prefix(ROOT) {
+        Some(rest) if rest.starts_with(|c: char| c == '.' || c == '[' || c == ':') => {
+            format!("{}{}{}", ROOT, segment, rest)
+        }
+        _ => format!("{}{}: {}", ROOT, segment, message),
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Error {
+    path: Option<String>,
+    message: String,
+}
+
+impl Error {
+    fn at(mut self, path: &str) -> Error {
+        if self.path.is_none() {
+            self.path = Some(path.to_string());
+        }
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}: {}", path, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Error {
+        Error {
+            path: None,
+            message: message.to_string(),
+        }
+    }
+}
+
+struct ValueDeserializer {
+    value: Value,
+    path: String,
+    policy: RenamePolicy,
+}
+
+struct Parent {
+    path: String,
+    policy: RenamePolicy,
+}
+
+impl Parent {
+    fn child(&self, value: Value, segment: String) -> ValueDeserializer {
+        ValueDeserializer {
+            value,
+            path: format!("{}{}", self.path, segment),
+            policy: self.policy,
+        }
+    }
+
+    fn key_segment(&self, key: &str) -> String {
+        format!(".{}", self.policy.to_js(key))
+    }
+}
+
+fn visit_number<'de, V: Visitor<'de>>(number: Number, visitor: V) -> Result<V::Value, Error> {
+    if let Some(value) = number.as_u64() {
+        visitor.visit_u64(value)
+    } else if let Some(value) = number.as_i64() {
+        visitor.visit_i64(value)
+    } else {
+        visitor.visit_f64(number.as_f64().unwrap_or(0.0))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let path = self.path.clone();
+        let parent = Parent {
+            path: self.path,
+            policy: self.policy,
+        };
+        match self.value {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(value) => visitor.visit_bool(value),
+            Value::Number(value) => visit_number(value, visitor),
+            Value::String(value) => visitor.visit_string(value),
+            Value::Array(values) => {
+                let len = values.len();
+                let mut seq = SeqDeserializer {
+                    parent,
+                    iter: values.into_iter().enumerate(),
+                };
+                visitor
+                    .visit_seq(&mut seq)
+                    .and_then(|value| match seq.iter.len() {
+                        0 => Ok(value),
+                        _ => Err(de::Error::invalid_length(len, &"fewer elements in array")),
+                    })
+            }
+            Value::Object(values) => visitor.visit_map(MapDeserializer {
+                parent,
+                iter: values.into_iter(),
+                value: None,
+            }),
+        }
+        .map_err(|e| e.at(&path))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let path = self.path.clone();
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+        .map_err(|e| e.at(&path))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let path = self.path.clone();
+        visitor.visit_newtype_struct(self).map_err(|e| e.at(&path))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let path = self.path.clone();
+        let parent = Parent {
+            path: self.path,
+            policy: self.policy,
+        };
+        let (variant, value) = match self.value {
+            Value::String(variant) => (variant, None),
+            Value::Object(map) => {
+                let mut iter = map.into_iter();
+                let (variant, value) = match (iter.next(), iter.next()) {
+                    (Some(entry), None) => entry,
+                    _ => {
+                        return Err(de::Error::invalid_value(
+                            de::Unexpected::Map,
+                            &"map with a single key",
+                        ))
+                        .map_err(|e: Error| e.at(&path))
+                    }
+                };
+                let segment = parent.key_segment(&variant);
+                let value = parent.child(value, segment);
+                (variant, Some(value))
+            }
+            other => {
+                return Err(de::Error::invalid_type(
+                    unexpected(&other),
+                    &"string or map",
+                ))
+                .map_err(|e: Error| e.at(&path))
+            }
+        };
+        visitor
+            .visit_enum(EnumDeserializer { variant, value })
+            .map_err(|e| e.at(&path))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+fn unexpected(value: &Value) -> de::Unexpected<'_> {
+    match value {
+        Value::Null => de::Unexpected::Unit,
+        Value::Bool(value) => de::Unexpected::Bool(*value),
+        Value::Number(_) => de::Unexpected::Other("number"),
+        Value::String(value) => de::Unexpected::Str(value),
+        Value::Array(_) => de::Unexpected::Seq,
+        Value::Object(_) => de::Unexpected::Map,
+    }
+}
+
+struct SeqDeserializer {
+    parent: Parent,
+    iter: std::iter::Enumerate<std::vec::IntoIter<Value>>,
+}
+
+impl<'de> de::SeqAccess<'de> for SeqDeserializer {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.iter.next() {
+            Some((i, value)) => seed
+                .deserialize(self.parent.child(value, format!("[{}]", i)))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct MapDeserializer {
+    parent: Parent,
+    iter: <Map<String, Value> as IntoIterator>::IntoIter,
+    value: Option<(String, Value)>,
+}
+
+impl<'de> de::MapAccess<'de> for MapDeserializer {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                let segment = self.parent.key_segment(&key);
+                self.value = Some((segment, value));
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.value.take() {
+            Some((segment, value)) => seed.deserialize(self.parent.child(value, segment)),
+            None => Err(de::Error::custom("value is missing")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    value: Option<ValueDeserializer>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer {
+    type Error = Error;
+    type Variant = VariantDeserializer;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantDeserializer), Error> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, VariantDeserializer { value: self.value }))
+    }
+}
+
+struct VariantDeserializer {
+    value: Option<ValueDeserializer>,
+}
+
+impl<'de> de::VariantAccess<'de> for VariantDeserializer {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.value {
+            Some(value) => de::Deserialize::deserialize(value),
+            None => Ok(()),
+        }
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        match self.value {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::Error::invalid_type(
+                de::Unexpected::UnitVariant,
+                &"newtype variant",
+            )),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Some(value) => de::Deserializer::deserialize_any(value, visitor),
+            None => Err(de::Error::invalid_type(
+                de::Unexpected::UnitVariant,
+                &"tuple variant",
+            )),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.value {
+            Some(value) => de::Deserializer::deserialize_any(value, visitor),
+            None => Err(de::Error::invalid_type(
+                de::Unexpected::UnitVariant,
+                &"struct variant",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Item {
+        unit_price: f64,
+        tags: Option<Vec<String>>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Order {
+        items: Vec<Item>,
+    }
+
+    #[test]
+    fn error_paths() {
+        let order = serde_json::json!({
+            "items": [
+                { "unit_price": 1.5, "tags": null },
+                { "unit_price": 2.0, "tags": ["a", 3] },
+            ],
+        });
+        let error = from_value::<Order>(order, RenamePolicy::CamelCase).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "value.items[1].tags[1]: invalid type: integer `3`, expected a string"
+        );
+        let order = serde_json::json!({ "items": [{ "unit_price": "1" }] });
+        let error = from_value::<Order>(order, RenamePolicy::CamelCase).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "value.items[0].unitPrice: invalid type: string \"1\", expected f64"
+        );
+        let order = serde_json::json!({ "items": [{}] });
+        let error = from_value::<Order>(order, RenamePolicy::Keep).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "value.items[0]: missing field `unit_price`"
+        );
+        let ok = from_value::<Order>(serde_json::json!({ "items": [] }), RenamePolicy::Keep);
+        assert!(ok.is_ok());
+    }
+
+    #[test]
+    fn joined_paths() {
+        assert_eq!(at_path("[1]", "value.x: bad"), "value[1].x: bad");
+        assert_eq!(at_path("[1]", "value: bad"), "value[1]: bad");
+        assert_eq!(at_path("[1]", "value has more"), "value[1]: value has more");
+        assert_eq!(at_path("[0]", "bad"), "value[0]: bad");
+    }
+}
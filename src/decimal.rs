@@ -0,0 +1,169 @@
+//! `FFICompat` conversions for `rust_decimal::Decimal` that never pass
+//! through `f64`. `Decimal` converts to and from a JS string, while
+//! `DecimalObject` uses a `{ s, e, m }` object of sign, base 10 exponent
+//! and mantissa digits.
+
+use crate::util::*;
+use crate::FFICompat;
+use crate::FFIError;
+use rust_decimal::Decimal;
+use rusty_v8 as v8;
+use std::convert::TryInto;
+use std::ops::Deref;
+use std::str::FromStr;
+
+/// Split `value` into its sign, base 10 exponent and mantissa digits.
+fn decimal_parts(value: &Decimal) -> (i32, i32, String) {
+    let sign = if value.is_sign_negative() && !value.is_zero() {
+        -1
+    } else {
+        1
+    };
+    let mantissa: String = value
+        .to_string()
+        .chars()
+        .filter(|c| c.is_ascii_digit())
+        .collect();
+    let mantissa = mantissa.trim_start_matches('0');
+    let mantissa = if mantissa.is_empty() { "0" } else { mantissa };
+    (sign, -(value.scale() as i32), mantissa.to_string())
+}
+
+fn decimal_from_parts(sign: i32, exponent: i32, mantissa: &str) -> Option<Decimal> {
+    if mantissa.is_empty() || !mantissa.bytes().all(|x| x.is_ascii_digit()) || exponent.abs() > 28 {
+        return None;
+    }
+    let mut digits = mantissa.to_string();
+    if exponent >= 0 {
+        digits.extend(std::iter::repeat('0').take(exponent as usize));
+    } else {
+        let scale = (-exponent) as usize;
+        if digits.len() <= scale {
+            digits = format!("{}{}", "0".repeat(scale + 1 - digits.len()), digits);
+        }
+        digits.insert(digits.len() - scale, '.');
+    }
+    if sign < 0 {
+        digits.insert(0, '-');
+    }
+    Decimal::from_str(&digits).ok()
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for Decimal {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        let string: v8::Local<v8::String> = value.try_into().map_err(|_| {
+            FFIError::TypeError(format!(
+                "invalid type for argument in ffi call, expected decimal string, received {}",
+                type_of(value)
+            ))
+        })?;
+        let string = string.to_rust_string_lossy(scope);
+        Decimal::from_str(string.trim())
+            .map_err(|_| FFIError::RangeError(format!("invalid decimal: {:?}", string)))
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        Ok(make_str(scope, &self.to_string()))
+    }
+}
+
+/// `DecimalObject` is a `Decimal` represented in JS as `{ s, e, m }`, where
+/// `s` is `1` or `-1`, `e` the base 10 exponent and `m` the mantissa digits
+/// as a string, so that the value is `s * m * 10^e`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct DecimalObject(pub Decimal);
+
+impl Deref for DecimalObject {
+    type Target = Decimal;
+
+    fn deref(&self) -> &Decimal {
+        &self.0
+    }
+}
+
+impl From<Decimal> for DecimalObject {
+    fn from(value: Decimal) -> DecimalObject {
+        DecimalObject(value)
+    }
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for DecimalObject {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        let object: v8::Local<v8::Object> = value.try_into().map_err(|_| {
+            FFIError::TypeError(format!(
+                "invalid type for argument in ffi call, expected decimal object, received {}",
+                type_of(value)
+            ))
+        })?;
+        let invalid =
+            || FFIError::TypeError("invalid decimal object, expected { s, e, m }".to_string());
+        let key = make_str(scope, "s");
+        let sign = object.get(scope, context, key).ok_or_else(invalid)?;
+        let sign = i32::from_value(sign, scope, context).map_err(|_| invalid())?;
+        let key = make_str(scope, "e");
+        let exponent = object.get(scope, context, key).ok_or_else(invalid)?;
+        let exponent = i32::from_value(exponent, scope, context).map_err(|_| invalid())?;
+        let key = make_str(scope, "m");
+        let mantissa = object.get(scope, context, key).ok_or_else(invalid)?;
+        let mantissa = String::from_value(mantissa, scope, context).map_err(|_| invalid())?;
+        decimal_from_parts(sign, exponent, &mantissa)
+            .map(DecimalObject)
+            .ok_or_else(|| FFIError::RangeError("decimal object out of range".to_string()))
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        let (sign, exponent, mantissa) = decimal_parts(&self.0);
+        let object = v8::Object::new(scope);
+        let key = make_str(scope, "s");
+        let value = make_num(scope, sign as f64);
+        object.set(context, key, value);
+        let key = make_str(scope, "e");
+        let value = make_num(scope, exponent as f64);
+        object.set(context, key, value);
+        let key = make_str(scope, "m");
+        let value = make_str(scope, &mantissa);
+        object.set(context, key, value);
+        Ok(object.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parts_roundtrip() {
+        for value in &["1.50", "-0.001", "0", "123456789012345678901234.5678", "-7"] {
+            let value = Decimal::from_str(value).unwrap();
+            let (sign, exponent, mantissa) = decimal_parts(&value);
+            let parsed = decimal_from_parts(sign, exponent, &mantissa).unwrap();
+            assert_eq!(parsed, value);
+            assert_eq!(parsed.scale(), value.scale());
+        }
+        assert_eq!(
+            decimal_from_parts(1, 2, "15"),
+            Some(Decimal::from_str("1500").unwrap())
+        );
+        assert_eq!(decimal_from_parts(1, 0, "1e5"), None);
+    }
+}
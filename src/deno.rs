@@ -0,0 +1,155 @@
+//! Registration of `#[v8_ffi(deno_op)]` functions as JSON ops in a deno_core
+//! `JsRuntime`, so one set of annotated functions serves both runtimes.
+//!
+//! deno_core runs on its own build of V8, so op arguments and return values
+//! are converted through serde rather than `FFICompat`: arguments must be
+//! `DeserializeOwned` and return values `OpReturn`, which covers the
+//! primitives, `String`, `Vec`, `Option`, `Result` and `FFIObject` types.
+//! From JS, arguments are passed as an array:
+//! `Deno.core.jsonOpSync("read", [path, length])`.
+
+use crate::FFIError;
+use crate::FFIObject;
+use deno_core::error::{generic_error, range_error, type_error, AnyError};
+use deno_core::JsRuntime;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+
+#[doc(hidden)]
+pub use serde_json::Value;
+
+/// An op generated by `#[v8_ffi(deno_op)]`, see `load_deno_op!`.
+pub type JsonOp = fn(Value) -> Result<Value, FFIError>;
+
+/// Register `op` as the synchronous JSON op `name` of `runtime`.
+pub fn register_op(runtime: &mut JsRuntime, name: &str, op: JsonOp) {
+    runtime.register_op(
+        name,
+        deno_core::json_op_sync(move |_state, args, _zero_copy| op(args).map_err(to_any_error)),
+    );
+}
+
+fn to_any_error(error: FFIError) -> AnyError {
+    match error {
+        FFIError::TypeError(message) => type_error(message),
+        FFIError::RangeError(message) => range_error(message),
+        FFIError::Error(message) => generic_error(message),
+    }
+}
+
+/// Read argument `index` of an op called with `args`.
+#[doc(hidden)]
+pub fn op_arg<T: DeserializeOwned>(args: &Value, index: usize) -> Result<T, FFIError> {
+    let arg = match args {
+        Value::Array(args) => args.get(index).cloned().unwrap_or(Value::Null),
+        args if index == 0 => args.clone(),
+        _ => Value::Null,
+    };
+    serde_json::from_value(arg)
+        .map_err(|e| FFIError::TypeError(format!("invalid argument {} in op call: {}", index, e)))
+}
+
+fn to_op_value<T: Serialize>(value: T) -> Result<Value, FFIError> {
+    serde_json::to_value(value).map_err(|e| FFIError::TypeError(format!("{:?}", e)))
+}
+
+/// Conversion of a `#[v8_ffi(deno_op)]` return value to the op result.
+pub trait OpReturn {
+    fn into_op_value(self) -> Result<Value, FFIError>;
+}
+
+impl<T: Serialize + DeserializeOwned + FFIObject> OpReturn for T {
+    fn into_op_value(self) -> Result<Value, FFIError> {
+        to_op_value(self)
+    }
+}
+
+macro_rules! op_return_serialize {
+    ($($ty:ty),*) => {
+        $(
+            impl OpReturn for $ty {
+                fn into_op_value(self) -> Result<Value, FFIError> {
+                    to_op_value(self)
+                }
+            }
+        )*
+    };
+}
+
+op_return_serialize!(
+    (),
+    bool,
+    String,
+    f64,
+    f32,
+    i64,
+    u64,
+    i32,
+    u32,
+    i16,
+    u16,
+    i8,
+    u8
+);
+
+impl<T: OpReturn> OpReturn for Option<T> {
+    fn into_op_value(self) -> Result<Value, FFIError> {
+        match self {
+            Some(value) => value.into_op_value(),
+            None => Ok(Value::Null),
+        }
+    }
+}
+
+impl<T: OpReturn> OpReturn for Vec<T> {
+    fn into_op_value(self) -> Result<Value, FFIError> {
+        self.into_iter()
+            .map(OpReturn::into_op_value)
+            .collect::<Result<Vec<Value>, FFIError>>()
+            .map(Value::Array)
+    }
+}
+
+impl<T: OpReturn, E: Debug + 'static> OpReturn for Result<T, E> {
+    fn into_op_value(self) -> Result<Value, FFIError> {
+        match self {
+            Ok(value) => value.into_op_value(),
+            Err(e) => match (&e as &dyn std::any::Any).downcast_ref::<FFIError>() {
+                Some(e) => Err(e.clone()),
+                None => Err(FFIError::Error(format!("{:?}", e))),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusty_v8_helper_derive::v8_ffi;
+
+    #[test]
+    fn op_args() {
+        let args = serde_json::json!(["a", 2]);
+        assert_eq!(op_arg::<String>(&args, 0), Ok("a".to_string()));
+        assert_eq!(op_arg::<u32>(&args, 1), Ok(2));
+        assert_eq!(op_arg::<Option<u32>>(&args, 2), Ok(None));
+        assert!(op_arg::<u32>(&args, 0).is_err());
+        let ret: Result<Vec<u32>, FFIError> = Ok(vec![1, 2]);
+        assert_eq!(ret.into_op_value(), Ok(serde_json::json!([1, 2])));
+    }
+
+    #[cfg(feature = "deno")]
+    #[v8_ffi(deno_op)]
+    fn test_ffi_deno_op(count: u32, text: &str) -> Result<u32, crate::FFIError> {
+        Ok(count + text.len() as u32)
+    }
+
+    #[cfg(feature = "deno")]
+    #[test]
+    fn deno_op() {
+        let op = load_deno_op!(test_ffi_deno_op);
+        assert_eq!(op(serde_json::json!([2, "abc"])), Ok(serde_json::json!(5)));
+        assert!(op(serde_json::json!(["x"])).is_err());
+    }
+}
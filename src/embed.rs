@@ -0,0 +1,421 @@
+//! `Embed`, the whole embedding of V8 in one expression, for tools that
+//! only need to run some JS with a few natives, the `embed!` macro
+//! building one, and `run_file`, running a JS file as a program.
+
+use crate::executor::{run_event_loop, set_executor, LocalExecutor};
+use crate::extensions::Extension;
+use crate::ffi_fn::{load_ffi_fn, FfiFn};
+use crate::script::compile_only;
+use crate::util::make_str;
+use crate::{FFICompat, FFIError, JsError, Runtime};
+use rusty_v8 as v8;
+use rusty_v8_helper_derive::v8_ffi;
+use std::path::Path;
+use std::sync::Once;
+use v8::Global;
+
+/// Initialize V8 with the default platform, once per process. Further
+/// calls do nothing, so every embedder can call it.
+pub fn initialize_v8() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let platform = v8::new_default_platform();
+        v8::V8::initialize_platform(platform);
+        v8::V8::initialize();
+    });
+}
+
+type Installer = Box<dyn Fn(&mut v8::Isolate, &Global<v8::Context>) -> Result<(), FFIError>>;
+
+/// `Embed` runs a script in a new isolate and context with the given
+/// `v8_ffi` fns and extensions installed, and converts its completion
+/// value. V8 is initialized as needed, futures and timers run on a
+/// `LocalExecutor`, and the isolate is torn down once the script is done,
+/// see `Runtime`.
+///
+/// ```ignore
+/// let total: f64 = Embed::new()
+///     .function::<read_file>()
+///     .extension(TimersExtension)
+///     .eval_file("sum.js")?;
+/// ```
+#[derive(Default)]
+pub struct Embed {
+    installers: Vec<Installer>,
+}
+
+impl Embed {
+    pub fn new() -> Embed {
+        Embed::default()
+    }
+
+    /// Install the `v8_ffi` fn `F` as the global named like the Rust fn.
+    pub fn function<F: FfiFn>(self) -> Embed {
+        self.function_as::<F>(F::NAME)
+    }
+
+    /// Install the `v8_ffi` fn `F` as the global `name`.
+    pub fn function_as<F: FfiFn>(mut self, name: &str) -> Embed {
+        let name = name.to_string();
+        self.installers.push(Box::new(move |isolate, context| {
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            let context = context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            let function = load_ffi_fn::<F>(scope, context);
+            let key = make_str(scope, &name);
+            context.global(scope).set(context, key, function.into());
+            Ok(())
+        }));
+        self
+    }
+
+    /// Install `extension`, after the functions and extensions before it.
+    pub fn extension(mut self, extension: impl Extension + 'static) -> Embed {
+        self.installers.push(Box::new(move |isolate, context| {
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            let context = context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            extension.install(scope, context)
+        }));
+        self
+    }
+
+    /// Run `source` as a script named `origin`, drive the event loop until
+    /// idle, i.e. until its timers have fired, and convert the script's
+    /// completion value.
+    pub fn eval<T>(self, source: &str, origin: &str) -> Result<T, JsError>
+    where
+        T: for<'sc, 'c> FFICompat<'sc, 'c>,
+    {
+        self.with_context(|isolate, executor, context| {
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            let context = context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            let value = compile_only(scope, context, source, origin)?.run(scope, context)?;
+            executor.block_on(run_event_loop(scope.isolate()));
+            T::from_value(value, scope, context).map_err(|e| {
+                JsError::new(format!(
+                    "failed to convert the result of {}: {:?}",
+                    origin, e
+                ))
+            })
+        })
+    }
+
+    /// Run the script at `path`, named by its path, as `eval` does.
+    pub fn eval_file<T>(self, path: impl AsRef<Path>) -> Result<T, JsError>
+    where
+        T: for<'sc, 'c> FFICompat<'sc, 'c>,
+    {
+        let path = path.as_ref();
+        self.eval(&read_source(path)?, &path.to_string_lossy())
+    }
+
+    /// Run the file at `path` as a program, with a node-style global
+    /// `process`: `process.argv` is the current executable, `path`, then
+    /// `args`, and the program ends with `process.exit(code)` or once the
+    /// event loop is idle, with `process.exitCode`.
+    ///
+    /// A leading `#!` line is ignored. `.mjs` files are run as ES modules,
+    /// importing modules relative to their directory, and other files as
+    /// scripts. An uncaught error is returned as is, rather than as a
+    /// failed `ExitStatus`.
+    pub fn run_file(
+        self,
+        path: impl AsRef<Path>,
+        args: Vec<String>,
+    ) -> Result<ExitStatus, JsError> {
+        let path = path.as_ref();
+        let source = read_source(path)?;
+        let source = strip_shebang(&source);
+        self.with_context(|isolate, executor, context| {
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            let context = context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            install_process(scope, context, path, args).map_err(ffi_error)?;
+            let completed = if path.extension().map(|x| x == "mjs").unwrap_or(false) {
+                let entry = path
+                    .file_name()
+                    .map(|x| x.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let root = path.parent().unwrap_or_else(|| Path::new("."));
+                set_module_resolver(
+                    scope,
+                    EntryResolver {
+                        modules: FsModuleResolver::new(root),
+                        entry: entry.clone(),
+                        source: source.to_string(),
+                    },
+                );
+                run_module(scope, context, &entry)
+                    .map(|_| ())
+                    .map_err(ffi_error)
+            } else {
+                compile_only(scope, context, source, &path.to_string_lossy())
+                    .and_then(|script| script.run(scope, context))
+                    .map(|_| ())
+            };
+            if let Some(status) = take_exit(scope) {
+                return Ok(status);
+            }
+            completed?;
+            executor.block_on(run_event_loop(scope.isolate()));
+            if let Some(status) = take_exit(scope) {
+                return Ok(status);
+            }
+            let code = run_script(
+                scope,
+                context,
+                "(globalThis.process && process.exitCode) | 0",
+            )
+            .and_then(|code| i32::from_value(code, scope, context).ok())
+            .unwrap_or(0);
+            Ok(ExitStatus(code))
+        })
+    }
+
+    /// Run `f` with a new isolate and context, with the functions and
+    /// extensions installed and a `LocalExecutor` set, tearing them down
+    /// after.
+    fn with_context<R>(
+        self,
+        f: impl FnOnce(&mut v8::Isolate, &LocalExecutor, &Global<v8::Context>) -> Result<R, JsError>,
+    ) -> Result<R, JsError> {
+        initialize_v8();
+        let mut runtime = Runtime::new();
+        let executor = LocalExecutor::new();
+        set_executor(runtime.isolate(), executor.clone());
+        let (_, mut context) = runtime.create_context();
+        let mut result = Ok(());
+        for install in &self.installers {
+            result = install(runtime.isolate(), &context).map_err(ffi_error);
+            if result.is_err() {
+                break;
+            }
+        }
+        let result = result.and_then(|()| f(runtime.isolate(), &executor, &context));
+        context.reset(runtime.isolate());
+        result
+    }
+}
+
+fn ffi_error(error: FFIError) -> JsError {
+    JsError::new(error.to_string())
+}
+
+fn read_source(path: &Path) -> Result<String, JsError> {
+    std::fs::read_to_string(path)
+        .map_err(|e| JsError::new(format!("failed to read {}: {}", path.display(), e)))
+}
+
+/// Blank out a leading `#!` line, keeping the line numbers of the rest.
+fn strip_shebang(source: &str) -> &str {
+    if !source.starts_with("#!") {
+        return source;
+    }
+    match source.find('\n') {
+        Some(end) => &source[end..],
+        None => "",
+    }
+}
+
+/// The status a program run with `run_file` exited with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExitStatus(i32);
+
+impl ExitStatus {
+    pub fn code(&self) -> i32 {
+        self.0
+    }
+
+    pub fn success(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// Run the file at `path` as a program with `args`, see
+/// `Embed::run_file`.
+///
+/// ```ignore
+/// let args = std::env::args().skip(2).collect();
+/// let status = run_file(&script, args)?;
+/// std::process::exit(status.code());
+/// ```
+pub fn run_file(path: impl AsRef<Path>, args: Vec<String>) -> Result<ExitStatus, JsError> {
+    Embed::new().run_file(path, args)
+}
+
+/// Serves the entry module from its source with the shebang stripped, and
+/// the modules it imports from disk.
+struct EntryResolver {
+    modules: FsModuleResolver,
+    entry: String,
+    source: String,
+}
+
+impl ModuleResolver for EntryResolver {
+    fn load(&self, url: &str) -> Result<String, String> {
+        if url == self.entry {
+            Ok(self.source.clone())
+        } else {
+            self.modules.load(url)
+        }
+    }
+}
+
+/// The code `process.exit` was called with.
+struct ExitRequested(i32);
+
+#[v8_ffi(scoped)]
+fn process_exit<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    _context: v8::Local<'c, v8::Context>,
+    code: i32,
+) {
+    set_isolate_slot(scope, ExitRequested(code));
+    scope.isolate().terminate_execution();
+}
+
+/// The status of a `process.exit`, if the program called it, letting the
+/// isolate run JS again.
+fn take_exit(scope: &mut impl v8::InIsolate) -> Option<ExitStatus> {
+    let exit = remove_isolate_slot::<ExitRequested>(scope)?;
+    scope.isolate().cancel_terminate_execution();
+    Some(ExitStatus(exit.0))
+}
+
+const PROCESS_BOOTSTRAP: &str = r#"
+(function (argv, exit) {
+    const process = this.process || (this.process = {});
+    process.argv = argv;
+    process.exitCode = undefined;
+    process.exit = (code) => {
+        exit((code === undefined ? process.exitCode : code) | 0);
+    };
+})
+"#;
+
+fn install_process<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    path: &Path,
+    args: Vec<String>,
+) -> Result<(), FFIError> {
+    let program = std::env::current_exe()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut argv = vec![program, path.to_string_lossy().into_owned()];
+    argv.extend(args);
+    let argv = argv.to_value(scope, context)?;
+    let exit = load_v8_ffi!(process_exit, scope, context);
+    run_bootstrap(scope, context, PROCESS_BOOTSTRAP, &[argv, exit])
+}
+
+/// Run a script with `v8_ffi` fns and extensions installed, see `Embed`.
+/// The script is given as `source: ".."` or `file: ".."`, and the result
+/// is converted to the type it is assigned to.
+///
+/// ```ignore
+/// let total: f64 = embed!(
+///     file: "sum.js",
+///     functions: [read_file, util::parse_csv],
+///     extensions: [TimersExtension],
+/// )?;
+/// ```
+#[macro_export]
+macro_rules! embed {
+    (@run source: $source:expr, $embed:expr) => {
+        $embed.eval($source, "<embed>")
+    };
+    (@run file: $path:expr, $embed:expr) => {
+        $embed.eval_file($path)
+    };
+    ($kind:ident: $script:expr
+        $(, functions: [$($function:path),* $(,)?])?
+        $(, extensions: [$($extension:expr),* $(,)?])?
+        $(,)?
+    ) => {
+        $crate::embed!(
+            @run $kind: $script,
+            $crate::embed::Embed::new()
+                $($(.function::<$function>())*)?
+                $($(.extension($extension))*)?
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[v8_ffi]
+    fn test_ffi_multiply(a: i32, b: f64) -> f64 {
+        a as f64 * b
+    }
+
+    #[test]
+    fn embed() {
+        let result: Result<f64, _> = crate::embed!(
+            source: "let x = 0; setTimeout(() => x++, 5); test_ffi_multiply(2, 1.5) + x",
+            functions: [test_ffi_multiply],
+            extensions: [crate::extensions::timers::TimersExtension],
+        );
+        assert_eq!(result, Ok(3.0));
+
+        let path = std::env::temp_dir().join("rusty_v8_helper_embed.js");
+        std::fs::write(&path, "[1, 2].map(x => x * 2)").unwrap();
+        let result: Vec<u32> = crate::embed!(file: &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result, vec![2, 4]);
+
+        let result = crate::Embed::new().eval::<u32>("'text'", "main.js");
+        assert!(result.unwrap_err().message.contains("main.js"));
+    }
+
+    #[test]
+    fn run_file() {
+        let dir = std::env::temp_dir().join("rusty_v8_helper_run_file");
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("task.js");
+        std::fs::write(
+            &script,
+            "#!/usr/bin/env task\nprocess.exitCode = process.argv.length + Number(process.argv[2]);",
+        )
+        .unwrap();
+        let status = crate::run_file(&script, vec!["4".to_string()]).unwrap();
+        assert_eq!(status.code(), 7);
+
+        std::fs::write(
+            &script,
+            "setTimeout(() => process.exit(3), 1); setTimeout(() => { throw 1 }, 50);",
+        )
+        .unwrap();
+        let status = crate::Embed::new()
+            .extension(crate::extensions::timers::TimersExtension)
+            .run_file(&script, vec![])
+            .unwrap();
+        assert_eq!(status.code(), 3);
+
+        std::fs::write(dir.join("lib.mjs"), "export const ok = 2;").unwrap();
+        let module = dir.join("main.mjs");
+        std::fs::write(
+            &module,
+            "#!/usr/bin/env task\nimport { ok } from './lib.mjs';\nprocess.exit(ok - 2);\nundefined();",
+        )
+        .unwrap();
+        assert!(crate::run_file(&module, vec![]).unwrap().success());
+
+        std::fs::write(&script, "\nundefined();").unwrap();
+        let error = crate::run_file(&script, vec![]).unwrap_err();
+        assert_eq!(error.line, Some(2));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,189 @@
+use crate::sources::{excerpt, source_of};
+use rusty_v8 as v8;
+use std::fmt;
+
+/// `FFIError` is an error raised at the FFI boundary that knows which JS
+/// error constructor it should be thrown as.
+///
+/// Any `FFICompat::E` that is an `FFIError` is thrown by the generated
+/// `v8_ffi` wrappers as a real JS `Error`, `TypeError` or `RangeError`,
+/// while other error types keep being thrown as their `Debug` string.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FFIError {
+    Error(String),
+    TypeError(String),
+    RangeError(String),
+}
+
+impl FFIError {
+    /// The message the JS error will be constructed with.
+    pub fn message(&self) -> &str {
+        match self {
+            FFIError::Error(message) => message,
+            FFIError::TypeError(message) => message,
+            FFIError::RangeError(message) => message,
+        }
+    }
+
+    /// Construct the JS error object for this error.
+    pub fn to_exception<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+    ) -> v8::Local<'sc, v8::Value> {
+        let message = v8::String::new(scope, self.message()).unwrap();
+        match self {
+            FFIError::Error(_) => v8::Exception::error(scope, message),
+            FFIError::TypeError(_) => v8::Exception::type_error(scope, message),
+            FFIError::RangeError(_) => v8::Exception::range_error(scope, message),
+        }
+    }
+
+    /// Throw this error as an exception in the current isolate.
+    pub fn throw<'sc>(&self, scope: &mut impl v8::ToLocal<'sc>) {
+        let exception = self.to_exception(scope);
+        scope.isolate().throw_exception(exception);
+    }
+}
+
+impl fmt::Display for FFIError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for FFIError {}
+
+impl From<String> for FFIError {
+    fn from(message: String) -> FFIError {
+        FFIError::Error(message)
+    }
+}
+
+/// `JsError` is an exception caught from running or compiling JS, with the
+/// location V8 reported for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsError {
+    pub message: String,
+    pub resource_name: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub source_line: Option<String>,
+    pub stack: Option<String>,
+    /// The offending line with the lines before it and a caret under the
+    /// offending code, see `render`.
+    pub excerpt: Option<String>,
+}
+
+impl JsError {
+    /// A `JsError` with no location information.
+    pub fn new(message: impl Into<String>) -> JsError {
+        JsError {
+            message: message.into(),
+            resource_name: None,
+            line: None,
+            column: None,
+            source_line: None,
+            stack: None,
+            excerpt: None,
+        }
+    }
+
+    /// Build a `JsError` from the exception caught by `try_catch`.
+    pub fn from_try_catch<'sc>(
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+        try_catch: &mut v8::TryCatch,
+    ) -> JsError {
+        let exception = try_catch.exception();
+        let message = match exception {
+            Some(exception) => crate::util::exception_message(scope, exception),
+            None => "unknown exception".to_string(),
+        };
+        let stack = try_catch
+            .stack_trace(scope, context)
+            .and_then(|x| x.to_string(scope))
+            .map(|x| x.to_rust_string_lossy(scope));
+        let mut error = JsError::new(message);
+        error.stack = stack;
+        if let Some(details) = try_catch.message() {
+            error.resource_name = details
+                .get_script_resource_name(scope)
+                .and_then(|x| x.to_string(scope))
+                .map(|x| x.to_rust_string_lossy(scope));
+            error.line = details.get_line_number(context);
+            error.column = Some(details.get_start_column());
+            error.source_line = details
+                .get_source_line(scope, context)
+                .map(|x| x.to_rust_string_lossy(scope));
+            if let Some(line) = error.line {
+                let start = details.get_start_column();
+                let end = details.get_end_column();
+                // V8 only keeps the offending line of scripts not
+                // compiled through this crate
+                let recorded = error
+                    .resource_name
+                    .as_deref()
+                    .and_then(|name| source_of(scope, name));
+                error.excerpt = match (recorded, &error.source_line) {
+                    (Some(source), _) => excerpt(&source, 1, line, start, end),
+                    (None, Some(source_line)) => excerpt(source_line, line, line, start, end),
+                    (None, None) => None,
+                };
+            }
+        }
+        error
+    }
+
+    /// The error with where it was thrown and an excerpt of the source
+    /// there, like rustc or Node print it:
+    ///
+    /// ```text
+    /// TypeError: x is not a function
+    ///  --> upload.js:2:1
+    ///   |
+    /// 1 | let x = 1;
+    /// 2 | x();
+    ///   | ^^^
+    /// ```
+    pub fn render(&self) -> String {
+        let (line, column) = match (self.line, self.column) {
+            (Some(line), Some(column)) => (line, column),
+            _ => return self.message.clone(),
+        };
+        let resource_name = self.resource_name.as_deref().unwrap_or("<anonymous>");
+        let width = line.to_string().len();
+        let mut out = format!(
+            "{}\n{:w$}--> {}:{}:{}",
+            self.message,
+            "",
+            resource_name,
+            line,
+            column + 1,
+            w = width
+        );
+        if let Some(excerpt) = &self.excerpt {
+            out.push('\n');
+            out.push_str(excerpt);
+        }
+        out
+    }
+}
+
+impl fmt::Display for JsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            let resource_name = self.resource_name.as_deref().unwrap_or("<anonymous>");
+            write!(f, " at {}:{}:{}", resource_name, line, column + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for JsError {}
+
+impl From<JsError> for FFIError {
+    fn from(error: JsError) -> FFIError {
+        FFIError::Error(error.to_string())
+    }
+}
@@ -0,0 +1,207 @@
+//! JS `Error` subclasses mirroring a Rust error enum, so that JS can tell
+//! the errors returned from `v8_ffi` fns apart with `instanceof`.
+
+use crate::util::{call_function, eval_function, isolate_slot, make_str, set_isolate_slot};
+use crate::{FFICompat, FFIError, JsValue};
+use rusty_v8 as v8;
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Display};
+use v8::Global;
+
+const INSTALL_SOURCE: &str = r#"(function (target, global, name, variants) {
+    const key = Symbol.for('rusty_v8_helper.errorClasses');
+    if (!Object.prototype.hasOwnProperty.call(global, key)) {
+        Object.defineProperty(global, key, { value: Object.create(null) });
+    }
+    const define = (Class, name) => {
+        Object.defineProperty(Class, 'name', { value: name });
+        Object.defineProperty(Class.prototype, 'name', { value: name, writable: true, configurable: true });
+        Object.defineProperty(target, name, { value: Class, writable: true, configurable: true });
+        return Class;
+    };
+    const Base = define(class extends Error {
+        constructor(message, fields) {
+            super(message);
+            Object.assign(this, fields);
+        }
+    }, name);
+    const classes = Object.create(null);
+    for (const variant of variants) {
+        classes[variant] = define(class extends Base {}, variant);
+    }
+    global[key][name] = classes;
+})"#;
+
+const CONSTRUCT_SOURCE: &str = r#"(function (global, name, variant, message, fields) {
+    const classes = global[Symbol.for('rusty_v8_helper.errorClasses')];
+    const Class = classes && classes[name] && classes[name][variant];
+    return Class ? new Class(message, fields) : undefined;
+})"#;
+
+/// `JsErrorClass` mirrors a Rust error enum in JS as a class extending
+/// `Error`, named after the enum, and one class per variant extending it.
+/// Derive it with `#[derive(JsErrorClass)]`, which takes the properties
+/// from the variant's fields, and install the classes with
+/// `install_error_classes`.
+///
+/// ```ignore
+/// #[derive(Debug, JsErrorClass)]
+/// enum StoreError {
+///     NotFound { key: String },
+///     Full(u32),
+///     Io(#[js_skip] std::io::Error),
+/// }
+///
+/// // `e instanceof NotFound && e instanceof StoreError && e.key === "a"`
+/// ```
+pub trait JsErrorClass: Display + Debug + Any {
+    /// The name of the class every variant's class extends.
+    const NAME: &'static str;
+
+    /// The names of the variants' classes, in declaration order.
+    fn classes() -> &'static [&'static str];
+
+    /// The name of the class of this error's variant.
+    fn class_name(&self) -> &'static str;
+
+    /// The properties set on the thrown error besides its `message`: named
+    /// fields by name, tuple fields as the array `values`.
+    fn fields(&self) -> Vec<(&'static str, JsValue)>;
+}
+
+/// An error of a `JsErrorClass` type, ready to be thrown.
+struct Instance {
+    name: &'static str,
+    variant: &'static str,
+    message: String,
+    fields: Vec<(&'static str, JsValue)>,
+}
+
+type Describe = fn(&dyn Any) -> Option<Instance>;
+
+fn describe<T: JsErrorClass>(error: &dyn Any) -> Option<Instance> {
+    let error = error.downcast_ref::<T>()?;
+    Some(Instance {
+        name: T::NAME,
+        variant: error.class_name(),
+        message: error.to_string(),
+        fields: error.fields(),
+    })
+}
+
+thread_local! {
+    static DESCRIBERS: RefCell<HashMap<TypeId, Describe>> = RefCell::new(HashMap::new());
+}
+
+struct ConstructSlot(Global<v8::Function>);
+
+/// Define the class `T::NAME`, extending `Error`, and a class for each of
+/// its variants, extending it, as properties of `target`. From then on, a
+/// `T` returned as the error of a `Result` from a `v8_ffi` fn called in
+/// `context` is thrown as an instance of its variant's class, with the
+/// `Display` of the error as its message.
+pub fn install_error_classes<'sc, T: JsErrorClass>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    target: v8::Local<v8::Object>,
+) -> Result<(), FFIError> {
+    DESCRIBERS.with(|describers| {
+        describers
+            .borrow_mut()
+            .insert(TypeId::of::<T>(), describe::<T> as Describe)
+    });
+    let install = eval_function(scope, context, INSTALL_SOURCE)?;
+    let global = context.global(scope).into();
+    let name = make_str(scope, T::NAME);
+    let variants = T::classes()
+        .iter()
+        .map(|x| JsValue::from(*x))
+        .collect::<Vec<JsValue>>();
+    let variants = JsValue::Array(variants).to_value(scope, context)?;
+    let undefined = v8::undefined(scope).into();
+    call_function(
+        scope,
+        context,
+        install,
+        undefined,
+        &[target.into(), global, name, variants],
+    )?;
+    Ok(())
+}
+
+/// The error of a `Result` returned to JS. An error whose classes were
+/// installed with `install_error_classes` is thrown as an instance of its
+/// variant's class, any other as its `Debug` string.
+pub struct ReturnedError {
+    debug: String,
+    /// Taken when the error is thrown.
+    instance: RefCell<Option<Instance>>,
+}
+
+impl ReturnedError {
+    pub fn new<E: Debug + Any>(error: &E) -> ReturnedError {
+        let describer =
+            DESCRIBERS.with(|describers| describers.borrow().get(&TypeId::of::<E>()).cloned());
+        ReturnedError {
+            debug: format!("{:?}", error),
+            instance: RefCell::new(describer.and_then(|describe| describe(error))),
+        }
+    }
+
+    /// The instance of the error's class in the current context of `scope`,
+    /// if its classes are installed there.
+    pub(crate) fn to_exception<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+    ) -> Option<v8::Local<'sc, v8::Value>> {
+        let instance = self.instance.borrow_mut().take()?;
+        let context = scope.get_current_context()?;
+        let cached = isolate_slot::<ConstructSlot>(scope).and_then(|slot| slot.0.get(scope));
+        let construct = match cached {
+            Some(construct) => construct,
+            None => {
+                let construct = eval_function(scope, context, CONSTRUCT_SOURCE).ok()?;
+                let global = Global::new_from(scope, construct);
+                set_isolate_slot(scope, ConstructSlot(global));
+                construct
+            }
+        };
+        let fields = instance
+            .fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect();
+        let fields = JsValue::Object(fields).to_value(scope, context).ok()?;
+        let args = [
+            context.global(scope).into(),
+            make_str(scope, instance.name),
+            make_str(scope, instance.variant),
+            make_str(scope, &instance.message),
+            fields,
+        ];
+        let undefined = v8::undefined(scope).into();
+        let exception = call_function(scope, context, construct, undefined, &args).ok()?;
+        if exception.is_undefined() {
+            return None;
+        }
+        Some(exception)
+    }
+}
+
+impl From<String> for ReturnedError {
+    fn from(debug: String) -> ReturnedError {
+        ReturnedError {
+            debug,
+            instance: RefCell::new(None),
+        }
+    }
+}
+
+impl Debug for ReturnedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // as the `String` it replaces
+        Debug::fmt(&self.debug, f)
+    }
+}
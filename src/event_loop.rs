@@ -0,0 +1,207 @@
+use crate::util::isolate_key;
+use rusty_v8 as v8;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+use v8::InIsolate;
+use v8::Isolate;
+
+type LocalTask = Box<dyn FnOnce(&mut Isolate)>;
+type RemoteTask = Box<dyn FnOnce(&mut Isolate) + Send>;
+
+struct EventLoopState {
+    local: RefCell<VecDeque<LocalTask>>,
+    remote_sender: Sender<RemoteTask>,
+    remote_receiver: Receiver<RemoteTask>,
+    outstanding: Cell<usize>,
+    /// Woken when a task is queued, for `poll_until_idle`.
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl EventLoopState {
+    fn new() -> EventLoopState {
+        let (remote_sender, remote_receiver) = channel();
+        EventLoopState {
+            local: RefCell::new(VecDeque::new()),
+            remote_sender,
+            remote_receiver,
+            outstanding: Cell::new(0),
+            waker: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+fn wake(waker: &Mutex<Option<Waker>>) {
+    if let Some(waker) = waker.lock().unwrap().take() {
+        waker.wake();
+    }
+}
+
+thread_local! {
+    static EVENT_LOOPS: RefCell<HashMap<usize, Rc<EventLoopState>>> = RefCell::new(HashMap::new());
+}
+
+fn state_for(key: usize) -> Rc<EventLoopState> {
+    EVENT_LOOPS.with(|loops| {
+        loops
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| Rc::new(EventLoopState::new()))
+            .clone()
+    })
+}
+
+/// A `Send` handle used to queue work onto an isolate's event loop from
+/// other threads. Queued tasks run on the isolate thread during
+/// `run_pending` or `run_until_idle`.
+#[derive(Clone)]
+pub struct EventLoopHandle {
+    sender: Sender<RemoteTask>,
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl EventLoopHandle {
+    /// Queue `task` to run on the isolate thread.
+    ///
+    /// Returns `false` if the event loop has been dropped.
+    pub fn post(&self, task: impl FnOnce(&mut Isolate) + Send + 'static) -> bool {
+        if self.sender.send(Box::new(task)).is_err() {
+            return false;
+        }
+        wake(&self.waker);
+        true
+    }
+}
+
+/// Get a `Send` handle to the event loop of the current isolate.
+pub fn handle(scope: &mut impl InIsolate) -> EventLoopHandle {
+    let state = state_for(isolate_key(scope));
+    EventLoopHandle {
+        sender: state.remote_sender.clone(),
+        waker: state.waker.clone(),
+    }
+}
+
+/// Queue `task` to run on the current isolate's event loop.
+pub fn enqueue(scope: &mut impl InIsolate, task: impl FnOnce(&mut Isolate) + 'static) {
+    enqueue_for(isolate_key(scope), task);
+}
+
+pub(crate) fn enqueue_for(key: usize, task: impl FnOnce(&mut Isolate) + 'static) {
+    let state = state_for(key);
+    state.local.borrow_mut().push_back(Box::new(task));
+    wake(&state.waker);
+}
+
+/// Mark that a unit of work (i.e. a background thread) will post back to
+/// this event loop later, keeping `run_until_idle` from returning early.
+pub(crate) fn begin_outstanding(scope: &mut impl InIsolate) {
+    let state = state_for(isolate_key(scope));
+    state.outstanding.set(state.outstanding.get() + 1);
+}
+
+/// Counterpart to `begin_outstanding`, called from the posted task.
+pub(crate) fn end_outstanding(scope: &mut impl InIsolate) {
+    let state = state_for(isolate_key(scope));
+    state
+        .outstanding
+        .set(state.outstanding.get().saturating_sub(1));
+}
+
+/// Check if there are queued tasks or outstanding background work.
+pub fn has_pending(scope: &mut impl InIsolate) -> bool {
+    let state = state_for(isolate_key(scope));
+    !state.local.borrow().is_empty() || state.outstanding.get() > 0
+}
+
+/// Run every task queued so far without blocking, including tasks posted
+/// from other threads. Returns the number of tasks run.
+pub fn run_pending(scope: &mut impl InIsolate) -> usize {
+    let state = state_for(isolate_key(scope));
+    let mut count = 0;
+    loop {
+        let task = state.local.borrow_mut().pop_front();
+        if let Some(task) = task {
+            task(scope.isolate());
+            count += 1;
+            continue;
+        }
+        match state.remote_receiver.try_recv() {
+            Ok(task) => {
+                task(scope.isolate());
+                count += 1;
+            }
+            Err(_) => break,
+        }
+    }
+    count
+}
+
+/// Run tasks until there is no queued or outstanding work left, blocking
+/// for background work to post back as needed.
+pub fn run_until_idle(scope: &mut impl InIsolate) {
+    let state = state_for(isolate_key(scope));
+    loop {
+        run_pending(scope);
+        if state.outstanding.get() == 0 && state.local.borrow().is_empty() {
+            break;
+        }
+        match state.remote_receiver.recv() {
+            Ok(task) => task(scope.isolate()),
+            Err(_) => break,
+        }
+    }
+}
+
+/// Like `run_until_idle`, but gives up once `timeout` has passed.
+///
+/// Returns `false` if work was still pending at the deadline.
+pub fn run_until_idle_timeout(scope: &mut impl InIsolate, timeout: Duration) -> bool {
+    let state = state_for(isolate_key(scope));
+    let deadline = Instant::now() + timeout;
+    loop {
+        run_pending(scope);
+        if state.outstanding.get() == 0 && state.local.borrow().is_empty() {
+            return true;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return false;
+        }
+        match state.remote_receiver.recv_timeout(deadline - now) {
+            Ok(task) => task(scope.isolate()),
+            Err(RecvTimeoutError::Timeout) => return false,
+            Err(RecvTimeoutError::Disconnected) => return true,
+        }
+    }
+}
+
+/// Run tasks like `run_until_idle`, but without blocking, for driving the
+/// event loop from an async executor: returns `Poll::Pending` while work is
+/// outstanding, and wakes `cx` once a task is queued.
+pub fn poll_until_idle(scope: &mut impl InIsolate, cx: &mut Context) -> Poll<()> {
+    let state = state_for(isolate_key(scope));
+    loop {
+        run_pending(scope);
+        if state.outstanding.get() == 0 && state.local.borrow().is_empty() {
+            return Poll::Ready(());
+        }
+        *state.waker.lock().unwrap() = Some(cx.waker().clone());
+        // a task queued before the waker was registered did not wake it
+        if run_pending(scope) == 0 {
+            return Poll::Pending;
+        }
+    }
+}
+
+/// Drop the event loop of the current isolate along with any queued tasks.
+/// Should be called before the isolate is disposed.
+pub fn dispose(scope: &mut impl InIsolate) {
+    let key = isolate_key(scope);
+    EVENT_LOOPS.with(|loops| loops.borrow_mut().remove(&key));
+}
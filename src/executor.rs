@@ -0,0 +1,595 @@
+//! `Executor`, the async runtime futures backing promises are spawned on,
+//! `LocalExecutor`, a minimal single threaded one, and `FakeTimers`, one
+//! following a manual clock for tests.
+//!
+//! An isolate must stay on one thread, so futures are spawned onto the
+//! isolate's thread and never touch the isolate; their results are handed
+//! to the isolate's event loop, which `run_event_loop` drives until idle.
+
+use crate::event_loop;
+use crate::promise;
+use crate::util::{isolate_key, isolate_slot, set_isolate_slot};
+use crate::FFICompat;
+use rusty_v8 as v8;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+use v8::Isolate;
+
+pub type LocalFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+/// `Executor` is the async runtime of an isolate, set with `set_executor`.
+/// It is only used on the isolate's thread, so spawned futures need not be
+/// `Send`.
+///
+/// With the `tokio` feature, `tokio_runtime::TokioExecutor` is used when
+/// none is set. Other runtimes implement it on their local executor, i.e.
+/// for smol:
+///
+/// ```ignore
+/// struct Smol(Rc<smol::LocalExecutor<'static>>);
+///
+/// impl Executor for Smol {
+///     fn spawn(&self, future: LocalFuture) {
+///         self.0.spawn(future).detach();
+///     }
+///
+///     fn sleep(&self, duration: Duration) -> LocalFuture {
+///         Box::pin(async move {
+///             smol::Timer::after(duration).await;
+///         })
+///     }
+/// }
+/// ```
+pub trait Executor: 'static {
+    /// Run `future` to completion on the current thread.
+    fn spawn(&self, future: LocalFuture);
+
+    /// A future completing after `duration`, i.e. for timers.
+    fn sleep(&self, duration: Duration) -> LocalFuture;
+}
+
+struct ExecutorSlot(Rc<dyn Executor>);
+
+/// Set the executor futures are spawned on in this isolate.
+pub fn set_executor(scope: &mut impl v8::InIsolate, executor: impl Executor) {
+    set_isolate_slot(scope, ExecutorSlot(Rc::new(executor)));
+}
+
+/// The executor of this isolate, if one was set, or with the `tokio`
+/// feature, `TokioExecutor`.
+pub fn executor(scope: &mut impl v8::InIsolate) -> Option<Rc<dyn Executor>> {
+    if let Some(slot) = isolate_slot::<ExecutorSlot>(scope) {
+        return Some(slot.0.clone());
+    }
+    #[cfg(feature = "tokio")]
+    {
+        Some(Rc::new(crate::tokio_runtime::TokioExecutor))
+    }
+    #[cfg(not(feature = "tokio"))]
+    {
+        None
+    }
+}
+
+/// Spawn `future` on the isolate's executor, returning a `Promise` that is
+/// settled with the future's `FFICompat` output.
+///
+/// The promise is settled on the isolate's event loop, while it is driven,
+/// i.e. by `run_event_loop`.
+///
+/// Panics if the isolate has no executor.
+pub fn spawn_local<'sc, F, R, E>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    future: F,
+) -> v8::Local<'sc, v8::Promise>
+where
+    F: Future<Output = R> + 'static,
+    R: for<'a, 'b> FFICompat<'a, 'b, E = E> + 'static,
+    E: Debug + Any,
+{
+    let executor = executor(scope).expect("no executor set for this isolate, see `set_executor`");
+    let context = scope.get_current_context().unwrap();
+    let (id, promise) = promise::new_pending(scope, context);
+    event_loop::begin_outstanding(scope);
+    let key = isolate_key(scope);
+    executor.spawn(Box::pin(async move {
+        let result = future.await;
+        event_loop::enqueue_for(key, move |isolate| {
+            event_loop::end_outstanding(isolate);
+            promise::settle(isolate, id, result);
+        });
+    }));
+    promise
+}
+
+/// Drive the event loop of `isolate` until there is no queued or
+/// outstanding work left, yielding to the executor while waiting rather
+/// than blocking the thread.
+pub fn run_event_loop(isolate: &mut Isolate) -> RunEventLoop {
+    RunEventLoop { isolate }
+}
+
+/// The future returned by `run_event_loop`.
+pub struct RunEventLoop<'a> {
+    isolate: &'a mut Isolate,
+}
+
+impl<'a> Future for RunEventLoop<'a> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        event_loop::poll_until_idle(self.get_mut().isolate, cx)
+    }
+}
+
+/// Which futures of a `LocalExecutor` were woken, by index, with the main
+/// future of `block_on` as `None`.
+struct Woken {
+    ready: Mutex<VecDeque<Option<usize>>>,
+    thread: Thread,
+}
+
+struct TaskWaker {
+    task: Option<usize>,
+    woken: Arc<Woken>,
+}
+
+impl TaskWaker {
+    fn wake(&self) {
+        self.woken.ready.lock().unwrap().push_back(self.task);
+        self.woken.thread.unpark();
+    }
+}
+
+fn task_waker(task: Option<usize>, woken: Arc<Woken>) -> Waker {
+    unsafe fn clone(data: *const ()) -> RawWaker {
+        let waker = Arc::from_raw(data as *const TaskWaker);
+        let cloned = waker.clone();
+        std::mem::forget(waker);
+        RawWaker::new(Arc::into_raw(cloned) as *const (), &VTABLE)
+    }
+    unsafe fn wake(data: *const ()) {
+        Arc::from_raw(data as *const TaskWaker).wake();
+    }
+    unsafe fn wake_by_ref(data: *const ()) {
+        (*(data as *const TaskWaker)).wake();
+    }
+    unsafe fn drop(data: *const ()) {
+        Arc::from_raw(data as *const TaskWaker);
+    }
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop);
+    let data = Arc::into_raw(Arc::new(TaskWaker { task, woken })) as *const ();
+    unsafe { Waker::from_raw(RawWaker::new(data, &VTABLE)) }
+}
+
+/// The futures spawned on a `LocalExecutor` or `FakeTimers`, by index.
+#[derive(Default)]
+struct Tasks {
+    tasks: RefCell<Vec<Option<LocalFuture>>>,
+    /// Spawned since the tasks were last polled.
+    spawned: RefCell<Vec<LocalFuture>>,
+}
+
+impl Tasks {
+    fn poll_task(&self, index: usize, woken: &Arc<Woken>) {
+        // taken out so that the task can spawn while it is polled
+        let task = self.tasks.borrow_mut()[index].take();
+        let mut task = match task {
+            Some(task) => task,
+            None => return,
+        };
+        let waker = task_waker(Some(index), woken.clone());
+        let mut cx = Context::from_waker(&waker);
+        if task.as_mut().poll(&mut cx).is_pending() {
+            self.tasks.borrow_mut()[index] = Some(task);
+        }
+    }
+
+    fn start_spawned(&self, woken: &Arc<Woken>) {
+        let spawned = std::mem::take(&mut *self.spawned.borrow_mut());
+        let mut ready = woken.ready.lock().unwrap();
+        let mut tasks = self.tasks.borrow_mut();
+        for task in spawned {
+            let index = match tasks.iter().position(|x| x.is_none()) {
+                Some(index) => index,
+                None => {
+                    tasks.push(None);
+                    tasks.len() - 1
+                }
+            };
+            tasks[index] = Some(task);
+            ready.push_back(Some(index));
+        }
+    }
+}
+
+#[derive(Default)]
+struct LocalState {
+    tasks: Tasks,
+    timers: RefCell<BTreeMap<(Instant, u64), Waker>>,
+    next_timer: Cell<u64>,
+}
+
+/// `LocalExecutor` is a minimal single threaded `Executor`, for embedders
+/// without an async runtime. Futures spawned on it, and its timers, run
+/// while `block_on` is running.
+///
+/// ```ignore
+/// let executor = LocalExecutor::new();
+/// set_executor(isolate, executor.clone());
+/// executor.block_on(async {
+///     // run scripts
+///     run_event_loop(isolate).await;
+/// });
+/// ```
+#[derive(Clone, Default)]
+pub struct LocalExecutor {
+    state: Rc<LocalState>,
+}
+
+impl LocalExecutor {
+    pub fn new() -> LocalExecutor {
+        LocalExecutor::default()
+    }
+
+    /// Run `future` to completion, along with the spawned futures, parking
+    /// the thread while none can make progress.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let woken = Arc::new(Woken {
+            ready: Mutex::new(VecDeque::new()),
+            thread: thread::current(),
+        });
+        woken.ready.lock().unwrap().push_back(None);
+        loop {
+            self.fire_timers();
+            let ready = std::mem::take(&mut *woken.ready.lock().unwrap());
+            for task in ready {
+                match task {
+                    None => {
+                        let waker = task_waker(None, woken.clone());
+                        let mut cx = Context::from_waker(&waker);
+                        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                            return output;
+                        }
+                    }
+                    Some(index) => self.state.tasks.poll_task(index, &woken),
+                }
+            }
+            self.state.tasks.start_spawned(&woken);
+            if !woken.ready.lock().unwrap().is_empty() {
+                continue;
+            }
+            let deadline = self.state.timers.borrow().keys().next().map(|x| x.0);
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if deadline > now {
+                        thread::park_timeout(deadline - now);
+                    }
+                }
+                None => thread::park(),
+            }
+        }
+    }
+
+    fn fire_timers(&self) {
+        let now = Instant::now();
+        let mut timers = self.state.timers.borrow_mut();
+        while let Some(&key) = timers.keys().next() {
+            if key.0 > now {
+                break;
+            }
+            timers.remove(&key).unwrap().wake();
+        }
+    }
+}
+
+struct Sleep {
+    deadline: Instant,
+    state: Rc<LocalState>,
+    timer: Option<(Instant, u64)>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let this = self.get_mut();
+        if Instant::now() >= this.deadline {
+            return Poll::Ready(());
+        }
+        let key = match this.timer {
+            Some(key) => key,
+            None => {
+                let id = this.state.next_timer.get();
+                this.state.next_timer.set(id + 1);
+                let key = (this.deadline, id);
+                this.timer = Some(key);
+                key
+            }
+        };
+        this.state
+            .timers
+            .borrow_mut()
+            .insert(key, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(key) = self.timer {
+            self.state.timers.borrow_mut().remove(&key);
+        }
+    }
+}
+
+impl Executor for LocalExecutor {
+    fn spawn(&self, future: LocalFuture) {
+        self.state.tasks.spawned.borrow_mut().push(future);
+    }
+
+    fn sleep(&self, duration: Duration) -> LocalFuture {
+        Box::pin(Sleep {
+            deadline: Instant::now() + duration,
+            state: self.state.clone(),
+            timer: None,
+        })
+    }
+}
+
+#[derive(Default)]
+struct FakeState {
+    tasks: Tasks,
+    /// The time since the `FakeTimers` were created.
+    now: Cell<Duration>,
+    timers: RefCell<BTreeMap<(Duration, u64), Waker>>,
+    next_timer: Cell<u64>,
+}
+
+/// `FakeTimers` is an `Executor` for tests whose timers follow a manual
+/// clock rather than real time, so that tests of script behavior can skip
+/// ahead without sleeping. It drives the isolate itself, so nothing runs
+/// until `advance_time` or `run_until_stalled` is called.
+///
+/// ```ignore
+/// let timers = FakeTimers::new();
+/// set_executor(isolate, timers.clone());
+/// // install `TimersExtension`, run `setTimeout(done, 1000)`
+/// timers.advance_time(isolate, 999);
+/// // `done` has not been called
+/// timers.advance_time(isolate, 1);
+/// // `done` has been called
+/// ```
+#[derive(Clone)]
+pub struct FakeTimers {
+    state: Rc<FakeState>,
+    woken: Arc<Woken>,
+}
+
+impl FakeTimers {
+    pub fn new() -> FakeTimers {
+        FakeTimers {
+            state: Rc::new(FakeState::default()),
+            woken: Arc::new(Woken {
+                ready: Mutex::new(VecDeque::new()),
+                thread: thread::current(),
+            }),
+        }
+    }
+
+    /// The time the clock was advanced by so far.
+    pub fn now(&self) -> Duration {
+        self.state.now.get()
+    }
+
+    /// The number of sleeps, i.e. JS timers, waiting for the clock.
+    pub fn pending_timers(&self) -> usize {
+        self.state.timers.borrow().len()
+    }
+
+    /// Move the clock forward by `millis` milliseconds. Timers due on the
+    /// way fire in order, each with the clock at its due time, and after
+    /// each the spawned futures and the event loop of the isolate of
+    /// `scope` run until stalled, so timers set meanwhile fire too if they
+    /// are due in time.
+    pub fn advance_time(&self, scope: &mut impl v8::InIsolate, millis: u64) {
+        let target = self.now() + Duration::from_millis(millis);
+        loop {
+            self.run_until_stalled(scope);
+            let deadline = self.state.timers.borrow().keys().next().map(|x| x.0);
+            match deadline {
+                Some(deadline) if deadline <= target => {
+                    self.state.now.set(self.now().max(deadline));
+                    self.fire_timers();
+                }
+                _ => break,
+            }
+        }
+        self.state.now.set(target);
+    }
+
+    /// Run the spawned futures and the event loop of the isolate of `scope`
+    /// until neither can make progress without the clock moving.
+    pub fn run_until_stalled(&self, scope: &mut impl v8::InIsolate) {
+        loop {
+            self.state.tasks.start_spawned(&self.woken);
+            let ready = std::mem::take(&mut *self.woken.ready.lock().unwrap());
+            let mut progressed = !ready.is_empty();
+            for task in ready.into_iter().flatten() {
+                self.state.tasks.poll_task(task, &self.woken);
+            }
+            progressed |= event_loop::run_pending(scope) > 0;
+            if !progressed && self.state.tasks.spawned.borrow().is_empty() {
+                return;
+            }
+        }
+    }
+
+    fn fire_timers(&self) {
+        let now = self.now();
+        let mut timers = self.state.timers.borrow_mut();
+        while let Some(&key) = timers.keys().next() {
+            if key.0 > now {
+                break;
+            }
+            timers.remove(&key).unwrap().wake();
+        }
+    }
+}
+
+impl Default for FakeTimers {
+    fn default() -> FakeTimers {
+        FakeTimers::new()
+    }
+}
+
+struct FakeSleep {
+    deadline: Duration,
+    state: Rc<FakeState>,
+    timer: Option<(Duration, u64)>,
+}
+
+impl Future for FakeSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let this = self.get_mut();
+        if this.state.now.get() >= this.deadline {
+            return Poll::Ready(());
+        }
+        let key = match this.timer {
+            Some(key) => key,
+            None => {
+                let id = this.state.next_timer.get();
+                this.state.next_timer.set(id + 1);
+                let key = (this.deadline, id);
+                this.timer = Some(key);
+                key
+            }
+        };
+        this.state
+            .timers
+            .borrow_mut()
+            .insert(key, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for FakeSleep {
+    fn drop(&mut self) {
+        if let Some(key) = self.timer {
+            self.state.timers.borrow_mut().remove(&key);
+        }
+    }
+}
+
+impl Executor for FakeTimers {
+    fn spawn(&self, future: LocalFuture) {
+        self.state.tasks.spawned.borrow_mut().push(future);
+    }
+
+    fn sleep(&self, duration: Duration) -> LocalFuture {
+        Box::pin(FakeSleep {
+            deadline: self.now() + duration,
+            state: self.state.clone(),
+            timer: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+
+    #[test]
+    fn local_executor() {
+        let executor = LocalExecutor::new();
+        let log = Rc::new(RefCell::new(vec![]));
+        let start = Instant::now();
+        let output = executor.block_on({
+            let executor = executor.clone();
+            let log = log.clone();
+            async move {
+                for (name, millis) in [("late", 30), ("early", 10)].iter() {
+                    let sleep = executor.sleep(Duration::from_millis(*millis));
+                    let log = log.clone();
+                    executor.spawn(Box::pin(async move {
+                        sleep.await;
+                        log.borrow_mut().push(*name);
+                    }));
+                }
+                // dropped before it fires
+                drop(executor.sleep(Duration::from_millis(5)));
+                executor.sleep(Duration::from_millis(50)).await;
+                log.borrow_mut().push("main");
+                42
+            }
+        });
+        assert_eq!(output, 42);
+        assert_eq!(*log.borrow(), vec!["early", "late", "main"]);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(executor.state.timers.borrow().is_empty());
+    }
+
+    #[test]
+    fn fake_timers() {
+        use crate::Extension;
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        let timers = crate::FakeTimers::new();
+        crate::set_executor(runtime.isolate(), timers.clone());
+        let (_, context) = runtime.create_context();
+        let isolate = runtime.isolate();
+        let mut hs = v8::HandleScope::new(isolate);
+        let scope = hs.enter();
+        let context = context.get(scope).unwrap();
+        let mut cs = v8::ContextScope::new(scope, context);
+        let scope = cs.enter();
+        crate::extensions::timers::TimersExtension
+            .install(scope, context)
+            .unwrap();
+        run_script(
+            scope,
+            context,
+            r#"
+            globalThis.log = [];
+            setTimeout(() => log.push('late'), 1000);
+            setTimeout(() => {
+                log.push('early');
+                setTimeout(() => log.push('nested'), 100);
+            }, 10);
+            const interval = setInterval(() => log.push('tick'), 300);
+            globalThis.stop = () => clearInterval(interval);
+            "#,
+        )
+        .unwrap();
+        timers.advance_time(scope, 999);
+        let log = run_script(scope, context, "log.join()").unwrap();
+        assert_eq!(
+            String::from_value(log, scope, context),
+            Ok("early,nested,tick,tick,tick".to_string())
+        );
+        timers.advance_time(scope, 1);
+        let log = run_script(scope, context, "log.join()").unwrap();
+        assert_eq!(
+            String::from_value(log, scope, context),
+            Ok("early,nested,tick,tick,tick,late".to_string())
+        );
+        run_script(scope, context, "stop()").unwrap();
+        timers.run_until_stalled(scope);
+        assert_eq!(timers.pending_timers(), 0);
+        assert_eq!(timers.now(), std::time::Duration::from_secs(1));
+    }
+}
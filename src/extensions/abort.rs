@@ -0,0 +1,133 @@
+use super::{run_bootstrap, Extension};
+use crate::util::*;
+use crate::FFICompat;
+use crate::FFIError;
+use crate::ObjectWrap;
+use rusty_v8 as v8;
+use rusty_v8_helper_derive::v8_ffi;
+use std::convert::TryInto;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// `CancellationToken` is the Rust side of a JS `AbortSignal`.
+///
+/// It can be taken as an argument of any `v8_ffi` function, including ones
+/// that hand work off to other threads, and is cancelled when the JS
+/// `AbortController` owning the signal is aborted.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for CancellationToken {
+    type E = String;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        _scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, String> {
+        let object: v8::Local<v8::Object> = value.try_into().map_err(|_| {
+            "invalid type for argument in ffi call, expected AbortSignal".to_string()
+        })?;
+        ObjectWrap::<CancellationToken>::from_object(object)
+            .map(|token| (*token).clone())
+            .ok_or_else(|| {
+                "invalid type for argument in ffi call, expected AbortSignal".to_string()
+            })
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, String> {
+        let mut wrapped = make_object_wrap(scope, context, self);
+        wrapped.make_weak();
+        Ok(wrapped.get(scope).unwrap().into())
+    }
+}
+
+#[v8_ffi]
+fn abort_create_signal() -> CancellationToken {
+    CancellationToken::new()
+}
+
+#[v8_ffi]
+fn abort_cancel_signal(token: CancellationToken) {
+    token.cancel();
+}
+
+const ABORT_BOOTSTRAP: &str = r#"
+(function (createSignal, cancelSignal) {
+    class AbortController {
+        constructor() {
+            const signal = createSignal();
+            const listeners = [];
+            let aborted = false;
+            let reason = undefined;
+            Object.defineProperties(signal, {
+                aborted: { get: () => aborted },
+                reason: { get: () => reason },
+            });
+            signal.onabort = null;
+            signal.addEventListener = (type, listener) => {
+                if (type === 'abort') listeners.push(listener);
+            };
+            signal.removeEventListener = (type, listener) => {
+                const index = listeners.indexOf(listener);
+                if (type === 'abort' && index >= 0) listeners.splice(index, 1);
+            };
+            signal.throwIfAborted = () => {
+                if (aborted) throw reason;
+            };
+            this.signal = signal;
+            this.abort = (abortReason) => {
+                if (aborted) return;
+                aborted = true;
+                reason = abortReason === undefined ? new Error('This operation was aborted') : abortReason;
+                cancelSignal(signal);
+                const event = { type: 'abort', target: signal };
+                if (typeof signal.onabort === 'function') signal.onabort(event);
+                for (const listener of listeners.slice()) listener(event);
+            };
+        }
+    }
+    this.AbortController = AbortController;
+})
+"#;
+
+/// Installs a global `AbortController` whose `signal` can be passed to
+/// `v8_ffi` functions taking a `CancellationToken`.
+pub struct AbortExtension;
+
+impl Extension for AbortExtension {
+    fn install<'sc, 'c>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<(), FFIError> {
+        let create_signal = load_v8_ffi!(abort_create_signal, scope, context);
+        let cancel_signal = load_v8_ffi!(abort_cancel_signal, scope, context);
+        run_bootstrap(
+            scope,
+            context,
+            ABORT_BOOTSTRAP,
+            &[create_signal, cancel_signal],
+        )
+    }
+}
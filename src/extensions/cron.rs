@@ -0,0 +1,669 @@
+use super::{run_bootstrap, Extension};
+use crate::event_loop::{self, EventLoopHandle};
+use crate::util::*;
+use crate::{CallbackId, CallbackRegistry, FFIError, FFIObject, IsolateMailbox, JsRef};
+use crate::{MailboxError, OverflowPolicy};
+use rusty_v8 as v8;
+use rusty_v8_helper_derive::v8_ffi;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How many runs can wait for a busy isolate before the oldest is dropped.
+const MAILBOX_CAPACITY: usize = 64;
+
+/// How far ahead `CronSchedule::next_after` looks, in days, covering a
+/// February 29th on a given weekday.
+const SEARCH_DAYS: i64 = 366 * 28;
+
+const MINUTE_MS: u64 = 60_000;
+
+/// A parsed cron expression, `minute hour day-of-month month day-of-week`,
+/// in UTC. Fields are `*`, numbers, ranges `a-b` and lists `a,b`, each
+/// optionally with a step `/n`; months and weekdays may be given by their
+/// three letter English names, and Sunday as 0 or 7. `@yearly`,
+/// `@monthly`, `@weekly`, `@daily` and `@hourly` are shorthands.
+///
+/// As in cron, if both the day of the month and the day of the week are
+/// restricted, a day matching either is run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+const MONTH_NAMES: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+const WEEKDAY_NAMES: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Parse one field into a bit set of the values in `min..=max`.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |text: &str| -> Result<u32, String> {
+        let named = names
+            .iter()
+            .position(|x| x.eq_ignore_ascii_case(text))
+            .map(|x| x as u32 + min);
+        let value = match named {
+            Some(value) => value,
+            None => text
+                .parse()
+                .map_err(|_| format!("invalid value '{}'", text))?,
+        };
+        if value < min || value > max {
+            return Err(format!("{} is out of range {}-{}", value, min, max));
+        }
+        Ok(value)
+    };
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(index) => {
+                let step: u32 = part[index + 1..]
+                    .parse()
+                    .map_err(|_| format!("invalid step in '{}'", part))?;
+                if step == 0 {
+                    return Err(format!("invalid step in '{}'", part));
+                }
+                (&part[..index], Some(step))
+            }
+            None => (part, None),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some(index) = range.find('-') {
+            (value(&range[..index])?, value(&range[index + 1..])?)
+        } else {
+            let start = value(range)?;
+            (start, if step.is_some() { max } else { start })
+        };
+        if start > end {
+            return Err(format!("invalid range '{}'", range));
+        }
+        for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<CronSchedule, String> {
+        let expanded = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expression => expression,
+        };
+        let fields = expanded.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err(format!(
+                "invalid cron expression '{}': expected 5 fields",
+                expression
+            ));
+        }
+        let invalid = |e: String| format!("invalid cron expression '{}': {}", expression, e);
+        let mut days_of_week = parse_field(fields[4], 0, 7, WEEKDAY_NAMES).map_err(invalid)?;
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week | 1) & !(1 << 7);
+        }
+        Ok(CronSchedule {
+            minutes: parse_field(fields[0], 0, 59, &[]).map_err(invalid)?,
+            hours: parse_field(fields[1], 0, 23, &[]).map_err(invalid)?,
+            days_of_month: parse_field(fields[2], 1, 31, &[]).map_err(invalid)?,
+            months: parse_field(fields[3], 1, 12, MONTH_NAMES).map_err(invalid)?,
+            days_of_week,
+            any_day_of_month: fields[2].starts_with('*'),
+            any_day_of_week: fields[4].starts_with('*'),
+        })
+    }
+
+    fn runs_on(&self, day: i64) -> bool {
+        let (_, month, day_of_month) = civil_from_days(day);
+        let day_of_week = (day + 4).rem_euclid(7);
+        if self.months & (1 << month) == 0 {
+            return false;
+        }
+        let on_day_of_month = self.days_of_month & (1 << day_of_month) != 0;
+        let on_day_of_week = self.days_of_week & (1 << day_of_week) != 0;
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (false, false) => on_day_of_month || on_day_of_week,
+            _ => on_day_of_month && on_day_of_week,
+        }
+    }
+
+    /// The first time the schedule runs strictly after `after`, both in
+    /// milliseconds since the Unix epoch, or `None` if it never does,
+    /// i.e. on February 30th.
+    pub fn next_after(&self, after: u64) -> Option<u64> {
+        let start = after / MINUTE_MS + 1;
+        let first_day = (start / 1440) as i64;
+        for day in first_day..first_day + SEARCH_DAYS {
+            if !self.runs_on(day) {
+                continue;
+            }
+            let first_minute = if day == first_day { start % 1440 } else { 0 };
+            for minute_of_day in first_minute..1440 {
+                let (hour, minute) = (minute_of_day / 60, minute_of_day % 60);
+                if self.hours & (1 << hour) != 0 && self.minutes & (1 << minute) != 0 {
+                    return Some((day as u64 * 1440 + minute_of_day) * MINUTE_MS);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The year, month and day of `days` since the Unix epoch.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = (if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    }) as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// A job of a `Scheduler`, as listed by `Scheduler::jobs` and saved to its
+/// `ScheduleStore`. Times are in milliseconds since the Unix epoch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledJob {
+    pub name: String,
+    pub expression: String,
+    pub last_run: Option<u64>,
+    /// `None` if the schedule never runs again.
+    pub next_run: Option<u64>,
+}
+
+/// `ScheduleStore` persists the jobs of a `Scheduler`, i.e. to a database,
+/// so that a restarted host knows when each job last ran.
+///
+/// Callbacks cannot be persisted, so scripts schedule their jobs again on
+/// startup; a job scheduled with a name and expression the store has
+/// picks up its last run, and with `catchUp`, runs once for the runs it
+/// missed while the host was down.
+///
+/// Called with the scheduler's lock held, from the scheduler thread or
+/// the isolate threads, so implementations should be quick.
+pub trait ScheduleStore: Send + Sync + 'static {
+    fn load(&self, name: &str) -> Option<ScheduledJob>;
+
+    fn save(&self, job: &ScheduledJob);
+
+    fn remove(&self, name: &str);
+}
+
+/// `ScheduleStore` keeping jobs in memory, i.e. for tests.
+#[derive(Default)]
+pub struct MemoryScheduleStore {
+    jobs: Mutex<BTreeMap<String, ScheduledJob>>,
+}
+
+impl MemoryScheduleStore {
+    pub fn new() -> MemoryScheduleStore {
+        MemoryScheduleStore::default()
+    }
+}
+
+impl ScheduleStore for MemoryScheduleStore {
+    fn load(&self, name: &str) -> Option<ScheduledJob> {
+        self.jobs.lock().unwrap().get(name).cloned()
+    }
+
+    fn save(&self, job: &ScheduledJob) {
+        self.jobs
+            .lock()
+            .unwrap()
+            .insert(job.name.clone(), job.clone());
+    }
+
+    fn remove(&self, name: &str) {
+        self.jobs.lock().unwrap().remove(name);
+    }
+}
+
+/// What a job's callback is called with.
+#[derive(Debug, Clone, Serialize, Deserialize, FFIObject)]
+#[serde(rename_all = "camelCase")]
+struct ScheduledRun {
+    name: String,
+    scheduled_time: f64,
+}
+
+struct Job {
+    info: ScheduledJob,
+    schedule: CronSchedule,
+    mailbox: IsolateMailbox,
+    event_loop: EventLoopHandle,
+    callback: CallbackId,
+}
+
+impl Job {
+    /// Release the job's callback on its isolate thread, letting the event
+    /// loop go idle.
+    fn release(self) {
+        let callback = self.callback;
+        self.event_loop.post(move |isolate| {
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            CallbackRegistry::of(scope).remove(scope, callback);
+            event_loop::end_outstanding(scope);
+        });
+    }
+}
+
+#[derive(Default)]
+struct Jobs {
+    jobs: BTreeMap<String, Job>,
+    stopped: bool,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    jobs: Mutex<Jobs>,
+    changed: Condvar,
+    store: Option<Box<dyn ScheduleStore>>,
+}
+
+/// `Scheduler` runs the recurring jobs scripts schedule through
+/// `CronExtension`. It can be shared between threads and isolates: jobs
+/// are sent to the isolate that scheduled them through an
+/// `IsolateMailbox`, and run while its event loop is driven. Like
+/// `setInterval`, a scheduled job keeps `run_event_loop` from returning
+/// until it is unscheduled.
+///
+/// Jobs run once `start` has spawned the scheduler thread, or whenever
+/// `tick` is called, i.e. by an embedder with a clock of its own. A run
+/// that is due while the isolate is busy waits for it, and runs missed
+/// while busy are not run again.
+///
+/// ```ignore
+/// let scheduler = Scheduler::with_store(SqliteSchedules::open("jobs.db")?);
+/// let thread = scheduler.start();
+/// CronExtension::new(scheduler.clone()).install(scope, context)?;
+/// // ... run scripts and drive the event loop
+/// scheduler.stop();
+/// thread.join().unwrap();
+/// ```
+#[derive(Clone, Default)]
+pub struct Scheduler(Arc<SchedulerState>);
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler::default()
+    }
+
+    /// A scheduler persisting its jobs to `store`.
+    pub fn with_store(store: impl ScheduleStore) -> Scheduler {
+        Scheduler(Arc::new(SchedulerState {
+            store: Some(Box::new(store)),
+            ..SchedulerState::default()
+        }))
+    }
+
+    /// The scheduled jobs, by name.
+    pub fn jobs(&self) -> Vec<ScheduledJob> {
+        let jobs = self.0.jobs.lock().unwrap();
+        jobs.jobs.values().map(|x| x.info.clone()).collect()
+    }
+
+    /// Unschedule the job `name`, from any thread. Returns `false` if it
+    /// was not scheduled.
+    pub fn unschedule(&self, name: &str) -> bool {
+        let job = self.0.jobs.lock().unwrap().jobs.remove(name);
+        match job {
+            Some(job) => {
+                if let Some(store) = &self.0.store {
+                    store.remove(name);
+                }
+                job.release();
+                self.0.changed.notify_all();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Send the jobs due at `now`, in milliseconds since the Unix epoch, to
+    /// their isolates, returning how many were sent. Jobs of isolates that
+    /// are gone are dropped, but stay in the store.
+    pub fn tick(&self, now: u64) -> usize {
+        let mut jobs = self.0.jobs.lock().unwrap();
+        let mut sent = 0;
+        let mut gone = vec![];
+        for job in jobs.jobs.values_mut() {
+            let scheduled = match job.info.next_run {
+                Some(next_run) if next_run <= now => next_run,
+                _ => continue,
+            };
+            job.info.last_run = Some(scheduled);
+            job.info.next_run = job.schedule.next_after(now);
+            if let Some(store) = &self.0.store {
+                store.save(&job.info);
+            }
+            let run = ScheduledRun {
+                name: job.info.name.clone(),
+                scheduled_time: scheduled as f64,
+            };
+            match job.mailbox.send_message(job.callback, run) {
+                Ok(()) => sent += 1,
+                Err(MailboxError::Closed) => gone.push(job.info.name.clone()),
+                Err(MailboxError::Full) => {}
+            }
+        }
+        for name in gone {
+            jobs.jobs.remove(&name);
+        }
+        sent
+    }
+
+    /// Spawn a thread sending jobs as they are due on the system clock,
+    /// until `stop` is called.
+    pub fn start(&self) -> JoinHandle<()> {
+        let scheduler = self.clone();
+        thread::spawn(move || loop {
+            scheduler.tick(now_ms());
+            let jobs = scheduler.0.jobs.lock().unwrap();
+            if jobs.stopped {
+                return;
+            }
+            let next_run = jobs.jobs.values().filter_map(|x| x.info.next_run).min();
+            match next_run {
+                Some(next_run) => {
+                    let wait = Duration::from_millis(next_run.saturating_sub(now_ms()));
+                    drop(scheduler.0.changed.wait_timeout(jobs, wait).unwrap());
+                }
+                None => drop(scheduler.0.changed.wait(jobs).unwrap()),
+            }
+        })
+    }
+
+    /// Stop the thread spawned by `start`. Jobs stay scheduled.
+    pub fn stop(&self) {
+        self.0.jobs.lock().unwrap().stopped = true;
+        self.0.changed.notify_all();
+    }
+
+    fn schedule(
+        &self,
+        name: String,
+        expression: String,
+        catch_up: bool,
+        mailbox: IsolateMailbox,
+        event_loop: EventLoopHandle,
+        callback: CallbackId,
+    ) -> Result<(), FFIError> {
+        let schedule = CronSchedule::parse(&expression).map_err(FFIError::RangeError)?;
+        let mut jobs = self.0.jobs.lock().unwrap();
+        if jobs.jobs.contains_key(&name) {
+            return Err(FFIError::Error(format!(
+                "job {} is already scheduled",
+                name
+            )));
+        }
+        let now = now_ms();
+        let last_run = self
+            .0
+            .store
+            .as_ref()
+            .and_then(|x| x.load(&name))
+            .filter(|x| x.expression == expression)
+            .and_then(|x| x.last_run);
+        let missed = last_run
+            .filter(|_| catch_up)
+            .and_then(|x| schedule.next_after(x))
+            .filter(|x| *x <= now);
+        let info = ScheduledJob {
+            name: name.clone(),
+            expression,
+            last_run,
+            next_run: missed.or_else(|| schedule.next_after(now)),
+        };
+        if let Some(store) = &self.0.store {
+            store.save(&info);
+        }
+        let job = Job {
+            info,
+            schedule,
+            mailbox,
+            event_loop,
+            callback,
+        };
+        jobs.jobs.insert(name, job);
+        self.0.changed.notify_all();
+        Ok(())
+    }
+}
+
+/// The mailbox jobs are sent to this isolate through.
+struct CronMailbox(IsolateMailbox);
+
+#[v8_ffi(scoped)]
+fn cron_schedule<'sc, 'c>(
+    this: &Scheduler,
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    name: String,
+    expression: String,
+    catch_up: bool,
+    callback: JsRef<v8::Function>,
+) -> Result<(), FFIError> {
+    let mailbox = match isolate_slot::<CronMailbox>(scope) {
+        Some(mailbox) => mailbox.0.clone(),
+        None => {
+            let mailbox = IsolateMailbox::new(scope, MAILBOX_CAPACITY, OverflowPolicy::DropOldest);
+            set_isolate_slot(scope, CronMailbox(mailbox.clone()));
+            mailbox
+        }
+    };
+    let registry = CallbackRegistry::of(scope);
+    let callback = registry.register(scope, context, callback);
+    let event_loop = event_loop::handle(scope);
+    match this.schedule(name, expression, catch_up, mailbox, event_loop, callback) {
+        Ok(()) => {
+            event_loop::begin_outstanding(scope);
+            Ok(())
+        }
+        Err(e) => {
+            registry.remove(scope, callback);
+            Err(e)
+        }
+    }
+}
+
+#[v8_ffi]
+fn cron_unschedule(this: &Scheduler, name: String) -> bool {
+    this.unschedule(&name)
+}
+
+#[v8_ffi]
+fn cron_jobs(this: &Scheduler) -> Vec<(String, String)> {
+    this.jobs()
+        .into_iter()
+        .map(|x| (x.name, x.expression))
+        .collect()
+}
+
+const CRON_BOOTSTRAP: &str = r#"
+(function (scheduler, schedule, unschedule, jobs) {
+    const host = this.host || (this.host = {});
+    let next = 0;
+    host.schedule = (expression, callback, options = {}) => {
+        if (typeof callback !== 'function') {
+            throw new TypeError('scheduled job callback must be a function');
+        }
+        const name = options.name === undefined ? `job-${++next}` : String(options.name);
+        schedule.call(scheduler, name, String(expression), !!options.catchUp, callback);
+        return name;
+    };
+    host.unschedule = (name) => unschedule.call(scheduler, String(name));
+    host.scheduled = () => Object.fromEntries(jobs.call(scheduler));
+})
+"#;
+
+/// Installs `host.schedule(expression, callback, options)`, scheduling
+/// `callback` to be called on the cron `expression`, see `CronSchedule`,
+/// with `{ name, scheduledTime }`. It returns the job's name, which is
+/// `options.name` or generated in order of scheduling. With
+/// `options.catchUp`, a run missed while the host was down is run once,
+/// see `ScheduleStore`.
+///
+/// `host.unschedule(name)` unschedules a job, returning whether it was
+/// scheduled, and `host.scheduled()` is an object of the scheduled jobs'
+/// expressions by name. Job names are shared by every isolate using the
+/// `Scheduler`.
+///
+/// ```js
+/// host.schedule('*/5 * * * *', ({ scheduledTime }) => sync(scheduledTime), { name: 'sync' });
+/// ```
+pub struct CronExtension {
+    scheduler: Scheduler,
+}
+
+impl CronExtension {
+    pub fn new(scheduler: Scheduler) -> CronExtension {
+        CronExtension { scheduler }
+    }
+}
+
+impl Extension for CronExtension {
+    fn install<'sc, 'c>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<(), FFIError> {
+        let mut scheduler = make_object_wrap(scope, context, self.scheduler.clone());
+        scheduler.make_weak();
+        let args = [
+            scheduler.get(scope).unwrap().into(),
+            load_v8_ffi!(cron_schedule, scope, context),
+            load_v8_ffi!(cron_unschedule, scope, context),
+            load_v8_ffi!(cron_jobs, scope, context),
+        ];
+        run_bootstrap(scope, context, CRON_BOOTSTRAP, &args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FFICompat;
+
+    #[test]
+    fn cron_extension() {
+        let weekdays = CronSchedule::parse("*/15 9-17 * * mon-fri").unwrap();
+        // Friday 2024-01-05 10:00 UTC, then 17:50
+        assert_eq!(weekdays.next_after(1704448800000), Some(1704449700000));
+        assert_eq!(weekdays.next_after(1704477000000), Some(1704704400000));
+        let leap_day = CronSchedule::parse("0 0 29 feb *").unwrap();
+        assert_eq!(leap_day.next_after(1709251200000), Some(1835395200000));
+        assert_eq!(
+            CronSchedule::parse("0 0 30 2 *").unwrap().next_after(0),
+            None
+        );
+        assert!(CronSchedule::parse("61 * * * *").is_err());
+        assert!(CronSchedule::parse("* * *").is_err());
+
+        crate::initialize_v8();
+        let store = MemoryScheduleStore::new();
+        store.save(&ScheduledJob {
+            name: "report".to_string(),
+            expression: "0 * * * *".to_string(),
+            last_run: Some(0),
+            next_run: None,
+        });
+        let scheduler = Scheduler::with_store(store);
+        let mut runtime = crate::Runtime::new();
+        let (_, mut context) = runtime.create_context();
+        let run = |isolate: &mut v8::Isolate, context: &v8::Global<v8::Context>, source: &str| {
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            let context = context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            let value = run_script(scope, context, source).unwrap();
+            String::from_value(value, scope, context).unwrap()
+        };
+        {
+            let mut hs = v8::HandleScope::new(runtime.isolate());
+            let scope = hs.enter();
+            let context = context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            CronExtension::new(scheduler.clone())
+                .install(scope, context)
+                .unwrap();
+        }
+        let scheduled = run(
+            runtime.isolate(),
+            &context,
+            r#"
+            globalThis.runs = [];
+            host.schedule('* * * * *', (run) => runs.push(run.name));
+            host.schedule('0 * * * *', (run) => runs.push(`${run.name} ${run.scheduledTime}`), {
+                name: 'report',
+                catchUp: true,
+            });
+            try { host.schedule('bad', () => {}) } catch (e) { runs.push(e.name) }
+            try { host.schedule('@daily', () => {}, { name: 'report' }) } catch (e) { runs.push(e.message) }
+            JSON.stringify(host.scheduled())
+            "#,
+        );
+        assert_eq!(scheduled, r#"{"job-1":"* * * * *","report":"0 * * * *"}"#);
+        let jobs = scheduler.jobs();
+        assert_eq!(jobs[1].last_run, Some(0));
+        assert_eq!(jobs[1].next_run, Some(3600000));
+        let now = jobs[0].next_run.unwrap();
+        assert_eq!(scheduler.tick(now), 2);
+        assert_eq!(scheduler.tick(now), 0);
+        crate::event_loop::run_pending(runtime.isolate());
+        assert_eq!(
+            run(runtime.isolate(), &context, "runs.join()"),
+            "RangeError,job report is already scheduled,job-1,report 3600000"
+        );
+        assert!(scheduler.jobs()[1].next_run.unwrap() > now);
+        assert_eq!(
+            run(
+                runtime.isolate(),
+                &context,
+                "String(host.unschedule('job-1'))"
+            ),
+            "true"
+        );
+        assert!(scheduler.unschedule("report"));
+        assert!(!scheduler.unschedule("report"));
+        crate::event_loop::run_pending(runtime.isolate());
+        assert!(scheduler.jobs().is_empty());
+        assert!(!crate::event_loop::has_pending(runtime.isolate()));
+        let thread = scheduler.start();
+        scheduler.stop();
+        thread.join().unwrap();
+        context.reset(runtime.isolate());
+    }
+}
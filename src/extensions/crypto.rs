@@ -0,0 +1,140 @@
+use super::{run_bootstrap, Extension};
+use crate::util::*;
+use crate::Bytes;
+use crate::FFIError;
+use rusty_v8 as v8;
+use rusty_v8_helper_derive::v8_ffi;
+
+/// Largest request `getRandomValues` accepts, as in browsers.
+const MAX_RANDOM_BYTES: u32 = 65536;
+
+fn random_bytes(length: usize) -> Result<Vec<u8>, FFIError> {
+    let mut data = vec![0; length];
+    getrandom::getrandom(&mut data[..])
+        .map_err(|e| FFIError::Error(format!("failed to get random values: {}", e)))?;
+    Ok(data)
+}
+
+/// Format 16 random bytes as a version 4 UUID.
+fn format_uuid_v4(mut data: [u8; 16]) -> String {
+    data[6] = (data[6] & 0x0f) | 0x40;
+    data[8] = (data[8] & 0x3f) | 0x80;
+    let hex: Vec<String> = data.iter().map(|x| format!("{:02x}", x)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        hex[0..4].concat(),
+        hex[4..6].concat(),
+        hex[6..8].concat(),
+        hex[8..10].concat(),
+        hex[10..16].concat()
+    )
+}
+
+#[v8_ffi]
+fn crypto_random_bytes(length: u32) -> Result<Bytes, FFIError> {
+    if length > MAX_RANDOM_BYTES {
+        return Err(FFIError::Error(format!(
+            "the requested length {} exceeds the maximum of {} bytes",
+            length, MAX_RANDOM_BYTES
+        )));
+    }
+    random_bytes(length as usize).map(Bytes)
+}
+
+#[v8_ffi]
+fn crypto_random_uuid() -> Result<String, FFIError> {
+    let mut data = [0; 16];
+    data.copy_from_slice(&random_bytes(16)?);
+    Ok(format_uuid_v4(data))
+}
+
+#[cfg(feature = "crypto-digest")]
+#[v8_ffi]
+fn crypto_digest(algorithm: String, data: Bytes) -> Result<Bytes, FFIError> {
+    use sha2::Digest;
+    match algorithm.to_uppercase().as_str() {
+        "SHA-256" => Ok(Bytes(sha2::Sha256::digest(&data[..]).to_vec())),
+        "SHA-384" => Ok(Bytes(sha2::Sha384::digest(&data[..]).to_vec())),
+        "SHA-512" => Ok(Bytes(sha2::Sha512::digest(&data[..]).to_vec())),
+        _ => Err(FFIError::Error(format!(
+            "unsupported digest algorithm '{}'",
+            algorithm
+        ))),
+    }
+}
+
+const CRYPTO_BOOTSTRAP: &str = r#"
+(function (randomBytes, randomUUID, digest) {
+    const integerArrays = [Int8Array, Uint8Array, Uint8ClampedArray, Int16Array, Uint16Array, Int32Array, Uint32Array];
+    const crypto = {
+        getRandomValues(array) {
+            if (!integerArrays.some(type => array instanceof type)) {
+                throw new TypeError('getRandomValues expects an integer typed array');
+            }
+            const bytes = randomBytes(array.byteLength);
+            new Uint8Array(array.buffer, array.byteOffset, array.byteLength).set(bytes);
+            return array;
+        },
+        randomUUID() {
+            return randomUUID();
+        },
+    };
+    if (digest) {
+        crypto.subtle = {
+            digest(algorithm, data) {
+                try {
+                    const name = typeof algorithm === 'string' ? algorithm : algorithm.name;
+                    const view = ArrayBuffer.isView(data)
+                        ? new Uint8Array(data.buffer, data.byteOffset, data.byteLength)
+                        : new Uint8Array(data);
+                    return Promise.resolve(digest(name, view).buffer);
+                } catch (e) {
+                    return Promise.reject(e);
+                }
+            },
+        };
+    }
+    this.crypto = crypto;
+})
+"#;
+
+/// Installs a global `crypto` object with `getRandomValues` and
+/// `randomUUID` backed by the OS random source.
+///
+/// With the `crypto-digest` feature, `crypto.subtle.digest` is installed as
+/// well, supporting SHA-256, SHA-384 and SHA-512.
+pub struct CryptoExtension;
+
+impl Extension for CryptoExtension {
+    fn install<'sc, 'c>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<(), FFIError> {
+        let random_bytes = load_v8_ffi!(crypto_random_bytes, scope, context);
+        let random_uuid = load_v8_ffi!(crypto_random_uuid, scope, context);
+        #[cfg(feature = "crypto-digest")]
+        let digest = load_v8_ffi!(crypto_digest, scope, context);
+        #[cfg(not(feature = "crypto-digest"))]
+        let digest = v8::undefined(scope).into();
+        run_bootstrap(
+            scope,
+            context,
+            CRYPTO_BOOTSTRAP,
+            &[random_bytes, random_uuid, digest],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uuid_format() {
+        let uuid = format_uuid_v4([0xff; 16]);
+        assert_eq!(uuid, "ffffffff-ffff-4fff-bfff-ffffffffffff");
+        let uuid = format_uuid_v4([0; 16]);
+        assert_eq!(uuid, "00000000-0000-4000-8000-000000000000");
+    }
+}
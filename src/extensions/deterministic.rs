@@ -0,0 +1,293 @@
+use super::Extension;
+use crate::util::{call_function, eval_function, isolate_key, make_num};
+use crate::{CallbackId, CallbackRegistry, FFIError, JsRef};
+use rusty_v8 as v8;
+use std::cell::{Cell, RefCell};
+use std::convert::TryInto;
+use std::rc::Rc;
+
+struct ClockState {
+    now: Cell<f64>,
+    /// The `fire` functions of the contexts the clock is installed in, by
+    /// isolate key.
+    fires: RefCell<Vec<(usize, CallbackId)>>,
+    errors: RefCell<Vec<FFIError>>,
+}
+
+/// `VirtualClock` is the time seen by contexts with a
+/// `DeterministicExtension`, which only moves when `advance` is called.
+///
+/// Clones share the same time, so one clock can drive several contexts.
+#[derive(Clone)]
+pub struct VirtualClock(Rc<ClockState>);
+
+impl VirtualClock {
+    /// A clock starting at `start` milliseconds since the Unix epoch.
+    pub fn new(start: f64) -> VirtualClock {
+        VirtualClock(Rc::new(ClockState {
+            now: Cell::new(start),
+            fires: RefCell::new(vec![]),
+            errors: RefCell::new(vec![]),
+        }))
+    }
+
+    /// The current time in milliseconds since the Unix epoch.
+    pub fn now(&self) -> f64 {
+        self.0.now.get()
+    }
+
+    /// Move the clock forward by `millis` milliseconds, queueing the timers
+    /// due by then on the event loops of the contexts the clock is
+    /// installed in. They fire in order of their due time, each seeing
+    /// `Date.now()` as its due time, once the event loop is driven, i.e. by
+    /// `event_loop::run_pending`. Timers set meanwhile fire too if they are
+    /// due by then.
+    pub fn advance(&self, millis: f64) {
+        self.0.now.set(self.now() + millis.max(0.0));
+        let mut keys: Vec<usize> = self.0.fires.borrow().iter().map(|x| x.0).collect();
+        keys.sort_unstable();
+        keys.dedup();
+        for key in keys {
+            let state = self.0.clone();
+            crate::event_loop::enqueue_for(key, move |isolate| fire(&state, key, isolate));
+        }
+    }
+
+    /// Take the errors thrown by timer callbacks so far.
+    pub fn take_errors(&self) -> Vec<FFIError> {
+        self.0.errors.replace(vec![])
+    }
+}
+
+/// Fire the timers due in every context of the isolate `key`, dropping the
+/// contexts since disposed.
+fn fire(state: &ClockState, key: usize, isolate: &mut v8::Isolate) {
+    let mut hs = v8::HandleScope::new(isolate);
+    let scope = hs.enter();
+    let registry = CallbackRegistry::of(scope);
+    let fires: Vec<CallbackId> = state
+        .fires
+        .borrow()
+        .iter()
+        .filter(|x| x.0 == key)
+        .map(|x| x.1)
+        .collect();
+    for id in fires {
+        let context = match registry.context_of(scope, id) {
+            Some(context) => context,
+            None => {
+                state.fires.borrow_mut().retain(|x| x.1 != id);
+                continue;
+            }
+        };
+        let now = make_num(scope, state.now.get());
+        if let Err(e) = registry.invoke(scope, context, id, &[now]) {
+            state.errors.borrow_mut().push(e);
+        }
+    }
+}
+
+const DETERMINISTIC_BOOTSTRAP: &str = r#"
+(function (seed, start) {
+    let state = seed >>> 0;
+    // mulberry32
+    Math.random = function random() {
+        state = (state + 0x6d2b79f5) >>> 0;
+        let t = state;
+        t = Math.imul(t ^ (t >>> 15), t | 1);
+        t ^= t + Math.imul(t ^ (t >>> 7), t | 61);
+        return ((t ^ (t >>> 14)) >>> 0) / 4294967296;
+    };
+
+    let now = start;
+    const RealDate = Date;
+    const VirtualDate = function Date(...args) {
+        if (!new.target) {
+            return new RealDate(now).toString();
+        }
+        return Reflect.construct(RealDate, args.length ? args : [now], new.target);
+    };
+    Object.setPrototypeOf(VirtualDate, RealDate);
+    VirtualDate.prototype = RealDate.prototype;
+    Object.defineProperty(RealDate.prototype, 'constructor', {
+        value: VirtualDate,
+        writable: true,
+        configurable: true,
+    });
+    VirtualDate.now = () => now;
+    this.Date = VirtualDate;
+
+    const timers = new Map();
+    let nextId = 1;
+    let sequence = 0;
+    const delayOf = (delay) => {
+        delay = Number(delay);
+        return delay > 0 ? delay : 0;
+    };
+    const add = (callback, delay, args, repeat) => {
+        if (typeof callback !== 'function') {
+            throw new TypeError('timer callback must be a function');
+        }
+        const id = nextId++;
+        delay = delayOf(delay);
+        timers.set(id, {
+            at: now + delay,
+            sequence: sequence++,
+            callback,
+            args,
+            // an interval of 0 would never let the clock move on
+            interval: repeat ? Math.max(delay, 1) : null,
+        });
+        return id;
+    };
+    this.setTimeout = (callback, delay, ...args) => add(callback, delay, args, false);
+    this.setInterval = (callback, delay, ...args) => add(callback, delay, args, true);
+    this.clearTimeout = this.clearInterval = (id) => {
+        timers.delete(id);
+    };
+
+    return function fire(target) {
+        let thrown = null;
+        for (;;) {
+            let next = null;
+            for (const [id, timer] of timers) {
+                if (timer.at > target) continue;
+                if (!next || timer.at < next[1].at
+                    || (timer.at === next[1].at && timer.sequence < next[1].sequence)) {
+                    next = [id, timer];
+                }
+            }
+            if (!next) break;
+            const [id, timer] = next;
+            now = Math.max(now, timer.at);
+            if (timer.interval === null) {
+                timers.delete(id);
+            } else {
+                timer.at += timer.interval;
+                timer.sequence = sequence++;
+            }
+            try {
+                timer.callback(...timer.args);
+            } catch (e) {
+                if (!thrown) thrown = { e };
+            }
+        }
+        now = Math.max(now, target);
+        if (thrown) throw thrown.e;
+    };
+})
+"#;
+
+/// Makes a context's executions reproducible, i.e. to replay user scripts
+/// while debugging: `Math.random` is a PRNG seeded with `seed`, and
+/// `Date.now`, `new Date()` and the global `setTimeout`, `setInterval`,
+/// `clearTimeout` and `clearInterval` follow `clock` rather than real time.
+///
+/// Timers are kept in JS and fire only as `clock` is advanced, so they
+/// never keep the event loop busy. The timers of `TimersExtension` are
+/// replaced if it was installed before.
+///
+/// ```ignore
+/// let clock = VirtualClock::new(0.0);
+/// DeterministicExtension::new(42, clock.clone()).install(scope, context)?;
+/// run_script(scope, context, "setTimeout(tick, 1000)");
+/// clock.advance(1000.0);
+/// event_loop::run_pending(scope);
+/// ```
+pub struct DeterministicExtension {
+    seed: u32,
+    clock: VirtualClock,
+}
+
+impl DeterministicExtension {
+    pub fn new(seed: u32, clock: VirtualClock) -> DeterministicExtension {
+        DeterministicExtension { seed, clock }
+    }
+}
+
+impl Extension for DeterministicExtension {
+    fn install<'sc, 'c>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<(), FFIError> {
+        let bootstrap = eval_function(scope, context, DETERMINISTIC_BOOTSTRAP)
+            .map_err(|e| FFIError::Error(format!("failed to bootstrap extension: {}", e)))?;
+        let global = context.global(scope).into();
+        let seed = make_num(scope, self.seed as f64);
+        let start = make_num(scope, self.clock.now());
+        let fire: v8::Local<v8::Function> =
+            call_function(scope, context, bootstrap, global, &[seed, start])
+                .map_err(|e| FFIError::Error(format!("failed to bootstrap extension: {}", e)))?
+                .try_into()
+                .unwrap();
+        let fire = JsRef::new(scope, fire);
+        let id = CallbackRegistry::of(scope).register(scope, context, fire);
+        self.clock
+            .0
+            .fires
+            .borrow_mut()
+            .push((isolate_key(scope), id));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+    use crate::FFICompat;
+
+    #[test]
+    fn deterministic_extension() {
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        let clock = VirtualClock::new(1000.0);
+        let contexts: Vec<_> = (0..3).map(|_| runtime.create_context().1).collect();
+        let isolate = runtime.isolate();
+        let mut hs = v8::HandleScope::new(isolate);
+        let scope = hs.enter();
+        for (context, seed) in contexts.iter().zip([7, 7, 8].iter()) {
+            let context = context.get(scope).unwrap();
+            DeterministicExtension::new(*seed, clock.clone())
+                .install(scope, context)
+                .unwrap();
+            run_script(
+                scope,
+                context,
+                r#"
+                globalThis.log = [Date.now(), new Date().getTime(), new Date(5).getTime()];
+                setTimeout(() => log.push('b@' + Date.now()), 50);
+                setTimeout(() => {
+                    log.push('a@' + Date.now());
+                    setTimeout(() => log.push('c@' + Date.now()), 10);
+                }, 20);
+                globalThis.interval = setInterval((x) => log.push(x + '@' + Date.now()), 40, 'i');
+                globalThis.random = [Math.random(), Math.random()].join();
+                "#,
+            )
+            .unwrap();
+        }
+        clock.advance(100.0);
+        crate::event_loop::run_pending(scope);
+        let mut logs = vec![];
+        for context in contexts.iter() {
+            let context = context.get(scope).unwrap();
+            let log = run_script(scope, context, "clearInterval(interval); log.join()").unwrap();
+            let random = run_script(scope, context, "random").unwrap();
+            logs.push(String::from_value(log, scope, context).unwrap());
+            logs.push(String::from_value(random, scope, context).unwrap());
+        }
+        assert_eq!(clock.now(), 1100.0);
+        assert_eq!(logs[0], "1000,1000,5,a@1020,c@1030,i@1040,b@1050,i@1080");
+        assert_eq!(logs[0], logs[2]);
+        assert_eq!(logs[0], logs[4]);
+        assert_eq!(logs[1], logs[3]);
+        assert_ne!(logs[1], logs[5]);
+        assert!(clock.take_errors().is_empty());
+        drop(hs);
+        for mut context in contexts {
+            context.reset(runtime.isolate());
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use super::{run_bootstrap, Extension};
+use crate::util::*;
+use crate::Bytes;
+use crate::FFIError;
+use rusty_v8 as v8;
+use rusty_v8_helper_derive::v8_ffi;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode `data` as padded standard base64.
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - i * 6)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decode standard base64, with or without padding. ASCII whitespace is
+/// ignored, as with `atob`.
+pub fn base64_decode(input: &str) -> Result<Vec<u8>, FFIError> {
+    let mut symbols: Vec<u8> = input.bytes().filter(|x| !x.is_ascii_whitespace()).collect();
+    if symbols.len() % 4 == 0 {
+        while symbols.last() == Some(&b'=') {
+            symbols.pop();
+        }
+    }
+    if symbols.len() % 4 == 1 {
+        return Err(FFIError::Error(
+            "invalid base64, incorrect length".to_string(),
+        ));
+    }
+    let mut out = Vec::with_capacity(symbols.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for symbol in symbols {
+        let value = match symbol {
+            b'A'..=b'Z' => symbol - b'A',
+            b'a'..=b'z' => symbol - b'a' + 26,
+            b'0'..=b'9' => symbol - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => {
+                return Err(FFIError::Error(format!(
+                    "invalid base64 character '{}'",
+                    symbol as char
+                )))
+            }
+        };
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+/// Encode `data` as lowercase hex.
+pub fn hex_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len() * 2);
+    for byte in data {
+        out.push_str(&format!("{:02x}", byte));
+    }
+    out
+}
+
+/// Decode case-insensitive hex.
+pub fn hex_decode(input: &str) -> Result<Vec<u8>, FFIError> {
+    if input.len() % 2 != 0 {
+        return Err(FFIError::Error("invalid hex, odd length".to_string()));
+    }
+    (0..input.len())
+        .step_by(2)
+        .map(|i| {
+            input
+                .get(i..i + 2)
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+                .ok_or_else(|| FFIError::Error(format!("invalid hex at offset {}", i)))
+        })
+        .collect()
+}
+
+#[v8_ffi]
+fn encoding_encode(input: String) -> Bytes {
+    Bytes(input.into_bytes())
+}
+
+#[v8_ffi]
+fn encoding_decode(input: Bytes, fatal: bool, ignore_bom: bool) -> Result<String, FFIError> {
+    let mut data = &input[..];
+    if !ignore_bom && data.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        data = &data[3..];
+    }
+    if fatal {
+        String::from_utf8(data.to_vec())
+            .map_err(|_| FFIError::TypeError("the encoded data was not valid utf-8".to_string()))
+    } else {
+        Ok(String::from_utf8_lossy(data).into_owned())
+    }
+}
+
+#[v8_ffi]
+fn encoding_atob(input: String) -> Result<String, FFIError> {
+    let data = base64_decode(&input)?;
+    Ok(data.into_iter().map(|x| x as char).collect())
+}
+
+#[v8_ffi]
+fn encoding_btoa(input: String) -> Result<String, FFIError> {
+    let data: Option<Vec<u8>> = input
+        .chars()
+        .map(|x| {
+            if (x as u32) < 256 {
+                Some(x as u8)
+            } else {
+                None
+            }
+        })
+        .collect();
+    match data {
+        Some(data) => Ok(base64_encode(&data)),
+        None => Err(FFIError::Error(
+            "the string to be encoded contains characters outside of the Latin1 range".to_string(),
+        )),
+    }
+}
+
+const ENCODING_BOOTSTRAP: &str = r#"
+(function (encode, decode, atob, btoa) {
+    const labels = ['utf-8', 'utf8', 'unicode-1-1-utf-8'];
+    class TextEncoder {
+        get encoding() {
+            return 'utf-8';
+        }
+        encode(input = '') {
+            return encode(String(input));
+        }
+    }
+    class TextDecoder {
+        constructor(label = 'utf-8', options = {}) {
+            if (!labels.includes(String(label).trim().toLowerCase())) {
+                throw new RangeError(`The encoding label provided ('${label}') is invalid.`);
+            }
+            this.fatal = !!options.fatal;
+            this.ignoreBOM = !!options.ignoreBOM;
+        }
+        get encoding() {
+            return 'utf-8';
+        }
+        decode(input) {
+            if (input === undefined) return '';
+            return decode(input, this.fatal, this.ignoreBOM);
+        }
+    }
+    this.TextEncoder = TextEncoder;
+    this.TextDecoder = TextDecoder;
+    this.atob = (input) => atob(String(input));
+    this.btoa = (input) => btoa(String(input));
+})
+"#;
+
+/// Installs UTF-8 `TextEncoder`/`TextDecoder`, `atob` and `btoa` globals.
+/// Encoded data is passed as `Uint8Array`s through `Bytes`.
+pub struct EncodingExtension;
+
+impl Extension for EncodingExtension {
+    fn install<'sc, 'c>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<(), FFIError> {
+        let encode = load_v8_ffi!(encoding_encode, scope, context);
+        let decode = load_v8_ffi!(encoding_decode, scope, context);
+        let atob = load_v8_ffi!(encoding_atob, scope, context);
+        let btoa = load_v8_ffi!(encoding_btoa, scope, context);
+        run_bootstrap(
+            scope,
+            context,
+            ENCODING_BOOTSTRAP,
+            &[encode, decode, atob, btoa],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_roundtrip() {
+        assert_eq!(base64_encode(b"hello"), "aGVsbG8=");
+        assert_eq!(base64_encode(b"hell"), "aGVsbA==");
+        assert_eq!(base64_encode(b"hel"), "aGVs");
+        assert_eq!(base64_decode("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(base64_decode("aGVsbA").unwrap(), b"hell");
+        assert!(base64_decode("aGVsb").is_err());
+        assert!(base64_decode("a$==").is_err());
+    }
+
+    #[test]
+    fn hex_roundtrip() {
+        assert_eq!(hex_encode(&[0, 15, 255]), "000fff");
+        assert_eq!(hex_decode("000FfF").unwrap(), vec![0, 15, 255]);
+        assert!(hex_decode("0").is_err());
+        assert!(hex_decode("zz").is_err());
+    }
+}
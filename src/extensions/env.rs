@@ -0,0 +1,192 @@
+use super::{run_bootstrap, Extension};
+use crate::util::*;
+use crate::FFIError;
+use rusty_v8 as v8;
+use rusty_v8_helper_derive::v8_ffi;
+use std::collections::BTreeSet;
+use std::rc::Rc;
+
+/// `EnvProvider` supplies the variables scripts can read through the `env`
+/// global of `EnvExtension`. Closures taking a variable name are providers
+/// too, which cannot be listed with `env.all()`.
+pub trait EnvProvider {
+    fn get(&self, name: &str) -> Option<String>;
+
+    /// Every variable, for `env.all()`, by name.
+    fn all(&self) -> Vec<(String, String)> {
+        vec![]
+    }
+}
+
+impl<F: Fn(&str) -> Option<String>> EnvProvider for F {
+    fn get(&self, name: &str) -> Option<String> {
+        self(name)
+    }
+}
+
+/// `EnvProvider` reading only the named variables of the process
+/// environment.
+#[derive(Debug, Clone, Default)]
+pub struct EnvAllowList(BTreeSet<String>);
+
+impl EnvAllowList {
+    pub fn new(names: &[&str]) -> EnvAllowList {
+        EnvAllowList(names.iter().map(|x| x.to_string()).collect())
+    }
+
+    pub fn allow(&mut self, name: &str) {
+        self.0.insert(name.to_string());
+    }
+}
+
+impl EnvProvider for EnvAllowList {
+    fn get(&self, name: &str) -> Option<String> {
+        if self.0.contains(name) {
+            std::env::var(name).ok()
+        } else {
+            None
+        }
+    }
+
+    fn all(&self) -> Vec<(String, String)> {
+        self.0
+            .iter()
+            .filter_map(|name| Some((name.clone(), std::env::var(name).ok()?)))
+            .collect()
+    }
+}
+
+struct EnvReader {
+    provider: Rc<dyn EnvProvider>,
+}
+
+#[v8_ffi]
+fn env_get(this: &EnvReader, name: String) -> Option<String> {
+    this.provider.get(&name)
+}
+
+#[v8_ffi]
+fn env_all(this: &EnvReader) -> Vec<(String, String)> {
+    this.provider.all()
+}
+
+const ENV_BOOTSTRAP: &str = r#"
+(function (reader, get, all, withAll, os, arch) {
+    const env = {
+        get: (name) => get.call(reader, String(name)),
+        has: (name) => get.call(reader, String(name)) != null,
+        os,
+        arch,
+    };
+    if (withAll) {
+        env.all = () => Object.fromEntries(all.call(reader));
+    }
+    this.env = Object.freeze(env);
+})
+"#;
+
+/// Installs a frozen global `env` through which scripts read configuration
+/// without access to the whole process environment: `env.get(name)`, the
+/// variable or `null`, `env.has(name)`, and with `with_all`,
+/// `env.all()`, an object of every variable the provider lists. `env.os`
+/// and `env.arch` are the platform the host was built for.
+///
+/// Each installation reads through its own provider, so contexts can be
+/// given different views of the environment.
+///
+/// ```ignore
+/// EnvExtension::new(EnvAllowList::new(&["HOME", "LANG"])).with_all().install(scope, context)?;
+/// EnvExtension::new(|name: &str| config.get(name).cloned()).install(scope, plugin)?;
+/// ```
+pub struct EnvExtension {
+    provider: Rc<dyn EnvProvider>,
+    with_all: bool,
+}
+
+impl EnvExtension {
+    pub fn new(provider: impl EnvProvider + 'static) -> EnvExtension {
+        EnvExtension {
+            provider: Rc::new(provider),
+            with_all: false,
+        }
+    }
+
+    /// Also install `env.all()`.
+    pub fn with_all(mut self) -> EnvExtension {
+        self.with_all = true;
+        self
+    }
+}
+
+impl Extension for EnvExtension {
+    fn install<'sc, 'c>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<(), FFIError> {
+        let mut reader = make_object_wrap(
+            scope,
+            context,
+            EnvReader {
+                provider: self.provider.clone(),
+            },
+        );
+        reader.make_weak();
+        let reader = reader.get(scope).unwrap().into();
+        let get = load_v8_ffi!(env_get, scope, context);
+        let all = load_v8_ffi!(env_all, scope, context);
+        let with_all = make_bool(scope, self.with_all);
+        let os = make_str(scope, std::env::consts::OS);
+        let arch = make_str(scope, std::env::consts::ARCH);
+        run_bootstrap(
+            scope,
+            context,
+            ENV_BOOTSTRAP,
+            &[reader, get, all, with_all, os, arch],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_extension() {
+        std::env::set_var("RUSTY_V8_HELPER_ENV", "visible");
+        std::env::set_var("RUSTY_V8_HELPER_SECRET", "hidden");
+        let allowed = EnvAllowList::new(&["RUSTY_V8_HELPER_ENV", "RUSTY_V8_HELPER_UNSET"]);
+        let result: String = crate::Embed::new()
+            .extension(EnvExtension::new(allowed).with_all())
+            .eval(
+                r#"
+                env.get = null;
+                [
+                    env.get('RUSTY_V8_HELPER_ENV'),
+                    env.get('RUSTY_V8_HELPER_SECRET'),
+                    env.has('RUSTY_V8_HELPER_UNSET'),
+                    JSON.stringify(env.all()),
+                ].join()
+                "#,
+                "env.js",
+            )
+            .unwrap();
+        assert_eq!(
+            result,
+            "visible,,false,{\"RUSTY_V8_HELPER_ENV\":\"visible\"}"
+        );
+
+        let provider = |name: &str| match name {
+            "MODE" => Some("test".to_string()),
+            _ => None,
+        };
+        let result: String = crate::Embed::new()
+            .extension(EnvExtension::new(provider))
+            .eval(
+                "`${env.get('MODE')} ${env.has('HOME')} ${typeof env.all}`",
+                "env.js",
+            )
+            .unwrap();
+        assert_eq!(result, "test false undefined");
+    }
+}
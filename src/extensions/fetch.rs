@@ -0,0 +1,355 @@
+use super::{run_bootstrap, Extension};
+use crate::executor::{executor, spawn_local};
+use crate::util::*;
+use crate::Bytes;
+use crate::CancellationToken;
+use crate::FFICompat;
+use crate::FFIError;
+use rusty_v8 as v8;
+use rusty_v8_helper_derive::v8_ffi;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// A request made by script through `fetch()`.
+#[derive(Clone)]
+pub struct HttpRequest {
+    pub url: String,
+    pub method: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+    /// Cancelled when the `signal` passed to `fetch()` is aborted.
+    pub signal: Option<CancellationToken>,
+}
+
+/// A response handed back to script as a `Response` object.
+#[derive(Clone, Debug, Default)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub status_text: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub url: String,
+}
+
+pub type HttpFuture = Pin<Box<dyn Future<Output = Result<HttpResponse, String>> + Send>>;
+
+/// `HttpBackend` performs the actual network requests behind `fetch()`.
+///
+/// Returned futures are driven on the isolate's executor, see
+/// `set_executor`.
+pub trait HttpBackend: Send + Sync + 'static {
+    fn fetch(&self, request: HttpRequest) -> HttpFuture;
+}
+
+struct FetchBackend(Arc<dyn HttpBackend>);
+
+/// The result of a backend fetch, converted to a wrapped `HttpResponse`
+/// back on the isolate thread. It is only ever returned to JS, never taken
+/// as an argument.
+struct FetchResult(Result<HttpResponse, String>);
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for FetchResult {
+    type E = FFIError;
+
+    fn from_value(
+        _value: v8::Local<'sc, v8::Value>,
+        _scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        Err(FFIError::TypeError(
+            "a fetch result cannot be passed to an ffi call".to_string(),
+        ))
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        match self.0 {
+            Ok(response) => {
+                let mut wrapped = make_object_wrap(scope, context, response);
+                wrapped.make_weak();
+                Ok(wrapped.get(scope).unwrap().into())
+            }
+            Err(e) => Err(FFIError::TypeError(format!("fetch failed: {}", e))),
+        }
+    }
+}
+
+#[v8_ffi(scoped)]
+fn fetch_native<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    _context: v8::Local<'c, v8::Context>,
+    url: String,
+    method: String,
+    headers: Vec<(String, String)>,
+    body_text: Option<String>,
+    body_bytes: Option<Bytes>,
+    signal: Option<CancellationToken>,
+) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+    if executor(scope).is_none() {
+        return Err(FFIError::Error(
+            "no executor set for this isolate".to_string(),
+        ));
+    }
+    let backend = isolate_slot::<FetchBackend>(scope)
+        .ok_or_else(|| FFIError::Error("no fetch backend installed".to_string()))?
+        .0
+        .clone();
+    let body = body_text
+        .map(|x| x.into_bytes())
+        .or_else(|| body_bytes.map(|x| x.0));
+    let request = HttpRequest {
+        url,
+        method,
+        headers,
+        body,
+        signal,
+    };
+    let response = backend.fetch(request);
+    Ok(spawn_local(scope, async move { FetchResult(response.await) }).into())
+}
+
+#[v8_ffi]
+fn fetch_response_meta(this: &HttpResponse) -> (u32, String, Vec<(String, String)>, String) {
+    (
+        this.status as u32,
+        this.status_text.clone(),
+        this.headers.clone(),
+        this.url.clone(),
+    )
+}
+
+#[v8_ffi]
+fn fetch_response_bytes(this: &HttpResponse) -> Bytes {
+    Bytes(this.body.clone())
+}
+
+#[v8_ffi]
+fn fetch_response_text(this: &HttpResponse) -> String {
+    String::from_utf8_lossy(&this.body).into_owned()
+}
+
+const FETCH_BOOTSTRAP: &str = r#"
+(function (nativeFetch, responseMeta, responseBytes, responseText) {
+    class Headers {
+        constructor(init) {
+            this._entries = new Map();
+            if (init instanceof Headers) {
+                init.forEach((value, name) => this.append(name, value));
+            } else if (Array.isArray(init)) {
+                for (const [name, value] of init) this.append(name, value);
+            } else if (init) {
+                for (const name of Object.keys(init)) this.append(name, init[name]);
+            }
+        }
+        append(name, value) {
+            name = String(name).toLowerCase();
+            const existing = this._entries.get(name);
+            this._entries.set(name, existing === undefined ? String(value) : `${existing}, ${value}`);
+        }
+        set(name, value) {
+            this._entries.set(String(name).toLowerCase(), String(value));
+        }
+        get(name) {
+            const value = this._entries.get(String(name).toLowerCase());
+            return value === undefined ? null : value;
+        }
+        has(name) {
+            return this._entries.has(String(name).toLowerCase());
+        }
+        delete(name) {
+            this._entries.delete(String(name).toLowerCase());
+        }
+        forEach(callback, thisArg) {
+            for (const [name, value] of this._entries) callback.call(thisArg, value, name, this);
+        }
+        entries() {
+            return this._entries.entries();
+        }
+        [Symbol.iterator]() {
+            return this._entries.entries();
+        }
+    }
+    class Response {
+        constructor(native) {
+            const [status, statusText, headers, url] = responseMeta.call(native);
+            this._native = native;
+            this.status = status;
+            this.statusText = statusText;
+            this.ok = status >= 200 && status < 300;
+            this.headers = new Headers(headers);
+            this.url = url;
+            this.bodyUsed = false;
+        }
+        _consume(read) {
+            if (this.bodyUsed) {
+                return Promise.reject(new TypeError('body has already been consumed'));
+            }
+            this.bodyUsed = true;
+            return Promise.resolve(read.call(this._native));
+        }
+        arrayBuffer() {
+            return this._consume(responseBytes).then(bytes => bytes.buffer);
+        }
+        text() {
+            return this._consume(responseText);
+        }
+        json() {
+            return this.text().then(JSON.parse);
+        }
+    }
+    this.Headers = Headers;
+    this.Response = Response;
+    this.fetch = (input, init = {}) => {
+        try {
+            const url = typeof input === 'string' ? input : String(input.url || input);
+            const method = String(init.method || 'GET').toUpperCase();
+            const headers = [];
+            new Headers(init.headers).forEach((value, name) => headers.push([name, value]));
+            const body = init.body === undefined || init.body === null ? null : init.body;
+            const bodyText = typeof body === 'string' ? body : null;
+            const bodyBytes = typeof body === 'string' ? null : body;
+            const signal = init.signal;
+            if (signal && signal.aborted) {
+                return Promise.reject(signal.reason);
+            }
+            const response = nativeFetch(url, method, headers, bodyText, bodyBytes, signal)
+                .then(native => new Response(native));
+            if (!signal) {
+                return response;
+            }
+            return new Promise((resolve, reject) => {
+                const onAbort = () => reject(signal.reason);
+                signal.addEventListener('abort', onAbort);
+                response.then(
+                    value => {
+                        signal.removeEventListener('abort', onAbort);
+                        resolve(value);
+                    },
+                    error => {
+                        signal.removeEventListener('abort', onAbort);
+                        reject(error);
+                    },
+                );
+            });
+        } catch (e) {
+            return Promise.reject(e);
+        }
+    };
+})
+"#;
+
+/// Installs spec-shaped `fetch`, `Headers` and `Response` globals that
+/// perform requests through an `HttpBackend`.
+///
+/// Requests run on the isolate's executor and returned promises are settled
+/// on its event loop, see `run_event_loop`. A promise is rejected with the
+/// signal's `reason` as soon as its `AbortSignal` is aborted, while the
+/// backend sees the request's `signal` cancelled.
+pub struct FetchExtension {
+    backend: Arc<dyn HttpBackend>,
+}
+
+impl FetchExtension {
+    pub fn new(backend: impl HttpBackend) -> FetchExtension {
+        FetchExtension {
+            backend: Arc::new(backend),
+        }
+    }
+}
+
+impl Extension for FetchExtension {
+    fn install<'sc, 'c>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<(), FFIError> {
+        set_isolate_slot(scope, FetchBackend(self.backend.clone()));
+        let native_fetch = load_v8_ffi!(fetch_native, scope, context);
+        let response_meta = load_v8_ffi!(fetch_response_meta, scope, context);
+        let response_bytes = load_v8_ffi!(fetch_response_bytes, scope, context);
+        let response_text = load_v8_ffi!(fetch_response_text, scope, context);
+        run_bootstrap(
+            scope,
+            context,
+            FETCH_BOOTSTRAP,
+            &[native_fetch, response_meta, response_bytes, response_text],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::abort::AbortExtension;
+    use std::task::{Context, Poll};
+
+    /// Answers `/ok` with its method and body, and holds any other request
+    /// until its signal is cancelled.
+    struct TestBackend;
+
+    struct UntilCancelled(Option<CancellationToken>);
+
+    impl Future for UntilCancelled {
+        type Output = Result<HttpResponse, String>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+            match &self.0 {
+                Some(signal) if !signal.is_cancelled() => {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+                _ => Poll::Ready(Err("cancelled".to_string())),
+            }
+        }
+    }
+
+    impl HttpBackend for TestBackend {
+        fn fetch(&self, request: HttpRequest) -> HttpFuture {
+            if request.url != "/ok" {
+                return Box::pin(UntilCancelled(request.signal));
+            }
+            let mut body = request.method.into_bytes();
+            body.extend(request.body.unwrap_or_default());
+            Box::pin(async move {
+                Ok(HttpResponse {
+                    status: 200,
+                    status_text: "OK".to_string(),
+                    headers: vec![("x-test".to_string(), "1".to_string())],
+                    body,
+                    url: "/ok".to_string(),
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn fetch_extension() {
+        let result: String = crate::Embed::new()
+            .extension(AbortExtension)
+            .extension(FetchExtension::new(TestBackend))
+            .eval(
+                r#"
+                const log = [];
+                (async () => {
+                    const response = await fetch('/ok', { method: 'post', body: ':hi' });
+                    log.push(`${response.status} ${response.headers.get('X-Test')}`);
+                    log.push(await response.text());
+                    const controller = new AbortController();
+                    const pending = fetch('/slow', { signal: controller.signal });
+                    controller.abort('stopped');
+                    await pending.catch((e) => log.push(e));
+                    await fetch('/ok', { signal: controller.signal }).catch((e) => log.push(e));
+                })();
+                log
+                "#,
+                "fetch.js",
+            )
+            .map(|log: Vec<String>| log.join("\n"))
+            .unwrap();
+        assert_eq!(result, "200 1\nPOST:hi\nstopped\nstopped");
+    }
+}
@@ -0,0 +1,548 @@
+use super::{run_bootstrap, Extension};
+use crate::module::resolved_jailed_path;
+use crate::spawn_blocking_ffi;
+use crate::util::*;
+use crate::{Bytes, FFICompat, FFIError, FFIObject};
+use rusty_v8 as v8;
+use rusty_v8_helper_derive::v8_ffi;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What `fs.stat` reports about a path.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FFIObject)]
+#[serde(rename_all = "camelCase")]
+pub struct FsStat {
+    pub is_file: bool,
+    pub is_directory: bool,
+    pub size: u64,
+    /// The last modification, in milliseconds since the Unix epoch, if
+    /// known.
+    pub modified_ms: Option<f64>,
+}
+
+/// `FsBackend` is the filesystem behind the `fs` global of `FsExtension`.
+/// Paths are as given by scripts, `/`-separated; backends decide what they
+/// are relative to and which are refused.
+///
+/// Backends are called on the blocking thread pool for the promise
+/// variants, so they must be `Send` and `Sync`.
+pub trait FsBackend: Send + Sync + 'static {
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>>;
+
+    /// Create or replace the file at `path`.
+    fn write_file(&self, path: &str, data: &[u8]) -> io::Result<()>;
+
+    /// The names of the entries of the directory at `path`, sorted.
+    fn read_dir(&self, path: &str) -> io::Result<Vec<String>>;
+
+    fn stat(&self, path: &str) -> io::Result<FsStat>;
+}
+
+/// `FsBackend` over the directory `root` of the real filesystem. Paths are
+/// relative to `root`, and paths with `..` are refused. Symbolic links
+/// are followed as long as they resolve within `root`, and refused
+/// otherwise.
+pub struct JailedFs {
+    root: PathBuf,
+}
+
+impl JailedFs {
+    pub fn new(root: impl Into<PathBuf>) -> JailedFs {
+        JailedFs { root: root.into() }
+    }
+
+    fn path(&self, path: &str) -> io::Result<PathBuf> {
+        resolved_jailed_path(&self.root, path)?.ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::PermissionDenied,
+                "path escapes the filesystem root",
+            )
+        })
+    }
+}
+
+impl FsBackend for JailedFs {
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        std::fs::read(self.path(path)?)
+    }
+
+    fn write_file(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        std::fs::write(self.path(path)?, data)
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<String>> {
+        let mut names = vec![];
+        for entry in std::fs::read_dir(self.path(path)?)? {
+            names.push(entry?.file_name().to_string_lossy().into_owned());
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn stat(&self, path: &str) -> io::Result<FsStat> {
+        let metadata = std::fs::metadata(self.path(path)?)?;
+        Ok(FsStat {
+            is_file: metadata.is_file(),
+            is_directory: metadata.is_dir(),
+            size: metadata.len(),
+            modified_ms: metadata.modified().ok().and_then(epoch_millis),
+        })
+    }
+}
+
+fn epoch_millis(time: SystemTime) -> Option<f64> {
+    Some(time.duration_since(UNIX_EPOCH).ok()?.as_secs_f64() * 1000.0)
+}
+
+struct MemoryFile {
+    data: Vec<u8>,
+    modified_ms: Option<f64>,
+}
+
+/// `FsBackend` keeping files in memory, i.e. for tests. Directories exist
+/// as long as they have files below them, and the root always does.
+#[derive(Default)]
+pub struct MemoryFs {
+    files: Mutex<BTreeMap<String, MemoryFile>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> MemoryFs {
+        MemoryFs::default()
+    }
+
+    /// Add the file `path` with `data`, replacing any there was.
+    pub fn insert(&self, path: &str, data: impl Into<Vec<u8>>) -> io::Result<()> {
+        self.write_file(path, &data.into())
+    }
+
+    /// The contents of the file `path`, i.e. to check what a script wrote.
+    pub fn get(&self, path: &str) -> Option<Vec<u8>> {
+        let path = normalize(path).ok()?;
+        self.files
+            .lock()
+            .unwrap()
+            .get(&path)
+            .map(|x| x.data.clone())
+    }
+}
+
+/// `path` without empty or `.` segments, `""` for the root.
+fn normalize(path: &str) -> io::Result<String> {
+    let mut segments = vec![];
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => (),
+            ".." => {
+                return Err(io::Error::new(
+                    ErrorKind::PermissionDenied,
+                    "path escapes the filesystem root",
+                ))
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Ok(segments.join("/"))
+}
+
+fn not_found() -> io::Error {
+    io::Error::new(ErrorKind::NotFound, "no such file or directory")
+}
+
+/// The names directly below the directory `dir`, if it is one.
+fn children(files: &BTreeMap<String, MemoryFile>, dir: &str) -> Option<Vec<String>> {
+    let prefix = if dir.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", dir)
+    };
+    let mut names: Vec<String> = files
+        .keys()
+        .filter_map(|path| path.strip_prefix(&prefix))
+        .map(|rest| rest.split('/').next().unwrap().to_string())
+        .collect();
+    names.sort();
+    names.dedup();
+    if names.is_empty() && !dir.is_empty() {
+        None
+    } else {
+        Some(names)
+    }
+}
+
+impl FsBackend for MemoryFs {
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        let path = normalize(path)?;
+        let files = self.files.lock().unwrap();
+        match files.get(&path) {
+            Some(file) => Ok(file.data.clone()),
+            None if children(&files, &path).is_some() => {
+                Err(io::Error::new(ErrorKind::Other, "is a directory"))
+            }
+            None => Err(not_found()),
+        }
+    }
+
+    fn write_file(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        let path = normalize(path)?;
+        let mut files = self.files.lock().unwrap();
+        if children(&files, &path).is_some() {
+            return Err(io::Error::new(ErrorKind::Other, "is a directory"));
+        }
+        let file = MemoryFile {
+            data: data.to_vec(),
+            modified_ms: epoch_millis(SystemTime::now()),
+        };
+        files.insert(path, file);
+        Ok(())
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<String>> {
+        let path = normalize(path)?;
+        let files = self.files.lock().unwrap();
+        if files.contains_key(&path) {
+            return Err(io::Error::new(ErrorKind::Other, "not a directory"));
+        }
+        children(&files, &path).ok_or_else(not_found)
+    }
+
+    fn stat(&self, path: &str) -> io::Result<FsStat> {
+        let path = normalize(path)?;
+        let files = self.files.lock().unwrap();
+        if let Some(file) = files.get(&path) {
+            return Ok(FsStat {
+                is_file: true,
+                is_directory: false,
+                size: file.data.len() as u64,
+                modified_ms: file.modified_ms,
+            });
+        }
+        children(&files, &path).ok_or_else(not_found)?;
+        Ok(FsStat {
+            is_file: false,
+            is_directory: true,
+            size: 0,
+            modified_ms: None,
+        })
+    }
+}
+
+/// The contents of a file, as text or as a `Uint8Array`.
+enum FileData {
+    Text(String),
+    Bytes(Bytes),
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for FileData {
+    type E = String;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, String> {
+        if value.is_string() {
+            String::from_value(value, scope, context).map(FileData::Text)
+        } else {
+            Bytes::from_value(value, scope, context).map(FileData::Bytes)
+        }
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, String> {
+        match self {
+            FileData::Text(text) => text.to_value(scope, context),
+            FileData::Bytes(bytes) => bytes.to_value(scope, context),
+        }
+    }
+}
+
+/// A node-style error, i.e. `ENOENT: no such file or directory, open 'a'`.
+fn fs_error(call: &str, path: &str, error: io::Error) -> FFIError {
+    let code = match error.kind() {
+        ErrorKind::NotFound => "ENOENT",
+        ErrorKind::PermissionDenied => "EACCES",
+        ErrorKind::AlreadyExists => "EEXIST",
+        ErrorKind::InvalidData => "EILSEQ",
+        _ => "EIO",
+    };
+    FFIError::Error(format!("{}: {}, {} '{}'", code, error, call, path))
+}
+
+fn read_file(backend: &dyn FsBackend, path: &str, text: bool) -> Result<FileData, FFIError> {
+    let data = backend
+        .read_file(path)
+        .map_err(|e| fs_error("open", path, e))?;
+    if !text {
+        return Ok(FileData::Bytes(Bytes(data)));
+    }
+    String::from_utf8(data)
+        .map(FileData::Text)
+        .map_err(|_| FFIError::TypeError(format!("{} is not valid UTF-8", path)))
+}
+
+fn write_file(backend: &dyn FsBackend, path: &str, data: FileData) -> Result<(), FFIError> {
+    let data = match &data {
+        FileData::Text(text) => text.as_bytes(),
+        FileData::Bytes(bytes) => &bytes[..],
+    };
+    backend
+        .write_file(path, data)
+        .map_err(|e| fs_error("open", path, e))
+}
+
+fn read_dir(backend: &dyn FsBackend, path: &str) -> Result<Vec<String>, FFIError> {
+    backend
+        .read_dir(path)
+        .map_err(|e| fs_error("scandir", path, e))
+}
+
+fn stat(backend: &dyn FsBackend, path: &str) -> Result<FsStat, FFIError> {
+    backend.stat(path).map_err(|e| fs_error("stat", path, e))
+}
+
+struct FsHandle {
+    backend: Arc<dyn FsBackend>,
+}
+
+#[v8_ffi]
+fn fs_read_file(this: &FsHandle, path: String, text: bool) -> Result<FileData, FFIError> {
+    read_file(&*this.backend, &path, text)
+}
+
+#[v8_ffi]
+fn fs_write_file(this: &FsHandle, path: String, data: FileData) -> Result<(), FFIError> {
+    write_file(&*this.backend, &path, data)
+}
+
+#[v8_ffi]
+fn fs_read_dir(this: &FsHandle, path: String) -> Result<Vec<String>, FFIError> {
+    read_dir(&*this.backend, &path)
+}
+
+#[v8_ffi]
+fn fs_stat(this: &FsHandle, path: String) -> Result<FsStat, FFIError> {
+    stat(&*this.backend, &path)
+}
+
+#[v8_ffi(scoped)]
+fn fs_read_file_async<'sc, 'c>(
+    this: &FsHandle,
+    scope: &mut impl v8::ToLocal<'sc>,
+    _context: v8::Local<'c, v8::Context>,
+    path: String,
+    text: bool,
+) -> v8::Local<'sc, v8::Value> {
+    let backend = this.backend.clone();
+    spawn_blocking_ffi(scope, move || read_file(&*backend, &path, text)).into()
+}
+
+#[v8_ffi(scoped)]
+fn fs_write_file_async<'sc, 'c>(
+    this: &FsHandle,
+    scope: &mut impl v8::ToLocal<'sc>,
+    _context: v8::Local<'c, v8::Context>,
+    path: String,
+    data: FileData,
+) -> v8::Local<'sc, v8::Value> {
+    let backend = this.backend.clone();
+    spawn_blocking_ffi(scope, move || write_file(&*backend, &path, data)).into()
+}
+
+#[v8_ffi(scoped)]
+fn fs_read_dir_async<'sc, 'c>(
+    this: &FsHandle,
+    scope: &mut impl v8::ToLocal<'sc>,
+    _context: v8::Local<'c, v8::Context>,
+    path: String,
+) -> v8::Local<'sc, v8::Value> {
+    let backend = this.backend.clone();
+    spawn_blocking_ffi(scope, move || read_dir(&*backend, &path)).into()
+}
+
+#[v8_ffi(scoped)]
+fn fs_stat_async<'sc, 'c>(
+    this: &FsHandle,
+    scope: &mut impl v8::ToLocal<'sc>,
+    _context: v8::Local<'c, v8::Context>,
+    path: String,
+) -> v8::Local<'sc, v8::Value> {
+    let backend = this.backend.clone();
+    spawn_blocking_ffi(scope, move || stat(&*backend, &path)).into()
+}
+
+const FS_BOOTSTRAP: &str = r#"
+(function (handle, readFile, writeFile, readDir, stat,
+    readFileAsync, writeFileAsync, readDirAsync, statAsync) {
+    const isText = (options) => {
+        const encoding = typeof options === 'string' ? options : options && options.encoding;
+        if (encoding === undefined || encoding === null) return false;
+        if (encoding !== 'utf8' && encoding !== 'utf-8') {
+            throw new TypeError(`unsupported encoding: ${encoding}`);
+        }
+        return true;
+    };
+    this.fs = Object.freeze({
+        readFileSync: (path, options) => readFile.call(handle, String(path), isText(options)),
+        writeFileSync: (path, data) => writeFile.call(handle, String(path), data),
+        readDirSync: (path) => readDir.call(handle, String(path)),
+        statSync: (path) => stat.call(handle, String(path)),
+        readFile: async (path, options) =>
+            readFileAsync.call(handle, String(path), isText(options)),
+        writeFile: async (path, data) => writeFileAsync.call(handle, String(path), data),
+        readDir: async (path) => readDirAsync.call(handle, String(path)),
+        stat: async (path) => statAsync.call(handle, String(path)),
+    });
+})
+"#;
+
+/// Installs a frozen global `fs` over an `FsBackend`, such as `JailedFs`
+/// or `MemoryFs`, with `readFile(path, encoding)`, `writeFile(path, data)`,
+/// `readDir(path)` and `stat(path)` returning promises, and their
+/// blocking `readFileSync`, `writeFileSync`, `readDirSync` and `statSync`
+/// variants.
+///
+/// Files are read as a `Uint8Array`, or as a string with the `utf8`
+/// encoding, and written from either. Errors are thrown with node-style
+/// messages, i.e. `ENOENT: no such file or directory, open 'a.txt'`.
+/// Promises are settled on the event loop, see `event_loop::run_until_idle`.
+///
+/// Each installation uses its own backend, so contexts can be given
+/// different filesystems.
+///
+/// ```ignore
+/// FsExtension::new(JailedFs::new("/srv/plugins/data")).install(scope, context)?;
+/// ```
+pub struct FsExtension {
+    backend: Arc<dyn FsBackend>,
+}
+
+impl FsExtension {
+    pub fn new(backend: impl FsBackend) -> FsExtension {
+        FsExtension {
+            backend: Arc::new(backend),
+        }
+    }
+
+    /// Share `backend` with the host, i.e. a `MemoryFs` to check the files
+    /// a script wrote.
+    pub fn shared(backend: Arc<dyn FsBackend>) -> FsExtension {
+        FsExtension { backend }
+    }
+}
+
+impl Extension for FsExtension {
+    fn install<'sc, 'c>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<(), FFIError> {
+        let mut handle = make_object_wrap(
+            scope,
+            context,
+            FsHandle {
+                backend: self.backend.clone(),
+            },
+        );
+        handle.make_weak();
+        let handle = handle.get(scope).unwrap().into();
+        let args = [
+            handle,
+            load_v8_ffi!(fs_read_file, scope, context),
+            load_v8_ffi!(fs_write_file, scope, context),
+            load_v8_ffi!(fs_read_dir, scope, context),
+            load_v8_ffi!(fs_stat, scope, context),
+            load_v8_ffi!(fs_read_file_async, scope, context),
+            load_v8_ffi!(fs_write_file_async, scope, context),
+            load_v8_ffi!(fs_read_dir_async, scope, context),
+            load_v8_ffi!(fs_stat_async, scope, context),
+        ];
+        run_bootstrap(scope, context, FS_BOOTSTRAP, &args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fs_extension() {
+        let memory = std::sync::Arc::new(MemoryFs::new());
+        memory.insert("data/in.txt", "hello").unwrap();
+        memory.insert("data/sub/deep.bin", vec![1, 2]).unwrap();
+        let result: String = crate::Embed::new()
+            .extension(FsExtension::shared(memory.clone()))
+            .eval(
+                r#"
+                const log = [];
+                log.push(fs.readFileSync('/data/in.txt', 'utf8'));
+                log.push(fs.readFileSync('data/sub/deep.bin').join('+'));
+                log.push(fs.readDirSync('data').join());
+                const stat = fs.statSync('data/in.txt');
+                log.push(`${stat.isFile} ${stat.isDirectory} ${stat.size}`);
+                try { fs.readFileSync('../etc/passwd') } catch (e) { log.push(e.message) }
+                fs.writeFileSync('data/out.txt', 'written');
+                (async () => {
+                    await fs.writeFile('data/async.bin', new Uint8Array([7]));
+                    log.push(await fs.readFile('data/out.txt', { encoding: 'utf8' }));
+                    log.push(String((await fs.stat('data/sub')).isDirectory));
+                    log.push(await fs.readFile('missing.txt').catch((e) => e.message));
+                    log.push(await fs.readFile('data/in.txt', 'latin1').catch((e) => e.name));
+                })();
+                log
+                "#,
+                "fs.js",
+            )
+            .map(|log: Vec<String>| log.join("\n"))
+            .unwrap();
+        assert_eq!(
+            result,
+            "hello\n1+2\nin.txt,sub\ntrue false 5\n\
+             EACCES: path escapes the filesystem root, open '../etc/passwd'\n\
+             written\ntrue\nENOENT: no such file or directory, open 'missing.txt'\nTypeError"
+        );
+        assert_eq!(memory.get("data/async.bin"), Some(vec![7]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn jailed_symlinks() {
+        let dir = std::env::temp_dir().join("rusty_v8_helper_fs_jail");
+        let _ = std::fs::remove_dir_all(&dir);
+        let root = dir.join("root");
+        std::fs::create_dir_all(root.join("data")).unwrap();
+        std::fs::create_dir_all(dir.join("outside")).unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        std::fs::write(root.join("data/in.txt"), "hello").unwrap();
+        std::os::unix::fs::symlink(dir.join("secret.txt"), root.join("leak.txt")).unwrap();
+        std::os::unix::fs::symlink(dir.join("outside"), root.join("out")).unwrap();
+        std::os::unix::fs::symlink(root.join("data"), root.join("alias")).unwrap();
+
+        let fs = JailedFs::new(root.clone());
+        assert_eq!(fs.read_file("alias/in.txt").unwrap(), b"hello");
+        fs.write_file("alias/new.txt", b"new").unwrap();
+        assert_eq!(fs.read_file("data/new.txt").unwrap(), b"new");
+        let denied = |result: io::Result<()>| result.unwrap_err().kind();
+        assert_eq!(
+            denied(fs.read_file("leak.txt").map(drop)),
+            ErrorKind::PermissionDenied
+        );
+        assert_eq!(
+            denied(fs.write_file("out/planted.txt", b"x")),
+            ErrorKind::PermissionDenied
+        );
+        assert_eq!(
+            denied(fs.stat("out").map(drop)),
+            ErrorKind::PermissionDenied
+        );
+        assert!(!dir.join("outside/planted.txt").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,213 @@
+use super::{run_bootstrap, Extension};
+use crate::spawn_blocking_ffi;
+use crate::util::*;
+use crate::FFIError;
+use rusty_v8 as v8;
+use rusty_v8_helper_derive::v8_ffi;
+use std::cell::RefCell;
+use std::io::{self, BufRead, BufReader, Write};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+type Input = Arc<Mutex<Box<dyn BufRead + Send>>>;
+
+struct Streams {
+    input: Input,
+    output: RefCell<Box<dyn Write>>,
+    error: RefCell<Box<dyn Write>>,
+}
+
+struct IoStreams(Rc<Streams>);
+
+fn streams(scope: &mut impl v8::InIsolate) -> Result<Rc<Streams>, FFIError> {
+    isolate_slot::<IoStreams>(scope)
+        .map(|x| x.0.clone())
+        .ok_or_else(|| FFIError::Error("no io streams installed".to_string()))
+}
+
+/// Read a line from `input` without its line ending, or `None` at the end
+/// of input.
+fn read_line(input: &Input) -> Result<Option<String>, FFIError> {
+    let mut line = String::new();
+    let read = input
+        .lock()
+        .unwrap()
+        .read_line(&mut line)
+        .map_err(|e| FFIError::Error(format!("failed to read input: {}", e)))?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Some(line))
+}
+
+#[v8_ffi(scoped)]
+fn io_read_line<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    _context: v8::Local<'c, v8::Context>,
+) -> Result<Option<String>, FFIError> {
+    read_line(&streams(scope)?.input)
+}
+
+/// A promise of the next line, read on the blocking thread pool.
+#[v8_ffi(scoped)]
+fn io_read_line_async<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    _context: v8::Local<'c, v8::Context>,
+) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+    let input = streams(scope)?.input.clone();
+    Ok(spawn_blocking_ffi(scope, move || read_line(&input)).into())
+}
+
+/// Write `text` to the output, or with `error` set, the error output.
+#[v8_ffi(scoped)]
+fn io_write<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    _context: v8::Local<'c, v8::Context>,
+    error: bool,
+    text: String,
+) -> Result<(), FFIError> {
+    let streams = streams(scope)?;
+    let mut out = if error {
+        streams.error.borrow_mut()
+    } else {
+        streams.output.borrow_mut()
+    };
+    out.write_all(text.as_bytes())
+        .and_then(|()| out.flush())
+        .map_err(|e| FFIError::Error(format!("failed to write output: {}", e)))
+}
+
+const IO_BOOTSTRAP: &str = r#"
+(function (readLine, readLineAsync, write) {
+    const format = (args) => args.map(String).join(' ') + '\n';
+    this.readLine = () => readLine();
+    this.print = (...args) => write(false, format(args));
+    this.eprint = (...args) => write(true, format(args));
+    this.stdin = {
+        readLine: () => readLineAsync(),
+        [Symbol.asyncIterator]() {
+            return {
+                next: () => readLineAsync().then((line) => line === null
+                    ? { done: true, value: undefined }
+                    : { done: false, value: line }),
+            };
+        },
+    };
+})
+"#;
+
+/// Installs global `readLine()`, `print(...args)` and `eprint(...args)`,
+/// and `stdin`, whose lines can be read with `await stdin.readLine()` or
+/// `for await (const line of stdin)`, for command line script hosts.
+///
+/// `readLine` blocks the isolate thread until a line is read, while
+/// `stdin` reads on the blocking thread pool, settling on the event loop.
+/// Both return `null` at the end of input. `print` and `eprint` join their
+/// arguments with spaces and end the line, flushing every write.
+///
+/// The streams default to the process' stdin, stdout and stderr; the
+/// extension can be installed into several contexts, which share them.
+pub struct IoExtension {
+    streams: Rc<Streams>,
+}
+
+impl IoExtension {
+    pub fn new() -> IoExtension {
+        IoExtension::with_streams(BufReader::new(io::stdin()), io::stdout(), io::stderr())
+    }
+
+    /// Read lines from `input` and write to `output` and `error` rather
+    /// than the standard streams, i.e. to capture a script's output.
+    pub fn with_streams(
+        input: impl BufRead + Send + 'static,
+        output: impl Write + 'static,
+        error: impl Write + 'static,
+    ) -> IoExtension {
+        IoExtension {
+            streams: Rc::new(Streams {
+                input: Arc::new(Mutex::new(Box::new(input))),
+                output: RefCell::new(Box::new(output)),
+                error: RefCell::new(Box::new(error)),
+            }),
+        }
+    }
+}
+
+impl Default for IoExtension {
+    fn default() -> IoExtension {
+        IoExtension::new()
+    }
+}
+
+impl Extension for IoExtension {
+    fn install<'sc, 'c>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<(), FFIError> {
+        set_isolate_slot(scope, IoStreams(self.streams.clone()));
+        let read_line = load_v8_ffi!(io_read_line, scope, context);
+        let read_line_async = load_v8_ffi!(io_read_line_async, scope, context);
+        let write = load_v8_ffi!(io_write, scope, context);
+        run_bootstrap(
+            scope,
+            context,
+            IO_BOOTSTRAP,
+            &[read_line, read_line_async, write],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_extension() {
+        struct Captured(Rc<RefCell<Vec<u8>>>);
+        impl std::io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let output = Rc::new(RefCell::new(vec![]));
+        let error = Rc::new(RefCell::new(vec![]));
+        let io = crate::extensions::io::IoExtension::with_streams(
+            std::io::Cursor::new(b"first\r\nsecond\n\nthird".to_vec()),
+            Captured(output.clone()),
+            Captured(error.clone()),
+        );
+        let result: Option<String> = crate::Embed::new()
+            .extension(io)
+            .eval(
+                r#"
+                const first = readLine();
+                print('got', first, 1);
+                eprint('oops');
+                (async () => {
+                    const lines = [];
+                    for await (const line of stdin) lines.push(line);
+                    print(lines.join('|'));
+                    print(readLine());
+                })();
+                first
+                "#,
+                "io.js",
+            )
+            .unwrap();
+        assert_eq!(result.as_deref(), Some("first"));
+        assert_eq!(&*output.borrow(), b"got first 1\nsecond||third\nnull\n");
+        assert_eq!(&*error.borrow(), b"oops\n");
+    }
+}
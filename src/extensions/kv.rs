@@ -0,0 +1,342 @@
+use super::{run_bootstrap, Extension};
+use crate::spawn_blocking_ffi;
+use crate::util::*;
+use crate::FFIError;
+use rusty_v8 as v8;
+use rusty_v8_helper_derive::v8_ffi;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::{Arc, Mutex};
+
+/// How many entries a `kv.scan` iterator fetches from the backend at once.
+const SCAN_BATCH: usize = 64;
+
+/// `KvBackend` is the store behind the `kv` global of `KvExtension`, i.e.
+/// over SQLite or sled. Values are JSON text, keys are sorted as strings.
+///
+/// Backends are called on the blocking thread pool, so they must be `Send`
+/// and `Sync`, and may block.
+pub trait KvBackend: Send + Sync + 'static {
+    fn get(&self, key: &str) -> Result<Option<String>, String>;
+
+    fn put(&self, key: &str, value: &str) -> Result<(), String>;
+
+    /// Delete `key`, returning whether it was set.
+    fn delete(&self, key: &str) -> Result<bool, String>;
+
+    /// Up to `limit` entries whose keys start with `prefix` and come after
+    /// `after`, if given, in key order.
+    fn scan(
+        &self,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>, String>;
+}
+
+/// `KvBackend` keeping entries in memory, i.e. for tests.
+#[derive(Default)]
+pub struct MemoryKv {
+    entries: Mutex<BTreeMap<String, String>>,
+}
+
+impl MemoryKv {
+    pub fn new() -> MemoryKv {
+        MemoryKv::default()
+    }
+}
+
+impl KvBackend for MemoryKv {
+    fn get(&self, key: &str) -> Result<Option<String>, String> {
+        Ok(self.entries.lock().unwrap().get(key).cloned())
+    }
+
+    fn put(&self, key: &str, value: &str) -> Result<(), String> {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, String> {
+        Ok(self.entries.lock().unwrap().remove(key).is_some())
+    }
+
+    fn scan(
+        &self,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>, String> {
+        let start = match after {
+            Some(after) => Bound::Excluded(after.to_string()),
+            None => Bound::Included(prefix.to_string()),
+        };
+        Ok(self
+            .entries
+            .lock()
+            .unwrap()
+            .range((start, Bound::Unbounded))
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .take(limit)
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+}
+
+fn kv_error(error: String) -> FFIError {
+    FFIError::Error(format!("kv: {}", error))
+}
+
+struct KvHandle {
+    backend: Arc<dyn KvBackend>,
+}
+
+/// Where a scan is at: the last key returned, and whether the backend ran
+/// out of entries.
+#[derive(Default)]
+struct ScanPosition {
+    after: Option<String>,
+    done: bool,
+}
+
+/// The native side of a `kv.scan` iterator, fetching entries in batches.
+struct KvCursor {
+    backend: Arc<dyn KvBackend>,
+    prefix: String,
+    position: Arc<Mutex<ScanPosition>>,
+}
+
+#[v8_ffi(scoped)]
+fn kv_get<'sc, 'c>(
+    this: &KvHandle,
+    scope: &mut impl v8::ToLocal<'sc>,
+    _context: v8::Local<'c, v8::Context>,
+    key: String,
+) -> v8::Local<'sc, v8::Value> {
+    let backend = this.backend.clone();
+    spawn_blocking_ffi(scope, move || backend.get(&key).map_err(kv_error)).into()
+}
+
+#[v8_ffi(scoped)]
+fn kv_put<'sc, 'c>(
+    this: &KvHandle,
+    scope: &mut impl v8::ToLocal<'sc>,
+    _context: v8::Local<'c, v8::Context>,
+    key: String,
+    value: String,
+) -> v8::Local<'sc, v8::Value> {
+    let backend = this.backend.clone();
+    spawn_blocking_ffi(scope, move || backend.put(&key, &value).map_err(kv_error)).into()
+}
+
+#[v8_ffi(scoped)]
+fn kv_delete<'sc, 'c>(
+    this: &KvHandle,
+    scope: &mut impl v8::ToLocal<'sc>,
+    _context: v8::Local<'c, v8::Context>,
+    key: String,
+) -> v8::Local<'sc, v8::Value> {
+    let backend = this.backend.clone();
+    spawn_blocking_ffi(scope, move || backend.delete(&key).map_err(kv_error)).into()
+}
+
+/// A new cursor over the entries whose keys start with `prefix`.
+#[v8_ffi(scoped)]
+fn kv_scan<'sc, 'c>(
+    this: &KvHandle,
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    prefix: String,
+) -> v8::Local<'sc, v8::Value> {
+    let mut cursor = make_object_wrap(
+        scope,
+        context,
+        KvCursor {
+            backend: this.backend.clone(),
+            prefix,
+            position: Arc::new(Mutex::new(ScanPosition::default())),
+        },
+    );
+    cursor.make_weak();
+    cursor.get(scope).unwrap().into()
+}
+
+/// A promise of the cursor's next batch of entries, empty once it is done.
+#[v8_ffi(scoped)]
+fn kv_cursor_next<'sc, 'c>(
+    this: &KvCursor,
+    scope: &mut impl v8::ToLocal<'sc>,
+    _context: v8::Local<'c, v8::Context>,
+) -> v8::Local<'sc, v8::Value> {
+    let backend = this.backend.clone();
+    let prefix = this.prefix.clone();
+    let position = this.position.clone();
+    let next = move || -> Result<Vec<(String, String)>, FFIError> {
+        let mut position = position.lock().unwrap();
+        if position.done {
+            return Ok(vec![]);
+        }
+        let entries = backend
+            .scan(&prefix, position.after.as_deref(), SCAN_BATCH)
+            .map_err(kv_error)?;
+        position.done = entries.len() < SCAN_BATCH;
+        if let Some((key, _)) = entries.last() {
+            position.after = Some(key.clone());
+        }
+        Ok(entries)
+    };
+    spawn_blocking_ffi(scope, next).into()
+}
+
+const KV_BOOTSTRAP: &str = r#"
+(function (handle, get, put, remove, scan, next) {
+    this.kv = Object.freeze({
+        get: async (key) => {
+            const value = await get.call(handle, String(key));
+            return value == null ? undefined : JSON.parse(value);
+        },
+        put: async (key, value) => {
+            const json = JSON.stringify(value);
+            if (json === undefined) {
+                throw new TypeError('kv values must be JSON-serializable');
+            }
+            await put.call(handle, String(key), json);
+        },
+        delete: async (key) => remove.call(handle, String(key)),
+        scan: (prefix = '') => {
+            const cursor = scan.call(handle, String(prefix));
+            let batch = [];
+            let done = false;
+            return {
+                async next() {
+                    if (batch.length === 0 && !done) {
+                        batch = await next.call(cursor);
+                        done = batch.length === 0;
+                    }
+                    if (batch.length === 0) {
+                        return { done: true, value: undefined };
+                    }
+                    const [key, value] = batch.shift();
+                    return { done: false, value: [key, JSON.parse(value)] };
+                },
+                [Symbol.asyncIterator]() {
+                    return this;
+                },
+            };
+        },
+    });
+})
+"#;
+
+/// Installs a frozen global `kv`, a key-value store over a `KvBackend`
+/// such as `MemoryKv`, with values of any JSON-serializable type:
+///
+/// ```js
+/// await kv.put('user/1', { name: 'ada' });
+/// const user = await kv.get('user/1'); // undefined if not set
+/// await kv.delete('user/1'); // whether it was set
+/// for await (const [key, value] of kv.scan('user/')) { ... }
+/// ```
+///
+/// Every call returns a promise, settled on the event loop once the
+/// backend is done on the blocking thread pool. `scan` returns an async
+/// iterator over the keys with a prefix, in order, backed by a wrapped
+/// cursor that fetches entries from the backend in batches.
+///
+/// Each installation uses its own backend, so contexts can be given
+/// different stores.
+pub struct KvExtension {
+    backend: Arc<dyn KvBackend>,
+}
+
+impl KvExtension {
+    pub fn new(backend: impl KvBackend) -> KvExtension {
+        KvExtension {
+            backend: Arc::new(backend),
+        }
+    }
+
+    /// Share `backend` with the host, i.e. to read what scripts stored.
+    pub fn shared(backend: Arc<dyn KvBackend>) -> KvExtension {
+        KvExtension { backend }
+    }
+}
+
+impl Extension for KvExtension {
+    fn install<'sc, 'c>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<(), FFIError> {
+        let mut handle = make_object_wrap(
+            scope,
+            context,
+            KvHandle {
+                backend: self.backend.clone(),
+            },
+        );
+        handle.make_weak();
+        let args = [
+            handle.get(scope).unwrap().into(),
+            load_v8_ffi!(kv_get, scope, context),
+            load_v8_ffi!(kv_put, scope, context),
+            load_v8_ffi!(kv_delete, scope, context),
+            load_v8_ffi!(kv_scan, scope, context),
+            load_v8_ffi!(kv_cursor_next, scope, context),
+        ];
+        run_bootstrap(scope, context, KV_BOOTSTRAP, &args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kv_extension() {
+        let memory = std::sync::Arc::new(MemoryKv::new());
+        memory.put("other", "0").unwrap();
+        let result: String = crate::Embed::new()
+            .extension(KvExtension::shared(memory.clone()))
+            .eval(
+                r#"
+                const log = [];
+                (async () => {
+                    await kv.put('user/1', { name: 'ada' });
+                    log.push((await kv.get('user/1')).name);
+                    log.push(String(await kv.get('missing')));
+                    log.push(String(await kv.delete('user/1')));
+                    log.push(String(await kv.delete('user/1')));
+                    await kv.put('keep', [1, 2]);
+                    for (let i = 0; i < 100; i++) {
+                        await kv.put(`n/${String(i).padStart(3, '0')}`, i);
+                    }
+                    let count = 0, sum = 0, last = '';
+                    for await (const [key, value] of kv.scan('n/')) {
+                        count++;
+                        sum += value;
+                        last = key;
+                    }
+                    log.push(`${count} ${sum} ${last}`);
+                    await kv.put('bad', undefined).catch((e) => log.push(e.name));
+                })();
+                log
+                "#,
+                "kv.js",
+            )
+            .map(|log: Vec<String>| log.join("\n"))
+            .unwrap();
+        assert_eq!(
+            result,
+            "ada\nundefined\ntrue\nfalse\n100 4950 n/099\nTypeError"
+        );
+        assert_eq!(memory.get("keep").unwrap(), Some("[1,2]".to_string()));
+        let first = memory.scan("", None, 2).unwrap();
+        assert_eq!(first[0].0, "keep");
+        assert_eq!(first[1], ("n/000".to_string(), "0".to_string()));
+        let after = memory.scan("n/", Some("n/098"), 10).unwrap();
+        assert_eq!(after.len(), 1);
+    }
+}
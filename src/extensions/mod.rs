@@ -0,0 +1,44 @@
+use crate::util::*;
+use crate::FFIError;
+use rusty_v8 as v8;
+
+pub mod abort;
+pub mod cron;
+#[cfg(feature = "crypto")]
+pub mod crypto;
+pub mod deterministic;
+pub mod encoding;
+pub mod env;
+pub mod fetch;
+pub mod fs;
+pub mod io;
+#[cfg(feature = "kv")]
+pub mod kv;
+pub mod timers;
+
+/// An `Extension` installs a set of globals backed by Rust into a context.
+pub trait Extension {
+    fn install<'sc, 'c>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<(), FFIError>;
+}
+
+/// Runs `source`, which must evaluate to a function, and calls it with the
+/// context's global object as `this` and `args` as arguments.
+///
+/// Extensions use this to wrap their native functions in spec-shaped JS.
+pub(crate) fn run_bootstrap<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    source: &str,
+    args: &[v8::Local<'sc, v8::Value>],
+) -> Result<(), FFIError> {
+    let function = eval_function(scope, context, source)
+        .map_err(|e| FFIError::Error(format!("failed to bootstrap extension: {}", e)))?;
+    let global = context.global(scope).into();
+    call_function(scope, context, function, global, args)
+        .map_err(|e| FFIError::Error(format!("failed to bootstrap extension: {}", e)))?;
+    Ok(())
+}
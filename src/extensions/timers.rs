@@ -0,0 +1,221 @@
+use super::{run_bootstrap, Extension};
+use crate::executor::{executor, spawn_local, LocalFuture};
+use crate::FFIError;
+use rusty_v8 as v8;
+use rusty_v8_helper_derive::v8_ffi;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+#[derive(Default)]
+struct Cancel {
+    cancelled: Cell<bool>,
+    waker: RefCell<Option<Waker>>,
+}
+
+/// Completes with `true` once `sleep` does, or with `false` once cancelled.
+struct Timer {
+    sleep: LocalFuture,
+    cancel: Rc<Cancel>,
+}
+
+impl Future for Timer {
+    type Output = bool;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<bool> {
+        if self.cancel.cancelled.get() {
+            return Poll::Ready(false);
+        }
+        if self.sleep.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(true);
+        }
+        *self.cancel.waker.borrow_mut() = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+thread_local! {
+    static TIMERS: RefCell<HashMap<u32, Rc<Cancel>>> = RefCell::new(HashMap::new());
+    static NEXT_TIMER: Cell<u32> = Cell::new(1);
+}
+
+#[v8_ffi]
+fn timer_create() -> u32 {
+    NEXT_TIMER.with(|next| {
+        let id = next.get();
+        next.set(id.wrapping_add(1).max(1));
+        id
+    })
+}
+
+/// A promise resolved with `true` after `delay` milliseconds, or with
+/// `false` once the timer `id` is cancelled.
+#[v8_ffi(scoped)]
+fn timer_sleep<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    _context: v8::Local<'c, v8::Context>,
+    id: u32,
+    delay: f64,
+) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+    let executor = executor(scope)
+        .ok_or_else(|| FFIError::Error("no executor set for this isolate".to_string()))?;
+    let cancel = Rc::new(Cancel::default());
+    TIMERS.with(|timers| timers.borrow_mut().insert(id, cancel.clone()));
+    let timer = Timer {
+        sleep: executor.sleep(Duration::from_secs_f64(delay.max(0.0) / 1000.0)),
+        cancel,
+    };
+    let fired = async move {
+        let fired = timer.await;
+        if fired {
+            TIMERS.with(|timers| timers.borrow_mut().remove(&id));
+        }
+        fired
+    };
+    Ok(spawn_local(scope, fired).into())
+}
+
+#[v8_ffi]
+fn timer_cancel(id: u32) {
+    let cancel = TIMERS.with(|timers| timers.borrow_mut().remove(&id));
+    if let Some(cancel) = cancel {
+        cancel.cancelled.set(true);
+        if let Some(waker) = cancel.waker.borrow_mut().take() {
+            waker.wake();
+        }
+    }
+}
+
+const TIMERS_BOOTSTRAP: &str = r#"
+(function (create, sleep, cancel) {
+    const active = new Set();
+    const delayOf = (delay) => {
+        delay = Number(delay);
+        return delay > 0 ? delay : 0;
+    };
+    const check = (callback) => {
+        if (typeof callback !== 'function') {
+            throw new TypeError('timer callback must be a function');
+        }
+    };
+    this.setTimeout = (callback, delay, ...args) => {
+        check(callback);
+        const id = create();
+        active.add(id);
+        sleep(id, delayOf(delay)).then((fired) => {
+            if (fired && active.delete(id)) callback(...args);
+        });
+        return id;
+    };
+    this.setInterval = (callback, delay, ...args) => {
+        check(callback);
+        const id = create();
+        delay = delayOf(delay);
+        active.add(id);
+        const schedule = () => sleep(id, delay).then((fired) => {
+            if (!fired || !active.has(id)) return;
+            callback(...args);
+            if (active.has(id)) schedule();
+        });
+        schedule();
+        return id;
+    };
+    this.clearTimeout = this.clearInterval = (id) => {
+        if (active.delete(id)) cancel(id);
+    };
+})
+"#;
+
+/// Installs global `setTimeout`, `setInterval`, `clearTimeout` and
+/// `clearInterval` backed by the isolate's `Executor`.
+///
+/// Timers fire while the event loop is driven by `run_event_loop`, which
+/// waits for pending timers.
+pub struct TimersExtension;
+
+impl Extension for TimersExtension {
+    fn install<'sc, 'c>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<(), FFIError> {
+        let create = load_v8_ffi!(timer_create, scope, context);
+        let sleep = load_v8_ffi!(timer_sleep, scope, context);
+        let cancel = load_v8_ffi!(timer_cancel, scope, context);
+        run_bootstrap(scope, context, TIMERS_BOOTSTRAP, &[create, sleep, cancel])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+    use crate::FFICompat;
+
+    async fn check_timers(executor: Option<crate::LocalExecutor>) {
+        use crate::executor::run_event_loop;
+        use crate::Extension;
+        let mut runtime = crate::Runtime::new();
+        if let Some(executor) = executor {
+            crate::set_executor(runtime.isolate(), executor);
+        }
+        let (_, context) = runtime.create_context();
+        {
+            let isolate = runtime.isolate();
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            let context = context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            crate::extensions::timers::TimersExtension
+                .install(scope, context)
+                .unwrap();
+            run_script(
+                scope,
+                context,
+                r#"
+                globalThis.log = [];
+                setTimeout((x) => log.push(x), 20, 'late');
+                setTimeout(() => log.push('early'), 0);
+                clearTimeout(setTimeout(() => log.push('cleared'), 5));
+                let ticks = 0;
+                const interval = setInterval(() => {
+                    log.push('tick');
+                    if (++ticks === 2) clearInterval(interval);
+                }, 1);
+                "#,
+            )
+            .unwrap();
+        }
+        run_event_loop(runtime.isolate()).await;
+        let isolate = runtime.isolate();
+        let mut hs = v8::HandleScope::new(isolate);
+        let scope = hs.enter();
+        let context = context.get(scope).unwrap();
+        let mut cs = v8::ContextScope::new(scope, context);
+        let scope = cs.enter();
+        let log = run_script(scope, context, "log.join()").unwrap();
+        assert_eq!(
+            String::from_value(log, scope, context),
+            Ok("early,tick,tick,late".to_string())
+        );
+    }
+
+    #[test]
+    fn local_executor_timers() {
+        crate::initialize_v8();
+        let executor = crate::LocalExecutor::new();
+        executor.block_on(check_timers(Some(executor.clone())));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn tokio_timers() {
+        crate::initialize_v8();
+        crate::tokio_runtime::run_isolate_on_current_thread(check_timers(None));
+    }
+}
@@ -0,0 +1,137 @@
+//! `FfiFn`, the link from a `#[v8_ffi]` fn to its generated JS function,
+//! and `FfiFnMeta`, a description of it for help text or autocompletion.
+
+use crate::util::{isolate_slot, set_isolate_slot};
+use crate::JsValue;
+use rusty_v8 as v8;
+use std::cell::RefCell;
+
+/// `FfiFn` is implemented by `#[v8_ffi]` for a hidden struct named like
+/// the fn. The struct lives in the type namespace next to the fn, so a
+/// `use` or `pub use` of the fn, renamed or not, brings it along, and
+/// `load_v8_ffi!` finds the JS function through any path to the fn.
+pub trait FfiFn {
+    /// The name of the Rust fn.
+    const NAME: &'static str;
+
+    /// The signature and doc comment of the fn.
+    const META: FfiFnMeta;
+
+    /// Create the JS function calling the fn.
+    fn load<'sc, 'c>(
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> v8::Local<'sc, v8::Function>;
+
+    /// The raw V8 callback calling the fn, see `ffi_callback`.
+    fn callback() -> v8::FunctionCallback;
+
+    /// A new `FunctionTemplate` calling the fn, see `ffi_template`.
+    fn template<'sc>(scope: &mut impl v8::ToLocal<'sc>) -> v8::Local<'sc, v8::FunctionTemplate>;
+}
+
+/// The JS-facing signature and doc comment of a `#[v8_ffi]` fn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FfiFnMeta {
+    pub name: &'static str,
+    /// The number of JS arguments, not counting `this`, `scope` and
+    /// `context`.
+    pub arity: usize,
+    pub params: &'static [FfiParam],
+    /// The Rust return type, `()` if there is none.
+    pub returns: &'static str,
+    /// The doc comment, without the leading `///`.
+    pub doc: &'static str,
+}
+
+/// A JS argument of a `#[v8_ffi]` fn, with its Rust type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FfiParam {
+    pub name: &'static str,
+    pub ty: &'static str,
+}
+
+impl FfiFnMeta {
+    /// The signature as a line of help text, i.e. `read_file(path: String)
+    /// -> Vec<u8>`.
+    pub fn signature(&self) -> String {
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .map(|param| format!("{}: {}", param.name, param.ty))
+            .collect();
+        format!("{}({}) -> {}", self.name, params.join(", "), self.returns)
+    }
+
+    /// A plain JS object describing the fn, i.e. for a `__listFunctions()`
+    /// REPL command.
+    pub fn to_js(&self) -> JsValue {
+        let params: Vec<JsValue> = self
+            .params
+            .iter()
+            .map(|param| JsValue::object(vec![("name", param.name), ("type", param.ty)]))
+            .collect();
+        JsValue::object(vec![
+            ("name", JsValue::from(self.name)),
+            ("arity", JsValue::from(self.arity as f64)),
+            ("params", JsValue::Array(params)),
+            ("returns", JsValue::from(self.returns)),
+            ("doc", JsValue::from(self.doc)),
+        ])
+    }
+}
+
+struct LoadedSlot(RefCell<Vec<FfiFnMeta>>);
+
+/// Create the JS function for the `#[v8_ffi]` fn `F`, recording it in the
+/// isolate's list of loaded functions. This is what `load_v8_ffi!` does.
+pub fn load_ffi_fn<'sc, 'c, F: FfiFn>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+) -> v8::Local<'sc, v8::Function> {
+    let loaded = match isolate_slot::<LoadedSlot>(scope) {
+        Some(loaded) => loaded,
+        None => {
+            set_isolate_slot(scope, LoadedSlot(RefCell::new(vec![])));
+            isolate_slot::<LoadedSlot>(scope).unwrap()
+        }
+    };
+    let mut loaded = loaded.0.borrow_mut();
+    if !loaded.iter().any(|meta| *meta == F::META) {
+        loaded.push(F::META);
+    }
+    drop(loaded);
+    F::load(scope, context)
+}
+
+/// The `#[v8_ffi]` fns loaded with `load_v8_ffi!` in this isolate, in the
+/// order they were first loaded.
+pub fn loaded_functions(scope: &mut impl v8::InIsolate) -> Vec<FfiFnMeta> {
+    isolate_slot::<LoadedSlot>(scope)
+        .map(|loaded| loaded.0.borrow().clone())
+        .unwrap_or_default()
+}
+
+/// The raw V8 callback of the `#[v8_ffi]` fn `F`, for templates or external
+/// references set up by hand, i.e. `ffi_callback::<read_file>()`.
+///
+/// The callback converts its arguments and `this` as the function from
+/// `load_v8_ffi!` does, using the context it is called in.
+pub fn ffi_callback<F: FfiFn>() -> v8::FunctionCallback {
+    F::callback()
+}
+
+/// A new `FunctionTemplate` calling the `#[v8_ffi]` fn `F`, to compose
+/// into prototypes and object templates where a `Function` from
+/// `load_v8_ffi!` cannot go. Unlike `load_ffi_fn`, this does not record
+/// `F` in `loaded_functions`.
+///
+/// ```ignore
+/// let mut template = ffi_template::<area>(scope);
+/// let area = template.get_function(scope, context).unwrap();
+/// ```
+pub fn ffi_template<'sc, F: FfiFn>(
+    scope: &mut impl v8::ToLocal<'sc>,
+) -> v8::Local<'sc, v8::FunctionTemplate> {
+    F::template(scope)
+}
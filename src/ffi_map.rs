@@ -1,11 +1,23 @@
+use crate::de::{at_path, ROOT};
+use crate::error_class::ReturnedError;
+use crate::limits::{self, LimitExceeded};
+use crate::nullish::none_to_value;
+use crate::properties::{
+    catching, getter_policy, property_mode, property_names, proxy_policy, GetterPolicy,
+    PropertyMode, ProxyPolicy,
+};
+use crate::rename::{rename_policy, RenamePolicy};
+use crate::scratch::{Locals, ScratchString};
 use crate::util::*;
+use crate::Bytes;
+use crate::FFIError;
 use crate::ObjectWrap;
 use rusty_v8 as v8;
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::{Map, Value};
 use std::any::Any;
 use std::convert::TryInto;
-use std::fmt::Debug;
+use std::fmt::{Debug, Write};
 use std::ops::Deref;
 use std::rc::Rc;
 
@@ -46,6 +58,46 @@ impl<'sc, 'c> FFICompat<'sc, 'c> for v8::Local<'sc, v8::Value> {
     }
 }
 
+/// Implement `FFICompat` for a `Local` of a subtype of `Value`, passing it
+/// through unconverted once its type is checked.
+macro_rules! local_pass_through {
+    ($($ty:ident => $expected:expr),* $(,)?) => {
+        $(
+            impl<'sc, 'c> FFICompat<'sc, 'c> for v8::Local<'sc, v8::$ty> {
+                type E = FFIError;
+                fn from_value(
+                    value: v8::Local<'sc, v8::Value>,
+                    _scope: &mut impl v8::ToLocal<'sc>,
+                    _context: v8::Local<'c, v8::Context>,
+                ) -> Result<Self, FFIError> {
+                    value.try_into().map_err(|_| {
+                        FFIError::TypeError(format!(
+                            "invalid type for argument in ffi call, expected {}, received {}",
+                            $expected,
+                            type_of(value)
+                        ))
+                    })
+                }
+
+                fn to_value(
+                    self,
+                    _scope: &mut impl v8::ToLocal<'sc>,
+                    _context: v8::Local<'c, v8::Context>,
+                ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+                    Ok(self.into())
+                }
+            }
+        )*
+    };
+}
+
+local_pass_through! {
+    Object => "object",
+    Function => "function",
+    Array => "array",
+    ArrayBuffer => "ArrayBuffer",
+}
+
 impl<'sc, 'c> FFICompat<'sc, 'c> for String {
     type E = String;
     fn from_value(
@@ -55,7 +107,10 @@ impl<'sc, 'c> FFICompat<'sc, 'c> for String {
     ) -> Result<Self, String> {
         let value: Option<v8::Local<'sc, v8::String>> = value.try_into().ok();
         match value {
-            Some(value) => Ok(value.to_rust_string_lossy(scope)),
+            Some(value) => {
+                limits::count_string(value.utf8_length(scope))?;
+                Ok(value.to_rust_string_lossy(scope))
+            }
             None => Err("invalid type for argument in ffi call, expected string".to_string()),
         }
     }
@@ -175,10 +230,13 @@ impl<'sc, 'c> FFICompat<'sc, 'c> for bool {
         _scope: &mut impl v8::ToLocal<'sc>,
         _context: v8::Local<'c, v8::Context>,
     ) -> Result<Self, String> {
-        let value: Option<v8::Local<'sc, v8::Boolean>> = value.try_into().ok();
-        match value.map(|n| n.is_true()) {
+        let boolean: Option<v8::Local<'sc, v8::Boolean>> = value.try_into().ok();
+        match boolean.map(|n| n.is_true()) {
             Some(value) => Ok(value),
-            None => Err("invalid type for argument in ffi call, expected boolean".to_string()),
+            None => Err(format!(
+                "invalid type for argument in ffi call, expected boolean, received {}",
+                type_of(value)
+            )),
         }
     }
 
@@ -210,6 +268,9 @@ impl<'sc, 'c> FFICompat<'sc, 'c> for () {
     }
 }
 
+/// `null` and `undefined` map to `None`, while any other value must convert
+/// to `T`. See `Lenient` to map invalid values to `None` instead, and
+/// `set_none_value` for what `None` converts to.
 impl<'sc, 'c, T: FFICompat<'sc, 'c>> FFICompat<'sc, 'c> for Option<T> {
     type E = T::E;
 
@@ -218,7 +279,46 @@ impl<'sc, 'c, T: FFICompat<'sc, 'c>> FFICompat<'sc, 'c> for Option<T> {
         scope: &mut impl v8::ToLocal<'sc>,
         context: v8::Local<'c, v8::Context>,
     ) -> Result<Self, Self::E> {
-        Ok(T::from_value(value, scope, context).ok())
+        if value.is_undefined() || value.is_null() {
+            return Ok(None);
+        }
+        T::from_value(value, scope, context).map(Some)
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, Self::E> {
+        match self {
+            Some(x) => x.to_value(scope, context),
+            None => Ok(none_to_value(scope)),
+        }
+    }
+}
+
+/// `Lenient` is an optional FFI type where any value failing to convert to
+/// `T` becomes `None` rather than an error.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Lenient<T>(pub Option<T>);
+
+impl<T> Deref for Lenient<T> {
+    type Target = Option<T>;
+
+    fn deref(&self) -> &Option<T> {
+        &self.0
+    }
+}
+
+impl<'sc, 'c, T: FFICompat<'sc, 'c>> FFICompat<'sc, 'c> for Lenient<T> {
+    type E = T::E;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, Self::E> {
+        Ok(Lenient(T::from_value(value, scope, context).ok()))
     }
 
     fn to_value(
@@ -227,14 +327,15 @@ impl<'sc, 'c, T: FFICompat<'sc, 'c>> FFICompat<'sc, 'c> for Option<T> {
         context: v8::Local<'c, v8::Context>,
     ) -> Result<v8::Local<'sc, v8::Value>, Self::E> {
         return Ok(self
+            .0
             .map(|x| x.to_value(scope, context).ok())
             .flatten()
-            .unwrap_or_else(|| v8::null(scope).into()));
+            .unwrap_or_else(|| none_to_value(scope)));
     }
 }
 
-impl<'sc, 'c, E: Debug, T: FFICompat<'sc, 'c>> FFICompat<'sc, 'c> for Result<T, E> {
-    type E = String;
+impl<'sc, 'c, E: Debug + Any, T: FFICompat<'sc, 'c>> FFICompat<'sc, 'c> for Result<T, E> {
+    type E = ReturnedError;
 
     fn from_value(
         _value: v8::Local<'sc, v8::Value>,
@@ -250,13 +351,44 @@ impl<'sc, 'c, E: Debug, T: FFICompat<'sc, 'c>> FFICompat<'sc, 'c> for Result<T,
         context: v8::Local<'c, v8::Context>,
     ) -> Result<v8::Local<'sc, v8::Value>, Self::E> {
         match self {
-            Ok(v) => v.to_value(scope, context).map_err(|e| format!("{:?}", e)),
-            Err(e) => Err(format!("{:?}", e)),
+            Ok(v) => v
+                .to_value(scope, context)
+                .map_err(|e| ReturnedError::from(format!("{:?}", e))),
+            Err(e) => Err(ReturnedError::new(&e)),
+        }
+    }
+}
+
+/// An error converting part of a value from JS, which can be located
+/// within the enclosing array, i.e. as `value[3].price: ...`.
+pub trait ErrorPath {
+    /// Locate the error at `segment`, i.e. `[3]`, of the enclosing value.
+    fn at(self, segment: &str) -> Self;
+}
+
+impl ErrorPath for String {
+    fn at(self, segment: &str) -> String {
+        at_path(segment, &self)
+    }
+}
+
+impl ErrorPath for FFIError {
+    fn at(self, segment: &str) -> FFIError {
+        match self {
+            FFIError::Error(message) => FFIError::Error(at_path(segment, &message)),
+            FFIError::TypeError(message) => FFIError::TypeError(at_path(segment, &message)),
+            FFIError::RangeError(message) => FFIError::RangeError(at_path(segment, &message)),
         }
     }
 }
 
-impl<'sc, 'c, T: FFICompat<'sc, 'c>> FFICompat<'sc, 'c> for Vec<T> {
+/// Conversion from JS is bounded by the isolate's `ConversionLimits`, so
+/// the element error type must be able to report them, and locates errors
+/// by the element's index.
+impl<'sc, 'c, T: FFICompat<'sc, 'c>> FFICompat<'sc, 'c> for Vec<T>
+where
+    T::E: From<LimitExceeded> + ErrorPath,
+{
     type E = T::E;
 
     fn from_value(
@@ -271,12 +403,15 @@ impl<'sc, 'c, T: FFICompat<'sc, 'c>> FFICompat<'sc, 'c> for Vec<T> {
                 return Ok(vec![]);
             }
         };
-        let mut values = vec![];
+        let _nested = limits::enter(scope)?;
+        limits::count_entries(value.length() as usize)?;
+        let mut values = Vec::with_capacity(value.length() as usize);
         for i in 0..value.length() {
             let local = value
                 .get_index(scope, context, i)
                 .unwrap_or_else(|| v8::undefined(scope).into());
-            values.push(T::from_value(local, scope, context)?);
+            values
+                .push(T::from_value(local, scope, context).map_err(|e| e.at(&format!("[{}]", i)))?);
         }
         Ok(values)
     }
@@ -286,7 +421,7 @@ impl<'sc, 'c, T: FFICompat<'sc, 'c>> FFICompat<'sc, 'c> for Vec<T> {
         scope: &mut impl v8::ToLocal<'sc>,
         context: v8::Local<'c, v8::Context>,
     ) -> Result<v8::Local<'sc, v8::Value>, Self::E> {
-        let localled: Result<Vec<v8::Local<'sc, v8::Value>>, Self::E> = self
+        let localled: Result<Locals<'sc>, Self::E> = self
             .into_iter()
             .map(|x| x.to_value(scope, context))
             .collect();
@@ -295,39 +430,129 @@ impl<'sc, 'c, T: FFICompat<'sc, 'c>> FFICompat<'sc, 'c> for Vec<T> {
     }
 }
 
+/// The objects being converted by `js_value_to_serde` that contain the
+/// current value, to detect circular references, and the current path.
+struct Ancestors<'sc> {
+    /// The objects, with the length of `path` at each.
+    objects: Vec<(v8::Local<'sc, v8::Value>, usize)>,
+    /// The path of the current value, extended and truncated in place as
+    /// the conversion descends rather than allocated for every value.
+    path: ScratchString,
+    mode: PropertyMode,
+    getters: GetterPolicy,
+    proxies: ProxyPolicy,
+}
+
+impl<'sc> Ancestors<'sc> {
+    /// Descend into `value` at the current path, failing if it contains
+    /// itself.
+    fn enter(&mut self, value: v8::Local<'sc, v8::Value>) -> Result<(), String> {
+        if let Some(&(_, len)) = self.objects.iter().find(|x| x.0.strict_equals(value)) {
+            return Err(format!(
+                "circular reference: {} refers back to {}",
+                &*self.path,
+                &self.path[..len]
+            ));
+        }
+        self.objects.push((value, self.path.len()));
+        Ok(())
+    }
+}
+
 fn js_value_to_serde<'sc, 'c>(
     value: v8::Local<'sc, v8::Value>,
     scope: &mut impl v8::ToLocal<'sc>,
     context: v8::Local<'c, v8::Context>,
 ) -> Result<Value, String> {
+    let mut ancestors = Ancestors {
+        objects: vec![],
+        path: ScratchString::new(),
+        mode: property_mode(scope),
+        getters: getter_policy(scope),
+        proxies: proxy_policy(scope),
+    };
+    ancestors.path.push_str(ROOT);
+    js_value_to_serde_at(value, scope, context, &mut ancestors)
+}
+
+fn js_value_to_serde_at<'sc, 'c>(
+    value: v8::Local<'sc, v8::Value>,
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    ancestors: &mut Ancestors<'sc>,
+) -> Result<Value, String> {
+    if value.is_proxy() && !crate::interceptor::is_intercepted(scope, context, value) {
+        match ancestors.proxies {
+            ProxyPolicy::ConvertThrough => (),
+            ProxyPolicy::Reject => return Err(format!("{} is a Proxy", &*ancestors.path)),
+            ProxyPolicy::Opaque => return Ok(Value::Null),
+        }
+    }
     let nvalue: Result<v8::Local<v8::Array>, _> = value.try_into();
     if let Ok(nvalue) = nvalue {
-        let mut values = vec![];
+        let _nested = limits::enter(scope)?;
+        limits::count_entries(nvalue.length() as usize)?;
+        ancestors.enter(value)?;
+        let mut values = Vec::with_capacity(nvalue.length() as usize);
         for i in 0..nvalue.length() {
-            let local = nvalue
-                .get_index(scope, context, i)
-                .unwrap_or_else(|| v8::undefined(scope).into());
-            values.push(js_value_to_serde(local, scope, context)?);
+            let len = ancestors.path.len();
+            write!(ancestors.path, "[{}]", i).unwrap();
+            let local = match catching(scope, |scope| nvalue.get_index(scope, context, i)) {
+                Ok(local) => local.unwrap_or_else(|| v8::undefined(scope).into()),
+                // keep the indices of the later elements
+                Err(_) if ancestors.getters == GetterPolicy::Skip => v8::null(scope).into(),
+                Err(e) => return Err(format!("reading {} threw: {}", &*ancestors.path, e)),
+            };
+            values.push(js_value_to_serde_at(local, scope, context, ancestors)?);
+            ancestors.path.truncate(len);
         }
+        ancestors.objects.pop();
         return Ok(Value::Array(values));
     }
+    // byte buffers, i.e. for `serde_bytes` fields, deserialize from a sequence
+    if value.is_array_buffer_view() || value.is_array_buffer() {
+        let bytes = Bytes::from_value(value, scope, context)?;
+        return Ok(Value::Array(bytes.0.into_iter().map(Value::from).collect()));
+    }
     let nvalue: Result<v8::Local<v8::Object>, _> = value.try_into();
     if let Ok(nvalue) = nvalue {
-        let names = nvalue
-            .get_own_property_names(scope, context)
-            .unwrap_or(vec![]);
+        let _nested = limits::enter(scope)?;
+        let mode = ancestors.mode;
+        let names = match catching(scope, |scope| property_names(nvalue, scope, context, mode)) {
+            Ok(names) => names?,
+            Err(_) if ancestors.getters == GetterPolicy::Skip => vec![],
+            Err(e) => {
+                return Err(format!(
+                    "listing the properties of {} threw: {}",
+                    &*ancestors.path, e
+                ))
+            }
+        };
+        limits::count_entries(names.len())?;
+        ancestors.enter(value)?;
         let mut values: Map<String, Value> = Map::new();
         for name in names {
+            let len = ancestors.path.len();
+            write!(ancestors.path, ".{}", name).unwrap();
             let lname = make_str(scope, &name);
-            let local = nvalue
-                .get(scope, context, lname)
-                .unwrap_or_else(|| v8::undefined(scope).into());
-            values.insert(name, js_value_to_serde(local, scope, context)?);
+            let local = match catching(scope, |scope| nvalue.get(scope, context, lname)) {
+                Ok(local) => local.unwrap_or_else(|| v8::undefined(scope).into()),
+                Err(_) if ancestors.getters == GetterPolicy::Skip => {
+                    ancestors.path.truncate(len);
+                    continue;
+                }
+                Err(e) => return Err(format!("reading {} threw: {}", &*ancestors.path, e)),
+            };
+            let value = js_value_to_serde_at(local, scope, context, ancestors)?;
+            ancestors.path.truncate(len);
+            values.insert(name, value);
         }
+        ancestors.objects.pop();
         return Ok(Value::Object(values));
     }
     let nvalue: Result<v8::Local<v8::String>, _> = value.try_into();
     if let Ok(nvalue) = nvalue {
+        limits::count_string(nvalue.utf8_length(scope))?;
         return Ok(Value::String(nvalue.to_rust_string_lossy(scope)));
     }
     let nvalue: Result<v8::Local<v8::Number>, _> = value.try_into();
@@ -346,14 +571,15 @@ fn js_value_to_serde<'sc, 'c>(
     return Err("unknown js type for jsonification".to_string());
 }
 
-fn serde_to_js_value<'sc, 'c>(
+pub(crate) fn serde_to_js_value<'sc, 'c>(
     value: Value,
     scope: &mut impl v8::ToLocal<'sc>,
     context: v8::Local<'c, v8::Context>,
 ) -> Result<v8::Local<'sc, v8::Value>, String> {
     match value {
         Value::Array(array) => {
-            let localled: Result<Vec<v8::Local<'sc, v8::Value>>, String> = array
+            let _nested = limits::enter(scope)?;
+            let localled: Result<Locals<'sc>, String> = array
                 .into_iter()
                 .map(|x| serde_to_js_value(x, scope, context))
                 .collect();
@@ -362,6 +588,10 @@ fn serde_to_js_value<'sc, 'c>(
             Ok(v8::Array::new_with_elements(scope, &localled[..]).into())
         }
         Value::Object(obj) => {
+            if let Some(bytes) = crate::ser::as_bytes(&obj) {
+                return Bytes(bytes).to_value(scope, context);
+            }
+            let _nested = limits::enter(scope)?;
             let js_obj = v8::Object::new(scope);
             for (key, value) in obj.into_iter() {
                 let key = make_str(scope, &key);
@@ -376,12 +606,81 @@ fn serde_to_js_value<'sc, 'c>(
     }
 }
 
-/// marker trait for json mapping
-pub trait FFIObject {}
+/// `FFIObject` types are converted to and from JS through JSON with
+/// serde. `#[derive(FFIObject)]` implements it along with `FFICompat`;
+/// other serde types are converted per use site with `JsonVia` or
+/// `#[ffi(json)]`.
+pub trait FFIObject {
+    /// How object keys are renamed in JS, `None` to use the isolate's
+    /// policy, see `rename::set_rename_policy`.
+    const RENAME: Option<RenamePolicy> = None;
+
+    /// A JSON Schema, with JS key names, that values converted from JS are
+    /// validated against before deserializing them, reporting every
+    /// violation at once. See `schema` for the supported keywords.
+    fn schema() -> Option<Value> {
+        None
+    }
+}
+
+/// Arbitrary JSON is passed through with its keys unchanged.
+impl FFIObject for Value {
+    const RENAME: Option<RenamePolicy> = Some(RenamePolicy::Keep);
+}
+
+/// Convert `value` to `T` through JSON, renaming keys with `rename`, or the
+/// isolate's policy, after validating it against `schema`.
+pub(crate) fn json_from_value<'sc, 'c, T: DeserializeOwned>(
+    value: v8::Local<'sc, v8::Value>,
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    rename: Option<RenamePolicy>,
+    schema: Option<Value>,
+) -> Result<T, String> {
+    let policy = rename.unwrap_or_else(|| rename_policy(scope));
+    let value = js_value_to_serde(value, scope, context)?;
+    if let Some(schema) = schema {
+        crate::schema::validate(&schema, &value).map_err(|e| crate::schema::describe(&e))?;
+    }
+    let value = policy.rename_keys(value, RenamePolicy::from_js);
+    crate::de::from_value(value, policy).map_err(|e| e.to_string())
+}
+
+/// Convert `value` to JS through JSON, renaming keys with `rename`, or the
+/// isolate's policy.
+pub(crate) fn json_to_value<'sc, 'c, T: Serialize>(
+    value: &T,
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    rename: Option<RenamePolicy>,
+) -> Result<v8::Local<'sc, v8::Value>, String> {
+    let policy = rename.unwrap_or_else(|| rename_policy(scope));
+    let value = crate::ser::to_value(value).map_err(|e| format!("{:?}", e))?;
+    let value = policy.rename_keys(value, RenamePolicy::to_js);
+    serde_to_js_value(value, scope, context)
+}
+
+/// `from_value` of `FFIObject` types, called by `#[derive(FFIObject)]`.
+#[doc(hidden)]
+pub fn object_from_value<'sc, 'c, T: DeserializeOwned + FFIObject>(
+    value: v8::Local<'sc, v8::Value>,
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+) -> Result<T, String> {
+    json_from_value(value, scope, context, T::RENAME, T::schema())
+}
 
-impl FFIObject for Value {}
+/// `to_value` of `FFIObject` types, called by `#[derive(FFIObject)]`.
+#[doc(hidden)]
+pub fn object_to_value<'sc, 'c, T: Serialize + FFIObject>(
+    value: T,
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+) -> Result<v8::Local<'sc, v8::Value>, String> {
+    json_to_value(&value, scope, context, T::RENAME)
+}
 
-impl<'sc, 'c, T: Serialize + DeserializeOwned + FFIObject> FFICompat<'sc, 'c> for T {
+impl<'sc, 'c> FFICompat<'sc, 'c> for Value {
     type E = String;
 
     fn from_value(
@@ -389,8 +688,7 @@ impl<'sc, 'c, T: Serialize + DeserializeOwned + FFIObject> FFICompat<'sc, 'c> fo
         scope: &mut impl v8::ToLocal<'sc>,
         context: v8::Local<'c, v8::Context>,
     ) -> Result<Self, String> {
-        let value = js_value_to_serde(value, scope, context)?;
-        serde_json::from_value(value).map_err(|e| format!("{:?}", e))
+        object_from_value(value, scope, context)
     }
 
     fn to_value(
@@ -398,8 +696,7 @@ impl<'sc, 'c, T: Serialize + DeserializeOwned + FFIObject> FFICompat<'sc, 'c> fo
         scope: &mut impl v8::ToLocal<'sc>,
         context: v8::Local<'c, v8::Context>,
     ) -> Result<v8::Local<'sc, v8::Value>, String> {
-        let value = serde_json::to_value(self).map_err(|e| format!("{:?}", e))?;
-        serde_to_js_value(value, scope, context)
+        object_to_value(self, scope, context)
     }
 }
 
@@ -422,8 +719,10 @@ impl<'sc, 'c, A1: FFICompat<'sc, 'c>, A2: FFICompat<'sc, 'c>> FFICompat<'sc, 'c>
             let v2 = value
                 .get_index(scope, context, 1)
                 .unwrap_or_else(|| v8::undefined(scope).into());
-            let v1 = A1::from_value(v1, scope, context).map_err(|e| format!("{:?}", e))?;
-            let v2 = A2::from_value(v2, scope, context).map_err(|e| format!("{:?}", e))?;
+            let v1 = A1::from_value(v1, scope, context)
+                .map_err(|e| at_path("[0]", &format!("{:?}", e)))?;
+            let v2 = A2::from_value(v2, scope, context)
+                .map_err(|e| at_path("[1]", &format!("{:?}", e)))?;
             return Ok((v1, v2));
         } else {
             return Err("expected array for tuple ffi".to_string());
@@ -471,9 +770,12 @@ impl<'sc, 'c, A1: FFICompat<'sc, 'c>, A2: FFICompat<'sc, 'c>, A3: FFICompat<'sc,
             let v3 = value
                 .get_index(scope, context, 2)
                 .unwrap_or_else(|| v8::undefined(scope).into());
-            let v1 = A1::from_value(v1, scope, context).map_err(|e| format!("{:?}", e))?;
-            let v2 = A2::from_value(v2, scope, context).map_err(|e| format!("{:?}", e))?;
-            let v3 = A3::from_value(v3, scope, context).map_err(|e| format!("{:?}", e))?;
+            let v1 = A1::from_value(v1, scope, context)
+                .map_err(|e| at_path("[0]", &format!("{:?}", e)))?;
+            let v2 = A2::from_value(v2, scope, context)
+                .map_err(|e| at_path("[1]", &format!("{:?}", e)))?;
+            let v3 = A3::from_value(v3, scope, context)
+                .map_err(|e| at_path("[2]", &format!("{:?}", e)))?;
             return Ok((v1, v2, v3));
         } else {
             return Err("expected array for tuple ffi".to_string());
@@ -534,10 +836,14 @@ impl<
             let v4 = value
                 .get_index(scope, context, 3)
                 .unwrap_or_else(|| v8::undefined(scope).into());
-            let v1 = A1::from_value(v1, scope, context).map_err(|e| format!("{:?}", e))?;
-            let v2 = A2::from_value(v2, scope, context).map_err(|e| format!("{:?}", e))?;
-            let v3 = A3::from_value(v3, scope, context).map_err(|e| format!("{:?}", e))?;
-            let v4 = A4::from_value(v4, scope, context).map_err(|e| format!("{:?}", e))?;
+            let v1 = A1::from_value(v1, scope, context)
+                .map_err(|e| at_path("[0]", &format!("{:?}", e)))?;
+            let v2 = A2::from_value(v2, scope, context)
+                .map_err(|e| at_path("[1]", &format!("{:?}", e)))?;
+            let v3 = A3::from_value(v3, scope, context)
+                .map_err(|e| at_path("[2]", &format!("{:?}", e)))?;
+            let v4 = A4::from_value(v4, scope, context)
+                .map_err(|e| at_path("[3]", &format!("{:?}", e)))?;
             return Ok((v1, v2, v3, v4));
         } else {
             return Err("expected array for tuple ffi".to_string());
@@ -606,11 +912,16 @@ impl<
             let v5 = value
                 .get_index(scope, context, 4)
                 .unwrap_or_else(|| v8::undefined(scope).into());
-            let v1 = A1::from_value(v1, scope, context).map_err(|e| format!("{:?}", e))?;
-            let v2 = A2::from_value(v2, scope, context).map_err(|e| format!("{:?}", e))?;
-            let v3 = A3::from_value(v3, scope, context).map_err(|e| format!("{:?}", e))?;
-            let v4 = A4::from_value(v4, scope, context).map_err(|e| format!("{:?}", e))?;
-            let v5 = A5::from_value(v5, scope, context).map_err(|e| format!("{:?}", e))?;
+            let v1 = A1::from_value(v1, scope, context)
+                .map_err(|e| at_path("[0]", &format!("{:?}", e)))?;
+            let v2 = A2::from_value(v2, scope, context)
+                .map_err(|e| at_path("[1]", &format!("{:?}", e)))?;
+            let v3 = A3::from_value(v3, scope, context)
+                .map_err(|e| at_path("[2]", &format!("{:?}", e)))?;
+            let v4 = A4::from_value(v4, scope, context)
+                .map_err(|e| at_path("[3]", &format!("{:?}", e)))?;
+            let v5 = A5::from_value(v5, scope, context)
+                .map_err(|e| at_path("[4]", &format!("{:?}", e)))?;
             return Ok((v1, v2, v3, v4, v5));
         } else {
             return Err("expected array for tuple ffi".to_string());
@@ -726,12 +1037,72 @@ mod tests {
 
     struct TestWrapper(String);
 
-    #[derive(Serialize, Deserialize)]
+    trait TestNamed {
+        fn name(&self) -> String;
+    }
+
+    impl TestNamed for TestWrapper {
+        fn name(&self) -> String {
+            self.0.clone()
+        }
+    }
+
+    struct TestNumbered(u32);
+
+    impl TestNamed for TestNumbered {
+        fn name(&self) -> String {
+            format!("number {}", self.0)
+        }
+    }
+
+    #[derive(Serialize, Deserialize, crate::FFIObject)]
     struct TestObj {
         value: String,
     }
 
-    impl FFIObject for TestObj {}
+    #[derive(Serialize, Deserialize, crate::FFIObject)]
+    #[js_schema = "test_order_schema"]
+    struct TestOrder {
+        items: Vec<f64>,
+    }
+
+    fn test_order_schema() -> Value {
+        serde_json::json!({
+            "type": "object",
+            "required": ["items"],
+            "additionalProperties": false,
+            "properties": { "items": { "type": "array", "items": { "minimum": 0 } } },
+        })
+    }
+
+    /// Stands in for a `serde_bytes::ByteBuf`.
+    struct TestRaw(Vec<u8>);
+
+    impl Serialize for TestRaw {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for TestRaw {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            Vec::<u8>::deserialize(deserializer).map(TestRaw)
+        }
+    }
+
+    #[derive(Serialize, Deserialize, crate::FFIObject)]
+    struct TestBinary {
+        name: String,
+        data: TestRaw,
+    }
+
+    #[derive(Serialize, Deserialize, crate::FFIObject)]
+    #[js_rename_all = "camelCase"]
+    struct TestRenamed {
+        max_retries: u32,
+        #[serde(rename = "URL")]
+        url: String,
+    }
 
     static TEST_RESPONSE: AtomicU64 = AtomicU64::new(0);
 
@@ -761,6 +1132,11 @@ mod tests {
         }
     }
 
+    #[v8_ffi]
+    fn test_ffi_lenient_arg(arg: Lenient<String>) {
+        test_ffi_opt_arg(arg.0);
+    }
+
     #[v8_ffi]
     fn test_ffi_return() -> String {
         "test".to_string()
@@ -818,6 +1194,156 @@ mod tests {
         }
     }
 
+    #[v8_ffi]
+    fn test_ffi_wrap_dyn(this: &dyn TestNamed) -> String {
+        this.name()
+    }
+
+    #[v8_ffi]
+    fn test_ffi_this_of(this: &crate::ThisOf<(TestWrapper, TestNumbered)>) -> String {
+        match this {
+            crate::OneOf2::A(wrapper) => format!("wrapper {}", wrapper.0),
+            crate::OneOf2::B(numbered) => format!("numbered {}", numbered.0),
+        }
+    }
+
+    #[v8_ffi(scoped)]
+    fn test_ffi_wrap_reentrant<'sc, 'c>(
+        this: &mut TestWrapper,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> String {
+        this.0 = "test5".to_string();
+        let result = run_script(
+            scope,
+            context,
+            "(() => { try { test_ffi_wrap_mut.bind(test_ffi_wrap_mut_data)(); return 'ok'; } catch (e) { return e instanceof Error ? e.message : 'not an Error'; } })()",
+        )
+        .unwrap();
+        String::from_value(result, scope, context).unwrap()
+    }
+
+    #[v8_ffi]
+    fn test_ffi_binary(mut arg: TestBinary) -> TestBinary {
+        arg.data.0.push(arg.name.len() as u8);
+        arg
+    }
+
+    /// Calls `callback` with `target`.
+    ///
+    /// Returns `target`.
+    #[v8_ffi]
+    fn test_ffi_local_args(
+        callback: v8::Local<v8::Function>,
+        target: v8::Local<v8::Object>,
+    ) -> v8::Local<v8::Object> {
+        let _ = callback;
+        target
+    }
+
+    thread_local! {
+        static STASHED: std::cell::RefCell<Option<crate::JsRef<v8::Function>>> =
+            std::cell::RefCell::new(None);
+    }
+
+    #[v8_ffi]
+    fn test_ffi_stash(callback: crate::JsRef<v8::Function>) {
+        STASHED.with(|stashed| *stashed.borrow_mut() = Some(callback));
+    }
+
+    #[v8_ffi]
+    fn test_ffi_nullish(found: bool, _explicit: crate::Null) -> Option<u32> {
+        if found {
+            Some(1)
+        } else {
+            None
+        }
+    }
+
+    #[v8_ffi]
+    fn test_ffi_undefined(_missing: crate::Undefined) -> crate::Undefined {
+        crate::Undefined
+    }
+
+    #[v8_ffi]
+    fn test_ffi_timestamp(at: crate::Timestamp, offset: crate::Millis) -> crate::Millis {
+        crate::Millis(at.0 as f64 + offset.0)
+    }
+
+    #[derive(Debug, crate::JsErrorClass)]
+    enum TestStoreError {
+        NotFound {
+            key: String,
+            #[js_name = "attempts"]
+            tries: u32,
+        },
+        Full(u32, #[js_skip] std::time::Duration),
+        Closed,
+    }
+
+    impl std::fmt::Display for TestStoreError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                TestStoreError::NotFound { key, .. } => write!(f, "no entry {}", key),
+                TestStoreError::Full(size, after) => {
+                    write!(f, "store is full at {} after {:?}", size, after)
+                }
+                TestStoreError::Closed => write!(f, "store is closed"),
+            }
+        }
+    }
+
+    #[v8_ffi]
+    fn test_ffi_store_get(key: String) -> Result<u32, TestStoreError> {
+        match key.as_str() {
+            "full" => Err(TestStoreError::Full(3, std::time::Duration::from_secs(1))),
+            "closed" => Err(TestStoreError::Closed),
+            _ => Err(TestStoreError::NotFound { key, tries: 2 }),
+        }
+    }
+
+    /// A `u32` as a hex string, for `#[ffi(with)]`.
+    mod test_hex {
+        use crate::util::make_str;
+        use crate::FFIError;
+        use rusty_v8 as v8;
+
+        pub fn from_value<'sc, 'c>(
+            value: v8::Local<'sc, v8::Value>,
+            scope: &mut impl v8::ToLocal<'sc>,
+            _context: v8::Local<'c, v8::Context>,
+        ) -> Result<u32, FFIError> {
+            if !value.is_string() {
+                return Err(FFIError::TypeError("expected a hex string".to_string()));
+            }
+            let hex = value.to_rust_string_lossy(scope);
+            u32::from_str_radix(&hex, 16)
+                .map_err(|_| FFIError::RangeError(format!("invalid hex {}", hex)))
+        }
+
+        pub fn to_value<'sc, 'c>(
+            value: u32,
+            scope: &mut impl v8::ToLocal<'sc>,
+            _context: v8::Local<'c, v8::Context>,
+        ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+            Ok(make_str(scope, &format!("{:x}", value)))
+        }
+    }
+
+    #[v8_ffi(return_with = "test_hex")]
+    fn test_ffi_with(#[ffi(with = "test_hex")] hex: u32, plain: u32) -> u32 {
+        hex + plain
+    }
+
+    mod reexports {
+        pub(super) use super::test_ffi_obj as renamed_test_ffi_obj;
+    }
+
+    #[v8_ffi]
+    fn test_ffi_obj_count(args: Vec<TestObj>) -> u32 {
+        args.len() as u32
+    }
+
     #[v8_ffi]
     fn test_ffi_obj(arg: TestObj) -> TestObj {
         if arg.value == "test1" {
@@ -922,296 +1448,1233 @@ mod tests {
         string.into()
     }
 
+    #[v8_ffi]
+    fn test_ffi_numeric(percent: crate::Percentage, count: crate::Positive<u32>) -> f64 {
+        TEST_RESPONSE.store(26, Ordering::SeqCst);
+        percent.fraction() * *count as f64
+    }
+
+    #[v8_ffi(coerce)]
+    fn test_ffi_coerce(count: u32, label: String, flag: bool, strict: crate::Coerced<f64>) {
+        if count == 5 && label == "7" && flag && *strict == 1.5 {
+            TEST_RESPONSE.store(32, Ordering::SeqCst);
+        }
+    }
+
+    #[v8_ffi]
+    fn test_ffi_truthy(flag: crate::Truthy, strict: bool) {
+        if *flag && !strict {
+            TEST_RESPONSE.store(33, Ordering::SeqCst);
+        }
+    }
+
+    #[v8_ffi]
+    fn test_ffi_strict_utf8(arg: crate::StrictUtf8) {
+        if *arg == "test\u{1F600}" {
+            TEST_RESPONSE.store(34, Ordering::SeqCst);
+        }
+    }
+
+    #[v8_ffi]
+    fn test_ffi_utf16(arg: crate::Utf16String) -> crate::Utf16String {
+        arg
+    }
+
+    #[v8_ffi]
+    fn test_ffi_borrowed(text: &str, data: &[u8]) -> u32 {
+        (text.len() + data.len()) as u32
+    }
+
+    #[crate::js_class]
+    trait TestPlugin {
+        #[getter]
+        fn name(&self) -> String;
+
+        #[js_name = "addTwo"]
+        fn add_two(&self, value: i32) -> i32;
+    }
+
+    #[derive(crate::FromJsObject)]
+    struct TestOptions {
+        name: String,
+        #[js_name = "maxCount"]
+        max_count: u32,
+        #[js_default]
+        verbose: bool,
+        label: Option<String>,
+    }
+
+    #[v8_ffi]
+    fn test_ffi_js_value(input: crate::JsValue) -> crate::JsValue {
+        crate::JsValue::object(vec![
+            ("count", crate::JsValue::from(2)),
+            ("items", vec!["a", "b"].into()),
+            ("input", input),
+        ])
+    }
+
+    struct TestUnconvertible;
+
+    impl<'sc, 'c> FFICompat<'sc, 'c> for TestUnconvertible {
+        type E = crate::FFIError;
+
+        fn from_value(
+            _value: v8::Local<'sc, v8::Value>,
+            _scope: &mut impl v8::ToLocal<'sc>,
+            _context: v8::Local<'c, v8::Context>,
+        ) -> Result<Self, crate::FFIError> {
+            Err(crate::FFIError::TypeError("unconvertible".to_string()))
+        }
+
+        fn to_value(
+            self,
+            _scope: &mut impl v8::ToLocal<'sc>,
+            _context: v8::Local<'c, v8::Context>,
+        ) -> Result<v8::Local<'sc, v8::Value>, crate::FFIError> {
+            Err(crate::FFIError::RangeError("unconvertible".to_string()))
+        }
+    }
+
+    #[v8_ffi]
+    fn test_ffi_return_error() -> TestUnconvertible {
+        TestUnconvertible
+    }
+
+    #[v8_ffi(return_undefined_on_error)]
+    fn test_ffi_return_undefined() -> TestUnconvertible {
+        TestUnconvertible
+    }
+
+    #[v8_ffi(multi_return(bytes_read, eof))]
+    fn test_ffi_multi_return(len: u32) -> Result<(u32, bool), crate::FFIError> {
+        Ok((len, len == 0))
+    }
+
+    // compiled out with its generated items, or `TestMissing` would not
+    // resolve
+    #[v8_ffi]
+    #[cfg(any())]
+    fn test_ffi_compiled_out(missing: TestMissing) -> TestMissing {
+        missing
+    }
+
+    /// Doubles `value`.
+    #[v8_ffi]
+    #[cfg(not(any()))]
+    #[inline]
+    #[allow(clippy::float_cmp)]
+    fn test_ffi_configured(value: f64) -> f64 {
+        value * 2.0
+    }
+
+    mod test_internals {
+        use rusty_v8_helper_derive::v8_ffi;
+
+        // private to this module, but loadable from `tests`
+        #[v8_ffi(loader_vis = "pub(super)")]
+        fn test_ffi_hidden(value: u32) -> u32 {
+            value + 1
+        }
+    }
+
+    #[v8_ffi]
+    fn test_ffi_renamed(mut arg: TestRenamed) -> TestRenamed {
+        arg.max_retries += 1;
+        arg
+    }
+
+    #[derive(crate::FromJsObject)]
+    #[js_rename_all = "camelCase"]
+    struct TestRenamedOptions {
+        max_count: u32,
+        #[js_name = "label"]
+        label_text: String,
+    }
+
+    #[v8_ffi]
+    fn test_ffi_sink(sink: crate::Sink<u32>) {
+        for i in 0..3 {
+            sink.push(i);
+        }
+    }
+
+    #[v8_ffi]
+    fn test_ffi_sink_check(item: u32) {
+        TEST_RESPONSE.store(27 + item as u64, Ordering::SeqCst);
+    }
+
+    #[v8_ffi(scoped)]
+    fn test_ffi_blocking<'sc, 'c>(
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+        arg: u32,
+    ) -> v8::Local<'sc, v8::Value> {
+        crate::spawn_blocking_ffi(scope, move || arg * 2).into()
+    }
+
+    #[v8_ffi]
+    fn test_ffi_abort(token: crate::CancellationToken) {
+        if token.is_cancelled() {
+            TEST_RESPONSE.store(31, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn configured_ffi() {
+        let meta = <test_ffi_configured as crate::FfiFn>::META;
+        assert_eq!(meta.doc, "Doubles `value`.");
+        with_context!([test_ffi_configured], |scope, context| {
+            let result = run_script(scope, context, "test_ffi_configured(21)").unwrap();
+            assert_eq!(f64::from_value(result, scope, context), Ok(42.0));
+        });
+    }
+
+    #[test]
+    fn loader_visibility() {
+        with_context!(|scope, context| {
+            let function = load_v8_ffi!(test_internals::test_ffi_hidden, scope, context);
+            let global = context.global(scope);
+            global.set(context, make_str(scope, "test_ffi_hidden"), function);
+            let result = run_script(scope, context, "test_ffi_hidden(41)").unwrap();
+            assert_eq!(u32::from_value(result, scope, context), Ok(42));
+        });
+    }
+
     #[test]
     fn exec_tests() {
-        let platform = v8::new_default_platform();
-        v8::V8::initialize_platform(platform);
-        v8::V8::initialize();
-        let mut create_params = v8::Isolate::create_params();
-        create_params.set_array_buffer_allocator(v8::new_default_allocator());
-        let mut isolate = v8::Isolate::new(create_params);
-        let mut hs = v8::HandleScope::new(&mut isolate);
-        let scope = hs.enter();
-        let context = v8::Context::new(scope);
-        let mut cs = v8::ContextScope::new(scope, context);
-        let scope = cs.enter();
-        let global = context.global(scope);
-        //basic
-        global.set(
-            context,
-            make_str(scope, "test_ffi_basic"),
-            load_v8_ffi!(test_ffi_basic, scope, context),
-        );
-        run_script(scope, context, "test_ffi_basic()");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 1);
-        //arg
-        global.set(
-            context,
-            make_str(scope, "test_ffi_arg"),
-            load_v8_ffi!(test_ffi_arg, scope, context),
-        );
-        run_script(scope, context, "test_ffi_arg('test1')");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 2);
-        run_script(scope, context, "test_ffi_arg('test2')");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 3);
-        // optional arg
-        global.set(
-            context,
-            make_str(scope, "test_ffi_opt_arg"),
-            load_v8_ffi!(test_ffi_opt_arg, scope, context),
-        );
-        run_script(scope, context, "test_ffi_opt_arg(null)");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 4);
-        run_script(scope, context, "test_ffi_opt_arg('test')");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 5);
-        run_script(scope, context, "test_ffi_opt_arg(undefined)");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 4);
-        run_script(scope, context, "test_ffi_opt_arg('test')");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 5);
-        run_script(scope, context, "test_ffi_opt_arg(77)");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 4);
-        run_script(scope, context, "test_ffi_opt_arg('test')");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 5);
-        // return val & roundtripping
-        global.set(
-            context,
-            make_str(scope, "test_ffi_return"),
-            load_v8_ffi!(test_ffi_return, scope, context),
-        );
-        global.set(
-            context,
-            make_str(scope, "test_ffi_roundtrip"),
-            load_v8_ffi!(test_ffi_roundtrip, scope, context),
-        );
-        global.set(
-            context,
-            make_str(scope, "test_ffi_roundtrip_check"),
-            load_v8_ffi!(test_ffi_roundtrip_check, scope, context),
-        );
-        run_script(
-            scope,
-            context,
-            "test_ffi_roundtrip_check(test_ffi_roundtrip(test_ffi_return()))",
-        );
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 6);
-        // throw on bad type
-        run_script(
-            scope,
-            context,
-            "try { test_ffi_arg(undefined) } catch (e) { test_ffi_arg('test2') }",
-        );
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 3);
-        // can pass result
-        global.set(
-            context,
-            make_str(scope, "test_ffi_result"),
-            load_v8_ffi!(test_ffi_result, scope, context),
-        );
-        run_script(
-            scope,
-            context,
-            "try { test_ffi_result('success') } catch (e) { test_ffi_arg('test1') }",
-        );
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 3);
-        // can fail result
-        run_script(
-            scope,
-            context,
-            "try { test_ffi_result('failure') } catch (e) { test_ffi_arg('test1') }",
-        );
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 2);
-        // vectors
-        global.set(
-            context,
-            make_str(scope, "test_ffi_vec"),
-            load_v8_ffi!(test_ffi_vec, scope, context),
-        );
-        run_script(scope, context, "test_ffi_vec(['test'])");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 7);
-        run_script(scope, context, "test_ffi_vec(test_ffi_vec(['test']))");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 8);
-        // immutable ffi wrap
-        global.set(
-            context,
-            make_str(scope, "test_ffi_wrap"),
-            load_v8_ffi!(test_ffi_wrap, scope, context),
-        );
-        let test_ffi_wrap_data = make_object_wrap(scope, context, TestWrapper("test1".to_string()));
-        global.set(
-            context,
-            make_str(scope, "test_ffi_wrap_data"),
-            test_ffi_wrap_data.get(scope).unwrap().into(),
-        );
-        run_script(scope, context, "test_ffi_wrap.bind(test_ffi_wrap_data)()");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 9);
-        let test_ffi_wrap_data2 =
-            make_object_wrap(scope, context, TestWrapper("test2".to_string()));
-        global.set(
-            context,
-            make_str(scope, "test_ffi_wrap_data2"),
-            test_ffi_wrap_data2.get(scope).unwrap().into(),
-        );
-        run_script(scope, context, "test_ffi_wrap.bind(test_ffi_wrap_data2)()");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 10);
-        global.set(
-            context,
-            make_str(scope, "test_ffi_wrap_mut"),
-            load_v8_ffi!(test_ffi_wrap_mut, scope, context),
-        );
-        let test_ffi_wrap_mut_data =
-            make_object_wrap(scope, context, Mutex::new(TestWrapper("test1".to_string())));
-        global.set(
-            context,
-            make_str(scope, "test_ffi_wrap_mut_data"),
-            test_ffi_wrap_mut_data.get(scope).unwrap().into(),
-        );
-        run_script(
-            scope,
-            context,
-            "test_ffi_wrap_mut.bind(test_ffi_wrap_mut_data)()",
-        );
-        assert_eq!(
-            test_ffi_wrap_mut_data
-                .unwrap(scope)
-                .unwrap()
-                .lock()
-                .unwrap()
-                .0,
-            "test3"
-        );
-        run_script(
-            scope,
-            context,
-            "test_ffi_wrap_mut.bind(test_ffi_wrap_mut_data)()",
-        );
-        assert_eq!(
-            test_ffi_wrap_mut_data
-                .unwrap(scope)
-                .unwrap()
-                .lock()
-                .unwrap()
-                .0,
-            "test4"
-        );
-        global.set(
-            context,
-            make_str(scope, "test_ffi_obj"),
-            load_v8_ffi!(test_ffi_obj, scope, context),
-        );
-        run_script(scope, context, "test_ffi_obj({ value: 'test1' })");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 11);
-        run_script(
-            scope,
-            context,
-            "test_ffi_obj(test_ffi_obj({ value: 'test1' }))",
-        );
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 12);
-        global.set(
-            context,
-            make_str(scope, "test_ffi_result_join"),
-            load_v8_ffi!(test_ffi_result_join, scope, context),
-        );
-        run_script(scope, context, "test_ffi_result_join('test')");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 13);
-        global.set(
-            context,
-            make_str(scope, "test_ffi_unit"),
-            load_v8_ffi!(test_ffi_unit, scope, context),
-        );
-        run_script(scope, context, "test_ffi_unit()");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 14);
-        global.set(
-            context,
-            make_str(scope, "test_ffi_tuple2_1"),
-            load_v8_ffi!(test_ffi_tuple2_1, scope, context),
-        );
-        global.set(
-            context,
-            make_str(scope, "test_ffi_tuple2_2"),
-            load_v8_ffi!(test_ffi_tuple2_2, scope, context),
-        );
-        run_script(scope, context, "test_ffi_tuple2_1(['test', 10])");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 15);
-        run_script(
-            scope,
-            context,
-            "test_ffi_tuple2_2(test_ffi_tuple2_1(['test', 10]))",
-        );
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 16);
-        global.set(
-            context,
-            make_str(scope, "test_ffi_tuple3"),
-            load_v8_ffi!(test_ffi_tuple3, scope, context),
-        );
-        run_script(scope, context, "test_ffi_tuple3(['test', 10, 'test2'])");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 17);
-        global.set(
-            context,
-            make_str(scope, "test_ffi_tuple4"),
-            load_v8_ffi!(test_ffi_tuple4, scope, context),
-        );
-        run_script(scope, context, "test_ffi_tuple4(['test', 10, 'test2', 20])");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 18);
-        global.set(
-            context,
-            make_str(scope, "test_ffi_tuple5"),
-            load_v8_ffi!(test_ffi_tuple5, scope, context),
-        );
-        run_script(
-            scope,
-            context,
-            "test_ffi_tuple5(['test', 10, 'test2', 20, 'test3'])",
-        );
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 19);
+        with_context!(|scope, context| {
+            let global = context.global(scope);
+            //basic
+            global.set(
+                context,
+                make_str(scope, "test_ffi_basic"),
+                load_v8_ffi!(test_ffi_basic, scope, context),
+            );
+            run_script(scope, context, "test_ffi_basic()");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 1);
+            //arg
+            global.set(
+                context,
+                make_str(scope, "test_ffi_arg"),
+                load_v8_ffi!(test_ffi_arg, scope, context),
+            );
+            run_script(scope, context, "test_ffi_arg('test1')");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 2);
+            run_script(scope, context, "test_ffi_arg('test2')");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 3);
+            // optional arg
+            global.set(
+                context,
+                make_str(scope, "test_ffi_opt_arg"),
+                load_v8_ffi!(test_ffi_opt_arg, scope, context),
+            );
+            run_script(scope, context, "test_ffi_opt_arg(null)");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 4);
+            run_script(scope, context, "test_ffi_opt_arg('test')");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 5);
+            run_script(scope, context, "test_ffi_opt_arg(undefined)");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 4);
+            run_script(scope, context, "test_ffi_opt_arg('test')");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 5);
+            run_script(scope, context, "try { test_ffi_opt_arg(77) } catch (e) {}");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 5);
+            global.set(
+                context,
+                make_str(scope, "test_ffi_lenient_arg"),
+                load_v8_ffi!(test_ffi_lenient_arg, scope, context),
+            );
+            run_script(scope, context, "test_ffi_lenient_arg(77)");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 4);
+            run_script(scope, context, "test_ffi_opt_arg('test')");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 5);
+            // return val & roundtripping
+            global.set(
+                context,
+                make_str(scope, "test_ffi_return"),
+                load_v8_ffi!(test_ffi_return, scope, context),
+            );
+            global.set(
+                context,
+                make_str(scope, "test_ffi_roundtrip"),
+                load_v8_ffi!(test_ffi_roundtrip, scope, context),
+            );
+            global.set(
+                context,
+                make_str(scope, "test_ffi_roundtrip_check"),
+                load_v8_ffi!(test_ffi_roundtrip_check, scope, context),
+            );
+            run_script(
+                scope,
+                context,
+                "test_ffi_roundtrip_check(test_ffi_roundtrip(test_ffi_return()))",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 6);
+            // throw on bad type
+            run_script(
+                scope,
+                context,
+                "try { test_ffi_arg(undefined) } catch (e) { test_ffi_arg('test2') }",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 3);
+            // can pass result
+            global.set(
+                context,
+                make_str(scope, "test_ffi_result"),
+                load_v8_ffi!(test_ffi_result, scope, context),
+            );
+            run_script(
+                scope,
+                context,
+                "try { test_ffi_result('success') } catch (e) { test_ffi_arg('test1') }",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 3);
+            // can fail result
+            run_script(
+                scope,
+                context,
+                "try { test_ffi_result('failure') } catch (e) { test_ffi_arg('test1') }",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 2);
+            // vectors
+            global.set(
+                context,
+                make_str(scope, "test_ffi_vec"),
+                load_v8_ffi!(test_ffi_vec, scope, context),
+            );
+            run_script(scope, context, "test_ffi_vec(['test'])");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 7);
+            run_script(scope, context, "test_ffi_vec(test_ffi_vec(['test']))");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 8);
+            // immutable ffi wrap
+            global.set(
+                context,
+                make_str(scope, "test_ffi_wrap"),
+                load_v8_ffi!(test_ffi_wrap, scope, context),
+            );
+            let test_ffi_wrap_data =
+                make_object_wrap(scope, context, TestWrapper("test1".to_string()));
+            global.set(
+                context,
+                make_str(scope, "test_ffi_wrap_data"),
+                test_ffi_wrap_data.get(scope).unwrap().into(),
+            );
+            run_script(scope, context, "test_ffi_wrap.bind(test_ffi_wrap_data)()");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 9);
+            let inspected = run_script(
+                scope,
+                context,
+                "const inspected = { n: -0, s: 'it\\'s', f() {}, deep: { a: { b: { c: {} } } }, wrap: test_ffi_wrap_data, 'a-b': [1n, Symbol('x'), null] }; inspected.self = inspected; inspected",
+            )
+            .unwrap();
+            assert_eq!(
+                crate::util::inspect(scope, context, inspected),
+                "{\n  n: -0,\n  s: 'it\\'s',\n  f: [Function: f],\n  deep: { a: { b: [Object] } },\n  wrap: [Native rusty_v8_helper::ffi_map::tests::TestWrapper] {},\n  'a-b': [ 1n, Symbol(x), null ],\n  self: [Circular]\n}"
+            );
+            let error = run_script(scope, context, "new RangeError('bad')").unwrap();
+            assert!(
+                crate::util::inspect(scope, context, error).starts_with("RangeError: bad\n    at ")
+            );
+            let long =
+                run_script(scope, context, "Array.from({ length: 102 }, (_, i) => i)").unwrap();
+            let options = crate::util::InspectOptions {
+                max_array_length: 2,
+                ..Default::default()
+            };
+            assert_eq!(
+                crate::util::inspect_with(scope, context, long, options),
+                "[ 0, 1, ... 100 more items ]"
+            );
+            let test_ffi_wrap_data2 =
+                make_object_wrap(scope, context, TestWrapper("test2".to_string()));
+            global.set(
+                context,
+                make_str(scope, "test_ffi_wrap_data2"),
+                test_ffi_wrap_data2.get(scope).unwrap().into(),
+            );
+            run_script(scope, context, "test_ffi_wrap.bind(test_ffi_wrap_data2)()");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 10);
+            global.set(
+                context,
+                make_str(scope, "test_ffi_wrap_mut"),
+                load_v8_ffi!(test_ffi_wrap_mut, scope, context),
+            );
+            let test_ffi_wrap_mut_data =
+                make_object_wrap(scope, context, Mutex::new(TestWrapper("test1".to_string())));
+            global.set(
+                context,
+                make_str(scope, "test_ffi_wrap_mut_data"),
+                test_ffi_wrap_mut_data.get(scope).unwrap().into(),
+            );
+            run_script(
+                scope,
+                context,
+                "test_ffi_wrap_mut.bind(test_ffi_wrap_mut_data)()",
+            );
+            assert_eq!(
+                test_ffi_wrap_mut_data
+                    .unwrap(scope)
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .0,
+                "test3"
+            );
+            run_script(
+                scope,
+                context,
+                "test_ffi_wrap_mut.bind(test_ffi_wrap_mut_data)()",
+            );
+            assert_eq!(
+                test_ffi_wrap_mut_data
+                    .unwrap(scope)
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .0,
+                "test4"
+            );
+            global.set(
+                context,
+                make_str(scope, "test_ffi_obj"),
+                load_v8_ffi!(test_ffi_obj, scope, context),
+            );
+            run_script(scope, context, "test_ffi_obj({ value: 'test1' })");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 11);
+            run_script(
+                scope,
+                context,
+                "test_ffi_obj(test_ffi_obj({ value: 'test1' }))",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 12);
+            global.set(
+                context,
+                make_str(scope, "test_ffi_result_join"),
+                load_v8_ffi!(test_ffi_result_join, scope, context),
+            );
+            run_script(scope, context, "test_ffi_result_join('test')");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 13);
+            global.set(
+                context,
+                make_str(scope, "test_ffi_unit"),
+                load_v8_ffi!(test_ffi_unit, scope, context),
+            );
+            run_script(scope, context, "test_ffi_unit()");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 14);
+            global.set(
+                context,
+                make_str(scope, "test_ffi_tuple2_1"),
+                load_v8_ffi!(test_ffi_tuple2_1, scope, context),
+            );
+            global.set(
+                context,
+                make_str(scope, "test_ffi_tuple2_2"),
+                load_v8_ffi!(test_ffi_tuple2_2, scope, context),
+            );
+            run_script(scope, context, "test_ffi_tuple2_1(['test', 10])");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 15);
+            run_script(
+                scope,
+                context,
+                "test_ffi_tuple2_2(test_ffi_tuple2_1(['test', 10]))",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 16);
+            global.set(
+                context,
+                make_str(scope, "test_ffi_tuple3"),
+                load_v8_ffi!(test_ffi_tuple3, scope, context),
+            );
+            run_script(scope, context, "test_ffi_tuple3(['test', 10, 'test2'])");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 17);
+            global.set(
+                context,
+                make_str(scope, "test_ffi_tuple4"),
+                load_v8_ffi!(test_ffi_tuple4, scope, context),
+            );
+            run_script(scope, context, "test_ffi_tuple4(['test', 10, 'test2', 20])");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 18);
+            global.set(
+                context,
+                make_str(scope, "test_ffi_tuple5"),
+                load_v8_ffi!(test_ffi_tuple5, scope, context),
+            );
+            run_script(
+                scope,
+                context,
+                "test_ffi_tuple5(['test', 10, 'test2', 20, 'test3'])",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 19);
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_scoped"),
+                load_v8_ffi!(test_ffi_scoped, scope, context),
+            );
+            run_script(scope, context, "test_ffi_scoped('test1')");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 20);
+            run_script(scope, context, "test_ffi_scoped(test_ffi_scoped('test1'))");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 21);
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_explicit_wrap"),
+                load_v8_ffi!(test_ffi_explicit_wrap, scope, context),
+            );
+            global.set(
+                context,
+                make_str(scope, "check_ffi_explicit_wrap"),
+                load_v8_ffi!(check_ffi_explicit_wrap, scope, context),
+            );
+            global.set(
+                context,
+                make_str(scope, "ffi_wrap_make_str"),
+                load_v8_ffi!(ffi_wrap_make_str, scope, context),
+            );
+            run_script(
+                scope,
+                context,
+                "test_ffi_explicit_wrap(ffi_wrap_make_str('test'))",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 22);
+            run_script(
+                scope,
+                context,
+                "test_ffi_explicit_wrap(ffi_wrap_make_str('fgsdfg'))",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 23);
+            run_script(
+                scope,
+                context,
+                "check_ffi_explicit_wrap(test_ffi_explicit_wrap(ffi_wrap_make_str('7')))",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 24);
+            run_script(
+                scope,
+                context,
+                "check_ffi_explicit_wrap(test_ffi_explicit_wrap(ffi_wrap_make_str('test')))",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 25);
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_numeric"),
+                load_v8_ffi!(test_ffi_numeric, scope, context),
+            );
+            run_script(scope, context, "test_ffi_numeric(50, 4)");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 26);
+            run_script(
+                scope,
+                context,
+                "try { test_ffi_numeric(150, 4) } catch (e) { if (e instanceof RangeError) test_ffi_arg('test1') }",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 2);
+            run_script(
+                scope,
+                context,
+                "try { test_ffi_numeric(50, 0.5) } catch (e) { if (e instanceof RangeError) test_ffi_arg('test2') }",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 3);
+
+            struct TestObserver(Rc<std::cell::RefCell<Vec<String>>>);
+            impl crate::instrument::FfiObserver for TestObserver {
+                fn on_call_start(&self, function: &'static str) {
+                    self.0.borrow_mut().push(format!("start {}", function));
+                }
+
+                fn on_conversion_error(&self, function: &'static str, _error: &str) {
+                    self.0.borrow_mut().push(format!("conversion {}", function));
+                }
+            }
+            let events = Rc::new(std::cell::RefCell::new(vec![]));
+            crate::instrument::set_ffi_observer(scope, TestObserver(events.clone()));
+            run_script(
+                scope,
+                context,
+                "test_ffi_numeric(50, 4); try { test_ffi_numeric(150, 4) } catch (e) {}",
+            );
+            crate::instrument::clear_ffi_observer(scope);
+            run_script(scope, context, "test_ffi_numeric(50, 4)");
+            assert_eq!(
+                *events.borrow(),
+                vec![
+                    "start test_ffi_numeric",
+                    "start test_ffi_numeric",
+                    "conversion test_ffi_numeric"
+                ]
+            );
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_sink"),
+                load_v8_ffi!(test_ffi_sink, scope, context),
+            );
+            global.set(
+                context,
+                make_str(scope, "test_ffi_sink_check"),
+                load_v8_ffi!(test_ffi_sink_check, scope, context),
+            );
+            run_script(scope, context, "test_ffi_sink(x => test_ffi_sink_check(x))");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 3);
+            assert_eq!(crate::event_loop::run_pending(scope), 3);
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 29);
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_blocking"),
+                load_v8_ffi!(test_ffi_blocking, scope, context),
+            );
+            run_script(
+                scope,
+                context,
+                "test_ffi_blocking(3).then(x => test_ffi_sink_check(x - 3))",
+            );
+            crate::event_loop::run_until_idle(scope);
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 30);
+
+            use crate::Extension;
+            crate::extensions::abort::AbortExtension
+                .install(scope, context)
+                .unwrap();
+            global.set(
+                context,
+                make_str(scope, "test_ffi_abort"),
+                load_v8_ffi!(test_ffi_abort, scope, context),
+            );
+            run_script(
+                scope,
+                context,
+                "const controller = new AbortController(); test_ffi_abort(controller.signal)",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 30);
+            run_script(
+                scope,
+                context,
+                "controller.abort(); test_ffi_abort(controller.signal)",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 31);
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_coerce"),
+                load_v8_ffi!(test_ffi_coerce, scope, context),
+            );
+            run_script(scope, context, "test_ffi_coerce('5', 7, 1, '1.5')");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 32);
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_truthy"),
+                load_v8_ffi!(test_ffi_truthy, scope, context),
+            );
+            let error = run_script(
+                scope,
+                context,
+                "try { test_ffi_truthy('yes', 1) } catch (e) { e }",
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(error, scope, context).unwrap(),
+                "\"invalid type for argument in ffi call, expected boolean, received number\""
+            );
+            run_script(scope, context, "test_ffi_truthy('yes', false)");
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 33);
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_strict_utf8"),
+                load_v8_ffi!(test_ffi_strict_utf8, scope, context),
+            );
+            global.set(
+                context,
+                make_str(scope, "test_ffi_utf16"),
+                load_v8_ffi!(test_ffi_utf16, scope, context),
+            );
+            run_script(
+                scope,
+                context,
+                "try { test_ffi_strict_utf8('test\\uD83D') } catch (e) { if (e instanceof TypeError) test_ffi_arg('test1') }",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 2);
+            run_script(
+                scope,
+                context,
+                "if (test_ffi_utf16('a\\uD83D') === 'a\\uD83D') test_ffi_strict_utf8('test\\uD83D\\uDE00')",
+            );
+            assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 34);
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_borrowed"),
+                load_v8_ffi!(test_ffi_borrowed, scope, context),
+            );
+            let result = run_script(
+                scope,
+                context,
+                "test_ffi_borrowed('abc', new Uint8Array([1, 2]))",
+            )
+            .unwrap();
+            assert_eq!(u32::from_value(result, scope, context), Ok(5));
+
+            let plugin = run_script(
+                scope,
+                context,
+                "({ name: 'plugin', addTwo(x) { return x + 2 } })",
+            )
+            .unwrap();
+            let plugin = TestPlugin::from_value(plugin, scope, context).unwrap();
+            assert_eq!(plugin.name(scope, context).unwrap(), "plugin");
+            assert_eq!(plugin.add_two(scope, context, 3).unwrap(), 5);
+
+            let options = run_script(scope, context, "({ name: 'opts', maxCount: 3 })").unwrap();
+            let options = TestOptions::from_value(options, scope, context).unwrap();
+            assert_eq!(options.name, "opts");
+            assert_eq!(options.max_count, 3);
+            assert!(!options.verbose);
+            assert_eq!(options.label, None);
+            let options = run_script(scope, context, "({ maxCount: 'x', verbose: 1 })").unwrap();
+            let error = TestOptions::from_value(options, scope, context)
+                .err()
+                .unwrap();
+            assert_eq!(error.message().matches("field `").count(), 3);
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_js_value"),
+                load_v8_ffi!(test_ffi_js_value, scope, context),
+            );
+            let result = run_script(
+                scope,
+                context,
+                "const jsInput = {}; const out = test_ffi_js_value(jsInput); out.count + out.items.join('').length + (out.input === jsInput ? 1 : 0)",
+            )
+            .unwrap();
+            assert_eq!(u32::from_value(result, scope, context), Ok(5));
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_return_error"),
+                load_v8_ffi!(test_ffi_return_error, scope, context),
+            );
+            global.set(
+                context,
+                make_str(scope, "test_ffi_return_undefined"),
+                load_v8_ffi!(test_ffi_return_undefined, scope, context),
+            );
+            let result = run_script(
+                scope,
+                context,
+                "let thrown; try { test_ffi_return_error() } catch (e) { thrown = e instanceof RangeError }; thrown && test_ffi_return_undefined() === undefined",
+            )
+            .unwrap();
+            assert!(result.is_true());
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_multi_return"),
+                load_v8_ffi!(test_ffi_multi_return, scope, context),
+            );
+            let result = run_script(
+                scope,
+                context,
+                "const read = test_ffi_multi_return(3); read.bytesRead === 3 && read.eof === false && !Array.isArray(read)",
+            )
+            .unwrap();
+            assert!(result.is_true());
+
+            crate::rename::set_function(
+                scope,
+                context,
+                global,
+                "test_ffi_renamed",
+                load_v8_ffi!(test_ffi_renamed, scope, context),
+            );
+            let result = run_script(
+                scope,
+                context,
+                "const renamed = test_ffi_renamed({ maxRetries: 2, URL: 'a' }); renamed.maxRetries === 3 && renamed.URL === 'a'",
+            )
+            .unwrap();
+            assert!(result.is_true());
+            crate::rename::set_rename_policy(scope, RenamePolicy::CamelCase);
+            crate::rename::set_function(
+                scope,
+                context,
+                global,
+                "test_ffi_renamed",
+                load_v8_ffi!(test_ffi_renamed, scope, context),
+            );
+            crate::rename::set_rename_policy(scope, RenamePolicy::Keep);
+            let result = run_script(scope, context, "typeof testFfiRenamed").unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok("function".to_string())
+            );
+            let options = run_script(scope, context, "({ maxCount: 4, label: 'x' })").unwrap();
+            let options = TestRenamedOptions::from_value(options, scope, context).unwrap();
+            assert_eq!(options.max_count, 4);
+            assert_eq!(options.label_text, "x");
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_wrap_reentrant"),
+                load_v8_ffi!(test_ffi_wrap_reentrant, scope, context),
+            );
+            let result = run_script(
+                scope,
+                context,
+                "test_ffi_wrap_reentrant.bind(test_ffi_wrap_mut_data)()",
+            )
+            .unwrap();
+            let message = String::from_value(result, scope, context).unwrap();
+            assert!(message.starts_with("reentrant ffi call"), "{}", message);
+            assert!(message.contains("still in use by test_ffi_wrap_reentrant"));
+            // the lock is released once the outer call returns
+            run_script(
+                scope,
+                context,
+                "test_ffi_wrap_mut.bind(test_ffi_wrap_mut_data)()",
+            );
+            assert_eq!(
+                test_ffi_wrap_mut_data
+                    .unwrap(scope)
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .0,
+                "test5"
+            );
+
+            let result = run_script(
+                scope,
+                context,
+                "(() => { try { test_ffi_wrap.bind({})(); } catch (e) { return e instanceof TypeError && e.message; } })()",
+            )
+            .unwrap();
+            let expected = if cfg!(feature = "minimal-errors") {
+                "invalid 'this' for ffi call test_ffi_wrap"
+            } else {
+                "invalid 'this' for ffi call test_ffi_wrap: not a wrapped object"
+            };
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok(expected.to_string())
+            );
+            let result = run_script(
+                scope,
+                context,
+                "(() => { try { test_ffi_wrap.bind(test_ffi_wrap_mut_data)(); } catch (e) { return e.message; } })()",
+            )
+            .unwrap();
+            let message = String::from_value(result, scope, context).unwrap();
+            assert!(
+                message.contains("wrong type (expected rusty_v8_helper::ffi_map::tests::TestWrapper, found std::sync::Mutex<"),
+                "{}",
+                message
+            );
+
+            ObjectWrap::<Rc<dyn TestNamed>>::register_cast(
+                |x: Rc<TestWrapper>| -> Rc<dyn TestNamed> { x },
+            );
+            global.set(
+                context,
+                make_str(scope, "test_ffi_wrap_dyn"),
+                load_v8_ffi!(test_ffi_wrap_dyn, scope, context),
+            );
+            let numbered: Rc<dyn TestNamed> = Rc::new(TestNumbered(3));
+            let test_ffi_wrap_dyn_data = make_object_wrap(scope, context, numbered);
+            global.set(
+                context,
+                make_str(scope, "test_ffi_wrap_dyn_data"),
+                test_ffi_wrap_dyn_data.get(scope).unwrap().into(),
+            );
+            let result = run_script(
+                scope,
+                context,
+                "test_ffi_wrap_dyn.bind(test_ffi_wrap_dyn_data)() + ', ' + test_ffi_wrap_dyn.bind(test_ffi_wrap_data2)()",
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok("number 3, test2".to_string())
+            );
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_this_of"),
+                load_v8_ffi!(test_ffi_this_of, scope, context),
+            );
+            let test_ffi_this_of_data = make_object_wrap(scope, context, TestNumbered(4));
+            global.set(
+                context,
+                make_str(scope, "test_ffi_this_of_data"),
+                test_ffi_this_of_data.get(scope).unwrap().into(),
+            );
+            let result = run_script(
+                scope,
+                context,
+                "test_ffi_this_of.bind(test_ffi_wrap_data2)() + ', ' + test_ffi_this_of.bind(test_ffi_this_of_data)()",
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok("wrapper test2, numbered 4".to_string())
+            );
+            let result = run_script(
+                scope,
+                context,
+                "(() => { try { test_ffi_this_of.bind(test_ffi_wrap_mut_data)(); } catch (e) { return e.message; } })()",
+            )
+            .unwrap();
+            let message = String::from_value(result, scope, context).unwrap();
+            assert!(
+                message.contains("expected rusty_v8_helper::ffi_map::tests::TestWrapper | rusty_v8_helper::ffi_map::tests::TestNumbered"),
+                "{}",
+                message
+            );
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_binary"),
+                load_v8_ffi!(test_ffi_binary, scope, context),
+            );
+            let result = run_script(
+                scope,
+                context,
+                "(() => { const binary = test_ffi_binary({ name: 'abc', data: new Uint8Array([1, 2]) }); return binary.data instanceof Uint8Array && binary.data.join() === '1,2,3'; })()",
+            )
+            .unwrap();
+            assert!(result.is_true());
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_local_args"),
+                load_v8_ffi!(test_ffi_local_args, scope, context),
+            );
+            let result = run_script(
+                scope,
+                context,
+                "(() => { const target = {}; return test_ffi_local_args(() => 1, target) === target; })()",
+            )
+            .unwrap();
+            assert!(result.is_true());
+            let result = run_script(
+                scope,
+                context,
+                "(() => { try { test_ffi_local_args(1, {}); } catch (e) { return e instanceof TypeError && e.message; } })()",
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok(
+                    "invalid type for argument in ffi call, expected function, received number"
+                        .to_string()
+                )
+            );
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_stash"),
+                load_v8_ffi!(test_ffi_stash, scope, context),
+            );
+            run_script(scope, context, "test_ffi_stash((x) => x * 2)").unwrap();
+            let stashed = STASHED.with(|stashed| stashed.borrow_mut().take()).unwrap();
+            let recv = v8::undefined(scope).into();
+            let arg = make_num(scope, 21.0);
+            let result = stashed.call(scope, context, recv, &[arg]).unwrap();
+            assert_eq!(f64::from_value(result, scope, context), Ok(42.0));
+            stashed.release(scope);
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_obj_count"),
+                load_v8_ffi!(test_ffi_obj_count, scope, context),
+            );
+            let result = run_script(
+                scope,
+                context,
+                "(() => { try { test_ffi_obj_count([{ value: 'a' }, { value: 3 }]); } catch (e) { return e; } })()",
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok(format!(
+                    "{:?}",
+                    "value[1].value: invalid type: floating point `3.0`, expected a string"
+                ))
+            );
+
+            let order = run_script(scope, context, "({ items: [1, -1], note: '' })").unwrap();
+            assert_eq!(
+                TestOrder::from_value(order, scope, context).err(),
+                Some(
+                    "value.items[1]: must be at least 0; value.note: unexpected property"
+                        .to_string()
+                )
+            );
+
+            load_v8_ffi!(test_ffi_obj, scope, context, global, "nested.ffi.testObj").unwrap();
+            let result = run_script(
+                scope,
+                context,
+                "nested.ffi.testObj({ value: 'test1' }).value",
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok("test2".to_string())
+            );
+            load_v8_ffi!(
+                reexports::renamed_test_ffi_obj,
+                scope,
+                context,
+                global,
+                "nested.renamed"
+            )
+            .unwrap();
+            let result =
+                run_script(scope, context, "nested.renamed({ value: 'test1' }).value").unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok("test2".to_string())
+            );
+            run_script(scope, context, "nested.blocked = 1").unwrap();
+            assert_eq!(
+                load_v8_ffi!(
+                    test_ffi_obj,
+                    scope,
+                    context,
+                    global,
+                    "nested.blocked.testObj"
+                ),
+                Err(crate::FFIError::TypeError(
+                    "cannot set nested.blocked.testObj: nested.blocked is number, not an object"
+                        .to_string()
+                ))
+            );
+
+            let meta = <test_ffi_local_args as crate::FfiFn>::META;
+            assert_eq!(meta.arity, 2);
+            assert_eq!(
+                meta.signature(),
+                "test_ffi_local_args(callback: v8::Local<v8::Function>, target: v8::Local<v8::Object>) -> v8::Local<v8::Object>"
+            );
+            assert_eq!(
+                meta.doc,
+                "Calls `callback` with `target`.\n\nReturns `target`."
+            );
+            let loaded = crate::loaded_functions(scope);
+            assert!(loaded.contains(&meta));
+            assert_eq!(
+                loaded.iter().filter(|x| x.name == "test_ffi_obj").count(),
+                1
+            );
+            let listed = crate::FfiFnMeta::to_js(&<test_ffi_obj as crate::FfiFn>::META)
+                .to_value(scope, context)
+                .unwrap();
+            global.set(context, make_str(scope, "testObjMeta"), listed);
+            let result = run_script(
+                scope,
+                context,
+                "JSON.stringify([testObjMeta.arity, testObjMeta.params, testObjMeta.returns])",
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok(r#"[1,[{"name":"arg","type":"TestObj"}],"TestObj"]"#.to_string())
+            );
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_nullish"),
+                load_v8_ffi!(test_ffi_nullish, scope, context),
+            );
+            global.set(
+                context,
+                make_str(scope, "test_ffi_undefined"),
+                load_v8_ffi!(test_ffi_undefined, scope, context),
+            );
+            let result = run_script(
+                scope,
+                context,
+                "[test_ffi_nullish(true, null), test_ffi_nullish(false, null), test_ffi_undefined()]",
+            )
+            .unwrap();
+            assert_eq!(
+                crate::util::inspect(scope, context, result),
+                "[ 1, null, undefined ]"
+            );
+            crate::set_none_value(scope, crate::NoneValue::Undefined);
+            let result = run_script(scope, context, "test_ffi_nullish(false, null)").unwrap();
+            assert!(result.is_undefined());
+            crate::set_none_value(scope, crate::NoneValue::Null);
+            let result = run_script(
+                scope,
+                context,
+                "(() => { try { test_ffi_nullish(false); } catch (e) { return e instanceof TypeError && e.message; } })()",
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok(
+                    "invalid type for argument in ffi call, expected null, received undefined"
+                        .to_string()
+                )
+            );
+            let result = run_script(
+                scope,
+                context,
+                "(() => { try { test_ffi_undefined(null); } catch (e) { return e.message; } })()",
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok(
+                    "invalid type for argument in ffi call, expected undefined, received null"
+                        .to_string()
+                )
+            );
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_timestamp"),
+                load_v8_ffi!(test_ffi_timestamp, scope, context),
+            );
+            let result = run_script(
+                scope,
+                context,
+                "[test_ffi_timestamp(new Date(1000), 0.5), test_ffi_timestamp(-1000, 0)]",
+            )
+            .unwrap();
+            assert_eq!(
+                Vec::<f64>::from_value(result, scope, context),
+                Ok(vec![1000.5, -1000.0])
+            );
+            crate::set_time_format(scope, crate::TimeFormat::Date);
+            let result =
+                run_script(scope, context, "test_ffi_timestamp(0, 1500).toISOString()").unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok("1970-01-01T00:00:01.500Z".to_string())
+            );
+            crate::set_time_format(scope, crate::TimeFormat::Number);
+            for (call, error) in [
+                (
+                    "test_ffi_timestamp(1.5, 0)",
+                    "RangeError: 1.5 is out of range, expected whole milliseconds",
+                ),
+                (
+                    "test_ffi_timestamp(new Date(NaN), 0)",
+                    "RangeError: NaN is out of range, expected milliseconds within 8640000000000000 of the epoch",
+                ),
+                (
+                    "test_ffi_timestamp(0, '1')",
+                    "TypeError: invalid type for argument in ffi call, expected number or Date, received string",
+                ),
+            ]
+            .iter()
+            {
+                let script = format!("(() => {{ try {{ {}; }} catch (e) {{ return String(e); }} }})()", call);
+                let result = run_script(scope, context, &script).unwrap();
+                assert_eq!(String::from_value(result, scope, context), Ok(error.to_string()));
+            }
 
-        global.set(
-            context,
-            make_str(scope, "test_ffi_scoped"),
-            load_v8_ffi!(test_ffi_scoped, scope, context),
-        );
-        run_script(scope, context, "test_ffi_scoped('test1')");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 20);
-        run_script(scope, context, "test_ffi_scoped(test_ffi_scoped('test1'))");
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 21);
-
-        global.set(
-            context,
-            make_str(scope, "test_ffi_explicit_wrap"),
-            load_v8_ffi!(test_ffi_explicit_wrap, scope, context),
-        );
-        global.set(
-            context,
-            make_str(scope, "check_ffi_explicit_wrap"),
-            load_v8_ffi!(check_ffi_explicit_wrap, scope, context),
-        );
-        global.set(
-            context,
-            make_str(scope, "ffi_wrap_make_str"),
-            load_v8_ffi!(ffi_wrap_make_str, scope, context),
-        );
-        run_script(
-            scope,
-            context,
-            "test_ffi_explicit_wrap(ffi_wrap_make_str('test'))",
-        );
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 22);
-        run_script(
-            scope,
-            context,
-            "test_ffi_explicit_wrap(ffi_wrap_make_str('fgsdfg'))",
-        );
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 23);
-        run_script(
-            scope,
-            context,
-            "check_ffi_explicit_wrap(test_ffi_explicit_wrap(ffi_wrap_make_str('7')))",
-        );
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 24);
-        run_script(
-            scope,
-            context,
-            "check_ffi_explicit_wrap(test_ffi_explicit_wrap(ffi_wrap_make_str('test')))",
-        );
-        assert_eq!(TEST_RESPONSE.load(Ordering::SeqCst), 25);
+            global.set(
+                context,
+                make_str(scope, "test_ffi_store_get"),
+                load_v8_ffi!(test_ffi_store_get, scope, context),
+            );
+            let thrown = run_script(
+                scope,
+                context,
+                "(() => { try { test_ffi_store_get('a'); } catch (e) { return e; } })()",
+            )
+            .unwrap();
+            // thrown as its `Debug` string until the classes are installed
+            assert!(thrown.is_string());
+            assert!(thrown.to_rust_string_lossy(scope).contains("NotFound"));
+            crate::install_error_classes::<TestStoreError>(scope, context, global).unwrap();
+            let result = run_script(
+                scope,
+                context,
+                "(() => { try { test_ffi_store_get('a'); } catch (e) { \
+                    return [e instanceof NotFound, e instanceof TestStoreError, e instanceof Error, \
+                        !(e instanceof Full), e.name === 'NotFound', e.message === 'no entry a', \
+                        e.key === 'a', e.attempts === 2, e.tries === undefined, \
+                        String(e) === 'NotFound: no entry a']; } })()",
+            )
+            .unwrap();
+            assert_eq!(
+                Vec::<bool>::from_value(result, scope, context),
+                Ok(vec![true; 10])
+            );
+            let result = run_script(
+                scope,
+                context,
+                "(() => { try { test_ffi_store_get('full'); } catch (e) { \
+                    return [e instanceof Full, e.values.length === 1, e.values[0] === 3]; } })()",
+            )
+            .unwrap();
+            assert_eq!(
+                Vec::<bool>::from_value(result, scope, context),
+                Ok(vec![true; 3])
+            );
+            let result = run_script(
+                scope,
+                context,
+                "(() => { try { test_ffi_store_get('closed'); } catch (e) { \
+                    return [e instanceof Closed, Object.keys(e).length === 0, \
+                        TestStoreError.name === 'TestStoreError']; } })()",
+            )
+            .unwrap();
+            assert_eq!(
+                Vec::<bool>::from_value(result, scope, context),
+                Ok(vec![true; 3])
+            );
+
+            global.set(
+                context,
+                make_str(scope, "test_ffi_with"),
+                load_v8_ffi!(test_ffi_with, scope, context),
+            );
+            let result = run_script(scope, context, "test_ffi_with('ff', 1)").unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok("100".to_string())
+            );
+            for (call, error) in [
+                ("test_ffi_with(255, 1)", "TypeError: expected a hex string"),
+                ("test_ffi_with('zz', 1)", "RangeError: invalid hex zz"),
+            ]
+            .iter()
+            {
+                let script = format!(
+                    "(() => {{ try {{ {}; }} catch (e) {{ return String(e); }} }})()",
+                    call
+                );
+                let result = run_script(scope, context, &script).unwrap();
+                assert_eq!(
+                    String::from_value(result, scope, context),
+                    Ok(error.to_string())
+                );
+            }
+
+            let mut template = crate::ffi_template::<test_ffi_with>(scope);
+            let from_template = template.get_function(scope, context).unwrap();
+            global.set(
+                context,
+                make_str(scope, "test_ffi_from_template"),
+                from_template.into(),
+            );
+            let result = run_script(scope, context, "test_ffi_from_template('a', 1)").unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok("b".to_string())
+            );
+
+            let items = vec![1, 5, 9];
+            let threshold = 4;
+            let count: u32 =
+                js!(scope, context, #items.filter(x => x > #threshold).length).unwrap();
+            assert_eq!(count, 2);
+        });
     }
 }
@@ -0,0 +1,222 @@
+//! Observing the garbage collections of an isolate, i.e. to correlate
+//! latency with GC pauses.
+//!
+//! rusty_v8 does not bind `Isolate::AddGCPrologueCallback` and
+//! `AddGCEpilogueCallback`, so this module provides their callbacks,
+//! `gc_prologue` and `gc_epilogue`, with the `GCCallbackWithData`
+//! signature, for embedders whose bindings can register them. Each
+//! collection is then reported to the isolate's `GcObserver`, which
+//! `GcMetrics` implements to aggregate pause times.
+//!
+//! V8 has no public API for allocation observers, so allocations can only
+//! be followed through heap statistics.
+
+use crate::util::{isolate_slot, remove_isolate_slot, set_isolate_slot};
+use rusty_v8 as v8;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// The kind of a garbage collection, after V8's `GCType`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GcType {
+    /// A collection of the young generation by copying.
+    Scavenge,
+    /// A collection of the young generation by mark-compact.
+    MinorMarkCompact,
+    /// A full collection.
+    MarkSweepCompact,
+    /// A step of incremental marking, between full collections.
+    IncrementalMarking,
+    /// Processing of weak callbacks, i.e. collected `ObjectWrap`s.
+    ProcessWeakCallbacks,
+}
+
+impl GcType {
+    /// The type of V8's `GCType` flag `raw`, for callbacks that are passed
+    /// a single type.
+    pub fn from_raw(raw: u32) -> Option<GcType> {
+        match raw {
+            1 => Some(GcType::Scavenge),
+            2 => Some(GcType::MinorMarkCompact),
+            4 => Some(GcType::MarkSweepCompact),
+            8 => Some(GcType::IncrementalMarking),
+            16 => Some(GcType::ProcessWeakCallbacks),
+            _ => None,
+        }
+    }
+}
+
+/// `GcObserver` receives the garbage collections of an isolate. Its
+/// methods are called during the collection, so they must not use the
+/// isolate, and should be quick. All methods default to no-ops.
+pub trait GcObserver {
+    fn on_gc_start(&self, _kind: GcType) {}
+
+    /// The collection `kind` ended after pausing JS for `pause`.
+    fn on_gc_end(&self, _kind: GcType, _pause: Duration) {}
+}
+
+struct ObserverSlot {
+    observer: Rc<dyn GcObserver>,
+    /// When the collections in progress started, innermost last.
+    started: RefCell<Vec<(GcType, Instant)>>,
+}
+
+/// Set the observer notified of garbage collections in this isolate.
+pub fn set_gc_observer(scope: &mut impl v8::InIsolate, observer: impl GcObserver + 'static) {
+    set_isolate_slot(
+        scope,
+        ObserverSlot {
+            observer: Rc::new(observer),
+            started: RefCell::new(vec![]),
+        },
+    );
+}
+
+/// Remove the GC observer of this isolate, if any.
+pub fn clear_gc_observer(scope: &mut impl v8::InIsolate) {
+    remove_isolate_slot::<ObserverSlot>(scope);
+}
+
+/// The GC prologue callback reporting to the isolate's `GcObserver`, with
+/// the `GCCallbackWithData` signature of `Isolate::AddGCPrologueCallback`.
+///
+/// # Safety
+/// `isolate` must be the isolate being collected, on its thread.
+pub unsafe extern "C" fn gc_prologue(
+    isolate: *mut v8::Isolate,
+    kind: u32,
+    _flags: u32,
+    _data: *mut c_void,
+) {
+    let isolate = &mut *isolate;
+    let (slot, kind) = match (
+        isolate_slot::<ObserverSlot>(isolate),
+        GcType::from_raw(kind),
+    ) {
+        (Some(slot), Some(kind)) => (slot, kind),
+        _ => return,
+    };
+    slot.started.borrow_mut().push((kind, Instant::now()));
+    slot.observer.on_gc_start(kind);
+}
+
+/// The GC epilogue callback reporting to the isolate's `GcObserver`, with
+/// the `GCCallbackWithData` signature of `Isolate::AddGCEpilogueCallback`.
+///
+/// # Safety
+/// `isolate` must be the isolate being collected, on its thread.
+pub unsafe extern "C" fn gc_epilogue(
+    isolate: *mut v8::Isolate,
+    kind: u32,
+    _flags: u32,
+    _data: *mut c_void,
+) {
+    let isolate = &mut *isolate;
+    let (slot, kind) = match (
+        isolate_slot::<ObserverSlot>(isolate),
+        GcType::from_raw(kind),
+    ) {
+        (Some(slot), Some(kind)) => (slot, kind),
+        _ => return,
+    };
+    let started = {
+        let mut started = slot.started.borrow_mut();
+        let index = started.iter().rposition(|x| x.0 == kind);
+        index.map(|index| started.remove(index).1)
+    };
+    // the observer was set during the collection
+    if let Some(started) = started {
+        slot.observer.on_gc_end(kind, started.elapsed());
+    }
+}
+
+/// Aggregate pauses of one kind of garbage collection.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GcCounters {
+    pub collections: u64,
+    pub total_pause: Duration,
+    pub max_pause: Duration,
+    pub last_pause: Duration,
+}
+
+/// `GcObserver` aggregating pauses by kind of collection. Clones share
+/// the same counters, so keep one to read them.
+///
+/// ```ignore
+/// let metrics = GcMetrics::new();
+/// set_gc_observer(isolate, metrics.clone());
+/// // run scripts
+/// let full = metrics.counters().get(&GcType::MarkSweepCompact).cloned();
+/// ```
+#[derive(Clone, Default)]
+pub struct GcMetrics(Rc<RefCell<HashMap<GcType, GcCounters>>>);
+
+impl GcMetrics {
+    pub fn new() -> GcMetrics {
+        GcMetrics::default()
+    }
+
+    /// Snapshot the counters of every kind of collection seen so far.
+    pub fn counters(&self) -> HashMap<GcType, GcCounters> {
+        self.0.borrow().clone()
+    }
+
+    /// The time JS was paused for collections of any kind.
+    pub fn total_pause(&self) -> Duration {
+        self.0.borrow().values().map(|x| x.total_pause).sum()
+    }
+
+    pub fn reset(&self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+impl GcObserver for GcMetrics {
+    fn on_gc_end(&self, kind: GcType, pause: Duration) {
+        let mut counters = self.0.borrow_mut();
+        let counters = counters.entry(kind).or_default();
+        counters.collections += 1;
+        counters.total_pause += pause;
+        counters.max_pause = counters.max_pause.max(pause);
+        counters.last_pause = pause;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gc_metrics() {
+        use crate::gc::{self, GcMetrics, GcType};
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        let isolate = runtime.isolate();
+        let metrics = GcMetrics::new();
+        gc::set_gc_observer(isolate, metrics.clone());
+        let raw: *mut v8::Isolate = isolate;
+        unsafe {
+            gc::gc_prologue(raw, 4, 0, std::ptr::null_mut());
+            gc::gc_prologue(raw, 1, 0, std::ptr::null_mut());
+            gc::gc_epilogue(raw, 1, 0, std::ptr::null_mut());
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            gc::gc_epilogue(raw, 4, 0, std::ptr::null_mut());
+            // not started while observed
+            gc::gc_epilogue(raw, 8, 0, std::ptr::null_mut());
+        }
+        let counters = metrics.counters();
+        assert_eq!(counters.len(), 2);
+        assert_eq!(counters[&GcType::Scavenge].collections, 1);
+        let full = &counters[&GcType::MarkSweepCompact];
+        assert_eq!(full.collections, 1);
+        assert!(full.max_pause >= std::time::Duration::from_millis(2));
+        assert!(full.max_pause > counters[&GcType::Scavenge].max_pause);
+        gc::clear_gc_observer(isolate);
+        unsafe { gc::gc_prologue(raw, 1, 0, std::ptr::null_mut()) };
+        assert_eq!(metrics.counters().len(), 2);
+    }
+}
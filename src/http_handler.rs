@@ -0,0 +1,517 @@
+//! `HttpHandler`, serving HTTP requests with a script's handler, i.e. for
+//! a workers-style platform, and `HttpAdapter`, converting the requests
+//! and responses of a server framework.
+//!
+//! ```ignore
+//! run_script(scope, context, r#"
+//!     globalThis.app = {
+//!         async fetch(request) {
+//!             const { name } = await request.json();
+//!             return { status: 200, headers: { 'x-served-by': 'js' }, body: `hello ${name}` };
+//!         },
+//!     };
+//! "#);
+//! let handler = HttpHandler::from_global(scope, context, "app")?;
+//! // then, for every request, outside of any scope
+//! let response = handler.handle_with::<Hyper>(isolate, request).await?;
+//! ```
+
+use crate::event_loop;
+use crate::util::*;
+use crate::{Bytes, FFICompat, FFIError, JsError, JsRef};
+use rusty_v8 as v8;
+use rusty_v8_helper_derive::v8_ffi;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use v8::Global;
+
+/// A request to be handled by script, as a `Request` object.
+#[derive(Clone, Debug, Default)]
+pub struct HandlerRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// The response a script's handler returned.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HandlerResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// `HttpAdapter` converts the requests and responses of a server framework,
+/// for `HttpHandler::handle_with`. With the `hyper` feature, `Hyper` is an
+/// adapter for hyper.
+pub trait HttpAdapter {
+    type Request;
+    type Response;
+
+    fn into_request(request: Self::Request) -> HandlerRequest;
+
+    fn from_response(response: HandlerResponse) -> Self::Response;
+}
+
+/// The responses of handlers that settled but were not yet taken, by
+/// request id.
+#[derive(Default)]
+struct Responses {
+    next_id: Cell<u32>,
+    settled: RefCell<HashMap<u32, Result<HandlerResponse, JsError>>>,
+}
+
+fn responses(scope: &mut impl v8::InIsolate) -> Rc<Responses> {
+    if let Some(responses) = isolate_slot::<Responses>(scope) {
+        return responses;
+    }
+    set_isolate_slot(scope, Responses::default());
+    isolate_slot::<Responses>(scope).unwrap()
+}
+
+fn settle(scope: &mut impl v8::InIsolate, id: u32, response: Result<HandlerResponse, JsError>) {
+    responses(scope).settled.borrow_mut().insert(id, response);
+}
+
+fn take_response(
+    scope: &mut impl v8::InIsolate,
+    id: u32,
+) -> Option<Result<HandlerResponse, JsError>> {
+    responses(scope).settled.borrow_mut().remove(&id)
+}
+
+#[v8_ffi(scoped)]
+fn http_respond<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    _context: v8::Local<'c, v8::Context>,
+    id: u32,
+    status: u32,
+    headers: Vec<(String, String)>,
+    body_text: Option<String>,
+    body_bytes: Option<Bytes>,
+) {
+    let body = body_text
+        .map(|x| x.into_bytes())
+        .or_else(|| body_bytes.map(|x| x.0))
+        .unwrap_or_default();
+    let response = HandlerResponse {
+        status: status as u16,
+        headers,
+        body,
+    };
+    settle(scope, id, Ok(response));
+}
+
+#[v8_ffi(scoped)]
+fn http_fail<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    _context: v8::Local<'c, v8::Context>,
+    id: u32,
+    message: String,
+    stack: Option<String>,
+) {
+    let mut error = JsError::new(message);
+    error.stack = stack;
+    settle(scope, id, Err(error));
+}
+
+#[v8_ffi]
+fn http_body_text(body: Bytes) -> String {
+    String::from_utf8_lossy(&body.0).into_owned()
+}
+
+const HTTP_BOOTSTRAP: &str = r#"
+(function (respond, fail, bodyText) {
+    const makeHeaders = (entries) => typeof Headers === 'function'
+        ? new Headers(entries)
+        : new Map(entries);
+    class Request {
+        constructor(method, url, headers, body) {
+            this.method = method;
+            this.url = url;
+            this.headers = makeHeaders(headers);
+            this.bodyUsed = false;
+            this._body = body;
+        }
+        _consume() {
+            if (this.bodyUsed) {
+                return Promise.reject(new TypeError('body has already been consumed'));
+            }
+            this.bodyUsed = true;
+            return Promise.resolve(this._body);
+        }
+        arrayBuffer() {
+            return this._consume().then(bytes => bytes.buffer);
+        }
+        text() {
+            return this._consume().then(bodyText);
+        }
+        json() {
+            return this.text().then(JSON.parse);
+        }
+    }
+    const headerEntries = (headers) => {
+        const entries = [];
+        if (headers == null) {
+            return entries;
+        } else if (Array.isArray(headers)) {
+            for (const [name, value] of headers) entries.push([String(name), String(value)]);
+        } else if (typeof headers.forEach === 'function') {
+            headers.forEach((value, name) => entries.push([String(name), String(value)]));
+        } else {
+            for (const name of Object.keys(headers)) entries.push([name, String(headers[name])]);
+        }
+        return entries;
+    };
+    const toResponse = async (response) => {
+        if (typeof response === 'string') {
+            return [200, [['content-type', 'text/plain;charset=UTF-8']], response, null];
+        }
+        if (response === null || typeof response !== 'object') {
+            throw new TypeError('handler must return a response object or a string');
+        }
+        const status = response.status === undefined ? 200 : Number(response.status);
+        if (!Number.isInteger(status) || status < 200 || status > 599) {
+            throw new RangeError(`invalid response status ${response.status}`);
+        }
+        const headers = headerEntries(response.headers);
+        let body = response.body;
+        if (body === undefined && typeof response.arrayBuffer === 'function') {
+            body = await response.arrayBuffer();
+        }
+        if (body == null) {
+            return [status, headers, null, null];
+        } else if (typeof body === 'string') {
+            return [status, headers, body, null];
+        } else if (body instanceof ArrayBuffer || ArrayBuffer.isView(body)) {
+            return [status, headers, null, body];
+        }
+        if (!headers.some(([name]) => name.toLowerCase() === 'content-type')) {
+            headers.push(['content-type', 'application/json']);
+        }
+        return [status, headers, JSON.stringify(body), null];
+    };
+    return (handler, id, method, url, headers, body) => {
+        const request = new Request(method, url, headers, body);
+        new Promise(resolve => resolve(typeof handler === 'function'
+            ? handler(request)
+            : handler.fetch(request)))
+            .then(toResponse)
+            .then(
+                ([status, headers, text, bytes]) => respond(id, status, headers, text, bytes),
+                (e) => e instanceof Error
+                    ? fail(id, `${e.name}: ${e.message}`, e.stack || null)
+                    : fail(id, `Uncaught ${String(e)}`, null));
+    };
+})
+"#;
+
+fn request_value<'sc, 'c, T: FFICompat<'sc, 'c>>(
+    value: T,
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+) -> Result<v8::Local<'sc, v8::Value>, JsError> {
+    value
+        .to_value(scope, context)
+        .map_err(|e| JsError::new(format!("failed to convert the request: {:?}", e)))
+}
+
+/// `HttpHandler` serves requests with a script's handler: a function, or an
+/// object with a `fetch` method, i.e. a module's default export, taking a
+/// `Request` and returning a response or a promise of one.
+///
+/// The `Request` has `method`, `url`, `headers`, a `Headers` if the fetch
+/// extension is installed and otherwise a `Map` of the headers, and the
+/// body through `text()`, `json()` and `arrayBuffer()`.
+///
+/// The response is a string, served as text, or an object with `status`,
+/// 200 by default, `headers`, as a `Headers`, `Map`, array of entries or
+/// object, and `body`, a string, `ArrayBuffer`, typed array, or anything
+/// else serialized as JSON. A `Response` of the fetch extension is read
+/// with `arrayBuffer()`, so fetched responses can be passed through.
+///
+/// A handler is bound to the context it was created in and the isolate's
+/// thread. Rather than dropping it, `release` it while the isolate is
+/// still alive.
+pub struct HttpHandler {
+    context: Global<v8::Context>,
+    handler: JsRef<v8::Value>,
+    dispatch: JsRef<v8::Function>,
+}
+
+impl HttpHandler {
+    pub fn new<'sc>(
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+        handler: v8::Local<'sc, v8::Value>,
+    ) -> Result<HttpHandler, FFIError> {
+        if !handler.is_function() {
+            let object: Option<v8::Local<v8::Object>> = handler.try_into().ok();
+            let key = make_str(scope, "fetch");
+            let fetch = object.and_then(|x| x.get(scope, context, key));
+            if !fetch.map(|x| x.is_function()).unwrap_or(false) {
+                return Err(FFIError::TypeError(
+                    "handler must be a function or an object with a fetch method".to_string(),
+                ));
+            }
+        }
+        let respond = load_v8_ffi!(http_respond, scope, context);
+        let fail = load_v8_ffi!(http_fail, scope, context);
+        let body_text = load_v8_ffi!(http_body_text, scope, context);
+        let bootstrap = eval_function(scope, context, HTTP_BOOTSTRAP)?;
+        let global = context.global(scope).into();
+        let dispatch: v8::Local<v8::Function> = call_function(
+            scope,
+            context,
+            bootstrap,
+            global,
+            &[respond, fail, body_text],
+        )?
+        .try_into()
+        .map_err(|_| FFIError::Error("failed to bootstrap http handler".to_string()))?;
+        Ok(HttpHandler {
+            context: Global::new_from(scope, context),
+            handler: JsRef::new(scope, handler),
+            dispatch: JsRef::new(scope, dispatch),
+        })
+    }
+
+    /// The handler at the global `name` of `context`.
+    pub fn from_global<'sc>(
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+        name: &str,
+    ) -> Result<HttpHandler, FFIError> {
+        let key = make_str(scope, name);
+        let handler = context
+            .global(scope)
+            .get(scope, context, key)
+            .filter(|x| !x.is_undefined())
+            .ok_or_else(|| FFIError::Error(format!("{} is not defined", name)))?;
+        HttpHandler::new(scope, context, handler)
+    }
+
+    /// Serve `request`, driving the isolate's event loop until the handler's
+    /// response settles. Fails with what the handler threw or rejected
+    /// with, or if the event loop went idle without it settling.
+    ///
+    /// Must not be called from within a scope of the isolate.
+    pub async fn handle(
+        &self,
+        isolate: &mut v8::Isolate,
+        request: HandlerRequest,
+    ) -> Result<HandlerResponse, JsError> {
+        let id = self.dispatch(isolate, request)?;
+        Settled { isolate, id }.await
+    }
+
+    /// Serve a request of the framework of `A`, see `handle`.
+    pub async fn handle_with<A: HttpAdapter>(
+        &self,
+        isolate: &mut v8::Isolate,
+        request: A::Request,
+    ) -> Result<A::Response, JsError> {
+        let response = self.handle(isolate, A::into_request(request)).await?;
+        Ok(A::from_response(response))
+    }
+
+    /// Release the handles to the handler and its context.
+    pub fn release(mut self, scope: &mut impl v8::InIsolate) {
+        self.handler.release(scope);
+        self.dispatch.release(scope);
+        self.context.reset(scope);
+    }
+
+    /// Call the handler with `request`, returning the id its response is
+    /// settled with.
+    fn dispatch(&self, isolate: &mut v8::Isolate, request: HandlerRequest) -> Result<u32, JsError> {
+        let mut hs = v8::HandleScope::new(isolate);
+        let scope = hs.enter();
+        let context = self.context.get(scope).unwrap();
+        let mut cs = v8::ContextScope::new(scope, context);
+        let scope = cs.enter();
+        let responses = responses(scope);
+        let id = responses.next_id.get();
+        responses.next_id.set(id.wrapping_add(1));
+        let args = [
+            self.handler.get(scope),
+            request_value(id, scope, context)?,
+            request_value(request.method, scope, context)?,
+            request_value(request.url, scope, context)?,
+            request_value(request.headers, scope, context)?,
+            request_value(Bytes(request.body), scope, context)?,
+        ];
+        let global = context.global(scope).into();
+        self.dispatch
+            .call(scope, context, global, &args)
+            .map_err(|e| JsError::new(e.to_string()))?;
+        scope.isolate().run_microtasks();
+        Ok(id)
+    }
+}
+
+/// The future of the response settled with `id`.
+struct Settled<'a> {
+    isolate: &'a mut v8::Isolate,
+    id: u32,
+}
+
+impl<'a> Future for Settled<'a> {
+    type Output = Result<HandlerResponse, JsError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if let Some(response) = take_response(this.isolate, this.id) {
+            return Poll::Ready(response);
+        }
+        let idle = event_loop::poll_until_idle(this.isolate, cx).is_ready();
+        match take_response(this.isolate, this.id) {
+            Some(response) => Poll::Ready(response),
+            None if idle => Poll::Ready(Err(JsError::new("the handler's response never settled"))),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// `HttpAdapter` for hyper, serving requests whose body was read with
+/// `read_hyper_request`. Relative request urls are made absolute with the
+/// `host` header, as scripts expect.
+#[cfg(feature = "hyper")]
+pub struct Hyper;
+
+#[cfg(feature = "hyper")]
+impl HttpAdapter for Hyper {
+    type Request = hyper::Request<hyper::body::Bytes>;
+    type Response = hyper::Response<hyper::Body>;
+
+    fn into_request(request: Self::Request) -> HandlerRequest {
+        let (parts, body) = request.into_parts();
+        let host = parts
+            .headers
+            .get(hyper::header::HOST)
+            .and_then(|x| x.to_str().ok());
+        let url = match (parts.uri.scheme(), host) {
+            (None, Some(host)) => format!("http://{}{}", host, parts.uri),
+            _ => parts.uri.to_string(),
+        };
+        HandlerRequest {
+            method: parts.method.to_string(),
+            url,
+            headers: parts
+                .headers
+                .iter()
+                .map(|(name, value)| {
+                    let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                    (name.as_str().to_string(), value)
+                })
+                .collect(),
+            body: body.to_vec(),
+        }
+    }
+
+    fn from_response(response: HandlerResponse) -> Self::Response {
+        let mut builder = hyper::Response::builder().status(response.status);
+        for (name, value) in &response.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        builder.body(response.body.into()).unwrap_or_else(|_| {
+            let mut error = hyper::Response::new("invalid response header".into());
+            *error.status_mut() = hyper::StatusCode::INTERNAL_SERVER_ERROR;
+            error
+        })
+    }
+}
+
+/// Read the body of `request`, for `HttpHandler::handle_with::<Hyper>`.
+#[cfg(feature = "hyper")]
+pub async fn read_hyper_request(
+    request: hyper::Request<hyper::Body>,
+) -> hyper::Result<hyper::Request<hyper::body::Bytes>> {
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+    Ok(hyper::Request::from_parts(parts, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_handler() {
+        use crate::Extension;
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        let executor = crate::LocalExecutor::new();
+        crate::set_executor(runtime.isolate(), executor.clone());
+        let (_, mut context) = runtime.create_context();
+        let handler = {
+            let mut hs = v8::HandleScope::new(runtime.isolate());
+            let scope = hs.enter();
+            let context = context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            crate::extensions::timers::TimersExtension
+                .install(scope, context)
+                .unwrap();
+            run_script(
+                scope,
+                context,
+                r#"
+                globalThis.app = {
+                    async fetch(request) {
+                        if (request.method === 'DELETE') throw new RangeError('not allowed');
+                        if (request.method === 'GET') return `got ${request.url}`;
+                        const { name } = await request.json();
+                        await new Promise((resolve) => setTimeout(resolve, 1));
+                        return {
+                            status: 201,
+                            headers: { 'x-agent': request.headers.get('user-agent') },
+                            body: { hello: name },
+                        };
+                    },
+                };
+                "#,
+            );
+            assert!(HttpHandler::from_global(scope, context, "missing").is_err());
+            HttpHandler::from_global(scope, context, "app").unwrap()
+        };
+        let request = |method: &str, body: &str| HandlerRequest {
+            method: method.to_string(),
+            url: "http://localhost/hello".to_string(),
+            headers: vec![("user-agent".to_string(), "test".to_string())],
+            body: body.as_bytes().to_vec(),
+        };
+        let isolate = runtime.isolate();
+        let created = executor
+            .block_on(handler.handle(isolate, request("POST", r#"{"name":"ada"}"#)))
+            .unwrap();
+        assert_eq!(created.status, 201);
+        assert_eq!(
+            created.headers,
+            vec![
+                ("x-agent".to_string(), "test".to_string()),
+                ("content-type".to_string(), "application/json".to_string()),
+            ]
+        );
+        assert_eq!(created.body, br#"{"hello":"ada"}"#.to_vec());
+        let got = executor
+            .block_on(handler.handle(isolate, request("GET", "")))
+            .unwrap();
+        assert_eq!(
+            (got.status, got.body),
+            (200, b"got http://localhost/hello".to_vec())
+        );
+        let error = executor
+            .block_on(handler.handle(isolate, request("DELETE", "")))
+            .unwrap_err();
+        assert_eq!(error.message, "RangeError: not allowed");
+        handler.release(isolate);
+        context.reset(isolate);
+    }
+}
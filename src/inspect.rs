@@ -0,0 +1,281 @@
+//! `inspect`, a readable rendering of any JS value in the style of Node's
+//! `util.inspect`, for consoles, the REPL and error messages.
+
+use crate::object_wrap::wrapped_type_name;
+use crate::properties::{catching, property_names};
+use crate::util::{call_function, exception_message, make_str, type_of};
+use crate::PropertyMode;
+use rusty_v8 as v8;
+use std::convert::TryInto;
+
+/// How much of a value `inspect_with` renders.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InspectOptions {
+    /// How deep to render nested objects and arrays; deeper ones are shown
+    /// as `[Object]` or `[Array]`.
+    pub depth: usize,
+    /// How many elements of an array to render before `... n more items`.
+    pub max_array_length: usize,
+    /// The longest an object or array is rendered on a single line.
+    pub break_length: usize,
+}
+
+impl Default for InspectOptions {
+    fn default() -> InspectOptions {
+        InspectOptions {
+            depth: 2,
+            max_array_length: 100,
+            break_length: 72,
+        }
+    }
+}
+
+/// Render `value` like Node's `util.inspect` with the default options.
+///
+/// Getters are run, and render as `[Thrown: ...]` if they throw.
+pub fn inspect<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    value: v8::Local<'sc, v8::Value>,
+) -> String {
+    inspect_with(scope, context, value, InspectOptions::default())
+}
+
+/// Render `value` like Node's `util.inspect`, i.e. `{ a: [ 1, 'x' ],
+/// self: [Circular] }`. Objects created by `ObjectWrap` are prefixed with
+/// the Rust type they wrap, i.e. `[Native app::Counter] {}`.
+pub fn inspect_with<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    value: v8::Local<'sc, v8::Value>,
+    options: InspectOptions,
+) -> String {
+    let mut inspector = Inspector {
+        options,
+        ancestors: vec![],
+    };
+    inspector.inspect(scope, context, value)
+}
+
+struct Inspector<'sc> {
+    options: InspectOptions,
+    ancestors: Vec<v8::Local<'sc, v8::Value>>,
+}
+
+impl<'sc> Inspector<'sc> {
+    fn inspect(
+        &mut self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+        value: v8::Local<'sc, v8::Value>,
+    ) -> String {
+        match type_of(value) {
+            "undefined" | "null" | "boolean" => return exception_message(scope, value),
+            "number" => {
+                let number = value.number_value(scope).unwrap_or(f64::NAN);
+                if number == 0.0 && number.is_sign_negative() {
+                    return "-0".to_string();
+                }
+                return exception_message(scope, value);
+            }
+            "bigint" => return format!("{}n", exception_message(scope, value)),
+            "string" => return quote(&value.to_rust_string_lossy(scope)),
+            "symbol" => return display(scope, context, value),
+            "function" => return function_name(scope, context, value),
+            _ => (),
+        }
+        if value.is_native_error() {
+            let object: v8::Local<v8::Object> = value.try_into().unwrap();
+            let key = make_str(scope, "stack");
+            let stack = catching(scope, |scope| object.get(scope, context, key))
+                .ok()
+                .flatten();
+            return match stack {
+                Some(stack) if stack.is_string() => stack.to_rust_string_lossy(scope),
+                _ => format!("[{}]", display(scope, context, value)),
+            };
+        }
+        if value.is_date() || value.is_reg_exp() {
+            return display(scope, context, value);
+        }
+        if self.ancestors.iter().any(|x| x.strict_equals(value)) {
+            return "[Circular]".to_string();
+        }
+        let object: v8::Local<v8::Object> = value.try_into().unwrap();
+        let native = wrapped_type_name(object).map(|name| format!("[Native {}]", name));
+        if self.ancestors.len() > self.options.depth {
+            return match native {
+                Some(native) => native,
+                None if value.is_array() => "[Array]".to_string(),
+                None => "[Object]".to_string(),
+            };
+        }
+        self.ancestors.push(value);
+        let (open, close, entries) = if value.is_array() {
+            let array: v8::Local<v8::Array> = value.try_into().unwrap();
+            ("[", "]", self.elements(scope, context, array))
+        } else {
+            ("{", "}", self.properties(scope, context, object))
+        };
+        self.ancestors.pop();
+        let prefix = native.map(|x| format!("{} ", x)).unwrap_or_default();
+        if entries.is_empty() {
+            return format!("{}{}{}", prefix, open, close);
+        }
+        let single_line = format!("{}{} {} {}", prefix, open, entries.join(", "), close);
+        let indent = self.ancestors.len() * 2;
+        if single_line.len() + indent <= self.options.break_length && !single_line.contains('\n') {
+            return single_line;
+        }
+        let entries: Vec<String> = entries
+            .iter()
+            .map(|x| format!("  {}", x.replace('\n', "\n  ")))
+            .collect();
+        format!("{}{}\n{}\n{}", prefix, open, entries.join(",\n"), close)
+    }
+
+    fn elements(
+        &mut self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+        array: v8::Local<'sc, v8::Array>,
+    ) -> Vec<String> {
+        let length = array.length() as usize;
+        let shown = length.min(self.options.max_array_length);
+        let mut entries = Vec::with_capacity(shown + 1);
+        for i in 0..shown {
+            let element = catching(scope, |scope| array.get_index(scope, context, i as u32));
+            entries.push(match element {
+                Ok(element) => {
+                    let element = element.unwrap_or_else(|| v8::undefined(scope).into());
+                    self.inspect(scope, context, element)
+                }
+                Err(e) => format!("[Thrown: {}]", e),
+            });
+        }
+        if length > shown {
+            let more = length - shown;
+            entries.push(format!(
+                "... {} more item{}",
+                more,
+                if more == 1 { "" } else { "s" }
+            ));
+        }
+        entries
+    }
+
+    fn properties(
+        &mut self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+        object: v8::Local<'sc, v8::Object>,
+    ) -> Vec<String> {
+        let mode = PropertyMode::OwnEnumerable;
+        let names = match catching(scope, |scope| property_names(object, scope, context, mode)) {
+            Ok(Ok(names)) => names,
+            Ok(Err(e)) | Err(e) => return vec![format!("[Thrown: {}]", e)],
+        };
+        let mut entries = Vec::with_capacity(names.len());
+        for name in names {
+            let key = make_str(scope, &name);
+            let property = catching(scope, |scope| object.get(scope, context, key));
+            let property = match property {
+                Ok(property) => {
+                    let property = property.unwrap_or_else(|| v8::undefined(scope).into());
+                    self.inspect(scope, context, property)
+                }
+                Err(e) => format!("[Thrown: {}]", e),
+            };
+            entries.push(format!("{}: {}", key_name(&name), property));
+        }
+        entries
+    }
+}
+
+/// `String(value)`, which unlike `ToString` also works for symbols.
+fn display<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    value: v8::Local<v8::Value>,
+) -> String {
+    let key = make_str(scope, "String");
+    let string = context
+        .global(scope)
+        .get(scope, context, key)
+        .and_then(|x| x.try_into().ok());
+    let recv = v8::undefined(scope).into();
+    match string.map(|x| call_function(scope, context, x, recv, &[value])) {
+        Some(Ok(string)) => string.to_rust_string_lossy(scope),
+        _ => format!("[{}]", type_of(value)),
+    }
+}
+
+fn function_name<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    value: v8::Local<v8::Value>,
+) -> String {
+    let function: v8::Local<v8::Object> = value.try_into().unwrap();
+    let key = make_str(scope, "name");
+    let name = catching(scope, |scope| function.get(scope, context, key))
+        .ok()
+        .flatten()
+        .filter(|x| x.is_string())
+        .map(|x| x.to_rust_string_lossy(scope))
+        .unwrap_or_default();
+    match name.as_str() {
+        "" => "[Function (anonymous)]".to_string(),
+        name => format!("[Function: {}]", name),
+    }
+}
+
+/// `string` as a single quoted JS string literal.
+fn quote(string: &str) -> String {
+    let mut quoted = String::with_capacity(string.len() + 2);
+    quoted.push('\'');
+    for c in string.chars() {
+        match c {
+            '\'' => quoted.push_str("\\'"),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// `name` as an object key: bare if it is an identifier, quoted otherwise.
+fn key_name(name: &str) -> String {
+    let mut chars = name.chars();
+    let identifier = match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' || c == '$' => {
+            chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+        }
+        _ => false,
+    };
+    if identifier {
+        name.to_string()
+    } else {
+        quote(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("it's"), "'it\\'s'");
+        assert_eq!(quote("a\nb\u{1}"), "'a\\nb\\u0001'");
+        assert_eq!(key_name("camelCase"), "camelCase");
+        assert_eq!(key_name("$_1"), "$_1");
+        assert_eq!(key_name("with space"), "'with space'");
+        assert_eq!(key_name("1"), "'1'");
+        assert_eq!(key_name(""), "''");
+    }
+}
@@ -0,0 +1,205 @@
+//! Instrumentation of `v8_ffi` calls. The generated trampolines open an
+//! `FfiCall` for every call, which reports to the isolate's `FfiObserver`
+//! if one is set, and to `tracing` if the `tracing` feature is enabled.
+
+use crate::util::{describe_error, isolate_slot, remove_isolate_slot, set_isolate_slot};
+use rusty_v8 as v8;
+use std::any::Any;
+use std::cell::Cell;
+use std::fmt::Debug;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "tracing")]
+pub use counters::{counters, reset_counters, FfiCounters};
+
+/// `FfiObserver` receives events for every `v8_ffi` call made in an
+/// isolate, i.e. to feed aggregate metrics. All methods default to no-ops.
+pub trait FfiObserver {
+    fn on_call_start(&self, _function: &'static str) {}
+
+    fn on_call_end(&self, _function: &'static str, _elapsed: Duration) {}
+
+    /// An argument or `this` failed to convert from JS.
+    fn on_conversion_error(&self, _function: &'static str, _error: &str) {}
+
+    /// The call threw `error` back into JS.
+    fn on_exception(&self, _function: &'static str, _error: &str) {}
+}
+
+struct ObserverSlot(Rc<dyn FfiObserver>);
+
+thread_local! {
+    /// How many isolates of this thread have an observer, so that calls
+    /// skip looking one up while none has, as is usual.
+    static OBSERVED_ISOLATES: Cell<usize> = Cell::new(0);
+}
+
+/// Set the observer notified of `v8_ffi` calls in this isolate.
+pub fn set_ffi_observer(scope: &mut impl v8::InIsolate, observer: impl FfiObserver + 'static) {
+    if isolate_slot::<ObserverSlot>(scope).is_none() {
+        OBSERVED_ISOLATES.with(|count| count.set(count.get() + 1));
+    }
+    set_isolate_slot(scope, ObserverSlot(Rc::new(observer)));
+}
+
+/// Remove the observer of this isolate, if any.
+pub fn clear_ffi_observer(scope: &mut impl v8::InIsolate) {
+    if remove_isolate_slot::<ObserverSlot>(scope).is_some() {
+        OBSERVED_ISOLATES.with(|count| count.set(count.get() - 1));
+    }
+}
+
+/// The observer of this isolate, if any.
+pub(crate) fn observer(scope: &mut impl v8::InIsolate) -> Option<Rc<dyn FfiObserver>> {
+    if OBSERVED_ISOLATES.with(Cell::get) == 0 {
+        return None;
+    }
+    isolate_slot::<ObserverSlot>(scope).map(|slot| slot.0.clone())
+}
+
+/// A single in-flight FFI call, closed when dropped.
+pub struct FfiCall {
+    name: &'static str,
+    observer: Option<(Rc<dyn FfiObserver>, Instant)>,
+    #[cfg(feature = "tracing")]
+    inner: counters::TracedCall,
+}
+
+impl FfiCall {
+    #[doc(hidden)]
+    pub fn start(scope: &mut impl v8::InIsolate, name: &'static str) -> FfiCall {
+        let observer = observer(scope).map(|observer| {
+            observer.on_call_start(name);
+            (observer, Instant::now())
+        });
+        FfiCall {
+            name,
+            observer,
+            #[cfg(feature = "tracing")]
+            inner: counters::TracedCall::start(scope, name),
+        }
+    }
+
+    /// An argument or `this` failed to convert from JS.
+    #[doc(hidden)]
+    pub fn conversion_error<E: Debug + Any>(&self, error: &E) {
+        if let Some((observer, _)) = &self.observer {
+            observer.on_conversion_error(self.name, &describe_error(error));
+        }
+        #[cfg(feature = "tracing")]
+        self.inner.conversion_error(error);
+    }
+
+    /// The call is about to throw `error` back into JS.
+    #[doc(hidden)]
+    pub fn exception<E: Debug + Any>(&self, error: &E) {
+        if let Some((observer, _)) = &self.observer {
+            observer.on_exception(self.name, &describe_error(error));
+        }
+        #[cfg(feature = "tracing")]
+        self.inner.exception(error);
+    }
+}
+
+impl Drop for FfiCall {
+    fn drop(&mut self) {
+        if let Some((observer, started)) = &self.observer {
+            observer.on_call_end(self.name, started.elapsed());
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+mod counters {
+    use crate::util::{isolate_slot, set_isolate_slot};
+    use rusty_v8 as v8;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::fmt::Debug;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    /// Aggregate counters for one FFI function in an isolate.
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct FfiCounters {
+        pub calls: u64,
+        pub conversion_errors: u64,
+        pub exceptions: u64,
+        pub total_time: Duration,
+        pub max_time: Duration,
+    }
+
+    type CounterRegistry = RefCell<HashMap<&'static str, FfiCounters>>;
+
+    fn registry(scope: &mut impl v8::InIsolate) -> Rc<CounterRegistry> {
+        if let Some(registry) = isolate_slot::<CounterRegistry>(scope) {
+            return registry;
+        }
+        set_isolate_slot::<CounterRegistry>(scope, RefCell::new(HashMap::new()));
+        isolate_slot::<CounterRegistry>(scope).unwrap()
+    }
+
+    /// Snapshot the counters of every FFI function called in this isolate.
+    pub fn counters(scope: &mut impl v8::InIsolate) -> HashMap<&'static str, FfiCounters> {
+        registry(scope).borrow().clone()
+    }
+
+    /// Clear the counters of this isolate.
+    pub fn reset_counters(scope: &mut impl v8::InIsolate) {
+        registry(scope).borrow_mut().clear();
+    }
+
+    pub(super) struct TracedCall {
+        name: &'static str,
+        started: Instant,
+        registry: Rc<CounterRegistry>,
+        _span: tracing::span::EnteredSpan,
+    }
+
+    impl TracedCall {
+        pub(super) fn start(scope: &mut impl v8::InIsolate, name: &'static str) -> TracedCall {
+            let registry = registry(scope);
+            registry.borrow_mut().entry(name).or_default().calls += 1;
+            TracedCall {
+                name,
+                started: Instant::now(),
+                registry,
+                _span: tracing::trace_span!("v8_ffi", function = name).entered(),
+            }
+        }
+
+        pub(super) fn conversion_error(&self, error: &impl Debug) {
+            tracing::warn!(function = self.name, error = ?error, "ffi argument conversion failed");
+            self.registry
+                .borrow_mut()
+                .entry(self.name)
+                .or_default()
+                .conversion_errors += 1;
+        }
+
+        pub(super) fn exception(&self, error: &impl Debug) {
+            tracing::debug!(function = self.name, error = ?error, "ffi call threw");
+            self.registry
+                .borrow_mut()
+                .entry(self.name)
+                .or_default()
+                .exceptions += 1;
+        }
+    }
+
+    impl Drop for TracedCall {
+        fn drop(&mut self) {
+            let elapsed = self.started.elapsed();
+            tracing::trace!(
+                function = self.name,
+                elapsed_us = elapsed.as_micros() as u64,
+                "ffi call finished"
+            );
+            let mut registry = self.registry.borrow_mut();
+            let counters = registry.entry(self.name).or_default();
+            counters.total_time += elapsed;
+            counters.max_time = counters.max_time.max(elapsed);
+        }
+    }
+}
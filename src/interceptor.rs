@@ -0,0 +1,647 @@
+//! `Interceptor`, properties of a JS object answered by Rust, for objects
+//! whose keys are not known when they are set up, and `JsArrayLike`, Rust
+//! collections indexed like JS arrays.
+//!
+//! Neither `rusty_v8_protryon` 3.10 nor upstream `rusty_v8` 0.3 bind the
+//! named and indexed property handlers of `ObjectTemplate`, so these
+//! objects are a JS `Proxy` of an `ObjectWrap` rather than instances of a
+//! template with interceptors: they cannot be set up on a template, e.g.
+//! as the instance template of a class, every property access calls into
+//! Rust through a trap function, and script sees a `Proxy`, i.e. with
+//! `util.types.isProxy`. Conversions tell them apart from other proxies,
+//! so a `ProxyPolicy` does not apply to them.
+
+use crate::util::{
+    call_function, context_function, make_bool, make_num, make_object_wrap, make_str,
+    throw_ffi_error,
+};
+use crate::{FFICompat, FFIError, JsValue, ObjectWrap};
+use rusty_v8 as v8;
+use std::any::Any;
+use std::convert::TryInto;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+/// Evaluates to the function creating the `Proxy` of an interceptor, with
+/// an `isIntercepted` function telling whether a value is one it created.
+/// The builtins it relies on are captured when it is evaluated, once per
+/// context, see `context_function`.
+const PROXY_SOURCE: &str = r#"(function () {
+    const { Proxy, Set, WeakSet } = globalThis;
+    const {
+        get: reflectGet,
+        set: reflectSet,
+        has: reflectHas,
+        deleteProperty: reflectDelete,
+        ownKeys: reflectOwnKeys,
+        getOwnPropertyDescriptor: reflectDescriptor,
+    } = Reflect;
+    const call = Function.prototype.call;
+    const setAdd = call.bind(Set.prototype.add);
+    const setHas = call.bind(Set.prototype.has);
+    const weakSetAdd = call.bind(WeakSet.prototype.add);
+    const weakSetHas = call.bind(WeakSet.prototype.has);
+    const proxies = new WeakSet();
+    const create = function (target, get, set, has, deleteProperty, ownKeys) {
+        const unhandled = {};
+        const proxy = new Proxy(target, {
+            __proto__: null,
+            get(target, key, receiver) {
+                const value = get(target, key, unhandled);
+                return value === unhandled ? reflectGet(target, key, receiver) : value;
+            },
+            set(target, key, value, receiver) {
+                const done = set(target, key, value, unhandled);
+                return done === unhandled ? reflectSet(target, key, value, receiver) : done;
+            },
+            has(target, key) {
+                const found = has(target, key, unhandled);
+                return found === unhandled ? reflectHas(target, key) : found;
+            },
+            deleteProperty(target, key) {
+                const done = deleteProperty(target, key, unhandled);
+                return done === unhandled ? reflectDelete(target, key) : done;
+            },
+            ownKeys(target) {
+                const lists = [ownKeys(target), reflectOwnKeys(target)];
+                const seen = new Set();
+                const keys = [];
+                for (let i = 0; i < lists.length; i++) {
+                    const list = lists[i];
+                    for (let j = 0; j < list.length; j++) {
+                        if (!setHas(seen, list[j])) {
+                            setAdd(seen, list[j]);
+                            keys[keys.length] = list[j];
+                        }
+                    }
+                }
+                return keys;
+            },
+            getOwnPropertyDescriptor(target, key) {
+                if (has(target, key, unhandled) === unhandled) {
+                    return reflectDescriptor(target, key);
+                }
+                const value = get(target, key, unhandled);
+                return { __proto__: null, value, writable: true, enumerable: true, configurable: true };
+            },
+        });
+        weakSetAdd(proxies, proxy);
+        return proxy;
+    };
+    create.isIntercepted = (value) => weakSetHas(proxies, value);
+    return create;
+})()"#;
+
+/// A property key an `Interceptor` answers for. Keys that do not convert
+/// are left to the object itself, as are symbols.
+pub trait InterceptorKey: Sized {
+    fn from_key(key: &str) -> Option<Self>;
+
+    fn to_key(&self) -> String;
+}
+
+/// Any named property.
+impl InterceptorKey for String {
+    fn from_key(key: &str) -> Option<String> {
+        Some(key.to_string())
+    }
+
+    fn to_key(&self) -> String {
+        self.clone()
+    }
+}
+
+/// Array indices, `"0"` up to `"4294967294"`.
+impl InterceptorKey for u32 {
+    fn from_key(key: &str) -> Option<u32> {
+        let index = key.parse::<u32>().ok()?;
+        if index == u32::MAX || index.to_string() != key {
+            return None;
+        }
+        Some(index)
+    }
+
+    fn to_key(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// `Interceptor` answers the property reads, writes, `in` checks, deletes
+/// and enumeration of an object created by `intercepted`. Values are
+/// converted with `FFICompat`, as the arguments and return values of
+/// `v8_ffi` fns are. Methods take `&self`, so keep mutable state in a
+/// `RefCell`.
+///
+/// ```ignore
+/// struct Config(RefCell<HashMap<String, String>>);
+///
+/// impl Interceptor for Config {
+///     type Key = String;
+///     type Value = String;
+///
+///     fn get(&self, key: &String) -> Option<String> {
+///         self.0.borrow().get(key).cloned()
+///     }
+///
+///     fn keys(&self) -> Vec<String> {
+///         self.0.borrow().keys().cloned().collect()
+///     }
+/// }
+/// ```
+pub trait Interceptor: 'static {
+    /// `String` to answer for named properties, `u32` for indices.
+    type Key: InterceptorKey;
+    type Value;
+
+    /// The value of `key`, or `None` to look it up on the object and its
+    /// prototype as usual.
+    fn get(&self, key: &Self::Key) -> Option<Self::Value>;
+
+    /// Store `value` as `key`, returning whether it was stored. A refused
+    /// write throws a `TypeError` in strict mode code. Refuses every write
+    /// by default.
+    fn set(&self, _key: Self::Key, _value: Self::Value) -> Result<bool, FFIError> {
+        Ok(false)
+    }
+
+    /// Whether `key` is a property, for `in` and property descriptors.
+    fn query(&self, key: &Self::Key) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Remove the property `key`, returning whether it was removed. Only
+    /// called for keys that `query` reports. Refuses by default.
+    fn delete(&self, _key: &Self::Key) -> bool {
+        false
+    }
+
+    /// The keys to enumerate, i.e. for `Object.keys` or `for .. in`.
+    fn keys(&self) -> Vec<Self::Key> {
+        vec![]
+    }
+}
+
+/// A new object whose properties are answered by `handler`. It is a
+/// `Proxy` of an object wrapping `handler`, which lives as long as the
+/// object does, see the module docs for how that differs from template
+/// interceptors.
+///
+/// The `Proxy`, `Reflect` and `Set` builtins the object relies on are
+/// captured the first time a context creates one, so replacing them from
+/// script afterwards does not change how it behaves.
+pub fn intercepted<'sc, I, E>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    handler: I,
+) -> Result<v8::Local<'sc, v8::Object>, FFIError>
+where
+    I: Interceptor,
+    I::Value: for<'a, 'b> FFICompat<'a, 'b, E = E>,
+    E: Debug + Any,
+{
+    let mut wrapped = make_object_wrap(scope, context, handler);
+    wrapped.make_weak();
+    let target = wrapped.get(scope).unwrap();
+    intercept::<I, E>(scope, context, target)
+}
+
+/// A `Proxy` of `target`, an object wrapping an `I`, with `I` answering
+/// its properties.
+fn intercept<'sc, I, E>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    target: v8::Local<v8::Object>,
+) -> Result<v8::Local<'sc, v8::Object>, FFIError>
+where
+    I: Interceptor,
+    I::Value: for<'a, 'b> FFICompat<'a, 'b, E = E>,
+    E: Debug + Any,
+{
+    let traps = [
+        v8::Function::new(scope, context, trap_get::<I, E>),
+        v8::Function::new(scope, context, trap_set::<I, E>),
+        v8::Function::new(scope, context, trap_has::<I>),
+        v8::Function::new(scope, context, trap_delete::<I>),
+        v8::Function::new(scope, context, trap_keys::<I>),
+    ];
+    let mut args = vec![target.into()];
+    for trap in traps.iter() {
+        args.push(trap.unwrap().into());
+    }
+    let proxy = context_function(scope, context, PROXY_SOURCE)?;
+    let undefined = v8::undefined(scope).into();
+    let proxy = call_function(scope, context, proxy, undefined, &args)?;
+    proxy
+        .try_into()
+        .map_err(|_| FFIError::Error("failed to create the interceptor proxy".to_string()))
+}
+
+/// Whether `value` is an object of `intercepted` or `array_like` created in
+/// `context`, a `Proxy` whose traps are answered by Rust.
+pub(crate) fn is_intercepted<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    value: v8::Local<v8::Value>,
+) -> bool {
+    let create = match context_function(scope, context, PROXY_SOURCE) {
+        Ok(create) => create,
+        Err(_) => return false,
+    };
+    let key = make_str(scope, "isIntercepted");
+    let check: Option<v8::Local<v8::Function>> = create
+        .get(scope, context, key)
+        .and_then(|x| x.try_into().ok());
+    let undefined = v8::undefined(scope).into();
+    match check.map(|check| call_function(scope, context, check, undefined, &[value])) {
+        Some(Ok(result)) => result.is_true(),
+        _ => false,
+    }
+}
+
+/// Evaluates to the function turning an interceptor's target into an array
+/// like one, with the builtins it relies on captured like `PROXY_SOURCE`.
+const ARRAY_SOURCE: &str = r#"(function () {
+    const { setPrototypeOf, defineProperty } = Object;
+    const arrayPrototype = Array.prototype;
+    return function (target, length) {
+        setPrototypeOf(target, arrayPrototype);
+        defineProperty(target, 'length', { __proto__: null, get: () => length(target) });
+    };
+})()"#;
+
+/// `JsArrayLike` is a Rust collection indexed like a JS array, see
+/// `array_like`.
+pub trait JsArrayLike: 'static {
+    type Item;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The item at `index`, which is less than `len()`.
+    fn get(&self, index: usize) -> Option<Self::Item>;
+
+    /// Replace the item at `index`, which is less than `len()`, returning
+    /// whether it was replaced. Refuses by default, making the array
+    /// read-only.
+    fn set(&self, _index: usize, _item: Self::Item) -> Result<bool, FFIError> {
+        Ok(false)
+    }
+}
+
+/// The `Interceptor` of the elements of a `JsArrayLike`.
+struct Elements<A>(A);
+
+impl<A: JsArrayLike> Interceptor for Elements<A> {
+    type Key = u32;
+    type Value = A::Item;
+
+    fn get(&self, key: &u32) -> Option<A::Item> {
+        if !self.query(key) {
+            return None;
+        }
+        self.0.get(*key as usize)
+    }
+
+    fn set(&self, key: u32, value: A::Item) -> Result<bool, FFIError> {
+        if !self.query(&key) {
+            return Ok(false);
+        }
+        self.0.set(key as usize, value)
+    }
+
+    fn query(&self, key: &u32) -> bool {
+        (*key as usize) < self.0.len()
+    }
+
+    fn keys(&self) -> Vec<u32> {
+        (0..self.0.len() as u32).collect()
+    }
+}
+
+/// A new object that reads like a JS array of the items of `collection`:
+/// it has indices up to a `length` that follows `collection`, and the
+/// methods of `Array.prototype`, including `Symbol.iterator` for `for ..
+/// of` and spreading. `Array.isArray` is `false` for it, and it cannot grow
+/// or shrink from JS.
+pub fn array_like<'sc, A, E>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    collection: A,
+) -> Result<v8::Local<'sc, v8::Object>, FFIError>
+where
+    A: JsArrayLike,
+    A::Item: for<'a, 'b> FFICompat<'a, 'b, E = E>,
+    E: Debug + Any,
+{
+    let mut wrapped = make_object_wrap(scope, context, Elements(collection));
+    wrapped.make_weak();
+    let target = wrapped.get(scope).unwrap();
+    let length = v8::Function::new(scope, context, array_length::<A>).unwrap();
+    let setup = context_function(scope, context, ARRAY_SOURCE)?;
+    let undefined = v8::undefined(scope).into();
+    call_function(
+        scope,
+        context,
+        setup,
+        undefined,
+        &[target.into(), length.into()],
+    )?;
+    intercept::<Elements<A>, E>(scope, context, target)
+}
+
+fn array_length<'sc, A: JsArrayLike>(
+    scope: v8::FunctionCallbackScope<'sc>,
+    args: v8::FunctionCallbackArguments<'sc>,
+    mut rv: v8::ReturnValue<'sc>,
+) {
+    let target: Option<v8::Local<v8::Object>> = args.get(0).try_into().ok();
+    let length = match target.and_then(ObjectWrap::<Elements<A>>::from_object) {
+        Some(elements) => elements.0.len(),
+        None => 0,
+    };
+    rv.set(make_num(scope, length as f64));
+}
+
+/// The handler of the trap's target and the key it is called for, or
+/// `None` if the key is not for the handler.
+fn receive<'sc, I: Interceptor>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    args: &v8::FunctionCallbackArguments<'sc>,
+) -> Option<(Rc<I>, I::Key)> {
+    let target: v8::Local<v8::Object> = args.get(0).try_into().ok()?;
+    let handler = ObjectWrap::<I>::from_object(target)?;
+    let key = args.get(1);
+    if !key.is_string() {
+        return None;
+    }
+    let key = I::Key::from_key(&key.to_rust_string_lossy(scope))?;
+    Some((handler, key))
+}
+
+fn trap_get<'sc, I, E>(
+    scope: v8::FunctionCallbackScope<'sc>,
+    args: v8::FunctionCallbackArguments<'sc>,
+    mut rv: v8::ReturnValue<'sc>,
+) where
+    I: Interceptor,
+    I::Value: for<'a, 'b> FFICompat<'a, 'b, E = E>,
+    E: Debug + Any,
+{
+    let context = scope.get_current_context().unwrap();
+    let unhandled = args.get(2);
+    let value = match receive::<I>(scope, &args) {
+        Some((handler, key)) => handler.get(&key),
+        None => None,
+    };
+    match value.map(|value| value.to_value(scope, context)) {
+        Some(Ok(value)) => rv.set(value),
+        Some(Err(e)) => throw_ffi_error(scope, &e),
+        None => rv.set(unhandled),
+    }
+}
+
+fn trap_set<'sc, I, E>(
+    scope: v8::FunctionCallbackScope<'sc>,
+    args: v8::FunctionCallbackArguments<'sc>,
+    mut rv: v8::ReturnValue<'sc>,
+) where
+    I: Interceptor,
+    I::Value: for<'a, 'b> FFICompat<'a, 'b, E = E>,
+    E: Debug + Any,
+{
+    let context = scope.get_current_context().unwrap();
+    let (handler, key) = match receive::<I>(scope, &args) {
+        Some(received) => received,
+        None => {
+            rv.set(args.get(3));
+            return;
+        }
+    };
+    let value = match I::Value::from_value(args.get(2), scope, context) {
+        Ok(value) => value,
+        Err(e) => {
+            throw_ffi_error(scope, &e);
+            return;
+        }
+    };
+    match handler.set(key, value) {
+        Ok(stored) => rv.set(make_bool(scope, stored)),
+        Err(e) => throw_ffi_error(scope, &e),
+    }
+}
+
+fn trap_has<'sc, I: Interceptor>(
+    scope: v8::FunctionCallbackScope<'sc>,
+    args: v8::FunctionCallbackArguments<'sc>,
+    mut rv: v8::ReturnValue<'sc>,
+) {
+    match receive::<I>(scope, &args) {
+        Some((handler, key)) if handler.query(&key) => rv.set(make_bool(scope, true)),
+        _ => rv.set(args.get(2)),
+    }
+}
+
+fn trap_delete<'sc, I: Interceptor>(
+    scope: v8::FunctionCallbackScope<'sc>,
+    args: v8::FunctionCallbackArguments<'sc>,
+    mut rv: v8::ReturnValue<'sc>,
+) {
+    match receive::<I>(scope, &args) {
+        Some((handler, key)) if handler.query(&key) => {
+            let deleted = handler.delete(&key);
+            rv.set(make_bool(scope, deleted))
+        }
+        _ => rv.set(args.get(2)),
+    }
+}
+
+fn trap_keys<'sc, I: Interceptor>(
+    scope: v8::FunctionCallbackScope<'sc>,
+    args: v8::FunctionCallbackArguments<'sc>,
+    mut rv: v8::ReturnValue<'sc>,
+) {
+    let context = scope.get_current_context().unwrap();
+    let target: Option<v8::Local<v8::Object>> = args.get(0).try_into().ok();
+    let keys = match target.and_then(ObjectWrap::<I>::from_object) {
+        Some(handler) => handler.keys(),
+        None => vec![],
+    };
+    let keys = keys
+        .iter()
+        .map(|key| JsValue::String(key.to_key()))
+        .collect();
+    match JsValue::Array(keys).to_value(scope, context) {
+        Ok(keys) => rv.set(keys),
+        Err(e) => throw_ffi_error(scope, &e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{make_str, run_script};
+
+    struct TestConfig(std::cell::RefCell<std::collections::BTreeMap<String, String>>);
+
+    impl crate::Interceptor for TestConfig {
+        type Key = String;
+        type Value = String;
+
+        fn get(&self, key: &String) -> Option<String> {
+            self.0.borrow().get(key).cloned()
+        }
+
+        fn set(&self, key: String, value: String) -> Result<bool, crate::FFIError> {
+            if key.starts_with('_') {
+                return Err(crate::FFIError::RangeError(format!("reserved key {}", key)));
+            }
+            self.0.borrow_mut().insert(key, value);
+            Ok(true)
+        }
+
+        fn delete(&self, key: &String) -> bool {
+            self.0.borrow_mut().remove(key).is_some()
+        }
+
+        fn keys(&self) -> Vec<String> {
+            self.0.borrow().keys().cloned().collect()
+        }
+    }
+
+    struct TestSquares;
+
+    impl crate::Interceptor for TestSquares {
+        type Key = u32;
+        type Value = f64;
+
+        fn get(&self, key: &u32) -> Option<f64> {
+            if *key < 3 {
+                Some((key * key) as f64)
+            } else {
+                None
+            }
+        }
+
+        fn keys(&self) -> Vec<u32> {
+            vec![0, 1, 2]
+        }
+    }
+
+    struct TestList(std::cell::RefCell<Vec<f64>>);
+
+    impl crate::JsArrayLike for TestList {
+        type Item = f64;
+
+        fn len(&self) -> usize {
+            self.0.borrow().len()
+        }
+
+        fn get(&self, index: usize) -> Option<f64> {
+            self.0.borrow().get(index).copied()
+        }
+
+        fn set(&self, index: usize, item: f64) -> Result<bool, crate::FFIError> {
+            self.0.borrow_mut()[index] = item;
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn interceptors() {
+        with_context!(|scope, context| {
+            let config = TestConfig(Default::default());
+            config
+                .0
+                .borrow_mut()
+                .insert("host".to_string(), "a".to_string());
+            let config = crate::intercepted(scope, context, config).unwrap();
+            let squares = crate::intercepted(scope, context, TestSquares).unwrap();
+            let global = context.global(scope);
+            global.set(context, make_str(scope, "config"), config.into());
+            global.set(context, make_str(scope, "squares"), squares.into());
+            let result = run_script(
+                scope,
+                context,
+                "config.port = '80'; \
+                [config.host, config.port, 'port' in config, 'other' in config, \
+                    typeof config.toString, Object.keys(config).join(), \
+                    delete config.host, 'host' in config, JSON.stringify(config), \
+                    Object.keys(squares).join(), squares[2], squares[3], squares.length].join('|')",
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok(
+                    "a|80|true|false|function|host,port|true|false|{\"port\":\"80\"}|0,1,2|4||"
+                        .to_string()
+                )
+            );
+            crate::set_proxy_policy(scope, crate::ProxyPolicy::Reject);
+            assert_eq!(
+                serde_json::Value::from_value(config.into(), scope, context),
+                Ok(serde_json::json!({ "port": "80" }))
+            );
+            let other = run_script(scope, context, "new Proxy({}, {})").unwrap();
+            assert_eq!(
+                serde_json::Value::from_value(other, scope, context),
+                Err("value is a Proxy".to_string())
+            );
+            crate::set_proxy_policy(scope, crate::ProxyPolicy::ConvertThrough);
+            let result = run_script(
+                scope,
+                context,
+                "(() => { 'use strict'; const errors = []; \
+                    try { config._secret = 'x'; } catch (e) { errors.push(String(e)); } \
+                    try { squares[0] = 1; } catch (e) { errors.push(e.name); } \
+                    return errors.join('|'); })()",
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok("RangeError: reserved key _secret|TypeError".to_string())
+            );
+            let list = TestList(std::cell::RefCell::new(vec![1.0, 2.0, 3.0]));
+            let list = crate::array_like(scope, context, list).unwrap();
+            global.set(context, make_str(scope, "list"), list.into());
+            let result = run_script(
+                scope,
+                context,
+                "list[0] = 10; let sum = 0; for (const x of list) { sum += x; } \
+                [list.length, list[0], list[3], sum, [...list].join(), list.map((x) => x * 2).join(), \
+                    Array.isArray(list), 2 in list, 3 in list, Object.keys(list).join()].join('|')",
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok("3|10||15|10,2,3|20,4,6|false|true|false|0,1,2".to_string())
+            );
+            run_script(
+                scope,
+                context,
+                "Reflect.ownKeys = () => []; Reflect.get = () => 'patched'; \
+                globalThis.Set = function () { throw 1; }; Array.prototype[Symbol.iterator] = null;",
+            )
+            .unwrap();
+            let config = TestConfig(Default::default());
+            config
+                .0
+                .borrow_mut()
+                .insert("host".to_string(), "b".to_string());
+            let config = crate::intercepted(scope, context, config).unwrap();
+            global.set(context, make_str(scope, "patched"), config.into());
+            let result = run_script(
+                scope,
+                context,
+                "patched.own = '1'; [patched.host, Object.keys(patched).join(), typeof patched.toString].join('|')",
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok("b|host,own|function".to_string())
+            );
+        });
+    }
+}
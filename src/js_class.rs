@@ -0,0 +1,74 @@
+//! Runtime support for `#[js_class]` wrappers, also usable directly to
+//! read properties of and call methods on JS objects.
+
+use crate::util::*;
+use crate::FFICompat;
+use crate::FFIError;
+use rusty_v8 as v8;
+use std::convert::TryInto;
+use v8::Global;
+
+/// Hold on to `value` as an object, for a wrapper of JS class `class`.
+pub fn object_from_value<'sc>(
+    value: v8::Local<'sc, v8::Value>,
+    scope: &mut impl v8::ToLocal<'sc>,
+    class: &str,
+) -> Result<Global<v8::Object>, FFIError> {
+    let object: v8::Local<v8::Object> = value.try_into().map_err(|_| {
+        FFIError::TypeError(format!(
+            "invalid type for argument in ffi call, expected {}, received {}",
+            class,
+            type_of(value)
+        ))
+    })?;
+    Ok(Global::new_from(scope, object))
+}
+
+fn get_value<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    object: &Global<v8::Object>,
+    name: &str,
+) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+    let object = object.get(scope).unwrap();
+    let key = make_str(scope, name);
+    let mut try_catch = v8::TryCatch::new(scope);
+    let tc = try_catch.enter();
+    let value = object.get(scope, context, key);
+    if tc.has_caught() {
+        let exception = tc.exception().unwrap();
+        return Err(FFIError::Error(exception_message(scope, exception)));
+    }
+    Ok(value.unwrap_or_else(|| v8::undefined(scope).into()))
+}
+
+/// Read the property `name` of `object` as an `R`.
+pub fn get_property<'sc, 'c, R: FFICompat<'sc, 'c>>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    object: &Global<v8::Object>,
+    name: &str,
+) -> Result<R, FFIError> {
+    let value = get_value(scope, context, object, name)?;
+    R::from_value(value, scope, context)
+        .map_err(|e| FFIError::TypeError(format!("invalid property {}: {:?}", name, e)))
+}
+
+/// Call the method `name` of `object` with `args`, converting the result
+/// to an `R`.
+pub fn call_method<'sc, 'c, R: FFICompat<'sc, 'c>>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    object: &Global<v8::Object>,
+    name: &str,
+    args: &[v8::Local<'sc, v8::Value>],
+) -> Result<R, FFIError> {
+    let method = get_value(scope, context, object, name)?;
+    let method: v8::Local<v8::Function> = method
+        .try_into()
+        .map_err(|_| FFIError::TypeError(format!("{} is not a function", name)))?;
+    let recv = object.get(scope).unwrap().into();
+    let result = call_function(scope, context, method, recv, args)?;
+    R::from_value(result, scope, context)
+        .map_err(|e| FFIError::TypeError(format!("invalid return value from {}: {:?}", name, e)))
+}
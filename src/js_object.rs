@@ -0,0 +1,193 @@
+//! Runtime support for `#[derive(FromJsObject)]`.
+
+use crate::error_class::ReturnedError;
+use crate::util::*;
+use crate::FFICompat;
+use crate::FFIError;
+use rusty_v8 as v8;
+use std::any::Any;
+use std::convert::TryInto;
+use std::fmt::Debug;
+
+/// Require `value` to be an object to read a `name` struct from.
+pub fn expect_object<'sc>(
+    value: v8::Local<'sc, v8::Value>,
+    name: &str,
+) -> Result<v8::Local<'sc, v8::Object>, FFIError> {
+    value.try_into().map_err(|_| {
+        FFIError::TypeError(format!(
+            "invalid type for argument in ffi call, expected {} object, received {}",
+            name,
+            type_of(value)
+        ))
+    })
+}
+
+fn get_field<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    object: v8::Local<'sc, v8::Object>,
+    name: &str,
+) -> Result<v8::Local<'sc, v8::Value>, String> {
+    let key = make_str(scope, name);
+    let mut try_catch = v8::TryCatch::new(scope);
+    let tc = try_catch.enter();
+    let value = object.get(scope, context, key);
+    if tc.has_caught() {
+        let exception = tc.exception().unwrap();
+        return Err(format!(
+            "field `{}`: {}",
+            name,
+            exception_message(scope, exception)
+        ));
+    }
+    Ok(value.unwrap_or_else(|| v8::undefined(scope).into()))
+}
+
+fn convert_field<'sc, 'c, T: FFICompat<'sc, 'c>>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    value: v8::Local<'sc, v8::Value>,
+    name: &str,
+    errors: &mut Vec<String>,
+) -> Option<T> {
+    let missing = value.is_undefined();
+    match T::from_value(value, scope, context) {
+        Ok(value) => Some(value),
+        Err(_) if missing => {
+            errors.push(format!("missing field `{}`", name));
+            None
+        }
+        Err(e) => {
+            errors.push(format!("field `{}`: {:?}", name, e));
+            None
+        }
+    }
+}
+
+/// Read the property `name` of `object` as a `T`, recording any failure in
+/// `errors` so that every bad field can be reported at once.
+pub fn read_field<'sc, 'c, T: FFICompat<'sc, 'c>>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    object: v8::Local<'sc, v8::Object>,
+    name: &str,
+    errors: &mut Vec<String>,
+) -> Option<T> {
+    match get_field(scope, context, object, name) {
+        Ok(value) => convert_field(scope, context, value, name, errors),
+        Err(e) => {
+            errors.push(e);
+            None
+        }
+    }
+}
+
+/// Like `read_field`, but an `undefined` property becomes `T::default()`.
+pub fn read_field_or_default<'sc, 'c, T: FFICompat<'sc, 'c> + Default>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    object: v8::Local<'sc, v8::Object>,
+    name: &str,
+    errors: &mut Vec<String>,
+) -> Option<T> {
+    match get_field(scope, context, object, name) {
+        Ok(value) if value.is_undefined() => Some(T::default()),
+        Ok(value) => convert_field(scope, context, value, name, errors),
+        Err(e) => {
+            errors.push(e);
+            None
+        }
+    }
+}
+
+/// Combine the errors collected by `read_field` into one `FFIError`.
+pub fn check_fields(name: &str, errors: Vec<String>) -> Result<(), FFIError> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(FFIError::TypeError(format!(
+        "invalid {}: {}",
+        name,
+        errors.join("; ")
+    )))
+}
+
+/// Set the property `name` of `object` to `value`.
+pub fn write_field<'sc, 'c, T: FFICompat<'sc, 'c>>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    object: v8::Local<'sc, v8::Object>,
+    name: &str,
+    value: T,
+) -> Result<(), FFIError> {
+    let value = value
+        .to_value(scope, context)
+        .map_err(|e| FFIError::TypeError(format!("field `{}`: {:?}", name, e)))?;
+    let key = make_str(scope, name);
+    object.set(context, key, value);
+    Ok(())
+}
+
+/// Conversion of a returned tuple to an object with the given property
+/// names, used by `#[v8_ffi(multi_return(..))]`.
+pub trait MultiReturn<'sc, 'c> {
+    type E: Debug;
+
+    fn to_object(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+        names: &[&str],
+    ) -> Result<v8::Local<'sc, v8::Value>, Self::E>;
+}
+
+macro_rules! multi_return_tuple {
+    ($len:expr, $($ty:ident $index:tt),*) => {
+        impl<'sc, 'c, $($ty: FFICompat<'sc, 'c>),*> MultiReturn<'sc, 'c> for ($($ty,)*) {
+            type E = FFIError;
+
+            fn to_object(
+                self,
+                scope: &mut impl v8::ToLocal<'sc>,
+                context: v8::Local<'c, v8::Context>,
+                names: &[&str],
+            ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+                if names.len() != $len {
+                    return Err(FFIError::Error(format!(
+                        "multi_return expected {} names, got {}",
+                        $len,
+                        names.len()
+                    )));
+                }
+                let object = v8::Object::new(scope);
+                $(write_field(scope, context, object, names[$index], self.$index)?;)*
+                Ok(object.into())
+            }
+        }
+    };
+}
+
+multi_return_tuple!(1, A1 0);
+multi_return_tuple!(2, A1 0, A2 1);
+multi_return_tuple!(3, A1 0, A2 1, A3 2);
+multi_return_tuple!(4, A1 0, A2 1, A3 2, A4 3);
+multi_return_tuple!(5, A1 0, A2 1, A3 2, A4 3, A5 4);
+
+impl<'sc, 'c, T: MultiReturn<'sc, 'c>, E: Debug + Any> MultiReturn<'sc, 'c> for Result<T, E> {
+    type E = ReturnedError;
+
+    fn to_object(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+        names: &[&str],
+    ) -> Result<v8::Local<'sc, v8::Value>, ReturnedError> {
+        match self {
+            Ok(v) => v
+                .to_object(scope, context, names)
+                .map_err(|e| ReturnedError::from(format!("{:?}", e))),
+            Err(e) => Err(ReturnedError::new(&e)),
+        }
+    }
+}
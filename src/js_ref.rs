@@ -0,0 +1,184 @@
+//! `JsRef`, a JS value kept by `Global` handle beyond the call it was
+//! passed to, and `WeakJsRef`, which does not keep it alive.
+
+use crate::affinity::Affinity;
+use crate::shim::{self, IsolateRef, WeakOwner};
+use crate::util::call_function;
+use crate::{FFICompat, FFIError};
+use rusty_v8 as v8;
+use std::cell::RefCell;
+use std::rc::Rc;
+use v8::Global;
+
+/// `JsRef` holds a JS object, function, array or other value by `Global`
+/// handle, so a `v8_ffi` fn can keep it after returning, i.e. to register a
+/// callback. It converts from JS with the same type check as the `Local`
+/// of `T`, and back to JS as the same value.
+///
+/// A `JsRef` can only be used with the isolate it came from, on the
+/// isolate's thread, which debug builds check. Rather than dropping it,
+/// `release` it on the isolate's thread while the isolate is still alive.
+/// To pass it through another thread, see `SendWrapper`.
+///
+/// ```ignore
+/// #[v8_ffi]
+/// fn on_message(callback: JsRef<v8::Function>) {
+///     CALLBACKS.with(|callbacks| callbacks.borrow_mut().push(callback));
+/// }
+/// ```
+pub struct JsRef<T>(Global<T>, Affinity);
+
+impl<T> JsRef<T> {
+    pub fn new<'sc>(scope: &mut impl v8::InIsolate, local: v8::Local<'sc, T>) -> JsRef<T> {
+        JsRef(Global::new_from(scope, local), Affinity::current())
+    }
+
+    /// A `Local` of the referenced value in the current scope.
+    pub fn get<'sc>(&self, scope: &mut impl v8::ToLocal<'sc>) -> v8::Local<'sc, T> {
+        self.1.check("JsRef");
+        self.0.get(scope).expect("JsRef is never empty")
+    }
+
+    /// Release the handle, allowing the value to be garbage collected.
+    pub fn release(mut self, scope: &mut impl v8::InIsolate) {
+        self.1.check("JsRef");
+        self.0.reset(scope);
+    }
+}
+
+impl JsRef<v8::Function> {
+    /// Call the referenced function, catching anything it throws.
+    pub fn call<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+        recv: v8::Local<v8::Value>,
+        args: &[v8::Local<v8::Value>],
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        let function = self.get(scope);
+        call_function(scope, context, function, recv, args)
+    }
+}
+
+impl<'sc, 'c, T> FFICompat<'sc, 'c> for JsRef<T>
+where
+    v8::Local<'sc, T>: FFICompat<'sc, 'c>,
+{
+    type E = <v8::Local<'sc, T> as FFICompat<'sc, 'c>>::E;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, Self::E> {
+        let local = v8::Local::<'sc, T>::from_value(value, scope, context)?;
+        Ok(JsRef::new(scope, local))
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, Self::E> {
+        let local = self.get(scope);
+        self.release(scope);
+        local.to_value(scope, context)
+    }
+}
+
+/// `WeakJsRef` refers to a JS object or function without keeping it alive,
+/// i.e. for a cache of JS values keyed by Rust values. Once the V8 GC has
+/// collected the value, `get` returns `None`.
+///
+/// With the `upstream-v8` feature there are no weak handles, so
+/// `WeakJsRef::new` panics, see `shim`.
+pub struct WeakJsRef<T: 'static>(Rc<WeakJsRefInternal<T>>);
+
+struct WeakJsRefInternal<T: 'static> {
+    handle: RefCell<Option<Global<T>>>,
+    affinity: Affinity,
+    isolate: IsolateRef,
+}
+
+impl<T: 'static> WeakOwner for WeakJsRefInternal<T> {
+    fn collected(&self, isolate: &mut v8::Isolate) {
+        if let Some(handle) = self.handle.borrow_mut().take() {
+            shim::release_collected(handle, isolate);
+        }
+    }
+}
+
+impl<T: 'static> WeakJsRef<T> {
+    pub fn new<'sc>(scope: &mut impl v8::InIsolate, local: v8::Local<'sc, T>) -> WeakJsRef<T> {
+        let mut global = Global::new_from(scope, local);
+        let weak = WeakJsRef(Rc::new(WeakJsRefInternal {
+            handle: RefCell::new(None),
+            affinity: Affinity::current(),
+            isolate: IsolateRef::new(scope),
+        }));
+        shim::set_weak_owner(&mut global, weak.0.clone());
+        shim::set_weak(&mut global);
+        weak.0.handle.replace(Some(global));
+        weak
+    }
+
+    /// A `Local` of the referenced value, or `None` if it was collected.
+    pub fn get<'sc>(&self, scope: &mut impl v8::ToLocal<'sc>) -> Option<v8::Local<'sc, T>> {
+        self.0.affinity.check("WeakJsRef");
+        self.0.handle.borrow().as_ref().and_then(|x| x.get(scope))
+    }
+
+    /// A strong reference to the value, or `None` if it was collected.
+    pub fn upgrade<'sc>(&self, scope: &mut impl v8::ToLocal<'sc>) -> Option<JsRef<T>> {
+        let local = self.get(scope)?;
+        Some(JsRef::new(scope, local))
+    }
+
+    /// Check if the V8 GC has collected the value.
+    pub fn is_collected(&self) -> bool {
+        self.0.handle.borrow().is_none()
+    }
+}
+
+impl<T: 'static> Drop for WeakJsRef<T> {
+    fn drop(&mut self) {
+        let mut handle = match self.0.handle.borrow_mut().take() {
+            Some(handle) => handle,
+            None => return,
+        };
+        // without the isolate, only our own reference can be released
+        let isolate = unsafe { self.0.isolate.isolate_ptr().as_mut() };
+        let isolate = match isolate {
+            Some(isolate) => isolate,
+            None => {
+                std::mem::forget(handle);
+                return;
+            }
+        };
+        // releases the reference held by V8
+        shim::clear_weak(&mut handle);
+        handle.reset(isolate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+    use std::convert::TryInto;
+
+    #[test]
+    fn weak_js_ref() {
+        with_context!(|scope, context| {
+            let cached = run_script(scope, context, "({ cached: true })").unwrap();
+            let cached: v8::Local<v8::Object> = cached.try_into().unwrap();
+            let weak = crate::WeakJsRef::new(scope, cached);
+            assert!(!weak.is_collected());
+            assert!(weak.get(scope).unwrap().strict_equals(cached.into()));
+            let strong = weak.upgrade(scope).unwrap();
+            assert!(strong.get(scope).strict_equals(cached.into()));
+            strong.release(scope);
+            drop(weak);
+        });
+    }
+}
@@ -0,0 +1,214 @@
+use crate::limits;
+use crate::util::*;
+use crate::FFICompat;
+use crate::FFIError;
+use rusty_v8 as v8;
+use std::convert::TryInto;
+use std::fmt;
+use v8::Global;
+
+/// `JsValue` is a dynamically typed JS value that can be built without a
+/// scope, so non-scoped `v8_ffi` functions can return mixed results.
+///
+/// When converted from JS, arrays are copied element by element, while any
+/// other object or function is kept by handle as `JsValue::Handle`.
+pub enum JsValue {
+    Undefined,
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsValue>),
+    /// A new plain object with these properties, in order.
+    Object(Vec<(String, JsValue)>),
+    /// An existing JS value, such as an object or function.
+    Handle(Global<v8::Value>),
+}
+
+impl JsValue {
+    /// Build a plain object from `(key, value)` pairs.
+    pub fn object<K: Into<String>, V: Into<JsValue>>(
+        entries: impl IntoIterator<Item = (K, V)>,
+    ) -> JsValue {
+        JsValue::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        )
+    }
+
+    pub fn is_nullish(&self) -> bool {
+        match self {
+            JsValue::Undefined | JsValue::Null => true,
+            _ => false,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsValue::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for JsValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsValue::Undefined => write!(f, "Undefined"),
+            JsValue::Null => write!(f, "Null"),
+            JsValue::Bool(value) => f.debug_tuple("Bool").field(value).finish(),
+            JsValue::Number(value) => f.debug_tuple("Number").field(value).finish(),
+            JsValue::String(value) => f.debug_tuple("String").field(value).finish(),
+            JsValue::Array(value) => f.debug_tuple("Array").field(value).finish(),
+            JsValue::Object(value) => f.debug_tuple("Object").field(value).finish(),
+            JsValue::Handle(_) => write!(f, "Handle(..)"),
+        }
+    }
+}
+
+impl Default for JsValue {
+    fn default() -> JsValue {
+        JsValue::Undefined
+    }
+}
+
+impl From<()> for JsValue {
+    fn from(_: ()) -> JsValue {
+        JsValue::Undefined
+    }
+}
+
+impl From<bool> for JsValue {
+    fn from(value: bool) -> JsValue {
+        JsValue::Bool(value)
+    }
+}
+
+macro_rules! js_value_from_number {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for JsValue {
+                fn from(value: $ty) -> JsValue {
+                    JsValue::Number(value as f64)
+                }
+            }
+        )*
+    };
+}
+
+js_value_from_number!(f64, f32, i32, u32, i64, u64, i16, u16, i8, u8);
+
+impl From<String> for JsValue {
+    fn from(value: String) -> JsValue {
+        JsValue::String(value)
+    }
+}
+
+impl From<&str> for JsValue {
+    fn from(value: &str) -> JsValue {
+        JsValue::String(value.to_string())
+    }
+}
+
+impl<T: Into<JsValue>> From<Vec<T>> for JsValue {
+    fn from(value: Vec<T>) -> JsValue {
+        JsValue::Array(value.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<JsValue>> From<Option<T>> for JsValue {
+    fn from(value: Option<T>) -> JsValue {
+        value.map(Into::into).unwrap_or(JsValue::Null)
+    }
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for JsValue {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        if value.is_undefined() {
+            return Ok(JsValue::Undefined);
+        }
+        if value.is_null() {
+            return Ok(JsValue::Null);
+        }
+        if let Ok(boolean) = TryInto::<v8::Local<v8::Boolean>>::try_into(value) {
+            return Ok(JsValue::Bool(boolean.is_true()));
+        }
+        if let Ok(number) = TryInto::<v8::Local<v8::Number>>::try_into(value) {
+            return Ok(JsValue::Number(
+                number.number_value(scope).unwrap_or(f64::NAN),
+            ));
+        }
+        if let Ok(string) = TryInto::<v8::Local<v8::String>>::try_into(value) {
+            limits::count_string(string.utf8_length(scope))?;
+            return Ok(JsValue::String(string.to_rust_string_lossy(scope)));
+        }
+        if let Ok(array) = TryInto::<v8::Local<v8::Array>>::try_into(value) {
+            let _nested = limits::enter(scope)?;
+            limits::count_entries(array.length() as usize)?;
+            let mut values = Vec::with_capacity(array.length() as usize);
+            for i in 0..array.length() {
+                let item = array
+                    .get_index(scope, context, i)
+                    .unwrap_or_else(|| v8::undefined(scope).into());
+                values.push(JsValue::from_value(item, scope, context)?);
+            }
+            return Ok(JsValue::Array(values));
+        }
+        Ok(JsValue::Handle(Global::new_from(scope, value)))
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        Ok(match self {
+            JsValue::Undefined => v8::undefined(scope).into(),
+            JsValue::Null => v8::null(scope).into(),
+            JsValue::Bool(value) => make_bool(scope, value),
+            JsValue::Number(value) => make_num(scope, value),
+            JsValue::String(value) => make_str(scope, &value),
+            JsValue::Array(values) => {
+                let _nested = limits::enter(scope)?;
+                let mut items = Vec::with_capacity(values.len());
+                for value in values {
+                    items.push(value.to_value(scope, context)?);
+                }
+                v8::Array::new_with_elements(scope, &items).into()
+            }
+            JsValue::Object(entries) => {
+                let _nested = limits::enter(scope)?;
+                let object = v8::Object::new(scope);
+                for (key, value) in entries {
+                    let key = make_str(scope, &key);
+                    let value = value.to_value(scope, context)?;
+                    object.set(context, key, value);
+                }
+                object.into()
+            }
+            JsValue::Handle(handle) => handle.get(scope).unwrap(),
+        })
+    }
+}
@@ -0,0 +1,137 @@
+//! Conversion of serde types through JSON, opted into per type with
+//! `#[derive(FFIObject)]`, or per use site with `JsonVia<T>`, or
+//! `#[ffi(json)]` on a `v8_ffi` argument and
+//! `return_with = "rusty_v8_helper::json"` on its return value:
+//!
+//! ```ignore
+//! #[v8_ffi(return_with = "rusty_v8_helper::json")]
+//! fn rotate(#[ffi(json)] point: Point, turns: Vec<JsonVia<Turn>>) -> Point { .. }
+//! ```
+//!
+//! Per use site, keys are renamed with the isolate's rename policy and
+//! values are not validated against a schema, as those are configured by
+//! `FFIObject`.
+
+use crate::ffi_map::{json_from_value, json_to_value};
+use crate::FFICompat;
+use rusty_v8 as v8;
+use serde::{de::DeserializeOwned, Serialize};
+use std::ops::{Deref, DerefMut};
+
+#[doc(hidden)]
+pub use crate::ffi_map::{object_from_value, object_to_value};
+pub use serde_json::Value;
+
+/// A `T` converted to and from JS through JSON, without `T` implementing
+/// `FFIObject`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct JsonVia<T>(pub T);
+
+impl<T> Deref for JsonVia<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for JsonVia<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<'sc, 'c, T: Serialize + DeserializeOwned> FFICompat<'sc, 'c> for JsonVia<T> {
+    type E = String;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, String> {
+        json_from_value(value, scope, context, None, None).map(JsonVia)
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, String> {
+        json_to_value(&self.0, scope, context, None)
+    }
+}
+
+/// Convert a `v8_ffi` argument through JSON, for `#[ffi(json)]`.
+pub fn from_value<'sc, 'c, T: DeserializeOwned>(
+    value: v8::Local<'sc, v8::Value>,
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+) -> Result<T, String> {
+    json_from_value(value, scope, context, None, None)
+}
+
+/// Convert a `v8_ffi` return value through JSON, for
+/// `return_with = "rusty_v8_helper::json"`.
+pub fn to_value<'sc, 'c, T: Serialize>(
+    value: T,
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+) -> Result<v8::Local<'sc, v8::Value>, String> {
+    json_to_value(&value, scope, context, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+    use rusty_v8_helper_derive::v8_ffi;
+    use serde::Deserialize;
+
+    /// Converted only per use site, see `crate::json`.
+    #[derive(Serialize, Deserialize)]
+    struct TestPoint {
+        x: f64,
+        y: f64,
+    }
+
+    #[v8_ffi(return_with = "crate::json")]
+    fn test_ffi_json(
+        #[ffi(json)] start: TestPoint,
+        moves: Vec<crate::JsonVia<TestPoint>>,
+    ) -> TestPoint {
+        moves.iter().fold(start, |at, step| TestPoint {
+            x: at.x + step.x,
+            y: at.y + step.y,
+        })
+    }
+
+    #[test]
+    fn json_via() {
+        with_context!([test_ffi_json], |scope, context| {
+            let result = run_script(
+                scope,
+                context,
+                r#"
+                const end = test_ffi_json({ x: 1, y: 2 }, [{ x: 1, y: 1 }, { x: -3, y: 0 }]);
+                let error;
+                try {
+                    test_ffi_json({ x: 1 }, []);
+                } catch (e) {
+                    error = e;
+                }
+                [JSON.stringify(end), typeof error].join()
+                "#,
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context).unwrap(),
+                r#"{"x":-1,"y":3},string"#
+            );
+            let value = crate::JsonVia(TestPoint { x: 0.5, y: 0.0 })
+                .to_value(scope, context)
+                .unwrap();
+            let point = crate::JsonVia::<TestPoint>::from_value(value, scope, context).unwrap();
+            assert_eq!((point.x, point.y), (0.5, 0.0));
+        });
+    }
+}
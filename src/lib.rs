@@ -1,15 +1,215 @@
+#[cfg(not(feature = "upstream-v8"))]
 extern crate rusty_v8_protryon as rusty_v8;
+#[cfg(feature = "upstream-v8")]
+extern crate rusty_v8_upstream as rusty_v8;
 extern crate self as rusty_v8_helper;
 
+#[cfg(not(any(feature = "protryon", feature = "upstream-v8")))]
+compile_error!("enable either the `protryon` (default) or the `upstream-v8` feature");
+
+/// The V8 bindings this crate was built against, `rusty_v8_protryon` or
+/// upstream `rusty_v8` with the `upstream-v8` feature.
+pub use rusty_v8 as v8;
+
 use proc_macro_hack::proc_macro_hack;
 #[proc_macro_hack]
+pub use rusty_v8_helper_derive::js;
+pub use rusty_v8_helper_derive::js_class;
+#[cfg(feature = "deno")]
+#[proc_macro_hack]
+pub use rusty_v8_helper_derive::load_deno_op;
+#[proc_macro_hack]
 pub use rusty_v8_helper_derive::load_v8_ffi;
 pub use rusty_v8_helper_derive::v8_ffi;
+pub use rusty_v8_helper_derive::FFIObject;
+pub use rusty_v8_helper_derive::FfiTransparent;
+pub use rusty_v8_helper_derive::FromJsObject;
+pub use rusty_v8_helper_derive::JsEnum;
+pub use rusty_v8_helper_derive::JsErrorClass;
+
+#[cfg(test)]
+#[macro_use]
+mod test_util;
+
+mod shim;
 
 mod object_wrap;
-pub use object_wrap::ObjectWrap;
+pub use object_wrap::{ObjectWrap, ThisGuard, WrapError};
+
+mod this_of;
+pub use this_of::{FromThis, OneOf2, OneOf3, OneOf4, ThisOf, ThisTypes};
+
+mod ffi_fn;
+pub use ffi_fn::{
+    ffi_callback, ffi_template, load_ffi_fn, loaded_functions, FfiFn, FfiFnMeta, FfiParam,
+};
 
 mod ffi_map;
+pub use ffi_map::ErrorPath;
 pub use ffi_map::FFICompat;
 pub use ffi_map::FFIObject;
+pub use ffi_map::Lenient;
+
+pub mod rename;
+pub use rename::RenamePolicy;
+
+mod error;
+pub use error::{FFIError, JsError};
+
+mod error_class;
+pub use error_class::{install_error_classes, JsErrorClass, ReturnedError};
+
+mod coerce;
+pub use coerce::{Coerced, Coercible};
+
+mod numeric;
+pub use numeric::{FFINumber, Finite, Index, NonNegative, Percentage, Positive};
+
+pub mod range;
+pub use range::JsRange;
+
+mod adapter;
+pub use adapter::{AsString, Js, JsAdapter};
+
+pub mod json;
+pub use json::JsonVia;
+
+pub mod owned;
+pub use owned::{FfiFrom, FfiInto};
+
+pub mod event_loop;
+
+mod runtime;
+pub use runtime::{ContextId, Runtime};
+
+pub mod embed;
+pub use embed::{initialize_v8, run_file, Embed, ExitStatus};
+
+pub mod http_handler;
+pub use http_handler::{HandlerRequest, HandlerResponse, HttpAdapter, HttpHandler};
+
+mod budget;
+pub use budget::{BudgetExceeded, ExecutionBudget};
+
+mod sink;
+pub use sink::Sink;
+
+mod promise;
+
+mod blocking;
+pub use blocking::spawn_blocking_ffi;
+
+pub mod executor;
+pub use executor::{set_executor, spawn_local, Executor, FakeTimers, LocalExecutor};
+
+#[cfg(feature = "tokio")]
+pub mod tokio_runtime;
+
+mod time;
+pub use time::{set_time_format, time_format, Millis, TimeFormat, Timestamp};
+
+mod strings;
+pub use strings::{StrictUtf8, Utf16String};
+
+#[cfg(feature = "bigint")]
+mod bigint;
+
+#[cfg(feature = "decimal")]
+mod decimal;
+#[cfg(feature = "decimal")]
+pub use decimal::DecimalObject;
+
+mod nullish;
+pub use nullish::{none_value, set_none_value, NoneValue, Null, Undefined};
+
+mod js_value;
+pub use js_value::JsValue;
+
+mod js_ref;
+pub use js_ref::{JsRef, WeakJsRef};
+
+mod affinity;
+pub use affinity::SendWrapper;
+
+mod callbacks;
+pub use callbacks::{CallbackId, CallbackRegistry};
+
+mod capability;
+pub use capability::{expose_function, expose_object};
+
+mod realm;
+pub use realm::Realm;
+
+mod recycler;
+pub use recycler::{ContextRecycler, RecycleReport};
+
+mod context_template;
+pub use context_template::ContextTemplate;
+
+mod mailbox;
+pub use mailbox::{IsolateMailbox, MailboxError, MailboxMetrics, OverflowPolicy};
+
+mod interceptor;
+pub use interceptor::{array_like, intercepted, Interceptor, InterceptorKey, JsArrayLike};
+
+mod consts;
+pub use consts::{install_js_enum, Consts, JsEnum};
+
+mod bytes;
+pub use bytes::Bytes;
+
+mod scratch;
+
+mod ser;
+
+mod de;
+
+pub mod schema;
+pub use schema::SchemaError;
+
+pub mod sources;
+
+pub mod coverage;
+
+mod properties;
+pub use properties::{
+    getter_policy, property_mode, proxy_policy, set_getter_policy, set_property_mode,
+    set_proxy_policy, GetterPolicy, PropertyMode, ProxyPolicy,
+};
+
+mod limits;
+pub use limits::{
+    conversion_limits, set_conversion_limits, set_js_stack_size, ConversionLimits, LimitExceeded,
+};
+
+pub mod js_class;
+pub mod js_object;
+
+pub mod wasm;
+
+pub mod module;
+
+mod script;
+pub use script::{compile_only, eval_with_bindings, CompiledScript};
+
+mod inspect;
+
+pub mod repl;
+pub use repl::{Repl, ReplOutput};
+
+#[cfg(feature = "commonjs")]
+pub mod commonjs;
+
+#[cfg(feature = "deno")]
+pub mod deno;
+
+pub mod accounting;
+pub use accounting::{ContextUsage, UsageMeter};
+pub mod extensions;
+pub use extensions::abort::CancellationToken;
+pub use extensions::Extension;
+pub mod gc;
+pub mod instrument;
+pub mod policy;
+pub use policy::{AllowList, PolicyHook};
 pub mod util;
@@ -0,0 +1,299 @@
+//! Limits on the values converted to and from JS, so that self-referential
+//! or gigantic values passed by untrusted code fail to convert rather than
+//! overflowing the stack or exhausting memory, and on the stack used by JS.
+
+use crate::util::{isolate_slot, set_isolate_slot};
+use crate::FFIError;
+use rusty_v8 as v8;
+use std::cell::RefCell;
+use std::fmt;
+
+/// `ConversionLimits` bound each conversion of a nested value from JS, i.e.
+/// one `v8_ffi` argument, through `FFIObject`, `Vec` or `JsValue`. The
+/// depth also bounds conversions of `FFIObject` and `JsValue` to JS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConversionLimits {
+    /// How deeply arrays and objects may be nested.
+    pub max_depth: usize,
+    /// The total number of array elements and object properties.
+    pub max_entries: usize,
+    /// The total UTF-8 length of the strings within.
+    pub max_string_bytes: usize,
+}
+
+/// Nesting is limited to 128 levels, which also stops self-referential
+/// values. The other limits are unbounded.
+impl Default for ConversionLimits {
+    fn default() -> ConversionLimits {
+        ConversionLimits {
+            max_depth: 128,
+            max_entries: usize::MAX,
+            max_string_bytes: usize::MAX,
+        }
+    }
+}
+
+/// A value converted from JS exceeded one of its `ConversionLimits`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitExceeded {
+    Depth(usize),
+    Entries(usize),
+    StringBytes(usize),
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitExceeded::Depth(limit) => {
+                write!(f, "value is nested deeper than {} levels", limit)
+            }
+            LimitExceeded::Entries(limit) => {
+                write!(f, "value has more than {} elements and properties", limit)
+            }
+            LimitExceeded::StringBytes(limit) => {
+                write!(f, "value has more than {} bytes of strings", limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+impl From<LimitExceeded> for String {
+    fn from(error: LimitExceeded) -> String {
+        error.to_string()
+    }
+}
+
+impl From<LimitExceeded> for FFIError {
+    fn from(error: LimitExceeded) -> FFIError {
+        FFIError::RangeError(error.to_string())
+    }
+}
+
+struct LimitsSlot(ConversionLimits);
+
+/// Set the limits of conversions from JS in this isolate.
+pub fn set_conversion_limits(scope: &mut impl v8::InIsolate, limits: ConversionLimits) {
+    set_isolate_slot(scope, LimitsSlot(limits));
+}
+
+/// The limits of conversions from JS in this isolate.
+pub fn conversion_limits(scope: &mut impl v8::InIsolate) -> ConversionLimits {
+    isolate_slot::<LimitsSlot>(scope)
+        .map(|slot| slot.0)
+        .unwrap_or_default()
+}
+
+/// Set the stack V8 lets JS use in isolates created from now on, in
+/// kilobytes. Deeper recursion throws a `RangeError`, `Maximum call stack
+/// size exceeded`, that scripts can catch, rather than overflowing the
+/// stack of the thread and crashing the process.
+///
+/// V8 defaults to just under 1 MB, which assumes the thread running the
+/// isolate has a larger stack. Keep well below the thread's stack size,
+/// i.e. half of what `std::thread::Builder::stack_size` was given, leaving
+/// room for the native frames of `v8_ffi` fns and conversions.
+pub fn set_js_stack_size(kilobytes: usize) {
+    v8::V8::set_flags_from_string(&format!("--stack-size={}", kilobytes));
+}
+
+#[derive(Default)]
+struct Usage {
+    depth: usize,
+    entries: usize,
+    string_bytes: usize,
+    limits: ConversionLimits,
+}
+
+thread_local! {
+    static USAGE: RefCell<Usage> = RefCell::new(Usage::default());
+}
+
+/// One level of nesting in a conversion, left when dropped.
+pub(crate) struct Nested(());
+
+impl Drop for Nested {
+    fn drop(&mut self) {
+        USAGE.with(|usage| usage.borrow_mut().depth -= 1);
+    }
+}
+
+/// Enter an array or object being converted, starting a new conversion
+/// with the isolate's limits if this is the outermost one.
+pub(crate) fn enter(scope: &mut impl v8::InIsolate) -> Result<Nested, LimitExceeded> {
+    let outermost = USAGE.with(|usage| usage.borrow().depth == 0);
+    let limits = if outermost {
+        Some(conversion_limits(scope))
+    } else {
+        None
+    };
+    USAGE.with(|usage| enter_with(&mut usage.borrow_mut(), limits))
+}
+
+fn enter_with(
+    usage: &mut Usage,
+    limits: Option<ConversionLimits>,
+) -> Result<Nested, LimitExceeded> {
+    if let Some(limits) = limits {
+        *usage = Usage {
+            limits,
+            ..Usage::default()
+        };
+    }
+    if usage.depth >= usage.limits.max_depth {
+        return Err(LimitExceeded::Depth(usage.limits.max_depth));
+    }
+    usage.depth += 1;
+    Ok(Nested(()))
+}
+
+/// Count `count` elements or properties against the current conversion.
+pub(crate) fn count_entries(count: usize) -> Result<(), LimitExceeded> {
+    USAGE.with(|usage| {
+        let mut usage = usage.borrow_mut();
+        if usage.depth == 0 {
+            return Ok(());
+        }
+        usage.entries = usage.entries.saturating_add(count);
+        if usage.entries > usage.limits.max_entries {
+            return Err(LimitExceeded::Entries(usage.limits.max_entries));
+        }
+        Ok(())
+    })
+}
+
+/// Count a string of `bytes` against the current conversion.
+pub(crate) fn count_string(bytes: usize) -> Result<(), LimitExceeded> {
+    USAGE.with(|usage| {
+        let mut usage = usage.borrow_mut();
+        if usage.depth == 0 {
+            return Ok(());
+        }
+        usage.string_bytes = usage.string_bytes.saturating_add(bytes);
+        if usage.string_bytes > usage.limits.max_string_bytes {
+            return Err(LimitExceeded::StringBytes(usage.limits.max_string_bytes));
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+    use crate::FFICompat;
+    use serde_json::Value;
+
+    #[test]
+    fn nested_limits() {
+        let limits = ConversionLimits {
+            max_depth: 2,
+            max_entries: 3,
+            max_string_bytes: 4,
+        };
+        let outer = USAGE.with(|usage| enter_with(&mut usage.borrow_mut(), Some(limits)));
+        let outer = outer.unwrap();
+        let inner = USAGE.with(|usage| enter_with(&mut usage.borrow_mut(), None));
+        let inner = inner.unwrap();
+        let too_deep = USAGE.with(|usage| enter_with(&mut usage.borrow_mut(), None));
+        assert_eq!(too_deep.err(), Some(LimitExceeded::Depth(2)));
+        assert_eq!(count_entries(3), Ok(()));
+        assert_eq!(count_entries(1), Err(LimitExceeded::Entries(3)));
+        assert_eq!(count_string(5), Err(LimitExceeded::StringBytes(4)));
+        drop(inner);
+        drop(outer);
+        // outside of a conversion nothing is counted
+        assert_eq!(count_entries(10), Ok(()));
+    }
+
+    #[test]
+    fn limited_conversions() {
+        with_context!(|scope, context| {
+            let cyclic = run_script(
+                scope,
+                context,
+                "const cyclic = [1]; cyclic.push(cyclic); cyclic",
+            )
+            .unwrap();
+            assert!(matches!(
+                crate::JsValue::from_value(cyclic, scope, context),
+                Err(crate::FFIError::RangeError(_))
+            ));
+            assert_eq!(
+                Value::from_value(cyclic, scope, context).err(),
+                Some("circular reference: value[1] refers back to value".to_string())
+            );
+            let cyclic = run_script(
+                scope,
+                context,
+                "const parent = { name: 'a', child: {} }; parent.child.self = parent.child; parent",
+            )
+            .unwrap();
+            assert_eq!(
+                Value::from_value(cyclic, scope, context).err(),
+                Some("circular reference: value.child.self refers back to value.child".to_string())
+            );
+            let shared =
+                run_script(scope, context, "const leaf = {}; ({ a: leaf, b: leaf })").unwrap();
+            assert!(Value::from_value(shared, scope, context).is_ok());
+            crate::set_conversion_limits(
+                scope,
+                crate::ConversionLimits {
+                    max_entries: 4,
+                    max_string_bytes: 4,
+                    ..Default::default()
+                },
+            );
+            let wide = run_script(scope, context, "[[1, 2], [3]]").unwrap();
+            assert_eq!(
+                Vec::<Vec<u32>>::from_value(wide, scope, context).err(),
+                Some("value has more than 4 elements and properties".to_string())
+            );
+            let long = run_script(scope, context, "({ a: 'abc', b: 'de' })").unwrap();
+            assert_eq!(
+                Value::from_value(long, scope, context).err(),
+                Some("value has more than 4 bytes of strings".to_string())
+            );
+            let small = run_script(scope, context, "[[1], [2]]").unwrap();
+            assert_eq!(
+                Vec::<Vec<u32>>::from_value(small, scope, context),
+                Ok(vec![vec![1], vec![2]])
+            );
+            let mut deep = crate::JsValue::Null;
+            let mut deep_json = Value::Null;
+            for _ in 0..200 {
+                deep = crate::JsValue::Array(vec![deep]);
+                deep_json = serde_json::json!({ "a": deep_json });
+            }
+            assert_eq!(
+                deep.to_value(scope, context).err(),
+                Some(crate::FFIError::RangeError(
+                    "value is nested deeper than 128 levels".to_string()
+                ))
+            );
+            assert_eq!(
+                deep_json.to_value(scope, context).err(),
+                Some("value is nested deeper than 128 levels".to_string())
+            );
+            let deep = run_script(
+                scope,
+                context,
+                "let deep = []; for (let i = 0; i < 100000; i++) deep = [deep]; deep",
+            )
+            .unwrap();
+            assert!(matches!(
+                crate::JsValue::from_value(deep, scope, context),
+                Err(crate::FFIError::RangeError(_))
+            ));
+            let result = run_script(
+                scope,
+                context,
+                "(() => { const f = (n) => f(n + 1); try { f(0); } catch (e) { return e instanceof RangeError; } })()",
+            )
+            .unwrap();
+            assert!(result.is_true());
+            crate::set_conversion_limits(scope, Default::default());
+        });
+    }
+}
@@ -0,0 +1,334 @@
+//! `IsolateMailbox`, a bounded queue of jobs sent into an isolate from
+//! other threads.
+
+use crate::event_loop::{self, EventLoopHandle};
+use crate::{CallbackId, CallbackRegistry, FFICompat, FFIError};
+use rusty_v8 as v8;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, ThreadId};
+use v8::Isolate;
+
+type Job = Box<dyn FnOnce(&mut Isolate) -> Result<(), FFIError> + Send>;
+
+/// What sending to a full `IsolateMailbox` does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait until the isolate thread makes room. Sending from the isolate
+    /// thread itself fails with `MailboxError::Full` instead.
+    Block,
+    /// Fail with `MailboxError::Full`.
+    Reject,
+    /// Drop the oldest queued job to make room.
+    DropOldest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MailboxError {
+    /// The mailbox is full and its policy rejected the job.
+    Full,
+    /// The mailbox was closed, or the isolate's event loop dropped.
+    Closed,
+}
+
+impl fmt::Display for MailboxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MailboxError::Full => write!(f, "isolate mailbox is full"),
+            MailboxError::Closed => write!(f, "isolate mailbox is closed"),
+        }
+    }
+}
+
+impl std::error::Error for MailboxError {}
+
+/// Counters of an `IsolateMailbox` since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MailboxMetrics {
+    /// Jobs waiting to run now.
+    pub queued: usize,
+    /// The most jobs that were waiting at once.
+    pub high_water: usize,
+    pub enqueued: u64,
+    /// Jobs that ran and succeeded.
+    pub processed: u64,
+    /// Jobs that ran and failed, i.e. whose callback threw, see
+    /// `IsolateMailbox::last_error`.
+    pub failed: u64,
+    /// Jobs not queued because the mailbox was full.
+    pub rejected: u64,
+    /// Jobs dropped for newer ones by `OverflowPolicy::DropOldest`.
+    pub dropped: u64,
+}
+
+struct Queue {
+    jobs: VecDeque<Job>,
+    metrics: MailboxMetrics,
+    /// Whether a drain is posted to the event loop and has not started.
+    draining: bool,
+    closed: bool,
+    last_error: Option<FFIError>,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    not_full: Condvar,
+    capacity: usize,
+    policy: OverflowPolicy,
+    handle: Mutex<EventLoopHandle>,
+    thread: ThreadId,
+}
+
+/// `IsolateMailbox` is a bounded queue of jobs for an isolate, sent from
+/// any thread and run on the isolate thread while its event loop is
+/// driven. Unlike posting to an `EventLoopHandle`, at most `capacity` jobs
+/// wait at once, and a full mailbox applies its `OverflowPolicy`.
+///
+/// Only one task is posted to the event loop per batch of jobs, so the
+/// event loop's own queue stays small.
+///
+/// ```ignore
+/// let mailbox = IsolateMailbox::new(scope, 1024, OverflowPolicy::Block);
+/// let sender = mailbox.clone();
+/// thread::spawn(move || {
+///     for line in lines {
+///         sender.send_message(on_line, line)?;
+///     }
+/// });
+/// ```
+#[derive(Clone)]
+pub struct IsolateMailbox(Arc<Shared>);
+
+impl IsolateMailbox {
+    /// Create a mailbox for the isolate of `scope` holding up to `capacity`
+    /// jobs. Panics if `capacity` is 0.
+    pub fn new(
+        scope: &mut impl v8::InIsolate,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> IsolateMailbox {
+        assert!(capacity > 0, "isolate mailbox capacity must not be 0");
+        IsolateMailbox(Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                jobs: VecDeque::new(),
+                metrics: MailboxMetrics::default(),
+                draining: false,
+                closed: false,
+                last_error: None,
+            }),
+            not_full: Condvar::new(),
+            capacity,
+            policy,
+            handle: Mutex::new(event_loop::handle(scope)),
+            thread: thread::current().id(),
+        }))
+    }
+
+    /// Queue `job` to run on the isolate thread.
+    pub fn send(
+        &self,
+        job: impl FnOnce(&mut Isolate) + Send + 'static,
+    ) -> Result<(), MailboxError> {
+        self.push(Box::new(move |isolate| {
+            job(isolate);
+            Ok(())
+        }))
+    }
+
+    /// Queue calling the callback `callback` of the isolate's
+    /// `CallbackRegistry` with `message`, in the context it was registered
+    /// from. The job fails if the callback is gone or throws.
+    pub fn send_message<M>(&self, callback: CallbackId, message: M) -> Result<(), MailboxError>
+    where
+        M: for<'sc, 'c> FFICompat<'sc, 'c> + Send + 'static,
+    {
+        self.push(Box::new(move |isolate| {
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            let registry = CallbackRegistry::of(scope);
+            let context = registry.context_of(scope, callback).ok_or_else(|| {
+                FFIError::Error(format!("no callback registered as {:?}", callback))
+            })?;
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            let message = message
+                .to_value(scope, context)
+                .map_err(|e| FFIError::Error(format!("{:?}", e)))?;
+            registry.invoke(scope, context, callback, &[message])?;
+            Ok(())
+        }))
+    }
+
+    fn push(&self, job: Job) -> Result<(), MailboxError> {
+        let shared = &self.0;
+        let mut queue = shared.queue.lock().unwrap();
+        loop {
+            if queue.closed {
+                return Err(MailboxError::Closed);
+            }
+            if queue.jobs.len() < shared.capacity {
+                break;
+            }
+            match shared.policy {
+                OverflowPolicy::Block if thread::current().id() != shared.thread => {
+                    queue = shared.not_full.wait(queue).unwrap();
+                }
+                OverflowPolicy::Block | OverflowPolicy::Reject => {
+                    queue.metrics.rejected += 1;
+                    return Err(MailboxError::Full);
+                }
+                OverflowPolicy::DropOldest => {
+                    queue.jobs.pop_front();
+                    queue.metrics.dropped += 1;
+                }
+            }
+        }
+        queue.jobs.push_back(job);
+        queue.metrics.enqueued += 1;
+        queue.metrics.high_water = queue.metrics.high_water.max(queue.jobs.len());
+        if !queue.draining {
+            let drained = shared.clone();
+            let posted = shared
+                .handle
+                .lock()
+                .unwrap()
+                .post(move |isolate| drain(&drained, isolate));
+            if !posted {
+                queue.jobs.pop_back();
+                queue.closed = true;
+                shared.not_full.notify_all();
+                return Err(MailboxError::Closed);
+            }
+            queue.draining = true;
+        }
+        Ok(())
+    }
+
+    pub fn metrics(&self) -> MailboxMetrics {
+        let queue = self.0.queue.lock().unwrap();
+        MailboxMetrics {
+            queued: queue.jobs.len(),
+            ..queue.metrics
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.0.capacity
+    }
+
+    /// The error of the job that failed most recently, if any did, i.e. a
+    /// callback that was gone or threw. Counted as `MailboxMetrics::failed`.
+    pub fn last_error(&self) -> Option<FFIError> {
+        self.0.queue.lock().unwrap().last_error.clone()
+    }
+
+    /// Drop the queued jobs and fail sending from now on, waking blocked
+    /// senders. Should be called before the isolate is disposed if senders
+    /// may be blocked.
+    pub fn close(&self) {
+        let jobs = {
+            let mut queue = self.0.queue.lock().unwrap();
+            queue.closed = true;
+            std::mem::take(&mut queue.jobs)
+        };
+        self.0.not_full.notify_all();
+        drop(jobs);
+    }
+}
+
+/// Run as many jobs as were queued when the drain started, taking them one
+/// at a time so that each makes room for a blocked sender as it starts.
+/// Jobs sent meanwhile post another drain.
+fn drain(shared: &Shared, isolate: &mut Isolate) {
+    let count = {
+        let mut queue = shared.queue.lock().unwrap();
+        queue.draining = false;
+        queue.jobs.len()
+    };
+    for _ in 0..count {
+        let job = shared.queue.lock().unwrap().jobs.pop_front();
+        let job = match job {
+            Some(job) => job,
+            // closed, or jobs dropped for newer ones the next drain takes
+            None => break,
+        };
+        shared.not_full.notify_one();
+        let result = job(isolate);
+        let mut queue = shared.queue.lock().unwrap();
+        match result {
+            Ok(()) => queue.metrics.processed += 1,
+            Err(error) => {
+                queue.metrics.failed += 1;
+                queue.last_error = Some(error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+
+    #[test]
+    fn isolate_mailbox() {
+        with_context!(|scope, context| {
+            let function = run_script(scope, context, "globalThis.log = []; (x) => log.push(x)");
+            let function =
+                crate::JsRef::<v8::Function>::from_value(function.unwrap(), scope, context);
+            let callback =
+                crate::CallbackRegistry::of(scope).register(scope, context, function.unwrap());
+
+            let mailbox = IsolateMailbox::new(scope, 2, OverflowPolicy::Reject);
+            let sender = mailbox.clone();
+            let sent = std::thread::spawn(move || {
+                (1..=3)
+                    .map(|x| sender.send_message(callback, x as u32))
+                    .collect::<Vec<_>>()
+            })
+            .join()
+            .unwrap();
+            assert_eq!(sent, vec![Ok(()), Ok(()), Err(MailboxError::Full)]);
+            crate::event_loop::run_pending(scope);
+            let metrics = mailbox.metrics();
+            assert_eq!(
+                (metrics.queued, metrics.high_water, metrics.processed),
+                (0, 2, 2)
+            );
+            assert_eq!(metrics.rejected, 1);
+            assert_eq!(mailbox.last_error(), None);
+
+            let latest = IsolateMailbox::new(scope, 2, OverflowPolicy::DropOldest);
+            for x in 4..=6 {
+                latest.send_message(callback, x as u32).unwrap();
+            }
+            let blocking = IsolateMailbox::new(scope, 1, OverflowPolicy::Block);
+            blocking.send(|_| panic!("closed before it ran")).unwrap();
+            // blocking on the isolate thread would never return
+            assert_eq!(blocking.send(|_| {}), Err(MailboxError::Full));
+            blocking.close();
+            assert_eq!(blocking.send(|_| {}), Err(MailboxError::Closed));
+            crate::event_loop::run_pending(scope);
+            assert_eq!(latest.metrics().dropped, 1);
+            let log = run_script(scope, context, "log.join()").unwrap();
+            assert_eq!(
+                String::from_value(log, scope, context),
+                Ok("1,2,5,6".to_string())
+            );
+
+            let function = run_script(scope, context, "(x) => { throw new Error(`bad ${x}`); }");
+            let function =
+                crate::JsRef::<v8::Function>::from_value(function.unwrap(), scope, context);
+            let throwing =
+                crate::CallbackRegistry::of(scope).register(scope, context, function.unwrap());
+            mailbox.send_message(throwing, 7u32).unwrap();
+            crate::event_loop::run_pending(scope);
+            assert_eq!(mailbox.metrics().failed, 1);
+            assert!(matches!(
+                mailbox.last_error(),
+                Some(FFIError::Error(message)) if message.contains("bad 7")
+            ));
+        });
+    }
+}
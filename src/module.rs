@@ -0,0 +1,562 @@
+use crate::sources::record_source;
+use crate::util::*;
+use crate::FFIError;
+use rusty_v8 as v8;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use v8::Global;
+
+/// `ModuleResolver` maps import specifiers to module urls and source text
+/// for the ES module loader.
+pub trait ModuleResolver {
+    /// Resolve `specifier` as imported from the module at `referrer` to a
+    /// module url. `referrer` is empty for top-level loads.
+    fn resolve(&self, specifier: &str, referrer: &str) -> Result<String, String> {
+        Ok(resolve_relative(specifier, referrer))
+    }
+
+    /// Load the source text of the module at `url`.
+    fn load(&self, url: &str) -> Result<String, String>;
+}
+
+/// Resolve `./` and `../` specifiers against the directory of `referrer`.
+/// Absolute and bare specifiers are returned as is.
+pub fn resolve_relative(specifier: &str, referrer: &str) -> String {
+    if !(specifier.starts_with("./") || specifier.starts_with("../")) {
+        return specifier.to_string();
+    }
+    let mut segments: Vec<&str> = referrer.split('/').collect();
+    segments.pop();
+    for segment in specifier.split('/') {
+        match segment {
+            "." => (),
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+/// A `ModuleResolver` serving module sources from memory.
+#[derive(Default)]
+pub struct MemoryModuleResolver {
+    modules: HashMap<String, String>,
+}
+
+impl MemoryModuleResolver {
+    pub fn new() -> MemoryModuleResolver {
+        MemoryModuleResolver::default()
+    }
+
+    pub fn insert(&mut self, url: &str, source: &str) {
+        self.modules.insert(url.to_string(), source.to_string());
+    }
+}
+
+impl ModuleResolver for MemoryModuleResolver {
+    fn load(&self, url: &str) -> Result<String, String> {
+        self.modules
+            .get(url)
+            .cloned()
+            .ok_or_else(|| format!("module not found: {}", url))
+    }
+}
+
+/// A `ModuleResolver` reading modules from a directory on disk.
+///
+/// Module urls are paths relative to `root`, and anything resolving
+/// outside of `root`, through `..` or symbolic links, is refused.
+pub struct FsModuleResolver {
+    root: PathBuf,
+}
+
+impl FsModuleResolver {
+    pub fn new(root: impl Into<PathBuf>) -> FsModuleResolver {
+        FsModuleResolver { root: root.into() }
+    }
+}
+
+/// `path` below `root`, with leading `/`s ignored, or `None` if it has
+/// `..` or other components that could escape `root`.
+fn jailed_path(root: &Path, path: &str) -> Option<PathBuf> {
+    let mut jailed = root.to_path_buf();
+    for component in Path::new(path.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(segment) => jailed.push(segment),
+            Component::CurDir => (),
+            _ => return None,
+        }
+    }
+    Some(jailed)
+}
+
+/// `jailed_path` with symbolic links resolved, or `None` if it resolves
+/// outside of `root`. A path that does not exist yet, i.e. of a file to be
+/// created, is resolved through its closest existing ancestor, and refused
+/// if it is a dangling symbolic link.
+pub(crate) fn resolved_jailed_path(root: &Path, path: &str) -> io::Result<Option<PathBuf>> {
+    let jailed = match jailed_path(root, path) {
+        Some(jailed) => jailed,
+        None => return Ok(None),
+    };
+    let root = root.canonicalize()?;
+    let mut existing = jailed.as_path();
+    let mut missing = vec![];
+    let mut resolved = loop {
+        match existing.canonicalize() {
+            Ok(resolved) => break resolved,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                if fs::symlink_metadata(existing).is_ok() {
+                    return Ok(None);
+                }
+                match (existing.parent(), existing.file_name()) {
+                    (Some(parent), Some(name)) => {
+                        missing.push(name);
+                        existing = parent;
+                    }
+                    _ => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    if !resolved.starts_with(&root) {
+        return Ok(None);
+    }
+    resolved.extend(missing.into_iter().rev());
+    Ok(Some(resolved))
+}
+
+impl ModuleResolver for FsModuleResolver {
+    fn load(&self, url: &str) -> Result<String, String> {
+        let path = resolved_jailed_path(&self.root, url)
+            .map_err(|e| format!("failed to resolve module {}: {}", url, e))?
+            .ok_or_else(|| format!("module path escapes root: {}", url))?;
+        if !path.is_file() {
+            return Err(format!("module not found: {}", url));
+        }
+        fs::read_to_string(&path).map_err(|e| format!("failed to read module {}: {}", url, e))
+    }
+}
+
+/// The modules loaded in one context, as a module is instantiated in the
+/// context it is first loaded in.
+struct ContextModules {
+    context: Global<v8::Context>,
+    by_url: HashMap<String, Global<v8::Module>>,
+}
+
+struct ModuleMap {
+    resolver: Rc<dyn ModuleResolver>,
+    contexts: Vec<ContextModules>,
+    /// The url of every loaded module by its identity hash, which
+    /// `load_tree` keeps unique.
+    by_hash: HashMap<i32, String>,
+}
+
+impl ModuleMap {
+    fn context_index<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+    ) -> Option<usize> {
+        let target = context.global(scope);
+        self.contexts.iter().position(|x| {
+            x.context
+                .get(scope)
+                .map(|x| x.global(scope).strict_equals(target.into()))
+                .unwrap_or(false)
+        })
+    }
+
+    fn get<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+        url: &str,
+    ) -> Option<v8::Local<'sc, v8::Module>> {
+        let index = self.context_index(scope, context)?;
+        self.contexts[index].by_url.get(url)?.get(scope)
+    }
+
+    fn insert<'sc>(
+        &mut self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+        url: &str,
+        module: v8::Local<v8::Module>,
+    ) {
+        let index = match self.context_index(scope, context) {
+            Some(index) => index,
+            None => {
+                self.contexts.push(ContextModules {
+                    context: Global::new_from(scope, context),
+                    by_url: HashMap::new(),
+                });
+                self.contexts.len() - 1
+            }
+        };
+        self.contexts[index]
+            .by_url
+            .insert(url.to_string(), Global::new_from(scope, module));
+        self.by_hash
+            .insert(module.get_identity_hash(), url.to_string());
+    }
+}
+
+type ModuleMapSlot = RefCell<ModuleMap>;
+
+/// Set the resolver used to load ES modules in this isolate, and register
+/// the isolate callbacks for dynamic `import()` and `import.meta`.
+pub fn set_module_resolver(
+    scope: &mut impl v8::InIsolate,
+    resolver: impl ModuleResolver + 'static,
+) {
+    set_isolate_slot::<ModuleMapSlot>(
+        scope,
+        RefCell::new(ModuleMap {
+            resolver: Rc::new(resolver),
+            contexts: vec![],
+            by_hash: HashMap::new(),
+        }),
+    );
+    let isolate = scope.isolate();
+    isolate.set_host_import_module_dynamically_callback(dynamic_import_callback);
+    isolate.set_host_initialize_import_meta_object_callback(import_meta_callback);
+}
+
+fn module_map(scope: &mut impl v8::InIsolate) -> Result<Rc<ModuleMapSlot>, FFIError> {
+    isolate_slot::<ModuleMapSlot>(scope)
+        .ok_or_else(|| FFIError::Error("no module resolver set for isolate".to_string()))
+}
+
+fn compile_module<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    url: &str,
+    source: &str,
+) -> Result<v8::Local<'sc, v8::Module>, FFIError> {
+    record_source(scope, url, source);
+    let origin = make_script_origin(scope, url, true);
+    let source = v8::String::new(scope, source).unwrap();
+    let source = v8::script_compiler::Source::new(source, &origin);
+    let mut try_catch = v8::TryCatch::new(scope);
+    let tc = try_catch.enter();
+    let module = v8::script_compiler::compile_module(scope, source);
+    if tc.has_caught() {
+        let exception = tc.exception().unwrap();
+        return Err(FFIError::Error(format!(
+            "failed to compile module {}: {}",
+            url,
+            exception_message(scope, exception)
+        )));
+    }
+    module.ok_or_else(|| FFIError::Error(format!("failed to compile module {}", url)))
+}
+
+/// Compile the module at `url` for `context` and, recursively, all of its
+/// static imports.
+fn load_tree<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    url: &str,
+) -> Result<v8::Local<'sc, v8::Module>, FFIError> {
+    let map = module_map(scope)?;
+    let existing = map.borrow().get(scope, context, url);
+    if let Some(existing) = existing {
+        return Ok(existing);
+    }
+    let resolver = map.borrow().resolver.clone();
+    let source = resolver.load(url).map_err(FFIError::Error)?;
+    let mut module = compile_module(scope, url, &source)?;
+    // identity hashes are random, so a module colliding with one already
+    // loaded is compiled again rather than mistaken for it
+    while map
+        .borrow()
+        .by_hash
+        .contains_key(&module.get_identity_hash())
+    {
+        module = compile_module(scope, url, &source)?;
+    }
+    map.borrow_mut().insert(scope, context, url, module);
+    for i in 0..module.get_module_requests_length() {
+        let specifier = module.get_module_request(i).to_rust_string_lossy(scope);
+        let resolved = resolver.resolve(&specifier, url).map_err(FFIError::Error)?;
+        load_tree(scope, context, &resolved)?;
+    }
+    Ok(module)
+}
+
+/// Release the modules loaded in `context` as it is disposed.
+pub(crate) fn release_context(isolate: &mut v8::Isolate, context: &Global<v8::Context>) {
+    let mut hs = v8::HandleScope::new(isolate);
+    let scope = hs.enter();
+    let (map, context) = match (isolate_slot::<ModuleMapSlot>(scope), context.get(scope)) {
+        (Some(map), Some(context)) => (map, context),
+        _ => return,
+    };
+    let mut map = map.borrow_mut();
+    let index = match map.context_index(scope, context) {
+        Some(index) => index,
+        None => return,
+    };
+    let mut modules = map.contexts.remove(index);
+    for (_, mut module) in modules.by_url.drain() {
+        if let Some(local) = module.get(scope) {
+            map.by_hash.remove(&local.get_identity_hash());
+        }
+        module.reset(scope);
+    }
+    modules.context.reset(scope);
+}
+
+fn module_resolve_callback<'sc>(
+    context: v8::Local<'sc, v8::Context>,
+    specifier: v8::Local<'sc, v8::String>,
+    referrer: v8::Local<'sc, v8::Module>,
+) -> Option<v8::Local<'sc, v8::Module>> {
+    let mut cbs = v8::CallbackScope::new_escapable(context);
+    let mut hs = v8::EscapableHandleScope::new(cbs.enter());
+    let scope = hs.enter();
+    let map = module_map(scope).ok()?;
+    let specifier = specifier.to_rust_string_lossy(scope);
+    let resolved = {
+        let map = map.borrow();
+        let referrer = map.by_hash.get(&referrer.get_identity_hash())?;
+        map.resolver.resolve(&specifier, referrer).ok()?
+    };
+    let module = map.borrow().get(scope, context, &resolved)?;
+    Some(scope.escape(module))
+}
+
+/// Load, compile and instantiate the module tree rooted at `specifier`.
+pub fn load_module<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    specifier: &str,
+) -> Result<v8::Local<'sc, v8::Module>, FFIError> {
+    let resolver = module_map(scope)?.borrow().resolver.clone();
+    let url = resolver.resolve(specifier, "").map_err(FFIError::Error)?;
+    instantiate_tree(scope, context, &url)
+}
+
+fn instantiate_tree<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    url: &str,
+) -> Result<v8::Local<'sc, v8::Module>, FFIError> {
+    let mut module = load_tree(scope, context, url)?;
+    if module.get_status() != v8::ModuleStatus::Uninstantiated {
+        return Ok(module);
+    }
+    let mut try_catch = v8::TryCatch::new(scope);
+    let tc = try_catch.enter();
+    module.instantiate_module(context, module_resolve_callback);
+    if tc.has_caught() {
+        let exception = tc.exception().unwrap();
+        return Err(FFIError::Error(format!(
+            "failed to instantiate module {}: {}",
+            url,
+            exception_message(scope, exception)
+        )));
+    }
+    Ok(module)
+}
+
+/// Evaluate an instantiated module.
+pub fn evaluate_module<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    mut module: v8::Local<'sc, v8::Module>,
+) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+    if module.get_status() == v8::ModuleStatus::Evaluated {
+        return Ok(v8::undefined(scope).into());
+    }
+    let mut try_catch = v8::TryCatch::new(scope);
+    let tc = try_catch.enter();
+    let result = module.evaluate(scope, context);
+    if tc.has_caught() {
+        let exception = tc.exception().unwrap();
+        return Err(FFIError::Error(exception_message(scope, exception)));
+    }
+    if module.get_status() == v8::ModuleStatus::Errored {
+        let exception = module.get_exception();
+        return Err(FFIError::Error(exception_message(scope, exception)));
+    }
+    Ok(result.unwrap_or_else(|| v8::undefined(scope).into()))
+}
+
+/// Load and evaluate the module tree rooted at `specifier`.
+pub fn run_module<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    specifier: &str,
+) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+    let module = load_module(scope, context, specifier)?;
+    evaluate_module(scope, context, module)
+}
+
+extern "C" fn dynamic_import_callback(
+    context: v8::Local<v8::Context>,
+    referrer: v8::Local<v8::ScriptOrModule>,
+    specifier: v8::Local<v8::String>,
+) -> *mut v8::Promise {
+    let mut cbs = v8::CallbackScope::new_escapable(context);
+    let mut hs = v8::EscapableHandleScope::new(cbs.enter());
+    let scope = hs.enter();
+    let context = scope.get_current_context().unwrap();
+
+    let mut resolver = v8::PromiseResolver::new(scope, context).unwrap();
+    let promise = resolver.get_promise(scope);
+
+    let referrer = referrer.get_resource_name();
+    let referrer = referrer
+        .to_string(scope)
+        .map(|x| x.to_rust_string_lossy(scope))
+        .unwrap_or_default();
+    let specifier = specifier.to_rust_string_lossy(scope);
+
+    let namespace = module_map(scope)
+        .and_then(|map| {
+            let resolver = map.borrow().resolver.clone();
+            resolver
+                .resolve(&specifier, &referrer)
+                .map_err(FFIError::Error)
+        })
+        .and_then(|url| instantiate_tree(scope, context, &url))
+        .and_then(|module| {
+            evaluate_module(scope, context, module)?;
+            Ok(module.get_module_namespace())
+        });
+    match namespace {
+        Ok(namespace) => {
+            resolver.resolve(context, namespace);
+        }
+        Err(e) => {
+            let exception = e.to_exception(scope);
+            resolver.reject(context, exception);
+        }
+    }
+
+    &mut *scope.escape(promise)
+}
+
+extern "C" fn import_meta_callback(
+    context: v8::Local<v8::Context>,
+    module: v8::Local<v8::Module>,
+    meta: v8::Local<v8::Object>,
+) {
+    let mut cbs = v8::CallbackScope::new(context);
+    let mut hs = v8::HandleScope::new(cbs.enter());
+    let scope = hs.enter();
+    let url = match module_map(scope) {
+        Ok(map) => map
+            .borrow()
+            .by_hash
+            .get(&module.get_identity_hash())
+            .cloned(),
+        Err(_) => None,
+    };
+    if let Some(url) = url {
+        let key = make_str(scope, "url");
+        let value = make_str(scope, &url);
+        meta.set(context, key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FFICompat;
+
+    #[test]
+    fn relative_resolution() {
+        assert_eq!(resolve_relative("./b.js", "lib/a.js"), "lib/b.js");
+        assert_eq!(resolve_relative("../b.js", "lib/sub/a.js"), "lib/b.js");
+        assert_eq!(resolve_relative("./b.js", "a.js"), "b.js");
+        assert_eq!(resolve_relative("std/b.js", "lib/a.js"), "std/b.js");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_escape() {
+        let dir = std::env::temp_dir().join("rusty_v8_helper_module_jail");
+        let _ = fs::remove_dir_all(&dir);
+        let root = dir.join("root");
+        fs::create_dir_all(root.join("lib")).unwrap();
+        fs::write(dir.join("secret.js"), "export default 1;").unwrap();
+        fs::write(root.join("lib/a.js"), "export default 2;").unwrap();
+        std::os::unix::fs::symlink(dir.join("secret.js"), root.join("leak.js")).unwrap();
+        std::os::unix::fs::symlink(root.join("lib/a.js"), root.join("alias.js")).unwrap();
+        std::os::unix::fs::symlink(dir.join("missing.js"), root.join("dangling.js")).unwrap();
+
+        let resolver = FsModuleResolver::new(root.clone());
+        assert_eq!(
+            resolver.load("lib/a.js"),
+            Ok("export default 2;".to_string())
+        );
+        assert_eq!(
+            resolver.load("alias.js"),
+            Ok("export default 2;".to_string())
+        );
+        assert_eq!(
+            resolver.load("leak.js"),
+            Err("module path escapes root: leak.js".to_string())
+        );
+        assert_eq!(
+            resolved_jailed_path(&root, "lib/new.js").unwrap(),
+            Some(root.canonicalize().unwrap().join("lib/new.js"))
+        );
+        assert_eq!(resolved_jailed_path(&root, "dangling.js").unwrap(), None);
+        assert_eq!(resolved_jailed_path(&root, "../secret.js").unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn modules_per_context() {
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        let mut modules = MemoryModuleResolver::new();
+        modules.insert(
+            "counter.js",
+            "export let count = 0; export function bump() { return ++count; }",
+        );
+        modules.insert(
+            "main.js",
+            "import { bump } from './counter.js'; globalThis.result = `${bump()} ${import.meta.url}`;",
+        );
+        set_module_resolver(runtime.isolate(), modules);
+        let (first, mut first_context) = runtime.create_context();
+        let (_, mut second_context) = runtime.create_context();
+        for context in [&first_context, &second_context].iter() {
+            let mut hs = v8::HandleScope::new(runtime.isolate());
+            let scope = hs.enter();
+            let context = context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            run_module(scope, context, "main.js").unwrap();
+            let result = crate::util::run_script(scope, context, "result").unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context),
+                Ok("1 main.js".to_string())
+            );
+        }
+        let loaded = |runtime: &mut crate::Runtime| {
+            let mut hs = v8::HandleScope::new(runtime.isolate());
+            let scope = hs.enter();
+            let map = module_map(scope).unwrap();
+            let map = map.borrow();
+            (map.contexts.len(), map.by_hash.len())
+        };
+        assert_eq!(loaded(&mut runtime), (2, 4));
+        runtime.dispose_context(first);
+        assert_eq!(loaded(&mut runtime), (1, 2));
+        first_context.reset(runtime.isolate());
+        second_context.reset(runtime.isolate());
+    }
+}
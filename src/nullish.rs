@@ -0,0 +1,103 @@
+//! `Null` and `Undefined`, and which of the two `Option::None` converts to.
+
+use crate::util::{isolate_slot, set_isolate_slot, type_of};
+use crate::{FFICompat, FFIError};
+use rusty_v8 as v8;
+
+/// JS `null`. Converting from JS accepts nothing else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Null;
+
+/// JS `undefined`, including a missing argument. Converting from JS
+/// accepts nothing else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Undefined;
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for Null {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        _scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        if value.is_null() {
+            return Ok(Null);
+        }
+        Err(FFIError::TypeError(format!(
+            "invalid type for argument in ffi call, expected null, received {}",
+            type_of(value)
+        )))
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        Ok(v8::null(scope).into())
+    }
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for Undefined {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        _scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        if value.is_undefined() {
+            return Ok(Undefined);
+        }
+        Err(FFIError::TypeError(format!(
+            "invalid type for argument in ffi call, expected undefined, received {}",
+            type_of(value)
+        )))
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        Ok(v8::undefined(scope).into())
+    }
+}
+
+/// What `Option::None` and an empty `Lenient` convert to in JS. Both
+/// `null` and `undefined` convert from JS to `None` either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoneValue {
+    Null,
+    Undefined,
+}
+
+impl Default for NoneValue {
+    fn default() -> NoneValue {
+        NoneValue::Null
+    }
+}
+
+struct NoneValueSlot(NoneValue);
+
+/// Set what `None` converts to in this isolate.
+pub fn set_none_value(scope: &mut impl v8::InIsolate, value: NoneValue) {
+    set_isolate_slot(scope, NoneValueSlot(value));
+}
+
+/// What `None` converts to in this isolate, `NoneValue::Null` if it was not
+/// set.
+pub fn none_value(scope: &mut impl v8::InIsolate) -> NoneValue {
+    isolate_slot::<NoneValueSlot>(scope)
+        .map(|slot| slot.0)
+        .unwrap_or_default()
+}
+
+/// `None` as a JS value, following the isolate's `NoneValue`.
+pub(crate) fn none_to_value<'sc>(scope: &mut impl v8::ToLocal<'sc>) -> v8::Local<'sc, v8::Value> {
+    match none_value(scope) {
+        NoneValue::Null => v8::null(scope).into(),
+        NoneValue::Undefined => v8::undefined(scope).into(),
+    }
+}
@@ -0,0 +1,358 @@
+use crate::FFICompat;
+use crate::FFIError;
+use rusty_v8 as v8;
+use std::ops::Deref;
+
+/// Numeric types that validating newtypes such as `Finite` and `Positive`
+/// can be built over.
+pub trait FFINumber: Sized + Copy {
+    /// Name used in range error messages.
+    const NAME: &'static str;
+
+    /// Converts a JS number into `Self`, returning `None` if it cannot be
+    /// represented exactly (out of range, or fractional for integers).
+    fn from_f64(value: f64) -> Option<Self>;
+
+    fn to_f64(self) -> f64;
+}
+
+impl FFINumber for f64 {
+    const NAME: &'static str = "f64";
+
+    fn from_f64(value: f64) -> Option<Self> {
+        Some(value)
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+impl FFINumber for f32 {
+    const NAME: &'static str = "f32";
+
+    fn from_f64(value: f64) -> Option<Self> {
+        if value.is_finite() && value.abs() > f32::MAX as f64 {
+            return None;
+        }
+        Some(value as f32)
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+macro_rules! ffi_number_int {
+    ($ty:ty, $name:expr) => {
+        impl FFINumber for $ty {
+            const NAME: &'static str = $name;
+
+            fn from_f64(value: f64) -> Option<Self> {
+                // `MAX as f64` rounds up to a power of two for 64 bit types,
+                // which is then out of range itself
+                if value.fract() != 0.0
+                    || value < <$ty>::MIN as f64
+                    || value >= <$ty>::MAX as f64 + 1.0
+                {
+                    return None;
+                }
+                Some(value as $ty)
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        }
+    };
+}
+
+ffi_number_int!(i32, "i32");
+ffi_number_int!(u32, "u32");
+ffi_number_int!(i64, "i64");
+ffi_number_int!(u64, "u64");
+
+fn number_from_value<'sc, 'c>(
+    value: v8::Local<'sc, v8::Value>,
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+) -> Result<f64, FFIError> {
+    f64::from_value(value, scope, context).map_err(FFIError::TypeError)
+}
+
+fn checked<T: FFINumber>(value: f64, requirement: &str) -> Result<T, FFIError> {
+    T::from_f64(value).ok_or_else(|| {
+        FFIError::RangeError(format!(
+            "{} is out of range for {}, expected {}",
+            value,
+            T::NAME,
+            requirement
+        ))
+    })
+}
+
+/// A number that must not be `NaN` or infinite.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Finite<T: FFINumber>(pub T);
+
+/// A number that must be finite and strictly greater than zero.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Positive<T: FFINumber>(pub T);
+
+/// A number that must be finite and greater than or equal to zero.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct NonNegative<T: FFINumber>(pub T);
+
+/// A finite number between `0` and `100` inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Percentage(pub f64);
+
+impl Percentage {
+    /// The percentage as a fraction between `0` and `1`.
+    pub fn fraction(&self) -> f64 {
+        self.0 / 100.0
+    }
+}
+
+macro_rules! ffi_number_newtype {
+    ($name:ident, $requirement:expr, $check:expr) => {
+        impl<T: FFINumber> Deref for $name<T> {
+            type Target = T;
+
+            fn deref(&self) -> &T {
+                &self.0
+            }
+        }
+
+        impl<'sc, 'c, T: FFINumber> FFICompat<'sc, 'c> for $name<T> {
+            type E = FFIError;
+
+            fn from_value(
+                value: v8::Local<'sc, v8::Value>,
+                scope: &mut impl v8::ToLocal<'sc>,
+                context: v8::Local<'c, v8::Context>,
+            ) -> Result<Self, FFIError> {
+                let value = number_from_value(value, scope, context)?;
+                let check: fn(f64) -> bool = $check;
+                if !check(value) {
+                    return Err(FFIError::RangeError(format!(
+                        "{} is out of range, expected {}",
+                        value, $requirement
+                    )));
+                }
+                Ok($name(checked(value, $requirement)?))
+            }
+
+            fn to_value(
+                self,
+                scope: &mut impl v8::ToLocal<'sc>,
+                context: v8::Local<'c, v8::Context>,
+            ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+                self.0
+                    .to_f64()
+                    .to_value(scope, context)
+                    .map_err(FFIError::TypeError)
+            }
+        }
+    };
+}
+
+ffi_number_newtype!(Finite, "a finite number", |x| x.is_finite());
+ffi_number_newtype!(Positive, "a finite number greater than 0", |x| {
+    x.is_finite() && x > 0.0
+});
+ffi_number_newtype!(
+    NonNegative,
+    "a finite number greater than or equal to 0",
+    |x| x.is_finite() && x >= 0.0
+);
+
+impl Deref for Percentage {
+    type Target = f64;
+
+    fn deref(&self) -> &f64 {
+        &self.0
+    }
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for Percentage {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        let value = number_from_value(value, scope, context)?;
+        if !value.is_finite() || value < 0.0 || value > 100.0 {
+            return Err(FFIError::RangeError(format!(
+                "{} is out of range, expected a percentage between 0 and 100",
+                value
+            )));
+        }
+        Ok(Percentage(value))
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        self.0.to_value(scope, context).map_err(FFIError::TypeError)
+    }
+}
+
+/// An index into a slice or string: a non-negative integer no greater than
+/// `Number.MAX_SAFE_INTEGER`. Use the checked accessors rather than
+/// indexing with it directly, so that an out-of-bounds index throws a
+/// `RangeError` instead of panicking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Index(pub usize);
+
+/// `Number.MAX_SAFE_INTEGER`
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+impl Index {
+    /// Check that the index is within `len`, returning it.
+    pub fn check_bounds(self, len: usize) -> Result<usize, FFIError> {
+        if self.0 >= len {
+            return Err(FFIError::RangeError(format!(
+                "index {} is out of bounds for length {}",
+                self.0, len
+            )));
+        }
+        Ok(self.0)
+    }
+
+    /// Check that the index is at most `len`, i.e. to insert at, returning
+    /// it.
+    pub fn check_insert(self, len: usize) -> Result<usize, FFIError> {
+        if self.0 > len {
+            return Err(FFIError::RangeError(format!(
+                "index {} is out of bounds for insertion into length {}",
+                self.0, len
+            )));
+        }
+        Ok(self.0)
+    }
+
+    pub fn checked_get<T>(self, slice: &[T]) -> Result<&T, FFIError> {
+        Ok(&slice[self.check_bounds(slice.len())?])
+    }
+
+    pub fn checked_get_mut<T>(self, slice: &mut [T]) -> Result<&mut T, FFIError> {
+        let index = self.check_bounds(slice.len())?;
+        Ok(&mut slice[index])
+    }
+}
+
+impl Deref for Index {
+    type Target = usize;
+
+    fn deref(&self) -> &usize {
+        &self.0
+    }
+}
+
+impl From<Index> for usize {
+    fn from(index: Index) -> usize {
+        index.0
+    }
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for Index {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        let value = number_from_value(value, scope, context)?;
+        if value.fract() != 0.0 || value < 0.0 || value > MAX_SAFE_INTEGER {
+            return Err(FFIError::RangeError(format!(
+                "{} is not a valid index, expected an integer between 0 and {}",
+                value, MAX_SAFE_INTEGER
+            )));
+        }
+        if value > usize::MAX as f64 {
+            return Err(FFIError::RangeError(format!(
+                "index {} is out of range for usize",
+                value
+            )));
+        }
+        Ok(Index(value as usize))
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        (self.0 as f64)
+            .to_value(scope, context)
+            .map_err(FFIError::TypeError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+    use rusty_v8_helper_derive::v8_ffi;
+
+    #[v8_ffi]
+    fn test_ffi_index(items: Vec<String>, index: crate::Index) -> Result<String, crate::FFIError> {
+        index.checked_get(&items).cloned()
+    }
+
+    #[test]
+    fn index_conversion() {
+        with_context!([test_ffi_index], |scope, context| {
+            let result = run_script(
+                scope,
+                context,
+                r#"
+                [1, 3, -1, 1.5, NaN, 2 ** 53].map(index => {
+                    try {
+                        return test_ffi_index(['a', 'b', 'c'], index);
+                    } catch (e) {
+                        return `${e.name}: ${e.message}`;
+                    }
+                }).join('\n')
+                "#,
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context).unwrap(),
+                "b\n\
+                 RangeError: index 3 is out of bounds for length 3\n\
+                 RangeError: -1 is not a valid index, expected an integer between 0 and 9007199254740991\n\
+                 RangeError: 1.5 is not a valid index, expected an integer between 0 and 9007199254740991\n\
+                 RangeError: NaN is not a valid index, expected an integer between 0 and 9007199254740991\n\
+                 RangeError: 9007199254740992 is not a valid index, expected an integer between 0 and 9007199254740991"
+            );
+        });
+        assert_eq!(crate::Index(2).check_insert(2), Ok(2));
+        assert!(crate::Index(3).check_insert(2).is_err());
+        let mut items = vec![1, 2];
+        *crate::Index(1).checked_get_mut(&mut items).unwrap() = 5;
+        assert_eq!(items, vec![1, 5]);
+    }
+
+    #[test]
+    fn integer_bounds() {
+        let two_63 = 2f64.powi(63);
+        let two_64 = 2f64.powi(64);
+        assert_eq!(i64::from_f64(two_63), None);
+        assert_eq!(i64::from_f64(-two_63), Some(i64::MIN));
+        assert_eq!(i64::from_f64(two_63 - 1024.0), Some(i64::MAX - 1023));
+        assert_eq!(u64::from_f64(two_63), Some(1 << 63));
+        assert_eq!(u64::from_f64(two_64), None);
+        assert_eq!(u64::from_f64(two_64 - 2048.0), Some(u64::MAX - 2047));
+        assert_eq!(u32::from_f64(4294967295.0), Some(u32::MAX));
+        assert_eq!(u32::from_f64(4294967296.0), None);
+        assert_eq!(i32::from_f64(2147483648.0), None);
+        assert_eq!(i32::from_f64(-2147483648.0), Some(i32::MIN));
+    }
+}
@@ -1,20 +1,23 @@
+use crate::affinity::Affinity;
+use crate::shim::{self, internal_field_ptr, set_internal_field_ptr, IsolateRef, WeakOwner};
+use crate::FFIError;
 use rusty_v8 as v8;
 use std::any::Any;
 use std::any::TypeId;
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ffi::c_void;
+use std::fmt;
 use std::hash::Hash;
 use std::hash::Hasher;
-use std::ptr::NonNull;
+use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
+use std::sync::{Mutex, MutexGuard, TryLockError};
 use v8::Global;
 use v8::InIsolate;
-use v8::Isolate;
-use v8::IsolateHandle;
 use v8::Local;
 use v8::Object;
 use v8::ToLocal;
-use v8::{WeakCallback, Weakable};
 
 /// `ObjectWrap` is a non-standard helper to match arbitrary Rust objects
 /// to arbitrary JS objects within V8.
@@ -33,29 +36,34 @@ use v8::{WeakCallback, Weakable};
 /// In order for the V8 GC to track this object to be deallocated is to call
 /// `ObjectWrap::make_weak`. You can disable GC tracking with
 /// `ObjectWrap::clear_weak`.
+///
+/// With the `upstream-v8` feature there are no weak handles, so
+/// `ObjectWrap::make_weak` panics, and the wrapped `T` is released as the
+/// last clone of the `ObjectWrap` is dropped, see `shim`.
+///
+/// Like the isolate, an `ObjectWrap` belongs to the thread it was created
+/// on; debug builds panic when it is used on another.
 #[derive(Clone)]
 pub struct ObjectWrap<T: Any + 'static>(Rc<ObjectWrapInternal<T>>);
 
 struct ObjectWrapInternal<T: Any + 'static> {
     handle: RefCell<Option<Global<Object>>>,
     wrapping: RefCell<Option<*const T>>,
-    v8_reference: RefCell<Option<*const Self>>,
-    isolate_handle: IsolateHandle,
+    affinity: Affinity,
+    isolate: IsolateRef,
 }
 
-unsafe impl<T: 'static, Y: Any + 'static> Weakable<T> for ObjectWrapInternal<Y> {
-    fn get(self: Rc<Self>, _global: &Global<T>) -> NonNull<c_void> {
-        let v8_reference = Rc::into_raw(self.clone());
-        assert_eq!(self.v8_reference.replace(Some(v8_reference)), None);
-        unsafe { NonNull::new_unchecked(v8_reference as *mut libc::c_void) }
-    }
-
-    fn clear(&self, _global: &Global<T>) {
-        unsafe { Rc::from_raw(self.v8_reference.borrow_mut().take().unwrap()) };
-    }
-
-    fn get_callback(&self, _global: &Global<T>) -> WeakCallback<c_void> {
-        wrap_weak_callback::<Y>
+impl<T: Any + 'static> WeakOwner for ObjectWrapInternal<T> {
+    fn collected(&self, isolate: &mut v8::Isolate) {
+        let handle = match self.handle.borrow_mut().take() {
+            Some(handle) => handle,
+            None => return,
+        };
+        shim::release_collected(handle, isolate);
+        let ref_ptr = self.wrapping.borrow_mut().take();
+        if let Some(ref_ptr) = ref_ptr {
+            drop(unsafe { Rc::from_raw(ref_ptr) });
+        }
     }
 }
 
@@ -87,6 +95,78 @@ fn type_id_to_u64<T: Any + 'static>() -> u64 {
     hasher.finish() & (!1_u64) // must be 2 byte aligned
 }
 
+thread_local! {
+    // type tags of wrapped types, to name the type found in `WrapError`
+    static TYPE_NAMES: RefCell<HashMap<u64, &'static str>> = RefCell::new(HashMap::new());
+}
+
+type WrapCast = Rc<dyn Fn(*const c_void) -> Box<dyn Any>>;
+
+thread_local! {
+    // casts from (wrapped type tag, requested type tag), see `ObjectWrap::register_cast`
+    static WRAP_CASTS: RefCell<HashMap<(u64, u64), WrapCast>> = RefCell::new(HashMap::new());
+}
+
+/// `WrapError` is why `ObjectWrap::try_from_object` could not resolve an
+/// `Object` to the wrapped value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WrapError {
+    /// The object was not created by `ObjectWrap`.
+    NotWrapped,
+    /// The object wraps a value of another type.
+    WrongType {
+        expected: String,
+        /// The name of the wrapped type, or its type tag if it was wrapped
+        /// on another thread.
+        found: String,
+    },
+    /// The object's `ObjectWrap` was dropped, releasing the wrapped value.
+    Neutered { type_name: &'static str },
+}
+
+impl fmt::Display for WrapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WrapError::NotWrapped => write!(f, "not a wrapped object"),
+            WrapError::WrongType { expected, found } => write!(
+                f,
+                "wrapped object has the wrong type (expected {}, found {})",
+                expected, found
+            ),
+            WrapError::Neutered { type_name } => write!(
+                f,
+                "wrapped {} was already released by its ObjectWrap",
+                type_name
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WrapError {}
+
+impl From<WrapError> for FFIError {
+    fn from(error: WrapError) -> FFIError {
+        FFIError::TypeError(error.to_string())
+    }
+}
+
+/// The name of the Rust type wrapped by `object`, if it was created by
+/// `ObjectWrap`, or its type tag if it was wrapped on another thread.
+pub(crate) fn wrapped_type_name(object: Local<Object>) -> Option<String> {
+    if object.internal_field_count() != 2 {
+        return None;
+    }
+    let type_id = unsafe { internal_field_ptr::<c_void>(object, 0) } as usize as u64;
+    if type_id == 0 {
+        return None;
+    }
+    let name = TYPE_NAMES.with(|names| names.borrow().get(&type_id).copied());
+    Some(match name {
+        Some(name) => name.to_string(),
+        None => format!("tag {:#x}", type_id),
+    })
+}
+
 impl<T: Any + 'static> ObjectWrap<T> {
     /// Create a new `ObjectWrap` from a given scope, an `Object` that
     /// has exactly 1 allocated internal fields through
@@ -106,17 +186,28 @@ impl<T: Any + 'static> ObjectWrap<T> {
         wrap: Rc<T>,
     ) -> ObjectWrap<T> {
         assert_eq!(object.internal_field_count(), 2);
+        TYPE_NAMES.with(|names| {
+            names
+                .borrow_mut()
+                .insert(type_id_to_u64::<T>(), std::any::type_name::<T>())
+        });
         let wrap = Rc::into_raw(wrap);
-        unsafe { object.set_internal_field_ptr(0, type_id_to_u64::<T>() as usize as *mut c_void) };
-        unsafe { object.set_internal_field_ptr(1, wrap as *mut T) };
+        unsafe {
+            set_internal_field_ptr(
+                &mut object,
+                0,
+                type_id_to_u64::<T>() as usize as *mut c_void,
+            )
+        };
+        unsafe { set_internal_field_ptr(&mut object, 1, wrap as *mut T) };
         let mut global = Global::new_from(scope, object);
         let wrapper = ObjectWrap(Rc::new(ObjectWrapInternal {
             handle: RefCell::new(None),
             wrapping: RefCell::new(Some(wrap)),
-            v8_reference: RefCell::new(None),
-            isolate_handle: IsolateHandle::new(scope.isolate()),
+            affinity: Affinity::current(),
+            isolate: IsolateRef::new(scope),
         }));
-        global.set_weakable(wrapper.0.clone());
+        shim::set_weak_owner(&mut global, wrapper.0.clone());
         wrapper.0.handle.replace(Some(global));
         wrapper
     }
@@ -125,31 +216,96 @@ impl<T: Any + 'static> ObjectWrap<T> {
     ///
     /// Otherwise, returns None.
     pub fn from_object(object: Local<Object>) -> Option<Rc<T>> {
+        ObjectWrap::try_from_object(object).ok()
+    }
+
+    /// Resolves an arbitrary `Object` to a `std::rc::Rc<T>`, or a `WrapError`
+    /// saying why it could not.
+    pub fn try_from_object(object: Local<Object>) -> Result<Rc<T>, WrapError> {
         if object.internal_field_count() != 2 {
-            return None;
+            return Err(WrapError::NotWrapped);
+        }
+        let expected_type_id = type_id_to_u64::<T>();
+        let actual_type_id = unsafe { internal_field_ptr::<c_void>(object, 0) } as usize as u64;
+        if actual_type_id == 0 {
+            return Err(WrapError::NotWrapped);
         }
-        let expected_type_id = type_id_to_u64::<T>() as usize;
-        let actual_type_id = unsafe { object.get_internal_field_ptr::<c_void>(0) } as usize;
         if expected_type_id != actual_type_id {
-            return None;
+            let cast = WRAP_CASTS.with(|casts| {
+                casts
+                    .borrow()
+                    .get(&(actual_type_id, expected_type_id))
+                    .cloned()
+            });
+            if let Some(cast) = cast {
+                let raw_ptr = unsafe { internal_field_ptr::<c_void>(object, 1) };
+                if raw_ptr.is_null() {
+                    return Err(WrapError::Neutered {
+                        type_name: std::any::type_name::<T>(),
+                    });
+                }
+                let cast = cast(raw_ptr);
+                return Ok(Rc::new(*cast.downcast::<T>().unwrap()));
+            }
+            let found = TYPE_NAMES.with(|names| names.borrow().get(&actual_type_id).copied());
+            return Err(WrapError::WrongType {
+                expected: std::any::type_name::<T>().to_string(),
+                found: match found {
+                    Some(name) => name.to_string(),
+                    None => format!("tag {:#x}", actual_type_id),
+                },
+            });
+        }
+        let raw_ptr = unsafe { internal_field_ptr::<T>(object, 1) };
+        if raw_ptr.is_null() {
+            return Err(WrapError::Neutered {
+                type_name: std::any::type_name::<T>(),
+            });
         }
-        let raw_ptr = unsafe { object.get_internal_field_ptr::<T>(1) };
         let temp_rc = unsafe { Rc::from_raw(raw_ptr as *const T) };
         let new_rc = temp_rc.clone();
         Rc::into_raw(temp_rc);
-        Some(new_rc)
+        Ok(new_rc)
+    }
+
+    /// Let objects wrapping a `C` resolve as a `T` through `cast`, in
+    /// `try_from_object` and for `this` in `v8_ffi` fns.
+    ///
+    /// This is how heterogeneous objects sharing an interface are passed to
+    /// the same fns: with `T` as `Rc<dyn Trait>`, a `v8_ffi` fn taking
+    /// `this: &dyn Trait` accepts objects wrapping any registered `C`, as
+    /// well as objects wrapping an `Rc<dyn Trait>` directly.
+    /// ```ignore
+    /// ObjectWrap::<Rc<dyn Shape>>::register_cast(|x: Rc<Circle>| -> Rc<dyn Shape> { x });
+    /// ```
+    /// Casts are registered per thread.
+    pub fn register_cast<C: Any + 'static>(cast: fn(Rc<C>) -> T) {
+        let erased = move |ptr: *const c_void| -> Box<dyn Any> {
+            let wrapped = unsafe { Rc::from_raw(ptr as *const C) };
+            let out = cast(wrapped.clone());
+            Rc::into_raw(wrapped);
+            Box::new(out)
+        };
+        WRAP_CASTS.with(|casts| {
+            casts.borrow_mut().insert(
+                (type_id_to_u64::<C>(), type_id_to_u64::<T>()),
+                Rc::new(erased),
+            )
+        });
     }
 
     /// Get the underlying `Object` that is represented by this `ObjectWrap`.
     pub fn get<'sc>(&self, scope: &mut impl ToLocal<'sc>) -> Option<Local<'sc, Object>> {
+        self.0.affinity.check("ObjectWrap");
         self.0.handle.borrow().as_ref()?.get(scope)
     }
 
     /// Unwrap a `std::rc::Rc<T>` wrapped by this `ObjectWrap`.
     pub fn unwrap<'sc>(&self, scope: &mut impl ToLocal<'sc>) -> Option<Rc<T>> {
+        self.0.affinity.check("ObjectWrap");
         let object = self.0.handle.borrow().as_ref()?.get(scope)?;
 
-        let wrapped_ptr = unsafe { object.get_internal_field_ptr(1) } as *const T;
+        let wrapped_ptr = unsafe { internal_field_ptr(object, 1) } as *const T;
         let rc = unsafe { Rc::from_raw(wrapped_ptr) };
         let new_rc = rc.clone();
         Rc::into_raw(rc);
@@ -160,24 +316,27 @@ impl<T: Any + 'static> ObjectWrap<T> {
     /// Note that existing references to the `T` previously in this `ObjectWrap`
     /// will continue to hold onto the value through a reference counter.
     pub fn swap<'sc>(&mut self, scope: &mut impl ToLocal<'sc>, wrap: T) -> Option<Rc<T>> {
+        self.0.affinity.check("ObjectWrap");
         let mut object = self.0.handle.borrow().as_ref()?.get(scope)?;
         if object.internal_field_count() != 2 {
             return None;
         }
 
-        let wrapped_ptr = unsafe { object.get_internal_field_ptr(1) } as *mut T;
+        let wrapped_ptr = unsafe { internal_field_ptr(object, 1) } as *mut T;
         let wrapped = unsafe { Rc::from_raw(wrapped_ptr) };
         let new_ptr = Rc::into_raw(Rc::new(wrap));
         self.0.wrapping.replace(Some(new_ptr));
-        unsafe { object.set_internal_field_ptr(1, new_ptr as *mut T) }
+        unsafe { set_internal_field_ptr(&mut object, 1, new_ptr as *mut T) }
 
         Some(wrapped)
     }
 
     /// Enable V8 GC to collect the `Object` represented by this `ObjectWrap`.
+    ///
+    /// Panics with the `upstream-v8` feature, which has no weak handles.
     pub fn make_weak(&mut self) {
         if let Some(global) = self.0.handle.borrow_mut().as_mut() {
-            global.set_weak();
+            shim::set_weak(global);
         }
     }
 
@@ -185,10 +344,9 @@ impl<T: Any + 'static> ObjectWrap<T> {
     ///
     /// `false` if the object has been deallocated.
     pub fn is_weak(&self) -> bool {
-        if let Some(global) = self.0.handle.borrow_mut().as_mut() {
-            global.is_weak()
-        } else {
-            false
+        match self.0.handle.borrow_mut().as_mut() {
+            Some(global) => shim::is_weak(global),
+            None => false,
         }
     }
 
@@ -196,7 +354,7 @@ impl<T: Any + 'static> ObjectWrap<T> {
     /// `ObjectWrap`.
     pub fn clear_weak(&mut self) {
         if let Some(global) = self.0.handle.borrow_mut().as_mut() {
-            global.clear_weak();
+            shim::clear_weak(global);
         }
     }
 
@@ -209,52 +367,92 @@ impl<T: Any + 'static> ObjectWrap<T> {
 
 impl<T> Drop for ObjectWrapInternal<T> {
     fn drop(&mut self) {
-        let isolate = unsafe { self.isolate_handle.get_isolate_ptr().as_mut() };
-        if isolate.is_none() {
-            return;
+        let mut handle = match self.handle.borrow_mut().take() {
+            Some(handle) => handle,
+            None => return,
+        };
+        // without the isolate, the handle can only be leaked
+        let isolate = match unsafe { self.isolate.isolate_ptr().as_mut() } {
+            Some(isolate) => isolate,
+            None => {
+                std::mem::forget(handle);
+                return;
+            }
+        };
+        if shim::is_weak(&mut handle) {
+            shim::clear_weak(&mut handle);
         }
-        let isolate = isolate.unwrap();
-        let handle = &mut self.handle.borrow_mut();
-        if handle.is_none() {
-            return;
-        }
-        let mut handle = handle.take().unwrap();
-        if handle.is_weak() {
-            handle.clear_weak();
+        {
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            if let Some(mut object) = handle.get(scope) {
+                let wrapped_ptr = unsafe { internal_field_ptr(object, 1) } as *mut T;
+                // the object outlives its wrapper, so later lookups must see it neutered
+                unsafe { set_internal_field_ptr(&mut object, 1, std::ptr::null_mut::<T>()) };
+                self.wrapping.borrow_mut().take();
+                unsafe { Rc::from_raw(wrapped_ptr) };
+            }
         }
-        let object = handle.get_isolate(isolate);
-        if object.is_none() {
-            return;
+        handle.reset(isolate);
+    }
+}
+
+thread_local! {
+    static LOCKED_THIS: RefCell<HashMap<usize, &'static str>> = RefCell::new(HashMap::new());
+}
+
+/// `ThisGuard` is the `this: &mut T` of a `v8_ffi` call, locked for the
+/// duration of the call.
+///
+/// If the call re-enters JS, which then calls another `&mut` method on the
+/// same object, the inner call fails with a JS `Error` naming both
+/// functions instead of deadlocking.
+pub struct ThisGuard<'a, T> {
+    guard: MutexGuard<'a, T>,
+    key: usize,
+}
+
+impl<'a, T> ThisGuard<'a, T> {
+    /// Lock `this` for the `v8_ffi` fn `function`.
+    pub fn lock(this: &'a Rc<Mutex<T>>, function: &'static str) -> Result<Self, FFIError> {
+        let key = &**this as *const Mutex<T> as usize;
+        match this.try_lock() {
+            Ok(guard) => {
+                LOCKED_THIS.with(|locked| locked.borrow_mut().insert(key, function));
+                Ok(ThisGuard { guard, key })
+            }
+            Err(TryLockError::WouldBlock) => {
+                let holder = LOCKED_THIS.with(|locked| locked.borrow().get(&key).copied());
+                Err(FFIError::Error(format!(
+                    "reentrant ffi call: {} was called on an object that is still in use by {}, which called back into JS",
+                    function,
+                    holder.unwrap_or("another ffi call"),
+                )))
+            }
+            Err(TryLockError::Poisoned(_)) => Err(FFIError::Error(format!(
+                "{} was called on an object poisoned by a panic in an earlier ffi call",
+                function
+            ))),
         }
-        let object = object.unwrap();
-        let wrapped_ptr = unsafe { object.get_internal_field_ptr(1) } as *mut T;
-        self.wrapping.borrow_mut().take();
-        unsafe { Rc::from_raw(wrapped_ptr) };
     }
 }
 
-extern "C" fn wrap_weak_callback<T: 'static>(
-    value: NonNull<c_void>,
-    mut isolate: NonNull<Isolate>,
-) {
-    let this = unsafe {
-        (&value as *const NonNull<c_void> as *mut NonNull<ObjectWrapInternal<T>>)
-            .as_mut()
-            .unwrap()
-            .as_ref()
-    };
-    let this = unsafe { Rc::from_raw(this) };
-    let isolate = unsafe { isolate.as_mut() };
-
-    let mut handle = this.handle.borrow_mut();
-    if handle.is_none() {
-        return;
+impl<'a, T> Deref for ThisGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
     }
-    let mut handle = handle.take().unwrap();
-    handle.set_isolate(isolate, None);
+}
 
-    let ref_ptr = this.wrapping.borrow_mut().take();
-    if let Some(ref_ptr) = ref_ptr {
-        drop(unsafe { Rc::from_raw(ref_ptr) });
+impl<'a, T> DerefMut for ThisGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<'a, T> Drop for ThisGuard<'a, T> {
+    fn drop(&mut self) {
+        LOCKED_THIS.with(|locked| locked.borrow_mut().remove(&self.key));
     }
 }
@@ -0,0 +1,259 @@
+//! `FfiFrom` and `FfiInto`, conversions for owned types, whose values hold
+//! no `Local`s, without the lifetimes of `FFICompat`:
+//!
+//! ```ignore
+//! impl FfiFrom for Celsius {
+//!     fn ffi_from<'sc>(
+//!         value: v8::Local<'sc, v8::Value>,
+//!         scope: &mut impl v8::ToLocal<'sc>,
+//!         context: v8::Local<v8::Context>,
+//!     ) -> Result<Celsius, FFIError> {
+//!         Ok(Celsius(f64::from_value(value, scope, context)?))
+//!     }
+//! }
+//! ```
+//!
+//! A type implementing both is `FFICompat` too, so it can be nested, i.e.
+//! in a `Vec`. `v8_ffi` fns convert arguments with `FfiFrom` and return
+//! values with `FfiInto` when their types implement it, and with
+//! `FFICompat` otherwise, so a type taken or returned only needs the one
+//! it is used with.
+
+use crate::{FFICompat, FFIError};
+use rusty_v8 as v8;
+use std::marker::PhantomData;
+
+/// Conversion of an owned type from JS.
+pub trait FfiFrom: Sized {
+    fn ffi_from<'sc>(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+    ) -> Result<Self, FFIError>;
+}
+
+/// Conversion of an owned type to JS.
+pub trait FfiInto {
+    fn ffi_into<'sc>(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError>;
+}
+
+impl<'sc, 'c, T: FfiFrom + FfiInto> FFICompat<'sc, 'c> for T {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        T::ffi_from(value, scope, context)
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        self.ffi_into(scope, context)
+    }
+}
+
+/// Picks the conversion of `T` for the generated `v8_ffi` trampolines:
+/// methods of `Via*Owned`, implemented for `Dispatch<T>`, are found before
+/// those of `Via*Compat`, implemented for `&Dispatch<T>`, when called on a
+/// `&Dispatch<T>`.
+#[doc(hidden)]
+pub struct Dispatch<T>(PhantomData<T>);
+
+impl<T> Default for Dispatch<T> {
+    fn default() -> Dispatch<T> {
+        Dispatch(PhantomData)
+    }
+}
+
+impl<T> Dispatch<T> {
+    /// The dispatch of the type of `value`.
+    pub fn of(_value: &T) -> Dispatch<T> {
+        Dispatch(PhantomData)
+    }
+}
+
+#[doc(hidden)]
+pub trait ViaFromOwned<T> {
+    fn from_js<'sc>(
+        &self,
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+    ) -> Result<T, FFIError>;
+}
+
+impl<T: FfiFrom> ViaFromOwned<T> for Dispatch<T> {
+    fn from_js<'sc>(
+        &self,
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+    ) -> Result<T, FFIError> {
+        T::ffi_from(value, scope, context)
+    }
+}
+
+#[doc(hidden)]
+pub trait ViaFromCompat<'sc, 'c, T: FFICompat<'sc, 'c>> {
+    fn from_js(
+        &self,
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<T, T::E>;
+}
+
+impl<'sc, 'c, T: FFICompat<'sc, 'c>> ViaFromCompat<'sc, 'c, T> for &Dispatch<T> {
+    fn from_js(
+        &self,
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<T, T::E> {
+        T::from_value(value, scope, context)
+    }
+}
+
+#[doc(hidden)]
+pub trait ViaIntoOwned<T> {
+    fn to_js<'sc>(
+        &self,
+        value: T,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError>;
+}
+
+impl<T: FfiInto> ViaIntoOwned<T> for Dispatch<T> {
+    fn to_js<'sc>(
+        &self,
+        value: T,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        value.ffi_into(scope, context)
+    }
+}
+
+#[doc(hidden)]
+pub trait ViaIntoCompat<'sc, 'c, T: FFICompat<'sc, 'c>> {
+    fn to_js(
+        &self,
+        value: T,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, T::E>;
+}
+
+impl<'sc, 'c, T: FFICompat<'sc, 'c>> ViaIntoCompat<'sc, 'c, T> for &Dispatch<T> {
+    fn to_js(
+        &self,
+        value: T,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, T::E> {
+        value.to_value(scope, context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+    use rusty_v8_helper_derive::v8_ffi;
+
+    /// Converted from and to JS as a number of degrees.
+    struct TestCelsius(f64);
+
+    impl crate::FfiFrom for TestCelsius {
+        fn ffi_from<'sc>(
+            value: v8::Local<'sc, v8::Value>,
+            scope: &mut impl v8::ToLocal<'sc>,
+            context: v8::Local<v8::Context>,
+        ) -> Result<TestCelsius, crate::FFIError> {
+            let degrees = f64::from_value(value, scope, context)?;
+            if degrees < -273.15 {
+                return Err(crate::FFIError::RangeError(format!(
+                    "{} is below absolute zero",
+                    degrees
+                )));
+            }
+            Ok(TestCelsius(degrees))
+        }
+    }
+
+    impl crate::FfiInto for TestCelsius {
+        fn ffi_into<'sc>(
+            self,
+            scope: &mut impl v8::ToLocal<'sc>,
+            context: v8::Local<v8::Context>,
+        ) -> Result<v8::Local<'sc, v8::Value>, crate::FFIError> {
+            Ok(self.0.to_value(scope, context)?)
+        }
+    }
+
+    /// Only taken by `v8_ffi` fns, so converted with `FfiFrom` alone.
+    struct TestUnit(&'static str);
+
+    impl crate::FfiFrom for TestUnit {
+        fn ffi_from<'sc>(
+            value: v8::Local<'sc, v8::Value>,
+            scope: &mut impl v8::ToLocal<'sc>,
+            context: v8::Local<v8::Context>,
+        ) -> Result<TestUnit, crate::FFIError> {
+            match String::from_value(value, scope, context)?.as_str() {
+                "C" => Ok(TestUnit("C")),
+                "F" => Ok(TestUnit("F")),
+                unit => Err(crate::FFIError::TypeError(format!("unknown unit {}", unit))),
+            }
+        }
+    }
+
+    #[v8_ffi]
+    fn test_ffi_owned(readings: Vec<TestCelsius>, unit: TestUnit) -> TestCelsius {
+        let mean = readings.iter().map(|x| x.0).sum::<f64>() / readings.len() as f64;
+        match unit.0 {
+            "F" => TestCelsius(mean * 9.0 / 5.0 + 32.0),
+            _ => TestCelsius(mean),
+        }
+    }
+
+    #[test]
+    fn owned_conversion() {
+        with_context!([test_ffi_owned], |scope, context| {
+            let result = run_script(
+                scope,
+                context,
+                r#"
+                const attempt = (f) => {
+                    try {
+                        return String(f());
+                    } catch (e) {
+                        return `${e.name}: ${e.message}`;
+                    }
+                };
+                [
+                    () => test_ffi_owned([10, 20], 'C'),
+                    () => test_ffi_owned([10, 20], 'F'),
+                    () => test_ffi_owned([10, -300], 'C'),
+                    () => test_ffi_owned([10], 'K'),
+                ].map(attempt).join('\n')
+                "#,
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context).unwrap(),
+                "15\n59\nRangeError: value[1]: -300 is below absolute zero\nTypeError: unknown unit K"
+            );
+        });
+    }
+}
@@ -0,0 +1,248 @@
+//! Per-context permission checks of `v8_ffi` calls. The generated
+//! trampolines ask the `PolicyHook` of the calling context, if it has one,
+//! before converting any arguments, and throw a `PermissionError` if it
+//! denies the call.
+
+use crate::instrument::FfiCall;
+use crate::util::{isolate_slot, make_str, set_isolate_slot};
+use crate::{ContextId, JsRef};
+use rusty_v8 as v8;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::rc::Rc;
+
+/// `PolicyHook` decides which `v8_ffi` fns a context may call, i.e. per
+/// tenant of a multi-tenant host. Set one for a context with
+/// `Runtime::set_policy`.
+///
+/// It is asked synchronously on every call from its context, so it should
+/// be cheap. Closures taking the function name and the caller's context id
+/// are hooks too.
+///
+/// ```ignore
+/// runtime.set_policy(tenant, AllowList::new(&["read", "write"]));
+/// runtime.set_policy(admin, |_: &'static str, _: ContextId| Ok(()));
+/// ```
+pub trait PolicyHook {
+    /// Allow `function` to be called from the context `context`, or deny it
+    /// with the reason thrown to JS.
+    fn check(&self, function: &'static str, context: ContextId) -> Result<(), String>;
+}
+
+impl<F: Fn(&'static str, ContextId) -> Result<(), String>> PolicyHook for F {
+    fn check(&self, function: &'static str, context: ContextId) -> Result<(), String> {
+        self(function, context)
+    }
+}
+
+/// `PolicyHook` allowing only the named functions.
+#[derive(Debug, Clone, Default)]
+pub struct AllowList(HashSet<String>);
+
+impl AllowList {
+    pub fn new(functions: &[&str]) -> AllowList {
+        AllowList(functions.iter().map(|x| x.to_string()).collect())
+    }
+
+    pub fn allow(&mut self, function: &str) {
+        self.0.insert(function.to_string());
+    }
+}
+
+impl PolicyHook for AllowList {
+    fn check(&self, function: &'static str, _context: ContextId) -> Result<(), String> {
+        if self.0.contains(function) {
+            Ok(())
+        } else {
+            Err(format!("{} is not allowed in this context", function))
+        }
+    }
+}
+
+struct Entry {
+    id: ContextId,
+    context: JsRef<v8::Context>,
+    hook: Rc<dyn PolicyHook>,
+}
+
+#[derive(Default)]
+struct PolicyRegistry(RefCell<Vec<Entry>>);
+
+impl PolicyRegistry {
+    /// The entry of `context`, with its id and hook.
+    fn find<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+    ) -> Option<(ContextId, Rc<dyn PolicyHook>)> {
+        let target = context.global(scope);
+        self.0
+            .borrow()
+            .iter()
+            .find(|entry| {
+                entry
+                    .context
+                    .get(scope)
+                    .global(scope)
+                    .strict_equals(target.into())
+            })
+            .map(|entry| (entry.id, entry.hook.clone()))
+    }
+
+    /// Remove and release the entry of `id`, returning whether there was one.
+    fn remove(&self, scope: &mut impl v8::InIsolate, id: ContextId) -> bool {
+        let index = self.0.borrow().iter().position(|x| x.id == id);
+        match index {
+            Some(index) => {
+                let entry = self.0.borrow_mut().remove(index);
+                entry.context.release(scope);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Set the hook of the context `id`, replacing any it had.
+pub(crate) fn set_policy(
+    isolate: &mut v8::Isolate,
+    id: ContextId,
+    context: &v8::Global<v8::Context>,
+    hook: Rc<dyn PolicyHook>,
+) {
+    let mut hs = v8::HandleScope::new(isolate);
+    let scope = hs.enter();
+    let registry = match isolate_slot::<PolicyRegistry>(scope) {
+        Some(registry) => registry,
+        None => {
+            set_isolate_slot(scope, PolicyRegistry::default());
+            isolate_slot::<PolicyRegistry>(scope).unwrap()
+        }
+    };
+    registry.remove(scope, id);
+    let context = context.get(scope).unwrap();
+    registry.0.borrow_mut().push(Entry {
+        id,
+        context: JsRef::new(scope, context),
+        hook,
+    });
+}
+
+/// Remove the hook of the context `id`, i.e. as it is disposed.
+pub(crate) fn clear_policy(isolate: &mut v8::Isolate, id: ContextId) -> bool {
+    match isolate_slot::<PolicyRegistry>(isolate) {
+        Some(registry) => registry.remove(isolate, id),
+        None => false,
+    }
+}
+
+/// Ask the hook of `context` whether `function` may be called, throwing a
+/// `PermissionError` if not. Called by the generated `v8_ffi` trampolines.
+#[doc(hidden)]
+pub fn check_policy<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    call: &FfiCall,
+    function: &'static str,
+) -> bool {
+    let registry = match isolate_slot::<PolicyRegistry>(scope) {
+        Some(registry) => registry,
+        None => return true,
+    };
+    let (id, hook) = match registry.find(scope, context) {
+        Some(found) => found,
+        None => return true,
+    };
+    let reason = match hook.check(function, id) {
+        Ok(()) => return true,
+        Err(reason) => reason,
+    };
+    call.exception(&reason);
+    let message = v8::String::new(scope, &reason).unwrap();
+    let exception = v8::Exception::error(scope, message);
+    if let Ok(object) = TryInto::<v8::Local<v8::Object>>::try_into(exception) {
+        let key = make_str(scope, "name");
+        let name = make_str(scope, "PermissionError");
+        object.set(context, key, name);
+    }
+    scope.isolate().throw_exception(exception);
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+    use crate::FFICompat;
+    use rusty_v8_helper_derive::v8_ffi;
+
+    #[v8_ffi]
+    fn test_ffi_return() -> String {
+        "test".to_string()
+    }
+
+    #[v8_ffi]
+    fn test_ffi_roundtrip(arg: String) -> String {
+        arg
+    }
+
+    #[test]
+    fn policy_hook() {
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        let (admin_id, admin) = runtime.create_context();
+        let (tenant_id, tenant) = runtime.create_context();
+        let calls = Rc::new(std::cell::RefCell::new(vec![]));
+        let seen = calls.clone();
+        assert!(
+            runtime.set_policy(admin_id, move |function: &'static str, id| {
+                seen.borrow_mut().push((function, id));
+                Ok(())
+            })
+        );
+        assert!(runtime.set_policy(tenant_id, crate::AllowList::new(&["test_ffi_return"])));
+        {
+            let isolate = runtime.isolate();
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            for context in [&admin, &tenant].iter() {
+                let context = context.get(scope).unwrap();
+                let global = context.global(scope);
+                let function = load_v8_ffi!(test_ffi_return, scope, context);
+                global.set(context, make_str(scope, "test_ffi_return"), function);
+                let function = load_v8_ffi!(test_ffi_roundtrip, scope, context);
+                global.set(context, make_str(scope, "test_ffi_roundtrip"), function);
+            }
+            let probe = "[test_ffi_return(), (() => { try { return test_ffi_roundtrip('a') } \
+                catch (e) { return e.name + ': ' + e.message } })()].join()";
+            let admin = admin.get(scope).unwrap();
+            let result = run_script(scope, admin, probe).unwrap();
+            assert_eq!(
+                String::from_value(result, scope, admin),
+                Ok("test,a".to_string())
+            );
+            let tenant = tenant.get(scope).unwrap();
+            let result = run_script(scope, tenant, probe).unwrap();
+            assert_eq!(
+                String::from_value(result, scope, tenant),
+                Ok(
+                    "test,PermissionError: test_ffi_roundtrip is not allowed in this context"
+                        .to_string()
+                )
+            );
+        }
+        assert_eq!(
+            *calls.borrow(),
+            vec![
+                ("test_ffi_return", admin_id),
+                ("test_ffi_roundtrip", admin_id)
+            ]
+        );
+        assert!(runtime.clear_policy(tenant_id));
+        assert!(!runtime.clear_policy(tenant_id));
+        let (mut admin, mut tenant) = (admin, tenant);
+        admin.reset(runtime.isolate());
+        tenant.reset(runtime.isolate());
+    }
+}
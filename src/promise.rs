@@ -0,0 +1,69 @@
+use crate::util::*;
+use crate::FFICompat;
+use rusty_v8 as v8;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use v8::Global;
+
+struct PendingPromise {
+    resolver: Global<v8::PromiseResolver>,
+    context: Global<v8::Context>,
+}
+
+thread_local! {
+    static PENDING: RefCell<HashMap<u64, PendingPromise>> = RefCell::new(HashMap::new());
+    static NEXT_ID: Cell<u64> = Cell::new(0);
+}
+
+/// Create a new `Promise` in `context` that is later settled by `settle`
+/// with the returned id.
+pub(crate) fn new_pending<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+) -> (u64, v8::Local<'sc, v8::Promise>) {
+    let resolver = v8::PromiseResolver::new(scope, context).unwrap();
+    let promise = resolver.get_promise(scope);
+    let id = NEXT_ID.with(|next_id| {
+        let id = next_id.get();
+        next_id.set(id + 1);
+        id
+    });
+    let pending = PendingPromise {
+        resolver: Global::new_from(scope, resolver),
+        context: Global::new_from(scope, context),
+    };
+    PENDING.with(|promises| promises.borrow_mut().insert(id, pending));
+    (id, promise)
+}
+
+/// Resolve the pending promise `id` with `result`, or reject it if `result`
+/// fails to convert.
+pub(crate) fn settle<R, E>(isolate: &mut v8::Isolate, id: u64, result: R)
+where
+    R: for<'sc, 'c> FFICompat<'sc, 'c, E = E>,
+    E: Debug + Any,
+{
+    let pending = PENDING.with(|promises| promises.borrow_mut().remove(&id));
+    let pending = match pending {
+        Some(pending) => pending,
+        None => return,
+    };
+    let mut hs = v8::HandleScope::new(isolate);
+    let scope = hs.enter();
+    let context = pending.context.get(scope).unwrap();
+    let mut cs = v8::ContextScope::new(scope, context);
+    let scope = cs.enter();
+    let mut resolver = pending.resolver.get(scope).unwrap();
+    match result.to_value(scope, context) {
+        Ok(value) => {
+            resolver.resolve(context, value);
+        }
+        Err(e) => {
+            let exception = ffi_error_value(scope, &e);
+            resolver.reject(context, exception);
+        }
+    }
+    scope.isolate().run_microtasks();
+}
@@ -0,0 +1,257 @@
+//! Which properties of a JS object are read when it is converted through
+//! `FFIObject` or to a `serde_json::Value`.
+
+use crate::shim::own_property_names;
+use crate::util::*;
+use rusty_v8 as v8;
+use std::convert::TryInto;
+use v8::Global;
+
+const PROPERTY_NAMES_SOURCE: &str = r#"(function (object, inherited) {
+    const names = new Set();
+    let current = object;
+    // the root prototype, `Object.prototype` of any context, is not read
+    do {
+        for (const name of Object.getOwnPropertyNames(current)) {
+            const descriptor = Object.getOwnPropertyDescriptor(current, name);
+            if (name !== 'constructor' && typeof descriptor.value !== 'function') {
+                names.add(name);
+            }
+        }
+        current = inherited ? Object.getPrototypeOf(current) : null;
+    } while (current !== null && Object.getPrototypeOf(current) !== null);
+    return [...names];
+})"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyMode {
+    /// Own enumerable properties, like `Object.keys`.
+    OwnEnumerable,
+    /// Own properties, enumerable or not.
+    Own,
+    /// Own and inherited properties, enumerable or not, up to but excluding
+    /// `Object.prototype`, such as the getters of a class instance.
+    PrototypeChain,
+}
+
+impl Default for PropertyMode {
+    fn default() -> PropertyMode {
+        PropertyMode::OwnEnumerable
+    }
+}
+
+/// What to do when reading a property throws while converting an object,
+/// i.e. a getter or a `Proxy` trap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GetterPolicy {
+    /// Fail the conversion with the path of the property and the message
+    /// of what was thrown.
+    Fail,
+    /// Leave the property out, or convert the element to `null` in an
+    /// array.
+    Skip,
+}
+
+impl Default for GetterPolicy {
+    fn default() -> GetterPolicy {
+        GetterPolicy::Fail
+    }
+}
+
+/// How a `Proxy` is converted, since reading its properties runs its traps.
+/// Objects of `intercepted` and `array_like` are converted through under
+/// any policy, their traps being answered by Rust.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyPolicy {
+    /// Convert it like any other object, running its traps.
+    ConvertThrough,
+    /// Fail the conversion.
+    Reject,
+    /// Convert it to `null` without running any trap. A `JsValue` keeps
+    /// it by handle under any policy.
+    Opaque,
+}
+
+impl Default for ProxyPolicy {
+    fn default() -> ProxyPolicy {
+        ProxyPolicy::ConvertThrough
+    }
+}
+
+struct ModeSlot(PropertyMode);
+
+struct GetterPolicySlot(GetterPolicy);
+
+struct ProxyPolicySlot(ProxyPolicy);
+
+struct PropertyNamesSlot(Global<v8::Function>);
+
+/// Set which properties of JS objects are read when they are converted in
+/// this isolate. Outside of `PropertyMode::OwnEnumerable`, methods are not
+/// read.
+pub fn set_property_mode(scope: &mut impl v8::InIsolate, mode: PropertyMode) {
+    set_isolate_slot(scope, ModeSlot(mode));
+}
+
+/// The property mode of this isolate, `PropertyMode::OwnEnumerable` if none
+/// was set.
+pub fn property_mode(scope: &mut impl v8::InIsolate) -> PropertyMode {
+    isolate_slot::<ModeSlot>(scope)
+        .map(|slot| slot.0)
+        .unwrap_or_default()
+}
+
+/// Set what happens when reading a property throws while converting an
+/// object in this isolate.
+pub fn set_getter_policy(scope: &mut impl v8::InIsolate, policy: GetterPolicy) {
+    set_isolate_slot(scope, GetterPolicySlot(policy));
+}
+
+/// The getter policy of this isolate, `GetterPolicy::Fail` if none was set.
+pub fn getter_policy(scope: &mut impl v8::InIsolate) -> GetterPolicy {
+    isolate_slot::<GetterPolicySlot>(scope)
+        .map(|slot| slot.0)
+        .unwrap_or_default()
+}
+
+/// Set how a `Proxy` is converted in this isolate.
+pub fn set_proxy_policy(scope: &mut impl v8::InIsolate, policy: ProxyPolicy) {
+    set_isolate_slot(scope, ProxyPolicySlot(policy));
+}
+
+/// The proxy policy of this isolate, `ProxyPolicy::ConvertThrough` if none
+/// was set.
+pub fn proxy_policy(scope: &mut impl v8::InIsolate) -> ProxyPolicy {
+    isolate_slot::<ProxyPolicySlot>(scope)
+        .map(|slot| slot.0)
+        .unwrap_or_default()
+}
+
+/// Run `read`, catching anything it throws as the message of the exception.
+pub(crate) fn catching<'sc, S: v8::ToLocal<'sc>, R>(
+    scope: &mut S,
+    read: impl FnOnce(&mut S) -> R,
+) -> Result<R, String> {
+    let mut try_catch = v8::TryCatch::new(scope);
+    let tc = try_catch.enter();
+    let result = read(scope);
+    if tc.has_caught() {
+        let exception = tc.exception().unwrap();
+        return Err(exception_message(scope, exception));
+    }
+    Ok(result)
+}
+
+/// The names of the properties of `object` to read in `mode`.
+pub(crate) fn property_names<'sc>(
+    object: v8::Local<'sc, v8::Object>,
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    mode: PropertyMode,
+) -> Result<Vec<String>, String> {
+    if mode == PropertyMode::OwnEnumerable {
+        return Ok(own_property_names(object, scope, context));
+    }
+    let cached = isolate_slot::<PropertyNamesSlot>(scope).and_then(|slot| slot.0.get(scope));
+    let function = match cached {
+        Some(function) => function,
+        None => {
+            let function =
+                eval_function(scope, context, PROPERTY_NAMES_SOURCE).map_err(|e| e.to_string())?;
+            let global = Global::new_from(scope, function);
+            set_isolate_slot(scope, PropertyNamesSlot(global));
+            function
+        }
+    };
+    let inherited = make_bool(scope, mode == PropertyMode::PrototypeChain);
+    let undefined = v8::undefined(scope).into();
+    let names = call_function(
+        scope,
+        context,
+        function,
+        undefined,
+        &[object.into(), inherited],
+    )
+    .map_err(|e| e.to_string())?;
+    let names: v8::Local<v8::Array> = names
+        .try_into()
+        .map_err(|_| "property names are not an array".to_string())?;
+    let mut out = Vec::with_capacity(names.length() as usize);
+    for i in 0..names.length() {
+        if let Some(name) = names.get_index(scope, context, i) {
+            out.push(name.to_rust_string_lossy(scope));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FFICompat;
+    use serde_json::Value;
+
+    #[test]
+    fn property_policies() {
+        with_context!(|scope, context| {
+            let instance = run_script(
+                scope,
+                context,
+                "class TestPoint { constructor() { this.x = 1; } get y() { return 2; } get broken() { throw new Error('broken'); } norm() { return 0; } }; const point = new TestPoint(); Object.defineProperty(point, 'hidden', { value: 3 }); point",
+            )
+            .unwrap();
+            assert_eq!(
+                Value::from_value(instance, scope, context),
+                Ok(serde_json::json!({ "x": 1.0 }))
+            );
+            crate::set_property_mode(scope, crate::PropertyMode::Own);
+            assert_eq!(
+                Value::from_value(instance, scope, context),
+                Ok(serde_json::json!({ "x": 1.0, "hidden": 3.0 }))
+            );
+            crate::set_property_mode(scope, crate::PropertyMode::PrototypeChain);
+            assert_eq!(
+                Value::from_value(instance, scope, context),
+                Err("reading value.broken threw: Error: broken".to_string())
+            );
+            crate::set_getter_policy(scope, crate::GetterPolicy::Skip);
+            assert_eq!(
+                Value::from_value(instance, scope, context),
+                Ok(serde_json::json!({ "x": 1.0, "hidden": 3.0, "y": 2.0 }))
+            );
+            crate::set_property_mode(scope, crate::PropertyMode::OwnEnumerable);
+            let proxy = run_script(
+                scope,
+                context,
+                "new Proxy({ a: 1 }, { get(target, key) { if (key === 'a') throw new TypeError('trap'); return target[key]; } })",
+            )
+            .unwrap();
+            assert_eq!(
+                Value::from_value(proxy, scope, context),
+                Ok(serde_json::json!({}))
+            );
+            crate::set_getter_policy(scope, crate::GetterPolicy::Fail);
+            assert_eq!(
+                Value::from_value(proxy, scope, context),
+                Err("reading value.a threw: TypeError: trap".to_string())
+            );
+            let wrapped = run_script(
+                scope,
+                context,
+                "({ inner: new Proxy({}, { ownKeys() { throw new Error('trap ran'); } }) })",
+            )
+            .unwrap();
+            crate::set_proxy_policy(scope, crate::ProxyPolicy::Reject);
+            assert_eq!(
+                Value::from_value(wrapped, scope, context),
+                Err("value.inner is a Proxy".to_string())
+            );
+            crate::set_proxy_policy(scope, crate::ProxyPolicy::Opaque);
+            assert_eq!(
+                Value::from_value(wrapped, scope, context),
+                Ok(serde_json::json!({ "inner": null }))
+            );
+            crate::set_proxy_policy(scope, crate::ProxyPolicy::ConvertThrough);
+        });
+    }
+}
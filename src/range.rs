@@ -0,0 +1,269 @@
+//! `FFICompat` for `Range` and `RangeInclusive`, as `{ start, end }`
+//! objects, or as `[start, end]` arrays with
+//! `#[ffi(with = "rusty_v8_helper::range::array")]`.
+//!
+//! Converting from JS checks that `start` is not greater than `end`, and
+//! throws a `RangeError` otherwise.
+//!
+//! ```ignore
+//! #[v8_ffi]
+//! fn slice(text: String, range: Range<Index>) -> Result<String, FFIError> { .. }
+//! // slice('hello', { start: 1, end: 3 })
+//!
+//! #[v8_ffi(return_with = "rusty_v8_helper::range::array")]
+//! fn span(#[ffi(with = "rusty_v8_helper::range::array")] lines: RangeInclusive<u32>) -> Range<u32> { .. }
+//! // span([1, 3])
+//! ```
+
+use crate::util::make_str;
+use crate::{ErrorPath, FFICompat, FFIError};
+use rusty_v8 as v8;
+use std::convert::TryInto;
+use std::ops::{Range, RangeInclusive};
+
+/// A range converted to and from JS by its bounds, implemented for `Range`
+/// and `RangeInclusive`.
+pub trait JsRange: Sized {
+    type Bound;
+
+    fn from_bounds(start: Self::Bound, end: Self::Bound) -> Self;
+
+    fn into_bounds(self) -> (Self::Bound, Self::Bound);
+}
+
+impl<T> JsRange for Range<T> {
+    type Bound = T;
+
+    fn from_bounds(start: T, end: T) -> Range<T> {
+        start..end
+    }
+
+    fn into_bounds(self) -> (T, T) {
+        (self.start, self.end)
+    }
+}
+
+impl<T> JsRange for RangeInclusive<T> {
+    type Bound = T;
+
+    fn from_bounds(start: T, end: T) -> RangeInclusive<T> {
+        start..=end
+    }
+
+    fn into_bounds(self) -> (T, T) {
+        self.into_inner()
+    }
+}
+
+/// Convert `start` and `end`, located at `start_at` and `end_at`, into a
+/// range, checking their order.
+fn from_bounds<'sc, 'c, R>(
+    (start, start_at): (v8::Local<'sc, v8::Value>, &str),
+    (end, end_at): (v8::Local<'sc, v8::Value>, &str),
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+) -> Result<R, FFIError>
+where
+    R: JsRange,
+    R::Bound: FFICompat<'sc, 'c> + PartialOrd,
+    <R::Bound as FFICompat<'sc, 'c>>::E: ErrorPath + Into<FFIError>,
+{
+    let start_bound = <R::Bound as FFICompat<'sc, 'c>>::from_value(start, scope, context)
+        .map_err(|e| e.at(start_at).into())?;
+    let end_bound = <R::Bound as FFICompat<'sc, 'c>>::from_value(end, scope, context)
+        .map_err(|e| e.at(end_at).into())?;
+    if start_bound > end_bound {
+        return Err(FFIError::RangeError(format!(
+            "range start {} is greater than its end {}",
+            start.to_rust_string_lossy(scope),
+            end.to_rust_string_lossy(scope)
+        )));
+    }
+    Ok(R::from_bounds(start_bound, end_bound))
+}
+
+fn bound_value<'sc, 'c, T: FFICompat<'sc, 'c>>(
+    bound: T,
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+    bound
+        .to_value(scope, context)
+        .map_err(|e| FFIError::Error(format!("{:?}", e)))
+}
+
+macro_rules! ffi_range {
+    ($range:ident) => {
+        impl<'sc, 'c, T> FFICompat<'sc, 'c> for $range<T>
+        where
+            T: FFICompat<'sc, 'c> + PartialOrd,
+            T::E: ErrorPath + Into<FFIError>,
+        {
+            type E = FFIError;
+
+            fn from_value(
+                value: v8::Local<'sc, v8::Value>,
+                scope: &mut impl v8::ToLocal<'sc>,
+                context: v8::Local<'c, v8::Context>,
+            ) -> Result<Self, FFIError> {
+                let object: v8::Local<v8::Object> = match value.try_into() {
+                    Ok(object) if !value.is_array() => object,
+                    _ => {
+                        return Err(FFIError::TypeError(
+                            "expected an object with start and end for a range".to_string(),
+                        ))
+                    }
+                };
+                let key = make_str(scope, "start");
+                let start = object
+                    .get(scope, context, key)
+                    .unwrap_or_else(|| v8::undefined(scope).into());
+                let key = make_str(scope, "end");
+                let end = object
+                    .get(scope, context, key)
+                    .unwrap_or_else(|| v8::undefined(scope).into());
+                from_bounds((start, ".start"), (end, ".end"), scope, context)
+            }
+
+            fn to_value(
+                self,
+                scope: &mut impl v8::ToLocal<'sc>,
+                context: v8::Local<'c, v8::Context>,
+            ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+                let (start, end) = self.into_bounds();
+                let start = bound_value(start, scope, context)?;
+                let end = bound_value(end, scope, context)?;
+                let object = v8::Object::new(scope);
+                object.set(context, make_str(scope, "start"), start);
+                object.set(context, make_str(scope, "end"), end);
+                Ok(object.into())
+            }
+        }
+    };
+}
+
+ffi_range!(Range);
+ffi_range!(RangeInclusive);
+
+/// Convert ranges as `[start, end]` arrays, for `#[ffi(with)]` and
+/// `return_with`.
+pub mod array {
+    use super::{bound_value, from_bounds, JsRange};
+    use crate::{ErrorPath, FFICompat, FFIError};
+    use rusty_v8 as v8;
+    use std::convert::TryInto;
+
+    pub fn from_value<'sc, 'c, R>(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<R, FFIError>
+    where
+        R: JsRange,
+        R::Bound: FFICompat<'sc, 'c> + PartialOrd,
+        <R::Bound as FFICompat<'sc, 'c>>::E: ErrorPath + Into<FFIError>,
+    {
+        let array: v8::Local<v8::Array> = match value.try_into() {
+            Ok(array) if array.length() == 2 => array,
+            _ => {
+                return Err(FFIError::TypeError(
+                    "expected a [start, end] array for a range".to_string(),
+                ))
+            }
+        };
+        let start = array
+            .get_index(scope, context, 0)
+            .unwrap_or_else(|| v8::undefined(scope).into());
+        let end = array
+            .get_index(scope, context, 1)
+            .unwrap_or_else(|| v8::undefined(scope).into());
+        from_bounds((start, "[0]"), (end, "[1]"), scope, context)
+    }
+
+    pub fn to_value<'sc, 'c, R>(
+        range: R,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError>
+    where
+        R: JsRange,
+        R::Bound: FFICompat<'sc, 'c>,
+    {
+        let (start, end) = range.into_bounds();
+        let start = bound_value(start, scope, context)?;
+        let end = bound_value(end, scope, context)?;
+        Ok(v8::Array::new_with_elements(scope, &[start, end]).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+    use rusty_v8_helper_derive::v8_ffi;
+
+    #[v8_ffi]
+    fn test_ffi_range(
+        text: String,
+        range: std::ops::Range<crate::Index>,
+    ) -> Result<String, crate::FFIError> {
+        text.get(*range.start..*range.end)
+            .map(|x| x.to_string())
+            .ok_or_else(|| crate::FFIError::RangeError("range is out of bounds".to_string()))
+    }
+
+    #[v8_ffi(return_with = "crate::range::array")]
+    fn test_ffi_range_array(
+        #[ffi(with = "crate::range::array")] lines: std::ops::RangeInclusive<u32>,
+    ) -> std::ops::Range<u32> {
+        *lines.start()..*lines.end() + 1
+    }
+
+    #[test]
+    fn range_conversion() {
+        with_context!([test_ffi_range, test_ffi_range_array], |scope, context| {
+            let result = run_script(
+                scope,
+                context,
+                r#"
+                const attempt = (f) => {
+                    try {
+                        return JSON.stringify(f());
+                    } catch (e) {
+                        return `${e.name}: ${e.message}`;
+                    }
+                };
+                [
+                    () => test_ffi_range('hello', { start: 1, end: 3 }),
+                    () => test_ffi_range('hello', { start: 2, end: 2 }),
+                    () => test_ffi_range('hello', { start: 3, end: 1 }),
+                    () => test_ffi_range('hello', { start: -1, end: 1 }),
+                    () => test_ffi_range('hello', [1, 3]),
+                    () => test_ffi_range_array([1, 3]),
+                    () => test_ffi_range_array([3, 3]),
+                    () => test_ffi_range_array([4, 3]),
+                    () => test_ffi_range_array({ start: 1, end: 3 }),
+                ].map(attempt).join('\n')
+                "#,
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(result, scope, context).unwrap(),
+                r#""el"
+    ""
+    RangeError: range start 3 is greater than its end 1
+    RangeError: value.start: -1 is not a valid index, expected an integer between 0 and 9007199254740991
+    TypeError: expected an object with start and end for a range
+    [1,4]
+    [3,4]
+    RangeError: range start 4 is greater than its end 3
+    TypeError: expected a [start, end] array for a range"#
+            );
+            let range = (2u32..5).to_value(scope, context).unwrap();
+            assert_eq!(
+                std::ops::Range::<u32>::from_value(range, scope, context),
+                Ok(2..5)
+            );
+        });
+    }
+}
@@ -0,0 +1,200 @@
+//! `Realm`, a context for untrusted code that can only reach what the host
+//! explicitly grants it.
+
+use crate::capability::copy_between;
+use crate::script::compile_only;
+use crate::util::make_str;
+use crate::{ContextId, FFIError, JsError};
+use rusty_v8 as v8;
+use std::convert::TryInto;
+use v8::Global;
+
+/// `Realm` is a context of its own for untrusted code, i.e. a plugin,
+/// created with `Runtime::create_realm`. Besides the JS builtins, its
+/// global only has what the host grants it: functions and objects of the
+/// host's contexts, exposed with `expose_function`, and values defined in
+/// the realm itself, such as `v8_ffi` fns. Values crossing between the
+/// realm and the host are copied, so neither side can reach the other's
+/// objects through them.
+///
+/// ```ignore
+/// let realm = runtime.create_realm("plugin");
+/// realm.grant(scope, "log", host, log)?;
+/// realm.define(scope, "now", load_v8_ffi!(now, scope, realm.context(scope)))?;
+/// realm.evaluate(scope, &plugin_source, "plugin.js")?;
+/// let output = realm.call(scope, host, "main", &[input])?;
+/// runtime.dispose_realm(realm);
+/// ```
+pub struct Realm {
+    id: ContextId,
+    name: String,
+    context: Global<v8::Context>,
+}
+
+impl Realm {
+    pub(crate) fn new(id: ContextId, name: &str, context: Global<v8::Context>) -> Realm {
+        Realm {
+            id,
+            name: name.to_string(),
+            context,
+        }
+    }
+
+    pub fn id(&self) -> ContextId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn context<'sc>(&self, scope: &mut impl v8::ToLocal<'sc>) -> v8::Local<'sc, v8::Context> {
+        self.context.get(scope).unwrap()
+    }
+
+    pub fn global<'sc>(&self, scope: &mut impl v8::ToLocal<'sc>) -> v8::Local<'sc, v8::Object> {
+        let context = self.context(scope);
+        context.global(scope)
+    }
+
+    /// Grant the realm `value` of the host context `from` as the global
+    /// `name`: functions are exposed with `expose_function`, objects and
+    /// arrays copied, with their functions exposed, and primitives set as
+    /// they are.
+    pub fn grant<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        name: &str,
+        from: v8::Local<v8::Context>,
+        value: v8::Local<'sc, v8::Value>,
+    ) -> Result<(), FFIError> {
+        let context = self.context(scope);
+        let value = copy_between(scope, value, from, context)?;
+        self.define(scope, name, value)
+    }
+
+    /// Set the global `name` of the realm to `value`, which must belong to
+    /// the realm, i.e. a `v8_ffi` fn loaded into its context. Unlike with
+    /// `grant`, nothing is copied.
+    pub fn define<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        name: &str,
+        value: v8::Local<v8::Value>,
+    ) -> Result<(), FFIError> {
+        let context = self.context(scope);
+        let key = make_str(scope, name);
+        context.global(scope).set(context, key, value);
+        Ok(())
+    }
+
+    /// Run `source` in the realm under the resource name `origin`,
+    /// returning its completion value, which belongs to the realm.
+    pub fn evaluate<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        source: &str,
+        origin: &str,
+    ) -> Result<v8::Local<'sc, v8::Value>, JsError> {
+        let context = self.context(scope);
+        compile_only(scope, context, source, origin)?.run(scope, context)
+    }
+
+    /// Call the global function `name` of the realm with `args` of the host
+    /// context `host`, copying them into the realm and the result back to
+    /// `host`, as `grant` does.
+    pub fn call<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        host: v8::Local<v8::Context>,
+        name: &str,
+        args: &[v8::Local<'sc, v8::Value>],
+    ) -> Result<v8::Local<'sc, v8::Value>, JsError> {
+        let context = self.context(scope);
+        let key = make_str(scope, name);
+        let mut function: v8::Local<v8::Function> = context
+            .global(scope)
+            .get(scope, context, key)
+            .and_then(|x| x.try_into().ok())
+            .ok_or_else(|| JsError::new(format!("{} is not a function in {}", name, self.name)))?;
+        let mut copied = Vec::with_capacity(args.len());
+        for arg in args {
+            copied.push(copy_between(scope, *arg, host, context).map_err(copy_error)?);
+        }
+        let recv = v8::undefined(scope).into();
+        let mut try_catch = v8::TryCatch::new(scope);
+        let tc = try_catch.enter();
+        let result = function.call(scope, context, recv, &copied);
+        if tc.has_caught() {
+            return Err(JsError::from_try_catch(scope, context, tc));
+        }
+        let result = result.unwrap_or_else(|| v8::undefined(scope).into());
+        copy_between(scope, result, context, host).map_err(copy_error)
+    }
+
+    /// Release the realm's handle to its context.
+    pub(crate) fn release(mut self, scope: &mut impl v8::InIsolate) {
+        self.context.reset(scope);
+    }
+}
+
+fn copy_error(error: FFIError) -> JsError {
+    JsError::new(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+    use crate::FFICompat;
+
+    #[test]
+    fn realm() {
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        runtime.on_context_created(|isolate, _, context| {
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            let context = context.get(scope).unwrap();
+            run_script(scope, context, "globalThis.hostOnly = true");
+        });
+        let (_, host) = runtime.create_context();
+        let realm = runtime.create_realm("plugin");
+        {
+            let isolate = runtime.isolate();
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            let host = host.get(scope).unwrap();
+            let log = run_script(scope, host, "globalThis.lines = []; (x) => lines.push(x)");
+            realm.grant(scope, "log", host, log.unwrap()).unwrap();
+            let limits = run_script(scope, host, "({ max: 3 })").unwrap();
+            realm.grant(scope, "limits", host, limits).unwrap();
+            realm
+                .evaluate(
+                    scope,
+                    "function main(input) { log(input.name); return { n: input.n * limits.max }; }",
+                    "plugin.js",
+                )
+                .unwrap();
+            let input = run_script(scope, host, "({ name: 'a', n: 2 })").unwrap();
+            let output = realm.call(scope, host, "main", &[input]).unwrap();
+            let check = crate::eval_with_bindings::<String>(
+                scope,
+                host,
+                "[output.n, Object.getPrototypeOf(output) === Object.prototype, lines.join()].join()",
+                &[("output", output)],
+            );
+            assert_eq!(check, Ok("6,true,a".to_string()));
+            let hidden = realm.evaluate(scope, "typeof hostOnly + typeof lines", "probe.js");
+            assert_eq!(
+                String::from_value(hidden.unwrap(), scope, host),
+                Ok("undefinedundefined".to_string())
+            );
+            let error = realm.call(scope, host, "missing", &[]).unwrap_err();
+            assert_eq!(error.message, "missing is not a function in plugin");
+        }
+        assert!(runtime.dispose_realm(realm));
+        let mut host = host;
+        host.reset(runtime.isolate());
+    }
+}
@@ -0,0 +1,390 @@
+//! `ContextRecycler`, resetting a context between executions so that
+//! contexts can be pooled rather than created for every request.
+
+use crate::callbacks::CallbackRegistry;
+use crate::event_loop;
+use crate::util::{call_function, eval_function};
+use crate::{CallbackId, FFICompat, FFIError, JsRef};
+use rusty_v8 as v8;
+use v8::{Global, Isolate};
+
+type ResetHook = Box<dyn Fn(&mut Isolate, &Global<v8::Context>) -> Result<(), FFIError>>;
+
+/// Snapshots the properties of the global object, the builtin namespaces
+/// and the builtin prototypes, returning a function that restores them and
+/// reports what it deleted, restored, and could not delete.
+///
+/// Executions can patch any builtin, so the reset only uses primitives
+/// captured here and indexed loops rather than iterators.
+const RESET_BOOTSTRAP: &str = r#"
+(function () {
+    const { ownKeys, getOwnPropertyDescriptor, defineProperty, deleteProperty, setPrototypeOf } =
+        Reflect;
+    const call = Function.prototype.call;
+    const mapGet = call.bind(Map.prototype.get);
+    const toName = String;
+    // without a prototype, so that fields patched onto `Object.prototype`
+    // are not read as part of the descriptor
+    const describe = (target, key) => {
+        const descriptor = getOwnPropertyDescriptor(target, key);
+        if (descriptor !== undefined) setPrototypeOf(descriptor, null);
+        return descriptor;
+    };
+    const targets = [
+        ['', globalThis],
+        ['Object.', Object],
+        ['Function.', Function],
+        ['Array.', Array],
+        ['String.', String],
+        ['Number.', Number],
+        ['Boolean.', Boolean],
+        ['Symbol.', Symbol],
+        ['Promise.', Promise],
+        ['RegExp.', RegExp],
+        ['Date.', Date],
+        ['Error.', Error],
+        ['Map.', Map],
+        ['Set.', Set],
+        ['JSON.', JSON],
+        ['Math.', Math],
+        ['Reflect.', Reflect],
+        ['Proxy.', Proxy],
+        ['Object.prototype.', Object.prototype],
+        ['Function.prototype.', Function.prototype],
+        ['Array.prototype.', Array.prototype],
+        ['String.prototype.', String.prototype],
+        ['Number.prototype.', Number.prototype],
+        ['Boolean.prototype.', Boolean.prototype],
+        ['Symbol.prototype.', Symbol.prototype],
+        ['Promise.prototype.', Promise.prototype],
+        ['RegExp.prototype.', RegExp.prototype],
+        ['Date.prototype.', Date.prototype],
+        ['Error.prototype.', Error.prototype],
+        ['Map.prototype.', Map.prototype],
+        ['Set.prototype.', Set.prototype],
+    ].map(([prefix, target]) => {
+        const keys = ownKeys(target);
+        const snapshot = new Map();
+        for (const key of keys) snapshot.set(key, describe(target, key));
+        return { prefix, target, keys, snapshot };
+    });
+    const is = Object.is;
+    const same = (a, b) => is(a.value, b.value) && a.get === b.get && a.set === b.set &&
+        a.writable === b.writable && a.enumerable === b.enumerable &&
+        a.configurable === b.configurable;
+    return () => {
+        const removed = [];
+        const restored = [];
+        const retained = [];
+        const add = (list, name) => defineProperty(list, list.length, {
+            __proto__: null, value: name, writable: true, enumerable: true, configurable: true,
+        });
+        for (let i = 0; i < targets.length; i++) {
+            const { prefix, target, keys, snapshot } = targets[i];
+            const current = ownKeys(target);
+            for (let j = 0; j < current.length; j++) {
+                const key = current[j];
+                const name = prefix + toName(key);
+                const original = mapGet(snapshot, key);
+                const descriptor = describe(target, key);
+                if (original === undefined) {
+                    if (deleteProperty(target, key)) {
+                        add(removed, name);
+                    } else {
+                        if (descriptor.writable) target[key] = undefined;
+                        add(retained, name);
+                    }
+                } else if (!same(original, descriptor)) {
+                    add(defineProperty(target, key, original) ? restored : retained, name);
+                }
+            }
+            for (let j = 0; j < keys.length; j++) {
+                const key = keys[j];
+                if (describe(target, key) === undefined) {
+                    const original = mapGet(snapshot, key);
+                    const name = prefix + toName(key);
+                    add(defineProperty(target, key, original) ? restored : retained, name);
+                }
+            }
+        }
+        return [removed, restored, retained];
+    };
+})
+"#;
+
+/// What `ContextRecycler::recycle` found and undid.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecycleReport {
+    /// Globals, or properties of builtin namespaces and prototypes, that
+    /// were added and have been deleted.
+    pub removed: Vec<String>,
+    /// Properties that were replaced or deleted and have been restored.
+    pub restored: Vec<String>,
+    /// Properties that could not be deleted or restored, i.e. globals
+    /// declared with `var` or frozen prototypes. Added ones are set to
+    /// `undefined` if writable.
+    pub retained: Vec<String>,
+    /// Callbacks registered from the context that were left registered,
+    /// and have been released.
+    pub leaked_callbacks: usize,
+    /// Whether the isolate's event loop had work left, i.e. pending timers
+    /// or promises, which could still run in the context.
+    pub pending_work: bool,
+}
+
+impl RecycleReport {
+    /// Whether the context is safe to reuse: nothing of the execution is
+    /// left that could run later, or be seen by the next execution. A
+    /// builtin the execution patched and made non-configurable is
+    /// `retained`, and so not clean.
+    pub fn is_clean(&self) -> bool {
+        self.retained.is_empty() && self.leaked_callbacks == 0 && !self.pending_work
+    }
+}
+
+/// `ContextRecycler` resets a context to the state it was in when the
+/// recycler was created, i.e. once extensions are installed, so that a
+/// pool can reuse the context for the next request.
+///
+/// `recycle` runs the reset hooks, runs pending microtasks, releases
+/// callbacks registered from the context since, deletes added globals and
+/// restores replaced ones, on the global object and the builtin
+/// namespaces and prototypes, i.e. `Object.keys` and `Map.prototype.get`. A context whose `RecycleReport` is not clean should be
+/// disposed rather than reused.
+///
+/// Top-level `let`, `const` and `class` declarations of scripts are not
+/// properties of the global object and outlive a reset, so executions
+/// should be run as modules or functions.
+///
+/// ```ignore
+/// let recycler = ContextRecycler::new(scope, context)?;
+/// // for every request
+/// run_request(isolate, recycler.context())?;
+/// if !recycler.recycle(isolate)?.is_clean() {
+///     // dispose the context rather than returning it to the pool
+/// }
+/// ```
+pub struct ContextRecycler {
+    context: Global<v8::Context>,
+    reset: JsRef<v8::Function>,
+    callbacks_since: CallbackId,
+    hooks: Vec<ResetHook>,
+}
+
+impl ContextRecycler {
+    pub fn new<'sc>(
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+    ) -> Result<ContextRecycler, FFIError> {
+        let bootstrap = eval_function(scope, context, RESET_BOOTSTRAP)?;
+        let global = context.global(scope).into();
+        let reset = call_function(scope, context, bootstrap, global, &[])?;
+        let reset = JsRef::<v8::Function>::from_value(reset, scope, context)
+            .map_err(|e| FFIError::Error(format!("failed to snapshot context: {:?}", e)))?;
+        Ok(ContextRecycler {
+            context: Global::new_from(scope, context),
+            reset,
+            callbacks_since: CallbackRegistry::of(scope).next_id(),
+            hooks: vec![],
+        })
+    }
+
+    pub fn context(&self) -> &Global<v8::Context> {
+        &self.context
+    }
+
+    /// Run `hook` first on every `recycle`, i.e. to reset host state kept
+    /// for the context. A failing hook fails the `recycle`.
+    pub fn on_reset(
+        &mut self,
+        hook: impl Fn(&mut Isolate, &Global<v8::Context>) -> Result<(), FFIError> + 'static,
+    ) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// Reset the context, see `ContextRecycler`.
+    ///
+    /// Must not be called from within a scope of the isolate.
+    pub fn recycle(&self, isolate: &mut Isolate) -> Result<RecycleReport, FFIError> {
+        for hook in &self.hooks {
+            hook(isolate, &self.context)?;
+        }
+        let mut hs = v8::HandleScope::new(isolate);
+        let scope = hs.enter();
+        let context = self.context.get(scope).unwrap();
+        let mut cs = v8::ContextScope::new(scope, context);
+        let scope = cs.enter();
+        scope.isolate().run_microtasks();
+        let leaked_callbacks =
+            CallbackRegistry::of(scope).remove_context_since(scope, context, self.callbacks_since);
+        let pending_work = event_loop::has_pending(scope);
+        let global = context.global(scope).into();
+        let result = self.reset.call(scope, context, global, &[])?;
+        let (removed, restored, retained) =
+            <(Vec<String>, Vec<String>, Vec<String>)>::from_value(result, scope, context)
+                .map_err(|e| FFIError::Error(format!("failed to reset context: {:?}", e)))?;
+        Ok(RecycleReport {
+            removed,
+            restored,
+            retained,
+            leaked_callbacks,
+            pending_work,
+        })
+    }
+
+    /// Release the handles to the context and its snapshot.
+    pub fn release(mut self, scope: &mut impl v8::InIsolate) {
+        self.reset.release(scope);
+        self.context.reset(scope);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::run_script;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn context_recycler() {
+        use crate::Extension;
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        let executor = crate::LocalExecutor::new();
+        crate::set_executor(runtime.isolate(), executor.clone());
+        let (_, mut context) = runtime.create_context();
+        let resets = Rc::new(Cell::new(0));
+        let mut recycler = {
+            let mut hs = v8::HandleScope::new(runtime.isolate());
+            let scope = hs.enter();
+            let context = context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            crate::extensions::timers::TimersExtension
+                .install(scope, context)
+                .unwrap();
+            run_script(scope, context, "globalThis.config = { tenant: 'none' }");
+            ContextRecycler::new(scope, context).unwrap()
+        };
+        let counted = resets.clone();
+        recycler.on_reset(move |_, _| {
+            counted.set(counted.get() + 1);
+            Ok(())
+        });
+        let run = |isolate: &mut v8::Isolate, source: &str| {
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            let context = recycler.context().get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            let function = run_script(scope, context, "(x) => x");
+            let function =
+                crate::JsRef::<v8::Function>::from_value(function.unwrap(), scope, context);
+            crate::CallbackRegistry::of(scope).register(scope, context, function.unwrap());
+            let value = run_script(scope, context, source).unwrap();
+            String::from_value(value, scope, context).unwrap()
+        };
+
+        let isolate = runtime.isolate();
+        run(
+            isolate,
+            r#"
+            globalThis.secret = 'tenant a';
+            globalThis.config = { tenant: 'a' };
+            delete globalThis.setTimeout;
+            Array.prototype.leak = () => secret;
+            setInterval(() => {}, 1000);
+            ''
+            "#,
+        );
+        let report = recycler.recycle(isolate).unwrap();
+        assert_eq!(report.removed, vec!["secret", "Array.prototype.leak"]);
+        assert_eq!(report.restored, vec!["config", "setTimeout"]);
+        assert!(report.retained.is_empty());
+        assert_eq!(report.leaked_callbacks, 1);
+        assert!(report.pending_work);
+        assert!(!report.is_clean());
+        assert_eq!(resets.get(), 1);
+        assert_eq!(
+            run(
+                isolate,
+                "[typeof secret, typeof [].leak, typeof setTimeout, config.tenant].join()"
+            ),
+            "undefined,undefined,function,none"
+        );
+
+        run(isolate, "var declared = 'tenant b'; ''");
+        let report = recycler.recycle(isolate).unwrap();
+        assert_eq!(report.retained, vec!["declared"]);
+        assert_eq!(run(isolate, "String(declared)"), "undefined");
+        recycler.release(isolate);
+        context.reset(isolate);
+    }
+
+    #[test]
+    fn patched_builtins() {
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        let (_, mut context) = runtime.create_context();
+        let recycler = {
+            let mut hs = v8::HandleScope::new(runtime.isolate());
+            let scope = hs.enter();
+            let context = context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            ContextRecycler::new(scope, context).unwrap()
+        };
+        let run = |isolate: &mut v8::Isolate, source: &str| {
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            let context = recycler.context().get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            let value = run_script(scope, context, source).unwrap();
+            String::from_value(value, scope, context).unwrap()
+        };
+
+        let isolate = runtime.isolate();
+        run(
+            isolate,
+            r#"
+            globalThis.secret = 'tenant a';
+            Object.keys = () => [];
+            Map.prototype.get = () => undefined;
+            Array.prototype[Symbol.iterator] = function* () {};
+            Function.prototype.call = null;
+            ''
+            "#,
+        );
+        let report = recycler.recycle(isolate).unwrap();
+        assert_eq!(report.removed, vec!["secret"]);
+        assert_eq!(
+            report.restored,
+            vec![
+                "Object.keys",
+                "Function.prototype.call",
+                "Array.prototype.Symbol(Symbol.iterator)",
+                "Map.prototype.get",
+            ]
+        );
+        assert!(report.is_clean());
+        assert_eq!(
+            run(
+                isolate,
+                "[typeof secret, Object.keys({ a: 1 }), new Map([[1, 'b']]).get(1), ...[1]].join()"
+            ),
+            "undefined,a,b,1"
+        );
+
+        run(
+            isolate,
+            "Object.defineProperty(Object, 'keys', { value: () => [], configurable: false }); ''",
+        );
+        let report = recycler.recycle(isolate).unwrap();
+        assert_eq!(report.retained, vec!["Object.keys"]);
+        assert!(!report.is_clean());
+        recycler.release(isolate);
+        context.reset(isolate);
+    }
+}
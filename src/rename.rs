@@ -0,0 +1,155 @@
+//! Renaming of Rust snake_case names to JS camelCase as they cross into JS.
+//!
+//! `FFIObject` types use the policy set for the isolate with
+//! `set_rename_policy`, unless the type picks its own with
+//! `FFIObject::RENAME`. `#[derive(FromJsObject)]` structs opt in with
+//! `#[js_rename_all = "camelCase"]`, and `#[js_name]` overrides single fields.
+
+use crate::util::{isolate_slot, make_str, remove_isolate_slot, set_isolate_slot};
+use rusty_v8 as v8;
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenamePolicy {
+    /// Names are passed through unchanged.
+    Keep,
+    /// `max_retries` in Rust is `maxRetries` in JS.
+    CamelCase,
+}
+
+impl Default for RenamePolicy {
+    fn default() -> RenamePolicy {
+        RenamePolicy::Keep
+    }
+}
+
+impl RenamePolicy {
+    /// The JS name for the Rust name `name`.
+    pub fn to_js(self, name: &str) -> String {
+        match self {
+            RenamePolicy::Keep => name.to_string(),
+            RenamePolicy::CamelCase => camel_case(name),
+        }
+    }
+
+    /// The Rust name for the JS name `name`. Names that `to_js` would not
+    /// have produced, like a `#[serde(rename = "URL")]` override, are passed
+    /// through unchanged.
+    pub fn from_js(self, name: &str) -> String {
+        match self {
+            RenamePolicy::Keep => name.to_string(),
+            RenamePolicy::CamelCase => {
+                let snake = snake_case(name);
+                if !snake.chars().any(char::is_uppercase) && camel_case(&snake) == name {
+                    snake
+                } else {
+                    name.to_string()
+                }
+            }
+        }
+    }
+
+    /// Rename every object key in `value`, recursively.
+    pub(crate) fn rename_keys(
+        self,
+        value: Value,
+        rename: fn(RenamePolicy, &str) -> String,
+    ) -> Value {
+        if self == RenamePolicy::Keep {
+            return value;
+        }
+        match value {
+            Value::Array(values) => Value::Array(
+                values
+                    .into_iter()
+                    .map(|x| self.rename_keys(x, rename))
+                    .collect(),
+            ),
+            Value::Object(entries) => Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (rename(self, &key), self.rename_keys(value, rename)))
+                    .collect::<Map<String, Value>>(),
+            ),
+            value => value,
+        }
+    }
+}
+
+fn camel_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' && !out.is_empty() {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn snake_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len() + 4);
+    for c in name.chars() {
+        if c.is_uppercase() && !out.is_empty() {
+            out.push('_');
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+struct PolicySlot(RenamePolicy);
+
+/// Set the default rename policy for `FFIObject` types and `set_function`
+/// in this isolate.
+pub fn set_rename_policy(scope: &mut impl v8::InIsolate, policy: RenamePolicy) {
+    if policy == RenamePolicy::Keep {
+        remove_isolate_slot::<PolicySlot>(scope);
+    } else {
+        set_isolate_slot(scope, PolicySlot(policy));
+    }
+}
+
+/// The rename policy of this isolate, `RenamePolicy::Keep` if none was set.
+pub fn rename_policy(scope: &mut impl v8::InIsolate) -> RenamePolicy {
+    isolate_slot::<PolicySlot>(scope)
+        .map(|slot| slot.0)
+        .unwrap_or_default()
+}
+
+/// Set `function` as the property `name` of `object`, renamed by the
+/// isolate's rename policy, i.e. `read_file` becomes `readFile`.
+pub fn set_function<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    object: v8::Local<v8::Object>,
+    name: &str,
+    function: v8::Local<v8::Function>,
+) {
+    let name = rename_policy(scope).to_js(name);
+    let key = make_str(scope, &name);
+    object.set(context, key, function.into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn camel_case_round_trip() {
+        let policy = RenamePolicy::CamelCase;
+        assert_eq!(policy.to_js("max_retries"), "maxRetries");
+        assert_eq!(policy.to_js("_private"), "_private");
+        assert_eq!(policy.from_js("maxRetries"), "max_retries");
+        assert_eq!(policy.from_js("max_retries"), "max_retries");
+        assert_eq!(policy.from_js("URL"), "URL");
+        assert_eq!(RenamePolicy::Keep.from_js("maxRetries"), "maxRetries");
+    }
+}
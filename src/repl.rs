@@ -0,0 +1,304 @@
+//! Building blocks of a debug console: evaluating lines in a persistent
+//! context, detecting input that continues on the next line, and printing
+//! results and uncaught errors.
+
+use crate::script::compile_only;
+use crate::util::inspect;
+use crate::{JsError, JsRef};
+use rusty_v8 as v8;
+
+/// What feeding a line to a `Repl` produced.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplOutput {
+    /// The input continues on the next line, i.e. after an unclosed `{`.
+    Incomplete,
+    /// The input ran; its completion value, rendered by `util::inspect`.
+    Value(String),
+    /// The input threw or failed to compile; the error with its stack.
+    Error(String),
+}
+
+/// `Repl` evaluates lines of JS in one context, so that variables and
+/// functions declared by one input are available to the next. Lines of
+/// an input that is not complete yet are buffered until it is.
+///
+/// ```ignore
+/// let mut repl = Repl::new(scope, context);
+/// loop {
+///     let line = read_line(repl.prompt());
+///     match repl.feed(scope, &line) {
+///         ReplOutput::Incomplete => (),
+///         ReplOutput::Value(value) => println!("{}", value),
+///         ReplOutput::Error(error) => eprintln!("{}", error),
+///     }
+/// }
+/// ```
+///
+/// Like a `JsRef`, release a `Repl` while its isolate is still alive.
+pub struct Repl {
+    context: JsRef<v8::Context>,
+    pending: String,
+    inputs: usize,
+}
+
+impl Repl {
+    pub fn new<'sc>(scope: &mut impl v8::InIsolate, context: v8::Local<'sc, v8::Context>) -> Repl {
+        Repl {
+            context: JsRef::new(scope, context),
+            pending: String::new(),
+            inputs: 0,
+        }
+    }
+
+    /// The prompt for the next line: `> `, or `... ` within an input.
+    pub fn prompt(&self) -> &'static str {
+        if self.pending.is_empty() {
+            "> "
+        } else {
+            "... "
+        }
+    }
+
+    /// Whether lines of an incomplete input are buffered.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Drop the lines of an incomplete input, i.e. on Ctrl-C.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Add `line` to the input, running it once it is complete. An empty
+    /// line is `undefined` on its own, and ends an incomplete input early to
+    /// report its syntax error.
+    pub fn feed<'sc>(&mut self, scope: &mut impl v8::ToLocal<'sc>, line: &str) -> ReplOutput {
+        let force = line.trim().is_empty() && self.is_pending();
+        if !self.pending.is_empty() {
+            self.pending.push('\n');
+        }
+        self.pending.push_str(line);
+        if !force && !is_complete(&self.pending) {
+            return ReplOutput::Incomplete;
+        }
+        let context = self.context.get(scope);
+        let mut cs = v8::ContextScope::new(scope, context);
+        let scope = cs.enter();
+        let origin = format!("<repl:{}>", self.inputs + 1);
+        let script = match compile_only(scope, context, &self.pending, &origin) {
+            Err(e) if !force && is_unexpected_end(&e) => return ReplOutput::Incomplete,
+            Err(e) => {
+                self.pending.clear();
+                self.inputs += 1;
+                return ReplOutput::Error(format_error(&e));
+            }
+            Ok(script) => script,
+        };
+        self.pending.clear();
+        self.inputs += 1;
+        let result = script.run(scope, context);
+        scope.isolate().run_microtasks();
+        match result {
+            Ok(value) => ReplOutput::Value(inspect(scope, context, value)),
+            Err(e) => ReplOutput::Error(format_error(&e)),
+        }
+    }
+
+    pub fn release(self, scope: &mut impl v8::InIsolate) {
+        self.context.release(scope);
+    }
+}
+
+fn is_unexpected_end(error: &JsError) -> bool {
+    error.message.ends_with("Unexpected end of input")
+        || error.message.ends_with("Unterminated template literal")
+}
+
+/// An uncaught error as a console prints it: the stack if it is an `Error`,
+/// otherwise the message with an excerpt of where it was thrown.
+pub fn format_error(error: &JsError) -> String {
+    match &error.stack {
+        Some(stack) => format!("Uncaught {}", stack),
+        None => format!("Uncaught {}", error.render()),
+    }
+}
+
+/// Whether `source` is a complete input rather than one continuing on the
+/// next line: every bracket, template literal and block comment opened is
+/// closed. Unclosed strings and extra closing brackets count as complete,
+/// for V8 to report.
+pub fn is_complete(source: &str) -> bool {
+    let mut open: Vec<char> = vec![];
+    let mut chars = source.chars().peekable();
+    // the last significant character, to tell a regex from a division
+    let mut last = None;
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                if !skip_string(&mut chars, c) {
+                    // a string cannot continue on the next line
+                    return true;
+                }
+            }
+            '`' => open.push('`'),
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().map(|x| *x != '\n').unwrap_or(false) {
+                    chars.next();
+                }
+                continue;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut star = false;
+                loop {
+                    match chars.next() {
+                        Some('/') if star => break,
+                        Some(c) => star = c == '*',
+                        None => return false,
+                    }
+                }
+                continue;
+            }
+            '/' if last
+                .map(|x| "(,=:[!&|?{};+-*%<>~^".contains(x))
+                .unwrap_or(true) =>
+            {
+                skip_regex(&mut chars);
+            }
+            '(' | '[' | '{' => open.push(c),
+            ')' | ']' | '}' => {
+                if open.pop().is_none() {
+                    return true;
+                }
+            }
+            _ => (),
+        }
+        if !c.is_whitespace() {
+            last = Some(c);
+        }
+        // within a template literal, only `${` and its end are significant
+        while open.last() == Some(&'`') {
+            match chars.next() {
+                Some('\\') => {
+                    chars.next();
+                }
+                Some('`') => {
+                    open.pop();
+                    last = Some('`');
+                }
+                Some('$') if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    open.push('{');
+                    last = Some('{');
+                }
+                Some(_) => (),
+                None => return false,
+            }
+        }
+    }
+    open.is_empty()
+}
+
+/// Skip a string literal up to its closing `quote`, returning `false` if it
+/// is not closed on its line.
+fn skip_string(chars: &mut impl Iterator<Item = char>, quote: char) -> bool {
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\n' => return false,
+            c if c == quote => return true,
+            _ => (),
+        }
+    }
+    false
+}
+
+fn skip_regex(chars: &mut impl Iterator<Item = char>) {
+    let mut class = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' => class = true,
+            ']' => class = false,
+            '/' if !class => return,
+            '\n' => return,
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn continuation() {
+        assert!(is_complete("1 + 1"));
+        assert!(!is_complete("function f() {"));
+        assert!(is_complete("function f() {\n  return 1;\n}"));
+        assert!(!is_complete("[1,\n2"));
+        assert!(is_complete("'{'"));
+        assert!(is_complete("'abc\n{"));
+        assert!(is_complete("x // {"));
+        assert!(!is_complete("/* {"));
+        assert!(is_complete("/* { */ 1"));
+        assert!(!is_complete("`a ${"));
+        assert!(!is_complete("`a ${ {b: 1} }"));
+        assert!(is_complete("`a ${ {b: 1} } c`"));
+        assert!(is_complete("'a'.replace(/[{(]/g, '')"));
+        assert!(is_complete("4 / 2 / 1"));
+        assert!(is_complete("}"));
+    }
+
+    #[test]
+    fn repl() {
+        use crate::{Repl, ReplOutput};
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        let (_, context) = runtime.create_context();
+        let isolate = runtime.isolate();
+        let mut hs = v8::HandleScope::new(isolate);
+        let scope = hs.enter();
+        let context = context.get(scope).unwrap();
+        let mut repl = Repl::new(scope, context);
+        let value = |x: &str| ReplOutput::Value(x.to_string());
+        assert_eq!(repl.feed(scope, "let x = 20"), value("undefined"));
+        assert_eq!(
+            repl.feed(scope, "function add(a, b) {"),
+            ReplOutput::Incomplete
+        );
+        assert_eq!(repl.prompt(), "... ");
+        assert_eq!(repl.feed(scope, "  return a + b;"), ReplOutput::Incomplete);
+        assert_eq!(repl.feed(scope, "}"), value("undefined"));
+        assert_eq!(repl.prompt(), "> ");
+        assert_eq!(repl.feed(scope, "add(x, 22)"), value("42"));
+        assert_eq!(repl.feed(scope, "'a' + x"), value("'a20'"));
+        assert_eq!(repl.feed(scope, "add"), value("[Function: add]"));
+        assert_eq!(
+            repl.feed(scope, "({ a: [1, 2] })"),
+            value("{ a: [ 1, 2 ] }")
+        );
+        assert_eq!(
+            repl.feed(scope, "const o = {}; o.o = o; o"),
+            value("{ o: [Circular] }")
+        );
+        match repl.feed(scope, "(() => { throw new Error('oops'); })()") {
+            ReplOutput::Error(error) => {
+                assert!(error.starts_with("Uncaught Error: oops\n    at <repl:"));
+            }
+            other => panic!("expected an error, got {:?}", other),
+        }
+        assert_eq!(repl.feed(scope, "[1,"), ReplOutput::Incomplete);
+        match repl.feed(scope, "") {
+            ReplOutput::Error(error) => assert!(error.starts_with("Uncaught SyntaxError")),
+            other => panic!("expected an error, got {:?}", other),
+        }
+        assert_eq!(repl.feed(scope, "1 +"), ReplOutput::Incomplete);
+        assert_eq!(repl.feed(scope, "1"), value("2"));
+        repl.release(scope);
+    }
+}
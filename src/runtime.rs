@@ -0,0 +1,342 @@
+//! `Runtime` owns an isolate along with the resources tied to it, and tears
+//! them down in order.
+
+use crate::accounting::{self, ContextUsage, UsageMeter};
+use crate::callbacks;
+use crate::event_loop;
+use crate::module;
+use crate::policy::{self, PolicyHook};
+use crate::util::{self, clear_isolate_slots};
+use crate::{CancellationToken, Realm};
+use rusty_v8 as v8;
+use std::rc::Rc;
+use std::time::Duration;
+use v8::{Global, Isolate, OwnedIsolate};
+
+type Finalizer = Box<dyn FnOnce(&mut Isolate)>;
+type ContextHook = Box<dyn Fn(&mut Isolate, ContextId, &Global<v8::Context>)>;
+
+/// Identifies a context tracked by a `Runtime`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ContextId(u64);
+
+struct TrackedContext {
+    id: ContextId,
+    context: Global<v8::Context>,
+    cleanups: Vec<Finalizer>,
+}
+
+/// Run the cleanups of `tracked`, most recently added first, then the
+/// `disposed` hooks, and release the context along with the callbacks
+/// registered from it, the modules loaded in it and its policy.
+fn dispose_tracked(isolate: &mut Isolate, mut tracked: TrackedContext, disposed: &[ContextHook]) {
+    while let Some(cleanup) = tracked.cleanups.pop() {
+        cleanup(isolate);
+    }
+    for hook in disposed {
+        hook(isolate, tracked.id, &tracked.context);
+    }
+    callbacks::release_context(isolate, &tracked.context);
+    module::release_context(isolate, &tracked.context);
+    util::release_context_functions(isolate, &tracked.context);
+    policy::clear_policy(isolate, tracked.id);
+    accounting::clear_meter(isolate, tracked.id);
+    tracked.context.reset(isolate);
+}
+
+/// `Runtime` owns an isolate and tracks the contexts, cancellation tokens
+/// and finalizers created for it, so that it can be torn down safely.
+///
+/// Teardown, through `shutdown` or on drop without waiting for pending work,
+/// happens in order:
+/// 1. every tracked `CancellationToken` is cancelled,
+/// 2. the event loop is driven until idle, up to the shutdown timeout,
+/// 3. finalizers run, most recently added first,
+/// 4. tracked contexts are disposed, see `Runtime::dispose_context`,
+/// 5. queued tasks and isolate slots are dropped,
+/// 6. the isolate is disposed.
+///
+/// `ObjectWrap`s that outlive the runtime are safe to drop; they notice the
+/// isolate is gone and only release their own reference.
+pub struct Runtime {
+    isolate: Option<OwnedIsolate>,
+    contexts: Vec<TrackedContext>,
+    next_context_id: u64,
+    context_created: Vec<ContextHook>,
+    context_disposed: Vec<ContextHook>,
+    tokens: Vec<CancellationToken>,
+    finalizers: Vec<Finalizer>,
+}
+
+impl Runtime {
+    /// Create a runtime with a new isolate using the default allocator.
+    /// V8 must already be initialized.
+    pub fn new() -> Runtime {
+        let mut create_params = Isolate::create_params();
+        create_params.set_array_buffer_allocator(v8::new_default_allocator());
+        Runtime::from_isolate(Isolate::new(create_params))
+    }
+
+    /// Take ownership of `isolate`.
+    pub fn from_isolate(isolate: OwnedIsolate) -> Runtime {
+        Runtime {
+            isolate: Some(isolate),
+            contexts: vec![],
+            next_context_id: 0,
+            context_created: vec![],
+            context_disposed: vec![],
+            tokens: vec![],
+            finalizers: vec![],
+        }
+    }
+
+    pub fn isolate(&mut self) -> &mut Isolate {
+        self.isolate.as_mut().unwrap()
+    }
+
+    /// Create a new context that is disposed on teardown, running the
+    /// `on_context_created` hooks for it.
+    pub fn create_context(&mut self) -> (ContextId, Global<v8::Context>) {
+        let isolate = self.isolate.as_mut().unwrap();
+        let mut hs = v8::HandleScope::new(&mut **isolate);
+        let scope = hs.enter();
+        let context = v8::Context::new(scope);
+        let tracked = Global::new_from(scope, context);
+        let context = Global::new_from(scope, context);
+        (self.track_context(tracked), context)
+    }
+
+    /// Dispose `context` on teardown, running the `on_context_created`
+    /// hooks for it now.
+    pub fn track_context(&mut self, context: Global<v8::Context>) -> ContextId {
+        let id = self.next_id();
+        let isolate = self.isolate.as_mut().unwrap();
+        for hook in self.context_created.iter() {
+            hook(isolate, id, &context);
+        }
+        self.contexts.push(TrackedContext {
+            id,
+            context,
+            cleanups: vec![],
+        });
+        id
+    }
+
+    fn next_id(&mut self) -> ContextId {
+        let id = ContextId(self.next_context_id);
+        self.next_context_id += 1;
+        id
+    }
+
+    /// Create a `Realm` named `name`, a context that is disposed on
+    /// teardown, or with `dispose_realm`. The `on_context_created` hooks are
+    /// not run for it, so it starts out with nothing but the JS builtins.
+    pub fn create_realm(&mut self, name: &str) -> Realm {
+        let id = self.next_id();
+        let isolate = self.isolate.as_mut().unwrap();
+        let mut hs = v8::HandleScope::new(&mut **isolate);
+        let scope = hs.enter();
+        let context = v8::Context::new(scope);
+        self.contexts.push(TrackedContext {
+            id,
+            context: Global::new_from(scope, context),
+            cleanups: vec![],
+        });
+        Realm::new(id, name, Global::new_from(scope, context))
+    }
+
+    /// Dispose `realm` now, like `dispose_context`.
+    pub fn dispose_realm(&mut self, realm: Realm) -> bool {
+        let id = realm.id();
+        realm.release(self.isolate());
+        self.dispose_context(id)
+    }
+
+    /// Call `hook` for every context created or tracked from now on, i.e.
+    /// to install extensions.
+    pub fn on_context_created(
+        &mut self,
+        hook: impl Fn(&mut Isolate, ContextId, &Global<v8::Context>) + 'static,
+    ) {
+        self.context_created.push(Box::new(hook));
+    }
+
+    /// Call `hook` for every context as it is disposed, before it is
+    /// released.
+    pub fn on_context_disposed(
+        &mut self,
+        hook: impl Fn(&mut Isolate, ContextId, &Global<v8::Context>) + 'static,
+    ) {
+        self.context_disposed.push(Box::new(hook));
+    }
+
+    /// Run `cleanup` when the context `id` is disposed, i.e. to release
+    /// native resources held for it.
+    ///
+    /// Returns `false`, without running `cleanup`, if `id` is not tracked.
+    pub fn on_context_cleanup(
+        &mut self,
+        id: ContextId,
+        cleanup: impl FnOnce(&mut Isolate) + 'static,
+    ) -> bool {
+        match self.contexts.iter_mut().find(|x| x.id == id) {
+            Some(tracked) => {
+                tracked.cleanups.push(Box::new(cleanup));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Dispose the context `id` now: run its cleanups, most recently added
+    /// first, then the `on_context_disposed` hooks, and release it.
+    ///
+    /// Returns `false` if `id` is not tracked.
+    pub fn dispose_context(&mut self, id: ContextId) -> bool {
+        let index = match self.contexts.iter().position(|x| x.id == id) {
+            Some(index) => index,
+            None => return false,
+        };
+        let tracked = self.contexts.remove(index);
+        let isolate = self.isolate.as_mut().unwrap();
+        dispose_tracked(isolate, tracked, &self.context_disposed);
+        true
+    }
+
+    /// Check every `v8_ffi` call from the context `id` with `hook`, replacing
+    /// its previous hook. Denied calls throw a `PermissionError`.
+    ///
+    /// Returns `false` if `id` is not tracked.
+    pub fn set_policy(&mut self, id: ContextId, hook: impl PolicyHook + 'static) -> bool {
+        let tracked = match self.contexts.iter().find(|x| x.id == id) {
+            Some(tracked) => tracked,
+            None => return false,
+        };
+        let isolate = self.isolate.as_mut().unwrap();
+        policy::set_policy(isolate, id, &tracked.context, Rc::new(hook));
+        true
+    }
+
+    /// Remove the policy of the context `id`, allowing every call again.
+    pub fn clear_policy(&mut self, id: ContextId) -> bool {
+        policy::clear_policy(self.isolate(), id)
+    }
+
+    /// Meter the context `id`, attributing the time spent in it and the
+    /// external memory allocated for it, see `accounting`. Returns its
+    /// meter, which is shared with the runtime, or `None` if `id` is not
+    /// tracked.
+    pub fn meter_context(&mut self, id: ContextId) -> Option<UsageMeter> {
+        let tracked = self.contexts.iter().find(|x| x.id == id)?;
+        let isolate = self.isolate.as_mut().unwrap();
+        Some(accounting::meter_context(isolate, id, &tracked.context))
+    }
+
+    /// What the context `id` used since it was metered or its meter was
+    /// last reset, or `None` if it is not metered.
+    pub fn usage(&mut self, id: ContextId) -> Option<ContextUsage> {
+        accounting::meter_of(self.isolate(), id).map(|x| x.usage())
+    }
+
+    /// Cancel `token` on teardown, i.e. for an `AbortSignal` handed to JS.
+    pub fn track_cancellation(&mut self, token: CancellationToken) {
+        self.tokens.push(token);
+    }
+
+    /// Run `finalizer` on teardown, while the isolate is still alive.
+    pub fn on_shutdown(&mut self, finalizer: impl FnOnce(&mut Isolate) + 'static) {
+        self.finalizers.push(Box::new(finalizer));
+    }
+
+    /// Tear down the runtime, giving pending async work up to `timeout` to
+    /// finish first.
+    ///
+    /// Returns `false` if work was still pending when the isolate was
+    /// disposed.
+    pub fn shutdown(mut self, timeout: Duration) -> bool {
+        self.teardown(timeout)
+    }
+
+    fn teardown(&mut self, timeout: Duration) -> bool {
+        let mut isolate = match self.isolate.take() {
+            Some(isolate) => isolate,
+            None => return true,
+        };
+        let isolate: &mut Isolate = &mut isolate;
+        for token in self.tokens.drain(..) {
+            token.cancel();
+        }
+        let idle = event_loop::run_until_idle_timeout(isolate, timeout);
+        while let Some(finalizer) = self.finalizers.pop() {
+            finalizer(isolate);
+        }
+        while let Some(tracked) = self.contexts.pop() {
+            dispose_tracked(isolate, tracked, &self.context_disposed);
+        }
+        event_loop::dispose(isolate);
+        clear_isolate_slots(isolate);
+        idle
+    }
+}
+
+impl Default for Runtime {
+    fn default() -> Runtime {
+        Runtime::new()
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        self.teardown(Duration::from_secs(0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::make_object_wrap;
+    use std::cell::{Cell, RefCell};
+
+    #[test]
+    fn runtime_shutdown() {
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        let events = Rc::new(RefCell::new(vec![]));
+        let created = events.clone();
+        runtime.on_context_created(move |_, id, _| created.borrow_mut().push(("created", id)));
+        let disposed = events.clone();
+        runtime.on_context_disposed(move |_, id, _| disposed.borrow_mut().push(("disposed", id)));
+        let (first, _) = runtime.create_context();
+        let cleaned = events.clone();
+        let cleanup = move |_: &mut v8::Isolate| cleaned.borrow_mut().push(("cleanup", first));
+        assert!(runtime.on_context_cleanup(first, cleanup));
+        assert!(runtime.dispose_context(first));
+        assert!(!runtime.dispose_context(first));
+        assert_eq!(
+            *events.borrow(),
+            vec![("created", first), ("cleanup", first), ("disposed", first)]
+        );
+        let (second, context) = runtime.create_context();
+        let token = crate::CancellationToken::new();
+        runtime.track_cancellation(token.clone());
+        let finalized = Rc::new(Cell::new(false));
+        let flag = finalized.clone();
+        runtime.on_shutdown(move |_| flag.set(true));
+        {
+            let isolate = runtime.isolate();
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            let context = context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            crate::event_loop::enqueue(scope, |_| {});
+            drop(make_object_wrap(scope, context, "wrapped".to_string()));
+        }
+        let mut context = context;
+        context.reset(runtime.isolate());
+        assert!(runtime.shutdown(std::time::Duration::from_millis(100)));
+        assert!(token.is_cancelled());
+        assert!(finalized.get());
+        assert_eq!(events.borrow().last(), Some(&("disposed", second)));
+    }
+}
@@ -0,0 +1,274 @@
+//! Validation of values converted from JS against a JSON Schema, reporting
+//! every violation at once rather than the first serde error.
+//!
+//! The supported keywords are `type`, `enum`, `const`, `properties`,
+//! `required`, `additionalProperties`, `items`, `minItems`, `maxItems`,
+//! `minLength`, `maxLength`, `minimum`, `maximum`, `exclusiveMinimum`,
+//! `exclusiveMaximum` and `anyOf`. Other keywords are ignored.
+
+use crate::de::ROOT;
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// A violation of a schema by the value at `path`, i.e. `value.items[3]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaError {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+/// Validate `value` against the JSON Schema `schema`, returning every
+/// violation found.
+pub fn validate(schema: &Value, value: &Value) -> Result<(), Vec<SchemaError>> {
+    let mut errors = vec![];
+    validate_at(schema, value, ROOT, &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Join the messages of `errors` into one, i.e. for a conversion error.
+pub(crate) fn describe(errors: &[SchemaError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "integer" => value.as_f64().map(|x| x.fract() == 0.0).unwrap_or(false),
+        name => type_name(value) == name,
+    }
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str, errors: &mut Vec<SchemaError>) {
+    let schema = match schema {
+        Value::Object(schema) => schema,
+        // `true` accepts anything, `false` nothing
+        Value::Bool(false) => {
+            return errors.push(SchemaError {
+                path: path.to_string(),
+                message: "no value is allowed here".to_string(),
+            })
+        }
+        _ => return,
+    };
+    let mut error = |message: String| {
+        errors.push(SchemaError {
+            path: path.to_string(),
+            message,
+        })
+    };
+    if let Some(expected) = schema.get("type") {
+        let names: Vec<&str> = match expected {
+            Value::String(name) => vec![name],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        if !names.is_empty() && !names.iter().any(|name| has_type(value, name)) {
+            // a value of the wrong type is not checked any further
+            return error(format!(
+                "expected {}, got {}",
+                names.join(" or "),
+                type_name(value)
+            ));
+        }
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(ToString::to_string).collect();
+            error(format!("must be one of {}", allowed.join(", ")));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            error(format!("must be {}", expected));
+        }
+    }
+    match value {
+        Value::Number(number) => validate_number(schema, number.as_f64().unwrap_or(0.0), error),
+        Value::String(string) => {
+            let length = string.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if length < min {
+                    error(format!("must be at least {} characters long", min));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                if length > max {
+                    error(format!("must be at most {} characters long", max));
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                if (items.len() as u64) < min {
+                    error(format!("must have at least {} items", min));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+                if items.len() as u64 > max {
+                    error(format!("must have at most {} items", max));
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_at(item_schema, item, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        Value::Object(object) => validate_object(schema, object, path, errors),
+        _ => (),
+    }
+    if let Some(Value::Array(options)) = schema.get("anyOf") {
+        let matches = options.iter().any(|option| {
+            let mut option_errors = vec![];
+            validate_at(option, value, path, &mut option_errors);
+            option_errors.is_empty()
+        });
+        if !matches {
+            errors.push(SchemaError {
+                path: path.to_string(),
+                message: "does not match any of the allowed schemas".to_string(),
+            });
+        }
+    }
+}
+
+fn validate_number(schema: &Map<String, Value>, number: f64, mut error: impl FnMut(String)) {
+    let bound = |name: &str| schema.get(name).and_then(Value::as_f64);
+    if let Some(min) = bound("minimum") {
+        if number < min {
+            error(format!("must be at least {}", min));
+        }
+    }
+    if let Some(max) = bound("maximum") {
+        if number > max {
+            error(format!("must be at most {}", max));
+        }
+    }
+    if let Some(min) = bound("exclusiveMinimum") {
+        if number <= min {
+            error(format!("must be greater than {}", min));
+        }
+    }
+    if let Some(max) = bound("exclusiveMaximum") {
+        if number >= max {
+            error(format!("must be less than {}", max));
+        }
+    }
+}
+
+fn validate_object(
+    schema: &Map<String, Value>,
+    object: &Map<String, Value>,
+    path: &str,
+    errors: &mut Vec<SchemaError>,
+) {
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for name in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(name) {
+                errors.push(SchemaError {
+                    path: path.to_string(),
+                    message: format!("missing required property `{}`", name),
+                });
+            }
+        }
+    }
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (name, value) in object {
+        let property_path = format!("{}.{}", path, name);
+        match properties.and_then(|x| x.get(name)) {
+            Some(property) => validate_at(property, value, &property_path, errors),
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => errors.push(SchemaError {
+                    path: property_path,
+                    message: "unexpected property".to_string(),
+                }),
+                Some(additional) => validate_at(additional, value, &property_path, errors),
+                None => (),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn every_violation_is_reported() {
+        let schema = json!({
+            "type": "object",
+            "required": ["name", "items"],
+            "additionalProperties": false,
+            "properties": {
+                "name": { "type": "string", "minLength": 1 },
+                "items": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "price": { "type": "number", "minimum": 0 },
+                            "kind": { "enum": ["a", "b"] },
+                        },
+                    },
+                },
+            },
+        });
+        let value = json!({
+            "name": "",
+            "items": [{ "price": 1, "kind": "a" }, { "price": "1" }, { "price": -1, "kind": "c" }],
+            "extra": true,
+        });
+        let errors = validate(&schema, &value).unwrap_err();
+        assert_eq!(
+            describe(&errors),
+            "value.extra: unexpected property; \
+             value.items[1].price: expected number, got string; \
+             value.items[2].kind: must be one of \"a\", \"b\"; \
+             value.items[2].price: must be at least 0; \
+             value.name: must be at least 1 characters long"
+        );
+        let value = json!({ "name": "x", "items": [] });
+        assert_eq!(validate(&schema, &value), Ok(()));
+        let errors = validate(&schema, &json!({})).unwrap_err();
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn any_of_and_integers() {
+        let schema = json!({ "anyOf": [{ "type": "integer" }, { "type": "string" }] });
+        assert_eq!(validate(&schema, &json!(2.0)), Ok(()));
+        assert_eq!(validate(&schema, &json!("2")), Ok(()));
+        let errors = validate(&schema, &json!(2.5)).unwrap_err();
+        assert_eq!(
+            describe(&errors),
+            "value: does not match any of the allowed schemas"
+        );
+    }
+}
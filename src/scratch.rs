@@ -0,0 +1,68 @@
+//! Scratch buffers for the temporaries of conversions, so that converting
+//! on hot paths does not allocate and free them on every call.
+//!
+//! Strings are pooled per thread and handed out by `ScratchString`, which
+//! returns its buffer to the pool when dropped, so nested conversions, i.e.
+//! from getters calling `v8_ffi` fns, each get their own. Buffers of
+//! `Local`s cannot outlive their scope, so they are kept inline instead.
+
+use rusty_v8 as v8;
+use smallvec::SmallVec;
+use std::cell::RefCell;
+use std::ops::{Deref, DerefMut};
+
+/// How many strings the pool of a thread keeps.
+const POOLED_STRINGS: usize = 8;
+/// Strings grown beyond this are freed rather than pooled.
+const POOLED_CAPACITY: usize = 4096;
+
+thread_local! {
+    static STRINGS: RefCell<Vec<String>> = RefCell::new(vec![]);
+}
+
+/// An empty string taken from the thread's pool, returned to it on drop.
+pub(crate) struct ScratchString(String);
+
+impl ScratchString {
+    pub(crate) fn new() -> ScratchString {
+        let pooled = STRINGS
+            .try_with(|strings| strings.borrow_mut().pop())
+            .ok()
+            .flatten();
+        ScratchString(pooled.unwrap_or_default())
+    }
+}
+
+impl Deref for ScratchString {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        &self.0
+    }
+}
+
+impl DerefMut for ScratchString {
+    fn deref_mut(&mut self) -> &mut String {
+        &mut self.0
+    }
+}
+
+impl Drop for ScratchString {
+    fn drop(&mut self) {
+        if self.0.capacity() > POOLED_CAPACITY {
+            return;
+        }
+        let mut string = std::mem::take(&mut self.0);
+        string.clear();
+        // the pool is gone while the thread exits
+        let _ = STRINGS.try_with(|strings| {
+            let mut strings = strings.borrow_mut();
+            if strings.len() < POOLED_STRINGS {
+                strings.push(string);
+            }
+        });
+    }
+}
+
+/// The elements of an array being built, inline up to 16.
+pub(crate) type Locals<'sc> = SmallVec<[v8::Local<'sc, v8::Value>; 16]>;
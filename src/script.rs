@@ -0,0 +1,221 @@
+use crate::sources::record_source;
+use crate::util::*;
+use crate::{FFICompat, JsError};
+use rusty_v8 as v8;
+use std::convert::TryInto;
+use v8::Global;
+
+/// A script compiled by `compile_only`, ready to be run later without
+/// being parsed again.
+///
+/// A compiled script is bound to the context it was compiled in, so it can
+/// only be run in that context.
+pub struct CompiledScript {
+    script: Global<v8::Script>,
+    context: Global<v8::Context>,
+    resource_name: String,
+}
+
+impl CompiledScript {
+    /// The resource name the script was compiled with.
+    pub fn resource_name(&self) -> &str {
+        &self.resource_name
+    }
+
+    /// Run the script, returning its completion value.
+    ///
+    /// Returns an error if `context` is not the context the script was
+    /// compiled in.
+    pub fn run<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, JsError> {
+        let target = context.global(scope);
+        let compiled_in = self.context.get(scope).unwrap().global(scope);
+        if !compiled_in.strict_equals(target.into()) {
+            return Err(JsError::new(format!(
+                "{} was compiled in another context",
+                self.resource_name
+            )));
+        }
+        let mut script = self.script.get(scope).unwrap();
+        let mut try_catch = v8::TryCatch::new(scope);
+        let tc = try_catch.enter();
+        let result = script.run(scope, context);
+        if tc.has_caught() {
+            return Err(JsError::from_try_catch(scope, context, tc));
+        }
+        Ok(result.unwrap_or_else(|| v8::undefined(scope).into()))
+    }
+}
+
+/// Compile `source` under the resource name `origin` without running it.
+///
+/// Syntax errors are returned as a `JsError` carrying the line and column
+/// V8 reported. The source is recorded under `origin` for the excerpts of
+/// errors, see `sources::record_source`.
+pub fn compile_only<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    source: &str,
+    origin: &str,
+) -> Result<CompiledScript, JsError> {
+    record_source(scope, origin, source);
+    let script_origin = make_script_origin(scope, origin, false);
+    let source = v8::String::new(scope, source).unwrap();
+    let mut try_catch = v8::TryCatch::new(scope);
+    let tc = try_catch.enter();
+    let script = v8::Script::compile(scope, context, source, Some(&script_origin));
+    match script {
+        Some(script) if !tc.has_caught() => Ok(CompiledScript {
+            script: Global::new_from(scope, script),
+            context: Global::new_from(scope, context),
+            resource_name: origin.to_string(),
+        }),
+        _ => Err(JsError::from_try_catch(scope, context, tc)),
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' || c == '$' => (),
+        _ => return false,
+    }
+    chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Evaluate the expression `expr` with each of `bindings` in scope as a
+/// local variable, converting the result to `T`.
+///
+/// The values are passed as arguments to a function wrapping `expr` rather
+/// than being spliced into the source, so they cannot change how the
+/// expression parses.
+pub fn eval_with_bindings<'sc, 'c, T: FFICompat<'sc, 'c>>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    expr: &str,
+    bindings: &[(&str, v8::Local<'sc, v8::Value>)],
+) -> Result<T, JsError> {
+    let mut names = Vec::with_capacity(bindings.len());
+    let mut args = Vec::with_capacity(bindings.len());
+    for (name, value) in bindings {
+        if !is_identifier(name) {
+            return Err(JsError::new(format!("invalid binding name: {:?}", name)));
+        }
+        names.push(*name);
+        args.push(*value);
+    }
+    let source = format!(
+        "(function ({}) {{ return (\n{}\n); }})",
+        names.join(", "),
+        expr
+    );
+    let function = compile_only(scope, context, &source, "<expression>")?.run(scope, context)?;
+    let mut function: v8::Local<v8::Function> = function
+        .try_into()
+        .map_err(|_| JsError::new("expression did not compile to a function"))?;
+    let recv = v8::undefined(scope).into();
+    let mut try_catch = v8::TryCatch::new(scope);
+    let tc = try_catch.enter();
+    let result = function.call(scope, context, recv, &args);
+    if tc.has_caught() {
+        return Err(JsError::from_try_catch(scope, context, tc));
+    }
+    let result = result.unwrap_or_else(|| v8::undefined(scope).into());
+    T::from_value(result, scope, context).map_err(|e| JsError::new(format!("{:?}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compile_only_errors() {
+        with_context!(|scope, context| {
+            let error = crate::compile_only(scope, context, "let x = 1;\nlet = ;", "upload.js")
+                .err()
+                .unwrap();
+            assert_eq!(error.resource_name.as_deref(), Some("upload.js"));
+            assert_eq!(error.line, Some(2));
+            assert_eq!(
+                error.render(),
+                "SyntaxError: Unexpected token '='\n \
+                 --> upload.js:2:5\n  \
+                   |\n\
+                 1 | let x = 1;\n\
+                 2 | let = ;\n  \
+                   |     ^"
+            );
+            let compiled = crate::compile_only(
+                scope,
+                context,
+                "let y = 1;\nconst f = undefined;\n\n  f(y);\n",
+                "runtime.js",
+            )
+            .unwrap();
+            let error = compiled.run(scope, context).err().unwrap();
+            assert!(error
+                .excerpt
+                .unwrap()
+                .starts_with("  |\n2 | const f = undefined;\n3 | \n4 |   f(y);\n  |   ^"));
+            assert_eq!(
+                crate::sources::source_of(scope, "runtime.js").as_deref(),
+                Some("let y = 1;\nconst f = undefined;\n\n  f(y);\n")
+            );
+            assert!(crate::sources::forget_source(scope, "runtime.js"));
+            let compiled = crate::compile_only(scope, context, "20 + 22", "upload.js").unwrap();
+            let result = compiled.run(scope, context).unwrap();
+            assert_eq!(i32::from_value(result, scope, context), Ok(42));
+        });
+    }
+
+    #[test]
+    fn other_context() {
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        let (_, mut first) = runtime.create_context();
+        let (_, mut second) = runtime.create_context();
+        {
+            let mut hs = v8::HandleScope::new(runtime.isolate());
+            let scope = hs.enter();
+            let first = first.get(scope).unwrap();
+            let second = second.get(scope).unwrap();
+            let compiled = {
+                let mut cs = v8::ContextScope::new(scope, first);
+                let scope = cs.enter();
+                let compiled = crate::compile_only(scope, first, "40 + 2", "answer.js").unwrap();
+                let result = compiled.run(scope, first).unwrap();
+                assert_eq!(i32::from_value(result, scope, first), Ok(42));
+                compiled
+            };
+            let mut cs = v8::ContextScope::new(scope, second);
+            let scope = cs.enter();
+            let error = compiled.run(scope, second).err().unwrap();
+            assert_eq!(error.message, "answer.js was compiled in another context");
+        }
+        first.reset(runtime.isolate());
+        second.reset(runtime.isolate());
+    }
+
+    #[test]
+    fn eval_with_bindings() {
+        with_context!(|scope, context| {
+            let bindings = [
+                ("x", make_num(scope, 4.0)),
+                ("y", make_str(scope, "); throw 1; (")),
+            ];
+            let result: i32 =
+                crate::eval_with_bindings(scope, context, "x * 2 + y.length", &bindings).unwrap();
+            assert_eq!(result, 21);
+            assert!(crate::eval_with_bindings::<i32>(
+                scope,
+                context,
+                "1",
+                &[("a b", make_num(scope, 1.0))]
+            )
+            .is_err());
+        });
+    }
+}
@@ -0,0 +1,248 @@
+//! The parts of the V8 API used by this crate that differ between the
+//! `rusty_v8_protryon` fork and upstream `rusty_v8`, selected by the
+//! `upstream-v8` feature.
+//!
+//! Upstream `rusty_v8` 0.3 has no weak handle callbacks, so with
+//! `upstream-v8`, `set_weak` panics rather than keeping the value and its
+//! owner alive for the life of the process. That is, `ObjectWrap::make_weak`
+//! and `WeakJsRef::new` panic, and so does everything relying on them, i.e.
+//! returning wrapped objects from `v8_ffi` fns. Strong handles work and are
+//! released as their owner is dropped. Upstream has no `IsolateHandle`
+//! either, so an isolate counts as alive until `clear_isolate_slots` is
+//! called for it, which must happen before it is disposed, as `Runtime`
+//! does.
+
+use rusty_v8 as v8;
+use std::rc::Rc;
+use v8::Global;
+#[cfg(not(feature = "upstream-v8"))]
+use {
+    std::cell::RefCell,
+    std::ffi::c_void,
+    std::ptr::NonNull,
+    v8::{Isolate, IsolateHandle, WeakCallback, Weakable},
+};
+
+/// Read the pointer stored in internal field `index` of `object`.
+///
+/// # Safety
+/// The field must have been set with `set_internal_field_ptr`.
+#[cfg(not(feature = "upstream-v8"))]
+pub(crate) unsafe fn internal_field_ptr<T>(object: v8::Local<v8::Object>, index: usize) -> *mut T {
+    object.get_internal_field_ptr::<T>(index)
+}
+
+/// Store `ptr` in internal field `index` of `object`.
+///
+/// # Safety
+/// `object` must have more than `index` internal fields, and `ptr` must be
+/// 2 byte aligned.
+#[cfg(not(feature = "upstream-v8"))]
+pub(crate) unsafe fn set_internal_field_ptr<T>(
+    object: &mut v8::Local<v8::Object>,
+    index: usize,
+    ptr: *mut T,
+) {
+    object.set_internal_field_ptr(index, ptr)
+}
+
+#[cfg(feature = "upstream-v8")]
+pub(crate) unsafe fn internal_field_ptr<T>(object: v8::Local<v8::Object>, index: usize) -> *mut T {
+    object.get_aligned_pointer_from_internal_field(index as i32) as *mut T
+}
+
+#[cfg(feature = "upstream-v8")]
+pub(crate) unsafe fn set_internal_field_ptr<T>(
+    object: &mut v8::Local<v8::Object>,
+    index: usize,
+    ptr: *mut T,
+) {
+    object.set_aligned_pointer_in_internal_field(index as i32, ptr as *const std::ffi::c_void)
+}
+
+/// The names of the own enumerable properties of `object`.
+#[cfg(not(feature = "upstream-v8"))]
+pub(crate) fn own_property_names<'sc>(
+    object: v8::Local<'sc, v8::Object>,
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+) -> Vec<String> {
+    object
+        .get_own_property_names(scope, context)
+        .unwrap_or_default()
+}
+
+#[cfg(feature = "upstream-v8")]
+pub(crate) fn own_property_names<'sc>(
+    object: v8::Local<'sc, v8::Object>,
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+) -> Vec<String> {
+    let names = match object.get_own_property_names(scope, context) {
+        Some(names) => names,
+        None => return vec![],
+    };
+    let mut out = Vec::with_capacity(names.length() as usize);
+    for i in 0..names.length() {
+        if let Some(name) = names.get_index(scope, context, i) {
+            out.push(name.to_rust_string_lossy(scope));
+        }
+    }
+    out
+}
+
+/// The isolate a handle was created in, to release the handle as its owner
+/// is dropped, which may be after the isolate is disposed.
+#[cfg(not(feature = "upstream-v8"))]
+pub(crate) struct IsolateRef(IsolateHandle);
+
+#[cfg(not(feature = "upstream-v8"))]
+impl IsolateRef {
+    pub(crate) fn new(scope: &mut impl v8::InIsolate) -> IsolateRef {
+        IsolateRef(IsolateHandle::new(scope.isolate()))
+    }
+
+    /// The isolate, or null once it is disposed.
+    pub(crate) fn isolate_ptr(&self) -> *mut v8::Isolate {
+        unsafe { self.0.get_isolate_ptr() }
+    }
+}
+
+/// Kept in the isolate slots of an isolate while it is alive, see the
+/// module docs.
+#[cfg(feature = "upstream-v8")]
+struct IsolateAlive;
+
+#[cfg(feature = "upstream-v8")]
+pub(crate) struct IsolateRef {
+    isolate: *mut v8::Isolate,
+    alive: std::rc::Weak<IsolateAlive>,
+}
+
+#[cfg(feature = "upstream-v8")]
+impl IsolateRef {
+    pub(crate) fn new(scope: &mut impl v8::InIsolate) -> IsolateRef {
+        use crate::util::{isolate_slot, set_isolate_slot};
+        let alive = match isolate_slot::<IsolateAlive>(scope) {
+            Some(alive) => alive,
+            None => {
+                set_isolate_slot(scope, IsolateAlive);
+                isolate_slot::<IsolateAlive>(scope).unwrap()
+            }
+        };
+        IsolateRef {
+            isolate: scope.isolate(),
+            alive: Rc::downgrade(&alive),
+        }
+    }
+
+    /// The isolate, or null once its isolate slots are cleared.
+    pub(crate) fn isolate_ptr(&self) -> *mut v8::Isolate {
+        match self.alive.upgrade() {
+            Some(_) => self.isolate,
+            None => std::ptr::null_mut(),
+        }
+    }
+}
+
+/// The owner of a `Global` that can be made weak with `set_weak`, told when
+/// V8 collects the value.
+pub(crate) trait WeakOwner: 'static {
+    /// The value was collected: let go of the handle, with
+    /// `release_collected`, and of what the value kept alive.
+    fn collected(&self, isolate: &mut v8::Isolate);
+}
+
+/// Holds the `WeakOwner` of a `Global` for V8, which passes it back to
+/// `weak_owner_callback`.
+#[cfg(not(feature = "upstream-v8"))]
+struct WeakOwnerCell<W> {
+    owner: Rc<W>,
+    v8_reference: RefCell<Option<*const Self>>,
+}
+
+#[cfg(not(feature = "upstream-v8"))]
+unsafe impl<T: 'static, W: WeakOwner> Weakable<T> for WeakOwnerCell<W> {
+    fn get(self: Rc<Self>, _global: &Global<T>) -> NonNull<c_void> {
+        let v8_reference = Rc::into_raw(self.clone());
+        assert_eq!(self.v8_reference.replace(Some(v8_reference)), None);
+        unsafe { NonNull::new_unchecked(v8_reference as *mut c_void) }
+    }
+
+    fn clear(&self, _global: &Global<T>) {
+        unsafe { Rc::from_raw(self.v8_reference.borrow_mut().take().unwrap()) };
+    }
+
+    fn get_callback(&self, _global: &Global<T>) -> WeakCallback<c_void> {
+        weak_owner_callback::<W>
+    }
+}
+
+#[cfg(not(feature = "upstream-v8"))]
+extern "C" fn weak_owner_callback<W: WeakOwner>(
+    value: NonNull<c_void>,
+    mut isolate: NonNull<Isolate>,
+) {
+    let cell = unsafe { Rc::from_raw(value.cast::<WeakOwnerCell<W>>().as_ptr()) };
+    cell.v8_reference.borrow_mut().take();
+    cell.owner.collected(unsafe { isolate.as_mut() });
+}
+
+/// Let `global` be made weak with `set_weak`, telling `owner` when the value
+/// is collected. `global` keeps `owner` alive.
+#[cfg(not(feature = "upstream-v8"))]
+pub(crate) fn set_weak_owner<T: 'static, W: WeakOwner>(global: &mut Global<T>, owner: Rc<W>) {
+    global.set_weakable(Rc::new(WeakOwnerCell {
+        owner,
+        v8_reference: RefCell::new(None),
+    }));
+}
+
+/// Let V8 collect the value of `global` once nothing else refers to it.
+#[cfg(not(feature = "upstream-v8"))]
+pub(crate) fn set_weak<T>(global: &mut Global<T>) {
+    global.set_weak();
+}
+
+#[cfg(not(feature = "upstream-v8"))]
+pub(crate) fn clear_weak<T>(global: &mut Global<T>) {
+    global.clear_weak();
+}
+
+#[cfg(not(feature = "upstream-v8"))]
+pub(crate) fn is_weak<T>(global: &mut Global<T>) -> bool {
+    global.is_weak()
+}
+
+/// Let go of `global` from `WeakOwner::collected`, as V8 already released
+/// the value.
+#[cfg(not(feature = "upstream-v8"))]
+pub(crate) fn release_collected<T>(mut global: Global<T>, isolate: &mut v8::Isolate) {
+    global.set_isolate(isolate, None);
+}
+
+#[cfg(feature = "upstream-v8")]
+const NO_WEAK_HANDLES: &str = "weak handles are not supported with the `upstream-v8` feature, \
+    as upstream rusty_v8 has no weak callbacks; use the default `protryon` feature";
+
+/// Upstream never makes `global` weak, so `owner` is not needed.
+#[cfg(feature = "upstream-v8")]
+pub(crate) fn set_weak_owner<T: 'static, W: WeakOwner>(_global: &mut Global<T>, _owner: Rc<W>) {}
+
+#[cfg(feature = "upstream-v8")]
+pub(crate) fn set_weak<T>(_global: &mut Global<T>) {
+    panic!("{}", NO_WEAK_HANDLES);
+}
+
+#[cfg(feature = "upstream-v8")]
+pub(crate) fn clear_weak<T>(_global: &mut Global<T>) {}
+
+#[cfg(feature = "upstream-v8")]
+pub(crate) fn is_weak<T>(_global: &mut Global<T>) -> bool {
+    false
+}
+
+#[cfg(feature = "upstream-v8")]
+pub(crate) fn release_collected<T>(_global: Global<T>, _isolate: &mut v8::Isolate) {
+    unreachable!("{}", NO_WEAK_HANDLES);
+}
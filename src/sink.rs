@@ -0,0 +1,245 @@
+use crate::event_loop;
+use crate::instrument::observer;
+use crate::shim::IsolateRef;
+use crate::util::{call_function, describe_error, isolate_key};
+use crate::FFICompat;
+use rusty_v8 as v8;
+use std::any::Any;
+use std::convert::TryInto;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use v8::Global;
+
+/// The name `Sink::push` failures are reported under to the `FfiObserver`.
+const PUSH: &str = "Sink::push";
+
+struct SinkInner {
+    callback: Global<v8::Function>,
+    context: Global<v8::Context>,
+    isolate_key: usize,
+    isolate: IsolateRef,
+}
+
+impl Drop for SinkInner {
+    fn drop(&mut self) {
+        // without the isolate, the handles can only be leaked
+        if let Some(isolate) = unsafe { self.isolate.isolate_ptr().as_mut() } {
+            self.callback.reset(isolate);
+            self.context.reset(isolate);
+        }
+    }
+}
+
+/// `Sink` is an FFI argument type for a JS callback that a native function
+/// can push any number of `T` values into.
+///
+/// Values can be pushed synchronously while the FFI call is running with
+/// `Sink::push_now`, or at any later point on the isolate thread with
+/// `Sink::push`, which schedules the callback on the event loop.
+///
+/// The callback and its context are held by `Global` handle until the last
+/// clone of the `Sink` is dropped or `release`d. Dropping it only resets the
+/// handles while the isolate is alive, so `release` it on the isolate's
+/// thread where that matters.
+pub struct Sink<T> {
+    inner: Rc<SinkInner>,
+    _marker: PhantomData<fn(T)>,
+}
+
+impl<T> Clone for Sink<T> {
+    fn clone(&self) -> Self {
+        Sink {
+            inner: self.inner.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Sink<T> {
+    /// Call the JS callback with `item` immediately.
+    /// Any exception thrown by the callback is left pending.
+    pub fn push_now<'sc, 'c>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+        item: T,
+    ) -> Result<(), T::E>
+    where
+        T: FFICompat<'sc, 'c>,
+    {
+        let value = item.to_value(scope, context)?;
+        let mut callback = self.inner.callback.get(scope).unwrap();
+        let receiver = v8::undefined(scope).into();
+        callback.call(scope, context, receiver, &[value]);
+        Ok(())
+    }
+
+    /// Schedule the JS callback to be called with `item` on the event loop.
+    ///
+    /// As nothing is left to return them to, a failed conversion of `item`
+    /// and any exception thrown by the callback are reported to the
+    /// isolate's `FfiObserver` as `on_conversion_error` and `on_exception`
+    /// of `"Sink::push"`.
+    pub fn push<E>(&self, item: T)
+    where
+        T: for<'sc, 'c> FFICompat<'sc, 'c, E = E> + 'static,
+        E: Debug + Any,
+    {
+        let inner = self.inner.clone();
+        event_loop::enqueue_for(self.inner.isolate_key, move |isolate| {
+            let mut hs = v8::HandleScope::new(isolate);
+            let scope = hs.enter();
+            let context = inner.context.get(scope).unwrap();
+            let mut cs = v8::ContextScope::new(scope, context);
+            let scope = cs.enter();
+            let value = match item.to_value(scope, context) {
+                Ok(value) => value,
+                Err(error) => {
+                    if let Some(observer) = observer(scope) {
+                        observer.on_conversion_error(PUSH, &describe_error(&error));
+                    }
+                    return;
+                }
+            };
+            let callback = inner.callback.get(scope).unwrap();
+            let receiver = v8::undefined(scope).into();
+            if let Err(error) = call_function(scope, context, callback, receiver, &[value]) {
+                if let Some(observer) = observer(scope) {
+                    observer.on_exception(PUSH, &describe_error(&error));
+                }
+            }
+        });
+    }
+
+    /// Release this clone of the `Sink`, resetting the handles to the
+    /// callback and its context if it is the last one.
+    pub fn release(self, scope: &mut impl v8::InIsolate) {
+        if let Ok(mut inner) = Rc::try_unwrap(self.inner) {
+            inner.callback.reset(scope);
+            inner.context.reset(scope);
+        }
+    }
+}
+
+impl<'sc, 'c, T> FFICompat<'sc, 'c> for Sink<T> {
+    type E = String;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, String> {
+        let callback: v8::Local<'sc, v8::Function> = value
+            .try_into()
+            .map_err(|_| "invalid type for argument in ffi call, expected function".to_string())?;
+        Ok(Sink {
+            inner: Rc::new(SinkInner {
+                callback: Global::new_from(scope, callback),
+                context: Global::new_from(scope, context),
+                isolate_key: isolate_key(scope),
+                isolate: IsolateRef::new(scope),
+            }),
+            _marker: PhantomData,
+        })
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, String> {
+        Ok(self.inner.callback.get(scope).unwrap().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrument::{set_ffi_observer, FfiObserver};
+    use crate::util::run_script;
+    use crate::FFIError;
+    use rusty_v8_helper_derive::v8_ffi;
+    use std::cell::RefCell;
+
+    /// Converts to its number, or fails to convert when `None`.
+    struct TestItem(Option<u32>);
+
+    impl<'sc, 'c> FFICompat<'sc, 'c> for TestItem {
+        type E = FFIError;
+
+        fn from_value(
+            _value: v8::Local<'sc, v8::Value>,
+            _scope: &mut impl v8::ToLocal<'sc>,
+            _context: v8::Local<'c, v8::Context>,
+        ) -> Result<Self, FFIError> {
+            Err(FFIError::TypeError("only pushed".to_string()))
+        }
+
+        fn to_value(
+            self,
+            scope: &mut impl v8::ToLocal<'sc>,
+            context: v8::Local<'c, v8::Context>,
+        ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+            match self.0 {
+                Some(x) => x.to_value(scope, context).map_err(FFIError::Error),
+                None => Err(FFIError::RangeError("unconvertible".to_string())),
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct TestObserver(RefCell<Vec<String>>);
+
+    impl FfiObserver for Rc<TestObserver> {
+        fn on_conversion_error(&self, function: &'static str, error: &str) {
+            self.0
+                .borrow_mut()
+                .push(format!("{} conversion {}", function, error));
+        }
+
+        fn on_exception(&self, function: &'static str, error: &str) {
+            self.0
+                .borrow_mut()
+                .push(format!("{} exception {}", function, error));
+        }
+    }
+
+    #[v8_ffi(scoped)]
+    fn test_ffi_push<'sc, 'c>(
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+        sink: Sink<TestItem>,
+    ) {
+        for item in &[Some(1), None, Some(2), Some(3)] {
+            sink.push(TestItem(*item));
+        }
+        sink.release(scope);
+    }
+
+    #[test]
+    fn push_errors() {
+        let observer = Rc::new(TestObserver::default());
+        with_context!([test_ffi_push], |scope, context| {
+            set_ffi_observer(scope, observer.clone());
+            run_script(
+                scope,
+                context,
+                "globalThis.log = []; test_ffi_push(x => { if (x === 2) throw new Error('two'); log.push(x); })",
+            )
+            .unwrap();
+            event_loop::run_pending(scope);
+            let log = run_script(scope, context, "log.join()").unwrap();
+            assert_eq!(
+                String::from_value(log, scope, context),
+                Ok("1,3".to_string())
+            );
+        });
+        let reports = observer.0.borrow();
+        assert_eq!(reports.len(), 2);
+        assert!(
+            reports[0].starts_with("Sink::push conversion") && reports[0].contains("unconvertible")
+        );
+        assert!(reports[1].starts_with("Sink::push exception") && reports[1].contains("two"));
+    }
+}
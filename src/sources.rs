@@ -0,0 +1,117 @@
+//! The source text of scripts and modules compiled through this crate,
+//! kept per isolate by resource name so that a `JsError` can show the lines
+//! around where it was thrown.
+
+use crate::util::{isolate_slot, set_isolate_slot};
+use rusty_v8 as v8;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// How many lines before the offending one an excerpt shows.
+const CONTEXT_LINES: usize = 2;
+
+#[derive(Default)]
+struct SourceStore(RefCell<HashMap<String, Rc<str>>>);
+
+fn store(scope: &mut impl v8::InIsolate) -> Rc<SourceStore> {
+    if let Some(store) = isolate_slot::<SourceStore>(scope) {
+        return store;
+    }
+    set_isolate_slot(scope, SourceStore::default());
+    isolate_slot::<SourceStore>(scope).unwrap()
+}
+
+/// Keep `source` as the text of the script or module `resource_name`.
+/// `compile_only` and the module loader do this for everything they
+/// compile; use it for scripts compiled otherwise.
+pub fn record_source(scope: &mut impl v8::InIsolate, resource_name: &str, source: &str) {
+    store(scope)
+        .0
+        .borrow_mut()
+        .insert(resource_name.to_string(), source.into());
+}
+
+/// The text recorded for `resource_name`.
+pub fn source_of(scope: &mut impl v8::InIsolate, resource_name: &str) -> Option<Rc<str>> {
+    isolate_slot::<SourceStore>(scope)?
+        .0
+        .borrow()
+        .get(resource_name)
+        .cloned()
+}
+
+/// Drop the text recorded for `resource_name`, i.e. once a script that
+/// is not run again has finished.
+pub fn forget_source(scope: &mut impl v8::InIsolate, resource_name: &str) -> bool {
+    match isolate_slot::<SourceStore>(scope) {
+        Some(store) => store.0.borrow_mut().remove(resource_name).is_some(),
+        None => false,
+    }
+}
+
+/// Render the 1-based `line` of `source`, whose first line is numbered
+/// `first_line`, with the lines before it and a caret under the 0-based
+/// columns `start..end`, as rustc does:
+///
+/// ```text
+///   |
+/// 1 | let x = 1;
+/// 2 | x();
+///   | ^^^
+/// ```
+pub(crate) fn excerpt(
+    source: &str,
+    first_line: usize,
+    line: usize,
+    start: usize,
+    end: usize,
+) -> Option<String> {
+    let index = line.checked_sub(first_line)?;
+    let lines: Vec<&str> = source.lines().collect();
+    let text = lines.get(index)?;
+    let width = line.to_string().len();
+    let mut out = format!("{:w$} |\n", "", w = width);
+    for (i, shown) in lines
+        .iter()
+        .enumerate()
+        .take(index + 1)
+        .skip(index.saturating_sub(CONTEXT_LINES))
+    {
+        out.push_str(&format!("{:>w$} | {}\n", first_line + i, shown, w = width));
+    }
+    // keep tabs so the caret lines up with the text above it
+    let padding: String = text
+        .chars()
+        .take(start)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let carets = "^".repeat(end.saturating_sub(start).max(1));
+    out.push_str(&format!("{:w$} | {}{}", "", padding, carets, w = width));
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn excerpts() {
+        let source = "let a = 1;\nlet b = 2;\nlet c = 3;\n\tb();\nlet d = 4;";
+        assert_eq!(
+            excerpt(source, 1, 4, 1, 4).unwrap(),
+            "  |\n\
+             2 | let b = 2;\n\
+             3 | let c = 3;\n\
+             4 | \tb();\n  \
+               | \t^^^"
+        );
+        assert_eq!(excerpt("x()", 1, 1, 0, 0).unwrap(), "  |\n1 | x()\n  | ^");
+        assert_eq!(
+            excerpt("y();", 10, 10, 0, 1).unwrap(),
+            "   |\n10 | y();\n   | ^"
+        );
+        assert_eq!(excerpt(source, 1, 9, 0, 1), None);
+        assert_eq!(excerpt(source, 5, 4, 0, 1), None);
+    }
+}
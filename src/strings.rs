@@ -0,0 +1,130 @@
+use crate::util::type_of;
+use crate::FFICompat;
+use crate::FFIError;
+use rusty_v8 as v8;
+use std::convert::TryInto;
+use std::ops::Deref;
+
+fn string_units<'sc>(
+    value: v8::Local<'sc, v8::Value>,
+    scope: &mut impl v8::ToLocal<'sc>,
+    expected: &str,
+) -> Result<Vec<u16>, FFIError> {
+    let string: v8::Local<v8::String> = value.try_into().map_err(|_| {
+        FFIError::TypeError(format!(
+            "invalid type for argument in ffi call, expected {}, received {}",
+            expected,
+            type_of(value)
+        ))
+    })?;
+    let mut units = vec![0u16; string.length()];
+    string.write(scope, &mut units, 0, v8::WriteOptions::NO_NULL_TERMINATION);
+    Ok(units)
+}
+
+fn new_string_from_units<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    units: &[u16],
+) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+    v8::String::new_from_two_byte(scope, units, v8::NewStringType::Normal)
+        .map(|x| x.into())
+        .ok_or_else(|| FFIError::RangeError("string too long".to_string()))
+}
+
+/// `StrictUtf8` is an FFI string type that fails on strings containing
+/// unpaired surrogates, where `String` would silently replace them with
+/// U+FFFD.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct StrictUtf8(pub String);
+
+impl Deref for StrictUtf8 {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        &self.0
+    }
+}
+
+impl From<StrictUtf8> for String {
+    fn from(value: StrictUtf8) -> String {
+        value.0
+    }
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for StrictUtf8 {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        let units = string_units(value, scope, "string")?;
+        String::from_utf16(&units).map(StrictUtf8).map_err(|_| {
+            FFIError::TypeError(
+                "string argument is not valid unicode (unpaired surrogate)".to_string(),
+            )
+        })
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        Ok(v8::String::new(scope, &self.0)
+            .ok_or_else(|| FFIError::RangeError("string too long".to_string()))?
+            .into())
+    }
+}
+
+/// `Utf16String` holds the exact UTF-16 code units of a JS string, so any
+/// JS string, including ones with unpaired surrogates, round-trips unchanged.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Utf16String(pub Vec<u16>);
+
+impl Utf16String {
+    /// Convert to a `String`, failing on unpaired surrogates.
+    pub fn to_utf8(&self) -> Result<String, std::string::FromUtf16Error> {
+        String::from_utf16(&self.0)
+    }
+
+    /// Convert to a `String`, replacing unpaired surrogates with U+FFFD.
+    pub fn to_utf8_lossy(&self) -> String {
+        String::from_utf16_lossy(&self.0)
+    }
+}
+
+impl Deref for Utf16String {
+    type Target = [u16];
+
+    fn deref(&self) -> &[u16] {
+        &self.0
+    }
+}
+
+impl From<&str> for Utf16String {
+    fn from(value: &str) -> Utf16String {
+        Utf16String(value.encode_utf16().collect())
+    }
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for Utf16String {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        string_units(value, scope, "string").map(Utf16String)
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        new_string_from_units(scope, &self.0)
+    }
+}
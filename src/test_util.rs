@@ -0,0 +1,31 @@
+//! Helpers for the V8 tests of each module.
+
+/// Runs `body` with `scope` and `context` entered in a fresh context of a new
+/// `Runtime`, with the `#[v8_ffi]` fns listed in brackets set as globals of
+/// the same name, and resets the context afterwards. A macro rather than a
+/// fn taking a closure, as the entered scope types cannot be named.
+macro_rules! with_context {
+    ([$($function:ident),* $(,)?], |$scope:ident, $context:ident| $body:expr) => {{
+        crate::initialize_v8();
+        let mut runtime = crate::Runtime::new();
+        let (_, mut global_context) = runtime.create_context();
+        let result = {
+            let mut hs = crate::v8::HandleScope::new(runtime.isolate());
+            let $scope = hs.enter();
+            let $context = global_context.get($scope).unwrap();
+            let mut cs = crate::v8::ContextScope::new($scope, $context);
+            let $scope = cs.enter();
+            $(
+                let function = load_v8_ffi!($function, $scope, $context);
+                let name = crate::util::make_str($scope, stringify!($function));
+                $context.global($scope).set($context, name, function);
+            )*
+            $body
+        };
+        global_context.reset(runtime.isolate());
+        result
+    }};
+    (|$scope:ident, $context:ident| $body:expr) => {
+        with_context!([], |$scope, $context| $body)
+    };
+}
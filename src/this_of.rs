@@ -0,0 +1,70 @@
+//! `ThisOf`, a wrapped `this` that may be one of several types, so one
+//! `v8_ffi` fn can be installed as a method on multiple native classes.
+
+use crate::{ObjectWrap, WrapError};
+use rusty_v8 as v8;
+use std::any::Any;
+use std::rc::Rc;
+use v8::{Local, Object};
+
+/// Resolution of an `Object` to one of several wrapped types.
+pub trait FromThis: Sized {
+    fn from_this(object: Local<Object>) -> Result<Self, WrapError>;
+}
+
+/// A tuple of the types accepted by `ThisOf`.
+pub trait ThisTypes {
+    type Resolved: FromThis;
+}
+
+/// `ThisOf<(A, B)>` is a `this` wrapping either an `A` or a `B`, resolved to
+/// `OneOf2::A` or `OneOf2::B`. Up to 4 types are supported.
+///
+/// ```ignore
+/// #[v8_ffi]
+/// fn close(this: &ThisOf<(FileHandle, SocketHandle)>) {
+///     match this {
+///         OneOf2::A(file) => file.close(),
+///         OneOf2::B(socket) => socket.close(),
+///     }
+/// }
+/// ```
+///
+/// Types are tried in order, so the first matching type wins if a type is
+/// listed twice or resolves through `ObjectWrap::register_cast`.
+pub type ThisOf<T> = <T as ThisTypes>::Resolved;
+
+macro_rules! this_of {
+    ($name:ident, $($ty:ident),+) => {
+        /// A `ThisOf` resolved to one of its types, the variants being named
+        /// by position.
+        pub enum $name<$($ty: Any + 'static),+> {
+            $($ty(Rc<$ty>)),+
+        }
+
+        impl<$($ty: Any + 'static),+> ThisTypes for ($($ty,)+) {
+            type Resolved = $name<$($ty),+>;
+        }
+
+        impl<$($ty: Any + 'static),+> FromThis for $name<$($ty),+> {
+            fn from_this(object: Local<Object>) -> Result<Self, WrapError> {
+                let mut found = String::new();
+                $(
+                    match ObjectWrap::<$ty>::try_from_object(object) {
+                        Ok(wrapped) => return Ok($name::$ty(wrapped)),
+                        Err(WrapError::WrongType { found: other, .. }) => found = other,
+                        Err(e) => return Err(e),
+                    }
+                )+
+                Err(WrapError::WrongType {
+                    expected: vec![$(std::any::type_name::<$ty>()),+].join(" | "),
+                    found,
+                })
+            }
+        }
+    };
+}
+
+this_of!(OneOf2, A, B);
+this_of!(OneOf3, A, B, C);
+this_of!(OneOf4, A, B, C, D);
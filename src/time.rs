@@ -0,0 +1,225 @@
+//! `Millis` and `Timestamp`, milliseconds since the Unix epoch as JS has
+//! them, for codebases without a date library.
+
+use crate::util::{
+    call_function, eval_function, isolate_slot, make_num, set_isolate_slot, type_of,
+};
+use crate::{FFICompat, FFIError};
+use rusty_v8 as v8;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use v8::Global;
+
+/// The furthest a JS `Date` can be from the epoch, in milliseconds.
+pub const MAX_MILLIS: f64 = 8.64e15;
+
+/// Milliseconds since the Unix epoch, possibly fractional, as returned by
+/// `Date.now()` or `performance.timeOrigin`. Converts from a number or a
+/// `Date`, which must be within the range of a `Date`.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Millis(pub f64);
+
+/// Whole milliseconds since the Unix epoch. Converts from a number or a
+/// `Date`, which must be within the range of a `Date`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(pub i64);
+
+/// What `Millis` and `Timestamp` convert to in JS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeFormat {
+    /// A number of milliseconds, like `Date.now()`.
+    Number,
+    /// A `Date`.
+    Date,
+}
+
+impl Default for TimeFormat {
+    fn default() -> TimeFormat {
+        TimeFormat::Number
+    }
+}
+
+struct TimeFormatSlot(TimeFormat);
+
+struct NewDateSlot(Global<v8::Function>);
+
+/// Set what `Millis` and `Timestamp` convert to in this isolate.
+pub fn set_time_format(scope: &mut impl v8::InIsolate, format: TimeFormat) {
+    set_isolate_slot(scope, TimeFormatSlot(format));
+}
+
+/// What `Millis` and `Timestamp` convert to in this isolate,
+/// `TimeFormat::Number` if it was not set.
+pub fn time_format(scope: &mut impl v8::InIsolate) -> TimeFormat {
+    isolate_slot::<TimeFormatSlot>(scope)
+        .map(|slot| slot.0)
+        .unwrap_or_default()
+}
+
+impl Millis {
+    pub fn now() -> Millis {
+        Millis::from(SystemTime::now())
+    }
+
+    pub fn to_system_time(self) -> SystemTime {
+        let offset = Duration::from_nanos((self.0.abs() * 1e6).round() as u64);
+        if self.0 < 0.0 {
+            UNIX_EPOCH - offset
+        } else {
+            UNIX_EPOCH + offset
+        }
+    }
+}
+
+impl Timestamp {
+    pub fn now() -> Timestamp {
+        Timestamp::from(SystemTime::now())
+    }
+
+    pub fn to_system_time(self) -> SystemTime {
+        let offset = Duration::from_millis((self.0 as i128).abs() as u64);
+        if self.0 < 0 {
+            UNIX_EPOCH - offset
+        } else {
+            UNIX_EPOCH + offset
+        }
+    }
+}
+
+impl From<SystemTime> for Millis {
+    fn from(time: SystemTime) -> Millis {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => Millis(since.as_secs_f64() * 1000.0),
+            Err(e) => Millis(-e.duration().as_secs_f64() * 1000.0),
+        }
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Timestamp {
+        Timestamp(Millis::from(time).0.floor() as i64)
+    }
+}
+
+impl From<Timestamp> for Millis {
+    fn from(timestamp: Timestamp) -> Millis {
+        Millis(timestamp.0 as f64)
+    }
+}
+
+/// The milliseconds of a number or `Date`, checked to be within the range
+/// of a `Date`.
+fn millis_from_value<'sc>(
+    value: v8::Local<'sc, v8::Value>,
+    scope: &mut impl v8::ToLocal<'sc>,
+) -> Result<f64, FFIError> {
+    if !value.is_number() && !value.is_date() {
+        return Err(FFIError::TypeError(format!(
+            "invalid type for argument in ffi call, expected number or Date, received {}",
+            type_of(value)
+        )));
+    }
+    // a `Date` converts to its time value
+    let millis = value.number_value(scope).unwrap_or(f64::NAN);
+    if !millis.is_finite() || millis.abs() > MAX_MILLIS {
+        return Err(FFIError::RangeError(format!(
+            "{} is out of range, expected milliseconds within {} of the epoch",
+            millis, MAX_MILLIS
+        )));
+    }
+    Ok(millis)
+}
+
+/// `millis` as a number or a `Date`, following the isolate's `TimeFormat`.
+fn millis_to_value<'sc>(
+    millis: f64,
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+    let millis = make_num(scope, millis);
+    if time_format(scope) == TimeFormat::Number {
+        return Ok(millis);
+    }
+    let cached = isolate_slot::<NewDateSlot>(scope).and_then(|slot| slot.0.get(scope));
+    let new_date = match cached {
+        Some(new_date) => new_date,
+        None => {
+            let new_date = eval_function(scope, context, "(millis) => new Date(millis)")?;
+            let global = Global::new_from(scope, new_date);
+            set_isolate_slot(scope, NewDateSlot(global));
+            new_date
+        }
+    };
+    let undefined = v8::undefined(scope).into();
+    call_function(scope, context, new_date, undefined, &[millis])
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for Millis {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        millis_from_value(value, scope).map(Millis)
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        millis_to_value(self.0, scope, context)
+    }
+}
+
+impl<'sc, 'c> FFICompat<'sc, 'c> for Timestamp {
+    type E = FFIError;
+
+    fn from_value(
+        value: v8::Local<'sc, v8::Value>,
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Result<Self, FFIError> {
+        let millis = millis_from_value(value, scope)?;
+        if millis.fract() != 0.0 {
+            return Err(FFIError::RangeError(format!(
+                "{} is out of range, expected whole milliseconds",
+                millis
+            )));
+        }
+        Ok(Timestamp(millis as i64))
+    }
+
+    fn to_value(
+        self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+        millis_to_value(self.0 as f64, scope, context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_time() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_500);
+        assert_eq!(Millis::from(time), Millis(1_500.0));
+        assert_eq!(Timestamp::from(time), Timestamp(1_500));
+        assert_eq!(
+            Millis(-1_500.0).to_system_time(),
+            UNIX_EPOCH - Duration::from_millis(1_500)
+        );
+        assert_eq!(
+            Timestamp::from(Millis(-1.5).to_system_time()),
+            Timestamp(-2)
+        );
+        assert_eq!(
+            Timestamp(42).to_system_time(),
+            UNIX_EPOCH + Duration::from_millis(42)
+        );
+    }
+}
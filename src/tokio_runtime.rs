@@ -0,0 +1,54 @@
+//! Driving an isolate from a tokio runtime.
+//!
+//! An isolate must stay on one thread, so it is run on a current thread
+//! runtime inside a `LocalSet`, which `run_isolate_on_current_thread` sets
+//! up. `TokioExecutor` spawns the futures backing promises onto that
+//! `LocalSet`.
+//!
+//! ```ignore
+//! run_isolate_on_current_thread(async {
+//!     let mut runtime = Runtime::new();
+//!     let (_, context) = runtime.create_context();
+//!     // install extensions and run scripts, i.e. calling `v8_ffi` fns
+//!     // that return `spawn_local(scope, future)`
+//!     run_event_loop(runtime.isolate()).await;
+//! });
+//! ```
+
+use crate::executor::{Executor, LocalFuture};
+use std::future::Future;
+use std::time::Duration;
+
+pub use crate::executor::{run_event_loop, spawn_local};
+
+/// Run `future` to completion on a new current thread tokio runtime, inside
+/// a `LocalSet` so that `spawn_local` can be used. Isolates should be
+/// created and used only within `future`.
+///
+/// Panics if called from within a tokio runtime.
+pub fn run_isolate_on_current_thread<F: Future>(future: F) -> F::Output {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to build tokio runtime");
+    let local = tokio::task::LocalSet::new();
+    local.block_on(&runtime, future)
+}
+
+/// `Executor` spawning onto the current `LocalSet` and sleeping with tokio
+/// timers. Used by isolates without an executor set.
+///
+/// Spawning panics if not within a `LocalSet`, i.e. outside of
+/// `run_isolate_on_current_thread`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioExecutor;
+
+impl Executor for TokioExecutor {
+    fn spawn(&self, future: LocalFuture) {
+        tokio::task::spawn_local(future);
+    }
+
+    fn sleep(&self, duration: Duration) -> LocalFuture {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
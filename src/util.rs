@@ -1,7 +1,19 @@
+use crate::error_class::ReturnedError;
+use crate::instrument::FfiCall;
+use crate::FFIError;
+use crate::JsRef;
 use crate::ObjectWrap;
 use rusty_v8 as v8;
+use std::any::{Any, TypeId};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt::{self, Debug};
 use std::rc::Rc;
 
+pub use crate::inspect::{inspect, inspect_with, InspectOptions};
+
 pub fn make_str<'sc>(scope: &mut impl v8::ToLocal<'sc>, value: &str) -> v8::Local<'sc, v8::Value> {
     v8::String::new(scope, value).unwrap().into()
 }
@@ -19,6 +31,326 @@ pub fn throw_exception<'sc>(scope: &mut impl v8::ToLocal<'sc>, message: &str) {
     scope.isolate().throw_exception(message);
 }
 
+pub fn throw_error<'sc>(scope: &mut impl v8::ToLocal<'sc>, message: &str) {
+    let message = v8::String::new(scope, message).unwrap();
+    let exception = v8::Exception::error(scope, message);
+    scope.isolate().throw_exception(exception);
+}
+
+pub fn throw_type_error<'sc>(scope: &mut impl v8::ToLocal<'sc>, message: &str) {
+    let message = v8::String::new(scope, message).unwrap();
+    let exception = v8::Exception::type_error(scope, message);
+    scope.isolate().throw_exception(exception);
+}
+
+pub fn throw_range_error<'sc>(scope: &mut impl v8::ToLocal<'sc>, message: &str) {
+    let message = v8::String::new(scope, message).unwrap();
+    let exception = v8::Exception::range_error(scope, message);
+    scope.isolate().throw_exception(exception);
+}
+
+/// Converts an `FFICompat` conversion error to a JS value. `FFIError`s become
+/// their matching JS error type, errors returned with an installed
+/// `JsErrorClass` an instance of their class, and anything else becomes its
+/// description, see `describe_error`.
+pub fn ffi_error_value<'sc, E: Debug + Any>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    error: &E,
+) -> v8::Local<'sc, v8::Value> {
+    let any = error as &dyn Any;
+    if let Some(error) = any.downcast_ref::<FFIError>() {
+        return error.to_exception(scope);
+    }
+    if let Some(exception) = any
+        .downcast_ref::<ReturnedError>()
+        .and_then(|error| error.to_exception(scope))
+    {
+        return exception;
+    }
+    make_str(scope, &describe_error(error))
+}
+
+/// Throws an `FFICompat` conversion error, see `ffi_error_value`.
+pub fn throw_ffi_error<'sc, E: Debug + Any>(scope: &mut impl v8::ToLocal<'sc>, error: &E) {
+    let exception = ffi_error_value(scope, error);
+    scope.isolate().throw_exception(exception);
+}
+
+/// Describes an error that is not an `FFIError` by its `Debug` string, or
+/// with the `minimal-errors` feature by its type name unless it is a string,
+/// so that the `Debug` impls of error types and the formatting machinery
+/// stay out of the binary.
+#[cfg(not(feature = "minimal-errors"))]
+pub fn describe_error<E: Debug + Any>(error: &E) -> Cow<'_, str> {
+    Cow::Owned(format!("{:?}", error))
+}
+
+/// `describe_error` of the `minimal-errors` feature: `FFIError`s and string
+/// errors, like those of the builtin conversions, keep their message,
+/// anything else is only named.
+#[cfg(feature = "minimal-errors")]
+pub fn describe_error<E: Debug + Any>(error: &E) -> Cow<'_, str> {
+    let any = error as &dyn Any;
+    if let Some(error) = any.downcast_ref::<FFIError>() {
+        return Cow::Borrowed(error.message());
+    }
+    if let Some(error) = any.downcast_ref::<String>() {
+        return Cow::Borrowed(error);
+    }
+    if let Some(error) = any.downcast_ref::<&'static str>() {
+        return Cow::Borrowed(error);
+    }
+    Cow::Borrowed(std::any::type_name::<E>())
+}
+
+// The error paths of the `v8_ffi` trampolines, kept out of line so every
+// generated fn shares them rather than inlining its own copy.
+
+/// Reports a failed argument conversion of a `v8_ffi` call and throws it.
+#[doc(hidden)]
+pub fn throw_conversion_error<'sc, E: Debug + Any>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    call: &FfiCall,
+    error: &E,
+) {
+    call.conversion_error(error);
+    throw_ffi_error(scope, error);
+}
+
+/// Reports the `TypeError` of a `v8_ffi` call made on an invalid `this` and
+/// throws it. With the `minimal-errors` feature the message only names the
+/// function.
+#[doc(hidden)]
+pub fn throw_invalid_this<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    call: &FfiCall,
+    function: &'static str,
+    error: &dyn fmt::Display,
+) {
+    #[cfg(not(feature = "minimal-errors"))]
+    let message = format!("invalid 'this' for ffi call {}: {}", function, error);
+    #[cfg(feature = "minimal-errors")]
+    let message = {
+        let _ = error;
+        ["invalid 'this' for ffi call ", function].concat()
+    };
+    throw_conversion_error(scope, call, &FFIError::TypeError(message));
+}
+
+/// Reports the error a `v8_ffi` call is about to throw and throws it.
+#[doc(hidden)]
+pub fn throw_call_error<'sc, E: Debug + Any>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    call: &FfiCall,
+    error: &E,
+) {
+    call.exception(error);
+    throw_ffi_error(scope, error);
+}
+
+/// Describe the JS type of `value` for error messages, like `typeof` but
+/// distinguishing `null` and arrays.
+pub fn type_of(value: v8::Local<v8::Value>) -> &'static str {
+    if value.is_undefined() {
+        "undefined"
+    } else if value.is_null() {
+        "null"
+    } else if value.is_boolean() {
+        "boolean"
+    } else if value.is_number() {
+        "number"
+    } else if value.is_string() {
+        "string"
+    } else if value.is_symbol() {
+        "symbol"
+    } else if value.is_big_int() {
+        "bigint"
+    } else if value.is_function() {
+        "function"
+    } else if value.is_array() {
+        "array"
+    } else {
+        "object"
+    }
+}
+
+/// Render a thrown JS value as a message string.
+pub fn exception_message<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    exception: v8::Local<v8::Value>,
+) -> String {
+    exception
+        .to_string(scope)
+        .map(|x| x.to_rust_string_lossy(scope))
+        .unwrap_or_else(|| "unknown exception".to_string())
+}
+
+/// A frame of the JS stack, see `current_location`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsFrame {
+    /// The resource name of the script, i.e. the origin passed to
+    /// `compile_only`, if it has one.
+    pub script_name: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    /// The name of the function, if not anonymous.
+    pub function: Option<String>,
+}
+
+impl fmt::Display for JsFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let script_name = self.script_name.as_deref().unwrap_or("<anonymous>");
+        match &self.function {
+            Some(function) => write!(
+                f,
+                "{} ({}:{}:{})",
+                function, script_name, self.line, self.column
+            ),
+            None => write!(f, "{}:{}:{}", script_name, self.line, self.column),
+        }
+    }
+}
+
+/// The innermost JS frame on the stack, i.e. the script and function that
+/// called the running `v8_ffi` fn, for logging and auditing native calls.
+/// `None` if no JS is running.
+///
+/// ```ignore
+/// #[v8_ffi(scoped)]
+/// fn delete_user<'sc, 'c>(scope: &mut impl v8::ToLocal<'sc>, _context: v8::Local<'c, v8::Context>, id: u32) {
+///     let caller = current_location(scope);
+///     audit!("delete_user({}) from {:?}", id, caller.map(|x| x.to_string()));
+/// }
+/// ```
+pub fn current_location<'sc>(scope: &mut impl v8::ToLocal<'sc>) -> Option<JsFrame> {
+    let trace = v8::StackTrace::current_stack_trace(scope, 1)?;
+    let frame = trace.get_frame(scope, 0)?;
+    let script_name = frame
+        .get_script_name_or_source_url(scope)
+        .map(|x| x.to_rust_string_lossy(scope));
+    let function = frame
+        .get_function_name(scope)
+        .map(|x| x.to_rust_string_lossy(scope));
+    Some(JsFrame {
+        script_name: script_name.filter(|x| !x.is_empty()),
+        line: frame.get_line_number(),
+        column: frame.get_column(),
+        function: function.filter(|x| !x.is_empty()),
+    })
+}
+
+/// Run `source`, which must evaluate to a function, and return that function.
+pub fn eval_function<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    source: &str,
+) -> Result<v8::Local<'sc, v8::Function>, FFIError> {
+    let mut try_catch = v8::TryCatch::new(scope);
+    let tc = try_catch.enter();
+    let result = run_script(scope, context, source);
+    if tc.has_caught() {
+        let exception = tc.exception().unwrap();
+        return Err(FFIError::Error(exception_message(scope, exception)));
+    }
+    result
+        .and_then(|x| x.try_into().ok())
+        .ok_or_else(|| FFIError::Error("script did not evaluate to a function".to_string()))
+}
+
+/// A function of `context_function`, evaluated from `source` in `context`.
+struct ContextFunction {
+    source: &'static str,
+    context: JsRef<v8::Context>,
+    function: JsRef<v8::Function>,
+}
+
+#[derive(Default)]
+struct ContextFunctions(RefCell<Vec<ContextFunction>>);
+
+impl ContextFunctions {
+    fn position<'sc>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<v8::Context>,
+        source: Option<&'static str>,
+    ) -> Option<usize> {
+        let target = context.global(scope);
+        self.0.borrow().iter().position(|entry| {
+            source.map_or(true, |x| std::ptr::eq(x, entry.source))
+                && entry
+                    .context
+                    .get(scope)
+                    .global(scope)
+                    .strict_equals(target.into())
+        })
+    }
+}
+
+/// Like `eval_function`, but `source` is only evaluated the first time it is
+/// asked for in `context`, which gets the same function from then on. For
+/// helpers that capture builtins when they are evaluated, so that script
+/// replacing the builtins later does not affect them.
+pub(crate) fn context_function<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    source: &'static str,
+) -> Result<v8::Local<'sc, v8::Function>, FFIError> {
+    let functions = match isolate_slot::<ContextFunctions>(scope) {
+        Some(functions) => functions,
+        None => {
+            set_isolate_slot(scope, ContextFunctions::default());
+            isolate_slot::<ContextFunctions>(scope).unwrap()
+        }
+    };
+    if let Some(index) = functions.position(scope, context, Some(source)) {
+        return Ok(functions.0.borrow()[index].function.get(scope));
+    }
+    let function = eval_function(scope, context, source)?;
+    functions.0.borrow_mut().push(ContextFunction {
+        source,
+        context: JsRef::new(scope, context),
+        function: JsRef::new(scope, function),
+    });
+    Ok(function)
+}
+
+/// Release the functions `context_function` kept for `context` as it is
+/// disposed.
+pub(crate) fn release_context_functions(
+    isolate: &mut v8::Isolate,
+    context: &v8::Global<v8::Context>,
+) {
+    let mut hs = v8::HandleScope::new(isolate);
+    let scope = hs.enter();
+    let functions = isolate_slot::<ContextFunctions>(scope);
+    if let (Some(functions), Some(context)) = (functions, context.get(scope)) {
+        while let Some(index) = functions.position(scope, context, None) {
+            let entry = functions.0.borrow_mut().remove(index);
+            entry.context.release(scope);
+            entry.function.release(scope);
+        }
+    }
+}
+
+/// Call `function` with `recv` as `this`, catching any thrown exception as
+/// an `FFIError`.
+pub fn call_function<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    mut function: v8::Local<v8::Function>,
+    recv: v8::Local<v8::Value>,
+    args: &[v8::Local<v8::Value>],
+) -> Result<v8::Local<'sc, v8::Value>, FFIError> {
+    let mut try_catch = v8::TryCatch::new(scope);
+    let tc = try_catch.enter();
+    let result = function.call(scope, context, recv, args);
+    if tc.has_caught() {
+        let exception = tc.exception().unwrap();
+        return Err(FFIError::Error(exception_message(scope, exception)));
+    }
+    result.ok_or_else(|| FFIError::Error("function call failed".to_string()))
+}
+
 pub fn run_script<'sc>(
     scope: &mut impl v8::ToLocal<'sc>,
     context: v8::Local<v8::Context>,
@@ -30,6 +362,74 @@ pub fn run_script<'sc>(
     compiled.as_mut().map(|x| x.run(scope, context)).flatten()
 }
 
+/// Set `value` at the dotted `path` below `target`, i.e. `fs.promises.readFile`,
+/// creating the intermediate objects that do not exist yet.
+pub fn set_path<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<v8::Context>,
+    target: v8::Local<'sc, v8::Object>,
+    path: &str,
+    value: v8::Local<v8::Value>,
+) -> Result<(), FFIError> {
+    let mut names: Vec<&str> = path.split('.').collect();
+    let last = names.pop().unwrap();
+    if last.is_empty() || names.iter().any(|name| name.is_empty()) {
+        return Err(FFIError::TypeError(format!("invalid path {:?}", path)));
+    }
+    let mut object = target;
+    for (i, name) in names.iter().enumerate() {
+        let key = make_str(scope, name);
+        let child = object
+            .get(scope, context, key)
+            .unwrap_or_else(|| v8::undefined(scope).into());
+        object = if child.is_undefined() {
+            let child = v8::Object::new(scope);
+            object.set(context, key, child.into());
+            child
+        } else {
+            child.try_into().map_err(|_| {
+                FFIError::TypeError(format!(
+                    "cannot set {}: {} is {}, not an object",
+                    path,
+                    names[..=i].join("."),
+                    type_of(child)
+                ))
+            })?
+        };
+    }
+    let key = make_str(scope, last);
+    object.set(context, key, value);
+    Ok(())
+}
+
+/// Build a `ScriptOrigin` naming the script or module `resource_name`.
+pub fn make_script_origin<'sc>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    resource_name: &str,
+    is_module: bool,
+) -> v8::ScriptOrigin<'sc> {
+    let resource_name = make_str(scope, resource_name);
+    let resource_line_offset = v8::Integer::new(scope, 0);
+    let resource_column_offset = v8::Integer::new(scope, 0);
+    let resource_is_shared_cross_origin = v8::Boolean::new(scope, false);
+    let script_id = v8::Integer::new(scope, 0);
+    let source_map_url = make_str(scope, "");
+    let resource_is_opaque = v8::Boolean::new(scope, true);
+    let is_wasm = v8::Boolean::new(scope, false);
+    let is_module = v8::Boolean::new(scope, is_module);
+    v8::ScriptOrigin::new(
+        resource_name,
+        resource_line_offset,
+        resource_column_offset,
+        resource_is_shared_cross_origin,
+        script_id,
+        source_map_url,
+        resource_is_opaque,
+        is_wasm,
+        is_module,
+    )
+}
+
 pub fn make_object_wrap<'sc, T>(
     scope: &mut impl v8::ToLocal<'sc>,
     context: v8::Local<v8::Context>,
@@ -51,3 +451,105 @@ pub fn make_object_wrap_rc<'sc, T>(
     let obj = obj.new_instance(scope, context).unwrap();
     ObjectWrap::new_rc(scope, obj, wrap)
 }
+
+thread_local! {
+    static ISOLATE_SLOTS: RefCell<HashMap<(usize, TypeId), Rc<dyn Any>>> = RefCell::new(HashMap::new());
+}
+
+/// A key identifying the isolate `scope` belongs to, for per-isolate state
+/// kept on the isolate's thread.
+pub(crate) fn isolate_key(scope: &mut impl v8::InIsolate) -> usize {
+    scope.isolate() as *mut v8::Isolate as usize
+}
+
+/// Store a per-isolate value of type `T`, replacing any previous one.
+pub fn set_isolate_slot<T: Any>(scope: &mut impl v8::InIsolate, value: T) {
+    let key = (isolate_key(scope), TypeId::of::<T>());
+    ISOLATE_SLOTS.with(|slots| slots.borrow_mut().insert(key, Rc::new(value)));
+}
+
+/// Get the per-isolate value of type `T`, if one was set.
+pub fn isolate_slot<T: Any>(scope: &mut impl v8::InIsolate) -> Option<Rc<T>> {
+    let key = (isolate_key(scope), TypeId::of::<T>());
+    let slot = ISOLATE_SLOTS.with(|slots| slots.borrow().get(&key).cloned())?;
+    slot.downcast().ok()
+}
+
+/// Remove the per-isolate value of type `T`.
+pub fn remove_isolate_slot<T: Any>(scope: &mut impl v8::InIsolate) -> Option<Rc<T>> {
+    let key = (isolate_key(scope), TypeId::of::<T>());
+    let slot = ISOLATE_SLOTS.with(|slots| slots.borrow_mut().remove(&key))?;
+    slot.downcast().ok()
+}
+
+/// Remove every per-isolate value stored for the isolate of `scope`.
+/// Should be called before the isolate is disposed.
+pub fn clear_isolate_slots(scope: &mut impl v8::InIsolate) {
+    crate::instrument::clear_ffi_observer(scope);
+    let isolate = isolate_key(scope);
+    ISOLATE_SLOTS.with(|slots| slots.borrow_mut().retain(|key, _| key.0 != isolate));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FFICompat;
+    use rusty_v8_helper_derive::v8_ffi;
+
+    #[v8_ffi(scoped)]
+    fn test_ffi_location<'sc, 'c>(
+        scope: &mut impl v8::ToLocal<'sc>,
+        _context: v8::Local<'c, v8::Context>,
+    ) -> Option<String> {
+        crate::util::current_location(scope).map(|x| x.to_string())
+    }
+
+    #[test]
+    fn current_location() {
+        with_context!([test_ffi_location], |scope, context| {
+            let source = "function audited() {\n  return test_ffi_location();\n}\n[audited(), (() => test_ffi_location())()]";
+            let result = crate::compile_only(scope, context, source, "plugin.js")
+                .unwrap()
+                .run(scope, context)
+                .unwrap();
+            assert_eq!(
+                Vec::<String>::from_value(result, scope, context),
+                Ok(vec![
+                    "audited (plugin.js:2:10)".to_string(),
+                    "plugin.js:4:20".to_string()
+                ])
+            );
+            assert_eq!(crate::util::current_location(scope), None);
+        });
+    }
+
+    #[cfg(feature = "minimal-errors")]
+    #[v8_ffi]
+    fn test_ffi_half(value: f64) -> f64 {
+        value / 2.0
+    }
+
+    #[cfg(feature = "minimal-errors")]
+    #[test]
+    fn minimal_string_errors() {
+        let error = "expected a number".to_string();
+        assert!(matches!(
+            describe_error(&error),
+            Cow::Borrowed("expected a number")
+        ));
+        assert_eq!(describe_error(&"expected a string"), "expected a string");
+        assert_eq!(describe_error(&()), "()");
+        with_context!([test_ffi_half], |scope, context| {
+            let thrown = run_script(
+                scope,
+                context,
+                "(() => { try { test_ffi_half('x'); } catch (e) { return e; } })()",
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_value(thrown, scope, context),
+                Ok("invalid type for argument in ffi call, expected f64".to_string())
+            );
+        });
+    }
+}
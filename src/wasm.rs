@@ -0,0 +1,190 @@
+use crate::util::*;
+use crate::Bytes;
+use crate::FFICompat;
+use crate::FFIError;
+use rusty_v8 as v8;
+use std::convert::TryInto;
+
+/// Evaluate to the compile and instantiate helpers, capturing the
+/// `WebAssembly` constructors once per context, see `context_function`.
+const WASM_COMPILE: &str = r#"(function () {
+    const { Module } = WebAssembly;
+    return (bytes) => new Module(bytes);
+})()"#;
+const WASM_INSTANTIATE: &str = r#"(function () {
+    const { Instance } = WebAssembly;
+    return (module, imports) => new Instance(module, imports);
+})()"#;
+
+/// Builder for the import object passed when instantiating a wasm module.
+#[derive(Default)]
+pub struct WasmImports<'sc> {
+    modules: Vec<(String, Vec<(String, v8::Local<'sc, v8::Value>)>)>,
+}
+
+impl<'sc> WasmImports<'sc> {
+    pub fn new() -> WasmImports<'sc> {
+        WasmImports::default()
+    }
+
+    /// Add an import `module.name`, typically a function created with
+    /// `load_v8_ffi!`.
+    pub fn import(mut self, module: &str, name: &str, value: v8::Local<'sc, v8::Value>) -> Self {
+        match self.modules.iter_mut().find(|x| x.0 == module) {
+            Some(entry) => entry.1.push((name.to_string(), value)),
+            None => self
+                .modules
+                .push((module.to_string(), vec![(name.to_string(), value)])),
+        }
+        self
+    }
+
+    fn to_object<'c>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+    ) -> v8::Local<'sc, v8::Object> {
+        let imports = v8::Object::new(scope);
+        for (module, values) in self.modules.iter() {
+            let module_object = v8::Object::new(scope);
+            for (name, value) in values.iter() {
+                module_object.set(context, make_str(scope, name), *value);
+            }
+            imports.set(context, make_str(scope, module), module_object.into());
+        }
+        imports
+    }
+}
+
+/// An instantiated wasm module.
+pub struct WasmInstance<'sc> {
+    pub instance: v8::Local<'sc, v8::Object>,
+    pub exports: v8::Local<'sc, v8::Object>,
+}
+
+impl<'sc> WasmInstance<'sc> {
+    /// Read the export `name`, converted through `FFICompat`.
+    pub fn export<'c, T: FFICompat<'sc, 'c>>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+        name: &str,
+    ) -> Result<T, FFIError> {
+        let key = make_str(scope, name);
+        let value = self
+            .exports
+            .get(scope, context, key)
+            .filter(|x| !x.is_undefined())
+            .ok_or_else(|| FFIError::Error(format!("wasm module has no export '{}'", name)))?;
+        T::from_value(value, scope, context).map_err(|e| FFIError::TypeError(format!("{:?}", e)))
+    }
+
+    /// Call the exported function `name` with `args`, converting the result
+    /// through `FFICompat`.
+    pub fn call<'c, R: FFICompat<'sc, 'c>>(
+        &self,
+        scope: &mut impl v8::ToLocal<'sc>,
+        context: v8::Local<'c, v8::Context>,
+        name: &str,
+        args: &[v8::Local<'sc, v8::Value>],
+    ) -> Result<R, FFIError> {
+        let function: v8::Local<v8::Value> = self.export(scope, context, name)?;
+        let function: v8::Local<v8::Function> = function.try_into().map_err(|_| {
+            FFIError::TypeError(format!("wasm export '{}' is not a function", name))
+        })?;
+        let recv = v8::undefined(scope).into();
+        let result = call_function(scope, context, function, recv, args)?;
+        R::from_value(result, scope, context).map_err(|e| FFIError::TypeError(format!("{:?}", e)))
+    }
+}
+
+/// Compile a wasm binary into a `WebAssembly.Module` object.
+///
+/// `WebAssembly.Module` is captured the first time a context compiles a
+/// module, and `WebAssembly.Instance` the first time it instantiates one,
+/// so replacing them from script afterwards has no effect.
+pub fn compile_wasm<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    bytes: &[u8],
+) -> Result<v8::Local<'sc, v8::Object>, FFIError> {
+    let compile = context_function(scope, context, WASM_COMPILE)?;
+    let bytes = Bytes(bytes.to_vec()).to_value(scope, context)?;
+    let recv = v8::undefined(scope).into();
+    let module = call_function(scope, context, compile, recv, &[bytes])?;
+    module
+        .try_into()
+        .map_err(|_| FFIError::Error("failed to compile wasm module".to_string()))
+}
+
+/// Instantiate a module from `compile_wasm` with the given imports.
+pub fn instantiate_wasm<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    module: v8::Local<'sc, v8::Object>,
+    imports: &WasmImports<'sc>,
+) -> Result<WasmInstance<'sc>, FFIError> {
+    let instantiate = context_function(scope, context, WASM_INSTANTIATE)?;
+    let imports = imports.to_object(scope, context);
+    let recv = v8::undefined(scope).into();
+    let instance = call_function(
+        scope,
+        context,
+        instantiate,
+        recv,
+        &[module.into(), imports.into()],
+    )?;
+    let instance: v8::Local<v8::Object> = instance
+        .try_into()
+        .map_err(|_| FFIError::Error("failed to instantiate wasm module".to_string()))?;
+    let key = make_str(scope, "exports");
+    let exports = instance
+        .get(scope, context, key)
+        .and_then(|x| x.try_into().ok())
+        .ok_or_else(|| FFIError::Error("wasm instance has no exports".to_string()))?;
+    Ok(WasmInstance { instance, exports })
+}
+
+/// Compile and instantiate a wasm binary in one step.
+pub fn load_wasm<'sc, 'c>(
+    scope: &mut impl v8::ToLocal<'sc>,
+    context: v8::Local<'c, v8::Context>,
+    bytes: &[u8],
+    imports: &WasmImports<'sc>,
+) -> Result<WasmInstance<'sc>, FFIError> {
+    let module = compile_wasm(scope, context, bytes)?;
+    instantiate_wasm(scope, context, module, imports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_wasm_module() {
+        with_context!(|scope, context| {
+            let wasm_add = [
+                0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x60, 0x02, 0x7f,
+                0x7f, 0x01, 0x7f, 0x03, 0x02, 0x01, 0x00, 0x07, 0x07, 0x01, 0x03, 0x61, 0x64, 0x64,
+                0x00, 0x00, 0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b,
+            ];
+            let instance =
+                crate::wasm::load_wasm(scope, context, &wasm_add, &crate::wasm::WasmImports::new())
+                    .unwrap();
+            let args = [make_num(scope, 2.0), make_num(scope, 3.0)];
+            let sum: i32 = instance.call(scope, context, "add", &args).unwrap();
+            assert_eq!(sum, 5);
+            run_script(
+                scope,
+                context,
+                "WebAssembly.Module = function () { throw 1; }",
+            )
+            .unwrap();
+            let instance =
+                crate::wasm::load_wasm(scope, context, &wasm_add, &crate::wasm::WasmImports::new())
+                    .unwrap();
+            let sum: i32 = instance.call(scope, context, "add", &args).unwrap();
+            assert_eq!(sum, 5);
+        });
+    }
+}